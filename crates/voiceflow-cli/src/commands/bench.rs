@@ -1,7 +1,9 @@
 //! Bench command - run performance benchmarks
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use console::{style, Term};
+use std::path::Path;
+use voiceflow_core::audio::load_audio_file;
 use voiceflow_core::{Config, Pipeline};
 
 pub async fn run(config: &Config, iterations: u32, file: Option<&str>) -> Result<()> {
@@ -15,27 +17,10 @@ pub async fn run(config: &Config, iterations: u32, file: Option<&str>) -> Result
 
     // Generate or load test audio
     let samples = if let Some(path) = file {
-        // Load from file
-        let reader = hound::WavReader::open(path)?;
-        let spec = reader.spec();
-
-        let samples: Vec<f32> = match spec.sample_format {
-            hound::SampleFormat::Float => reader
-                .into_samples::<f32>()
-                .filter_map(Result::ok)
-                .collect(),
-            hound::SampleFormat::Int => {
-                let max_val = (1 << (spec.bits_per_sample - 1)) as f32;
-                reader
-                    .into_samples::<i32>()
-                    .filter_map(Result::ok)
-                    .map(|s| s as f32 / max_val)
-                    .collect()
-            }
-        };
-
-        // Resample to 16kHz
-        voiceflow_core::audio::resample_to_16khz(&samples, spec.sample_rate)?
+        // Load from file, downmixed and resampled to 16kHz
+        let buffer = load_audio_file(Path::new(path))
+            .with_context(|| format!("Failed to load audio file: {}", path))?;
+        buffer.as_input().to_16khz_mono()?.into_owned()
     } else {
         // Generate 5 seconds of silence (for testing pipeline overhead)
         term.write_line("Using synthetic audio (5s of low noise)")?;
@@ -59,10 +44,16 @@ pub async fn run(config: &Config, iterations: u32, file: Option<&str>) -> Result
     // Initialize pipeline (warm up)
     term.write_line("Initializing pipeline...")?;
     let mut pipeline = Pipeline::new(config)?;
+    pipeline.warm_up()?;
+    term.write_line(&format!(
+        "Prompt prefix cache: {}",
+        if config.llm_prefix_cache { "on" } else { "off" }
+    ))?;
 
     // Run benchmarks
     let mut transcription_times = Vec::new();
     let mut llm_times = Vec::new();
+    let mut prefill_times = Vec::new();
     let mut total_times = Vec::new();
 
     for i in 0..iterations {
@@ -72,6 +63,7 @@ pub async fn run(config: &Config, iterations: u32, file: Option<&str>) -> Result
 
         transcription_times.push(result.timings.transcription_ms);
         llm_times.push(result.timings.llm_formatting_ms);
+        prefill_times.push(result.timings.llm_prefill_ms);
         total_times.push(result.timings.total_ms);
     }
 
@@ -82,6 +74,7 @@ pub async fn run(config: &Config, iterations: u32, file: Option<&str>) -> Result
 
     let avg_transcription: u64 = transcription_times.iter().sum::<u64>() / iterations as u64;
     let avg_llm: u64 = llm_times.iter().sum::<u64>() / iterations as u64;
+    let avg_prefill: u64 = prefill_times.iter().sum::<u64>() / iterations as u64;
     let avg_total: u64 = total_times.iter().sum::<u64>() / iterations as u64;
 
     let min_total = *total_times.iter().min().unwrap_or(&0);
@@ -95,6 +88,10 @@ pub async fn run(config: &Config, iterations: u32, file: Option<&str>) -> Result
         "LLM Formatting: avg {}ms",
         style(avg_llm).cyan()
     ))?;
+    term.write_line(&format!(
+        "  Prefill:      avg {}ms",
+        style(avg_prefill).cyan()
+    ))?;
     term.write_line(&format!(
         "Total:          avg {}ms (min: {}, max: {})",
         style(avg_total).green(),
@@ -2,7 +2,7 @@
 
 use anyhow::Result;
 use console::{style, Term};
-use voiceflow_core::config::{LlmModel, WhisperModel};
+use voiceflow_core::config::{LlmModel, ModelPrecision, WhisperModel};
 use voiceflow_core::Config;
 
 pub fn show(config: &Config) -> Result<()> {
@@ -13,11 +13,11 @@ pub fn show(config: &Config) -> Result<()> {
 
     term.write_line(&format!(
         "Whisper model:    {}",
-        style(format!("{:?}", config.whisper_model)).cyan()
+        style(config.whisper_model.display_name()).cyan()
     ))?;
     term.write_line(&format!(
         "LLM model:        {}",
-        style(config.llm_model.display_name()).cyan()
+        style(config.llm_display_name()).cyan()
     ))?;
     term.write_line(&format!(
         "Default context:  {}",
@@ -58,6 +58,26 @@ pub fn show(config: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Print the value of a dotted config key, e.g. "llm.temperature"
+pub fn get(config: &Config, key: &str) -> Result<()> {
+    Term::stdout().write_line(&config.get_key(key)?)?;
+    Ok(())
+}
+
+/// Set a dotted config key and save the config file
+pub fn set(config: &mut Config, key: &str, value: &str, path: Option<&str>) -> Result<()> {
+    config.set_key(key, value)?;
+    config.save(path)?;
+
+    Term::stderr().write_line(&format!(
+        "{} {} set to {}",
+        style("✓").green(),
+        key,
+        config.get_key(key)?
+    ))?;
+    Ok(())
+}
+
 pub fn set_model(config: &mut Config, model: &str) -> Result<()> {
     let term = Term::stdout();
 
@@ -100,14 +120,13 @@ pub fn set_model(config: &mut Config, model: &str) -> Result<()> {
 pub fn set_whisper(config: &mut Config, size: &str) -> Result<()> {
     let term = Term::stdout();
 
-    let whisper_model = match size.to_lowercase().as_str() {
-        "tiny" => WhisperModel::Tiny,
-        "base" => WhisperModel::Base,
-        "small" => WhisperModel::Small,
-        "medium" => WhisperModel::Medium,
-        _ => {
+    let size_lower = size.to_lowercase();
+    let (model_id, precision) = ModelPrecision::split_variant_id(&size_lower);
+    let whisper_model = match WhisperModel::from_id(model_id) {
+        Some(model) => model,
+        None => {
             term.write_line(&format!(
-                "{} Unknown size '{}'. Available: tiny, base, small, medium",
+                "{} Unknown size '{}'. Available: tiny, base, small, medium, large-v3-turbo (add -quantized or -int8 for those variants)",
                 style("✗").red(),
                 size
             ))?;
@@ -116,12 +135,14 @@ pub fn set_whisper(config: &mut Config, size: &str) -> Result<()> {
     };
 
     config.whisper_model = whisper_model.clone();
+    config.whisper_precision = precision;
     config.save(None)?;
 
     term.write_line(&format!(
-        "{} Whisper model set to: {:?}",
+        "{} Whisper model set to: {:?} ({})",
         style("✓").green(),
-        whisper_model
+        whisper_model,
+        precision.id()
     ))?;
 
     // Check if model is downloaded
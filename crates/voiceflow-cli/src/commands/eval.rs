@@ -0,0 +1,59 @@
+//! Eval command - accuracy and latency against reference transcripts
+
+use anyhow::Result;
+use console::{style, Term};
+use std::path::Path;
+use voiceflow_core::eval::{evaluate, load_manifest};
+use voiceflow_core::{Config, Pipeline};
+
+pub async fn run(config: &Config, manifest: &str, json: bool) -> Result<()> {
+    let dataset = load_manifest(Path::new(manifest))?;
+    let mut pipeline = Pipeline::new(config)?;
+    let report = evaluate(&mut pipeline, &dataset)?;
+
+    let term = Term::stdout();
+    if json {
+        term.write_line(&serde_json::to_string_pretty(&report)?)?;
+        return Ok(());
+    }
+
+    for item in &report.items {
+        match &item.error {
+            Some(error) => term.write_line(&format!("{} {:?}: {}", style("✗").red(), item.audio, error))?,
+            None => term.write_line(&format!(
+                "{:?}: WER {:.1}%  CER {:.1}%  {}ms",
+                item.audio,
+                item.wer * 100.0,
+                item.cer * 100.0,
+                item.total_ms
+            ))?,
+        }
+    }
+    term.write_line("")?;
+    term.write_line(&format!(
+        "{} ({} evaluated, {} failed)",
+        style("Results:").bold(),
+        report.evaluated,
+        report.failed
+    ))?;
+    term.write_line(&format!("WER:               {}", style(format!("{:.2}%", report.wer * 100.0)).green()))?;
+    term.write_line(&format!("CER:               {}", style(format!("{:.2}%", report.cer * 100.0)).green()))?;
+    term.write_line(&format!("Format divergence: {:.2}%", report.format_divergence * 100.0))?;
+    for (label, latency) in [
+        ("Total:         ", &report.latency),
+        ("Transcription: ", &report.transcription_latency),
+        ("LLM Formatting:", &report.formatting_latency),
+    ] {
+        term.write_line(&format!(
+            "{} p50 {}ms  p90 {}ms  p99 {}ms  (mean {}ms, max {}ms)",
+            label,
+            style(latency.p50_ms).cyan(),
+            latency.p90_ms,
+            latency.p99_ms,
+            latency.mean_ms,
+            latency.max_ms
+        ))?;
+    }
+
+    Ok(())
+}
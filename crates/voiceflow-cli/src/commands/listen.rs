@@ -0,0 +1,98 @@
+//! Listen command - transcribe from the microphone as you speak
+
+use anyhow::Result;
+use arboard::Clipboard;
+use console::{style, Term};
+use voiceflow_core::audio::{list_input_devices, CaptureMode, CaptureOptions, Microphone};
+use voiceflow_core::streaming::listen;
+use voiceflow_core::{CancelToken, Config, Pipeline};
+
+/// How `voiceflow listen` captures
+pub struct ListenOptions<'a> {
+    /// Input device name; the default input when None
+    pub device: Option<&'a str>,
+    /// Capture until Ctrl+C instead of until a pause
+    pub push_to_talk: bool,
+    /// Silence (ms) after speech that ends the capture
+    pub silence_ms: Option<u32>,
+    /// Longest capture in seconds; the configured longest recording when None
+    pub max_secs: Option<u32>,
+}
+
+/// Print the input devices, the default first
+pub fn list_devices() -> Result<()> {
+    let out = Term::stdout();
+    let devices = list_input_devices()?;
+    if devices.is_empty() {
+        out.write_line("No input devices found")?;
+    }
+    for device in devices {
+        let default = if device.is_default { format!(" {}", style("(default)").green()) } else { String::new() };
+        out.write_line(&format!(
+            "{}{} - {} Hz, {} channel(s)",
+            device.name, default, device.sample_rate, device.channels
+        ))?;
+    }
+    Ok(())
+}
+
+/// Capture from the microphone, printing the transcript as it's recognized
+/// to stderr and the formatted text to stdout
+pub async fn run(config: &Config, options: ListenOptions<'_>, context: Option<&str>, clipboard: bool) -> Result<()> {
+    let term = Term::stderr();
+
+    // Load the models first, so capture starts when the prompt shows
+    term.write_line(&format!("{} Loading models...", style("⚙").cyan()))?;
+    let mut pipeline = Pipeline::new(config)?;
+
+    let mut capture = CaptureOptions::new(&config.audio);
+    capture.device = options.device.map(str::to_string);
+    if !options.push_to_talk {
+        capture.mode = CaptureMode::UntilSilence {
+            silence_ms: options.silence_ms.unwrap_or(config.audio.silence_duration_ms),
+        };
+    }
+    if let Some(secs) = options.max_secs {
+        capture.max_duration_ms = secs.saturating_mul(1000);
+    }
+    let mut microphone = Microphone::open(&capture)?;
+
+    // Ctrl+C ends the capture; what was said so far is still transcribed
+    let stop = microphone.stopper();
+    ctrlc::set_handler(move || stop.stop())?;
+
+    let until = if options.push_to_talk { "press Ctrl+C to stop" } else { "pause or press Ctrl+C to stop" };
+    term.write_line(&format!(
+        "{} Listening on {}... ({})",
+        style("🎙").green(),
+        microphone.device().name,
+        until
+    ))?;
+
+    let result = listen(&mut pipeline, &mut microphone, context, &CancelToken::new(), |partial| {
+        let _ = term.clear_line();
+        let _ = term.write_str(&format!("{} {}", style("…").dim(), partial));
+    })?;
+    term.clear_line()?;
+
+    if result.no_speech {
+        term.write_line(&format!("{} No speech detected", style("⚠").yellow()))?;
+        return Ok(());
+    }
+
+    Term::stdout().write_line(&result.formatted_text)?;
+    term.write_line(&format!(
+        "{} Transcription: {}ms | LLM: {}ms | Total: {}ms",
+        style("⏱").dim(),
+        result.timings.transcription_ms,
+        result.timings.llm_formatting_ms,
+        result.timings.total_ms
+    ))?;
+
+    if clipboard {
+        Clipboard::new()?.set_text(&result.formatted_text)?;
+        term.write_line(&format!("{} Copied to clipboard", style("📋").green()))?;
+    }
+
+    Ok(())
+}
@@ -2,7 +2,14 @@
 
 pub mod bench;
 pub mod config;
-pub mod file;
+#[cfg(feature = "eval")]
+pub mod eval;
+#[cfg(feature = "capture")]
+pub mod listen;
 pub mod models;
+#[cfg(feature = "capture")]
 pub mod record;
+#[cfg(feature = "server")]
+pub mod serve;
 pub mod setup;
+pub mod transcribe;
@@ -1,11 +1,14 @@
-//! Models command - list available models
+//! Models command - list, download and remove models
 
 use anyhow::Result;
 use console::{style, Term};
-use voiceflow_core::config::{LlmModel, WhisperModel};
+use std::path::Path;
+use voiceflow_core::config::{LlmModel, ModelPrecision, WhisperModel};
+use voiceflow_core::downloads::DownloadableModel;
+use voiceflow_core::models::storage;
 use voiceflow_core::Config;
 
-pub fn list() -> Result<()> {
+pub fn list(models_dir: &Path) -> Result<()> {
     let term = Term::stdout();
 
     term.write_line(&format!("{}", style("Available Models").bold()))?;
@@ -19,13 +22,12 @@ pub fn list() -> Result<()> {
         (WhisperModel::Tiny, "~75MB", "Fastest, lower accuracy"),
         (WhisperModel::Base, "~150MB", "Good balance (recommended)"),
         (WhisperModel::Small, "~500MB", "Better accuracy"),
-        (WhisperModel::Medium, "~1.5GB", "Best accuracy, slower"),
+        (WhisperModel::Medium, "~1.5GB", "High accuracy, slower"),
+        (WhisperModel::LargeV3Turbo, "~1.6GB", "Best accuracy, faster than medium"),
     ];
 
-    let models_dir = Config::models_dir()?;
-
     for (model, size, desc) in whisper_models {
-        let path = models_dir.join(model.filename());
+        let path = models_dir.join(model.filename(ModelPrecision::Float32));
         let installed = if path.exists() {
             style("✓").green()
         } else {
@@ -33,14 +35,18 @@ pub fn list() -> Result<()> {
         };
 
         term.write_line(&format!(
-            "  {} {:8} {:10} {}",
+            "  {} {:14} {:10} {}",
             installed,
-            format!("{:?}", model).to_lowercase(),
+            model.id(),
             style(size).dim(),
             desc
         ))?;
     }
 
+    term.write_line(&format!(
+        "  {}",
+        style("Smaller, faster variants: add -quantized or -int8 to the id (e.g. small-int8)").dim()
+    ))?;
     term.write_line("")?;
 
     // LLM models
@@ -85,3 +91,38 @@ pub fn list() -> Result<()> {
 
     Ok(())
 }
+
+/// Download a model by id, resuming a partial download
+pub fn download(id: &str, models_dir: &Path) -> Result<()> {
+    let term = Term::stderr();
+    let model = find(id)?;
+
+    if model.is_downloaded(models_dir) {
+        term.write_line(&format!("{} {} already downloaded", style("✓").green(), id))?;
+        return Ok(());
+    }
+    term.write_line(&format!("{} Downloading {}...", style("⬇").cyan(), id))?;
+    super::setup::download(&model, models_dir)?;
+    term.write_line(&format!("{} {} downloaded", style("✓").green(), id))?;
+    Ok(())
+}
+
+/// Delete the files of a model; the configured model only with `force`
+pub fn remove(config: &Config, id: &str, models_dir: &Path, force: bool) -> Result<()> {
+    let model = find(id)?;
+    let freed = storage::delete_model(&model, models_dir, config, force)?;
+    Term::stderr().write_line(&format!(
+        "{} Removed {} ({:.1} MB freed)",
+        style("✓").green(),
+        id,
+        freed as f64 / 1_000_000.0
+    ))?;
+    Ok(())
+}
+
+fn find(id: &str) -> Result<DownloadableModel> {
+    DownloadableModel::from_id(id).ok_or_else(|| {
+        let ids: Vec<String> = DownloadableModel::all_models().iter().map(|m| m.id()).collect();
+        anyhow::anyhow!("Unknown model '{}'. Available: {}", id, ids.join(", "))
+    })
+}
@@ -4,9 +4,8 @@ use anyhow::Result;
 use arboard::Clipboard;
 use console::{style, Term};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use voiceflow_core::audio::AudioCapture;
+use std::time::Duration;
+use voiceflow_core::audio::{CaptureOptions, Microphone};
 use voiceflow_core::{Config, Pipeline};
 
 pub async fn run(
@@ -17,24 +16,18 @@ pub async fn run(
 ) -> Result<()> {
     let term = Term::stdout();
 
-    // Set up Ctrl+C handler
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-    ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
-    })?;
-
-    // Start audio capture
-    let mut capture = AudioCapture::new()?;
+    // Start audio capture (push-to-talk: until Ctrl+C)
+    let mut microphone = Microphone::open(&CaptureOptions::new(&config.audio))?;
+    let stop = microphone.stopper();
+    ctrlc::set_handler(move || stop.stop())?;
 
     term.write_line(&format!(
-        "{} Recording... (press {} to stop)",
+        "{} Recording from {}... (press {} to stop)",
         style("🎙").green(),
+        microphone.device().name,
         style("Ctrl+C").cyan()
     ))?;
 
-    capture.start()?;
-
     // Show recording indicator
     let pb = ProgressBar::new_spinner();
     pb.set_style(
@@ -44,17 +37,16 @@ pub async fn run(
     );
     pb.set_message("Recording...");
 
-    // Wait for Ctrl+C
-    while running.load(Ordering::SeqCst) {
+    // Read until Ctrl+C (or the longest recording allowed)
+    let mut samples = Vec::new();
+    while let Some(chunk) = microphone.read(Duration::from_millis(100))? {
+        samples.extend(chunk);
         pb.tick();
-        std::thread::sleep(std::time::Duration::from_millis(100));
     }
 
     pb.finish_and_clear();
 
-    // Stop capture and get audio
-    let samples = capture.stop()?;
-    let duration_secs = samples.len() as f32 / capture.sample_rate() as f32;
+    let duration_secs = samples.len() as f32 / 16000.0;
 
     term.write_line(&format!(
         "{} Captured {:.1}s of audio ({} samples)",
@@ -82,6 +74,11 @@ pub async fn run(
     // Output result
     term.write_line("")?;
 
+    if result.no_speech {
+        term.write_line(&format!("{} No speech detected", style("⚠").yellow()))?;
+        return Ok(());
+    }
+
     if raw {
         term.write_line(&format!("{}", style("Raw transcript:").bold()))?;
     } else {
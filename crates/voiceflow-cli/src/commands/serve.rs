@@ -0,0 +1,40 @@
+//! Serve command - transcription over HTTP for other devices
+
+use anyhow::Result;
+use console::{style, Term};
+use std::net::SocketAddr;
+use std::time::Duration;
+use voiceflow_core::{Config, Pipeline};
+use voiceflow_server::ServerOptions;
+
+pub async fn run(
+    config: &Config,
+    listen: SocketAddr,
+    token: Option<String>,
+    max_body_mb: usize,
+    timeout_secs: u64,
+) -> Result<()> {
+    let term = Term::stderr();
+    term.write_line(&format!("{} Loading models...", style("⚙").cyan()))?;
+
+    // Load and warm up before listening, so the first request is fast
+    let config = config.clone();
+    let pipeline = tokio::task::spawn_blocking(move || -> Result<Pipeline> {
+        let mut pipeline = Pipeline::new(&config)?;
+        pipeline.warm_up()?;
+        Ok(pipeline)
+    })
+    .await??;
+
+    let options = ServerOptions {
+        token,
+        max_body_bytes: max_body_mb * 1024 * 1024,
+        request_timeout: Duration::from_secs(timeout_secs),
+    };
+    term.write_line(&format!(
+        "{} POST audio to http://{}/v1/transcribe (Ctrl+C to stop)",
+        style("✓").green(),
+        listen
+    ))?;
+    voiceflow_server::serve(listen, pipeline, options).await
+}
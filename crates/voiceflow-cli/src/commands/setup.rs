@@ -3,11 +3,12 @@
 use anyhow::Result;
 use console::{style, Term};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::process::Command;
-use voiceflow_core::config::{LlmModel, WhisperModel};
-use voiceflow_core::Config;
+use std::path::Path;
+use voiceflow_core::config::{LlmModel, ModelPrecision, WhisperModel};
+use voiceflow_core::downloads::{download_model, DownloadableModel};
+use voiceflow_core::{CancelToken, Config};
 
-pub async fn run(whisper: &str, llm: &str) -> Result<()> {
+pub async fn run(models_dir: &Path, whisper: &str, llm: &str) -> Result<()> {
     let term = Term::stdout();
 
     term.write_line(&format!(
@@ -16,17 +17,15 @@ pub async fn run(whisper: &str, llm: &str) -> Result<()> {
     ))?;
     term.write_line("")?;
 
-    let models_dir = Config::models_dir()?;
     term.write_line(&format!("Models directory: {:?}", models_dir))?;
     term.write_line("")?;
 
     // Parse model choices
-    let whisper_model = match whisper.to_lowercase().as_str() {
-        "tiny" => WhisperModel::Tiny,
-        "base" => WhisperModel::Base,
-        "small" => WhisperModel::Small,
-        "medium" => WhisperModel::Medium,
-        _ => {
+    let whisper_lower = whisper.to_lowercase();
+    let (whisper_id, whisper_precision) = ModelPrecision::split_variant_id(&whisper_lower);
+    let whisper_model = match WhisperModel::from_id(whisper_id) {
+        Some(model) => model,
+        None => {
             term.write_line(&format!(
                 "{} Unknown whisper model '{}', using 'base'",
                 style("⚠").yellow(),
@@ -52,7 +51,7 @@ pub async fn run(whisper: &str, llm: &str) -> Result<()> {
     };
 
     // Download Whisper model
-    let whisper_path = models_dir.join(whisper_model.filename());
+    let whisper_path = models_dir.join(whisper_model.filename(whisper_precision));
     if whisper_path.exists() {
         term.write_line(&format!(
             "{} Whisper {} already downloaded",
@@ -66,7 +65,7 @@ pub async fn run(whisper: &str, llm: &str) -> Result<()> {
             whisper
         ))?;
 
-        download_file(whisper_model.url(), &whisper_path)?;
+        download(&DownloadableModel::Whisper(whisper_model.clone(), whisper_precision), models_dir)?;
 
         term.write_line(&format!(
             "{} Whisper {} downloaded",
@@ -92,14 +91,7 @@ pub async fn run(whisper: &str, llm: &str) -> Result<()> {
             ))?;
             term.write_line(&format!("  From: {}", repo))?;
 
-            // Use huggingface-cli if available, otherwise curl
-            let hf_url = format!(
-                "https://huggingface.co/{}/resolve/main/{}",
-                repo,
-                llm_model.filename()
-            );
-
-            download_file(&hf_url, &llm_path)?;
+            download(&DownloadableModel::Llm(llm_model.clone()), models_dir)?;
 
             term.write_line(&format!(
                 "{} {} downloaded",
@@ -116,8 +108,9 @@ pub async fn run(whisper: &str, llm: &str) -> Result<()> {
     }
 
     // Save config with selected models
-    let mut config = Config::load(None).unwrap_or_default();
+    let mut config = Config::load_file(None).unwrap_or_default();
     config.whisper_model = whisper_model;
+    config.whisper_precision = whisper_precision;
     config.llm_model = llm_model;
     config.save(None)?;
 
@@ -131,34 +124,22 @@ pub async fn run(whisper: &str, llm: &str) -> Result<()> {
     Ok(())
 }
 
-fn download_file(url: &str, path: &std::path::Path) -> Result<()> {
-    // Create progress bar
-    let pb = ProgressBar::new(100);
+/// Download a model, resuming a previous partial download
+pub fn download(model: &DownloadableModel, models_dir: &Path) -> Result<()> {
+    let pb = ProgressBar::new(0);
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
             .progress_chars("#>-"),
     );
 
-    // Use curl for download with progress
-    let output = Command::new("curl")
-        .args([
-            "-L",
-            "-o",
-            path.to_str().unwrap(),
-            "--progress-bar",
-            url,
-        ])
-        .output()?;
+    let result = download_model(model, models_dir, &CancelToken::new(), |done, total| {
+        if let Some(total) = total {
+            pb.set_length(total);
+        }
+        pb.set_position(done);
+    });
 
     pb.finish();
-
-    if !output.status.success() {
-        anyhow::bail!(
-            "Download failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
-
-    Ok(())
+    result
 }
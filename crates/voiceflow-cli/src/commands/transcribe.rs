@@ -0,0 +1,105 @@
+//! Transcribe command - transcribe an audio file
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use console::{style, Term};
+use std::path::Path;
+use voiceflow_core::audio::load_audio_file;
+use voiceflow_core::output::{to_srt, to_vtt};
+use voiceflow_core::{Config, Pipeline, RESULT_SCHEMA_VERSION};
+
+/// How the result is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The formatted text, with timings
+    Text,
+    /// The whole result as JSON
+    Json,
+    /// SubRip subtitles of the raw transcript
+    Srt,
+    /// WebVTT subtitles of the raw transcript
+    Vtt,
+}
+
+/// Transcribe `path`, printing the result to stdout and progress to stderr
+pub async fn run(
+    config: &Config,
+    path: &str,
+    context: Option<&str>,
+    raw: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let term = Term::stderr();
+    let file_path = Path::new(path);
+
+    term.write_line(&format!(
+        "{} Loading audio file: {}",
+        style("📁").cyan(),
+        path
+    ))?;
+
+    // Read audio file (WAV, AIFF or CAF, or compressed with the
+    // compressed-audio feature, which decodes to 16kHz mono)
+    let buffer = load_audio_file(file_path)
+        .with_context(|| format!("Failed to load audio file: {}", path))?;
+
+    let bits = match buffer.bits_per_sample {
+        0 => String::new(),
+        bits => format!(", Bits: {}", bits),
+    };
+    term.write_line(&format!(
+        "  Sample rate: {} Hz, Channels: {}{}",
+        buffer.sample_rate, buffer.channels, bits
+    ))?;
+
+    // Downmix and resample to 16kHz mono
+    let samples = buffer.as_input().to_16khz_mono()?;
+
+    let duration_secs = samples.len() as f32 / 16000.0;
+    term.write_line(&format!(
+        "  Duration: {:.1}s ({} samples at 16kHz)",
+        duration_secs,
+        samples.len()
+    ))?;
+
+    // Process
+    term.write_line(&format!("{} Processing...", style("⚙").cyan()))?;
+
+    let mut pipeline = Pipeline::new(config)?;
+
+    let result = if raw {
+        pipeline.transcribe_only(&samples)?
+    } else {
+        pipeline.process(&samples, context)?
+    };
+
+    // Output
+    let out = Term::stdout();
+    match format {
+        OutputFormat::Json => {
+            let json = serde_json::json!({ "schema_version": RESULT_SCHEMA_VERSION, "result": result });
+            out.write_line(&serde_json::to_string_pretty(&json)?)?;
+        }
+        OutputFormat::Srt => out.write_str(&to_srt(&result)?)?,
+        OutputFormat::Vtt => out.write_str(&to_vtt(&result)?)?,
+        OutputFormat::Text => {
+            term.write_line("")?;
+            if result.no_speech {
+                term.write_line(&format!("{} No speech detected", style("⚠").yellow()))?;
+                return Ok(());
+            }
+            out.write_line(&result.formatted_text)?;
+            term.write_line("")?;
+
+            term.write_line(&format!(
+                "{} Transcription: {}ms | LLM: {}ms | Total: {}ms",
+                style("⏱").dim(),
+                result.timings.transcription_ms,
+                result.timings.llm_formatting_ms,
+                result.timings.total_ms
+            ))?;
+        }
+    }
+
+    Ok(())
+}
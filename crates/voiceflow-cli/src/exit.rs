@@ -0,0 +1,77 @@
+//! Exit codes, so scripts can tell failures apart
+//!
+//! 2 is left to clap, which exits with it on invalid arguments.
+
+use voiceflow_core::audio::AudioFileError;
+use voiceflow_core::downloads::DownloadError;
+use voiceflow_core::models::storage::StorageError;
+use voiceflow_core::{ConfigError, PipelineError};
+
+/// Any failure without a more specific code
+pub const FAILURE: u8 = 1;
+/// The config file or a config value is invalid
+pub const CONFIG: u8 = 3;
+/// A model is missing, corrupt or in use
+pub const MODEL: u8 = 4;
+/// The audio can't be read or is too short
+pub const AUDIO: u8 = 5;
+/// A model download failed
+pub const DOWNLOAD: u8 = 6;
+
+/// Exit code for an error, from the first cause with a known type
+pub fn code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        if cause.downcast_ref::<ConfigError>().is_some() {
+            return CONFIG;
+        }
+        if let Some(e) = cause.downcast_ref::<PipelineError>() {
+            match e.without_context() {
+                PipelineError::SttModelNotFound { .. }
+                | PipelineError::LlmModelNotFound { .. }
+                | PipelineError::SpeakerModelNotFound { .. }
+                | PipelineError::ModelCorrupted { .. } => return MODEL,
+                PipelineError::DiarizationUnavailable | PipelineError::Config(_) => return CONFIG,
+                PipelineError::AudioTooShort { .. }
+                | PipelineError::EmptyAudio
+                | PipelineError::InvalidSamples { .. }
+                | PipelineError::AudioTooLong { .. }
+                | PipelineError::AudioFile(_) => return AUDIO,
+                PipelineError::Other(e) => return code(e),
+                _ => return FAILURE,
+            }
+        }
+        if cause.downcast_ref::<AudioFileError>().is_some() {
+            return AUDIO;
+        }
+        if cause.downcast_ref::<DownloadError>().is_some() {
+            return DOWNLOAD;
+        }
+        if let Some(StorageError::ModelInUse { .. }) = cause.downcast_ref::<StorageError>() {
+            return MODEL;
+        }
+    }
+    FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_follow_the_error_chain() {
+        let err = anyhow::Error::from(ConfigError::UnknownKey { key: "llm.colour".to_string() });
+        assert_eq!(code(&err.context("Failed to set key")), CONFIG);
+
+        let err = anyhow::Error::from(StorageError::ModelInUse { id: "whisper-base".to_string() });
+        assert_eq!(code(&err), MODEL);
+
+        let err = anyhow::Error::from(PipelineError::AudioTooLong { duration_secs: 3600, max_secs: 1800 });
+        assert_eq!(code(&err.context("Failed to transcribe")), AUDIO);
+
+        // As Pipeline::new returns it
+        let err = PipelineError::from(anyhow::Error::from(ConfigError::InvalidTopP { value: 1.5 }).context("Loading"));
+        assert_eq!(code(&err.into()), CONFIG);
+
+        assert_eq!(code(&anyhow::anyhow!("something else")), FAILURE);
+    }
+}
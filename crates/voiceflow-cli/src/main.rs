@@ -2,9 +2,15 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use console::style;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use voiceflow_core::Config;
 
 mod commands;
+mod exit;
+
+use commands::transcribe::OutputFormat;
 
 #[derive(Parser)]
 #[command(name = "voiceflow")]
@@ -22,11 +28,16 @@ struct Cli {
     /// Verbose output (show timings and debug info)
     #[arg(short, long, global = true)]
     verbose: bool,
+
+    /// Models directory to use instead of the configured one (not saved)
+    #[arg(long, global = true, value_name = "DIR")]
+    models_dir: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Record audio and transcribe (press Ctrl+C to stop)
+    #[cfg(feature = "capture")]
     Record {
         /// Copy output to clipboard instead of stdout
         #[arg(short = 'c', long)]
@@ -41,9 +52,42 @@ enum Commands {
         raw: bool,
     },
 
+    /// Transcribe from the microphone as you speak, stopping at a pause
+    #[cfg(feature = "capture")]
+    Listen {
+        /// Input device to capture from (see --list-devices); the default input if omitted
+        #[arg(long)]
+        device: Option<String>,
+
+        /// Capture until Ctrl+C instead of stopping at a pause
+        #[arg(long, conflicts_with = "silence_ms")]
+        push_to_talk: bool,
+
+        /// Silence (ms) after speech that stops the capture [default: audio.silence_duration_ms]
+        #[arg(long)]
+        silence_ms: Option<u32>,
+
+        /// Stop after this many seconds [default: audio.max_duration_secs]
+        #[arg(long)]
+        max_secs: Option<u32>,
+
+        /// List the input devices and exit
+        #[arg(long)]
+        list_devices: bool,
+
+        /// Context hint (email, slack, code, default)
+        #[arg(long)]
+        context: Option<String>,
+
+        /// Also copy the formatted text to the clipboard
+        #[arg(short = 'c', long)]
+        clipboard: bool,
+    },
+
     /// Transcribe an existing audio file
-    File {
-        /// Path to audio file (WAV, 16kHz mono preferred)
+    #[command(alias = "file")]
+    Transcribe {
+        /// Path to audio file (WAV, AIFF or CAF; M4A/AAC, MP3, FLAC or Ogg with the compressed-audio feature)
         path: String,
 
         /// Context hint
@@ -53,11 +97,16 @@ enum Commands {
         /// Skip LLM formatting
         #[arg(long)]
         raw: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: OutputFormat,
     },
 
     /// Download required models
     Setup {
-        /// Whisper model size (tiny, base, small, medium)
+        /// Whisper model size (tiny, base, small, medium, large-v3-turbo),
+        /// with -quantized or -int8 for a quantized variant
         #[arg(long, default_value = "base")]
         whisper: String,
 
@@ -74,17 +123,81 @@ enum Commands {
 
     /// Run benchmark on sample audio
     Bench {
+        /// Path to test audio file (any format `transcribe` reads); synthetic audio if omitted
+        path: Option<String>,
+
         /// Number of iterations
-        #[arg(short, long, default_value = "3")]
+        #[arg(short, long, default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
         iterations: u32,
 
-        /// Path to test audio file
-        #[arg(short, long)]
+        /// Path to test audio file (same as the positional path)
+        #[arg(short, long, hide = true, conflicts_with = "path")]
         file: Option<String>,
+
+        /// Prefill the whole prompt on every run, to compare with the
+        /// prompt's start kept from the warm-up
+        #[arg(long)]
+        no_prefix_cache: bool,
+    },
+
+    /// List, download and remove models (lists them without a subcommand)
+    Models {
+        #[command(subcommand)]
+        action: Option<ModelsAction>,
+    },
+
+    /// Measure accuracy (WER, CER) and latency on recordings with reference transcripts
+    #[cfg(feature = "eval")]
+    Eval {
+        /// JSON Lines manifest, one {"audio": "clip.wav", "reference": "..."} per line
+        manifest: String,
+
+        /// Print the full report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Serve transcription over HTTP (POST /v1/transcribe) for other devices
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on (0.0.0.0:8787 to accept other devices)
+        #[arg(long, default_value = "127.0.0.1:8787")]
+        listen: std::net::SocketAddr,
+
+        /// Require `Authorization: Bearer <token>` on requests
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Largest accepted audio upload, in MB
+        #[arg(long, default_value = "50")]
+        max_body_mb: usize,
+
+        /// Longest a request may take, in seconds
+        #[arg(long, default_value = "120")]
+        timeout_secs: u64,
     },
+}
 
+#[derive(Subcommand)]
+enum ModelsAction {
     /// List available models
-    Models,
+    List,
+
+    /// Download a model
+    Download {
+        /// Model id (whisper-base, whisper-small-int8, moonshine-tiny, qwen3-1.7b, ...)
+        id: String,
+    },
+
+    /// Delete a model's files
+    Remove {
+        /// Model id
+        id: String,
+
+        /// Remove the model even if it is the configured one
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -92,6 +205,21 @@ enum ConfigAction {
     /// Show current configuration
     Show,
 
+    /// Print a config value by dotted key (e.g. llm.temperature)
+    Get {
+        /// Config key
+        key: String,
+    },
+
+    /// Set a config value by dotted key and save it
+    Set {
+        /// Config key
+        key: String,
+
+        /// New value, in the format `config get` prints
+        value: String,
+    },
+
     /// Set the LLM model
     SetModel {
         /// Model name (qwen3-1.7b, smollm3-3b, gemma2-2b)
@@ -100,7 +228,8 @@ enum ConfigAction {
 
     /// Set the Whisper model size
     SetWhisper {
-        /// Model size (tiny, base, small, medium)
+        /// Model size (tiny, base, small, medium, large-v3-turbo), with
+        /// -quantized or -int8 for a quantized variant
         size: String,
     },
 
@@ -115,20 +244,35 @@ enum ConfigAction {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    // Initialize logging
+    // Initialize logging on stderr, so stdout only carries command output
     let log_level = if cli.verbose { "debug" } else { "info" };
     tracing_subscriber::fmt()
         .with_env_filter(log_level)
         .with_target(false)
+        .with_writer(std::io::stderr)
         .init();
 
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{} {:#}", style("✗").red(), e);
+            ExitCode::from(exit::code(&e))
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
     // Load configuration
     let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(dir) = &cli.models_dir {
+        config.models_dir_override = Some(dir.clone());
+    }
 
     match cli.command {
+        #[cfg(feature = "capture")]
         Commands::Record {
             clipboard,
             context,
@@ -137,38 +281,79 @@ async fn main() -> Result<()> {
             commands::record::run(&config, clipboard, context.as_deref(), raw).await
         }
 
-        Commands::File { path, context, raw } => {
-            commands::file::run(&config, &path, context.as_deref(), raw).await
+        #[cfg(feature = "capture")]
+        Commands::Listen { list_devices: true, .. } => commands::listen::list_devices(),
+
+        #[cfg(feature = "capture")]
+        Commands::Listen { device, push_to_talk, silence_ms, max_secs, list_devices: false, context, clipboard } => {
+            let options = commands::listen::ListenOptions {
+                device: device.as_deref(),
+                push_to_talk,
+                silence_ms,
+                max_secs,
+            };
+            commands::listen::run(&config, options, context.as_deref(), clipboard).await
+        }
+
+        Commands::Transcribe { path, context, raw, format } => {
+            commands::transcribe::run(&config, &path, context.as_deref(), raw, format).await
         }
 
         Commands::Setup { whisper, llm } => {
-            commands::setup::run(&whisper, &llm).await
+            commands::setup::run(&config.models_dir()?, &whisper, &llm).await
         }
 
         Commands::Config { action } => match action {
             ConfigAction::Show => {
                 commands::config::show(&config)
             }
+            ConfigAction::Get { key } => {
+                commands::config::get(&config, &key)
+            }
+            // Changes are saved, so start from the file without environment overrides
+            ConfigAction::Set { key, value } => {
+                let path = cli.config.as_deref();
+                commands::config::set(&mut Config::load_file(path)?, &key, &value, path)
+            }
             ConfigAction::SetModel { model } => {
-                commands::config::set_model(&mut config, &model)
+                commands::config::set_model(&mut Config::load_file(None)?, &model)
             }
             ConfigAction::SetWhisper { size } => {
-                commands::config::set_whisper(&mut config, &size)
+                commands::config::set_whisper(&mut Config::load_file(None)?, &size)
             }
             ConfigAction::AddWord { word } => {
-                commands::config::add_word(&mut config, &word)
+                commands::config::add_word(&mut Config::load_file(None)?, &word)
             }
             ConfigAction::Path => {
                 commands::config::show_path()
             }
         },
 
-        Commands::Bench { iterations, file } => {
-            commands::bench::run(&config, iterations, file.as_deref()).await
+        Commands::Bench { path, iterations, file, no_prefix_cache } => {
+            if no_prefix_cache {
+                config.llm_prefix_cache = false;
+            }
+            commands::bench::run(&config, iterations, path.or(file).as_deref()).await
         }
 
-        Commands::Models => {
-            commands::models::list()
+        Commands::Models { action } => match action.unwrap_or(ModelsAction::List) {
+            ModelsAction::List => {
+                commands::models::list(&config.models_dir()?)
+            }
+            ModelsAction::Download { id } => {
+                commands::models::download(&id, &config.models_dir()?)
+            }
+            ModelsAction::Remove { id, force } => {
+                commands::models::remove(&config, &id, &config.models_dir()?, force)
+            }
+        },
+
+        #[cfg(feature = "eval")]
+        Commands::Eval { manifest, json } => commands::eval::run(&config, &manifest, json).await,
+
+        #[cfg(feature = "server")]
+        Commands::Serve { listen, token, max_body_mb, timeout_secs } => {
+            commands::serve::run(&config, listen, token, max_body_mb, timeout_secs).await
         }
     }
 }
@@ -0,0 +1,89 @@
+//! End-to-end runs of the voiceflow binary against a throwaway config file
+//! and models directory; nothing here needs a downloaded model
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voiceflow-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn voiceflow(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_voiceflow"))
+        .env("VOICEFLOW_CONFIG", dir.join("config.toml"))
+        .arg("--models-dir")
+        .arg(dir.join("models"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// One second of a 440Hz tone as a 16kHz mono 16-bit WAV file
+fn write_wav(path: &Path) {
+    let samples: Vec<i16> =
+        (0..16000).map(|i| ((i as f32 * 440.0 * std::f32::consts::TAU / 16000.0).sin() * 8000.0) as i16).collect();
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&16000u32.to_le_bytes());
+    wav.extend_from_slice(&32000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    std::fs::write(path, wav).unwrap();
+}
+
+#[test]
+fn test_config_set_then_get() {
+    let dir = scratch_dir("config");
+    let set = voiceflow(&dir, &["config", "set", "llm.temperature", "0.5"]);
+    assert!(set.status.success(), "{}", String::from_utf8_lossy(&set.stderr));
+    assert!(dir.join("config.toml").exists());
+
+    let get = voiceflow(&dir, &["config", "get", "llm.temperature"]);
+    assert!(get.status.success());
+    assert_eq!(stdout(&get), "0.5");
+
+    let unknown = voiceflow(&dir, &["config", "get", "llm.colour"]);
+    assert_eq!(unknown.status.code(), Some(3));
+    assert!(stdout(&unknown).is_empty());
+}
+
+#[test]
+fn test_failures_have_distinct_exit_codes() {
+    let dir = scratch_dir("exit-codes");
+
+    let missing_audio = voiceflow(&dir, &["transcribe", "/nonexistent/memo.wav", "--format", "json"]);
+    assert_eq!(missing_audio.status.code(), Some(5));
+
+    let wav = dir.join("tone.wav");
+    write_wav(&wav);
+    let missing_model = voiceflow(&dir, &["transcribe", wav.to_str().unwrap(), "--format", "json"]);
+    assert_eq!(missing_model.status.code(), Some(4), "{}", String::from_utf8_lossy(&missing_model.stderr));
+    assert!(stdout(&missing_model).is_empty());
+
+    let unknown_model = voiceflow(&dir, &["models", "download", "whisper-gigantic"]);
+    assert_eq!(unknown_model.status.code(), Some(1));
+
+    let in_use = voiceflow(&dir, &["models", "remove", "whisper-base"]);
+    assert_eq!(in_use.status.code(), Some(4));
+
+    let bad_usage = voiceflow(&dir, &["bench", "--iterations", "0"]);
+    assert_eq!(bad_usage.status.code(), Some(2));
+}
@@ -1,144 +1,609 @@
-//! Microphone audio capture using cpal
+//! Microphone capture (`capture` feature): an input device at its native
+//! rate and format, downmixed and resampled to 16kHz mono as it arrives
+//!
+//! A `Microphone` captures until it's stopped (push-to-talk) or, in
+//! `CaptureMode::UntilSilence`, until the speaker has been quiet for a
+//! while after speaking. `streaming::listen` feeds it to a streaming
+//! session. The audio comes from an `InputHost`: cpal's default host
+//! (CoreAudio, WASAPI, ALSA), or a stand-in in tests.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::sync::mpsc::{self, Receiver};
-use std::sync::{Arc, Mutex};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+use serde::Serialize;
+
+use super::input::TARGET_SAMPLE_RATE;
+use super::resample::{downmix_to_mono_into, StreamResampler};
+use crate::config::AudioOptions;
+use crate::streaming::Endpointer;
+
+/// An audio input device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InputDevice {
+    pub name: String,
+    /// Native sample rate of its default configuration
+    pub sample_rate: u32,
+    /// Native channel count of its default configuration
+    pub channels: u16,
+    /// Whether it's the system's default input
+    pub is_default: bool,
+}
 
-/// Events from audio capture
+/// What an input stream passes on to its `Microphone`
 #[derive(Debug)]
-pub enum AudioCaptureEvent {
-    /// Audio samples received
+pub enum HostEvent {
+    /// Interleaved samples at the device's native rate and channel count
     Samples(Vec<f32>),
-    /// Capture error
+    /// The stream failed, e.g. the device was unplugged
     Error(String),
-    /// Capture stopped
-    Stopped,
 }
 
-/// Audio capture from the default input device
-pub struct AudioCapture {
-    stream: Option<cpal::Stream>,
-    sample_rate: u32,
-    receiver: Receiver<AudioCaptureEvent>,
-    buffer: Arc<Mutex<Vec<f32>>>,
+/// Where input devices and their audio come from
+pub trait InputHost {
+    /// The input devices, the default first
+    fn input_devices(&self) -> Result<Vec<InputDevice>>;
+
+    /// Start capturing from the device named `name`, or the default input,
+    /// sending its audio to `events` until the returned stream is dropped
+    fn open(&self, name: Option<&str>, events: Sender<HostEvent>) -> Result<(InputDevice, Box<dyn Any>)>;
+}
+
+/// The input devices of cpal's default host, the default first
+pub fn list_input_devices() -> Result<Vec<InputDevice>> {
+    CpalHost::default().input_devices()
+}
+
+/// Error for a device name that isn't among `available`
+fn unknown_device(name: &str, available: impl IntoIterator<Item = String>) -> anyhow::Error {
+    let available: Vec<String> = available.into_iter().map(|name| format!("{:?}", name)).collect();
+    anyhow::anyhow!("No input device named {:?} (available: {})", name, available.join(", "))
+}
+
+/// cpal's default host
+pub struct CpalHost(cpal::Host);
+
+impl Default for CpalHost {
+    fn default() -> Self {
+        Self(cpal::default_host())
+    }
+}
+
+impl CpalHost {
+    fn default_name(&self) -> Option<String> {
+        self.0.default_input_device().and_then(|device| device.name().ok())
+    }
 }
 
-impl AudioCapture {
-    /// Create a new audio capture instance
-    pub fn new() -> Result<Self> {
-        let host = cpal::default_host();
-        let device = host
-            .default_input_device()
-            .context("No input device available")?;
+/// Describe a cpal device from its name and default input configuration
+fn describe(device: &cpal::Device, default_name: Option<&str>) -> Result<InputDevice> {
+    let name = device.name()?;
+    let config = device.default_input_config()?;
+    Ok(InputDevice {
+        is_default: default_name == Some(name.as_str()),
+        name,
+        sample_rate: config.sample_rate().0,
+        channels: config.channels(),
+    })
+}
 
-        tracing::info!("Using input device: {}", device.name().unwrap_or_default());
+impl InputHost for CpalHost {
+    fn input_devices(&self) -> Result<Vec<InputDevice>> {
+        let default_name = self.default_name();
+        // Devices that can't report an input configuration can't be opened
+        let mut devices: Vec<InputDevice> = self
+            .0
+            .input_devices()
+            .context("Failed to list input devices")?
+            .filter_map(|device| describe(&device, default_name.as_deref()).ok())
+            .collect();
+        devices.sort_by_key(|device| !device.is_default);
+        Ok(devices)
+    }
 
-        let config = device
-            .default_input_config()
-            .context("Failed to get default input config")?;
+    fn open(&self, name: Option<&str>, events: Sender<HostEvent>) -> Result<(InputDevice, Box<dyn Any>)> {
+        let device = match name {
+            None => self.0.default_input_device().context("No input device available")?,
+            Some(name) => {
+                let mut devices = self.0.input_devices().context("Failed to list input devices")?;
+                match devices.find(|device| device.name().is_ok_and(|n| n == name)) {
+                    Some(device) => device,
+                    None => return Err(unknown_device(name, self.input_devices()?.into_iter().map(|d| d.name))),
+                }
+            }
+        };
+        let info = describe(&device, self.default_name().as_deref())?;
+        let config = device.default_input_config().context("Failed to get the input config")?;
+        let format = config.sample_format();
+        let config: cpal::StreamConfig = config.into();
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, events),
+            SampleFormat::F64 => build_stream::<f64>(&device, &config, events),
+            SampleFormat::I8 => build_stream::<i8>(&device, &config, events),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, events),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, events),
+            SampleFormat::U8 => build_stream::<u8>(&device, &config, events),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, events),
+            SampleFormat::U32 => build_stream::<u32>(&device, &config, events),
+            format => anyhow::bail!("Unsupported sample format: {:?}", format),
+        }?;
+        stream.play().context("Failed to start the input stream")?;
+        Ok((info, Box::new(stream)))
+    }
+}
 
-        let sample_rate = config.sample_rate().0;
-        tracing::info!("Input sample rate: {} Hz", sample_rate);
+/// An input stream sending its samples as f32 to `events`
+fn build_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, events: Sender<HostEvent>) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let errors = events.clone();
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let _ = events.send(HostEvent::Samples(data.iter().map(|&s| f32::from_sample(s)).collect()));
+        },
+        move |err| {
+            let _ = errors.send(HostEvent::Error(err.to_string()));
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// When a capture ends by itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaptureMode {
+    /// Never: only when stopped, e.g. when the push-to-talk key is released
+    #[default]
+    PushToTalk,
+    /// Once `silence_ms` of silence follow speech (or when stopped)
+    UntilSilence { silence_ms: u32 },
+}
 
-        let (_sender, receiver) = mpsc::channel();
-        let buffer = Arc::new(Mutex::new(Vec::new()));
+/// Settings of a capture
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    /// Input device by name (see `list_input_devices`); the default input
+    /// when None
+    pub device: Option<String>,
+    pub mode: CaptureMode,
+    /// Stop after this long even while speaking (0 for no limit)
+    pub max_duration_ms: u32,
+    /// RMS level of a 30ms frame that counts as speech
+    pub vad_threshold: f32,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self::new(&AudioOptions::default())
+    }
+}
 
+impl CaptureOptions {
+    /// Push-to-talk on the default input, with the speech threshold and
+    /// longest recording of the audio settings
+    pub fn new(audio: &AudioOptions) -> Self {
+        Self {
+            device: None,
+            mode: CaptureMode::PushToTalk,
+            max_duration_ms: audio.max_duration_secs.saturating_mul(1000),
+            vad_threshold: audio.vad_threshold,
+        }
+    }
+}
+
+/// Why a capture stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// `Microphone::stop` or a `CaptureStop`
+    Requested,
+    /// The trailing silence of `CaptureMode::UntilSilence`
+    Silence,
+    /// `CaptureOptions::max_duration_ms` was reached
+    MaxDuration,
+    /// The input stream failed
+    DeviceFailed,
+}
+
+/// Where a capture is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureState {
+    /// Capturing, no speech yet
+    Listening,
+    /// Capturing, speech heard
+    Speaking,
+    /// Stopped; what was captured before is still returned by `read`
+    Stopped(StopReason),
+}
+
+/// Stops a capture from another thread, e.g. a Ctrl+C handler or the
+/// push-to-talk key being released
+#[derive(Debug, Clone, Default)]
+pub struct CaptureStop(Arc<AtomicBool>);
+
+impl CaptureStop {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Audio from an input device as 16kHz mono, until the capture stops
+///
+/// The device's stream runs from `open` until the capture stops; `read`
+/// returns what it captured since the last call.
+pub struct Microphone {
+    device: InputDevice,
+    /// Keeps the input stream running; dropped when the capture stops
+    stream: Option<Box<dyn Any>>,
+    events: Receiver<HostEvent>,
+    stop: CaptureStop,
+    /// None once flushed at the end of the capture
+    resampler: Option<StreamResampler>,
+    mono: Vec<f32>,
+    endpointer: Endpointer,
+    state: CaptureState,
+    /// 16kHz samples returned so far
+    captured: usize,
+    /// Longest capture in 16kHz samples (0 for no limit)
+    max_samples: usize,
+}
+
+impl std::fmt::Debug for Microphone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Microphone")
+            .field("device", &self.device)
+            .field("state", &self.state)
+            .field("captured", &self.captured)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Microphone {
+    /// Start capturing with cpal's default host
+    pub fn open(options: &CaptureOptions) -> Result<Self> {
+        Self::open_with(&CpalHost::default(), options)
+    }
+
+    /// Start capturing from a device of `host`
+    pub fn open_with(host: &dyn InputHost, options: &CaptureOptions) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let (device, stream) = host.open(options.device.as_deref(), sender)?;
+        tracing::info!(
+            "Capturing from {:?}: {} Hz, {} channel(s)",
+            device.name,
+            device.sample_rate,
+            device.channels
+        );
+        let resampler = StreamResampler::new(device.sample_rate)?;
+        let silence_ms = match options.mode {
+            CaptureMode::PushToTalk => None,
+            CaptureMode::UntilSilence { silence_ms } => Some(silence_ms),
+        };
         Ok(Self {
-            stream: None,
-            sample_rate,
-            receiver,
-            buffer,
+            device,
+            stream: Some(stream),
+            events,
+            stop: CaptureStop::default(),
+            resampler: Some(resampler),
+            mono: Vec::new(),
+            endpointer: Endpointer::new(options.vad_threshold, silence_ms, None),
+            state: CaptureState::Listening,
+            captured: 0,
+            max_samples: options.max_duration_ms as usize * (TARGET_SAMPLE_RATE as usize / 1000),
         })
     }
 
-    /// Get the input sample rate
-    pub fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    /// The device being captured
+    pub fn device(&self) -> &InputDevice {
+        &self.device
     }
 
-    /// Start recording audio
-    pub fn start(&mut self) -> Result<()> {
-        let host = cpal::default_host();
-        let device = host
-            .default_input_device()
-            .context("No input device available")?;
+    pub fn state(&self) -> CaptureState {
+        self.state
+    }
 
-        let config = device.default_input_config()?;
-        let sample_format = config.sample_format();
-        let config: cpal::StreamConfig = config.into();
+    /// A handle stopping the capture from another thread
+    pub fn stopper(&self) -> CaptureStop {
+        self.stop.clone()
+    }
 
-        let buffer = Arc::clone(&self.buffer);
-        let (sender, receiver) = mpsc::channel();
-        self.receiver = receiver;
-
-        let err_sender = sender.clone();
-
-        let stream = match sample_format {
-            cpal::SampleFormat::F32 => device.build_input_stream(
-                &config,
-                move |data: &[f32], _: &_| {
-                    let mut buf = buffer.lock().unwrap();
-                    buf.extend_from_slice(data);
-                },
-                move |err| {
-                    let _ = err_sender.send(AudioCaptureEvent::Error(err.to_string()));
-                },
-                None,
-            )?,
-            cpal::SampleFormat::I16 => {
-                let buffer = Arc::clone(&self.buffer);
-                device.build_input_stream(
-                    &config,
-                    move |data: &[i16], _: &_| {
-                        let samples: Vec<f32> = data
-                            .iter()
-                            .map(|&s| s as f32 / i16::MAX as f32)
-                            .collect();
-                        let mut buf = buffer.lock().unwrap();
-                        buf.extend_from_slice(&samples);
-                    },
-                    move |err| {
-                        let _ = sender.send(AudioCaptureEvent::Error(err.to_string()));
-                    },
-                    None,
-                )?
+    /// Stop capturing; `read` still returns what was captured before
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Wait up to `timeout` for audio and return it as 16kHz mono (empty if
+    /// none came)
+    ///
+    /// Returns None once the capture has stopped and all of it was read. A
+    /// failing input stream stops the capture with an error.
+    pub fn read(&mut self, timeout: Duration) -> Result<Option<Vec<f32>>> {
+        let mut output = Vec::new();
+        if self.resampler.is_none() {
+            return Ok(None);
+        }
+        if self.stop.is_stopped() {
+            self.finish(StopReason::Requested, &mut output)?;
+            return Ok(Some(output));
+        }
+
+        let mut event = match self.events.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.finish(StopReason::DeviceFailed, &mut output)?;
+                anyhow::bail!("The input stream of {:?} ended", self.device.name);
             }
-            _ => anyhow::bail!("Unsupported sample format: {:?}", sample_format),
         };
+        // Everything queued since, so a slow reader catches up
+        while let Some(next) = event {
+            match next {
+                HostEvent::Samples(samples) => self.convert(&samples, &mut output)?,
+                HostEvent::Error(message) => {
+                    self.finish(StopReason::DeviceFailed, &mut output)?;
+                    anyhow::bail!("Input device {:?} failed: {}", self.device.name, message);
+                }
+            }
+            event = self.events.try_recv().ok();
+        }
+        self.check_end(&mut output)?;
+        Ok(Some(output))
+    }
 
-        stream.play()?;
-        self.stream = Some(stream);
-        self.buffer.lock().unwrap().clear();
+    /// Downmix and resample native samples, appending them to `output`
+    fn convert(&mut self, samples: &[f32], output: &mut Vec<f32>) -> Result<()> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+        downmix_to_mono_into(samples, self.device.channels as usize, &mut self.mono);
+        resampler.push(&self.mono, output)
+    }
 
-        tracing::info!("Audio capture started");
+    /// Stop the capture where the endpointer or the length limit ends it
+    fn check_end(&mut self, output: &mut Vec<f32>) -> Result<()> {
+        if let Some((end, _)) = self.endpointer.push(output) {
+            output.truncate(end);
+            self.captured += output.len();
+            return self.stop_at(StopReason::Silence);
+        }
+        if self.max_samples > 0 && self.captured + output.len() >= self.max_samples {
+            output.truncate(self.max_samples - self.captured);
+            self.captured += output.len();
+            return self.stop_at(StopReason::MaxDuration);
+        }
+        self.captured += output.len();
+        if self.endpointer.speaking() {
+            self.state = CaptureState::Speaking;
+        }
+        Ok(())
+    }
+
+    /// End the capture where it was cut, dropping anything captured after
+    fn stop_at(&mut self, reason: StopReason) -> Result<()> {
+        self.stream = None;
+        self.resampler = None;
+        self.state = CaptureState::Stopped(reason);
+        tracing::info!("Capture stopped ({:?}) after {} samples", reason, self.captured);
         Ok(())
     }
 
-    /// Stop recording and return captured samples
-    pub fn stop(&mut self) -> Result<Vec<f32>> {
-        if let Some(stream) = self.stream.take() {
-            drop(stream);
+    /// Stop the input stream and append the rest of what it captured
+    fn finish(&mut self, reason: StopReason, output: &mut Vec<f32>) -> Result<()> {
+        self.stream = None;
+        while let Ok(HostEvent::Samples(samples)) = self.events.try_recv() {
+            self.convert(&samples, output)?;
         }
+        if let Some(resampler) = self.resampler.take() {
+            resampler.finish(output)?;
+        }
+        if self.max_samples > 0 {
+            output.truncate(self.max_samples.saturating_sub(self.captured));
+        }
+        self.captured += output.len();
+        self.state = CaptureState::Stopped(reason);
+        tracing::info!("Capture stopped ({:?}) after {} samples", reason, self.captured);
+        Ok(())
+    }
+}
 
-        let samples = std::mem::take(&mut *self.buffer.lock().unwrap());
-        tracing::info!("Audio capture stopped, {} samples", samples.len());
-        Ok(samples)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::rms;
+    use crate::streaming::FRAME_SAMPLES;
+    use std::sync::Mutex;
+
+    /// Stand-in for cpal: devices to list, and the open streams' senders to
+    /// push audio through
+    #[derive(Default)]
+    struct MockHost {
+        devices: Vec<InputDevice>,
+        streams: Mutex<Vec<(Sender<HostEvent>, Arc<AtomicBool>)>>,
     }
 
-    /// Check if currently recording
-    pub fn is_recording(&self) -> bool {
-        self.stream.is_some()
+    /// Sets its flag when dropped, like a cpal stream stopping
+    struct MockStream(Arc<AtomicBool>);
+
+    impl Drop for MockStream {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
     }
 
-    /// Get events from the capture (non-blocking)
-    pub fn try_recv(&self) -> Option<AudioCaptureEvent> {
-        self.receiver.try_recv().ok()
+    impl MockHost {
+        fn new() -> Self {
+            let device = |name: &str, sample_rate, channels, is_default| InputDevice {
+                name: name.to_string(),
+                sample_rate,
+                channels,
+                is_default,
+            };
+            Self {
+                devices: vec![device("Built-in Microphone", 48000, 2, true), device("USB Mic", 16000, 1, false)],
+                streams: Mutex::default(),
+            }
+        }
+
+        fn send(&self, samples: Vec<f32>) {
+            self.streams.lock().unwrap().last().unwrap().0.send(HostEvent::Samples(samples)).unwrap();
+        }
+
+        fn fail(&self, message: &str) {
+            self.streams.lock().unwrap().last().unwrap().0.send(HostEvent::Error(message.to_string())).unwrap();
+        }
+
+        fn stream_stopped(&self) -> bool {
+            self.streams.lock().unwrap().last().unwrap().1.load(Ordering::SeqCst)
+        }
+    }
+
+    impl InputHost for MockHost {
+        fn input_devices(&self) -> Result<Vec<InputDevice>> {
+            Ok(self.devices.clone())
+        }
+
+        fn open(&self, name: Option<&str>, events: Sender<HostEvent>) -> Result<(InputDevice, Box<dyn Any>)> {
+            let device = match name {
+                None => self.devices.iter().find(|device| device.is_default),
+                Some(name) => self.devices.iter().find(|device| device.name == name),
+            };
+            let device = device.cloned().ok_or_else(|| {
+                unknown_device(name.unwrap_or_default(), self.devices.iter().map(|d| d.name.clone()))
+            })?;
+            let stopped = Arc::new(AtomicBool::new(false));
+            self.streams.lock().unwrap().push((events, Arc::clone(&stopped)));
+            Ok((device, Box::new(MockStream(stopped))))
+        }
+    }
+
+    const WAIT: Duration = Duration::from_millis(10);
+
+    /// `ms` of interleaved audio at `rate` and `channels`, a tone or silence
+    fn audio(ms: usize, rate: usize, channels: usize, level: f32) -> Vec<f32> {
+        (0..ms * rate / 1000)
+            .flat_map(|i| std::iter::repeat_n((i as f32 * 0.1).sin() * level, channels))
+            .collect()
+    }
+
+    fn options(mode: CaptureMode) -> CaptureOptions {
+        CaptureOptions { mode, max_duration_ms: 0, vad_threshold: 0.01, ..CaptureOptions::default() }
+    }
+
+    /// Read until the capture ends, returning everything it captured
+    fn read_all(microphone: &mut Microphone) -> Vec<f32> {
+        let mut captured = Vec::new();
+        while let Some(samples) = microphone.read(WAIT).unwrap() {
+            captured.extend(samples);
+        }
+        captured
+    }
+
+    #[test]
+    fn test_devices_are_listed_and_selected_by_name() {
+        let host = MockHost::new();
+        assert_eq!(host.input_devices().unwrap()[0].name, "Built-in Microphone");
+
+        let microphone = Microphone::open_with(&host, &options(CaptureMode::PushToTalk)).unwrap();
+        assert_eq!(microphone.device().name, "Built-in Microphone");
+        let usb = CaptureOptions { device: Some("USB Mic".to_string()), ..options(CaptureMode::PushToTalk) };
+        assert_eq!(Microphone::open_with(&host, &usb).unwrap().device().sample_rate, 16000);
+
+        let missing = CaptureOptions { device: Some("Headset".to_string()), ..usb };
+        let err = Microphone::open_with(&host, &missing).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"No input device named "Headset" (available: "Built-in Microphone", "USB Mic")"#
+        );
+    }
+
+    #[test]
+    fn test_push_to_talk_runs_until_stopped() {
+        let host = MockHost::new();
+        let mut microphone = Microphone::open_with(&host, &options(CaptureMode::PushToTalk)).unwrap();
+        assert_eq!(microphone.read(WAIT).unwrap(), Some(vec![]));
+        assert_eq!(microphone.state(), CaptureState::Listening);
+
+        // 48kHz stereo in pieces, then a long silence that doesn't stop it
+        let speech = audio(500, 48000, 2, 0.5);
+        for piece in speech.chunks(960) {
+            host.send(piece.to_vec());
+        }
+        host.send(audio(2000, 48000, 2, 0.0));
+        let mut captured = microphone.read(WAIT).unwrap().unwrap();
+        assert_eq!(microphone.state(), CaptureState::Speaking);
+        assert!(!host.stream_stopped());
+
+        let stop = microphone.stopper();
+        host.send(audio(100, 48000, 2, 0.0));
+        stop.stop();
+        captured.extend(read_all(&mut microphone));
+        assert!(host.stream_stopped());
+        assert_eq!(microphone.state(), CaptureState::Stopped(StopReason::Requested));
+        // All 2.6s, at 16kHz mono
+        assert_eq!(captured.len(), 2600 * 16);
+        assert!(rms(&captured[800..7200]) > 0.3);
+    }
+
+    #[test]
+    fn test_until_silence_stops_after_the_trailing_silence() {
+        let host = MockHost::new();
+        let mode = CaptureMode::UntilSilence { silence_ms: 300 };
+        let usb = CaptureOptions { device: Some("USB Mic".to_string()), ..options(mode) };
+        let mut microphone = Microphone::open_with(&host, &usb).unwrap();
+
+        // Silence before speaking doesn't end it
+        host.send(audio(1000, 16000, 1, 0.0));
+        assert_eq!(microphone.read(WAIT).unwrap().map(|s| s.len()), Some(16000));
+        assert_eq!(microphone.state(), CaptureState::Listening);
+
+        // A short pause doesn't either, in pieces smaller than a frame
+        let mut speech = audio(600, 16000, 1, 0.5);
+        speech.extend(audio(150, 16000, 1, 0.0));
+        speech.extend(audio(600, 16000, 1, 0.5));
+        speech.extend(audio(1000, 16000, 1, 0.0));
+        for piece in speech.chunks(100) {
+            host.send(piece.to_vec());
+        }
+        let captured = read_all(&mut microphone);
+        assert_eq!(microphone.state(), CaptureState::Stopped(StopReason::Silence));
+        assert!(host.stream_stopped());
+        // Through the 10 frames of silence after the frame the speech ends in
+        let speech_end: usize = 16000 + 1350 * 16;
+        assert_eq!(16000 + captured.len(), speech_end.next_multiple_of(FRAME_SAMPLES) + 10 * FRAME_SAMPLES);
+        assert_eq!(microphone.read(WAIT).unwrap(), None);
+    }
+
+    #[test]
+    fn test_max_duration_stops_while_speaking() {
+        let host = MockHost::new();
+        let limited = CaptureOptions { max_duration_ms: 1000, ..options(CaptureMode::UntilSilence { silence_ms: 300 }) };
+        let mut microphone = Microphone::open_with(&host, &limited).unwrap();
+        host.send(audio(3000, 48000, 2, 0.5));
+        assert_eq!(read_all(&mut microphone).len(), 16000);
+        assert_eq!(microphone.state(), CaptureState::Stopped(StopReason::MaxDuration));
+        assert!(host.stream_stopped());
     }
-}
 
-impl Drop for AudioCapture {
-    fn drop(&mut self) {
-        let _ = self.stop();
+    #[test]
+    fn test_device_failure_stops_the_capture() {
+        let host = MockHost::new();
+        let mut microphone = Microphone::open_with(&host, &options(CaptureMode::PushToTalk)).unwrap();
+        host.fail("device unplugged");
+        let err = microphone.read(WAIT).unwrap_err();
+        assert_eq!(err.to_string(), r#"Input device "Built-in Microphone" failed: device unplugged"#);
+        assert_eq!(microphone.state(), CaptureState::Stopped(StopReason::DeviceFailed));
+        assert_eq!(microphone.read(WAIT).unwrap(), None);
     }
 }
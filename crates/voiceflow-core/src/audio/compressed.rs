@@ -0,0 +1,311 @@
+//! Compressed audio files: M4A/AAC, MP3, FLAC and Ogg Vorbis, decoded with
+//! Symphonia (`compressed-audio` feature)
+//!
+//! Files are decoded a packet at a time, and each packet is downmixed and
+//! resampled to 16kHz mono before the next is read, so an hour of 48kHz
+//! stereo never sits in memory at its own rate, only the 16kHz result.
+
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use super::file::malformed;
+use super::input::TARGET_SAMPLE_RATE;
+use super::resample::{downmix_to_mono_into, StreamResampler};
+use super::{AudioBuffer, AudioFileError};
+
+/// Sample entries and boxes only found in encrypted MP4 audio: FairPlay
+/// (`drms`, `drmi`) and Common Encryption (`enca`, and the `sinf` box naming
+/// the scheme)
+const PROTECTION_MARKERS: [&[u8; 4]; 4] = [b"drms", b"drmi", b"enca", b"sinf"];
+
+/// Most of a `moov` box read when looking for protection; it grows by a few
+/// bytes per packet, so this covers many hours of audio
+const MAX_MOOV_BYTES: u64 = 64 << 20;
+
+/// Decode a compressed file to 16kHz mono
+///
+/// `format` names the container for errors ("M4A", "MP3", ...). Corrupt
+/// packets are skipped with a warning; the file only fails if none decode.
+pub(super) fn decode<S: MediaSource + 'static>(
+    mut source: S,
+    format: &'static str,
+    extension: &str,
+) -> Result<AudioBuffer, AudioFileError> {
+    let read_error = |e: std::io::Error| malformed(format, &e.to_string());
+    if format == "M4A" && is_protected(&mut source).map_err(read_error)? {
+        return Err(AudioFileError::Protected(format));
+    }
+    source.seek(SeekFrom::Start(0)).map_err(read_error)?;
+
+    let mut hint = Hint::new();
+    if !extension.is_empty() {
+        hint.with_extension(extension);
+    }
+    let stream = MediaSourceStream::new(Box::new(source), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| match e {
+            SymphoniaError::Unsupported(_) => malformed(format, "no audio stream found"),
+            e => error(format, e),
+        })?;
+    let mut reader = probed.format;
+
+    let track = reader
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| malformed(format, "no audio track"))?;
+    let track_id = track.id;
+    let expected_frames = track.codec_params.n_frames.filter(|&frames| frames > 0);
+    let bits_per_sample = track.codec_params.bits_per_sample.unwrap_or(0) as u16;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|_| AudioFileError::UnsupportedEncoding(format!("{} audio (expected AAC, MP3, FLAC or Vorbis)", format)))?;
+
+    let mut resampler: Option<(u32, StreamResampler)> = None;
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    let mut mono = Vec::new();
+    let mut output = Vec::new();
+    let (mut decoded_packets, mut corrupt_packets, mut decoded_frames) = (0usize, 0usize, 0u64);
+    let resample_error = |e: anyhow::Error| malformed(format, &e.to_string());
+
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            // The end of the stream, or of a truncated file
+            Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(error(format, e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Skip a corrupt packet, as players do, rather than the file
+            Err(SymphoniaError::DecodeError(message)) => {
+                tracing::warn!("Skipping corrupt {} packet at {}: {}", format, packet.ts(), message);
+                corrupt_packets += 1;
+                continue;
+            }
+            Err(e) => return Err(error(format, e)),
+        };
+        decoded_packets += 1;
+        decoded_frames += decoded.frames() as u64;
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+        if samples.as_ref().is_some_and(|buffer| buffer.capacity() < decoded.capacity() * channels) {
+            samples = None;
+        }
+        let buffer = samples.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        buffer.copy_interleaved_ref(decoded);
+        downmix_to_mono_into(buffer.samples(), channels, &mut mono);
+
+        // Chained Ogg streams can change rate midway
+        let stream = match resampler.take() {
+            Some((rate, stream)) if rate == spec.rate => stream,
+            previous => {
+                if let Some((_, previous)) = previous {
+                    previous.finish(&mut output).map_err(resample_error)?;
+                }
+                tracing::debug!("Decoding {}: {} Hz, {} channel(s)", format, spec.rate, channels);
+                StreamResampler::new(spec.rate).map_err(resample_error)?
+            }
+        };
+        let (_, stream) = resampler.insert((spec.rate, stream));
+        stream.push(&mono, &mut output).map_err(resample_error)?;
+    }
+    if let Some((_, stream)) = resampler {
+        stream.finish(&mut output).map_err(resample_error)?;
+    }
+
+    // Nothing decoded from a file that says it has audio: cut off before
+    // the first packet, or every packet damaged
+    if decoded_packets == 0 && (corrupt_packets > 0 || expected_frames.is_some()) {
+        return Err(malformed(format, "no audio could be decoded (truncated or corrupt)"));
+    }
+    if let Some(expected) = expected_frames.filter(|&expected| decoded_frames < expected) {
+        tracing::warn!(
+            "The {} file ends after {} of its {} frames; decoding what's there",
+            format,
+            decoded_frames,
+            expected
+        );
+    }
+    if corrupt_packets > 0 {
+        tracing::warn!(
+            "Skipped {} corrupt packet(s) of {} in the {} file",
+            corrupt_packets,
+            decoded_packets + corrupt_packets,
+            format
+        );
+    }
+    if output.is_empty() {
+        return Err(AudioFileError::Empty);
+    }
+
+    Ok(AudioBuffer {
+        samples: output,
+        sample_rate: TARGET_SAMPLE_RATE,
+        channels: 1,
+        bits_per_sample,
+    })
+}
+
+/// A Symphonia error as a file error
+fn error(format: &'static str, e: SymphoniaError) -> AudioFileError {
+    match e {
+        SymphoniaError::IoError(e) if e.kind() == ErrorKind::UnexpectedEof => {
+            malformed(format, "unexpected end of file")
+        }
+        SymphoniaError::Unsupported(feature) => {
+            AudioFileError::UnsupportedEncoding(format!("{} ({})", format, feature))
+        }
+        e => malformed(format, &e.to_string()),
+    }
+}
+
+/// Whether an MP4 file's audio is encrypted, from the sample entries in its
+/// `moov` box
+fn is_protected(source: &mut (impl Read + Seek)) -> std::io::Result<bool> {
+    source.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 8];
+    loop {
+        match source.read_exact(&mut header) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            result => result?,
+        }
+        let (size, header_len) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            // 64-bit size after the type
+            1 => {
+                let mut size = [0u8; 8];
+                source.read_exact(&mut size)?;
+                (u64::from_be_bytes(size), 16)
+            }
+            // To the end of the file
+            0 => (u64::MAX, 8),
+            size => (size as u64, 8),
+        };
+        if size < header_len {
+            return Ok(false);
+        }
+        if &header[4..] == b"moov" {
+            let mut moov = Vec::new();
+            (&mut *source).take((size - header_len).min(MAX_MOOV_BYTES)).read_to_end(&mut moov)?;
+            return Ok(moov.windows(4).any(|tag| PROTECTION_MARKERS.iter().any(|marker| tag == &marker[..])));
+        }
+        if size == u64::MAX {
+            return Ok(false);
+        }
+        source.seek(SeekFrom::Current((size - header_len) as i64))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{decode_audio, load_audio_file};
+    use std::path::{Path, PathBuf};
+
+    // Built by testdata/compressed/generate.py
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/compressed").join(name)
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_flac_is_downmixed_and_resampled() {
+        // 0.25s of 22.05kHz stereo, a tone at half scale left and quarter right
+        let buf = load_audio_file(&fixture("tone.flac")).unwrap();
+        assert_eq!((buf.sample_rate, buf.channels, buf.bits_per_sample), (16000, 1, 16));
+        assert_eq!(buf.samples.len(), 4000);
+        let peak = buf.samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.375).abs() < 0.01, "peak {}", peak);
+        assert!((rms(&buf.samples[200..3800]) - 0.375 / 2f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_mp3() {
+        // 28 frames of 1152 samples at 32kHz
+        let buf = load_audio_file(&fixture("silence.mp3")).unwrap();
+        assert_eq!((buf.sample_rate, buf.channels), (16000, 1));
+        assert_eq!(buf.samples.len(), 28 * 1152 / 2);
+        assert!(buf.samples.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_m4a() {
+        // 16 AAC frames of 1024 samples at 16kHz
+        let buf = load_audio_file(&fixture("silence.m4a")).unwrap();
+        assert_eq!((buf.sample_rate, buf.channels), (16000, 1));
+        assert_eq!(buf.samples.len(), 16 * 1024);
+        assert!(rms(&buf.samples) < 1e-4);
+    }
+
+    #[test]
+    fn test_ogg_vorbis() {
+        let buf = load_audio_file(&fixture("silence.ogg")).unwrap();
+        assert_eq!((buf.sample_rate, buf.channels), (16000, 1));
+        assert_eq!(buf.samples.len(), 16000);
+        assert!(buf.samples.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_in_memory_matches_file() {
+        let bytes = std::fs::read(fixture("tone.flac")).unwrap();
+        let from_memory = decode_audio(&bytes, "").unwrap();
+        assert_eq!(from_memory.samples, load_audio_file(&fixture("tone.flac")).unwrap().samples);
+    }
+
+    #[test]
+    fn test_protected_m4a() {
+        // FairPlay-encrypted files have a `drms` sample entry in place of `mp4a`
+        let mut bytes = std::fs::read(fixture("silence.m4a")).unwrap();
+        let entry = bytes.windows(4).position(|tag| tag == b"mp4a").unwrap();
+        bytes[entry..entry + 4].copy_from_slice(b"drms");
+        let err = decode_audio(&bytes, "m4a").unwrap_err();
+        assert!(matches!(err, AudioFileError::Protected("M4A")), "{:?}", err);
+        assert_eq!(err.to_string(), "M4A file is DRM-protected and can't be decoded");
+    }
+
+    #[test]
+    fn test_truncated_files() {
+        for (name, cut) in [("silence.m4a", 300), ("tone.flac", 8000), ("silence.ogg", 200)] {
+            let bytes = std::fs::read(fixture(name)).unwrap();
+            let err = decode_audio(&bytes[..cut], "").unwrap_err();
+            assert!(matches!(err, AudioFileError::Malformed { .. }), "{}: {:?}", name, err);
+        }
+
+        // A cut-off MP3 keeps the frames before the cut
+        let bytes = std::fs::read(fixture("silence.mp3")).unwrap();
+        assert_eq!(decode_audio(&bytes[..144 * 10], "mp3").unwrap().samples.len(), 10 * 1152 / 2);
+    }
+
+    #[test]
+    fn test_corrupt_files() {
+        let mut bytes = std::fs::read(fixture("tone.flac")).unwrap();
+        let end = bytes.len();
+        bytes[100..end].iter_mut().for_each(|b| *b ^= 0x5A);
+        let err = decode_audio(&bytes, "flac").unwrap_err();
+        assert_eq!(err.to_string(), "malformed FLAC file: no audio could be decoded (truncated or corrupt)");
+
+        let mut bytes = std::fs::read(fixture("silence.ogg")).unwrap();
+        let end = bytes.len();
+        bytes[end - 50..].iter_mut().for_each(|b| *b ^= 0x5A);
+        let err = decode_audio(&bytes, "ogg").unwrap_err();
+        assert!(err.to_string().starts_with("malformed Ogg file"), "{}", err);
+
+        let err = decode_audio(b"not really an mp3 at all", "mp3").unwrap_err();
+        assert_eq!(err.to_string(), "malformed MP3 file: no audio stream found");
+    }
+}
@@ -0,0 +1,687 @@
+//! Audio file loading: WAV, AIFF/AIFF-C and CAF containers with PCM or
+//! float samples, and with the `compressed-audio` feature M4A/AAC, MP3,
+//! FLAC and Ogg Vorbis (see `compressed`)
+
+use super::input::AudioInput;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+#[cfg(feature = "compressed-audio")]
+use super::compressed::decode as decode_compressed;
+
+/// Error reading or decoding an audio file
+#[derive(Debug, thiserror::Error)]
+pub enum AudioFileError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("not a RIFF file")]
+    NotRiff,
+
+    #[error("not an AIFF file")]
+    NotAiff,
+
+    #[error("not a CAF file")]
+    NotCaf,
+
+    #[cfg_attr(
+        feature = "compressed-audio",
+        error("unrecognized audio format (expected WAV, AIFF, CAF, M4A, AAC, MP3, FLAC or Ogg)")
+    )]
+    #[cfg_attr(not(feature = "compressed-audio"), error("unrecognized audio format (expected WAV, AIFF or CAF)"))]
+    UnknownFormat,
+
+    #[error("{0} files need VoiceFlow built with the compressed-audio feature")]
+    CompressedUnsupported(&'static str),
+
+    #[error("{0} file is DRM-protected and can't be decoded")]
+    Protected(&'static str),
+
+    #[error("malformed {format} file: {message}")]
+    Malformed { format: &'static str, message: String },
+
+    #[error("unsupported bit depth {0}")]
+    UnsupportedBitDepth(u32),
+
+    #[error("unsupported encoding {0}")]
+    UnsupportedEncoding(String),
+
+    #[error("unsupported sample rate {0}")]
+    UnsupportedSampleRate(f64),
+
+    #[error("file contains no audio")]
+    Empty,
+}
+
+/// Decoded audio file: interleaved f32 samples plus their format
+#[derive(Debug, Clone)]
+pub struct AudioBuffer {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Bit depth of the source encoding (for display; 0 for lossy formats)
+    pub bits_per_sample: u16,
+}
+
+impl AudioBuffer {
+    /// Borrow as an `AudioInput` for the pipeline
+    pub fn as_input(&self) -> AudioInput<'_> {
+        AudioInput::new(&self.samples, self.sample_rate, self.channels)
+    }
+}
+
+/// Load a WAV, AIFF or CAF file, or with the `compressed-audio` feature an
+/// M4A/AAC, MP3, FLAC or Ogg Vorbis file
+///
+/// The container is detected from the file header; the extension is only
+/// a fallback when the header is unrecognized, and makes the error clearer.
+/// Compressed files are decoded to 16kHz mono as they're read, without
+/// holding the whole file or its decoded audio at the source rate.
+pub fn load_audio_file(path: &Path) -> Result<AudioBuffer, AudioFileError> {
+    let io_error = |source| AudioFileError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut bytes = Vec::new();
+    (&mut file).take(12).read_to_end(&mut bytes).map_err(io_error)?;
+    if let Some(format) = compressed_format(&bytes, &extension) {
+        return decode_compressed(file, format, &extension);
+    }
+    file.read_to_end(&mut bytes).map_err(io_error)?;
+    decode_audio(&bytes, &extension)
+}
+
+/// Decode an in-memory audio file (see `load_audio_file`)
+pub fn decode_audio(bytes: &[u8], extension: &str) -> Result<AudioBuffer, AudioFileError> {
+    match bytes.get(..4) {
+        Some(b"RIFF") => decode_wav(bytes),
+        Some(b"FORM") => decode_aiff(bytes),
+        Some(b"caff") => decode_caf(bytes),
+        _ => match compressed_format(bytes, extension) {
+            Some(format) => decode_compressed(std::io::Cursor::new(bytes.to_vec()), format, extension),
+            None => Err(match extension {
+                "wav" | "wave" => AudioFileError::NotRiff,
+                "aif" | "aiff" | "aifc" => AudioFileError::NotAiff,
+                "caf" => AudioFileError::NotCaf,
+                _ => AudioFileError::UnknownFormat,
+            }),
+        },
+    }
+}
+
+/// The compressed format of a file, from its first bytes or else its
+/// extension; None for the PCM containers, or a file named as one
+fn compressed_format(head: &[u8], extension: &str) -> Option<&'static str> {
+    if matches!(head.get(..4), Some(b"RIFF" | b"FORM" | b"caff"))
+        || matches!(extension, "wav" | "wave" | "aif" | "aiff" | "aifc" | "caf")
+    {
+        return None;
+    }
+    match head {
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("M4A"),
+        [b'f', b'L', b'a', b'C', ..] => Some("FLAC"),
+        [b'O', b'g', b'g', b'S', ..] => Some("Ogg"),
+        [b'I', b'D', b'3', ..] => Some(if extension == "flac" { "FLAC" } else { "MP3" }),
+        // ADTS (AAC) and MPEG audio frame sync, told apart by the layer bits
+        [0xFF, b, ..] if b & 0xF6 == 0xF0 => Some("AAC"),
+        [0xFF, b, ..] if b & 0xE0 == 0xE0 && b & 0x06 != 0 => Some("MP3"),
+        _ => match extension {
+            "m4a" | "m4b" | "mp4" => Some("M4A"),
+            "aac" => Some("AAC"),
+            "mp3" => Some("MP3"),
+            "flac" => Some("FLAC"),
+            "ogg" | "oga" => Some("Ogg"),
+            _ => None,
+        },
+    }
+}
+
+/// Without the `compressed-audio` feature, compressed files are refused
+/// with an error naming the feature
+#[cfg(not(feature = "compressed-audio"))]
+fn decode_compressed<S>(_source: S, format: &'static str, _extension: &str) -> Result<AudioBuffer, AudioFileError> {
+    Err(AudioFileError::CompressedUnsupported(format))
+}
+
+/// How samples are stored in the data chunk
+#[derive(Debug, Clone, Copy)]
+struct SampleFormat {
+    bits: u32,
+    float: bool,
+    little_endian: bool,
+    /// 8-bit WAV is unsigned; every other integer encoding is signed
+    unsigned_8bit: bool,
+}
+
+/// Cursor over a byte slice with bounds-checked reads
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    format: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], format: &'static str) -> Self {
+        Self { bytes, pos: 0, format }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], AudioFileError> {
+        if self.remaining() < n {
+            return Err(malformed(self.format, "unexpected end of file"));
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), AudioFileError> {
+        self.take(n).map(|_| ())
+    }
+
+    fn tag(&mut self) -> Result<[u8; 4], AudioFileError> {
+        let b = self.take(4)?;
+        Ok([b[0], b[1], b[2], b[3]])
+    }
+
+    fn u16_le(&mut self) -> Result<u16, AudioFileError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32_le(&mut self) -> Result<u32, AudioFileError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u16_be(&mut self) -> Result<u16, AudioFileError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32_be(&mut self) -> Result<u32, AudioFileError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i64_be(&mut self) -> Result<i64, AudioFileError> {
+        let b = self.take(8)?;
+        Ok(i64::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    fn f64_be(&mut self) -> Result<f64, AudioFileError> {
+        let b = self.take(8)?;
+        Ok(f64::from_be_bytes(b.try_into().unwrap()))
+    }
+}
+
+pub(super) fn malformed(format: &'static str, message: &str) -> AudioFileError {
+    AudioFileError::Malformed {
+        format,
+        message: message.to_string(),
+    }
+}
+
+fn decode_wav(bytes: &[u8]) -> Result<AudioBuffer, AudioFileError> {
+    let mut r = Reader::new(bytes, "WAV");
+    r.skip(4)?; // "RIFF"
+    r.skip(4)?; // RIFF size (often wrong in the wild, so not trusted)
+    if &r.tag()? != b"WAVE" {
+        return Err(AudioFileError::NotRiff);
+    }
+
+    let mut fmt = None;
+    let mut data = None;
+
+    while r.remaining() >= 8 && (fmt.is_none() || data.is_none()) {
+        let id = r.tag()?;
+        let size = r.u32_le()? as usize;
+        // Streaming writers leave the data size unset; use what's there
+        let size = size.min(r.remaining());
+        let body = r.take(size)?;
+        if size % 2 == 1 && r.remaining() > 0 {
+            r.skip(1)?;
+        }
+
+        match &id {
+            b"fmt " => {
+                let mut f = Reader::new(body, "WAV");
+                let mut format_tag = f.u16_le()?;
+                let channels = f.u16_le()?;
+                let sample_rate = f.u32_le()?;
+                f.skip(4)?; // byte rate
+                f.skip(2)?; // block align
+                let bits = f.u16_le()?;
+                if format_tag == 0xFFFE {
+                    // WAVE_FORMAT_EXTENSIBLE: the real format is the
+                    // first two bytes of the sub-format GUID
+                    f.skip(2)?; // extension size
+                    f.skip(2)?; // valid bits
+                    f.skip(4)?; // channel mask
+                    format_tag = f.u16_le()?;
+                }
+                fmt = Some((format_tag, channels, sample_rate, bits));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+    }
+
+    let (format_tag, channels, sample_rate, bits) =
+        fmt.ok_or_else(|| malformed("WAV", "missing fmt chunk"))?;
+    let data = data.ok_or_else(|| malformed("WAV", "missing data chunk"))?;
+
+    let float = match format_tag {
+        1 => false,
+        3 => true,
+        other => return Err(AudioFileError::UnsupportedEncoding(format!("WAV format tag {}", other))),
+    };
+
+    let format = SampleFormat {
+        bits: bits as u32,
+        float,
+        little_endian: true,
+        unsigned_8bit: true,
+    };
+    build_buffer(data, format, sample_rate as f64, channels as u32)
+}
+
+fn decode_aiff(bytes: &[u8]) -> Result<AudioBuffer, AudioFileError> {
+    let mut r = Reader::new(bytes, "AIFF");
+    r.skip(4)?; // "FORM"
+    r.skip(4)?; // FORM size
+    let is_aifc = match &r.tag()? {
+        b"AIFF" => false,
+        b"AIFC" => true,
+        _ => return Err(AudioFileError::NotAiff),
+    };
+
+    let mut comm = None;
+    let mut data = None;
+
+    while r.remaining() >= 8 && (comm.is_none() || data.is_none()) {
+        let id = r.tag()?;
+        let size = (r.u32_be()? as usize).min(r.remaining());
+        let body = r.take(size)?;
+        if size % 2 == 1 && r.remaining() > 0 {
+            r.skip(1)?;
+        }
+
+        match &id {
+            b"COMM" => {
+                let mut c = Reader::new(body, "AIFF");
+                let channels = c.u16_be()?;
+                c.skip(4)?; // frame count (derived from the data size instead)
+                let bits = c.u16_be()?;
+                let sample_rate = extended_to_f64(c.take(10)?);
+                let compression = if is_aifc { c.tag()? } else { *b"NONE" };
+                comm = Some((channels, bits, sample_rate, compression));
+            }
+            b"SSND" => {
+                let mut s = Reader::new(body, "AIFF");
+                let offset = s.u32_be()? as usize;
+                s.skip(4)?; // block size
+                s.skip(offset)?;
+                data = Some(&body[s.pos..]);
+            }
+            _ => {}
+        }
+    }
+
+    let (channels, bits, sample_rate, compression) =
+        comm.ok_or_else(|| malformed("AIFF", "missing COMM chunk"))?;
+    let data = data.ok_or_else(|| malformed("AIFF", "missing SSND chunk"))?;
+
+    let (bits, float, little_endian) = match &compression {
+        b"NONE" | b"twos" => (bits as u32, false, false),
+        b"sowt" => (bits as u32, false, true),
+        b"fl32" | b"FL32" => (32, true, false),
+        b"fl64" | b"FL64" => (64, true, false),
+        other => {
+            return Err(AudioFileError::UnsupportedEncoding(
+                String::from_utf8_lossy(other).trim().to_string(),
+            ))
+        }
+    };
+
+    let format = SampleFormat {
+        bits,
+        float,
+        little_endian,
+        unsigned_8bit: false,
+    };
+    build_buffer(data, format, sample_rate, channels as u32)
+}
+
+fn decode_caf(bytes: &[u8]) -> Result<AudioBuffer, AudioFileError> {
+    let mut r = Reader::new(bytes, "CAF");
+    r.skip(4)?; // "caff"
+    r.skip(2)?; // version
+    r.skip(2)?; // flags
+
+    let mut desc = None;
+    let mut data = None;
+
+    while r.remaining() >= 12 && (desc.is_none() || data.is_none()) {
+        let id = r.tag()?;
+        let size = r.i64_be()?;
+        // A size of -1 means the chunk runs to the end of the file
+        let size = if size < 0 { r.remaining() } else { (size as usize).min(r.remaining()) };
+        let body = r.take(size)?;
+
+        match &id {
+            b"desc" => {
+                let mut d = Reader::new(body, "CAF");
+                let sample_rate = d.f64_be()?;
+                let format_id = d.tag()?;
+                let flags = d.u32_be()?;
+                d.skip(4)?; // bytes per packet
+                d.skip(4)?; // frames per packet
+                let channels = d.u32_be()?;
+                let bits = d.u32_be()?;
+                desc = Some((sample_rate, format_id, flags, channels, bits));
+            }
+            b"data" => {
+                if body.len() < 4 {
+                    return Err(malformed("CAF", "data chunk too short"));
+                }
+                data = Some(&body[4..]); // skip edit count
+            }
+            _ => {}
+        }
+    }
+
+    let (sample_rate, format_id, flags, channels, bits) =
+        desc.ok_or_else(|| malformed("CAF", "missing desc chunk"))?;
+    let data = data.ok_or_else(|| malformed("CAF", "missing data chunk"))?;
+
+    if &format_id != b"lpcm" {
+        return Err(AudioFileError::UnsupportedEncoding(
+            String::from_utf8_lossy(&format_id).trim().to_string(),
+        ));
+    }
+
+    const FLAG_IS_FLOAT: u32 = 1;
+    const FLAG_IS_LITTLE_ENDIAN: u32 = 2;
+    let format = SampleFormat {
+        bits,
+        float: flags & FLAG_IS_FLOAT != 0,
+        little_endian: flags & FLAG_IS_LITTLE_ENDIAN != 0,
+        unsigned_8bit: false,
+    };
+    build_buffer(data, format, sample_rate, channels)
+}
+
+fn build_buffer(
+    data: &[u8],
+    format: SampleFormat,
+    sample_rate: f64,
+    channels: u32,
+) -> Result<AudioBuffer, AudioFileError> {
+    if channels == 0 || channels > u16::MAX as u32 {
+        return Err(AudioFileError::UnsupportedEncoding(format!("{} channels", channels)));
+    }
+    if !(1.0..=768_000.0).contains(&sample_rate) || sample_rate.fract() != 0.0 {
+        return Err(AudioFileError::UnsupportedSampleRate(sample_rate));
+    }
+
+    let samples = decode_samples(data, format)?;
+    if samples.is_empty() {
+        return Err(AudioFileError::Empty);
+    }
+
+    Ok(AudioBuffer {
+        samples,
+        sample_rate: sample_rate as u32,
+        channels: channels as u16,
+        bits_per_sample: format.bits as u16,
+    })
+}
+
+/// Decode raw sample bytes to f32 in [-1.0, 1.0]
+fn decode_samples(data: &[u8], format: SampleFormat) -> Result<Vec<f32>, AudioFileError> {
+    let SampleFormat {
+        bits,
+        float,
+        little_endian,
+        unsigned_8bit,
+    } = format;
+
+    let width = match (float, bits) {
+        (true, 32) | (true, 64) | (false, 8) | (false, 16) | (false, 24) | (false, 32) => {
+            bits as usize / 8
+        }
+        _ => return Err(AudioFileError::UnsupportedBitDepth(bits)),
+    };
+
+    let samples = data
+        .chunks_exact(width)
+        .map(|b| {
+            // Normalize to big-endian byte order so one decoder serves both
+            let mut be = [0u8; 8];
+            for (i, byte) in b.iter().enumerate() {
+                be[if little_endian { width - 1 - i } else { i }] = *byte;
+            }
+            let be = &be[..width];
+
+            match (float, width) {
+                (true, 4) => f32::from_be_bytes([be[0], be[1], be[2], be[3]]),
+                (true, _) => f64::from_be_bytes(be.try_into().unwrap()) as f32,
+                (false, 1) if unsigned_8bit => (be[0] as f32 - 128.0) / 128.0,
+                (false, 1) => be[0] as i8 as f32 / 128.0,
+                (false, 2) => i16::from_be_bytes([be[0], be[1]]) as f32 / 32768.0,
+                (false, 3) => {
+                    let v = i32::from_be_bytes([be[0], be[1], be[2], 0]) >> 8;
+                    v as f32 / 8_388_608.0
+                }
+                (false, _) => i32::from_be_bytes([be[0], be[1], be[2], be[3]]) as f32 / 2_147_483_648.0,
+            }
+        })
+        .map(|s| s.clamp(-1.0, 1.0))
+        .collect();
+
+    Ok(samples)
+}
+
+/// Convert an 80-bit IEEE 754 extended float (AIFF sample rate) to f64
+fn extended_to_f64(b: &[u8]) -> f64 {
+    let sign = if b[0] & 0x80 != 0 { -1.0 } else { 1.0 };
+    let exponent = (((b[0] & 0x7F) as i32) << 8) | b[1] as i32;
+    let mantissa = u64::from_be_bytes([b[2], b[3], b[4], b[5], b[6], b[7], b[8], b[9]]);
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(format_tag: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&format_tag.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&rate.to_le_bytes());
+        let block_align = channels * bits / 8;
+        fmt.extend_from_slice(&(rate * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&((4 + 8 + fmt.len() + 8 + data.len()) as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        out.extend_from_slice(&fmt);
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn test_wav_pcm16_mono() {
+        let data: Vec<u8> = [0i16, 16384, -16384, i16::MIN]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let buf = decode_audio(&wav(1, 1, 44100, 16, &data), "wav").unwrap();
+        assert_eq!(buf.sample_rate, 44100);
+        assert_eq!(buf.channels, 1);
+        assert_eq!(buf.samples, vec![0.0, 0.5, -0.5, -1.0]);
+    }
+
+    #[test]
+    fn test_wav_float32_stereo() {
+        let data: Vec<u8> = [0.25f32, -0.25, 1.0, 0.0]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let buf = decode_audio(&wav(3, 2, 48000, 32, &data), "wav").unwrap();
+        assert_eq!(buf.channels, 2);
+        assert_eq!(buf.samples, vec![0.25, -0.25, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_wav_pcm24() {
+        // 0x400000 = half scale
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xC0];
+        let buf = decode_audio(&wav(1, 1, 16000, 24, &data), "wav").unwrap();
+        assert_eq!(buf.samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_wav_unsupported_bit_depth() {
+        let err = decode_audio(&wav(1, 1, 16000, 12, &[0, 0]), "wav").unwrap_err();
+        assert_eq!(err.to_string(), "unsupported bit depth 12");
+    }
+
+    #[test]
+    fn test_not_riff() {
+        let err = decode_audio(b"ID3\x03 this is an mp3", "wav").unwrap_err();
+        assert_eq!(err.to_string(), "not a RIFF file");
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        let err = decode_audio(b"garbage data", "").unwrap_err();
+        assert!(matches!(err, AudioFileError::UnknownFormat));
+    }
+
+    #[test]
+    #[cfg(not(feature = "compressed-audio"))]
+    fn test_compressed_needs_feature() {
+        let err = decode_audio(b"fLaC\0\0\0\x22", "").unwrap_err();
+        assert_eq!(err.to_string(), "FLAC files need VoiceFlow built with the compressed-audio feature");
+        let err = decode_audio(b"\xFF\xFB\x18\xC0", "").unwrap_err();
+        assert!(matches!(err, AudioFileError::CompressedUnsupported("MP3")));
+        let err = decode_audio(b"no header", "m4a").unwrap_err();
+        assert!(matches!(err, AudioFileError::CompressedUnsupported("M4A")));
+    }
+
+    #[test]
+    fn test_truncated_wav() {
+        let bytes = wav(1, 1, 16000, 16, &[0, 0, 0, 0]);
+        let err = decode_audio(&bytes[..20], "wav").unwrap_err();
+        assert!(err.to_string().starts_with("malformed WAV file"), "{}", err);
+    }
+
+    #[test]
+    fn test_aiff_pcm16() {
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&1u16.to_be_bytes()); // channels
+        comm.extend_from_slice(&2u32.to_be_bytes()); // frames
+        comm.extend_from_slice(&16u16.to_be_bytes()); // bits
+        // 44100 as 80-bit extended
+        comm.extend_from_slice(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+
+        let mut ssnd = vec![0u8; 8]; // offset, block size
+        ssnd.extend_from_slice(&16384i16.to_be_bytes());
+        ssnd.extend_from_slice(&(-16384i16).to_be_bytes());
+
+        let mut bytes = b"FORM".to_vec();
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes.extend_from_slice(b"AIFF");
+        bytes.extend_from_slice(b"COMM");
+        bytes.extend_from_slice(&(comm.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&comm);
+        bytes.extend_from_slice(b"SSND");
+        bytes.extend_from_slice(&(ssnd.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&ssnd);
+
+        let buf = decode_audio(&bytes, "aiff").unwrap();
+        assert_eq!(buf.sample_rate, 44100);
+        assert_eq!(buf.samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_caf_float32() {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&48000f64.to_be_bytes());
+        desc.extend_from_slice(b"lpcm");
+        desc.extend_from_slice(&1u32.to_be_bytes()); // float, big-endian
+        desc.extend_from_slice(&4u32.to_be_bytes());
+        desc.extend_from_slice(&1u32.to_be_bytes());
+        desc.extend_from_slice(&1u32.to_be_bytes()); // channels
+        desc.extend_from_slice(&32u32.to_be_bytes());
+
+        let mut data = vec![0u8; 4]; // edit count
+        data.extend_from_slice(&0.75f32.to_be_bytes());
+
+        let mut bytes = b"caff".to_vec();
+        bytes.extend_from_slice(&[0, 1, 0, 0]);
+        bytes.extend_from_slice(b"desc");
+        bytes.extend_from_slice(&(desc.len() as i64).to_be_bytes());
+        bytes.extend_from_slice(&desc);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(-1i64).to_be_bytes());
+        bytes.extend_from_slice(&data);
+
+        let buf = decode_audio(&bytes, "caf").unwrap();
+        assert_eq!(buf.sample_rate, 48000);
+        assert_eq!(buf.samples, vec![0.75]);
+    }
+
+    #[test]
+    fn test_caf_aac_unsupported() {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&44100f64.to_be_bytes());
+        desc.extend_from_slice(b"aac ");
+        desc.extend_from_slice(&[0u8; 20]);
+
+        let mut bytes = b"caff".to_vec();
+        bytes.extend_from_slice(&[0, 1, 0, 0]);
+        bytes.extend_from_slice(b"desc");
+        bytes.extend_from_slice(&(desc.len() as i64).to_be_bytes());
+        bytes.extend_from_slice(&desc);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&8i64.to_be_bytes());
+        bytes.extend_from_slice(&[0u8; 8]);
+
+        let err = decode_audio(&bytes, "caf").unwrap_err();
+        assert_eq!(err.to_string(), "unsupported encoding aac");
+    }
+
+    #[test]
+    fn test_repo_test_audio() {
+        let path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav"));
+        let buf = load_audio_file(path).unwrap();
+        assert_eq!(buf.sample_rate, 22050);
+        assert_eq!(buf.channels, 1);
+        assert_eq!(buf.samples.len(), 132500);
+    }
+}
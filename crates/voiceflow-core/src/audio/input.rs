@@ -0,0 +1,180 @@
+//! Audio input with its sample format, normalized to what the STT engines expect
+
+use super::resample::{
+    downmix_to_mono, downmix_to_mono_into, resample_to_16khz, resample_to_16khz_into, ResampleState,
+};
+use anyhow::Result;
+use std::borrow::Cow;
+
+/// Sample rate the STT engines expect
+pub const TARGET_SAMPLE_RATE: u32 = 16000;
+
+/// Borrowed PCM samples together with their sample rate and channel count
+///
+/// Multi-channel audio is interleaved. `to_16khz_mono` downmixes and
+/// resamples as needed, and borrows the samples when they're already
+/// 16kHz mono.
+#[derive(Debug, Clone, Copy)]
+pub struct AudioInput<'a> {
+    samples: &'a [f32],
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl<'a> AudioInput<'a> {
+    /// Interleaved samples at the given rate and channel count
+    pub fn new(samples: &'a [f32], sample_rate: u32, channels: u16) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            channels,
+        }
+    }
+
+    /// Mono samples at the given rate
+    pub fn mono(samples: &'a [f32], sample_rate: u32) -> Self {
+        Self::new(samples, sample_rate, 1)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Duration in seconds
+    pub fn duration_secs(&self) -> f32 {
+        if self.sample_rate == 0 || self.channels == 0 {
+            return 0.0;
+        }
+        self.samples.len() as f32 / (self.sample_rate as f32 * self.channels as f32)
+    }
+
+    /// Convert to 16kHz mono
+    pub fn to_16khz_mono(&self) -> Result<Cow<'a, [f32]>> {
+        self.check_format()?;
+
+        if self.channels == 1 && self.sample_rate == TARGET_SAMPLE_RATE {
+            return Ok(Cow::Borrowed(self.samples));
+        }
+
+        let mono = if self.channels > 1 {
+            Cow::Owned(downmix_to_mono(self.samples, self.channels as usize))
+        } else {
+            Cow::Borrowed(self.samples)
+        };
+
+        if self.sample_rate == TARGET_SAMPLE_RATE {
+            return Ok(mono);
+        }
+        Ok(Cow::Owned(resample_to_16khz(&mono, self.sample_rate)?))
+    }
+
+    /// Convert to 16kHz mono into `output`, reusing `state` and the capacity
+    /// of `output` from earlier calls rather than allocating
+    pub fn to_16khz_mono_into(&self, state: &mut ResampleState, output: &mut Vec<f32>) -> Result<()> {
+        self.check_format()?;
+
+        if self.channels == 1 {
+            return resample_to_16khz_into(self.samples, self.sample_rate, state, output);
+        }
+        let mut mono = std::mem::take(&mut state.mono);
+        downmix_to_mono_into(self.samples, self.channels as usize, &mut mono);
+        let result = resample_to_16khz_into(&mono, self.sample_rate, state, output);
+        state.mono = mono;
+        result
+    }
+
+    fn check_format(&self) -> Result<()> {
+        if self.sample_rate == 0 {
+            anyhow::bail!("Invalid sample rate: 0 Hz");
+        }
+        if self.channels == 0 {
+            anyhow::bail!("Invalid channel count: 0");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_16k_mono_is_borrowed() {
+        let audio = vec![0.1f32; 160];
+        let input = AudioInput::mono(&audio, 16000);
+        assert!(matches!(input.to_16khz_mono().unwrap(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_stereo_48k_to_16k_mono() {
+        // Left/right carry the same tone, so the downmix keeps its level
+        let stereo: Vec<f32> = (0..48000)
+            .flat_map(|i| {
+                let s = ((i as f64 * 440.0 / 48000.0).fract() * std::f64::consts::TAU).sin() as f32;
+                [s * 0.5, s * 0.5]
+            })
+            .collect();
+        let input = AudioInput::new(&stereo, 48000, 2);
+        assert!((input.duration_secs() - 1.0).abs() < 1e-6);
+
+        let mono = input.to_16khz_mono().unwrap();
+        assert_eq!(mono.len(), 16000);
+        let peak = mono[400..15600].iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+        assert!((peak - 0.5).abs() < 0.02, "peak {}", peak);
+    }
+
+    #[test]
+    fn test_into_matches_allocating_conversion() {
+        let stereo: Vec<f32> = (0..9600).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let mut state = ResampleState::default();
+        let mut output = Vec::new();
+        for (rate, channels) in [(48000, 2), (16000, 1), (16000, 2), (48000, 2)] {
+            let input = AudioInput::new(&stereo, rate, channels);
+            input.to_16khz_mono_into(&mut state, &mut output).unwrap();
+            assert_eq!(output, *input.to_16khz_mono().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_format_rejected() {
+        let audio = vec![0.0f32; 10];
+        assert!(AudioInput::new(&audio, 0, 1).to_16khz_mono().is_err());
+        assert!(AudioInput::new(&audio, 16000, 0).to_16khz_mono().is_err());
+        let mut state = ResampleState::default();
+        assert!(AudioInput::new(&audio, 0, 1).to_16khz_mono_into(&mut state, &mut Vec::new()).is_err());
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_recording_transcript_independent_of_input_rate() {
+        use crate::{Config, Pipeline};
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let spec = reader.spec();
+        let native: Vec<f32> = reader
+            .samples::<i16>()
+            .map(|s| s.unwrap() as f32 / 32768.0)
+            .collect();
+
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+
+        let at_native_rate = AudioInput::mono(&native, spec.sample_rate).to_16khz_mono().unwrap();
+        let pre_resampled = resample_to_16khz(&native, spec.sample_rate).unwrap();
+        let upsampled_48k = {
+            // Resample to 16kHz then up to 48kHz by repeating each sample,
+            // so the 48kHz path has to undo it
+            pre_resampled.iter().flat_map(|&s| [s, s, s]).collect::<Vec<f32>>()
+        };
+        let from_48k = AudioInput::mono(&upsampled_48k, 48000).to_16khz_mono().unwrap();
+
+        let expected = pipeline.transcribe_only(&pre_resampled).unwrap().raw_transcript;
+        assert_eq!(pipeline.transcribe_only(&at_native_rate).unwrap().raw_transcript, expected);
+        assert_eq!(pipeline.transcribe_only(&from_48k).unwrap().raw_transcript, expected);
+    }
+}
@@ -1,7 +1,29 @@
 //! Audio capture and processing
 
+#[cfg(feature = "capture")]
 mod capture;
+#[cfg(feature = "compressed-audio")]
+mod compressed;
+mod file;
+mod input;
+mod preprocess;
 mod resample;
+mod vad;
 
-pub use capture::{AudioCapture, AudioCaptureEvent};
-pub use resample::{resample_to_16khz, stereo_to_mono};
+#[cfg(feature = "capture")]
+pub use capture::{
+    list_input_devices, CaptureMode, CaptureOptions, CaptureState, CaptureStop, CpalHost, HostEvent, InputDevice,
+    InputHost, Microphone, StopReason,
+};
+pub use file::{decode_audio, load_audio_file, AudioBuffer, AudioFileError};
+pub use input::{AudioInput, TARGET_SAMPLE_RATE};
+pub use preprocess::{
+    clipped_percent, non_finite_count, normalize, preprocess, remove_dc, repair_non_finite, PreprocessStats,
+    CLIPPING_WARN_PERCENT,
+};
+pub use resample::{
+    downmix_to_mono, downmix_to_mono_into, i16_to_f32, i16_to_f32_into, resample_to_16khz, resample_to_16khz_into,
+    stereo_to_mono, ResampleState, StreamResampler,
+};
+pub use vad::speech_regions;
+pub(crate) use vad::rms;
@@ -0,0 +1,227 @@
+//! Fixes before transcription: replacing NaN and infinite samples, DC
+//! offset removal, normalization and clipping detection
+
+use crate::config::{AudioOptions, NormalizeMode};
+
+/// Samples at or above this magnitude count as clipped
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Most gain normalization applies, so a near-silent recording isn't blown
+/// up into noise
+const MAX_GAIN_DB: f32 = 30.0;
+
+/// Share of clipped samples, in percent, above which a recording is flagged
+pub const CLIPPING_WARN_PERCENT: f32 = 0.1;
+
+/// What preprocessing found and changed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PreprocessStats {
+    /// Share of samples at full scale in the input, in percent
+    pub clipped_percent: f32,
+    /// `clipped_percent` is above `CLIPPING_WARN_PERCENT` and
+    /// `AudioOptions::warn_on_clipping` is set
+    pub clipping: bool,
+    /// Offset subtracted from every sample
+    pub dc_offset: f32,
+    /// Gain applied by normalization, in dB
+    pub gain_db: f32,
+    /// NaN or infinite samples replaced with silence
+    pub repaired_samples: usize,
+}
+
+/// Apply the preprocessing `options` ask for to 16kHz mono audio, in place
+///
+/// NaN and infinite samples are always replaced with silence. Clipping is
+/// measured on the input, before any other change. Nothing else happens
+/// when `AudioOptions::preprocess` is off.
+pub fn preprocess(samples: &mut [f32], options: &AudioOptions) -> PreprocessStats {
+    let repaired_samples = repair_non_finite(samples);
+    if !options.preprocess || samples.is_empty() {
+        return PreprocessStats { repaired_samples, ..PreprocessStats::default() };
+    }
+    let clipped_percent = clipped_percent(samples);
+    let clipping = options.warn_on_clipping && clipped_percent > CLIPPING_WARN_PERCENT;
+    if clipping {
+        tracing::warn!("{:.2}% of the recording is clipped; the input gain is too high", clipped_percent);
+    }
+    let dc_offset = if options.remove_dc { remove_dc(samples) } else { 0.0 };
+    let gain_db = match options.normalize_target_dbfs {
+        Some(target) => normalize(samples, target, options.normalize_mode),
+        None => 0.0,
+    };
+    PreprocessStats { clipped_percent, clipping, dc_offset, gain_db, repaired_samples }
+}
+
+/// Number of NaN and infinite samples
+pub fn non_finite_count(samples: &[f32]) -> usize {
+    samples.iter().filter(|s| !s.is_finite()).count()
+}
+
+/// Replace NaN and infinite samples with 0.0, returning how many there were
+pub fn repair_non_finite(samples: &mut [f32]) -> usize {
+    let mut repaired = 0;
+    for sample in samples.iter_mut().filter(|s| !s.is_finite()) {
+        *sample = 0.0;
+        repaired += 1;
+    }
+    repaired
+}
+
+/// Subtract the mean from every sample, returning it
+pub fn remove_dc(samples: &mut [f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let mean = (samples.iter().map(|&s| s as f64).sum::<f64>() / samples.len() as f64) as f32;
+    for sample in samples.iter_mut() {
+        *sample -= mean;
+    }
+    mean
+}
+
+/// Scale `samples` so their peak or RMS level reaches `target_dbfs`,
+/// returning the gain applied in dB
+///
+/// The gain is capped at 30 dB and at what the peak allows without
+/// clipping, so an RMS target may be missed. Silence is left alone.
+pub fn normalize(samples: &mut [f32], target_dbfs: f32, mode: NormalizeMode) -> f32 {
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let level = match mode {
+        NormalizeMode::Peak => peak,
+        NormalizeMode::Rms => rms(samples),
+    };
+    if level <= 0.0 {
+        return 0.0;
+    }
+    let gain_db = (target_dbfs - to_dbfs(level)).min(MAX_GAIN_DB).min(-to_dbfs(peak));
+    let gain = 10f32.powf(gain_db / 20.0);
+    for sample in samples.iter_mut() {
+        *sample = (*sample * gain).clamp(-1.0, 1.0);
+    }
+    gain_db
+}
+
+/// Share of samples at full scale, in percent
+pub fn clipped_percent(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let clipped = samples.iter().filter(|s| s.abs() >= CLIP_LEVEL).count();
+    clipped as f32 * 100.0 / samples.len() as f32
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    let sum: f64 = samples.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    (sum / samples.len() as f64).sqrt() as f32
+}
+
+fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One second of a 440Hz sine at `amplitude`, shifted by `offset`
+    fn sine(amplitude: f32, offset: f32) -> Vec<f32> {
+        (0..16000).map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / 16000.0).sin() * amplitude + offset).collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn test_remove_dc_centers_the_signal() {
+        let mut samples = sine(0.2, 0.15);
+        let offset = remove_dc(&mut samples);
+        assert!((offset - 0.15).abs() < 1e-3, "offset {}", offset);
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 1e-4, "mean {}", mean);
+        assert!((peak(&samples) - 0.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_peak_normalization_reaches_target() {
+        let mut samples = sine(0.01, 0.0);
+        // 37 dB short of the target, but the gain is capped
+        let gain_db = normalize(&mut samples, -3.0, NormalizeMode::Peak);
+        assert!((gain_db - MAX_GAIN_DB).abs() < 1e-3, "gain {}", gain_db);
+
+        let mut samples = sine(0.1, 0.0);
+        let gain_db = normalize(&mut samples, -6.0, NormalizeMode::Peak);
+        assert!((gain_db - 14.0).abs() < 0.1, "gain {}", gain_db);
+        assert!((to_dbfs(peak(&samples)) + 6.0).abs() < 0.05);
+
+        // Attenuates too
+        let mut samples = sine(0.9, 0.0);
+        assert!(normalize(&mut samples, -12.0, NormalizeMode::Peak) < 0.0);
+        assert!((to_dbfs(peak(&samples)) + 12.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_rms_normalization_never_clips() {
+        // A sine's RMS is 3 dB below its peak
+        let mut samples = sine(0.1, 0.0);
+        normalize(&mut samples, -20.0, NormalizeMode::Rms);
+        assert!((to_dbfs(rms(&samples)) + 20.0).abs() < 0.05);
+
+        // Reaching -1 dBFS RMS would push the peak past full scale
+        let mut samples = sine(0.1, 0.0);
+        normalize(&mut samples, -1.0, NormalizeMode::Rms);
+        assert!(peak(&samples) <= 1.0);
+        assert!((to_dbfs(rms(&samples)) + 3.0).abs() < 0.05, "stops with the peak at full scale");
+
+        let mut silence = vec![0.0; 1600];
+        assert_eq!(normalize(&mut silence, -3.0, NormalizeMode::Rms), 0.0);
+        assert!(silence.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_clipping_is_measured_on_the_input() {
+        // A sine driven 4x past full scale is flat for most of each cycle
+        let mut samples: Vec<f32> = sine(4.0, 0.0).into_iter().map(|s| s.clamp(-1.0, 1.0)).collect();
+        let clipped = clipped_percent(&samples);
+        assert!(clipped > 50.0, "{}% clipped", clipped);
+        assert_eq!(clipped_percent(&sine(0.5, 0.0)), 0.0);
+
+        let options = AudioOptions { normalize_target_dbfs: Some(-6.0), ..AudioOptions::default() };
+        let stats = preprocess(&mut samples, &options);
+        assert_eq!(stats.clipped_percent, clipped);
+        assert!(stats.clipping);
+        assert!(!preprocess(&mut samples, &AudioOptions { warn_on_clipping: false, ..options }).clipping);
+    }
+
+    #[test]
+    fn test_preprocess_can_be_skipped() {
+        let input = sine(0.01, 0.2);
+        let options = AudioOptions { normalize_target_dbfs: Some(-3.0), ..AudioOptions::default() };
+
+        let mut samples = input.clone();
+        let stats = preprocess(&mut samples, &options);
+        assert!((stats.dc_offset - 0.2).abs() < 1e-3);
+        assert!((stats.gain_db - MAX_GAIN_DB).abs() < 1e-3);
+        assert_ne!(samples, input);
+
+        let mut samples = input.clone();
+        let stats = preprocess(&mut samples, &AudioOptions { preprocess: false, ..options.clone() });
+        assert_eq!(stats, PreprocessStats::default());
+        assert_eq!(samples, input);
+
+        // Non-finite samples are replaced either way
+        let mut samples = input.clone();
+        samples[..3].copy_from_slice(&[f32::NAN, f32::INFINITY, f32::NEG_INFINITY]);
+        assert_eq!(non_finite_count(&samples), 3);
+        let stats = preprocess(&mut samples, &AudioOptions { preprocess: false, ..options.clone() });
+        assert_eq!(stats, PreprocessStats { repaired_samples: 3, ..PreprocessStats::default() });
+        assert_eq!(samples[..3], [0.0; 3]);
+        assert_eq!(samples[3..], input[3..]);
+
+        let mut samples = input.clone();
+        let untouched = AudioOptions { remove_dc: false, normalize_target_dbfs: None, ..options };
+        let stats = preprocess(&mut samples, &untouched);
+        assert_eq!(stats.dc_offset, 0.0);
+        assert_eq!(samples, input);
+    }
+}
@@ -1,14 +1,70 @@
 //! Audio resampling utilities
 
 use anyhow::Result;
-use rubato::{FftFixedIn, Resampler};
+use rubato::{
+    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
+
+/// Input chunk size fed to the resampler
+const CHUNK_SIZE: usize = 1024;
+
+const TARGET_RATE: u32 = 16000;
+
+/// A resampler and its buffers, kept between calls so converting audio at
+/// the same rate again doesn't rebuild the filter or allocate
+#[derive(Default)]
+pub struct ResampleState {
+    /// Resampler for the last input rate
+    resampler: Option<(u32, SincFixedIn<f32>)>,
+    /// Output of one resampler call
+    chunk: Vec<Vec<f32>>,
+    /// Downmixed input (see `AudioInput::to_16khz_mono_into`)
+    pub(super) mono: Vec<f32>,
+}
+
+impl std::fmt::Debug for ResampleState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResampleState")
+            .field("rate", &self.resampler.as_ref().map(|(rate, _)| *rate))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Windowed-sinc interpolation; the cutoff sits just below the output
+/// Nyquist so downsampling doesn't alias
+fn sinc_resampler(ratio: f64) -> Result<SincFixedIn<f32>> {
+    let params = SincInterpolationParameters {
+        sinc_len: 256,
+        f_cutoff: 0.95,
+        interpolation: SincInterpolationType::Linear,
+        oversampling_factor: 256,
+        window: WindowFunction::BlackmanHarris2,
+    };
+    Ok(SincFixedIn::<f32>::new(ratio, 1.0, params, CHUNK_SIZE, 1)?)
+}
 
 /// Resample audio to 16kHz mono (required by Whisper)
 pub fn resample_to_16khz(samples: &[f32], input_sample_rate: u32) -> Result<Vec<f32>> {
-    const TARGET_RATE: u32 = 16000;
+    let mut output = Vec::new();
+    resample_to_16khz_into(samples, input_sample_rate, &mut ResampleState::default(), &mut output)?;
+    Ok(output)
+}
 
+/// Resample audio to 16kHz mono into `output`, reusing `state` from earlier
+/// calls
+pub fn resample_to_16khz_into(
+    samples: &[f32],
+    input_sample_rate: u32,
+    state: &mut ResampleState,
+    output: &mut Vec<f32>,
+) -> Result<()> {
+    output.clear();
     if input_sample_rate == TARGET_RATE {
-        return Ok(samples.to_vec());
+        output.extend_from_slice(samples);
+        return Ok(());
+    }
+    if input_sample_rate == 0 {
+        anyhow::bail!("Invalid sample rate: 0 Hz");
     }
 
     tracing::debug!(
@@ -17,41 +73,152 @@ pub fn resample_to_16khz(samples: &[f32], input_sample_rate: u32) -> Result<Vec<
         TARGET_RATE
     );
 
-    let mut resampler = FftFixedIn::<f32>::new(
-        input_sample_rate as usize,
-        TARGET_RATE as usize,
-        1024, // chunk size
-        1,    // sub chunks
-        1,    // channels
-    )?;
+    let ratio = TARGET_RATE as f64 / input_sample_rate as f64;
+    if !matches!(&state.resampler, Some((rate, _)) if *rate == input_sample_rate) {
+        let resampler = sinc_resampler(ratio)?;
+        state.chunk = resampler.output_buffer_allocate(true);
+        state.resampler = Some((input_sample_rate, resampler));
+    }
+    let Some((_, resampler)) = state.resampler.as_mut() else {
+        unreachable!("resampler created above");
+    };
+    resampler.reset();
 
-    let input_frames = resampler.input_frames_next();
-    let mut output = Vec::new();
+    // The resampler leaves its filter delay out of the first chunk, so the
+    // output already lines up with the input; the last samples only come
+    // out once the filter is flushed past the end
+    let expected = (samples.len() as f64 * ratio).ceil() as usize;
+    output.reserve(expected);
+    let mut pos = 0;
 
-    // Process in chunks
-    for chunk in samples.chunks(input_frames) {
-        // Pad last chunk if needed
-        let input = if chunk.len() < input_frames {
-            let mut padded = chunk.to_vec();
-            padded.resize(input_frames, 0.0);
-            vec![padded]
+    while output.len() < expected {
+        let needed = resampler.input_frames_next();
+        let (_, written) = if pos + needed <= samples.len() {
+            let chunk = &samples[pos..pos + needed];
+            pos += needed;
+            resampler.process_into_buffer(&[chunk], state.chunk.as_mut_slice(), None)?
+        } else if pos < samples.len() {
+            let chunk = &samples[pos..];
+            pos = samples.len();
+            resampler.process_partial_into_buffer(Some(&[chunk]), state.chunk.as_mut_slice(), None)?
         } else {
-            vec![chunk.to_vec()]
+            // Flush the filter with silence
+            resampler.process_partial_into_buffer(None::<&[&[f32]]>, state.chunk.as_mut_slice(), None)?
         };
 
-        let resampled = resampler.process(&input, None)?;
-        if let Some(channel) = resampled.first() {
-            output.extend_from_slice(channel);
+        if let Some(channel) = state.chunk.first() {
+            output.extend_from_slice(&channel[..written]);
         }
     }
 
+    output.truncate(expected);
+
     tracing::debug!(
         "Resampled {} samples to {} samples",
         samples.len(),
         output.len()
     );
 
-    Ok(output)
+    Ok(())
+}
+
+/// Resamples mono audio to 16kHz as it arrives, for audio too long to hold
+/// at its own rate, e.g. decoded a packet at a time
+///
+/// The output is the same as `resample_to_16khz` on all of the input at
+/// once.
+pub struct StreamResampler {
+    /// None at 16kHz, where the input passes through
+    resampler: Option<SincFixedIn<f32>>,
+    ratio: f64,
+    /// Input short of the resampler's next chunk
+    pending: Vec<f32>,
+    /// Output of one resampler call
+    chunk: Vec<Vec<f32>>,
+    input_len: usize,
+    output_len: usize,
+}
+
+impl std::fmt::Debug for StreamResampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamResampler")
+            .field("ratio", &self.ratio)
+            .field("input_len", &self.input_len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StreamResampler {
+    pub fn new(input_sample_rate: u32) -> Result<Self> {
+        if input_sample_rate == 0 {
+            anyhow::bail!("Invalid sample rate: 0 Hz");
+        }
+        let ratio = TARGET_RATE as f64 / input_sample_rate as f64;
+        let resampler = if input_sample_rate == TARGET_RATE { None } else { Some(sinc_resampler(ratio)?) };
+        let chunk = resampler.as_ref().map(|r| r.output_buffer_allocate(true)).unwrap_or_default();
+        Ok(Self { resampler, ratio, pending: Vec::new(), chunk, input_len: 0, output_len: 0 })
+    }
+
+    /// Resample `samples`, appending to `output` what's ready
+    pub fn push(&mut self, samples: &[f32], output: &mut Vec<f32>) -> Result<()> {
+        self.input_len += samples.len();
+        let Some(resampler) = self.resampler.as_mut() else {
+            output.extend_from_slice(samples);
+            return Ok(());
+        };
+        self.pending.extend_from_slice(samples);
+        let mut pos = 0;
+        while self.pending.len() - pos >= resampler.input_frames_next() {
+            let needed = resampler.input_frames_next();
+            let input = &self.pending[pos..pos + needed];
+            pos += needed;
+            let (_, written) = resampler.process_into_buffer(&[input], self.chunk.as_mut_slice(), None)?;
+            output.extend_from_slice(&self.chunk[0][..written]);
+            self.output_len += written;
+        }
+        self.pending.drain(..pos);
+        Ok(())
+    }
+
+    /// Resample what's left and flush the filter, so the output lines up
+    /// with the end of the input
+    pub fn finish(mut self, output: &mut Vec<f32>) -> Result<()> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+        // Output still owed; `push` never gets ahead of it
+        let remaining = ((self.input_len as f64 * self.ratio).ceil() as usize).saturating_sub(self.output_len);
+        let start = output.len();
+        let expected = self.output_len + remaining;
+        let mut pending = (!self.pending.is_empty()).then_some(&self.pending[..]);
+        while self.output_len < expected {
+            let (_, written) = match pending.take() {
+                Some(input) => resampler.process_partial_into_buffer(Some(&[input]), self.chunk.as_mut_slice(), None)?,
+                None => resampler.process_partial_into_buffer(None::<&[&[f32]]>, self.chunk.as_mut_slice(), None)?,
+            };
+            output.extend_from_slice(&self.chunk[0][..written]);
+            self.output_len += written;
+        }
+        output.truncate(start + remaining);
+        Ok(())
+    }
+}
+
+/// Downmix interleaved multi-channel audio to mono by averaging channels
+pub fn downmix_to_mono(samples: &[f32], channels: usize) -> Vec<f32> {
+    let mut output = Vec::new();
+    downmix_to_mono_into(samples, channels, &mut output);
+    output
+}
+
+/// Downmix interleaved multi-channel audio to mono into `output`
+pub fn downmix_to_mono_into(samples: &[f32], channels: usize, output: &mut Vec<f32>) {
+    output.clear();
+    if channels <= 1 {
+        output.extend_from_slice(samples);
+        return;
+    }
+    output.extend(samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / frame.len() as f32));
 }
 
 /// Convert stereo to mono by averaging channels
@@ -67,3 +234,140 @@ pub fn stereo_to_mono(samples: &[f32]) -> Vec<f32> {
         })
         .collect()
 }
+
+/// Convert 16-bit PCM to f32 samples in [-1.0, 1.0]
+///
+/// Written as a plain map over the slice so the compiler can vectorize it.
+pub fn i16_to_f32(samples: &[i16]) -> Vec<f32> {
+    let mut output = Vec::new();
+    i16_to_f32_into(samples, &mut output);
+    output
+}
+
+/// Convert 16-bit PCM to f32 samples into `output`, reusing its capacity
+pub fn i16_to_f32_into(samples: &[i16], output: &mut Vec<f32>) {
+    output.clear();
+    output.extend(samples.iter().map(|&s| (s as f32 / 32768.0).clamp(-1.0, 1.0)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i16_to_f32_range() {
+        let out = i16_to_f32(&[i16::MIN, -16384, 0, 16384, i16::MAX]);
+        assert_eq!(out[0], -1.0);
+        assert_eq!(out[1], -0.5);
+        assert_eq!(out[2], 0.0);
+        assert_eq!(out[3], 0.5);
+        assert!(out[4] < 1.0 && out[4] > 0.9999);
+    }
+
+    #[test]
+    fn test_i16_matches_f32_path() {
+        // Same audio as f32 and as quantized i16 should reach the pipeline
+        // within one quantization step of each other
+        let f32_audio: Vec<f32> = (0..16000)
+            .map(|i| (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 16000.0).sin() * 0.8)
+            .collect();
+        let i16_audio: Vec<i16> = f32_audio
+            .iter()
+            .map(|&s| (s * 32768.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+            .collect();
+
+        let converted = i16_to_f32(&i16_audio);
+        assert_eq!(converted.len(), f32_audio.len());
+        for (a, b) in converted.iter().zip(&f32_audio) {
+            assert!((a - b).abs() <= 1.0 / 32768.0, "{} vs {}", a, b);
+        }
+    }
+
+    fn sine(freq: f32, rate: u32, secs: f32) -> Vec<f32> {
+        let n = (rate as f32 * secs) as usize;
+        (0..n)
+            .map(|i| ((i as f64 * freq as f64 / rate as f64).fract() * 2.0 * std::f64::consts::PI).sin() as f32 * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn test_resample_48k_matches_native_16k() {
+        let resampled = resample_to_16khz(&sine(440.0, 48000, 1.0), 48000).unwrap();
+        let native = sine(440.0, 16000, 1.0);
+        assert_eq!(resampled.len(), native.len());
+
+        // Ignore the filter's edge transients. The resampler places samples
+        // to within a fraction of an output sample, and a whole sample's
+        // shift moves a 440Hz tone by up to 0.086
+        for (a, b) in resampled[400..15600].iter().zip(&native[400..15600]) {
+            assert!((a - b).abs() < 0.07, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_resample_keeps_timing() {
+        let mut click = vec![0.0; 48000];
+        click[30000] = 1.0;
+        let resampled = resample_to_16khz(&click, 48000).unwrap();
+        let peak = (0..resampled.len()).max_by(|&a, &b| resampled[a].total_cmp(&resampled[b])).unwrap();
+        assert!(peak.abs_diff(10000) <= 1, "click at {}", peak);
+    }
+
+    #[test]
+    fn test_resample_44k_length() {
+        let resampled = resample_to_16khz(&sine(440.0, 44100, 2.0), 44100).unwrap();
+        assert_eq!(resampled.len(), 32000);
+    }
+
+    #[test]
+    fn test_resample_removes_content_above_nyquist() {
+        // 10kHz is above 16kHz's Nyquist and must not alias into the output
+        let resampled = resample_to_16khz(&sine(10000.0, 48000, 1.0), 48000).unwrap();
+        let rms = (resampled[400..15600].iter().map(|s| s * s).sum::<f32>() / 15200.0).sqrt();
+        assert!(rms < 0.01, "aliased energy: {}", rms);
+    }
+
+    #[test]
+    fn test_resample_state_reuse_matches_fresh() {
+        let mut state = ResampleState::default();
+        let mut output = Vec::new();
+        for (rate, secs) in [(48000, 1.0), (48000, 0.5), (44100, 1.0), (48000, 1.0)] {
+            let audio = sine(440.0, rate, secs);
+            resample_to_16khz_into(&audio, rate, &mut state, &mut output).unwrap();
+            assert_eq!(output, resample_to_16khz(&audio, rate).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_stream_resampler_matches_whole_input() {
+        for (rate, secs) in [(48000, 1.0), (44100, 0.7), (22050, 0.01), (16000, 0.5)] {
+            let audio = sine(440.0, rate, secs);
+            let mut resampler = StreamResampler::new(rate).unwrap();
+            let mut output = Vec::new();
+            // Uneven pieces, like decoded packets
+            for piece in audio.chunks(1152) {
+                resampler.push(piece, &mut output).unwrap();
+            }
+            resampler.finish(&mut output).unwrap();
+            assert_eq!(output, resample_to_16khz(&audio, rate).unwrap(), "{} Hz", rate);
+        }
+    }
+
+    #[test]
+    fn test_resample_16k_passthrough() {
+        let audio = sine(440.0, 16000, 0.1);
+        assert_eq!(resample_to_16khz(&audio, 16000).unwrap(), audio);
+    }
+
+    #[test]
+    fn test_downmix_to_mono() {
+        assert_eq!(downmix_to_mono(&[1.0, 0.0, 0.5, 0.5], 2), vec![0.5, 0.5]);
+        assert_eq!(downmix_to_mono(&[0.25, 0.5, 0.75], 3), vec![0.5]);
+        assert_eq!(downmix_to_mono(&[0.1, 0.2], 1), vec![0.1, 0.2]);
+    }
+
+    #[test]
+    fn test_i16_to_f32_empty() {
+        assert!(i16_to_f32(&[]).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Energy-based voice activity detection for trimming silence before STT
+
+use std::ops::Range;
+
+/// VAD frame length (30ms at 16kHz)
+const FRAME_SAMPLES: usize = 480;
+
+/// Audio kept around each speech region so onsets and tails aren't clipped (200ms)
+const PADDING_SAMPLES: usize = 3200;
+
+/// Root-mean-square energy of a frame
+pub(crate) fn rms(frame: &[f32]) -> f32 {
+    if frame.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = frame.iter().map(|s| s * s).sum();
+    (sum / frame.len() as f32).sqrt()
+}
+
+/// Find the regions of 16kHz audio that contain speech
+///
+/// Frames whose RMS reaches `threshold` count as speech. Speech separated
+/// by a pause of at least `min_silence_ms` starts a new region; with
+/// `min_silence_ms` of 0 there is a single region from the first to the
+/// last speech frame. Regions are padded by 200ms and never overlap.
+/// Returns no regions if no frame reaches the threshold.
+pub fn speech_regions(audio: &[f32], threshold: f32, min_silence_ms: u32) -> Vec<Range<usize>> {
+    let split_frames = if min_silence_ms == 0 {
+        usize::MAX
+    } else {
+        // 16 samples per millisecond
+        (min_silence_ms as usize * 16).div_ceil(FRAME_SAMPLES).max(1)
+    };
+
+    let mut regions: Vec<Range<usize>> = Vec::new();
+    let mut silent_frames = 0usize;
+    for (i, frame) in audio.chunks(FRAME_SAMPLES).enumerate() {
+        if rms(frame) < threshold {
+            silent_frames += 1;
+            continue;
+        }
+
+        let start = i * FRAME_SAMPLES;
+        let end = start + frame.len();
+        match regions.last_mut() {
+            Some(region) if silent_frames < split_frames => region.end = end,
+            _ => regions.push(start..end),
+        }
+        silent_frames = 0;
+    }
+
+    // Pad, splitting the difference where padding would overlap
+    let padded: Vec<Range<usize>> = regions
+        .iter()
+        .map(|r| r.start.saturating_sub(PADDING_SAMPLES)..(r.end + PADDING_SAMPLES).min(audio.len()))
+        .collect();
+    (0..padded.len())
+        .map(|i| {
+            let start = match i {
+                0 => padded[0].start,
+                _ => padded[i].start.max((regions[i - 1].end + regions[i].start) / 2),
+            };
+            let end = match regions.get(i + 1) {
+                Some(next) => padded[i].end.min((regions[i].end + next.start) / 2),
+                None => padded[i].end,
+            };
+            start..end
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(len: usize) -> Vec<f32> {
+        (0..len).map(|i| (i as f32 * 0.1).sin() * 0.3).collect()
+    }
+
+    /// Lengths are whole frames so region boundaries are exact
+    fn recording(parts: &[(bool, usize)]) -> Vec<f32> {
+        parts
+            .iter()
+            .flat_map(|&(speech, len)| if speech { tone(len) } else { vec![0.0; len] })
+            .collect()
+    }
+
+    #[test]
+    fn test_trims_leading_and_trailing_silence() {
+        // ~1s silence, ~1s speech, 1.5s silence
+        let audio = recording(&[(false, 15840), (true, 16320), (false, 24000)]);
+        let regions = speech_regions(&audio, 0.01, 0);
+        assert_eq!(regions, vec![15840 - PADDING_SAMPLES..32160 + PADDING_SAMPLES]);
+    }
+
+    #[test]
+    fn test_short_pause_keeps_one_region() {
+        let audio = recording(&[(true, 16320), (false, 8160), (true, 16320)]);
+        assert_eq!(speech_regions(&audio, 0.01, 1000).len(), 1);
+    }
+
+    #[test]
+    fn test_long_pause_splits_regions() {
+        let audio = recording(&[(true, 16320), (false, 48000), (true, 16320)]);
+        let regions = speech_regions(&audio, 0.01, 1000);
+        assert_eq!(regions, vec![0..16320 + PADDING_SAMPLES, 64320 - PADDING_SAMPLES..80640]);
+
+        // Splitting disabled
+        assert_eq!(speech_regions(&audio, 0.01, 0), vec![0..80640]);
+    }
+
+    #[test]
+    fn test_padding_never_overlaps() {
+        // 240ms pause: split, but closer than two paddings
+        let audio = recording(&[(true, 9600), (false, 3840), (true, 9600)]);
+        let regions = speech_regions(&audio, 0.01, 100);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].end, regions[1].start);
+    }
+
+    #[test]
+    fn test_silence_has_no_regions() {
+        assert!(speech_regions(&[0.0; 16000], 0.01, 0).is_empty());
+        assert!(speech_regions(&[], 0.01, 0).is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! Batch processing: many recordings in one call, with the models loaded
+//! once, upcoming files decoded while the current one is transcribed, and
+//! each transcribed while the one before it is formatted
+
+use crate::audio::{load_audio_file, AudioInput};
+use crate::cancel::CancelToken;
+use crate::pipeline::{Pipeline, PipelineError, PipelineResult, ProcessOptions, QueuedRequest};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Instant;
+
+/// Most threads decoding recordings ahead of the models
+pub const MAX_DECODE_THREADS: usize = 8;
+
+/// One recording of a batch
+#[derive(Debug, Clone)]
+pub enum BatchInput {
+    /// An audio file (see `load_audio_file`)
+    File(PathBuf),
+    /// Interleaved samples at any rate and channel count
+    Samples { samples: Vec<f32>, sample_rate: u32, channels: u16 },
+}
+
+/// How a batch is processed
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Options for every recording; triggering `cancel` stops the batch
+    pub process: ProcessOptions,
+    /// Context hint for every recording
+    pub context: Option<String>,
+    /// Threads decoding and resampling the next recordings while the models
+    /// work on the current one (0 or 1 decodes each in turn on the calling
+    /// thread; capped at `MAX_DECODE_THREADS`)
+    pub decode_threads: usize,
+}
+
+/// Progress of a batch, reported as each recording finishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchProgress {
+    /// Index of the recording that just finished
+    pub index: usize,
+    /// Recordings finished so far, failed ones included
+    pub completed: usize,
+    /// Recordings that failed so far
+    pub failed: usize,
+    pub total: usize,
+    /// Time since the batch started
+    pub elapsed_ms: u64,
+}
+
+impl Pipeline {
+    /// Process many recordings with the loaded models, returning a result
+    /// per input, in input order
+    ///
+    /// A recording that fails, such as a corrupt file, gets its error and
+    /// the batch goes on; once `options.process.cancel` is triggered, the
+    /// recordings not yet processed fail with `PipelineError::Cancelled`.
+    /// With formatting on, the next recording is transcribed while the LLM
+    /// formats the current one; with `decode_threads` above 1 the files after
+    /// it are decoded meanwhile, and may finish out of order. Timings are
+    /// per recording and leave out time spent queued. `audio_prep_ms`
+    /// includes reading and decoding the file.
+    pub fn process_batch(
+        &mut self,
+        inputs: &[BatchInput],
+        options: &BatchOptions,
+        mut progress: impl FnMut(BatchProgress),
+    ) -> Vec<Result<PipelineResult>> {
+        let start = Instant::now();
+        let total = inputs.len();
+        let cancel = &options.process.cancel;
+        let threads = options.decode_threads.min(MAX_DECODE_THREADS).min(total);
+        tracing::info!("Processing a batch of {} recordings ({} decode threads)", total, threads.max(1));
+
+        let context = &options.context;
+        if threads <= 1 {
+            let decoded = inputs.iter().enumerate().map(|(index, input)| {
+                QueuedRequest::decoded(index, decode(input, cancel), context.clone(), options.process.clone())
+            });
+            return self.run_batch(decoded, total, start, &mut progress);
+        }
+
+        let next = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            // Bounded, so decoding stays only a few recordings ahead
+            let (tx, rx) = mpsc::sync_channel(threads);
+            for _ in 0..threads {
+                let tx = tx.clone();
+                let next = &next;
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= total || tx.send((index, decode(&inputs[index], cancel))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+            let decoded = rx
+                .into_iter()
+                .map(|(index, audio)| QueuedRequest::decoded(index, audio, context.clone(), options.process.clone()));
+            self.run_batch(decoded, total, start, &mut progress)
+        })
+    }
+
+    fn run_batch(
+        &mut self,
+        decoded: impl Iterator<Item = QueuedRequest<usize>> + Send,
+        total: usize,
+        start: Instant,
+        progress: &mut impl FnMut(BatchProgress),
+    ) -> Vec<Result<PipelineResult>> {
+        let mut results: Vec<Option<Result<PipelineResult>>> = std::iter::repeat_with(|| None).take(total).collect();
+        let (mut completed, mut failed) = (0, 0);
+        self.process_queued(decoded, |index, result| {
+            let result = result.map_err(anyhow::Error::from);
+            if let Err(e) = &result {
+                tracing::warn!("Batch recording {} failed: {:#}", index, e);
+                failed += 1;
+            }
+            results[index] = Some(result);
+            completed += 1;
+            progress(BatchProgress { index, completed, failed, total, elapsed_ms: start.elapsed().as_millis() as u64 });
+        });
+        tracing::info!("Batch done: {} of {} recordings failed", failed, total);
+
+        // Every index is decoded exactly once
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow::anyhow!("Recording was not processed"))))
+            .collect()
+    }
+}
+
+/// Read and convert an input to 16kHz mono, returning the samples and the
+/// time it took
+fn decode(input: &BatchInput, cancel: &CancelToken) -> Result<(Vec<f32>, u64)> {
+    if cancel.is_cancelled() {
+        return Err(PipelineError::cancelled().into());
+    }
+    let t = Instant::now();
+    let audio = match input {
+        BatchInput::File(path) => load_audio_file(path)?.as_input().to_16khz_mono()?.into_owned(),
+        BatchInput::Samples { samples, sample_rate, channels } => {
+            AudioInput::new(samples, *sample_rate, *channels).to_16khz_mono()?.into_owned()
+        }
+    };
+    Ok((audio, t.elapsed().as_millis() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{FormatContext, TextFormatter};
+    use crate::pipeline::{FormattingMode, ProcessProfile, RecoveryConfig};
+    use crate::transcribe::{SpeechToText, SttOptions, TranscriptionResult};
+    use crate::PipelineBuilder;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Transcribes every recording as its length in samples
+    struct SampleCount;
+
+    impl SpeechToText for SampleCount {
+        fn transcribe(&mut self, audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+            Ok(TranscriptionResult {
+                text: format!("{} samples", audio.len()),
+                word_timestamps: Vec::new(),
+                confidence: 0.9,
+                no_speech_probability: 0.0,
+                language: Some("en".to_string()),
+                encode_ms: 0,
+                decode_ms: 0,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
+            })
+        }
+    }
+
+    /// `SampleCount` that fails on recordings of `fail_on` samples, after
+    /// sleeping `delay`
+    struct SlowSampleCount {
+        fail_on: usize,
+        delay: Duration,
+    }
+
+    impl SpeechToText for SlowSampleCount {
+        fn transcribe(&mut self, audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult> {
+            std::thread::sleep(self.delay);
+            if audio.len() == self.fail_on {
+                anyhow::bail!("STT failed on {} samples", audio.len());
+            }
+            SampleCount.transcribe(audio, opts)
+        }
+    }
+
+    /// Returns the transcript unchanged, failing on `fail_on`, after
+    /// sleeping `delay`
+    struct SlowEcho {
+        fail_on: &'static str,
+        delay: Duration,
+    }
+
+    impl TextFormatter for SlowEcho {
+        fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> Result<String> {
+            std::thread::sleep(self.delay);
+            if transcript == self.fail_on {
+                anyhow::bail!("Formatting failed on {:?}", transcript);
+            }
+            Ok(transcript.to_string())
+        }
+    }
+
+    /// `SampleCount` that sends the length of each recording it starts on
+    struct ReportingSampleCount(mpsc::Sender<usize>);
+
+    impl SpeechToText for ReportingSampleCount {
+        fn transcribe(&mut self, audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult> {
+            let _ = self.0.send(audio.len());
+            SampleCount.transcribe(audio, opts)
+        }
+    }
+
+    /// Returns the transcript unchanged once the STT engine has started on
+    /// a longer recording, noting whether it did within a few seconds
+    struct AwaitNextRecording {
+        stt_started: mpsc::Receiver<usize>,
+        /// The last recording's length, with none after it to wait for
+        last: usize,
+        overlapped: Arc<Mutex<Vec<bool>>>,
+    }
+
+    impl TextFormatter for AwaitNextRecording {
+        fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> Result<String> {
+            let samples: usize = transcript.split(' ').next().and_then(|n| n.parse().ok()).unwrap_or(self.last);
+            if samples < self.last {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                let overlapped = std::iter::from_fn(|| {
+                    self.stt_started.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok()
+                })
+                .any(|started| started > samples);
+                self.overlapped.lock().unwrap().push(overlapped);
+            }
+            Ok(transcript.to_string())
+        }
+    }
+
+    fn slow_pipeline(delay: Duration) -> Pipeline {
+        PipelineBuilder::new()
+            .stt(Box::new(SlowSampleCount { fail_on: 32000, delay }))
+            .llm_engine(Box::new(SlowEcho { fail_on: "48000 samples", delay }))
+            .recovery(RecoveryConfig { fallback_to_transcribe_only: false, ..Default::default() })
+            .build()
+            .unwrap()
+    }
+
+    fn tone(samples: usize) -> Vec<f32> {
+        (0..samples).map(|i| (i as f32 * 0.1).sin() * 0.5).collect()
+    }
+
+    fn batch() -> Vec<BatchInput> {
+        vec![
+            BatchInput::Samples { samples: tone(16000), sample_rate: 16000, channels: 1 },
+            BatchInput::File(PathBuf::from("/nonexistent/memo.wav")),
+            BatchInput::Samples { samples: tone(32000), sample_rate: 16000, channels: 1 },
+            BatchInput::Samples { samples: tone(16000), sample_rate: 0, channels: 1 },
+            BatchInput::Samples { samples: tone(48000), sample_rate: 16000, channels: 1 },
+        ]
+    }
+
+    #[test]
+    fn test_batch_keeps_order_and_isolates_failures() {
+        for decode_threads in [0, 3] {
+            let mut pipeline =
+                PipelineBuilder::new().stt(Box::new(SampleCount)).formatting(FormattingMode::None).build().unwrap();
+            let options = BatchOptions { decode_threads, ..Default::default() };
+            let mut reports = Vec::new();
+            let results = pipeline.process_batch(&batch(), &options, |p| reports.push(p));
+
+            assert_eq!(results.len(), 5);
+            let texts: Vec<_> = results.iter().map(|r| r.as_ref().ok().map(|r| r.raw_transcript.clone())).collect();
+            assert!(texts[0].is_some() && texts[2].is_some() && texts[4].is_some(), "{:?}", texts);
+            assert!(texts[1].is_none() && texts[3].is_none(), "{:?}", texts);
+            assert_ne!(texts[0], texts[2]);
+
+            assert_eq!(reports.len(), 5);
+            let last = reports.last().unwrap();
+            assert_eq!((last.completed, last.failed, last.total), (5, 2, 5));
+        }
+    }
+
+    #[test]
+    fn test_cancelled_batch_fails_the_rest() {
+        let mut pipeline =
+            PipelineBuilder::new().stt(Box::new(SampleCount)).formatting(FormattingMode::None).build().unwrap();
+        let options = BatchOptions::default();
+        let cancel = options.process.cancel.clone();
+        let results = pipeline.process_batch(&batch(), &options, |p| {
+            if p.index == 0 {
+                cancel.cancel();
+            }
+        });
+        assert!(results[0].is_ok());
+        for result in &results[1..] {
+            let err = result.as_ref().unwrap_err();
+            assert!(matches!(err.downcast_ref::<PipelineError>(), Some(PipelineError::Cancelled { .. })), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_stage_errors_reach_their_recording() {
+        for decode_threads in [0, 3] {
+            let mut pipeline = slow_pipeline(Duration::ZERO);
+            let options = BatchOptions { decode_threads, ..Default::default() };
+            let mut reports = Vec::new();
+            let results = pipeline.process_batch(&batch(), &options, |p| reports.push(p));
+
+            assert_eq!(results[0].as_ref().unwrap().formatted_text, "16000 samples");
+            let stt_error = results[2].as_ref().unwrap_err();
+            assert!(
+                matches!(stt_error.downcast_ref::<PipelineError>(), Some(PipelineError::TranscriptionFailed { .. })),
+                "{}",
+                stt_error
+            );
+            assert!(format!("{:#}", stt_error).contains("STT failed on 32000 samples"), "{:#}", stt_error);
+            let format_error = results[4].as_ref().unwrap_err();
+            assert!(
+                matches!(format_error.downcast_ref::<PipelineError>(), Some(PipelineError::LlmFormattingFailed { .. })),
+                "{}",
+                format_error
+            );
+            assert!(results[1].is_err() && results[3].is_err());
+
+            let last = reports.last().unwrap();
+            assert_eq!((last.completed, last.failed, last.total), (5, 4, 5));
+        }
+
+        // The engines are back in place for the next request
+        let mut pipeline = slow_pipeline(Duration::ZERO);
+        pipeline.process_batch(&batch(), &BatchOptions::default(), |_| {});
+        assert_eq!(pipeline.process(&tone(16000), None).unwrap().formatted_text, "16000 samples");
+    }
+
+    #[test]
+    fn test_next_recording_is_transcribed_while_one_is_formatted() {
+        let (started, stt_started) = mpsc::channel();
+        let overlapped = Arc::new(Mutex::new(Vec::new()));
+        let formatter = AwaitNextRecording { stt_started, last: 48000, overlapped: Arc::clone(&overlapped) };
+        let mut pipeline =
+            PipelineBuilder::new().stt(Box::new(ReportingSampleCount(started))).llm_engine(Box::new(formatter)).build().unwrap();
+
+        let options = ProcessOptions { profile: ProcessProfile::Dictation, ..Default::default() };
+        let queued = (1..=3).map(|i| QueuedRequest::new(i, tone(i * 16000), None, options.clone()));
+        let mut finished = Vec::new();
+        pipeline.process_queued(queued, |i, result| {
+            assert_eq!(result.unwrap().raw_transcript, format!("{} samples", i * 16000));
+            finished.push(i);
+        });
+
+        assert_eq!(finished, [1, 2, 3]);
+        // Recordings 2 and 3 were being transcribed while 1 and 2 were formatted
+        assert_eq!(*overlapped.lock().unwrap(), [true, true]);
+    }
+
+    /// Timing-dependent benchmark: `cargo test -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn test_batch_pipelining_speedup() {
+        let delay = Duration::from_millis(100);
+        let inputs: Vec<_> = (1..=10)
+            .map(|i| BatchInput::Samples { samples: tone(i * 1600), sample_rate: 16000, channels: 1 })
+            .collect();
+        let mut pipeline = slow_pipeline(delay);
+
+        let start = Instant::now();
+        let results = pipeline.process_batch(&inputs, &BatchOptions::default(), |_| {});
+        let wall_ms = start.elapsed().as_millis() as u64;
+        let serial_ms: u64 = results.iter().map(|r| r.as_ref().unwrap().timings.total_ms).sum();
+        println!("10 recordings: {}ms wall clock, {}ms one after the other", wall_ms, serial_ms);
+
+        for (i, result) in results.iter().enumerate() {
+            let result = result.as_ref().unwrap();
+            assert_eq!(result.raw_transcript, format!("{} samples", (i + 1) * 1600));
+            // Each recording's own timings don't include time spent queued
+            assert!(result.timings.total_ms < 3 * delay.as_millis() as u64, "{:?}", result.timings);
+        }
+        assert!(wall_ms * 4 < serial_ms * 3, "{}ms wall clock, {}ms serial", wall_ms, serial_ms);
+    }
+}
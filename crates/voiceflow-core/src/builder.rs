@@ -0,0 +1,384 @@
+//! Building a pipeline in code, for apps that embed the library instead of
+//! going through a config file
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::config::{AudioOptions, Config, Diarization, HistoryOptions, LlmModel, LlmOptions, ModelPrecision, MoonshineModel, SttEngine, VocabularyEntry, WhisperModel};
+use crate::diarize::SpeakerEmbedder;
+use crate::llm::TextFormatter;
+use crate::pipeline::{FormattingMode, InitProgress, Pipeline, RecoveryConfig};
+use crate::transcribe::SpeechToText;
+
+/// Builder settings that can't be used together
+#[derive(Debug, thiserror::Error)]
+pub enum BuildError {
+    #[error("A Whisper model path was given, but the STT engine is {engine}")]
+    WhisperPathWithoutWhisper { engine: String },
+
+    #[error("A Whisper model path and a speech-to-text engine were both given")]
+    WhisperPathWithCustomStt,
+
+    #[error("An LLM engine was given, but formatting is off")]
+    LlmEngineWithoutFormatting,
+}
+
+/// Voice activity detection settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VadSettings {
+    /// Trim leading and trailing silence before transcription
+    pub enabled: bool,
+    /// Voice activity detection threshold
+    pub threshold: f32,
+    /// Silence duration (ms) to trigger end of speech
+    pub silence_duration_ms: u32,
+    /// Pauses at least this long (ms) split the recording into separately
+    /// transcribed regions (0 never splits)
+    pub min_silence_ms: u32,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self::from(&AudioOptions::default())
+    }
+}
+
+impl From<&AudioOptions> for VadSettings {
+    fn from(audio: &AudioOptions) -> Self {
+        Self {
+            enabled: audio.vad_enabled,
+            threshold: audio.vad_threshold,
+            silence_duration_ms: audio.silence_duration_ms,
+            min_silence_ms: audio.min_silence_ms,
+        }
+    }
+}
+
+/// Builds a `Pipeline` setting by setting
+///
+/// Starts from the default config, or from a loaded one with
+/// `PipelineBuilder::from(config)`; settings without a setter can be changed
+/// there first. Combinations that can't work are rejected by `build` with a
+/// `BuildError` before any model is loaded.
+///
+/// ```no_run
+/// use voiceflow_core::{FormattingMode, PipelineBuilder, WhisperModel};
+///
+/// let mut pipeline = PipelineBuilder::new()
+///     .whisper_model(WhisperModel::Small)
+///     .language("de")
+///     .formatting(FormattingMode::PunctuationOnly)
+///     .build()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct PipelineBuilder {
+    pub(crate) config: Config,
+    pub(crate) recovery: RecoveryConfig,
+    pub(crate) formatting: FormattingMode,
+    pub(crate) whisper_model_path: Option<PathBuf>,
+    pub(crate) stt: Option<Box<dyn SpeechToText>>,
+    pub(crate) llm: Option<Box<dyn TextFormatter>>,
+    pub(crate) speaker_embedder: Option<Box<dyn SpeakerEmbedder>>,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        Self::from(Config::default())
+    }
+}
+
+impl From<Config> for PipelineBuilder {
+    fn from(config: Config) -> Self {
+        Self {
+            config,
+            recovery: RecoveryConfig::default(),
+            formatting: FormattingMode::default(),
+            whisper_model_path: None,
+            stt: None,
+            llm: None,
+            speaker_embedder: None,
+        }
+    }
+}
+
+impl PipelineBuilder {
+    /// Start from the default config
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The config the pipeline will be built with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Speech-to-text engine to load
+    pub fn stt_engine(mut self, engine: SttEngine) -> Self {
+        self.config.stt_engine = engine;
+        self
+    }
+
+    /// Whisper model to load from the models directory
+    pub fn whisper_model(mut self, model: WhisperModel) -> Self {
+        self.config.whisper_model = model;
+        self
+    }
+
+    /// Load Whisper from this model file instead of the models directory
+    ///
+    /// The file isn't checked against the known model checksums.
+    pub fn whisper_model_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.whisper_model_path = Some(path.into());
+        self
+    }
+
+    /// Moonshine model to load
+    pub fn moonshine_model(mut self, model: MoonshineModel) -> Self {
+        self.config.moonshine_model = model;
+        self
+    }
+
+    /// Precision of the Whisper and Moonshine weights to load (each
+    /// precision is a separate download)
+    pub fn stt_precision(mut self, precision: ModelPrecision) -> Self {
+        self.config.whisper_precision = precision;
+        self.config.moonshine_precision = precision;
+        self
+    }
+
+    /// Spoken language (ISO 639-1 code, or "auto" to detect it)
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.config.language = language.into();
+        self
+    }
+
+    /// LLM used for formatting
+    pub fn llm(mut self, model: LlmModel) -> Self {
+        self.config.llm_model = model;
+        self
+    }
+
+    /// LLM decoding parameters
+    pub fn llm_options(mut self, options: LlmOptions) -> Self {
+        self.config.llm_options = options;
+        self
+    }
+
+    /// Most formatting to apply: calls asking for more get this much, and
+    /// with `FormattingMode::None` the LLM is never loaded
+    pub fn formatting(mut self, formatting: FormattingMode) -> Self {
+        self.formatting = formatting;
+        self
+    }
+
+    /// Voice activity detection settings
+    pub fn vad(mut self, vad: VadSettings) -> Self {
+        self.config.audio.vad_enabled = vad.enabled;
+        self.config.audio.vad_threshold = vad.threshold;
+        self.config.audio.silence_duration_ms = vad.silence_duration_ms;
+        self.config.audio.min_silence_ms = vad.min_silence_ms;
+        self
+    }
+
+    /// Terms the STT engine and the LLM should get right
+    pub fn vocabulary(mut self, vocabulary: Vec<VocabularyEntry>) -> Self {
+        self.config.vocabulary = vocabulary;
+        self
+    }
+
+    /// Same audio in, same text out (see `Config::deterministic`)
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.config.deterministic = deterministic;
+        self
+    }
+
+    /// Fail requests when the LLM fails instead of returning the raw
+    /// transcript (see `Config::llm_strict`)
+    pub fn llm_strict(mut self, strict: bool) -> Self {
+        self.config.llm_strict = strict;
+        self
+    }
+
+    /// How LLM load failures are retried and recovered from
+    pub fn recovery(mut self, recovery: RecoveryConfig) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Transcribe with `engine` instead of loading one from the config
+    pub fn stt(mut self, engine: Box<dyn SpeechToText>) -> Self {
+        self.stt = Some(engine);
+        self
+    }
+
+    /// Format with `engine` instead of loading the configured LLM
+    pub fn llm_engine(mut self, engine: Box<dyn TextFormatter>) -> Self {
+        self.llm = Some(engine);
+        self
+    }
+
+    /// Speaker diarization settings
+    pub fn diarization(mut self, diarization: Diarization) -> Self {
+        self.config.diarization = diarization;
+        self
+    }
+
+    /// Keeping processed recordings with their results
+    pub fn history(mut self, history: HistoryOptions) -> Self {
+        self.config.history = history;
+        self
+    }
+
+    /// Embed voices with `embedder` instead of loading the configured
+    /// speaker model (diarization still has to be enabled)
+    pub fn speaker_embedder(mut self, embedder: Box<dyn SpeakerEmbedder>) -> Self {
+        self.speaker_embedder = Some(embedder);
+        self
+    }
+
+    /// Check the settings and load the pipeline
+    pub fn build(self) -> Result<Pipeline> {
+        self.build_with_progress(None)
+    }
+
+    /// Check the settings and load the pipeline, reporting each loading
+    /// stage to `progress` (see `Pipeline::new_with_progress`)
+    pub fn build_with_progress(self, progress: Option<&dyn InitProgress>) -> Result<Pipeline> {
+        self.check()?;
+        Pipeline::from_builder(self, progress)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.whisper_model_path.is_some() {
+            if self.stt.is_some() {
+                return Err(BuildError::WhisperPathWithCustomStt.into());
+            }
+            if self.config.stt_engine != SttEngine::Whisper {
+                return Err(BuildError::WhisperPathWithoutWhisper {
+                    engine: self.config.stt_engine.display_name().to_string(),
+                }
+                .into());
+            }
+        }
+        if self.llm.is_some() && self.formatting == FormattingMode::None {
+            return Err(BuildError::LlmEngineWithoutFormatting.into());
+        }
+        if self.stt.is_none() {
+            self.config.validate_language()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigError;
+    use crate::llm::FormatContext;
+    use crate::transcribe::{SttOptions, TranscriptionResult};
+
+    struct FixedTranscript(&'static str);
+
+    impl SpeechToText for FixedTranscript {
+        fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+            Ok(transcript(self.0))
+        }
+    }
+
+    fn transcript(text: &str) -> TranscriptionResult {
+        TranscriptionResult {
+            text: text.to_string(),
+            word_timestamps: Vec::new(),
+            confidence: 0.9,
+            no_speech_probability: 0.0,
+            language: Some("en".to_string()),
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature: 0.0,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
+        }
+    }
+
+    struct FixedFormatting(&'static str);
+
+    impl TextFormatter for FixedFormatting {
+        fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    /// Two seconds of a loud tone, kept by voice activity detection
+    fn speech_fixture() -> Vec<f32> {
+        (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect()
+    }
+
+    #[test]
+    fn test_supplied_engines_run_the_pipeline() {
+        let mut pipeline = Pipeline::with_engines(
+            Box::new(FixedTranscript("hello world how are you")),
+            Box::new(FixedFormatting("Hello world, how are you?")),
+        )
+        .unwrap();
+        let result = pipeline.process(&speech_fixture(), None).unwrap();
+        assert_eq!(result.raw_transcript, "hello world how are you");
+        assert_eq!(result.formatted_text, "Hello world, how are you?");
+
+        assert_eq!(result.timings.llm_load_ms, 0);
+
+        // A supplied formatter can't be reloaded, so it's kept
+        assert!(!pipeline.trim_memory());
+        assert!(!pipeline.unload_llm());
+        let result = pipeline.process(&speech_fixture(), None).unwrap();
+        assert_eq!(result.formatted_text, "Hello world, how are you?");
+
+        // Nor does the idle timeout unload supplied engines
+        let mut config = pipeline.config().clone();
+        config.idle_unload_seconds = Some(0);
+        config.idle_unload_stt = true;
+        pipeline.update_config(&config).unwrap();
+        assert_eq!(pipeline.idle_unload_in(), None);
+        assert!(!pipeline.unload_if_idle());
+        assert_eq!(pipeline.process(&speech_fixture(), None).unwrap().timings.stt_load_ms, 0);
+
+        let mut unformatted = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("hello world how are you")))
+            .formatting(FormattingMode::None)
+            .build()
+            .unwrap();
+        let result = unformatted.process(&speech_fixture(), None).unwrap();
+        assert_eq!(result.formatted_text, "hello world how are you");
+    }
+
+    #[test]
+    fn test_incompatible_settings_are_rejected() {
+        let err = PipelineBuilder::new()
+            .stt_engine(SttEngine::Moonshine)
+            .whisper_model_path("/models/ggml-small.bin")
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err.downcast_ref::<BuildError>(), Some(BuildError::WhisperPathWithoutWhisper { .. })), "{}", err);
+
+        let err = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("")))
+            .whisper_model_path("/models/ggml-small.bin")
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err.downcast_ref::<BuildError>(), Some(BuildError::WhisperPathWithCustomStt)), "{}", err);
+
+        let err = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("")))
+            .llm_engine(Box::new(FixedFormatting("")))
+            .formatting(FormattingMode::None)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err.downcast_ref::<BuildError>(), Some(BuildError::LlmEngineWithoutFormatting)), "{}", err);
+
+        let err = PipelineBuilder::new().stt_engine(SttEngine::Moonshine).language("de").build().err().unwrap();
+        assert!(err.downcast_ref::<ConfigError>().is_some(), "{}", err);
+    }
+}
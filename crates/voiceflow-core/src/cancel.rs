@@ -0,0 +1,151 @@
+//! Cooperative cancellation for in-flight pipeline runs, by the caller or
+//! by a time limit running out (see `Config::timeouts`)
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::pipeline::PipelineError;
+
+/// Shared flag checked between decode steps and LLM tokens
+///
+/// Clones share the same flag, so one clone can be handed to the pipeline
+/// while another is kept by whoever decides to cancel (e.g. a UI thread).
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    /// The earliest time limit of the run, if it has one
+    deadline: Option<Deadline>,
+}
+
+/// The time limit that stopped a request (see `TimeoutOptions`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutStage {
+    /// Transcription, `timeouts.stt_ms`
+    Stt,
+    /// Formatting a segment with the LLM, `timeouts.llm_ms`
+    Llm,
+    /// The whole request, `timeouts.total_ms`
+    Total,
+}
+
+impl std::fmt::Display for TimeoutStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Stt => "stt",
+            Self::Llm => "llm",
+            Self::Total => "total",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    stage: TimeoutStage,
+    started: Instant,
+    at: Instant,
+}
+
+impl CancelToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation of the run using this token
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether cancellation has been requested, or the time limit
+    /// has run out
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline.at)
+    }
+
+    /// A token sharing this one's flag that also stops the run `limit_ms`
+    /// after `started`, unless this one stops it sooner (0: no limit)
+    pub fn with_deadline(&self, stage: TimeoutStage, started: Instant, limit_ms: u64) -> Self {
+        let deadline = (limit_ms > 0).then(|| Deadline {
+            stage,
+            started,
+            at: started + Duration::from_millis(limit_ms),
+        });
+        let deadline = match (self.deadline, deadline) {
+            (Some(current), Some(new)) if current.at <= new.at => Some(current),
+            (current, new) => new.or(current),
+        };
+        Self { flag: Arc::clone(&self.flag), deadline }
+    }
+
+    /// `PipelineError::Timeout` if the run was stopped by its time limit
+    /// running out rather than by `cancel`
+    pub(crate) fn timeout(&self) -> Option<PipelineError> {
+        if self.flag.load(Ordering::SeqCst) {
+            return None;
+        }
+        let deadline = self.deadline?;
+        let now = Instant::now();
+        (now >= deadline.at).then(|| PipelineError::Timeout {
+            stage: deadline.stage,
+            elapsed_ms: now.duration_since(deadline.started).as_millis() as u64,
+        })
+    }
+
+    /// Clear the flag so the token can be reused for the next run
+    pub fn reset(&self) {
+        self.flag.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_token_not_cancelled() {
+        assert!(!CancelToken::new().is_cancelled());
+    }
+
+    #[test]
+    fn test_clones_share_flag() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        clone.cancel();
+        assert!(token.is_cancelled());
+
+        token.reset();
+        assert!(!clone.is_cancelled());
+    }
+
+    #[test]
+    fn test_deadline_stops_the_run() {
+        let token = CancelToken::new();
+        let now = Instant::now();
+        let unlimited = token.with_deadline(TimeoutStage::Stt, now, 0);
+        assert!(!unlimited.is_cancelled() && unlimited.timeout().is_none());
+
+        let expired = token.with_deadline(TimeoutStage::Total, now - Duration::from_secs(2), 1000);
+        assert!(expired.is_cancelled());
+        assert!(!token.is_cancelled(), "the original keeps no deadline");
+        match expired.timeout() {
+            Some(PipelineError::Timeout { stage: TimeoutStage::Total, elapsed_ms }) => assert!(elapsed_ms >= 2000),
+            other => panic!("unexpected {:?}", other),
+        }
+        // A later limit doesn't extend an earlier one
+        let stt = expired.with_deadline(TimeoutStage::Stt, now, 60_000);
+        assert!(matches!(stt.timeout(), Some(PipelineError::Timeout { stage: TimeoutStage::Total, .. })));
+        // And an earlier one takes over
+        let stage = token
+            .with_deadline(TimeoutStage::Total, now, 60_000)
+            .with_deadline(TimeoutStage::Llm, now - Duration::from_secs(1), 10);
+        assert!(matches!(stage.timeout(), Some(PipelineError::Timeout { stage: TimeoutStage::Llm, .. })));
+
+        // Cancelling is still reported as a cancel
+        stage.cancel();
+        assert!(stage.is_cancelled() && stage.timeout().is_none());
+        assert!(token.is_cancelled(), "the flag is shared");
+    }
+}
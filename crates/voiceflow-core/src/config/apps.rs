@@ -0,0 +1,241 @@
+//! Per-app settings: formatting preferences stored in the `[app_overrides]`
+//! table of the config file, keyed by the bundle id of the app the text goes
+//! into and applied to calls that name it (`ProcessOptions::app_id`)
+//!
+//! ```toml
+//! [app_overrides."com.apple.dt.Xcode"]
+//! formatting_mode = "punctuation-only"
+//! vocabulary_extra = ["VoiceFlow", "SwiftUI"]
+//!
+//! [app_overrides."com.apple.mail"]
+//! preset = "email"
+//! ```
+//!
+//! An override merges over the rest of the config:
+//! - `preset` replaces `default_preset`; a preset chosen by the call wins
+//! - `formatting_mode` caps the formatting, like the pipeline's: a call
+//!   never gets more than the app allows
+//! - `vocabulary_extra` is appended to `vocabulary`; an entry for a term
+//!   already there replaces it
+//! - `replacements_extra` runs after `replacements`, on their output
+
+use super::{check_prompt_template, check_vocabulary, Config, ConfigError, ReplacementRule, VocabularyEntry};
+use crate::llm::FormattingPreset;
+use crate::pipeline::FormattingMode;
+use crate::text::ReplacementRules;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Formatting preferences for one app
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AppOverride {
+    /// Preset used when a call doesn't choose one, instead of
+    /// `Config::default_preset`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<FormattingPreset>,
+    /// Most formatting applied, whatever a call asks for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatting_mode: Option<FormattingMode>,
+    /// Terms added to `Config::vocabulary`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary_extra: Vec<VocabularyEntry>,
+    /// Rules applied after `Config::replacements`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub replacements_extra: Vec<ReplacementRule>,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Config {
+    /// The override for app `app_id`, if there is one
+    pub fn app_override(&self, app_id: &str) -> Option<&AppOverride> {
+        self.app_overrides.get(app_id)
+    }
+
+    /// The vocabulary for dictation into app `app_id`: the configured
+    /// entries, then the app's, an app entry replacing a configured one
+    /// for the same term
+    pub fn vocabulary_for_app(&self, app_id: &str) -> Vec<VocabularyEntry> {
+        let extra = self.app_override(app_id).map_or(&[][..], |app| &app.vocabulary_extra);
+        merged_vocabulary(&self.vocabulary, extra)
+    }
+
+    /// The replacement rules for dictation into app `app_id`: the
+    /// configured ones, then the app's
+    pub fn replacements_for_app(&self, app_id: &str) -> Vec<ReplacementRule> {
+        let extra = self.app_override(app_id).map_or(&[][..], |app| &app.replacements_extra);
+        self.replacements.iter().chain(extra).cloned().collect()
+    }
+
+    /// Glossary of the terms app `app_id` adds, for the STT prompt (the
+    /// configured ones are in `stt_initial_prompt` already)
+    pub fn app_stt_glossary(&self, app_id: &str) -> Option<String> {
+        let app = self.app_override(app_id)?;
+        let terms: Vec<&str> = app
+            .vocabulary_extra
+            .iter()
+            .map(|entry| entry.term.trim())
+            .filter(|term| !self.vocabulary.iter().any(|configured| configured.term.trim() == *term))
+            .collect();
+        (!terms.is_empty()).then(|| format!("Glossary: {}.", terms.join(", ")))
+    }
+
+    /// Check every app override, each merged with the rest of the config
+    pub fn validate_app_overrides(&self) -> Result<()> {
+        for (app_id, app) in &self.app_overrides {
+            self.check_app_override(app_id, app).with_context(|| format!("Invalid app override {:?}", app_id))?;
+        }
+        Ok(())
+    }
+
+    fn check_app_override(&self, app_id: &str, app: &AppOverride) -> Result<()> {
+        if app_id.trim().is_empty() {
+            return Err(ConfigError::InvalidValue {
+                key: "app_overrides".to_string(),
+                message: "the app id must not be empty".to_string(),
+            }
+            .into());
+        }
+        if let Some(FormattingPreset::Custom(template)) = &app.preset {
+            check_prompt_template(template)?;
+        }
+        check_vocabulary(&merged_vocabulary(&self.vocabulary, &app.vocabulary_extra))?;
+        ReplacementRules::compile(&app.replacements_extra)?;
+        Ok(())
+    }
+
+    /// Store `app` as the override for app `app_id`, replacing any it had
+    ///
+    /// Fails, leaving the config as it was, if the override doesn't
+    /// validate or has fields this version doesn't know.
+    pub fn set_app_override(&mut self, app_id: &str, app: AppOverride) -> Result<()> {
+        if let Some(field) = app.unknown_fields.keys().next() {
+            return Err(ConfigError::UnknownField { field: format!("app_overrides.{}.{}", app_id, field) }.into());
+        }
+        self.check_app_override(app_id, &app)
+            .with_context(|| format!("Invalid app override {:?}", app_id))?;
+        self.app_overrides.insert(app_id.to_string(), app);
+        Ok(())
+    }
+
+    /// Remove the override for app `app_id`, returning whether it had one
+    pub fn remove_app_override(&mut self, app_id: &str) -> bool {
+        self.app_overrides.remove(app_id).is_some()
+    }
+}
+
+/// `configured`, with each entry of `extra` replacing the one for its term
+/// or appended
+fn merged_vocabulary(configured: &[VocabularyEntry], extra: &[VocabularyEntry]) -> Vec<VocabularyEntry> {
+    let mut vocabulary = configured.to_vec();
+    for entry in extra {
+        match vocabulary.iter_mut().find(|configured| configured.term.trim() == entry.term.trim()) {
+            Some(configured) => *configured = entry.clone(),
+            None => vocabulary.push(entry.clone()),
+        }
+    }
+    vocabulary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APPS: &str = r#"
+vocabulary = ["VoiceFlow", { term = "Kubernetes", sounds_like = ["cooper netties"] }]
+
+[[replacements]]
+pattern = "teh"
+replacement = "the"
+
+[app_overrides."com.apple.dt.Xcode"]
+formatting_mode = "punctuation-only"
+vocabulary_extra = ["SwiftUI", { term = "Kubernetes", sounds_like = ["cube control"] }]
+
+[[app_overrides."com.apple.dt.Xcode".replacements_extra]]
+pattern = "the"
+replacement = "THE"
+case_sensitive = true
+
+[app_overrides."com.apple.mail"]
+preset = "email"
+"#;
+
+    fn config() -> Config {
+        crate::config::migrate::parse_config(APPS).unwrap()
+    }
+
+    #[test]
+    fn test_app_override_from_toml() {
+        let config = config();
+        config.validate().unwrap();
+        let xcode = config.app_override("com.apple.dt.Xcode").unwrap();
+        assert_eq!(xcode.formatting_mode, Some(FormattingMode::PunctuationOnly));
+        assert_eq!(xcode.preset, None);
+        assert_eq!(config.app_override("com.apple.mail").unwrap().preset, Some(FormattingPreset::Email));
+        assert!(config.app_override("com.apple.Notes").is_none());
+
+        let saved = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(crate::config::migrate::parse_config(&saved).unwrap().app_overrides, config.app_overrides);
+    }
+
+    #[test]
+    fn test_vocabulary_is_appended_and_same_terms_replaced() {
+        let config = config();
+        let terms: Vec<_> = config.vocabulary_for_app("com.apple.dt.Xcode").into_iter().map(|e| e.term).collect();
+        assert_eq!(terms, ["VoiceFlow", "Kubernetes", "SwiftUI"]);
+        assert_eq!(config.vocabulary_for_app("com.apple.dt.Xcode")[1].sounds_like, ["cube control"]);
+        assert_eq!(config.vocabulary_for_app("com.apple.mail"), config.vocabulary);
+        assert_eq!(config.vocabulary_for_app("com.apple.Notes"), config.vocabulary);
+
+        // Only the terms the app adds; the configured ones are in the
+        // initial prompt
+        assert_eq!(config.app_stt_glossary("com.apple.dt.Xcode").as_deref(), Some("Glossary: SwiftUI."));
+        assert_eq!(config.app_stt_glossary("com.apple.mail"), None);
+    }
+
+    #[test]
+    fn test_replacements_run_after_the_configured_ones() {
+        let config = config();
+        let rules = ReplacementRules::compile(&config.replacements_for_app("com.apple.dt.Xcode")).unwrap();
+        assert_eq!(rules.apply("teh end"), "THE end");
+        let rules = ReplacementRules::compile(&config.replacements_for_app("com.apple.mail")).unwrap();
+        assert_eq!(rules.apply("teh end"), "the end");
+    }
+
+    #[test]
+    fn test_set_and_remove_app_override() {
+        let mut config = config();
+        let notes = AppOverride { preset: Some(FormattingPreset::Notes), ..AppOverride::default() };
+        config.set_app_override("com.apple.Notes", notes.clone()).unwrap();
+        assert_eq!(config.app_override("com.apple.Notes"), Some(&notes));
+
+        // The merged vocabulary must stay within the limits: two entries
+        // are configured already
+        let too_many = AppOverride {
+            vocabulary_extra: (0..crate::config::MAX_VOCABULARY_ENTRIES - 1).map(|i| VocabularyEntry::new(format!("term {}", i))).collect(),
+            ..AppOverride::default()
+        };
+        let err = config.set_app_override("com.apple.Notes", too_many).unwrap_err();
+        assert!(matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::TooManyVocabularyEntries { .. })));
+        assert_eq!(config.app_override("com.apple.Notes"), Some(&notes), "a failed set changes nothing");
+
+        let mut typo = AppOverride::default();
+        typo.unknown_fields.insert("presett".to_string(), "email".into());
+        let err = config.set_app_override("com.apple.Notes", typo).unwrap_err();
+        assert!(format!("{:#}", err).contains("app_overrides.com.apple.Notes.presett"), "{:#}", err);
+        assert!(config.set_app_override(" ", AppOverride::default()).is_err());
+
+        assert!(config.remove_app_override("com.apple.Notes"));
+        assert!(!config.remove_app_override("com.apple.Notes"));
+    }
+
+    #[test]
+    fn test_unknown_app_override_field_is_listed() {
+        let config = crate::config::migrate::parse_config("[app_overrides.\"com.apple.mail\"]\npresett = \"email\"\n")
+            .unwrap();
+        assert_eq!(config.unknown_field_names(), ["app_overrides.com.apple.mail.presett"]);
+    }
+}
@@ -0,0 +1,504 @@
+//! Access to single config fields by dotted key, so a settings toggle can
+//! change one option without knowing the whole config layout
+
+use super::{Config, ConfigError};
+use anyhow::Result;
+use serde_json::Value;
+
+/// Dotted keys and the config fields they address
+pub const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("stt.engine", "stt_engine"),
+    ("stt.whisper_model", "whisper_model"),
+    ("stt.moonshine_model", "moonshine_model"),
+    ("stt.whisper_precision", "whisper_precision"),
+    ("stt.moonshine_precision", "moonshine_precision"),
+    ("stt.language", "language"),
+    ("stt.task", "stt_task"),
+    ("stt.keep_original_transcript", "keep_original_transcript"),
+    ("stt.execution_provider", "stt_execution_provider"),
+    ("stt.threads", "stt_threads"),
+    ("stt.beam_size", "stt_decode.beam_size"),
+    ("stt.patience", "stt_decode.patience"),
+    ("stt.alternatives", "stt_decode.alternatives"),
+    ("stt.alternatives_in_prompt", "stt_decode.alternatives_in_prompt"),
+    ("stt.temperature_fallback", "stt_decode.temperature_fallback"),
+    ("stt.no_repeat_ngram", "stt_decode.no_repeat_ngram"),
+    ("stt.suppress_blank", "stt_decode.suppress_blank"),
+    ("stt.initial_prompt", "stt_decode.initial_prompt"),
+    ("stt.fallback_enabled", "stt_fallback_enabled"),
+    ("stt.min_confidence", "min_speech_confidence"),
+    ("stt.max_no_speech_probability", "max_no_speech_probability"),
+    ("stt.hallucination_filter", "hallucination_filter.enabled"),
+    ("stt.hallucination_repeat_threshold", "hallucination_filter.repeat_threshold"),
+    ("stt.hallucination_phrases", "hallucination_filter.extra_phrases"),
+    ("stt.hallucination_min_no_speech_probability", "hallucination_filter.min_no_speech_probability"),
+    ("llm.model", "llm_model"),
+    ("llm.custom_model_name", "custom_model_name"),
+    ("llm.chat_template", "chat_template"),
+    ("llm.formatter", "formatter"),
+    ("llm.max_tokens", "llm_options.max_tokens"),
+    ("llm.temperature", "llm_options.temperature"),
+    ("llm.top_p", "llm_options.top_p"),
+    ("llm.top_k", "llm_options.top_k"),
+    ("llm.repeat_penalty", "llm_options.repeat_penalty"),
+    ("llm.seed", "llm_options.seed"),
+    ("llm.n_gpu_layers", "llm_options.n_gpu_layers"),
+    ("llm.enable_thinking", "llm_options.enable_thinking"),
+    ("llm.threads", "llm_threads"),
+    ("llm.context_window", "llm_context_window"),
+    ("llm.prefix_cache", "llm_prefix_cache"),
+    ("llm.preload", "llm_preload"),
+    ("llm.strict", "llm_strict"),
+    ("llm.min_similarity", "min_format_similarity"),
+    ("llm.cache_size", "format_cache_size"),
+    ("llm.strip_code_fences", "llm_output.strip_code_fences"),
+    ("llm.keep_raw_output", "llm_output.keep_raw_output"),
+    ("audio.sample_rate", "audio.sample_rate"),
+    ("audio.max_chunk_ms", "audio.max_chunk_ms"),
+    ("audio.chunk_overlap_ms", "audio.chunk_overlap_ms"),
+    ("audio.preprocess", "audio.preprocess"),
+    ("audio.remove_dc", "audio.remove_dc"),
+    ("audio.normalize_target_dbfs", "audio.normalize_target_dbfs"),
+    ("audio.normalize_mode", "audio.normalize_mode"),
+    ("audio.warn_on_clipping", "audio.warn_on_clipping"),
+    ("audio.strict", "audio.strict"),
+    ("audio.max_duration_secs", "audio.max_duration_secs"),
+    ("vad.enabled", "audio.vad_enabled"),
+    ("vad.threshold", "audio.vad_threshold"),
+    ("vad.silence_duration_ms", "audio.silence_duration_ms"),
+    ("vad.min_silence_ms", "audio.min_silence_ms"),
+    ("formatting.context", "default_context"),
+    ("formatting.prompt", "formatting_prompt"),
+    ("voice_commands.enabled", "voice_commands.enabled"),
+    ("voice_commands.language", "voice_commands.language"),
+    ("voice_commands.custom", "voice_commands.custom"),
+    ("voice_commands.disabled", "voice_commands.disabled"),
+    ("numbers.enabled", "number_formatting.enabled"),
+    ("numbers.locale", "number_formatting.locale"),
+    ("numbers.cardinals", "number_formatting.cardinals"),
+    ("numbers.ordinals", "number_formatting.ordinals"),
+    ("numbers.times", "number_formatting.times"),
+    ("numbers.dates", "number_formatting.dates"),
+    ("numbers.currencies", "number_formatting.currencies"),
+    ("numbers.percentages", "number_formatting.percentages"),
+    ("numbers.phone_numbers", "number_formatting.phone_numbers"),
+    ("diarization.enabled", "diarization.enabled"),
+    ("diarization.model", "diarization.model"),
+    ("diarization.max_speakers", "diarization.max_speakers"),
+    ("diarization.similarity_threshold", "diarization.similarity_threshold"),
+    ("diarization.label_speakers", "diarization.label_speakers"),
+    ("history.enabled", "history.enabled"),
+    ("history.dir", "history.dir"),
+    ("history.max_entries", "history.max_entries"),
+    ("history.max_bytes", "history.max_bytes"),
+    ("history.max_age_days", "history.max_age_days"),
+    ("learning.enabled", "learning.enabled"),
+    ("learning.file", "learning.file"),
+    ("learning.min_reports", "learning.min_reports"),
+    ("learning.min_agreement", "learning.min_agreement"),
+    ("learning.max_learned", "learning.max_learned"),
+    ("endpointing.trailing_silence_ms", "endpointing.trailing_silence_ms"),
+    ("endpointing.max_utterance_ms", "endpointing.max_utterance_ms"),
+    ("telemetry.endpoint", "telemetry.endpoint"),
+    ("timeouts.stt_ms", "timeouts.stt_ms"),
+    ("timeouts.llm_ms", "timeouts.llm_ms"),
+    ("timeouts.total_ms", "timeouts.total_ms"),
+    ("command.max_ms", "command_max_ms"),
+    ("session.context_tokens", "session_context_tokens"),
+    ("app.auto_clipboard", "auto_clipboard"),
+    ("app.verify_models", "verify_models"),
+    ("app.warm_up_on_init", "warm_up_on_init"),
+    ("app.idle_unload_seconds", "idle_unload_seconds"),
+    ("app.idle_unload_stt", "idle_unload_stt"),
+    ("app.deterministic", "deterministic"),
+    ("app.log_file", "log_file"),
+    ("app.models_dir", "models_dir_override"),
+];
+
+/// Fields that only take effect when a model is loaded, as dotted field
+/// names
+pub const RELOAD_FIELDS: &[&str] = &[
+    "stt_engine",
+    "whisper_model",
+    "moonshine_model",
+    "whisper_precision",
+    "moonshine_precision",
+    "stt_execution_provider",
+    "stt_threads",
+    // The decoder's settings; stt_decode.alternatives_in_prompt only changes
+    // the LLM's prompt
+    "stt_decode.beam_size",
+    "stt_decode.patience",
+    "stt_decode.alternatives",
+    "stt_decode.temperature_fallback",
+    "stt_decode.no_repeat_ngram",
+    "stt_decode.suppress_blank",
+    "stt_decode.initial_prompt",
+    // Baked into the STT decoder's prompt or bias when it loads
+    "vocabulary",
+    "llm_model",
+    "custom_model_name",
+    "chat_template",
+    "formatter",
+    "llm_options.seed",
+    "llm_options.n_gpu_layers",
+    "llm_threads",
+    "llm_prefix_cache",
+    "models_dir_override",
+    // Thread counts and the LLM seed
+    "deterministic",
+];
+
+/// Comma-separated list of the supported keys
+pub(super) fn key_names() -> String {
+    CONFIG_KEYS.iter().map(|(key, _)| *key).collect::<Vec<_>>().join(", ")
+}
+
+impl Config {
+    /// Read a field by dotted key (see `CONFIG_KEYS`)
+    ///
+    /// Strings are returned as they are, unset optional fields as an empty
+    /// string and everything else as JSON (`true`, `0.7`,
+    /// `{"custom":"/path/model.gguf"}`).
+    pub fn get_key(&self, key: &str) -> Result<String> {
+        let path = field_path(key)?;
+        let json = serde_json::to_value(self)?;
+        Ok(match lookup(&json, path) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            // Float fields are f32; print them without the widening noise
+            Some(Value::Number(n)) if n.is_f64() => (n.as_f64().unwrap_or_default() as f32).to_string(),
+            Some(other) => other.to_string(),
+        })
+    }
+
+    /// Set a field by dotted key, parsing `value` in the format `get_key`
+    /// returns
+    ///
+    /// An empty value clears an optional field. The config is left unchanged
+    /// if the value has the wrong type or the result doesn't validate.
+    pub fn set_key(&mut self, key: &str, value: &str) -> Result<()> {
+        let path = field_path(key)?;
+        let mut json = serde_json::to_value(&*self)?;
+        if value.is_empty() {
+            // Null only reads back for an optional field, whatever it holds
+            let mut cleared = json.clone();
+            *lookup_mut(&mut cleared, path) = Value::Null;
+            if let Ok(updated) = serde_json::from_value::<Config>(cleared) {
+                updated.validate()?;
+                *self = updated;
+                return Ok(());
+            }
+        }
+        let field = lookup_mut(&mut json, path);
+        *field = parse_value(key, field, value)?;
+        self.replace_validated(json, key)
+    }
+
+    /// The whole config as a JSON object, with the field names of the TOML
+    /// file
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Overlay a JSON object in the format of `to_json` on the config
+    ///
+    /// Nested objects are merged, so only the fields to change need to be
+    /// given. Unknown fields are rejected, and the config is left unchanged
+    /// if the result doesn't validate.
+    pub fn apply_json(&mut self, json: &str) -> Result<()> {
+        let overrides: Value = serde_json::from_str(json).map_err(|e| invalid_value("json", e))?;
+        if !overrides.is_object() {
+            return Err(invalid_value("json", "expected an object").into());
+        }
+        let updated = self.overlaid(&overrides, "json")?;
+        updated.validate()?;
+        *self = updated;
+        Ok(())
+    }
+
+    /// This config with the fields of the JSON object `overrides` merged
+    /// over it, without validation; unknown fields are rejected
+    pub(super) fn overlaid(&self, overrides: &Value, key: &str) -> Result<Config> {
+        let mut merged = serde_json::to_value(self)?;
+        merge(&mut merged, overrides);
+
+        let updated: Config = serde_json::from_value(merged).map_err(|e| invalid_value(key, e))?;
+        // Unknown fields either land in `unknown_fields` or are skipped by serde
+        let known = self.unknown_field_names();
+        if let Some(field) = updated.unknown_field_names().into_iter().find(|name| !known.contains(name)) {
+            return Err(ConfigError::UnknownField { field }.into());
+        }
+        if let Some(field) = unknown_field(&serde_json::to_value(&updated)?, overrides, "") {
+            return Err(ConfigError::UnknownField { field }.into());
+        }
+        Ok(updated)
+    }
+
+    /// Build a config from a JSON object in the format of `to_json`, with
+    /// defaults for the fields it leaves out
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut config = Self::default();
+        config.apply_json(json)?;
+        Ok(config)
+    }
+
+    /// Split the changes from `loaded`, the config a pipeline's models were
+    /// loaded with, into those that apply at runtime and those that don't
+    ///
+    /// Returns this config with the `RELOAD_FIELDS` of `loaded`, and the
+    /// names of the reload fields that differ. The language and task are
+    /// kept too if they don't suit the loaded STT engine.
+    pub fn runtime_changes(&self, loaded: &Config) -> Result<(Config, Vec<&'static str>)> {
+        let mut json = serde_json::to_value(self)?;
+        let loaded_json = serde_json::to_value(loaded)?;
+        let mut needs_reload = Vec::new();
+        for path in RELOAD_FIELDS {
+            let current = lookup(&loaded_json, path).cloned();
+            if lookup(&json, path) != current.as_ref() {
+                needs_reload.push(*path);
+                set_field(&mut json, path, current);
+            }
+        }
+
+        let mut updated: Config = serde_json::from_value(json)?;
+        if updated.validate_language().is_err() {
+            if updated.language != loaded.language {
+                needs_reload.push("language");
+            }
+            if updated.stt_task != loaded.stt_task {
+                needs_reload.push("stt_task");
+            }
+            updated.language = loaded.language.clone();
+            updated.stt_task = loaded.stt_task;
+        }
+        Ok((updated, needs_reload))
+    }
+
+    fn replace_validated(&mut self, json: Value, key: &str) -> Result<()> {
+        let updated: Config = serde_json::from_value(json).map_err(|e| invalid_value(key, e))?;
+        updated.validate()?;
+        *self = updated;
+        Ok(())
+    }
+}
+
+fn field_path(key: &str) -> Result<&'static str, ConfigError> {
+    CONFIG_KEYS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, path)| *path)
+        .ok_or_else(|| ConfigError::UnknownKey { key: key.to_string() })
+}
+
+fn invalid_value(key: &str, message: impl ToString) -> ConfigError {
+    ConfigError::InvalidValue { key: key.to_string(), message: message.to_string() }
+}
+
+pub(super) fn lookup<'a>(json: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(json, |json, field| json.get(field))
+}
+
+/// The field at `path`, inserted as null if it isn't set
+pub(super) fn lookup_mut<'a>(json: &'a mut Value, path: &str) -> &'a mut Value {
+    path.split('.').fold(json, |json, field| &mut json[field])
+}
+
+/// Set the field at `path`, removing it for `None` (fields left out when
+/// empty can't be null)
+fn set_field(json: &mut Value, path: &str, value: Option<Value>) {
+    match value {
+        Some(value) => *lookup_mut(json, path) = value,
+        None => {
+            let (parent, field) = match path.rsplit_once('.') {
+                Some((parent, field)) => (lookup_mut(json, parent), field),
+                None => (json, path),
+            };
+            if let Some(object) = parent.as_object_mut() {
+                object.remove(field);
+            }
+        }
+    }
+}
+
+/// Parse a value for a field whose current value is `current`
+pub(super) fn parse_value(key: &str, current: &Value, value: &str) -> Result<Value, ConfigError> {
+    match current {
+        Value::Bool(_) => value.parse().map(Value::Bool).map_err(|_| invalid_value(key, "expected true or false")),
+        Value::Number(_) => match serde_json::from_str(value) {
+            Ok(number @ Value::Number(_)) => Ok(number),
+            _ => Err(invalid_value(key, "expected a number")),
+        },
+        Value::Null if value.is_empty() => Ok(Value::Null),
+        // Strings, enums and unset optional fields: JSON for structured
+        // values ({"custom": ...}, a seed), otherwise the text itself
+        _ => Ok(match serde_json::from_str(value) {
+            Ok(object @ Value::Object(_)) => object,
+            Ok(scalar @ (Value::Number(_) | Value::Bool(_))) if current.is_null() => scalar,
+            _ => Value::String(value.to_string()),
+        }),
+    }
+}
+
+/// Merge `overrides` into `base`, recursing into objects present in both
+fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => merge(existing, value),
+                    _ => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// The first field set in `overrides` that is missing from the config
+/// deserialized from them
+fn unknown_field(config: &Value, overrides: &Value, prefix: &str) -> Option<String> {
+    let overrides = overrides.as_object()?;
+    for (key, value) in overrides {
+        // Unset and empty fields aren't serialized
+        if value.is_null() || value.as_array().is_some_and(Vec::is_empty) {
+            continue;
+        }
+        let name = format!("{}{}", prefix, key);
+        match config.get(key) {
+            None => return Some(name),
+            Some(field) if field.is_object() && value.is_object() => {
+                if let Some(unknown) = unknown_field(field, value, &format!("{}.", name)) {
+                    return Some(unknown);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_key_reads() {
+        let config = Config::default();
+        for (key, _) in CONFIG_KEYS {
+            config.get_key(key).unwrap();
+        }
+        assert_eq!(config.get_key("stt.engine").unwrap(), "whisper");
+        assert_eq!(config.get_key("vad.enabled").unwrap(), "true");
+        assert_eq!(config.get_key("llm.min_similarity").unwrap(), "0.7");
+        assert_eq!(config.get_key("llm.seed").unwrap(), "");
+    }
+
+    #[test]
+    fn test_set_key_parses_the_field_type() {
+        let mut config = Config::default();
+        config.set_key("llm.temperature", "0.2").unwrap();
+        config.set_key("vad.enabled", "false").unwrap();
+        config.set_key("stt.engine", "moonshine").unwrap();
+        config.set_key("llm.seed", "42").unwrap();
+        config.set_key("formatting.prompt", "Tidy up: {transcript}").unwrap();
+        assert_eq!(config.llm_options.temperature, 0.2);
+        assert!(!config.audio.vad_enabled);
+        assert_eq!(config.get_key("stt.engine").unwrap(), "moonshine");
+        assert_eq!(config.llm_options.seed, Some(42));
+        assert_eq!(config.formatting_prompt.as_deref(), Some("Tidy up: {transcript}"));
+
+        config.set_key("llm.seed", "").unwrap();
+        assert_eq!(config.llm_options.seed, None);
+        config.set_key("formatting.prompt", "").unwrap();
+        assert_eq!(config.formatting_prompt, None);
+        assert!(config.set_key("llm.temperature", "").is_err());
+
+        config.set_key("app.idle_unload_seconds", "300").unwrap();
+        assert_eq!(config.idle_unload_seconds, Some(300));
+        assert_eq!(config.get_key("app.idle_unload_seconds").unwrap(), "300");
+    }
+
+    #[test]
+    fn test_bad_values_leave_config_unchanged() {
+        let mut config = Config::default();
+        let original = config.to_json().unwrap();
+
+        let err = config.set_key("llm.tempature", "0.2").unwrap_err();
+        assert!(matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::UnknownKey { .. })), "{}", err);
+        let err = config.set_key("llm.max_tokens", "lots").unwrap_err();
+        assert!(matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::InvalidValue { .. })), "{}", err);
+        assert!(config.set_key("vad.enabled", "yes").is_err());
+        assert!(config.set_key("stt.engine", "dragon").is_err());
+        // Parses, but fails validation
+        assert!(config.set_key("llm.temperature", "5").is_err());
+
+        assert_eq!(config.to_json().unwrap(), original);
+    }
+
+    #[test]
+    fn test_apply_json_merges_and_rejects_unknown_fields() {
+        let mut config = Config::default();
+        config.apply_json(r#"{"llm_options": {"temperature": 0.1}, "audio": {"vad_enabled": false}}"#).unwrap();
+        assert_eq!(config.llm_options.temperature, 0.1);
+        assert_eq!(config.llm_options.max_tokens, Config::default().llm_options.max_tokens);
+        assert!(!config.audio.vad_enabled);
+
+        config.apply_json(r#"{"vocabulary": [], "log_file": null}"#).unwrap();
+
+        let err = config.apply_json(r#"{"audio": {"vad_enabld": true}}"#).unwrap_err();
+        assert!(err.to_string().contains("audio.vad_enabld"), "{}", err);
+        assert!(config.apply_json(r#"{"llm_options": {"top_p": 3.0}}"#).is_err());
+        assert!(config.apply_json("[1, 2]").is_err());
+        assert_eq!(config.llm_options.top_p, Config::default().llm_options.top_p);
+
+        let round_trip: Config = serde_json::from_str(&config.to_json().unwrap()).unwrap();
+        assert!(!round_trip.audio.vad_enabled);
+    }
+
+    #[test]
+    fn test_runtime_changes_keep_loaded_models() {
+        use crate::config::SttEngine;
+
+        let loaded = Config::default();
+        let requested = Config::from_json(
+            r#"{"llm_model": {"custom": "/models/other.gguf"}, "llm_options": {"temperature": 0.3, "seed": 7},
+                "audio": {"vad_threshold": 0.1}, "formatting_prompt": "Tidy: {transcript}"}"#,
+        )
+        .unwrap();
+        let (updated, needs_reload) = requested.runtime_changes(&loaded).unwrap();
+        assert_eq!(needs_reload, ["llm_model", "llm_options.seed"]);
+        assert!(updated.vocabulary.is_empty());
+        assert_eq!(updated.llm_model, loaded.llm_model);
+        assert_eq!(updated.llm_options.seed, None);
+        assert_eq!(updated.llm_options.temperature, 0.3);
+        assert_eq!(updated.audio.vad_threshold, 0.1);
+        assert_eq!(updated.formatting_prompt.as_deref(), Some("Tidy: {transcript}"));
+
+        // German needs Whisper, which only a reload would bring
+        let moonshine = Config { stt_engine: SttEngine::Moonshine, ..Config::default() };
+        let requested = Config::from_json(r#"{"stt_engine": "whisper", "language": "de"}"#).unwrap();
+        let (updated, needs_reload) = requested.runtime_changes(&moonshine).unwrap();
+        assert_eq!(needs_reload, ["stt_engine", "language"]);
+        assert_eq!(updated.language, moonshine.language);
+        updated.validate().unwrap();
+
+        let (_, needs_reload) = loaded.runtime_changes(&loaded).unwrap();
+        assert!(needs_reload.is_empty());
+
+        let requested = Config::from_json(r#"{"vocabulary": ["VoiceFlow"]}"#).unwrap();
+        let (updated, needs_reload) = requested.runtime_changes(&loaded).unwrap();
+        assert_eq!(needs_reload, ["vocabulary"]);
+        assert!(updated.vocabulary.is_empty());
+
+        // Only the prompt shows the alternatives, the decoder finds them
+        let requested =
+            Config::from_json(r#"{"stt_decode": {"alternatives_in_prompt": true, "beam_size": 4}}"#).unwrap();
+        let (updated, needs_reload) = requested.runtime_changes(&loaded).unwrap();
+        assert_eq!(needs_reload, ["stt_decode.beam_size"]);
+        assert!(updated.stt_decode.alternatives_in_prompt);
+        assert_eq!(updated.stt_decode.beam_size, 1);
+    }
+}
@@ -0,0 +1,226 @@
+//! Upgrade of config files written by other versions
+//!
+//! Files are upgraded in memory when loaded and written in the current
+//! format on the next save. Settings this version doesn't know are kept in
+//! the `unknown_fields` tables, so a newer version's settings survive a
+//! downgrade.
+
+use super::Config;
+use toml::{Table, Value};
+
+/// Version of the config file format written by this build
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// Upgrade of a config file from a version to the next, as (version
+/// upgraded from, upgrade)
+type Migration = (u32, fn(&mut Table));
+
+/// Upgrades from older versions
+///
+/// Only needed when a field is renamed or changes meaning; fields missing
+/// from older files take their default.
+const MIGRATIONS: &[Migration] = &[];
+
+/// Parse a config file, upgrading it from the version it was written by
+///
+/// Only fails if the file isn't TOML. A field that doesn't parse (a model
+/// that no longer exists, a wrong type) is reset to its default with a
+/// warning, keeping the rest of the file.
+pub(super) fn parse_config(contents: &str) -> Result<Config, toml::de::Error> {
+    let mut table: Table = toml::from_str(contents)?;
+
+    let version = table
+        .get("schema_version")
+        .and_then(Value::as_integer)
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or(0);
+    if version > CONFIG_SCHEMA_VERSION {
+        tracing::warn!(
+            "Config was written by a newer version (schema {}); settings this version doesn't know are kept",
+            version
+        );
+    }
+    for (from, upgrade) in MIGRATIONS {
+        if version <= *from {
+            tracing::info!("Upgrading config from schema {}", from);
+            upgrade(&mut table);
+        }
+    }
+    // A newer version's format stays marked as such, so it isn't upgraded twice
+    table.insert("schema_version".to_string(), Value::Integer(version.max(CONFIG_SCHEMA_VERSION).into()));
+
+    let mut merged = default_table();
+    overlay(&mut merged, &table);
+    if let Ok(config) = Value::Table(merged).try_into() {
+        return Ok(config);
+    }
+
+    // Keep each field that parses on its own
+    let mut accepted = default_table();
+    for (path, value) in fields(&table, &accepted, Vec::new()) {
+        let mut candidate = accepted.clone();
+        set(&mut candidate, &path, value.clone());
+        match Value::Table(candidate.clone()).try_into::<Config>() {
+            Ok(_) => accepted = candidate,
+            Err(e) => tracing::warn!("Ignoring config field {}, using its default: {}", path.join("."), e),
+        }
+    }
+    Value::Table(accepted).try_into()
+}
+
+/// The default config, which supplies the fields a file is missing
+fn default_table() -> Table {
+    match Value::try_from(Config::default()) {
+        Ok(Value::Table(table)) => table,
+        _ => Table::new(),
+    }
+}
+
+/// Copy the fields of `file` over `base`, merging tables present in both
+fn overlay(base: &mut Table, file: &Table) {
+    for (key, value) in file {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(file)) => overlay(base, file),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The fields of `file`, descending into the tables that are sections of
+/// the config (tables in `defaults` too)
+fn fields<'a>(file: &'a Table, defaults: &Table, path: Vec<String>) -> Vec<(Vec<String>, &'a Value)> {
+    let mut found = Vec::new();
+    for (key, value) in file {
+        let mut field = path.clone();
+        field.push(key.clone());
+        match (value, defaults.get(key)) {
+            (Value::Table(file), Some(Value::Table(defaults))) => found.extend(fields(file, defaults, field)),
+            _ => found.push((field, value)),
+        }
+    }
+    found
+}
+
+fn set(table: &mut Table, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for key in parents {
+        let entry = table.entry(key.clone()).or_insert_with(|| Value::Table(Table::new()));
+        let Value::Table(inner) = entry else {
+            return;
+        };
+        table = inner;
+    }
+    table.insert(last.clone(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LlmModel, SttEngine, WhisperModel};
+
+    /// A config as the first release wrote it: no schema version and only
+    /// the fields it had
+    const V0_CONFIG: &str = r#"
+stt_engine = "whisper"
+whisper_model = "small"
+default_context = "email"
+personal_dictionary = ["VoiceFlow"]
+auto_clipboard = false
+
+[llm_model]
+custom = "/Users/me/models/mistral-7b-instruct.Q4_K_M.gguf"
+
+[llm_options]
+max_tokens = 256
+temperature = 0.2
+top_p = 0.8
+n_gpu_layers = -1
+enable_thinking = false
+
+[audio]
+sample_rate = 48000
+vad_threshold = 0.02
+silence_duration_ms = 600
+"#;
+
+    #[test]
+    fn test_v0_config_is_upgraded() {
+        let config = parse_config(V0_CONFIG).unwrap();
+        assert_eq!(config.schema_version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.llm_model, LlmModel::Custom("/Users/me/models/mistral-7b-instruct.Q4_K_M.gguf".to_string()));
+        assert_eq!(config.whisper_model, WhisperModel::Small);
+        assert_eq!(config.default_context, "email");
+        assert!(!config.auto_clipboard);
+        assert_eq!(config.llm_options.max_tokens, 256);
+        assert_eq!(config.audio.sample_rate, 48000);
+        // Fields added since then take their defaults
+        let defaults = Config::default();
+        assert_eq!(config.llm_options.repeat_penalty, defaults.llm_options.repeat_penalty);
+        assert_eq!(config.audio.max_chunk_ms, defaults.audio.max_chunk_ms);
+        assert_eq!(config.llm_output, defaults.llm_output);
+        assert_eq!(config.min_format_similarity, defaults.min_format_similarity);
+        assert!(config.unknown_field_names().is_empty());
+    }
+
+    #[test]
+    fn test_v0_config_with_missing_required_fields() {
+        // Hand-edited: sections cut down to the fields the user changed
+        let config = parse_config("llm_model = \"phi2\"\n[llm_options]\ntemperature = 0.5\n").unwrap();
+        assert_eq!(config.llm_model, LlmModel::Phi2);
+        assert_eq!(config.llm_options.temperature, 0.5);
+        assert_eq!(config.llm_options.max_tokens, Config::default().llm_options.max_tokens);
+        assert_eq!(config.audio.sample_rate, Config::default().audio.sample_rate);
+    }
+
+    #[test]
+    fn test_invalid_field_keeps_the_rest() {
+        let contents = V0_CONFIG
+            .replace("whisper_model = \"small\"", "whisper_model = \"huge\"")
+            .replace("sample_rate = 48000", "sample_rate = \"fast\"");
+        let config = parse_config(&contents).unwrap();
+        assert_eq!(config.whisper_model, WhisperModel::default());
+        assert_eq!(config.audio.sample_rate, Config::default().audio.sample_rate);
+        assert_eq!(config.audio.vad_threshold, 0.02);
+        assert!(matches!(config.llm_model, LlmModel::Custom(_)));
+        assert_eq!(config.stt_engine, SttEngine::Whisper);
+
+        assert!(parse_config("llm_model = ").is_err());
+    }
+
+    #[test]
+    fn test_newer_settings_survive_a_round_trip() {
+        let contents = r#"
+schema_version = 7
+llm_model = "phi2"
+speculative_decoding = true
+
+[llm_options]
+temperature = 0.1
+min_p = 0.05
+
+[audio]
+noise_suppression = "strong"
+
+[sync]
+endpoint = "https://example.com"
+"#;
+        let config = parse_config(contents).unwrap();
+        assert_eq!(config.schema_version, 7);
+        let mut names = config.unknown_field_names();
+        names.sort();
+        assert_eq!(names, ["audio.noise_suppression", "llm_options.min_p", "speculative_decoding", "sync"]);
+
+        let saved = toml::to_string_pretty(&config).unwrap();
+        let reloaded = parse_config(&saved).unwrap();
+        assert_eq!(reloaded.schema_version, 7);
+        assert_eq!(reloaded.unknown_fields, config.unknown_fields);
+        assert_eq!(reloaded.llm_options.unknown_fields["min_p"].as_float(), Some(0.05));
+        assert_eq!(reloaded.audio.unknown_fields["noise_suppression"].as_str(), Some("strong"));
+        assert_eq!(reloaded.llm_options.temperature, 0.1);
+    }
+}
@@ -0,0 +1,3080 @@
+//! Configuration management for VoiceFlow
+
+use crate::context::CONTEXT_FIELDS;
+use crate::llm::{ChatTemplate, FormattingPreset, OutputSanitizer};
+use crate::prosody::VOICE_COMMAND_LANGUAGES;
+use crate::text::ReplacementRules;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::env;
+
+mod apps;
+mod keys;
+mod migrate;
+mod overrides;
+mod paths;
+mod preflight;
+mod profiles;
+
+pub use apps::AppOverride;
+pub use keys::{CONFIG_KEYS, RELOAD_FIELDS};
+pub use migrate::CONFIG_SCHEMA_VERSION;
+pub use overrides::env_var_fields;
+pub use paths::AppDirs;
+pub use preflight::{ComponentReport, ModelStatus, PreflightReport};
+
+/// Configuration validation error
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Invalid temperature: {value}. Must be between 0.0 and 2.0")]
+    InvalidTemperature { value: f32 },
+
+    #[error("Invalid top_p: {value}. Must be between 0.0 and 1.0")]
+    InvalidTopP { value: f32 },
+
+    #[error("Invalid max_tokens: {value}. Must be between 1 and 8192")]
+    InvalidMaxTokens { value: u32 },
+
+    #[error("Invalid sample_rate: {value}. Must be one of: 8000, 16000, 22050, 44100, 48000")]
+    InvalidSampleRate { value: u32 },
+
+    #[error("Invalid VAD threshold: {value}. Must be between 0.0 and 1.0")]
+    InvalidVadThreshold { value: f32 },
+
+    #[error("Invalid silence duration: {value}ms. Must be between 100 and 5000")]
+    InvalidSilenceDuration { value: u32 },
+
+    #[error("Invalid min_silence_ms: {value}ms. Must be 0 (disabled) or between 100 and 30000")]
+    InvalidMinSilence { value: u32 },
+
+    #[error("Invalid max_chunk_ms: {value}ms. Must be between 5000 and 30000")]
+    InvalidMaxChunk { value: u32 },
+
+    #[error("Invalid chunk_overlap_ms: {value}ms. Must be at most half of max_chunk_ms")]
+    InvalidChunkOverlap { value: u32 },
+
+    #[error("Invalid normalize_target_dbfs: {value}. Must be between -60.0 and 0.0")]
+    InvalidNormalizeTarget { value: f32 },
+
+    #[error("Invalid min_speech_confidence: {value}. Must be between 0.0 and 1.0")]
+    InvalidMinSpeechConfidence { value: f32 },
+
+    #[error("Invalid max_no_speech_probability: {value}. Must be between 0.0 and 1.0")]
+    InvalidMaxNoSpeechProbability { value: f32 },
+
+    #[error("Invalid min_format_similarity: {value}. Must be between 0.0 and 1.0")]
+    InvalidMinFormatSimilarity { value: f32 },
+
+    #[error("Invalid hallucination_filter.repeat_threshold: {value}. Must be at least 2")]
+    InvalidRepeatThreshold { value: u32 },
+
+    #[error("Invalid hallucination_filter.min_no_speech_probability: {value}. Must be between 0.0 and 1.0")]
+    InvalidHallucinationNoSpeechProbability { value: f32 },
+
+    #[error("Unsupported voice_commands.language: {language}. Use \"auto\" or one of: {supported}")]
+    UnsupportedVoiceCommandLanguage { language: String, supported: String },
+
+    #[error("Invalid voice command {phrase:?}: the spoken phrase must have a word")]
+    InvalidVoiceCommand { phrase: String },
+
+    #[error("Invalid diarization.max_speakers: {value}. Must be between 1 and 3")]
+    InvalidMaxSpeakers { value: u8 },
+
+    #[error("Invalid diarization.similarity_threshold: {value}. Must be between 0.0 and 1.0")]
+    InvalidSpeakerSimilarity { value: f32 },
+
+    #[error("Invalid endpointing.trailing_silence_ms: {value}ms. Must be between 100 and 10000")]
+    InvalidTrailingSilence { value: u32 },
+
+    #[error("Invalid endpointing.max_utterance_ms: {value}ms. Must be 0 (no limit) or between 1000 and 600000")]
+    InvalidMaxUtterance { value: u32 },
+
+    #[error("Unknown placeholder {{{placeholder}}} in formatting_prompt. Valid placeholders: {{transcript}}, {{context}}, {{personal_dictionary}}, {{app_name}}, {{document_kind}}, {{recipient}}, {{text_before_cursor}}, {{text_after_cursor}}, {{user_instructions}}")]
+    UnknownPromptPlaceholder { placeholder: String },
+
+    #[error("formatting_prompt must contain the {{transcript}} placeholder")]
+    MissingTranscriptPlaceholder,
+
+    #[error("Too many vocabulary entries: {count}. At most 200 are allowed")]
+    TooManyVocabularyEntries { count: usize },
+
+    #[error("Invalid vocabulary term {term:?}. Must be 1-64 characters")]
+    InvalidVocabularyTerm { term: String },
+
+    #[error("Invalid replacement pattern {pattern:?}: {message}")]
+    InvalidReplacementPattern { pattern: String, message: String },
+
+    #[error("Invalid llm_output pattern {pattern:?}: {message}")]
+    InvalidOutputPattern { pattern: String, message: String },
+
+    #[error("Unsupported language: {language:?}. Use an ISO 639-1 code Whisper supports (e.g. \"en\", \"de\") or \"auto\"")]
+    UnsupportedLanguage { language: String },
+
+    #[error("Moonshine only transcribes English, but the language is {language:?}. Switch stt_engine to whisper for other languages")]
+    MoonshineLanguage { language: String },
+
+    #[error("Moonshine can't translate. Switch stt_engine to whisper to use the translate task")]
+    MoonshineTranslate,
+
+    #[error("Unknown context: {context}. Valid contexts: default, email, slack, code")]
+    InvalidContext { context: String },
+
+    #[error("Failed to parse config from {path}: {message}")]
+    ParseFailed { path: String, message: String },
+
+    #[error("Unknown config key {key:?}. Valid keys: {}", keys::key_names())]
+    UnknownKey { key: String },
+
+    #[error("Unknown config field {field:?}")]
+    UnknownField { field: String },
+
+    #[error("Invalid value for {key}: {message}")]
+    InvalidValue { key: String, message: String },
+
+    #[error("Unknown profile {name:?}")]
+    UnknownProfile { name: String },
+
+    #[error("Learning from corrections is off. Set learning.enabled to turn it on")]
+    LearningDisabled,
+}
+
+/// Speech-to-Text engine selection
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SttEngine {
+    #[default]
+    Whisper,
+    Moonshine,
+}
+
+impl SttEngine {
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::Whisper => "Whisper",
+            Self::Moonshine => "Moonshine",
+        }
+    }
+}
+
+/// What the STT engine produces from the audio
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SttTask {
+    /// Text in the spoken language
+    #[default]
+    Transcribe,
+    /// English text whatever the spoken language (Whisper only)
+    Translate,
+}
+
+/// Hardware the STT engine runs on
+///
+/// Moonshine picks the ONNX Runtime execution provider. Whisper runs on
+/// whisper.cpp, where anything but `Cpu` uses its GPU backend (Metal on
+/// macOS, CUDA in builds with the `cuda` feature).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SttExecutionProvider {
+    Cpu,
+    /// Core ML (Apple platforms); falls back to the CPU if it can't be used
+    #[serde(rename = "coreml")]
+    CoreMl,
+    /// The fastest available: Core ML on Apple platforms, else the CPU
+    #[default]
+    Auto,
+}
+
+impl SttExecutionProvider {
+    /// Name used in the config file and over FFI
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::CoreMl => "coreml",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// Parse a name from `id`
+    pub fn from_id(id: &str) -> Option<Self> {
+        [Self::Cpu, Self::CoreMl, Self::Auto].into_iter().find(|provider| provider.id() == id)
+    }
+}
+
+/// Numeric precision of a speech model's weights: the quantized variants are
+/// smaller and faster, at some cost in accuracy
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelPrecision {
+    /// The published full-precision weights (float16 for Whisper)
+    #[default]
+    Float32,
+    /// Weights quantized to 8 bits, activations in float (5-bit weights for
+    /// Whisper)
+    Quantized,
+    /// Weights and activations in 8-bit integers
+    Int8,
+}
+
+impl ModelPrecision {
+    /// Stable identifier used in config files, model ids and over FFI
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Float32 => "float32",
+            Self::Quantized => "quantized",
+            Self::Int8 => "int8",
+        }
+    }
+
+    /// Look up a precision by id
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::all().into_iter().find(|precision| precision.id() == id)
+    }
+
+    /// Every precision, largest first
+    pub fn all() -> Vec<ModelPrecision> {
+        vec![Self::Float32, Self::Quantized, Self::Int8]
+    }
+
+    /// Id of a model's variant at this precision: the model id, with
+    /// "-quantized" or "-int8" appended below full precision (so existing
+    /// downloads keep their names)
+    pub fn variant_id(&self, model_id: &str) -> String {
+        match self {
+            Self::Float32 => model_id.to_string(),
+            precision => format!("{}-{}", model_id, precision.id()),
+        }
+    }
+
+    /// Split a variant id into the model id and the precision
+    pub fn split_variant_id(id: &str) -> (&str, ModelPrecision) {
+        [Self::Quantized, Self::Int8]
+            .into_iter()
+            .find_map(|precision| {
+                let model_id = id.strip_suffix(precision.id())?.strip_suffix('-')?;
+                Some((model_id, precision))
+            })
+            .unwrap_or((id, Self::Float32))
+    }
+}
+
+/// Moonshine model sizes
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MoonshineModel {
+    #[default]
+    Tiny,
+    Base,
+}
+
+impl MoonshineModel {
+    /// Stable identifier used in config files and over FFI
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Tiny => "tiny",
+            Self::Base => "base",
+        }
+    }
+
+    /// Look up a model by id
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::all_models().into_iter().find(|model| model.id() == id)
+    }
+
+    /// Get the model directory name (contains preprocessor, encoder,
+    /// decoder), e.g. "moonshine-tiny" or "moonshine-tiny-int8"
+    pub fn dir_name(&self, precision: ModelPrecision) -> String {
+        precision.variant_id(&format!("moonshine-{}", self.id()))
+    }
+
+    /// Get HuggingFace repo for downloading
+    pub fn hf_repo(&self) -> &str {
+        "UsefulSensors/moonshine"
+    }
+
+    /// Get the ONNX files path prefix within the repo
+    pub fn onnx_path(&self, precision: ModelPrecision) -> String {
+        match precision {
+            ModelPrecision::Float32 => format!("onnx/{}", self.id()),
+            precision => format!("onnx/{}/{}", self.id(), precision.id()),
+        }
+    }
+
+    /// Get display name
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::Tiny => "Moonshine Tiny (27M)",
+            Self::Base => "Moonshine Base (62M)",
+        }
+    }
+
+    /// Get estimated size of a variant in MB
+    pub fn size_mb(&self, precision: ModelPrecision) -> u32 {
+        match (self, precision) {
+            (Self::Tiny, ModelPrecision::Float32) => 190,
+            (Self::Tiny, ModelPrecision::Quantized) => 60,
+            (Self::Tiny, ModelPrecision::Int8) => 50,
+            (Self::Base, ModelPrecision::Float32) => 400,
+            (Self::Base, ModelPrecision::Quantized) => 125,
+            (Self::Base, ModelPrecision::Int8) => 105,
+        }
+    }
+
+    /// Get all available Moonshine models
+    pub fn all_models() -> Vec<MoonshineModel> {
+        vec![Self::Tiny, Self::Base]
+    }
+
+    /// Required ONNX files for this model (the same at every precision)
+    pub fn required_files(&self) -> Vec<&'static str> {
+        vec!["preprocess.onnx", "encode.onnx", "uncached_decode.onnx", "cached_decode.onnx"]
+    }
+}
+
+/// Supported LLM models (non-Meta, permissive licenses)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LlmModel {
+    /// Qwen3 1.7B - Fast, multilingual (Apache 2.0)
+    Qwen3_1_7B,
+    /// Qwen3 4B - Higher quality, multilingual (Apache 2.0)
+    Qwen3_4B,
+    /// SmolLM3 3B - Hugging Face efficient model (Apache 2.0)
+    SmolLM3_3B,
+    /// Gemma 2 2B - Google's compact model
+    Gemma2_2B,
+    /// Phi-2 - Microsoft's 2.7B model (test)
+    Phi2,
+    /// Custom GGUF model path (vetted by `llm::gguf::check_custom_model`)
+    Custom(String),
+}
+
+impl Default for LlmModel {
+    fn default() -> Self {
+        Self::Qwen3_1_7B
+    }
+}
+
+impl LlmModel {
+    /// Get the model filename for downloading (exact HuggingFace filenames)
+    pub fn filename(&self) -> &str {
+        match self {
+            // Keep original filename for backwards compatibility with existing downloads
+            Self::Qwen3_1_7B => "qwen3-1.7b-q4_k_m.gguf",
+            Self::Qwen3_4B => "Qwen3-4B-Q4_K_M.gguf",
+            Self::SmolLM3_3B => "SmolLM3-Q4_K_M.gguf",
+            Self::Gemma2_2B => "gemma-2-2b-it-Q4_K_M.gguf",
+            Self::Phi2 => "phi-2-q4.gguf",
+            Self::Custom(path) => path,
+        }
+    }
+
+    /// Get the Hugging Face repo for this model
+    pub fn hf_repo(&self) -> Option<&str> {
+        match self {
+            Self::Qwen3_1_7B => Some("Qwen/Qwen3-1.7B-GGUF"),
+            Self::Qwen3_4B => Some("Qwen/Qwen3-4B-GGUF"),
+            Self::SmolLM3_3B => Some("ggml-org/SmolLM3-3B-GGUF"),
+            Self::Gemma2_2B => Some("bartowski/gemma-2-2b-it-GGUF"),
+            Self::Phi2 => Some("TheBloke/phi-2-GGUF"),
+            Self::Custom(_) => None,
+        }
+    }
+
+    /// Get display name
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::Qwen3_1_7B => "Qwen3 1.7B",
+            Self::Qwen3_4B => "Qwen3 4B",
+            Self::SmolLM3_3B => "SmolLM3 3B",
+            Self::Gemma2_2B => "Gemma 2 2B",
+            Self::Phi2 => "Phi-2",
+            Self::Custom(path) => path,
+        }
+    }
+
+    /// Get estimated model size in GB (Q4_K_M quantization, from HuggingFace)
+    pub fn size_gb(&self) -> f32 {
+        match self {
+            Self::Qwen3_1_7B => 1.28,
+            Self::Qwen3_4B => 2.5,
+            Self::SmolLM3_3B => 1.92,
+            Self::Gemma2_2B => 1.71,
+            Self::Phi2 => 1.6,
+            Self::Custom(_) => 0.0,
+        }
+    }
+
+    /// Get all available models (excluding Custom)
+    pub fn all_models() -> Vec<LlmModel> {
+        vec![
+            Self::Qwen3_1_7B,
+            Self::Qwen3_4B,
+            Self::SmolLM3_3B,
+            Self::Gemma2_2B,
+        ]
+    }
+
+    /// Whether the model reasons in a `<think>` block unless the prompt ends
+    /// with `/no_think` (Qwen3 and SmolLM3)
+    pub fn has_thinking_mode(&self) -> bool {
+        matches!(self, Self::Qwen3_1_7B | Self::Qwen3_4B | Self::SmolLM3_3B)
+    }
+
+    /// Context window in tokens (a conservative guess for custom models)
+    pub fn context_window(&self) -> usize {
+        match self {
+            Self::Qwen3_1_7B | Self::Qwen3_4B => 32768,
+            Self::SmolLM3_3B => 65536,
+            Self::Gemma2_2B => 8192,
+            Self::Phi2 => 2048,
+            Self::Custom(_) => 4096,
+        }
+    }
+}
+
+/// Where transcripts are formatted
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FormatterBackend {
+    /// The local `llm_model`
+    #[default]
+    Local,
+    /// An OpenAI-compatible server (Ollama, llama.cpp's server, ...) instead
+    /// of the local model; needs the `remote-formatter` feature
+    Remote {
+        /// Server root, e.g. "http://desktop.local:11434"; requests go to
+        /// `/v1/chat/completions` under it
+        base_url: String,
+        /// Model name the server knows
+        model: String,
+        /// Environment variable holding the API key, sent as a bearer token
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key_env: Option<String>,
+        /// Seconds to wait for a response
+        #[serde(default = "default_remote_timeout_secs")]
+        timeout_secs: u64,
+    },
+}
+
+/// Speaker-embedding models used for diarization
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeakerModel {
+    /// WeSpeaker ResNet34 trained on VoxCeleb (Apache 2.0)
+    #[default]
+    WespeakerResnet34,
+}
+
+impl SpeakerModel {
+    pub fn filename(&self) -> &str {
+        match self {
+            Self::WespeakerResnet34 => "wespeaker-voxceleb-resnet34-LM.onnx",
+        }
+    }
+
+    /// Get HuggingFace repo for downloading
+    pub fn hf_repo(&self) -> &str {
+        match self {
+            Self::WespeakerResnet34 => "Wespeaker/wespeaker-voxceleb-resnet34-LM",
+        }
+    }
+
+    /// Name of the file within the repo
+    pub fn hf_filename(&self) -> &str {
+        match self {
+            Self::WespeakerResnet34 => "voxceleb_resnet34_LM.onnx",
+        }
+    }
+
+    /// Get display name
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::WespeakerResnet34 => "WeSpeaker ResNet34 (6.6M)",
+        }
+    }
+
+    /// Get estimated model size in MB
+    pub fn size_mb(&self) -> u32 {
+        match self {
+            Self::WespeakerResnet34 => 27,
+        }
+    }
+
+    /// Get all available speaker-embedding models
+    pub fn all_models() -> Vec<SpeakerModel> {
+        vec![Self::WespeakerResnet34]
+    }
+}
+
+/// Supported Whisper model sizes
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WhisperModel {
+    Tiny,
+    #[default]
+    Base,
+    Small,
+    Medium,
+    /// Large v3 distilled to 4 decoder layers: near-large accuracy at medium speed
+    #[serde(rename = "large-v3-turbo")]
+    LargeV3Turbo,
+}
+
+impl WhisperModel {
+    /// Stable identifier used in config files and over FFI
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Tiny => "tiny",
+            Self::Base => "base",
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::LargeV3Turbo => "large-v3-turbo",
+        }
+    }
+
+    /// Look up a model by id
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::all_models().into_iter().find(|model| model.id() == id)
+    }
+
+    /// ggml file of a variant, as published by whisper.cpp: q5_1 (q5_0
+    /// for the larger models) when quantized, q8_0 for int8
+    pub fn filename(&self, precision: ModelPrecision) -> String {
+        let quantization = match (precision, self) {
+            (ModelPrecision::Float32, _) => "",
+            (ModelPrecision::Quantized, Self::Medium | Self::LargeV3Turbo) => "-q5_0",
+            (ModelPrecision::Quantized, _) => "-q5_1",
+            (ModelPrecision::Int8, _) => "-q8_0",
+        };
+        format!("ggml-{}{}.bin", self.id(), quantization)
+    }
+
+    /// Get HuggingFace repo for downloading
+    pub fn hf_repo(&self) -> &str {
+        "ggerganov/whisper.cpp"
+    }
+
+    /// Get display name
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::Tiny => "Whisper Tiny (39M)",
+            Self::Base => "Whisper Base (74M)",
+            Self::Small => "Whisper Small (244M)",
+            Self::Medium => "Whisper Medium (769M)",
+            Self::LargeV3Turbo => "Whisper Large v3 Turbo (809M)",
+        }
+    }
+
+    /// Get estimated size of a variant in MB
+    pub fn size_mb(&self, precision: ModelPrecision) -> u32 {
+        match (self, precision) {
+            (Self::Tiny, ModelPrecision::Float32) => 75,
+            (Self::Tiny, ModelPrecision::Quantized) => 31,
+            (Self::Tiny, ModelPrecision::Int8) => 42,
+            (Self::Base, ModelPrecision::Float32) => 142,
+            (Self::Base, ModelPrecision::Quantized) => 57,
+            (Self::Base, ModelPrecision::Int8) => 78,
+            (Self::Small, ModelPrecision::Float32) => 466,
+            (Self::Small, ModelPrecision::Quantized) => 181,
+            (Self::Small, ModelPrecision::Int8) => 252,
+            (Self::Medium, ModelPrecision::Float32) => 1530,
+            (Self::Medium, ModelPrecision::Quantized) => 514,
+            (Self::Medium, ModelPrecision::Int8) => 785,
+            (Self::LargeV3Turbo, ModelPrecision::Float32) => 1620,
+            (Self::LargeV3Turbo, ModelPrecision::Quantized) => 547,
+            (Self::LargeV3Turbo, ModelPrecision::Int8) => 834,
+        }
+    }
+
+    /// Get all available Whisper models
+    pub fn all_models() -> Vec<WhisperModel> {
+        vec![Self::Tiny, Self::Base, Self::Small, Self::Medium, Self::LargeV3Turbo]
+    }
+}
+
+/// Largest `LlmOptions::max_tokens`
+pub const MAX_LLM_TOKENS: u32 = 8192;
+
+/// Largest `SttDecodeParams::beam_size`
+pub const MAX_BEAM_SIZE: u32 = 8;
+
+/// LLM generation parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmOptions {
+    /// Maximum tokens to generate
+    pub max_tokens: u32,
+    /// Temperature (0.0 = deterministic, 1.0 = creative)
+    pub temperature: f32,
+    /// Top-p nucleus sampling
+    pub top_p: f32,
+    /// Top-k sampling (0 = disabled)
+    #[serde(default)]
+    pub top_k: u32,
+    /// Penalty for repeating tokens (1.0 = none)
+    #[serde(default = "default_repeat_penalty")]
+    pub repeat_penalty: f32,
+    /// Sampler seed, for reproducible output at a temperature above 0.
+    /// Applied when the model loads, so a per-call seed has no effect.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Number of GPU layers to offload (-1 = all)
+    pub n_gpu_layers: i32,
+    /// Let models with a thinking mode reason before answering (slower;
+    /// the reasoning is stripped from the output)
+    pub enable_thinking: bool,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+fn default_repeat_penalty() -> f32 {
+    1.0
+}
+
+impl Default for LlmOptions {
+    fn default() -> Self {
+        Self {
+            max_tokens: 512,
+            temperature: 0.3,
+            top_p: 0.9,
+            top_k: 0,
+            repeat_penalty: default_repeat_penalty(),
+            seed: None,
+            n_gpu_layers: -1, // All layers on GPU (mistral.rs handles this automatically)
+            enable_thinking: false, // Fast inference, no chain-of-thought
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+impl LlmOptions {
+    /// Copy with the sampling parameters brought into range, warning about
+    /// each one that had to change
+    pub fn clamped(&self) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_tokens: clamp_param("max_tokens", self.max_tokens, 1, MAX_LLM_TOKENS),
+            temperature: clamp_float_param("temperature", self.temperature, 0.0, 2.0, defaults.temperature),
+            top_p: clamp_float_param("top_p", self.top_p, 0.0, 1.0, defaults.top_p),
+            repeat_penalty: clamp_float_param("repeat_penalty", self.repeat_penalty, 1.0, 2.0, defaults.repeat_penalty),
+            ..self.clone()
+        }
+    }
+
+    /// Copy that decodes greedily (temperature 0), with `DETERMINISTIC_SEED`
+    /// unless a seed is set, for `Config::deterministic`
+    pub fn deterministic(&self) -> Self {
+        Self {
+            temperature: 0.0,
+            seed: Some(self.seed.unwrap_or(DETERMINISTIC_SEED)),
+            ..self.clone()
+        }
+    }
+}
+
+fn clamp_param<T: PartialOrd + Copy + std::fmt::Display>(name: &str, value: T, min: T, max: T) -> T {
+    let clamped = if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    };
+    if clamped != value {
+        tracing::warn!("LLM {} {} is out of range; using {}", name, value, clamped);
+    }
+    clamped
+}
+
+/// Like `clamp_param`, replacing NaN with the default
+fn clamp_float_param(name: &str, value: f32, min: f32, max: f32, default: f32) -> f32 {
+    if value.is_nan() {
+        tracing::warn!("LLM {} is not a number; using {}", name, default);
+        return default;
+    }
+    clamp_param(name, value, min, max)
+}
+
+/// Cleanup of LLM output, applied by `llm::OutputSanitizer`
+///
+/// Patterns are case-insensitive regexes; preambles are matched at the start
+/// of the output and epilogues against all of the text after its end.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmOutputRules {
+    /// Chat-template tokens; the output is cut at the first one
+    pub stop_sequences: Vec<String>,
+    /// Chatter before the text, e.g. "Sure, here's the formatted text:"
+    pub preamble_patterns: Vec<String>,
+    /// Remarks about the formatting itself, e.g. "Note: I removed the
+    /// filler words", removed only when the model set them apart after the
+    /// text: past a closing code fence or a `---` line. Without one, the
+    /// last lines are taken as dictated, however they read
+    pub epilogue_patterns: Vec<String>,
+    /// Unwrap output that is wrapped whole in a markdown code fence
+    pub strip_code_fences: bool,
+    /// Keep the unsanitized output in `PipelineResult::raw_llm_output`, to
+    /// diagnose new quirks
+    pub keep_raw_output: bool,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for LlmOutputRules {
+    fn default() -> Self {
+        let strings = |items: &[&str]| -> Vec<String> { items.iter().map(|s| s.to_string()).collect() };
+        Self {
+            stop_sequences: strings(&[
+                "<|im_end|>",
+                "<|im_start|>",
+                "<|endoftext|>",
+                "<|eot_id|>",
+                "<|start_header_id|>",
+                "<end_of_turn>",
+                "<start_of_turn>",
+                "<|end|>",
+                "<|user|>",
+                "<|assistant|>",
+                "</s>",
+            ]),
+            preamble_patterns: strings(&[
+                // Role headers as chat templates write them, so a text opening
+                // with "Model" on a line of its own is kept
+                r"(?-i:assistant|model)[ \t]*\n",
+                r"(?:(?:sure|okay|ok|certainly|of course)[,.!]?\s+)?(?:here(?:'s|’s| is)|below is) (?:the|your) (?:formatted|corrected|cleaned[- ]up|edited|revised|punctuated)\b[^:\n]*:\s*",
+                r"(?:formatted|corrected) (?:text|transcript|output|version):\s*",
+            ]),
+            epilogue_patterns: strings(&[
+                r"\(?note: i (?:removed|corrected|fixed|added|changed|kept|formatted)[^\n]*",
+                r"i(?: have|'ve|’ve)? (?:removed|corrected|fixed|added|changed|kept|formatted|cleaned up)\b[^\n]*\b(?:filler|punctuation|capitali[sz]ation|formatting|transcript|typos?)\b[^\n]*",
+                r"(?:the )?(?:text|transcript) (?:above )?(?:has been|was|is now) (?:formatted|corrected|cleaned up|punctuated)\b[^\n]*",
+            ]),
+            strip_code_fences: true,
+            keep_raw_output: false,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Removal of text the STT engine makes up on silence or music, applied by
+/// `transcribe::filter_hallucinations`
+///
+/// Phrases are compared ignoring case and punctuation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HallucinationFilter {
+    pub enabled: bool,
+    /// A run of two or more words repeated back to back this many times is
+    /// collapsed to one; single words must repeat twice as often
+    pub repeat_threshold: u32,
+    /// Sentences dropped as made up, on top of the built-in ones such as
+    /// "Thanks for watching"
+    pub extra_phrases: Vec<String>,
+    /// Phrases are only dropped when the no-speech probability reaches this,
+    /// so a real "thank you" is kept
+    pub min_no_speech_probability: f32,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for HallucinationFilter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            repeat_threshold: 3,
+            extra_phrases: Vec::new(),
+            min_no_speech_probability: 0.2,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Rewriting of spoken numbers as digits, see `text::normalize_numbers`
+///
+/// Each rule class can be turned off on its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberFormatting {
+    pub enabled: bool,
+    /// Conventions for dates, times, currencies and phone numbers
+    pub locale: NumberLocale,
+    /// "twelve hundred" → "1,200"
+    pub cardinals: bool,
+    /// "twenty first" → "21st"
+    pub ordinals: bool,
+    /// "three thirty pm" → "3:30 PM"
+    pub times: bool,
+    /// "january fifth" → "January 5th"
+    pub dates: bool,
+    /// "five dollars and fifty cents" → "$5.50"
+    pub currencies: bool,
+    /// "five percent" → "5%"
+    pub percentages: bool,
+    /// "five five five one two three four" → "555-1234"
+    pub phone_numbers: bool,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for NumberFormatting {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            locale: NumberLocale::default(),
+            cardinals: true,
+            ordinals: true,
+            times: true,
+            dates: true,
+            currencies: true,
+            percentages: true,
+            phone_numbers: true,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Locale whose conventions `NumberFormatting` follows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberLocale {
+    /// "January 5th, 2025", "3:30 PM", "555-123-4567"
+    #[default]
+    #[serde(rename = "en-US")]
+    EnUs,
+    /// "5 January 2025", "3:30 pm", "£10.50", "020 7946 0958"
+    #[serde(rename = "en-GB")]
+    EnGb,
+}
+
+/// Spoken punctuation and editing commands, see
+/// `prosody::apply_voice_commands`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceCommands {
+    pub enabled: bool,
+    /// Language of the built-in commands, or "auto" for the transcript's
+    pub language: String,
+    /// Spoken phrases and the text they insert, on top of the built-in
+    /// commands
+    pub custom: BTreeMap<String, String>,
+    /// Built-in phrases left as words, e.g. "star"
+    pub disabled: Vec<String>,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for VoiceCommands {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            language: AUTO_LANGUAGE.to_string(),
+            custom: BTreeMap::new(),
+            disabled: Vec::new(),
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// How the STT engine decodes
+///
+/// The defaults decode greedily, retrying at higher temperatures only when
+/// a decode looks wrong (a low log-probability or a loop), as whisper.cpp
+/// does on its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SttDecodeParams {
+    /// Hypotheses kept by beam search at temperature 0 (1 decodes greedily,
+    /// the fastest)
+    pub beam_size: u32,
+    /// Beam search stops once `beam_size * patience` hypotheses have
+    /// finished
+    pub patience: f32,
+    /// Readings returned per transcript, the best included (1 returns none
+    /// besides the transcript); Moonshine with beam search only, as
+    /// whisper.cpp keeps its beams to itself
+    pub alternatives: u32,
+    /// Show the LLM the alternative readings alongside the transcript
+    pub alternatives_in_prompt: bool,
+    /// Temperatures tried in order until a decode passes the quality
+    /// checks; above 0 the decoder samples
+    pub temperature_fallback: Vec<f32>,
+    /// Never repeat a run of this many tokens (0 allows repeats); Moonshine
+    /// only, whisper.cpp has no such option
+    pub no_repeat_ngram: u32,
+    /// Don't start a transcript with a blank
+    pub suppress_blank: bool,
+    /// Text Whisper decodes as if it preceded the audio, ahead of the
+    /// vocabulary glossary; Moonshine has no prompt and ignores it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for SttDecodeParams {
+    fn default() -> Self {
+        Self {
+            beam_size: 1,
+            patience: 1.0,
+            alternatives: 1,
+            alternatives_in_prompt: false,
+            temperature_fallback: vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0],
+            no_repeat_ngram: 0,
+            suppress_blank: true,
+            initial_prompt: None,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+impl SttDecodeParams {
+    /// Temperatures to decode at, in order: only the first in deterministic
+    /// mode, 0.0 if none are set
+    pub fn temperatures(&self, deterministic: bool) -> &[f32] {
+        match self.temperature_fallback.as_slice() {
+            [] => &[0.0],
+            temperatures if deterministic => &temperatures[..1],
+            temperatures => temperatures,
+        }
+    }
+}
+
+/// Labeling who spoke when, for recordings of two or three people; see
+/// `diarize`
+///
+/// Needs the `diarization` cargo feature and the speaker model, downloaded
+/// like the others.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Diarization {
+    pub enabled: bool,
+    pub model: SpeakerModel,
+    /// Most speakers to tell apart
+    pub max_speakers: u8,
+    /// Stretches of speech whose voices are at least this similar (cosine
+    /// similarity) are taken as the same speaker; higher values find more
+    /// speakers
+    pub similarity_threshold: f32,
+    /// Write the formatted text as "Speaker 1:" / "Speaker 2:" paragraphs
+    /// when more than one speaker is found
+    pub label_speakers: bool,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for Diarization {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: SpeakerModel::default(),
+            max_speakers: 2,
+            similarity_threshold: 0.5,
+            label_speakers: true,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Keeping each processed recording with its transcript and formatted
+/// text, so a dictation can be found and played back later; see `history`
+///
+/// Off by default. Entries are pruned as each one is written, oldest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryOptions {
+    pub enabled: bool,
+    /// Directory to keep the entries in, instead of history/ in the data
+    /// directory
+    pub dir: Option<PathBuf>,
+    /// Most entries to keep (0 for no limit)
+    pub max_entries: usize,
+    /// Most bytes the entries may take up, recordings included (0 for no
+    /// limit)
+    pub max_bytes: u64,
+    /// Entries older than this many days are removed (0 keeps them)
+    pub max_age_days: u32,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            max_entries: 1000,
+            max_bytes: 500 * 1024 * 1024,
+            max_age_days: 30,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Learning from the corrections the user makes to dictated text; see
+/// `learning`
+///
+/// Off by default, and nothing is recorded while it is. A correction is
+/// promoted into the vocabulary and the replacement rules once it has been
+/// reported `min_reports` times, and in at least `min_agreement` of the
+/// reports of the same words.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LearningOptions {
+    pub enabled: bool,
+    /// File to keep the reported corrections in, instead of
+    /// corrections.json in the data directory
+    pub file: Option<PathBuf>,
+    /// Reports of a correction before it's promoted
+    pub min_reports: u32,
+    /// Share of the reports of the same words that must agree on the
+    /// correction (0.0 - 1.0)
+    pub min_agreement: f32,
+    /// Most corrections ever promoted
+    pub max_learned: usize,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl LearningOptions {
+    /// Check the thresholds, as `Config::validate` does
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let (key, message) = if self.min_reports == 0 {
+            ("learning.min_reports", "must be at least 1")
+        } else if !(self.min_agreement > 0.0 && self.min_agreement <= 1.0) {
+            ("learning.min_agreement", "must be above 0.0 and at most 1.0")
+        } else {
+            return Ok(());
+        };
+        Err(ConfigError::InvalidValue { key: key.to_string(), message: message.to_string() })
+    }
+}
+
+impl Default for LearningOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: None,
+            min_reports: 3,
+            min_agreement: 0.8,
+            max_learned: 50,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Export of the pipeline's stage spans to an OpenTelemetry collector,
+/// in builds of voiceflow-ffi with the `otlp` feature
+///
+/// Off unless `endpoint` is set: nothing leaves the machine otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryOptions {
+    /// OTLP/HTTP collector URL, e.g. "http://localhost:4318"; spans are
+    /// posted to its /v1/traces
+    pub endpoint: Option<String>,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl TelemetryOptions {
+    /// Check the endpoint, as `Config::validate` does
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match &self.endpoint {
+            Some(endpoint) if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") => {
+                Err(ConfigError::InvalidValue {
+                    key: "telemetry.endpoint".to_string(),
+                    message: "must start with http:// or https://".to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// When a hands-free streaming session ends an utterance by itself; see
+/// `StreamingSession::with_endpointing`
+///
+/// Only sessions started with endpointing use these; others run until
+/// they're finished.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointingOptions {
+    /// Silence (ms) after speech that ends the utterance
+    pub trailing_silence_ms: u32,
+    /// Longest utterance (ms), counted from its first speech, before it's
+    /// ended anyway (0 for no limit)
+    pub max_utterance_ms: u32,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl EndpointingOptions {
+    /// Check the thresholds, as `Config::validate` does
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(100..=10000).contains(&self.trailing_silence_ms) {
+            return Err(ConfigError::InvalidTrailingSilence { value: self.trailing_silence_ms });
+        }
+        if self.max_utterance_ms != 0 && !(1000..=600000).contains(&self.max_utterance_ms) {
+            return Err(ConfigError::InvalidMaxUtterance { value: self.max_utterance_ms });
+        }
+        Ok(())
+    }
+}
+
+impl Default for EndpointingOptions {
+    fn default() -> Self {
+        Self {
+            trailing_silence_ms: 800,
+            max_utterance_ms: 60000,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Time limits of a request, so a stalled engine fails the request instead
+/// of holding it forever
+///
+/// They're checked between STT decode steps and LLM tokens, so a single
+/// step that never returns isn't stopped. 0 is no limit. A request that
+/// runs out of time fails with `PipelineError::Timeout`, unless it has a
+/// transcript already: then the rest of it is left unformatted, as when the
+/// LLM fails.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutOptions {
+    /// Transcription of a minute of audio; longer recordings get
+    /// proportionally longer
+    pub stt_ms: u64,
+    /// Formatting one segment with the LLM
+    pub llm_ms: u64,
+    /// The whole request, for a minute of audio; longer recordings get
+    /// proportionally longer
+    pub total_ms: u64,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl TimeoutOptions {
+    /// The limit on transcribing `audio_ms` of audio (0: none)
+    pub fn stt_limit_ms(&self, audio_ms: u64) -> u64 {
+        scaled_limit(self.stt_ms, audio_ms)
+    }
+
+    /// The limit on a whole request for `audio_ms` of audio (0: none)
+    pub fn total_limit_ms(&self, audio_ms: u64) -> u64 {
+        scaled_limit(self.total_ms, audio_ms)
+    }
+}
+
+/// `per_minute` for every minute of `audio_ms`, and at least once
+fn scaled_limit(per_minute: u64, audio_ms: u64) -> u64 {
+    per_minute.saturating_mul(audio_ms.max(60_000)) / 60_000
+}
+
+impl Default for TimeoutOptions {
+    fn default() -> Self {
+        Self {
+            stt_ms: 120_000,
+            llm_ms: 60_000,
+            total_ms: 300_000,
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Audio capture settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioOptions {
+    /// Input sample rate (will be resampled to 16kHz for Whisper)
+    pub sample_rate: u32,
+    /// Voice activity detection threshold
+    pub vad_threshold: f32,
+    /// Silence duration (ms) to trigger end of speech
+    pub silence_duration_ms: u32,
+    /// Trim leading and trailing silence before transcription
+    #[serde(default = "default_vad_enabled")]
+    pub vad_enabled: bool,
+    /// Pauses at least this long (ms) split the recording into separately
+    /// transcribed regions (0 never splits)
+    #[serde(default)]
+    pub min_silence_ms: u32,
+    /// Longest chunk (ms) handed to the STT engine; longer recordings are
+    /// transcribed in overlapping chunks
+    #[serde(default = "default_max_chunk_ms")]
+    pub max_chunk_ms: u32,
+    /// Overlap (ms) between consecutive chunks
+    #[serde(default = "default_chunk_overlap_ms")]
+    pub chunk_overlap_ms: u32,
+    /// Clean up levels before transcription (DC removal, normalization and
+    /// the clipping check below); turn off when the host app preprocesses.
+    /// Streaming sessions transcribe as the audio comes and skip it.
+    #[serde(default = "default_preprocess")]
+    pub preprocess: bool,
+    /// Remove a constant offset from the signal, as some USB mics add
+    #[serde(default = "default_remove_dc")]
+    pub remove_dc: bool,
+    /// Scale the recording so its level reaches this many dBFS, gaining at
+    /// most 30 dB and never clipping (no normalization when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize_target_dbfs: Option<f32>,
+    /// Level `normalize_target_dbfs` applies to
+    #[serde(default)]
+    pub normalize_mode: NormalizeMode,
+    /// Flag results with more than 0.1% of samples at full scale, so the
+    /// app can suggest lowering the input gain
+    #[serde(default = "default_warn_on_clipping")]
+    pub warn_on_clipping: bool,
+    /// Reject audio with NaN or infinite samples instead of replacing them
+    /// with silence
+    #[serde(default)]
+    pub strict: bool,
+    /// Longest recording accepted, in seconds (0 accepts any length)
+    #[serde(default = "default_max_duration_secs")]
+    pub max_duration_secs: u32,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+fn default_vad_enabled() -> bool {
+    true
+}
+
+fn default_max_chunk_ms() -> u32 {
+    30000
+}
+
+fn default_chunk_overlap_ms() -> u32 {
+    1000
+}
+
+fn default_preprocess() -> bool {
+    true
+}
+
+fn default_remove_dc() -> bool {
+    true
+}
+
+fn default_warn_on_clipping() -> bool {
+    true
+}
+
+fn default_max_duration_secs() -> u32 {
+    1800
+}
+
+/// Level that audio normalization brings to its target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalizeMode {
+    /// Loudest sample
+    #[default]
+    Peak,
+    /// Root-mean-square level of the whole recording
+    Rms,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            vad_threshold: 0.01,
+            silence_duration_ms: 800,
+            vad_enabled: default_vad_enabled(),
+            min_silence_ms: 0,
+            max_chunk_ms: default_max_chunk_ms(),
+            chunk_overlap_ms: default_chunk_overlap_ms(),
+            preprocess: default_preprocess(),
+            remove_dc: default_remove_dc(),
+            normalize_target_dbfs: None,
+            normalize_mode: NormalizeMode::default(),
+            warn_on_clipping: default_warn_on_clipping(),
+            strict: false,
+            max_duration_secs: default_max_duration_secs(),
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Main configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Version of the file format (0 for files written before it was
+    /// recorded)
+    #[serde(default)]
+    pub schema_version: u32,
+    /// STT engine selection (whisper or moonshine)
+    #[serde(default)]
+    pub stt_engine: SttEngine,
+    /// Whisper model size (used when stt_engine is Whisper)
+    #[serde(default)]
+    pub whisper_model: WhisperModel,
+    /// Precision of the Whisper model's weights
+    #[serde(default)]
+    pub whisper_precision: ModelPrecision,
+    /// Moonshine model size (used when stt_engine is Moonshine)
+    #[serde(default)]
+    pub moonshine_model: MoonshineModel,
+    /// Precision of the Moonshine model's weights
+    #[serde(default)]
+    pub moonshine_precision: ModelPrecision,
+    /// LLM model selection
+    #[serde(default)]
+    pub llm_model: LlmModel,
+    /// Display name of a custom LLM model (the file name when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_model_name: Option<String>,
+    /// Prompt format of the LLM ("auto" uses the one in the GGUF file)
+    #[serde(default)]
+    pub chat_template: ChatTemplate,
+    /// Where transcripts are formatted: the local LLM or a remote server
+    #[serde(default)]
+    pub formatter: FormatterBackend,
+    /// LLM generation options
+    #[serde(default)]
+    pub llm_options: LlmOptions,
+    /// Cleanup of model quirks in the LLM output
+    #[serde(default)]
+    pub llm_output: LlmOutputRules,
+    /// Audio capture options
+    #[serde(default)]
+    pub audio: AudioOptions,
+    /// Default context when none specified
+    #[serde(default = "default_context")]
+    pub default_context: String,
+    /// Personal dictionary words
+    #[serde(default)]
+    pub personal_dictionary: Vec<String>,
+    /// Spoken language: an ISO 639-1 code ("en", "de") or "auto" to detect it
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Transcribe in the spoken language, or translate to English
+    #[serde(default)]
+    pub stt_task: SttTask,
+    /// Hardware to run the STT engine on
+    #[serde(default)]
+    pub stt_execution_provider: SttExecutionProvider,
+    /// CPU threads for the STT engine (0 for one per core)
+    #[serde(default)]
+    pub stt_threads: u32,
+    /// Beam search, temperature fallback and the other STT decoding options
+    #[serde(default)]
+    pub stt_decode: SttDecodeParams,
+    /// When the STT engine fails to load, run a smaller downloaded model of
+    /// the same engine or the other engine instead of failing (see
+    /// `Pipeline::active_stt_engine`); the config keeps the failed choice
+    #[serde(default)]
+    pub stt_fallback_enabled: bool,
+    /// CPU threads for the local LLM (0 for one per core); applies to the
+    /// whole process, see `LlmEngine`
+    #[serde(default)]
+    pub llm_threads: u32,
+    /// Context window of the LLM in tokens, in place of the model's
+    /// (`LlmModel::context_window`); prompts that don't fit are cut down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_context_window: Option<u32>,
+    /// Keep the LLM's prefill of the prompt up to the transcript and reuse
+    /// it for requests that start the same way (see `LlmEngine::prime`)
+    #[serde(default = "default_llm_prefix_cache")]
+    pub llm_prefix_cache: bool,
+    /// When translating, also transcribe in the spoken language (a second
+    /// STT pass) and return it as the original transcript
+    #[serde(default)]
+    pub keep_original_transcript: bool,
+    /// Auto-copy to clipboard
+    #[serde(default = "default_auto_clipboard")]
+    pub auto_clipboard: bool,
+    /// Hash model files against their recorded SHA-256 before loading (the
+    /// size is always checked); adds a few seconds to startup
+    #[serde(default)]
+    pub verify_models: bool,
+    /// Load the LLM and run a short warm-up inference when the pipeline is
+    /// created, so the first request isn't slower than the rest
+    #[serde(default)]
+    pub warm_up_on_init: bool,
+    /// Load the LLM when the pipeline is created rather than on the first
+    /// request that formats (implied by `warm_up_on_init`)
+    #[serde(default)]
+    pub llm_preload: bool,
+    /// Fail the request when the LLM fails to load or to format, rather
+    /// than returning the raw transcript with `formatting_error` set
+    #[serde(default)]
+    pub llm_strict: bool,
+    /// Unload the LLM once no request has finished for this many seconds; it
+    /// reloads on the next request that formats (never unloaded when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_unload_seconds: Option<u64>,
+    /// Unload the STT engine too after `idle_unload_seconds`
+    #[serde(default)]
+    pub idle_unload_stt: bool,
+    /// Same audio in, same text out, for regression testing: the LLM
+    /// decodes greedily with a fixed seed, the STT engine and the LLM run on
+    /// one CPU thread (so sums are always added up in the same order), and
+    /// idle unloading and the format cache are off
+    #[serde(default)]
+    pub deterministic: bool,
+    /// Transcripts with a lower STT confidence are treated as no speech (0.0 disables)
+    #[serde(default = "default_min_speech_confidence")]
+    pub min_speech_confidence: f32,
+    /// Transcripts with a higher no-speech probability are treated as no speech (1.0 disables)
+    #[serde(default = "default_max_no_speech_probability")]
+    pub max_no_speech_probability: f32,
+    /// Removal of loops and stock phrases made up by the STT engine
+    #[serde(default)]
+    pub hallucination_filter: HallucinationFilter,
+    /// Spoken punctuation and editing commands ("comma", "scratch that")
+    #[serde(default)]
+    pub voice_commands: VoiceCommands,
+    /// Spoken numbers, dates, times and amounts written with digits
+    #[serde(default)]
+    pub number_formatting: NumberFormatting,
+    /// Speaker labels for recordings of several people
+    #[serde(default)]
+    pub diarization: Diarization,
+    /// Recordings and their results kept for later
+    #[serde(default)]
+    pub history: HistoryOptions,
+    /// Corrections learned from (off by default)
+    #[serde(default)]
+    pub learning: LearningOptions,
+    /// Utterance ends of hands-free streaming sessions
+    #[serde(default)]
+    pub endpointing: EndpointingOptions,
+    /// Stage spans sent to an OpenTelemetry collector (off by default)
+    #[serde(default)]
+    pub telemetry: TelemetryOptions,
+    /// Time limits of a request's stages
+    #[serde(default)]
+    pub timeouts: TimeoutOptions,
+    /// Formatted text sharing less than this fraction of words with the
+    /// transcript is rejected in favor of the raw transcript (0.0 disables)
+    #[serde(default = "default_min_format_similarity")]
+    pub min_format_similarity: f32,
+    /// Most tokens of earlier dictations included in the prompt when
+    /// processing within a session (0 disables session context)
+    #[serde(default = "default_session_context_tokens")]
+    pub session_context_tokens: u32,
+    /// Recordings shorter than this are taken as short voice commands and
+    /// skip silence trimming and the LLM, unless a request says otherwise
+    /// (see `ProcessProfile`; 0 disables it)
+    #[serde(default = "default_command_max_ms")]
+    pub command_max_ms: u32,
+    /// Formatting results kept for reuse when the same transcript is
+    /// formatted the same way again (0 disables the cache)
+    #[serde(default = "default_format_cache_size")]
+    pub format_cache_size: usize,
+    /// Append logs to this file (no file logging when unset); a relative
+    /// path is in the platform log directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+    /// Store models here instead of the platform data directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub models_dir_override: Option<PathBuf>,
+    /// LLM formatting prompt used instead of the built-in ones
+    ///
+    /// `{transcript}` is replaced by the transcript, `{context}` by the
+    /// context name and `{personal_dictionary}` by the personal dictionary.
+    /// The fields of a structured context (`{app_name}`, `{recipient}`,
+    /// `{text_before_cursor}`, ...; see `DictationContext`) can be placed
+    /// too, and are appended to the prompt when none is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatting_prompt: Option<String>,
+    /// Formatting preset used when a call doesn't choose one (context-based
+    /// prompts when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_preset: Option<FormattingPreset>,
+    /// Proper nouns and jargon to bias transcription and formatting towards
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary: Vec<VocabularyEntry>,
+    /// Find-and-replace rules applied, in order, to the formatted text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replacements: Vec<ReplacementRule>,
+    /// Formatting preferences per app, keyed by bundle id (see
+    /// `AppOverride`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub app_overrides: BTreeMap<String, AppOverride>,
+    /// Named sets of settings, each holding the fields it changes (see
+    /// `Config::with_profile`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, toml::Table>,
+    /// Settings from a newer version, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub unknown_fields: toml::Table,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            schema_version: CONFIG_SCHEMA_VERSION,
+            stt_engine: SttEngine::default(),
+            whisper_model: WhisperModel::default(),
+            whisper_precision: ModelPrecision::default(),
+            moonshine_model: MoonshineModel::default(),
+            moonshine_precision: ModelPrecision::default(),
+            llm_model: LlmModel::default(),
+            custom_model_name: None,
+            chat_template: ChatTemplate::default(),
+            formatter: FormatterBackend::default(),
+            llm_options: LlmOptions::default(),
+            llm_output: LlmOutputRules::default(),
+            audio: AudioOptions::default(),
+            default_context: default_context(),
+            personal_dictionary: vec![],
+            language: default_language(),
+            stt_task: SttTask::default(),
+            stt_execution_provider: SttExecutionProvider::default(),
+            stt_threads: 0,
+            stt_decode: SttDecodeParams::default(),
+            stt_fallback_enabled: false,
+            llm_threads: 0,
+            llm_context_window: None,
+            llm_prefix_cache: default_llm_prefix_cache(),
+            keep_original_transcript: false,
+            auto_clipboard: default_auto_clipboard(),
+            verify_models: false,
+            warm_up_on_init: false,
+            llm_preload: false,
+            llm_strict: false,
+            idle_unload_seconds: None,
+            idle_unload_stt: false,
+            deterministic: false,
+            min_speech_confidence: default_min_speech_confidence(),
+            max_no_speech_probability: default_max_no_speech_probability(),
+            hallucination_filter: HallucinationFilter::default(),
+            voice_commands: VoiceCommands::default(),
+            number_formatting: NumberFormatting::default(),
+            diarization: Diarization::default(),
+            history: HistoryOptions::default(),
+            learning: LearningOptions::default(),
+            endpointing: EndpointingOptions::default(),
+            telemetry: TelemetryOptions::default(),
+            timeouts: TimeoutOptions::default(),
+            min_format_similarity: default_min_format_similarity(),
+            session_context_tokens: default_session_context_tokens(),
+            command_max_ms: default_command_max_ms(),
+            format_cache_size: default_format_cache_size(),
+            log_file: None,
+            models_dir_override: None,
+            formatting_prompt: None,
+            default_preset: None,
+            vocabulary: vec![],
+            replacements: vec![],
+            app_overrides: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            unknown_fields: toml::Table::new(),
+        }
+    }
+}
+
+/// Most vocabulary entries allowed in the config
+pub const MAX_VOCABULARY_ENTRIES: usize = 200;
+
+/// Longest vocabulary term or sounds-like alias, in characters
+pub const MAX_VOCABULARY_TERM_CHARS: usize = 64;
+
+/// Longest Whisper initial prompt built from the vocabulary, in characters
+/// (whisper.cpp keeps at most ~224 prompt tokens)
+const MAX_STT_PROMPT_CHARS: usize = 600;
+
+/// A custom vocabulary term, with optional spellings the STT engine tends
+/// to produce for it instead
+///
+/// In the config file an entry is either a plain string or a table:
+/// `vocabulary = ["VoiceFlow", { term = "Kubernetes", sounds_like = ["cooper netties"] }]`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(from = "VocabularyEntryRepr")]
+pub struct VocabularyEntry {
+    /// Correct spelling
+    pub term: String,
+    /// Misrecognitions to correct to `term`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sounds_like: Vec<String>,
+}
+
+impl VocabularyEntry {
+    /// Create an entry without aliases
+    pub fn new(term: impl Into<String>) -> Self {
+        Self {
+            term: term.into(),
+            sounds_like: vec![],
+        }
+    }
+
+    /// Description of the entry for the LLM prompt
+    pub fn prompt_hint(&self) -> String {
+        if self.sounds_like.is_empty() {
+            return self.term.clone();
+        }
+        let aliases: Vec<String> = self.sounds_like.iter().map(|alias| format!("\"{}\"", alias)).collect();
+        format!("{} (may be transcribed as {})", self.term, aliases.join(" or "))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VocabularyEntryRepr {
+    Term(String),
+    Entry {
+        term: String,
+        #[serde(default)]
+        sounds_like: Vec<String>,
+    },
+}
+
+impl From<VocabularyEntryRepr> for VocabularyEntry {
+    fn from(repr: VocabularyEntryRepr) -> Self {
+        match repr {
+            VocabularyEntryRepr::Term(term) => Self::new(term),
+            VocabularyEntryRepr::Entry { term, sounds_like } => Self { term, sounds_like },
+        }
+    }
+}
+
+/// A find-and-replace rule applied after formatting
+///
+/// Literal patterns match whole words only. Regex patterns use the `regex`
+/// crate syntax and may refer to groups (`$1`) in the replacement.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReplacementRule {
+    /// Text or regex to find
+    pub pattern: String,
+    /// Text to put in its place
+    pub replacement: String,
+    /// Treat `pattern` as a regular expression
+    #[serde(default)]
+    pub is_regex: bool,
+    /// Match case exactly
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Also apply the rule to the raw transcript
+    #[serde(default)]
+    pub apply_to_raw: bool,
+}
+
+/// Check the size of a vocabulary and the length of every term and alias
+fn check_vocabulary(vocabulary: &[VocabularyEntry]) -> Result<()> {
+    if vocabulary.len() > MAX_VOCABULARY_ENTRIES {
+        return Err(ConfigError::TooManyVocabularyEntries {
+            count: vocabulary.len(),
+        }.into());
+    }
+
+    let terms = vocabulary
+        .iter()
+        .flat_map(|entry| std::iter::once(&entry.term).chain(&entry.sounds_like));
+    for term in terms {
+        let len = term.trim().chars().count();
+        if len == 0 || len > MAX_VOCABULARY_TERM_CHARS {
+            return Err(ConfigError::InvalidVocabularyTerm { term: term.clone() }.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Placeholders substituted in `formatting_prompt`, besides the fields of
+/// a structured context
+const PROMPT_PLACEHOLDERS: [&str; 3] = ["transcript", "context", "personal_dictionary"];
+
+/// Check that a prompt template only uses known placeholders and includes
+/// the transcript
+pub fn check_prompt_template(template: &str) -> Result<()> {
+    let known = |name: &&str| PROMPT_PLACEHOLDERS.contains(name) || CONTEXT_FIELDS.contains(name);
+    if let Some(unknown) = prompt_placeholders(template).find(|name| !known(name)) {
+        return Err(ConfigError::UnknownPromptPlaceholder {
+            placeholder: unknown.to_string(),
+        }.into());
+    }
+
+    if !prompt_placeholders(template).any(|name| name == "transcript") {
+        return Err(ConfigError::MissingTranscriptPlaceholder.into());
+    }
+
+    Ok(())
+}
+
+/// Names of the `{placeholder}`s in a prompt template
+///
+/// Only braces around an identifier count, so literal braces (e.g. JSON
+/// examples) are left alone.
+fn prompt_placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|rest| {
+        let name = &rest[..rest.find('}')?];
+        let is_identifier = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        is_identifier.then_some(name)
+    })
+}
+
+/// Language value that makes Whisper detect the spoken language
+pub const AUTO_LANGUAGE: &str = "auto";
+
+/// ISO 639-1 codes of the languages Whisper can transcribe
+pub const WHISPER_LANGUAGES: [&str; 98] = [
+    "en", "zh", "de", "es", "ru", "ko", "fr", "ja", "pt", "tr", "pl", "ca", "nl", "ar", "sv", "it", "id",
+    "hi", "fi", "vi", "he", "uk", "el", "ms", "cs", "ro", "da", "hu", "ta", "no", "th", "ur", "hr", "bg",
+    "lt", "la", "mi", "ml", "cy", "sk", "te", "fa", "lv", "bn", "sr", "az", "sl", "kn", "et", "mk", "br",
+    "eu", "is", "hy", "ne", "mn", "bs", "kk", "sq", "sw", "gl", "mr", "pa", "si", "km", "sn", "yo", "so",
+    "af", "oc", "ka", "be", "tg", "sd", "gu", "am", "yi", "lo", "uz", "fo", "ht", "ps", "tk", "nn", "mt",
+    "sa", "lb", "my", "bo", "tl", "mg", "as", "tt", "ln", "ha", "ba", "jw", "su",
+];
+
+/// Check that `language` is "auto" or a code the engine can transcribe
+///
+/// Moonshine is English-only, so it only accepts "en".
+pub fn check_language(language: &str, engine: &SttEngine) -> Result<()> {
+    if language != AUTO_LANGUAGE && !WHISPER_LANGUAGES.contains(&language) {
+        return Err(ConfigError::UnsupportedLanguage {
+            language: language.to_string(),
+        }.into());
+    }
+
+    if *engine == SttEngine::Moonshine && language != "en" {
+        return Err(ConfigError::MoonshineLanguage {
+            language: language.to_string(),
+        }.into());
+    }
+
+    Ok(())
+}
+
+/// Check that the engine can run the task (only Whisper translates)
+pub fn check_stt_task(task: SttTask, engine: &SttEngine) -> Result<()> {
+    if task == SttTask::Translate && *engine == SttEngine::Moonshine {
+        return Err(ConfigError::MoonshineTranslate.into());
+    }
+    Ok(())
+}
+
+fn default_llm_prefix_cache() -> bool {
+    true
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+fn default_context() -> String {
+    "default".to_string()
+}
+
+fn default_auto_clipboard() -> bool {
+    true
+}
+
+fn default_min_speech_confidence() -> f32 {
+    0.3
+}
+
+fn default_max_no_speech_probability() -> f32 {
+    0.6
+}
+
+fn default_min_format_similarity() -> f32 {
+    0.7
+}
+
+fn default_session_context_tokens() -> u32 {
+    512
+}
+
+fn default_command_max_ms() -> u32 {
+    1500
+}
+
+fn default_format_cache_size() -> usize {
+    32
+}
+
+fn default_remote_timeout_secs() -> u64 {
+    30
+}
+
+/// LLM sampler seed in deterministic mode, when `llm_options.seed` is unset
+pub const DETERMINISTIC_SEED: u64 = 0;
+
+/// Threads to run with for a `stt_threads` or `llm_threads` setting: one per
+/// core for 0, and never more than there are cores
+pub fn thread_count(setting: u32) -> usize {
+    let available = std::thread::available_parallelism().map_or(1, |n| n.get());
+    match setting {
+        0 => available,
+        threads => (threads as usize).min(available),
+    }
+}
+
+/// Write a file through a temporary sibling and a rename
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+    // Unique per process and call, so concurrent saves don't share it
+    let tmp = path.with_extension(format!(
+        "toml.{}-{}.tmp",
+        std::process::id(),
+        NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    let write = || {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    };
+    write().inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// Environment variable names for configuration overrides
+///
+/// Every field has a variable (see `env_var_fields`); these are the ones
+/// with special meaning or a shorter name.
+pub mod env_vars {
+    /// Prefix of every variable
+    pub const PREFIX: &str = "VOICEFLOW_";
+    /// Config file to use instead of the default one
+    pub const CONFIG: &str = "VOICEFLOW_CONFIG";
+    pub const STT_ENGINE: &str = "VOICEFLOW_STT_ENGINE";
+    pub const WHISPER_MODEL: &str = "VOICEFLOW_WHISPER_MODEL";
+    pub const MOONSHINE_MODEL: &str = "VOICEFLOW_MOONSHINE_MODEL";
+    pub const LLM_MODEL: &str = "VOICEFLOW_LLM_MODEL";
+    pub const LLM_TEMPERATURE: &str = "VOICEFLOW_LLM_TEMPERATURE";
+    pub const LLM_MAX_TOKENS: &str = "VOICEFLOW_LLM_MAX_TOKENS";
+    pub const LLM_TOP_P: &str = "VOICEFLOW_LLM_TOP_P";
+    pub const ENABLE_THINKING: &str = "VOICEFLOW_ENABLE_THINKING";
+    pub const DEFAULT_CONTEXT: &str = "VOICEFLOW_DEFAULT_CONTEXT";
+    pub const LANGUAGE: &str = "VOICEFLOW_LANGUAGE";
+    pub const MODELS_DIR: &str = "VOICEFLOW_MODELS_DIR";
+    pub const LOG_FILE: &str = "VOICEFLOW_LOG_FILE";
+}
+
+impl Config {
+    /// Load the configuration: defaults, overlaid by the config file (if it
+    /// exists), overlaid by `VOICEFLOW_*` environment variables
+    ///
+    /// Files written by older versions are upgraded (see `migrate`), and a
+    /// field that no longer parses is reset to its default with a warning
+    /// rather than failing the whole file. An environment variable that
+    /// doesn't parse is an error naming it.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let mut config = Self::read_file(path)?;
+        config.apply_env_overrides()?;
+        config.checked()
+    }
+
+    /// Load the configuration without environment overrides, to change
+    /// and save it
+    pub fn load_file(path: Option<&str>) -> Result<Self> {
+        Self::read_file(path)?.checked()
+    }
+
+    fn read_file(path: Option<&str>) -> Result<Self> {
+        let config_path = match path {
+            Some(p) => PathBuf::from(p),
+            None => Self::default_config_path()?,
+        };
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read config from {:?}", config_path))?;
+        Ok(migrate::parse_config(&contents).map_err(|e| ConfigError::ParseFailed {
+            path: config_path.display().to_string(),
+            message: e.to_string(),
+        })?)
+    }
+
+    fn checked(mut self) -> Result<Self> {
+        self.llm_options = self.llm_options.clamped();
+
+        // A broken prompt template or rule would silently garble every transcript
+        self.validate_formatting_prompt()?;
+        self.validate_replacements()?;
+        self.validate_llm_output()?;
+        self.validate_formatter()?;
+        self.validate_stt_decode()?;
+        self.validate_language()?;
+
+        Ok(self)
+    }
+
+    /// Load configuration with validation
+    pub fn load_validated(path: Option<&str>) -> Result<Self> {
+        let config = Self::load(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validate the configuration values
+    pub fn validate(&self) -> Result<()> {
+        // Validate LLM options
+        if self.llm_options.temperature < 0.0 || self.llm_options.temperature > 2.0 {
+            return Err(ConfigError::InvalidTemperature {
+                value: self.llm_options.temperature,
+            }.into());
+        }
+
+        if self.llm_options.top_p < 0.0 || self.llm_options.top_p > 1.0 {
+            return Err(ConfigError::InvalidTopP {
+                value: self.llm_options.top_p,
+            }.into());
+        }
+
+        if self.llm_options.max_tokens == 0 || self.llm_options.max_tokens > MAX_LLM_TOKENS {
+            return Err(ConfigError::InvalidMaxTokens {
+                value: self.llm_options.max_tokens,
+            }.into());
+        }
+
+        // Validate audio options
+        let valid_sample_rates = [8000, 16000, 22050, 44100, 48000];
+        if !valid_sample_rates.contains(&self.audio.sample_rate) {
+            return Err(ConfigError::InvalidSampleRate {
+                value: self.audio.sample_rate,
+            }.into());
+        }
+
+        if self.audio.vad_threshold < 0.0 || self.audio.vad_threshold > 1.0 {
+            return Err(ConfigError::InvalidVadThreshold {
+                value: self.audio.vad_threshold,
+            }.into());
+        }
+
+        if self.audio.silence_duration_ms < 100 || self.audio.silence_duration_ms > 5000 {
+            return Err(ConfigError::InvalidSilenceDuration {
+                value: self.audio.silence_duration_ms,
+            }.into());
+        }
+
+        let min_silence_ms = self.audio.min_silence_ms;
+        if min_silence_ms != 0 && !(100..=30000).contains(&min_silence_ms) {
+            return Err(ConfigError::InvalidMinSilence {
+                value: min_silence_ms,
+            }.into());
+        }
+
+        if !(5000..=30000).contains(&self.audio.max_chunk_ms) {
+            return Err(ConfigError::InvalidMaxChunk {
+                value: self.audio.max_chunk_ms,
+            }.into());
+        }
+
+        if self.audio.chunk_overlap_ms > self.audio.max_chunk_ms / 2 {
+            return Err(ConfigError::InvalidChunkOverlap {
+                value: self.audio.chunk_overlap_ms,
+            }.into());
+        }
+
+        if let Some(target) = self.audio.normalize_target_dbfs.filter(|target| !(-60.0..=0.0).contains(target)) {
+            return Err(ConfigError::InvalidNormalizeTarget { value: target }.into());
+        }
+
+        // Validate no-speech thresholds
+        if !(0.0..=1.0).contains(&self.min_speech_confidence) {
+            return Err(ConfigError::InvalidMinSpeechConfidence {
+                value: self.min_speech_confidence,
+            }.into());
+        }
+
+        if !(0.0..=1.0).contains(&self.max_no_speech_probability) {
+            return Err(ConfigError::InvalidMaxNoSpeechProbability {
+                value: self.max_no_speech_probability,
+            }.into());
+        }
+
+        if !(0.0..=1.0).contains(&self.min_format_similarity) {
+            return Err(ConfigError::InvalidMinFormatSimilarity {
+                value: self.min_format_similarity,
+            }.into());
+        }
+
+        if self.hallucination_filter.repeat_threshold < 2 {
+            return Err(ConfigError::InvalidRepeatThreshold {
+                value: self.hallucination_filter.repeat_threshold,
+            }.into());
+        }
+
+        if !(0.0..=1.0).contains(&self.hallucination_filter.min_no_speech_probability) {
+            return Err(ConfigError::InvalidHallucinationNoSpeechProbability {
+                value: self.hallucination_filter.min_no_speech_probability,
+            }.into());
+        }
+
+        let commands = &self.voice_commands;
+        if commands.language != AUTO_LANGUAGE && !VOICE_COMMAND_LANGUAGES.contains(&commands.language.as_str()) {
+            return Err(ConfigError::UnsupportedVoiceCommandLanguage {
+                language: commands.language.clone(),
+                supported: VOICE_COMMAND_LANGUAGES.join(", "),
+            }.into());
+        }
+        if let Some(phrase) = commands.custom.keys().find(|phrase| !phrase.contains(char::is_alphanumeric)) {
+            return Err(ConfigError::InvalidVoiceCommand { phrase: phrase.clone() }.into());
+        }
+
+        if !(1..=3).contains(&self.diarization.max_speakers) {
+            return Err(ConfigError::InvalidMaxSpeakers {
+                value: self.diarization.max_speakers,
+            }.into());
+        }
+
+        if !(0.0..=1.0).contains(&self.diarization.similarity_threshold) {
+            return Err(ConfigError::InvalidSpeakerSimilarity {
+                value: self.diarization.similarity_threshold,
+            }.into());
+        }
+
+        self.endpointing.validate()?;
+        self.telemetry.validate()?;
+        self.learning.validate()?;
+        self.validate_formatting_prompt()?;
+        self.validate_vocabulary()?;
+        self.validate_replacements()?;
+        self.validate_app_overrides()?;
+        self.validate_llm_output()?;
+        self.validate_formatter()?;
+        self.validate_stt_decode()?;
+        self.validate_language()?;
+
+        // Validate context
+        let valid_contexts = ["default", "email", "slack", "code"];
+        if !valid_contexts.contains(&self.default_context.as_str()) {
+            return Err(ConfigError::InvalidContext {
+                context: self.default_context.clone(),
+            }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Check the user prompt templates (`formatting_prompt` and a custom
+    /// `default_preset`) with `check_prompt_template`
+    pub fn validate_formatting_prompt(&self) -> Result<()> {
+        if let Some(template) = &self.formatting_prompt {
+            check_prompt_template(template)?;
+        }
+        if let Some(FormattingPreset::Custom(template)) = &self.default_preset {
+            check_prompt_template(template)?;
+        }
+        Ok(())
+    }
+
+    /// Check the vocabulary size and the length of every term and alias
+    pub fn validate_vocabulary(&self) -> Result<()> {
+        check_vocabulary(&self.vocabulary)
+    }
+
+    /// Check that every replacement pattern compiles
+    pub fn validate_replacements(&self) -> Result<()> {
+        ReplacementRules::compile(&self.replacements)?;
+        Ok(())
+    }
+
+    /// Check the `llm_output` patterns compile
+    pub fn validate_llm_output(&self) -> Result<()> {
+        OutputSanitizer::compile(&self.llm_output)?;
+        Ok(())
+    }
+
+    /// Check a remote formatter's URL, model and timeout
+    pub fn validate_formatter(&self) -> Result<()> {
+        let FormatterBackend::Remote { base_url, model, timeout_secs, .. } = &self.formatter else {
+            return Ok(());
+        };
+        let message = if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            "base_url must start with http:// or https://"
+        } else if model.trim().is_empty() {
+            "model must not be empty"
+        } else if *timeout_secs == 0 {
+            "timeout_secs must be at least 1"
+        } else {
+            return Ok(());
+        };
+        Err(ConfigError::InvalidValue { key: "formatter".to_string(), message: message.to_string() }.into())
+    }
+
+    /// Check the beam search and temperature settings of `stt_decode`
+    pub fn validate_stt_decode(&self) -> Result<()> {
+        let decode = &self.stt_decode;
+        let (key, message) = if !(1..=MAX_BEAM_SIZE).contains(&decode.beam_size) {
+            ("stt_decode.beam_size", format!("must be between 1 and {}", MAX_BEAM_SIZE))
+        } else if !(decode.patience > 0.0 && decode.patience <= 4.0) {
+            ("stt_decode.patience", "must be above 0.0 and at most 4.0".to_string())
+        } else if !(1..=MAX_BEAM_SIZE).contains(&decode.alternatives) {
+            ("stt_decode.alternatives", format!("must be between 1 and {}", MAX_BEAM_SIZE))
+        } else if decode.temperature_fallback.iter().any(|t| !(0.0..=1.0).contains(t)) {
+            ("stt_decode.temperature_fallback", "temperatures must be between 0.0 and 1.0".to_string())
+        } else {
+            return Ok(());
+        };
+        Err(ConfigError::InvalidValue { key: key.to_string(), message }.into())
+    }
+
+    /// Check the language and task against the selected STT engine with
+    /// `check_language` and `check_stt_task`
+    pub fn validate_language(&self) -> Result<()> {
+        check_language(&self.language, &self.stt_engine)?;
+        check_stt_task(self.stt_task, &self.stt_engine)
+    }
+
+    /// Prompt that biases the STT decoder towards the vocabulary, if any:
+    /// `stt_decode.initial_prompt` followed by a glossary of the terms
+    ///
+    /// Terms that don't fit in the prompt budget are left out.
+    pub fn stt_initial_prompt(&self) -> Option<String> {
+        let text = self.stt_decode.initial_prompt.as_deref().map(str::trim).filter(|text| !text.is_empty());
+        let budget = MAX_STT_PROMPT_CHARS.saturating_sub(text.map_or(0, |text| text.len() + 1));
+        let mut prompt = String::from("Glossary:");
+        for entry in &self.vocabulary {
+            let term = entry.term.trim();
+            if prompt.len() + term.len() + 2 > budget {
+                tracing::warn!("Vocabulary too long for the STT prompt; later terms only reach the LLM");
+                break;
+            }
+            if !prompt.ends_with(':') {
+                prompt.push(',');
+            }
+            prompt.push(' ');
+            prompt.push_str(term);
+        }
+        let glossary = (!prompt.ends_with(':')).then(|| prompt + ".");
+        match (text, glossary) {
+            (Some(text), Some(glossary)) => Some(format!("{} {}", text, glossary)),
+            (Some(text), None) => Some(text.to_string()),
+            (None, glossary) => glossary,
+        }
+    }
+
+    /// Whether STT scores mean the audio had no speech, so the transcript
+    /// is most likely a hallucination
+    pub fn is_no_speech(&self, confidence: f32, no_speech_probability: f32) -> bool {
+        confidence < self.min_speech_confidence || no_speech_probability > self.max_no_speech_probability
+    }
+
+    /// Save configuration to file
+    ///
+    /// Written to a temporary file that is then renamed over the config, so
+    /// a crash mid-save leaves the previous file intact and concurrent saves
+    /// never interleave (the last one wins).
+    pub fn save(&self, path: Option<&str>) -> Result<()> {
+        let config_path = match path {
+            Some(p) => PathBuf::from(p),
+            None => Self::default_config_path()?,
+        };
+
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string_pretty(self)?;
+        write_atomic(&config_path, &contents).with_context(|| format!("Failed to write config to {:?}", config_path))
+    }
+
+    /// Dotted names of the settings kept in the `unknown_fields` tables
+    pub fn unknown_field_names(&self) -> Vec<String> {
+        let sections = [
+            ("", &self.unknown_fields),
+            ("llm_options.", &self.llm_options.unknown_fields),
+            ("llm_output.", &self.llm_output.unknown_fields),
+            ("hallucination_filter.", &self.hallucination_filter.unknown_fields),
+            ("voice_commands.", &self.voice_commands.unknown_fields),
+            ("number_formatting.", &self.number_formatting.unknown_fields),
+            ("stt_decode.", &self.stt_decode.unknown_fields),
+            ("diarization.", &self.diarization.unknown_fields),
+            ("history.", &self.history.unknown_fields),
+            ("learning.", &self.learning.unknown_fields),
+            ("endpointing.", &self.endpointing.unknown_fields),
+            ("telemetry.", &self.telemetry.unknown_fields),
+            ("timeouts.", &self.timeouts.unknown_fields),
+            ("audio.", &self.audio.unknown_fields),
+        ];
+        let apps = self.app_overrides.iter().flat_map(|(app_id, app)| {
+            app.unknown_fields.keys().map(move |key| format!("app_overrides.{}.{}", app_id, key))
+        });
+        sections
+            .iter()
+            .flat_map(|(prefix, fields)| fields.keys().map(move |key| format!("{}{}", prefix, key)))
+            .chain(apps)
+            .collect()
+    }
+
+    /// Get the config file path: `VOICEFLOW_CONFIG` if set, otherwise
+    /// config.toml in the platform config directory
+    pub fn default_config_path() -> Result<PathBuf> {
+        if let Some(path) = env::var_os(env_vars::CONFIG).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        Ok(Self::app_dirs()?.config_dir.join("config.toml"))
+    }
+
+    /// CPU threads for the STT engine: one in deterministic mode, otherwise
+    /// as `stt_threads` says
+    pub fn stt_thread_count(&self) -> usize {
+        if self.deterministic {
+            1
+        } else {
+            thread_count(self.stt_threads)
+        }
+    }
+
+    /// CPU threads for the LLM: one in deterministic mode, otherwise as
+    /// `llm_threads` says, or `None` for the default of one per core
+    pub fn llm_thread_count(&self) -> Option<usize> {
+        match (self.deterministic, self.llm_threads) {
+            (true, _) => Some(1),
+            (false, 0) => None,
+            (false, threads) => Some(thread_count(threads)),
+        }
+    }
+
+    /// Tokens the LLM's prompt and output share: `llm_context_window`, or
+    /// the model's context window
+    pub fn llm_context_tokens(&self) -> usize {
+        self.llm_context_window.map_or_else(|| self.llm_model.context_window(), |tokens| tokens as usize)
+    }
+
+    /// Seed the LLM loads with: `llm_options.seed`, or `DETERMINISTIC_SEED`
+    /// in deterministic mode
+    pub fn llm_seed(&self) -> Option<u64> {
+        self.llm_options.seed.or(self.deterministic.then_some(DETERMINISTIC_SEED))
+    }
+
+    /// Get the models directory, creating it if needed:
+    /// `models_dir_override` if set, otherwise `default_models_dir`
+    ///
+    /// Every model path is resolved through this.
+    pub fn models_dir(&self) -> Result<PathBuf> {
+        let models_dir = match &self.models_dir_override {
+            Some(dir) => dir.clone(),
+            None => Self::default_models_dir()?,
+        };
+        std::fs::create_dir_all(&models_dir)
+            .with_context(|| format!("Failed to create models directory {:?}", models_dir))?;
+        Ok(models_dir)
+    }
+
+    /// Get the platform's models directory (models/ in the data directory)
+    pub fn default_models_dir() -> Result<PathBuf> {
+        Ok(Self::app_dirs()?.data_dir.join("models"))
+    }
+
+    /// Path of the log file: `log_file` if absolute, otherwise resolved
+    /// against the platform log directory
+    pub fn log_file_path(&self) -> Option<PathBuf> {
+        let path = self.log_file.as_ref()?;
+        if path.is_absolute() {
+            return Some(path.clone());
+        }
+        Some(AppDirs::current().map_or_else(|| path.clone(), |dirs| dirs.log_dir.join(path)))
+    }
+
+    fn app_dirs() -> Result<AppDirs> {
+        AppDirs::current().context("Could not determine the home directory")
+    }
+
+    /// Get the directory recordings are kept in when `history` is enabled:
+    /// `history.dir` if set, otherwise history/ in the data directory
+    pub fn history_dir(&self) -> Result<PathBuf> {
+        match &self.history.dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(Self::app_dirs()?.data_dir.join("history")),
+        }
+    }
+
+    /// Get the file reported corrections are kept in when `learning` is
+    /// enabled: `learning.file` if set, otherwise corrections.json in the
+    /// data directory
+    pub fn corrections_path(&self) -> Result<PathBuf> {
+        match &self.learning.file {
+            Some(file) => Ok(file.clone()),
+            None => Ok(Self::app_dirs()?.data_dir.join("corrections.json")),
+        }
+    }
+
+    /// Get the prompts directory
+    pub fn prompts_dir() -> Result<PathBuf> {
+        let prompts_dir = Self::app_dirs()?.data_dir.join("prompts");
+        std::fs::create_dir_all(&prompts_dir)?;
+        Ok(prompts_dir)
+    }
+
+    /// Get full path to the configured Whisper model variant
+    pub fn whisper_model_path(&self) -> Result<PathBuf> {
+        Ok(self.models_dir()?.join(self.whisper_model.filename(self.whisper_precision)))
+    }
+
+    /// Check if a Whisper model variant is downloaded
+    pub fn whisper_model_downloaded_for(&self, model: &WhisperModel, precision: ModelPrecision) -> bool {
+        self.models_dir().is_ok_and(|dir| dir.join(model.filename(precision)).exists())
+    }
+
+    /// Get full path to LLM model
+    pub fn llm_model_path(&self) -> Result<PathBuf> {
+        Ok(self.models_dir()?.join(self.llm_model.filename()))
+    }
+
+    /// Get the speaker-embedding model path used for diarization
+    pub fn speaker_model_path(&self) -> Result<PathBuf> {
+        Ok(self.models_dir()?.join(self.diarization.model.filename()))
+    }
+
+    /// Get the LLM's display name, using `custom_model_name` for a custom model
+    pub fn llm_display_name(&self) -> String {
+        if let FormatterBackend::Remote { base_url, model, .. } = &self.formatter {
+            return format!("{} at {}", model, base_url);
+        }
+        match &self.llm_model {
+            LlmModel::Custom(path) => self.custom_model_name.clone().unwrap_or_else(|| {
+                Path::new(path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| path.clone())
+            }),
+            model => model.display_name().to_string(),
+        }
+    }
+
+    /// Get directory containing the configured Moonshine variant's ONNX
+    /// models
+    pub fn moonshine_model_dir(&self) -> Result<PathBuf> {
+        Ok(self.models_dir()?.join(self.moonshine_model.dir_name(self.moonshine_precision)))
+    }
+
+    /// Check if configured Moonshine model files are downloaded
+    pub fn moonshine_model_downloaded(&self) -> bool {
+        self.moonshine_model_downloaded_for(&self.moonshine_model, self.moonshine_precision)
+    }
+
+    /// Check if a Moonshine model variant's files are downloaded
+    pub fn moonshine_model_downloaded_for(&self, model: &MoonshineModel, precision: ModelPrecision) -> bool {
+        if let Ok(models_dir) = self.models_dir() {
+            let model_dir = models_dir.join(model.dir_name(precision));
+            model
+                .required_files()
+                .iter()
+                .all(|f| model_dir.join(f).exists())
+        } else {
+            false
+        }
+    }
+
+    /// Get prompt template for a given context
+    ///
+    /// A configured `formatting_prompt` takes precedence over the built-in
+    /// and user prompt files.
+    pub fn get_prompt_for_context(&self, context: Option<&str>) -> String {
+        let ctx = context.unwrap_or(&self.default_context);
+        self.prompt_template_for_context(context).replace("{context}", ctx)
+    }
+
+    /// Same as `get_prompt_for_context`, with `{context}` left in the
+    /// prompt
+    pub fn prompt_template_for_context(&self, context: Option<&str>) -> String {
+        let ctx = context.unwrap_or(&self.default_context);
+
+        if let Some(template) = &self.formatting_prompt {
+            return template.clone();
+        }
+
+        // Try to load from prompts directory
+        if let Ok(prompts_dir) = Self::prompts_dir() {
+            let prompt_file = prompts_dir.join(format!("{}.txt", ctx));
+            if let Ok(contents) = std::fs::read_to_string(&prompt_file) {
+                return contents;
+            }
+        }
+
+        // Fallback to built-in prompts
+        match ctx {
+            "email" => include_str!("../../../../prompts/email.txt").to_string(),
+            "slack" => include_str!("../../../../prompts/slack.txt").to_string(),
+            "code" => include_str!("../../../../prompts/code.txt").to_string(),
+            _ => include_str!("../../../../prompts/default.txt").to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file_path() {
+        let mut config = Config::default();
+        assert_eq!(config.log_file_path(), None);
+
+        let absolute = std::env::temp_dir().join("voiceflow.log");
+        config.log_file = Some(absolute.clone());
+        assert_eq!(config.log_file_path(), Some(absolute));
+
+        config.log_file = Some(PathBuf::from("voiceflow.log"));
+        if let Some(dirs) = AppDirs::current() {
+            assert_eq!(config.log_file_path(), Some(dirs.log_dir.join("voiceflow.log")));
+        }
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_temperature() {
+        let mut config = Config::default();
+        config.llm_options.temperature = 3.0;
+        assert!(config.validate().is_err());
+
+        config.llm_options.temperature = -0.5;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_top_p() {
+        let mut config = Config::default();
+        config.llm_options.top_p = 1.5;
+        assert!(config.validate().is_err());
+
+        config.llm_options.top_p = -0.1;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_max_tokens() {
+        let mut config = Config::default();
+        config.llm_options.max_tokens = 0;
+        assert!(config.validate().is_err());
+
+        config.llm_options.max_tokens = 10000;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_llm_options_are_clamped() {
+        let options = LlmOptions {
+            max_tokens: 0,
+            temperature: 5.0,
+            top_p: f32::NAN,
+            repeat_penalty: 0.5,
+            top_k: 40,
+            seed: Some(7),
+            ..LlmOptions::default()
+        };
+        let clamped = options.clamped();
+        assert_eq!(
+            clamped,
+            LlmOptions {
+                max_tokens: 1,
+                temperature: 2.0,
+                top_p: LlmOptions::default().top_p,
+                repeat_penalty: 1.0,
+                ..options
+            }
+        );
+        assert_eq!(LlmOptions::default().clamped(), LlmOptions::default());
+
+        let mut config = Config::default();
+        config.llm_options = LlmOptions { max_tokens: 100_000, ..LlmOptions::default() }.clamped();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_min_silence_validation() {
+        let mut config = Config::default();
+        for valid in [0, 100, 2000, 30000] {
+            config.audio.min_silence_ms = valid;
+            assert!(config.validate().is_ok(), "{} should be valid", valid);
+        }
+        for invalid in [50, 30001] {
+            config.audio.min_silence_ms = invalid;
+            assert!(config.validate().is_err(), "{} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn test_chunk_validation() {
+        let mut config = Config::default();
+        config.audio.max_chunk_ms = 60000;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.audio.max_chunk_ms = 10000;
+        config.audio.chunk_overlap_ms = 6000;
+        assert!(config.validate().is_err());
+        config.audio.chunk_overlap_ms = 5000;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_speech_thresholds() {
+        let mut config = Config::default();
+        config.min_speech_confidence = 1.5;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.max_no_speech_probability = -0.1;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.min_format_similarity = 1.2;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_is_no_speech() {
+        let config = Config::default();
+        assert!(!config.is_no_speech(0.8, 0.05));
+        assert!(config.is_no_speech(0.1, 0.05), "low confidence");
+        assert!(config.is_no_speech(0.8, 0.9), "high no-speech probability");
+
+        let disabled = Config {
+            min_speech_confidence: 0.0,
+            max_no_speech_probability: 1.0,
+            ..Config::default()
+        };
+        assert!(!disabled.is_no_speech(0.0, 1.0));
+    }
+
+    #[test]
+    fn test_missing_speech_thresholds_use_defaults() {
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        let table = value.as_table_mut().unwrap();
+        table.remove("min_speech_confidence");
+        table.remove("max_no_speech_probability");
+        let config: Config = value.try_into().unwrap();
+        assert_eq!(config.min_speech_confidence, default_min_speech_confidence());
+        assert_eq!(config.max_no_speech_probability, default_max_no_speech_probability());
+    }
+
+    #[test]
+    fn test_invalid_sample_rate() {
+        let mut config = Config::default();
+        config.audio.sample_rate = 12345;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_valid_sample_rates() {
+        let mut config = Config::default();
+        for rate in [8000, 16000, 22050, 44100, 48000] {
+            config.audio.sample_rate = rate;
+            assert!(config.validate().is_ok(), "Sample rate {} should be valid", rate);
+        }
+    }
+
+    #[test]
+    fn test_invalid_vad_threshold() {
+        let mut config = Config::default();
+        config.audio.vad_threshold = 1.5;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_silence_duration() {
+        let mut config = Config::default();
+        config.audio.silence_duration_ms = 50;
+        assert!(config.validate().is_err());
+
+        config.audio.silence_duration_ms = 10000;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_endpointing() {
+        let mut config = Config::default();
+        config.endpointing.trailing_silence_ms = 50;
+        assert!(config.validate().is_err());
+
+        config.endpointing = EndpointingOptions { max_utterance_ms: 500, ..EndpointingOptions::default() };
+        assert!(config.validate().is_err());
+
+        // No limit
+        config.endpointing.max_utterance_ms = 0;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_learning_validation() {
+        let mut config = Config::default();
+        assert!(!config.learning.enabled);
+        config.learning.min_reports = 0;
+        assert!(config.validate().unwrap_err().to_string().contains("learning.min_reports"));
+        config.learning.min_reports = 1;
+        config.learning.min_agreement = 0.0;
+        assert!(config.validate().unwrap_err().to_string().contains("learning.min_agreement"));
+        config.learning.min_agreement = 1.0;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_timeouts_scale_with_the_audio() {
+        let timeouts = TimeoutOptions::default();
+        assert_eq!(timeouts.stt_limit_ms(5_000), 120_000);
+        assert_eq!(timeouts.stt_limit_ms(600_000), 1_200_000);
+        assert_eq!(timeouts.total_limit_ms(90_000), 450_000);
+        let off = TimeoutOptions { stt_ms: 0, total_ms: 0, ..TimeoutOptions::default() };
+        assert_eq!(off.stt_limit_ms(600_000), 0);
+        assert_eq!(off.total_limit_ms(600_000), 0);
+
+        let config = migrate::parse_config("[timeouts]\nllm_ms = 0\n").unwrap();
+        assert_eq!(config.timeouts, TimeoutOptions { llm_ms: 0, ..TimeoutOptions::default() });
+    }
+
+    #[test]
+    fn test_invalid_telemetry_endpoint() {
+        let mut config = Config::default();
+        config.telemetry.endpoint = Some("localhost:4318".to_string());
+        assert!(config.validate().is_err());
+        config.telemetry.endpoint = Some("http://localhost:4318".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_context() {
+        let mut config = Config::default();
+        config.default_context = "invalid_context".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_valid_contexts() {
+        let mut config = Config::default();
+        for ctx in ["default", "email", "slack", "code"] {
+            config.default_context = ctx.to_string();
+            assert!(config.validate().is_ok(), "Context '{}' should be valid", ctx);
+        }
+    }
+
+    #[test]
+    fn test_formatting_prompt_placeholders() {
+        let mut config = Config::default();
+        config.formatting_prompt = Some("Keep abbreviations as dictated ({context}):\n{transcript}".to_string());
+        assert!(config.validate().is_ok());
+
+        // Literal braces are not placeholders
+        config.formatting_prompt = Some("Reply as {\"text\": ...} for {transcript}".to_string());
+        assert!(config.validate().is_ok());
+
+        // The fields of a structured context are placeholders too
+        config.formatting_prompt = Some("Write to {recipient} in {app_name}:\n{transcript}".to_string());
+        assert!(config.validate().is_ok());
+
+        config.formatting_prompt = Some("Format {transcript} for {audience}".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("{audience}"), "{}", err);
+
+        config.formatting_prompt = Some("Format this text nicely.".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_custom_default_preset_is_validated() {
+        let mut config = Config::default();
+        config.default_preset = Some(FormattingPreset::Custom("Tidy up: {transcript}".to_string()));
+        assert!(config.validate().is_ok());
+
+        config.default_preset = Some(FormattingPreset::Custom("Tidy up: {text}".to_string()));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_default_preset_toml() {
+        let mut config = Config::default();
+        config.default_preset = Some(FormattingPreset::CodeComment);
+        let toml_str = toml::to_string(&config).unwrap();
+        assert!(toml_str.contains("default_preset = \"code-comment\""), "{}", toml_str);
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert_eq!(parsed.default_preset, Some(FormattingPreset::CodeComment));
+    }
+
+    #[test]
+    fn test_voice_command_validation() {
+        let mut config = Config::default();
+        config.voice_commands.language = "de".to_string();
+        assert!(config.validate().is_ok());
+        config.voice_commands.language = "ja".to_string();
+        let err = config.validate().unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ConfigError::UnsupportedVoiceCommandLanguage { .. })), "{}", err);
+
+        config.voice_commands.language = AUTO_LANGUAGE.to_string();
+        config.voice_commands.custom.insert("smiley face".to_string(), ":)".to_string());
+        assert!(config.validate().is_ok());
+        config.voice_commands.custom.insert(" ! ".to_string(), "!".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ConfigError::InvalidVoiceCommand { .. })), "{}", err);
+    }
+
+    #[test]
+    fn test_number_formatting_toml() {
+        let config: Config = toml::from_str("[number_formatting]\nlocale = \"en-GB\"\nphone_numbers = false").unwrap();
+        assert_eq!(config.number_formatting.locale, NumberLocale::EnGb);
+        assert!(!config.number_formatting.phone_numbers);
+        assert!(config.number_formatting.dates);
+        assert!(toml::from_str::<Config>("[number_formatting]\nlocale = \"fr-FR\"").is_err());
+    }
+
+    #[test]
+    fn test_formatting_prompt_is_sent_to_llm() {
+        let mut config = Config::default();
+        config.formatting_prompt =
+            Some("Medical note ({context}). Do not expand abbreviations.{personal_dictionary}\n{transcript}".to_string());
+
+        let template = config.get_prompt_for_context(Some("email"));
+        let prompt = crate::llm::format_prompt(&template, "pt c/o SOB", &config);
+        assert!(
+            prompt.starts_with("Medical note (email). Do not expand abbreviations.\npt c/o SOB"),
+            "{}",
+            prompt
+        );
+    }
+
+    #[test]
+    fn test_vocabulary_entries_from_toml() {
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        let vocabulary: toml::Value =
+            toml::from_str(r#"v = ["VoiceFlow", { term = "Kubernetes", sounds_like = ["cooper netties"] }]"#).unwrap();
+        value
+            .as_table_mut()
+            .unwrap()
+            .insert("vocabulary".to_string(), vocabulary["v"].clone());
+        let config: Config = value.try_into().unwrap();
+
+        assert_eq!(
+            config.vocabulary,
+            vec![
+                VocabularyEntry::new("VoiceFlow"),
+                VocabularyEntry {
+                    term: "Kubernetes".to_string(),
+                    sounds_like: vec!["cooper netties".to_string()],
+                },
+            ]
+        );
+        assert_eq!(config.vocabulary[1].prompt_hint(), "Kubernetes (may be transcribed as \"cooper netties\")");
+        assert_eq!(config.stt_initial_prompt().as_deref(), Some("Glossary: VoiceFlow, Kubernetes."));
+    }
+
+    #[test]
+    fn test_vocabulary_limits() {
+        let mut config = Config::default();
+        assert_eq!(config.stt_initial_prompt(), None);
+
+        config.vocabulary = vec![VocabularyEntry::new("Kubernetes")];
+        assert!(config.validate().is_ok());
+
+        config.vocabulary = vec![VocabularyEntry::new("  ")];
+        assert!(config.validate().is_err());
+
+        config.vocabulary = vec![VocabularyEntry {
+            term: "Kubernetes".to_string(),
+            sounds_like: vec!["x".repeat(MAX_VOCABULARY_TERM_CHARS + 1)],
+        }];
+        assert!(config.validate().is_err());
+
+        config.vocabulary = vec![VocabularyEntry::new("term"); MAX_VOCABULARY_ENTRIES + 1];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_stt_prompt_leads_with_initial_prompt() {
+        let mut config = Config::default();
+        config.stt_decode.initial_prompt = Some("Notes from the cardiology ward.".to_string());
+        assert_eq!(config.stt_initial_prompt().as_deref(), Some("Notes from the cardiology ward."));
+
+        config.vocabulary = vec![VocabularyEntry::new("echocardiogram")];
+        assert_eq!(
+            config.stt_initial_prompt().as_deref(),
+            Some("Notes from the cardiology ward. Glossary: echocardiogram.")
+        );
+    }
+
+    #[test]
+    fn test_stt_decode_validation() {
+        let mut config = Config::default();
+        assert_eq!(config.stt_decode.temperatures(false), [0.0, 0.2, 0.4, 0.6, 0.8, 1.0]);
+        assert_eq!(config.stt_decode.temperatures(true), [0.0]);
+        config.stt_decode.temperature_fallback.clear();
+        assert_eq!(config.stt_decode.temperatures(false), [0.0]);
+
+        config.stt_decode.beam_size = 5;
+        assert!(config.validate().is_ok());
+        config.stt_decode.beam_size = 0;
+        assert!(config.validate().unwrap_err().to_string().contains("stt_decode.beam_size"));
+        config.stt_decode.beam_size = 5;
+        config.stt_decode.alternatives = MAX_BEAM_SIZE + 1;
+        assert!(config.validate().unwrap_err().to_string().contains("stt_decode.alternatives"));
+        config.stt_decode.alternatives = 3;
+        config.stt_decode.temperature_fallback = vec![0.0, 1.5];
+        assert!(config.validate().unwrap_err().to_string().contains("stt_decode.temperature_fallback"));
+    }
+
+    #[test]
+    fn test_stt_prompt_fits_budget() {
+        let mut config = Config::default();
+        config.vocabulary = vec![VocabularyEntry::new("x".repeat(MAX_VOCABULARY_TERM_CHARS)); MAX_VOCABULARY_ENTRIES];
+        let prompt = config.stt_initial_prompt().unwrap();
+        assert!(prompt.len() <= MAX_STT_PROMPT_CHARS + 1);
+    }
+
+    #[test]
+    fn test_invalid_replacement_regex() {
+        let mut config = Config::default();
+        config.replacements = vec![ReplacementRule {
+            pattern: "[a-".to_string(),
+            replacement: String::new(),
+            is_regex: true,
+            ..Default::default()
+        }];
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("[a-"), "{}", err);
+
+        config.replacements[0].is_regex = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_language_validation() {
+        let mut config = Config::default();
+        assert_eq!(config.language, "en");
+
+        for language in ["de", "auto"] {
+            config.language = language.to_string();
+            assert!(config.validate().is_ok(), "{}", language);
+        }
+
+        config.language = "german".to_string();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("Unsupported language"), "{}", err);
+
+        // Moonshine can't transcribe German, nor detect it
+        config.stt_engine = SttEngine::Moonshine;
+        for language in ["de", "auto"] {
+            config.language = language.to_string();
+            let err = config.validate().unwrap_err();
+            assert!(err.to_string().contains("Moonshine only transcribes English"), "{}", err);
+        }
+        config.language = "en".to_string();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_translate_task_requires_whisper() {
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        let table = value.as_table_mut().unwrap();
+        table.insert("stt_task".to_string(), toml::Value::String("translate".to_string()));
+        let mut config: Config = value.try_into().unwrap();
+        assert_eq!(config.stt_task, SttTask::Translate);
+        assert!(config.validate().is_ok());
+
+        config.stt_engine = SttEngine::Moonshine;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("Moonshine can't translate"), "{}", err);
+    }
+
+    #[test]
+    fn test_thread_count_is_clamped() {
+        let available = std::thread::available_parallelism().unwrap().get();
+        assert_eq!(thread_count(0), available);
+        assert_eq!(thread_count(1), 1);
+        assert_eq!(thread_count(u32::MAX), available);
+
+        let mut config = Config::default();
+        config.set_key("stt.threads", "2").unwrap();
+        config.set_key("llm.threads", "4").unwrap();
+        assert_eq!((config.stt_threads, config.llm_threads), (2, 4));
+        assert_eq!(config.get_key("stt.threads").unwrap(), "2");
+        assert!(config.set_key("llm.threads", "-1").is_err());
+    }
+
+    #[test]
+    fn test_stt_execution_provider() {
+        let mut config = Config::default();
+        assert_eq!(config.stt_execution_provider, SttExecutionProvider::Auto);
+        config.set_key("stt.execution_provider", "coreml").unwrap();
+        assert_eq!(config.stt_execution_provider, SttExecutionProvider::CoreMl);
+        assert!(config.set_key("stt.execution_provider", "tpu").is_err());
+
+        for provider in [SttExecutionProvider::Cpu, SttExecutionProvider::CoreMl, SttExecutionProvider::Auto] {
+            assert_eq!(SttExecutionProvider::from_id(provider.id()), Some(provider));
+            assert_eq!(serde_json::to_value(provider).unwrap(), provider.id());
+        }
+    }
+
+    #[test]
+    fn test_whisper_model_ids_match_serde_names() {
+        for model in WhisperModel::all_models() {
+            assert_eq!(WhisperModel::from_id(model.id()), Some(model.clone()));
+            let value = toml::Value::try_from(&model).unwrap();
+            assert_eq!(value.as_str(), Some(model.id()));
+        }
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        value.as_table_mut().unwrap().insert("whisper_model".into(), "large-v3-turbo".into());
+        let config: Config = value.try_into().unwrap();
+        assert_eq!(config.whisper_model, WhisperModel::LargeV3Turbo);
+        assert_eq!(config.whisper_model.filename(config.whisper_precision), "ggml-large-v3-turbo.bin");
+    }
+
+    #[test]
+    fn test_precision_variants() {
+        for precision in ModelPrecision::all() {
+            assert_eq!(ModelPrecision::from_id(precision.id()), Some(precision));
+            assert_eq!(toml::Value::try_from(precision).unwrap().as_str(), Some(precision.id()));
+        }
+        assert_eq!(WhisperModel::Small.filename(ModelPrecision::Quantized), "ggml-small-q5_1.bin");
+        assert_eq!(WhisperModel::Medium.filename(ModelPrecision::Quantized), "ggml-medium-q5_0.bin");
+        assert_eq!(WhisperModel::Base.filename(ModelPrecision::Int8), "ggml-base-q8_0.bin");
+        assert_eq!(MoonshineModel::Tiny.dir_name(ModelPrecision::Float32), "moonshine-tiny");
+        assert_eq!(MoonshineModel::Base.dir_name(ModelPrecision::Int8), "moonshine-base-int8");
+        assert_eq!(MoonshineModel::Base.onnx_path(ModelPrecision::Quantized), "onnx/base/quantized");
+        assert_eq!(ModelPrecision::split_variant_id("large-v3-turbo-int8"), ("large-v3-turbo", ModelPrecision::Int8));
+        assert_eq!(ModelPrecision::split_variant_id("tiny"), ("tiny", ModelPrecision::Float32));
+
+        // Files written before precision existed select the full-precision weights
+        let mut value = toml::Value::try_from(Config::default()).unwrap();
+        let table = value.as_table_mut().unwrap();
+        table.remove("whisper_precision");
+        table.remove("moonshine_precision");
+        let config: Config = value.try_into().unwrap();
+        assert_eq!((config.whisper_precision, config.moonshine_precision), (ModelPrecision::Float32, ModelPrecision::Float32));
+    }
+
+    #[test]
+    fn test_remote_formatter_round_trips_and_validates() {
+        let toml = "[formatter.remote]\nbase_url = \"http://desktop.local:11434\"\nmodel = \"qwen3:4b\"\n";
+        let config = migrate::parse_config(toml).unwrap();
+        let FormatterBackend::Remote { base_url, model, api_key_env, timeout_secs } = &config.formatter else {
+            panic!("expected a remote formatter: {:?}", config.formatter);
+        };
+        assert_eq!((base_url.as_str(), model.as_str()), ("http://desktop.local:11434", "qwen3:4b"));
+        assert_eq!((api_key_env, *timeout_secs), (&None, 30));
+        assert!(config.validate().is_ok());
+        assert_eq!(config.llm_display_name(), "qwen3:4b at http://desktop.local:11434");
+
+        let saved = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(migrate::parse_config(&saved).unwrap().formatter, config.formatter);
+        assert_eq!(migrate::parse_config("").unwrap().formatter, FormatterBackend::Local);
+
+        let mut config = Config::default();
+        config
+            .set_key("llm.formatter", r#"{"remote": {"base_url": "desktop.local:11434", "model": "qwen3:4b"}}"#)
+            .unwrap_err();
+        assert_eq!(config.formatter, FormatterBackend::Local);
+    }
+
+    fn temp_config_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voiceflow-config-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("config.toml")
+    }
+
+    fn dir_entries(path: &Path) -> usize {
+        std::fs::read_dir(path.parent().unwrap()).unwrap().count()
+    }
+
+    #[test]
+    fn test_save_replaces_the_file() {
+        let path = temp_config_path("save");
+        let mut config = Config {
+            llm_model: LlmModel::Custom("/models/custom.gguf".to_string()),
+            ..Config::default()
+        };
+        config.save(path.to_str()).unwrap();
+        config.default_context = "slack".to_string();
+        config.save(path.to_str()).unwrap();
+
+        let loaded = Config::load(path.to_str()).unwrap();
+        assert_eq!(loaded.default_context, "slack");
+        assert_eq!(loaded.llm_model, config.llm_model);
+        assert_eq!(loaded.schema_version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(dir_entries(&path), 1, "temporary file left behind");
+    }
+
+    /// Saves in a loop; run as a child process by
+    /// `test_concurrent_saves_from_two_processes`
+    #[test]
+    #[ignore]
+    fn save_loop_child() {
+        let Ok(path) = std::env::var("VOICEFLOW_TEST_SAVE_PATH") else {
+            return;
+        };
+        let default_context = std::env::var("VOICEFLOW_TEST_SAVE_CONTEXT").unwrap();
+        let config = Config { default_context, ..Config::default() };
+        for _ in 0..200 {
+            config.save(Some(&path)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_concurrent_saves_from_two_processes() {
+        let path = temp_config_path("concurrent");
+        Config::default().save(path.to_str()).unwrap();
+
+        let mut children: Vec<_> = ["email", "slack"]
+            .iter()
+            .map(|context| {
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["config::tests::save_loop_child", "--exact", "--ignored", "--quiet"])
+                    .env("VOICEFLOW_TEST_SAVE_PATH", &path)
+                    .env("VOICEFLOW_TEST_SAVE_CONTEXT", context)
+                    .stdout(std::process::Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+
+        // Every read sees one whole file, never a mix or a truncated one
+        while children.iter_mut().any(|child| child.try_wait().unwrap().is_none()) {
+            let contents = std::fs::read_to_string(&path).unwrap();
+            let config: Config = toml::from_str(&contents).unwrap_or_else(|e| panic!("{}\n{}", e, contents));
+            assert!(["default", "email", "slack"].contains(&config.default_context.as_str()));
+        }
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+
+        let config = Config::load(path.to_str()).unwrap();
+        assert!(["email", "slack"].contains(&config.default_context.as_str()));
+        assert_eq!(dir_entries(&path), 1, "temporary file left behind");
+    }
+
+    #[test]
+    fn test_env_var_names() {
+        // Ensure all env var names are unique and properly prefixed
+        let vars = [
+            env_vars::STT_ENGINE,
+            env_vars::WHISPER_MODEL,
+            env_vars::MOONSHINE_MODEL,
+            env_vars::LLM_MODEL,
+            env_vars::LLM_TEMPERATURE,
+            env_vars::LLM_MAX_TOKENS,
+            env_vars::LLM_TOP_P,
+            env_vars::ENABLE_THINKING,
+            env_vars::DEFAULT_CONTEXT,
+            env_vars::LANGUAGE,
+            env_vars::MODELS_DIR,
+            env_vars::LOG_FILE,
+            env_vars::CONFIG,
+        ];
+
+        for var in &vars {
+            assert!(var.starts_with("VOICEFLOW_"), "Env var {} should be prefixed", var);
+        }
+
+        // Check uniqueness
+        let mut unique: std::collections::HashSet<&str> = std::collections::HashSet::new();
+        for var in &vars {
+            assert!(unique.insert(var), "Duplicate env var: {}", var);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Environment variable overrides for every config field, the last layer of
+//! `Config::load` (defaults, then the file, then the environment)
+//!
+//! A field is overridden by `VOICEFLOW_` and its name in upper case, with
+//! `__` between a section and its field: `VOICEFLOW_STT_ENGINE`,
+//! `VOICEFLOW_AUDIO__VAD_THRESHOLD`. Values are written as for
+//! `Config::set_key`, and additionally:
+//! - enum values may use `-` or `_` and any case (`LARGE_V3_TURBO`)
+//! - models may be given by their download id (`qwen3-1.7b`), and
+//!   `llm_model` by the path of a `.gguf` file
+//! - booleans may also be `1`/`0`, `yes`/`no` or `on`/`off`
+//! - lists may be comma-separated (`VoiceFlow, Kubernetes`)
+
+use super::keys::{lookup, lookup_mut, parse_value};
+use super::{env_vars, Config, ConfigError, WhisperModel};
+use crate::downloads::DownloadableModel;
+use anyhow::Result;
+use serde_json::Value;
+
+/// Shorter names kept from before every field had a variable, as (variable,
+/// field); the full name wins if both are set
+const ALIASES: &[(&str, &str)] = &[
+    (env_vars::LLM_TEMPERATURE, "llm_options.temperature"),
+    (env_vars::LLM_MAX_TOKENS, "llm_options.max_tokens"),
+    (env_vars::LLM_TOP_P, "llm_options.top_p"),
+    (env_vars::ENABLE_THINKING, "llm_options.enable_thinking"),
+    (env_vars::MODELS_DIR, "models_dir_override"),
+];
+
+/// Fields that are managed rather than set
+const NOT_OVERRIDABLE: &[&str] = &["schema_version"];
+
+/// Every overridable field, as (variable, dotted field name)
+pub fn env_var_fields() -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    collect_fields(&field_template(), "", &mut fields);
+    fields
+}
+
+/// The default config as JSON, with the fields that are left out while
+/// unset or empty added with a value of their type
+fn field_template() -> serde_json::Map<String, Value> {
+    let mut template = match serde_json::to_value(Config::default()) {
+        Ok(Value::Object(template)) => template,
+        _ => serde_json::Map::new(),
+    };
+    for (field, value) in [
+        ("custom_model_name", Value::from("")),
+        ("log_file", Value::from("")),
+        ("models_dir_override", Value::from("")),
+        ("formatting_prompt", Value::from("")),
+        ("default_preset", Value::from("")),
+        ("vocabulary", Value::Array(Vec::new())),
+        ("replacements", Value::Array(Vec::new())),
+    ] {
+        template.entry(field).or_insert(value);
+    }
+    template
+}
+
+fn collect_fields(section: &serde_json::Map<String, Value>, prefix: &str, fields: &mut Vec<(String, String)>) {
+    for (key, value) in section {
+        let path = format!("{}{}", prefix, key);
+        if NOT_OVERRIDABLE.contains(&path.as_str()) {
+            continue;
+        }
+        match value {
+            Value::Object(inner) => collect_fields(inner, &format!("{}.", path), fields),
+            _ => fields.push((var_name(&path), path)),
+        }
+    }
+}
+
+fn var_name(path: &str) -> String {
+    format!("{}{}", env_vars::PREFIX, path.replace('.', "__").to_uppercase())
+}
+
+impl Config {
+    /// Apply the overrides in the process environment (see the module docs)
+    pub fn apply_env_overrides(&mut self) -> Result<()> {
+        self.apply_env(|name| std::env::var(name).ok())
+    }
+
+    /// Apply overrides, reading variables with `var`
+    ///
+    /// Fails on the first variable whose value doesn't parse, naming it,
+    /// and leaves the config unchanged.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let mut json = serde_json::to_value(&*self)?;
+        let template = Value::Object(field_template());
+        let defaults = serde_json::to_value(Config::default())?;
+        for (full_name, path) in env_var_fields() {
+            let alias = ALIASES.iter().find(|(_, field)| *field == path).map(|(alias, _)| *alias);
+            let Some((name, raw)) = var(&full_name)
+                .map(|raw| (full_name.as_str(), raw))
+                .or_else(|| alias.and_then(|alias| var(alias).map(|raw| (alias, raw))))
+            else {
+                continue;
+            };
+
+            let kind = lookup(&template, &path).unwrap_or(&Value::Null);
+            let optional = matches!(lookup(&defaults, &path), None | Some(Value::Null));
+            let candidates = if optional && raw.trim().is_empty() && !kind.is_array() {
+                vec![Value::Null]
+            } else {
+                candidates(name, &path, kind, &raw)?
+            };
+            let mut error = None;
+            for candidate in candidates {
+                *lookup_mut(&mut json, &path) = candidate;
+                match serde_json::from_value::<Config>(json.clone()) {
+                    Ok(_) => {
+                        error = None;
+                        break;
+                    }
+                    Err(e) => {
+                        error.get_or_insert(e.to_string());
+                    }
+                }
+            }
+            if let Some(message) = error {
+                return Err(ConfigError::InvalidValue { key: name.to_string(), message }.into());
+            }
+            tracing::debug!("Config field {} set from {}", path, name);
+        }
+        *self = serde_json::from_value(json)?;
+        Ok(())
+    }
+}
+
+/// The values `raw` may stand for in a field of the type of `current`, most
+/// specific first
+fn candidates(name: &str, path: &str, current: &Value, raw: &str) -> Result<Vec<Value>, ConfigError> {
+    let raw = raw.trim();
+    let invalid = |message: &str| ConfigError::InvalidValue { key: name.to_string(), message: message.to_string() };
+    match current {
+        Value::Bool(_) => match raw.to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(vec![Value::Bool(true)]),
+            "false" | "0" | "no" | "off" => Ok(vec![Value::Bool(false)]),
+            _ => Err(invalid("expected true or false")),
+        },
+        Value::Array(_) if !raw.starts_with('[') => Ok(vec![Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        )]),
+        Value::Array(_) => serde_json::from_str(raw).map(|list| vec![list]).map_err(|e| invalid(&e.to_string())),
+        _ if path == "language" => Ok(vec![Value::String(raw.to_lowercase())]),
+        _ => {
+            let mut values: Vec<Value> = model_by_id(path, raw).into_iter().collect();
+            let value = parse_value(name, current, raw)?;
+            let lower = value.as_str().map(str::to_lowercase);
+            values.push(value);
+            if let Some(lower) = lower {
+                values.extend(
+                    [lower.replace('_', "-"), lower.replace('-', "_"), lower.replace(['-', '_'], ""), lower]
+                        .into_iter()
+                        .map(Value::String),
+                );
+            }
+            Ok(values)
+        }
+    }
+}
+
+/// A model field's value for a download id, or a `.gguf` path
+fn model_by_id(path: &str, raw: &str) -> Option<Value> {
+    let id = raw.to_lowercase().replace('_', "-");
+    let value = match (path, DownloadableModel::from_id(&id)) {
+        ("llm_model", Some(DownloadableModel::Llm(model))) => serde_json::to_value(model),
+        ("llm_model", _) if raw.ends_with(".gguf") => return Some(serde_json::json!({ "custom": raw })),
+        ("whisper_model", Some(DownloadableModel::Whisper(model, _))) => serde_json::to_value(model),
+        ("whisper_model", _) => serde_json::to_value(WhisperModel::from_id(&id)?),
+        ("moonshine_model", Some(DownloadableModel::Moonshine(model, _))) => serde_json::to_value(model),
+        ("diarization.model", Some(DownloadableModel::Speaker(model))) => serde_json::to_value(model),
+        _ => return None,
+    };
+    value.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LlmModel, MoonshineModel, SttEngine};
+    use std::collections::HashMap;
+
+    /// A variable, its value and a check of the config it gives
+    type Case = (&'static str, &'static str, fn(&Config) -> bool);
+
+    fn with_env(vars: &[(&str, &str)]) -> Result<Config> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut config = Config::default();
+        config.apply_env(|name| vars.get(name).cloned())?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_every_field_has_a_variable() {
+        let fields = env_var_fields();
+        let names: std::collections::HashSet<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names.len(), fields.len(), "duplicate variable names");
+        for expected in [
+            env_vars::STT_ENGINE,
+            env_vars::WHISPER_MODEL,
+            env_vars::MOONSHINE_MODEL,
+            env_vars::LLM_MODEL,
+            env_vars::DEFAULT_CONTEXT,
+            env_vars::LANGUAGE,
+            env_vars::LOG_FILE,
+            "VOICEFLOW_LLM_OPTIONS__SEED",
+            "VOICEFLOW_AUDIO__VAD_THRESHOLD",
+            "VOICEFLOW_LLM_OUTPUT__STOP_SEQUENCES",
+            "VOICEFLOW_REPLACEMENTS",
+            "VOICEFLOW_VOCABULARY",
+            "VOICEFLOW_FORMATTING_PROMPT",
+        ] {
+            assert!(names.contains(expected), "{} missing", expected);
+        }
+        assert!(!names.contains("VOICEFLOW_SCHEMA_VERSION"));
+    }
+
+    #[test]
+    fn test_override_matrix() {
+        let cases: &[Case] = &[
+            ("VOICEFLOW_STT_ENGINE", "moonshine", |c| c.stt_engine == SttEngine::Moonshine),
+            ("VOICEFLOW_STT_ENGINE", "Whisper", |c| c.stt_engine == SttEngine::Whisper),
+            ("VOICEFLOW_WHISPER_MODEL", "large-v3-turbo", |c| c.whisper_model == WhisperModel::LargeV3Turbo),
+            ("VOICEFLOW_WHISPER_MODEL", "LARGE_V3_TURBO", |c| c.whisper_model == WhisperModel::LargeV3Turbo),
+            ("VOICEFLOW_WHISPER_MODEL", "whisper-small", |c| c.whisper_model == WhisperModel::Small),
+            ("VOICEFLOW_MOONSHINE_MODEL", "base", |c| c.moonshine_model == MoonshineModel::Base),
+            ("VOICEFLOW_MOONSHINE_MODEL", "moonshine-tiny", |c| c.moonshine_model == MoonshineModel::Tiny),
+            ("VOICEFLOW_LLM_MODEL", "qwen3-4b", |c| c.llm_model == LlmModel::Qwen3_4B),
+            ("VOICEFLOW_LLM_MODEL", "gemma2_2b", |c| c.llm_model == LlmModel::Gemma2_2B),
+            ("VOICEFLOW_LLM_MODEL", "/data/models/m.gguf", |c| {
+                c.llm_model == LlmModel::Custom("/data/models/m.gguf".to_string())
+            }),
+            ("VOICEFLOW_LLM_OPTIONS__TEMPERATURE", "0.25", |c| c.llm_options.temperature == 0.25),
+            ("VOICEFLOW_LLM_TEMPERATURE", "0.5", |c| c.llm_options.temperature == 0.5),
+            ("VOICEFLOW_LLM_OPTIONS__SEED", "42", |c| c.llm_options.seed == Some(42)),
+            ("VOICEFLOW_ENABLE_THINKING", "1", |c| c.llm_options.enable_thinking),
+            ("VOICEFLOW_AUDIO__VAD_ENABLED", "off", |c| !c.audio.vad_enabled),
+            ("VOICEFLOW_AUDIO__MAX_CHUNK_MS", "20000", |c| c.audio.max_chunk_ms == 20000),
+            ("VOICEFLOW_LANGUAGE", "DE", |c| c.language == "de"),
+            ("VOICEFLOW_LOG_FILE", "voiceflow.log", |c| c.log_file.is_some()),
+            ("VOICEFLOW_LOG_FILE", "", |c| c.log_file.is_none()),
+            ("VOICEFLOW_MODELS_DIR", "/data/models", |c| {
+                c.models_dir_override.as_deref() == Some(std::path::Path::new("/data/models"))
+            }),
+            ("VOICEFLOW_PERSONAL_DICTIONARY", "VoiceFlow, Kubernetes", |c| {
+                c.personal_dictionary == ["VoiceFlow", "Kubernetes"]
+            }),
+            ("VOICEFLOW_VOCABULARY", "VoiceFlow, Kubernetes", |c| c.vocabulary.len() == 2),
+            ("VOICEFLOW_VOCABULARY", "", |c| c.vocabulary.is_empty()),
+            ("VOICEFLOW_FORMATTING_PROMPT", "Tidy: {transcript}", |c| {
+                c.formatting_prompt.as_deref() == Some("Tidy: {transcript}")
+            }),
+        ];
+        for (name, value, check) in cases {
+            let config = with_env(&[(name, value)]).unwrap_or_else(|e| panic!("{}={}: {:#}", name, value, e));
+            assert!(check(&config), "{}={}", name, value);
+        }
+    }
+
+    #[test]
+    fn test_full_name_wins_over_alias() {
+        let config =
+            with_env(&[("VOICEFLOW_LLM_TEMPERATURE", "0.9"), ("VOICEFLOW_LLM_OPTIONS__TEMPERATURE", "0.1")]).unwrap();
+        assert_eq!(config.llm_options.temperature, 0.1);
+    }
+
+    #[test]
+    fn test_invalid_values_name_the_variable() {
+        for (name, value) in [
+            ("VOICEFLOW_STT_ENGINE", "dragon"),
+            ("VOICEFLOW_LLM_OPTIONS__MAX_TOKENS", "lots"),
+            ("VOICEFLOW_LLM_MAX_TOKENS", "-3"),
+            ("VOICEFLOW_AUDIO__VAD_ENABLED", "maybe"),
+            ("VOICEFLOW_LLM_MODEL", "llama-70b"),
+            ("VOICEFLOW_REPLACEMENTS", "[{\"find\": 1}]"),
+        ] {
+            let err = with_env(&[(name, value)]).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::InvalidValue { key, .. }) if key == name),
+                "{}={}: {}",
+                name,
+                value,
+                err
+            );
+            assert!(err.to_string().contains(name), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_unset_variables_change_nothing() {
+        let config = with_env(&[]).unwrap();
+        assert_eq!(config.to_json().unwrap(), Config::default().to_json().unwrap());
+    }
+}
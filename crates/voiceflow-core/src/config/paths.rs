@@ -0,0 +1,144 @@
+//! Per-platform config, data and log directories
+//!
+//! Follows the conventions of the `directories` crate for
+//! `ProjectDirs::from("com", "era-laboratories", "voiceflow")`, read from the
+//! environment so every platform's layout can be checked on any host:
+//!
+//! - Linux: `$XDG_CONFIG_HOME/voiceflow` for config, `$XDG_DATA_HOME/voiceflow`
+//!   for data and `$XDG_STATE_HOME/voiceflow` for logs
+//! - macOS: `~/Library/Application Support/com.era-laboratories.voiceflow` for
+//!   config and data, `~/Library/Logs/com.era-laboratories.voiceflow` for logs
+//! - Windows: `%APPDATA%\era-laboratories\voiceflow\config` and `...\data`,
+//!   `%LOCALAPPDATA%\era-laboratories\voiceflow\logs` for logs
+//!
+//! On Linux the XDG variables default to `~/.config`, `~/.local/share` and
+//! `~/.local/state`, and are ignored unless absolute.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+const QUALIFIER: &str = "com";
+const ORGANIZATION: &str = "era-laboratories";
+const APPLICATION: &str = "voiceflow";
+
+/// Where VoiceFlow keeps its files on a platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppDirs {
+    /// Holds config.toml
+    pub config_dir: PathBuf,
+    /// Holds models/, prompts/ and history/
+    pub data_dir: PathBuf,
+    /// Relative `log_file` paths are resolved here
+    pub log_dir: PathBuf,
+}
+
+impl AppDirs {
+    /// Directories of the platform this was built for
+    ///
+    /// `None` if the home directory (or `%APPDATA%` on Windows) is unknown.
+    pub fn current() -> Option<Self> {
+        Self::for_os(std::env::consts::OS, |name| std::env::var_os(name))
+    }
+
+    /// Directories for `os` (a `std::env::consts::OS` value), reading
+    /// environment variables through `var`
+    ///
+    /// Unknown Unix-likes use the Linux layout.
+    pub fn for_os(os: &str, var: impl Fn(&str) -> Option<OsString>) -> Option<Self> {
+        let non_empty = |name: &str| var(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        match os {
+            "windows" => {
+                let roaming = non_empty("APPDATA")?.join(ORGANIZATION).join(APPLICATION);
+                let local = non_empty("LOCALAPPDATA")
+                    .map(|dir| dir.join(ORGANIZATION).join(APPLICATION))
+                    .unwrap_or_else(|| roaming.clone());
+                Some(Self {
+                    config_dir: roaming.join("config"),
+                    data_dir: roaming.join("data"),
+                    log_dir: local.join("logs"),
+                })
+            }
+            "macos" | "ios" => {
+                let home = non_empty("HOME")?;
+                let bundle_id = format!("{}.{}.{}", QUALIFIER, ORGANIZATION, APPLICATION);
+                let support = home.join("Library").join("Application Support").join(&bundle_id);
+                Some(Self {
+                    config_dir: support.clone(),
+                    data_dir: support,
+                    log_dir: home.join("Library").join("Logs").join(&bundle_id),
+                })
+            }
+            _ => {
+                let home = non_empty("HOME");
+                let xdg = |name: &str, fallback: &[&str]| {
+                    non_empty(name).filter(|dir| dir.has_root()).or_else(|| {
+                        home.as_ref().map(|home| fallback.iter().fold(home.clone(), |dir, part| dir.join(part)))
+                    })
+                };
+                Some(Self {
+                    config_dir: xdg("XDG_CONFIG_HOME", &[".config"])?.join(APPLICATION),
+                    data_dir: xdg("XDG_DATA_HOME", &[".local", "share"])?.join(APPLICATION),
+                    log_dir: xdg("XDG_STATE_HOME", &[".local", "state"])?.join(APPLICATION),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn dirs(os: &str, vars: &[(&str, &str)]) -> Option<AppDirs> {
+        AppDirs::for_os(os, |name| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| OsString::from(v)))
+    }
+
+    #[test]
+    fn test_linux_follows_xdg() {
+        let home = dirs("linux", &[("HOME", "/home/ada")]).unwrap();
+        assert_eq!(home.config_dir, Path::new("/home/ada/.config/voiceflow"));
+        assert_eq!(home.data_dir, Path::new("/home/ada/.local/share/voiceflow"));
+        assert_eq!(home.log_dir, Path::new("/home/ada/.local/state/voiceflow"));
+
+        let xdg = dirs(
+            "linux",
+            &[("HOME", "/home/ada"), ("XDG_CONFIG_HOME", "/cfg"), ("XDG_DATA_HOME", "relative/data")],
+        )
+        .unwrap();
+        assert_eq!(xdg.config_dir, Path::new("/cfg/voiceflow"));
+        assert_eq!(xdg.data_dir, Path::new("/home/ada/.local/share/voiceflow"));
+
+        assert_eq!(dirs("linux", &[]), None);
+        assert_eq!(dirs("freebsd", &[("HOME", "/home/ada")]), Some(home));
+    }
+
+    #[test]
+    fn test_macos_uses_application_support() {
+        let dirs = dirs("macos", &[("HOME", "/Users/ada")]).unwrap();
+        let support = Path::new("/Users/ada/Library/Application Support/com.era-laboratories.voiceflow");
+        assert_eq!(dirs.config_dir, support);
+        assert_eq!(dirs.data_dir, support);
+        assert_eq!(dirs.log_dir, Path::new("/Users/ada/Library/Logs/com.era-laboratories.voiceflow"));
+    }
+
+    #[test]
+    fn test_windows_uses_appdata() {
+        let appdata = PathBuf::from("C:/Users/ada/AppData/Roaming");
+        let local = PathBuf::from("C:/Users/ada/AppData/Local");
+        let both = dirs(
+            "windows",
+            &[("APPDATA", "C:/Users/ada/AppData/Roaming"), ("LOCALAPPDATA", "C:/Users/ada/AppData/Local")],
+        )
+        .unwrap();
+        assert_eq!(both.config_dir, appdata.join("era-laboratories").join("voiceflow").join("config"));
+        assert_eq!(both.data_dir, appdata.join("era-laboratories").join("voiceflow").join("data"));
+        assert_eq!(both.log_dir, local.join("era-laboratories").join("voiceflow").join("logs"));
+
+        let roaming_only = dirs("windows", &[("APPDATA", "C:/Users/ada/AppData/Roaming")]).unwrap();
+        assert_eq!(roaming_only.log_dir, appdata.join("era-laboratories").join("voiceflow").join("logs"));
+
+        // HOME alone isn't enough on Windows
+        assert_eq!(dirs("windows", &[("HOME", "C:/Users/ada")]), None);
+    }
+}
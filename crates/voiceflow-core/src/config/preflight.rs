@@ -0,0 +1,295 @@
+//! Pre-flight check of a configuration: which of its models are on disk,
+//! what is left to download, the memory they need and the settings that
+//! can't work together, without loading anything
+//!
+//! Only file metadata is read (sizes against the checksum manifest, no
+//! hashing), so a check takes a few milliseconds.
+
+use super::{Config, FormatterBackend, LlmModel, SttEngine};
+use crate::downloads::{partial_path, DownloadableModel};
+use crate::integrity::verify_file;
+use crate::PipelineError;
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// State of a component's model files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelStatus {
+    /// Every file is present, at its recorded size
+    Downloaded,
+    /// A file is missing or only partly downloaded
+    Missing,
+    /// A file doesn't match its recorded size and has to be downloaded again
+    Corrupt,
+    /// Nothing to download: formatting runs on a remote server
+    Remote,
+}
+
+/// A model the configuration needs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentReport {
+    /// "stt", "llm" or "speaker"
+    pub component: &'static str,
+    /// Download id ("moonshine-base", "qwen3-1.7b"), the path of a custom
+    /// LLM or the model name on a remote server
+    pub id: String,
+    /// Display name
+    pub name: String,
+    pub status: ModelStatus,
+    /// Bytes left to download: partial downloads count as far as they got,
+    /// a corrupt file in full. Zero for a custom or remote model.
+    pub download_bytes: u64,
+    /// Estimated peak memory of the model while it runs (zero if unknown)
+    pub memory_bytes: u64,
+}
+
+/// Result of [`Config::preflight`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreflightReport {
+    /// Every model is downloaded and no setting is in the way
+    pub ready: bool,
+    /// The speech model, the formatting LLM and, with diarization, the
+    /// speaker model
+    pub components: Vec<ComponentReport>,
+    /// Bytes left to download for all the components
+    pub download_bytes: u64,
+    /// Estimated memory of all the models loaded together
+    pub memory_bytes: u64,
+    /// Invalid settings and settings that can't work together, as messages
+    pub problems: Vec<String>,
+    /// The models directory that was checked
+    pub models_dir: String,
+}
+
+impl Config {
+    /// Check what this configuration needs before a pipeline is created
+    /// from it: the state of each model, what's left to download, the
+    /// memory the models need and the settings that would fail to load
+    ///
+    /// No model is loaded, no directory created and no file hashed.
+    pub fn preflight(&self) -> PreflightReport {
+        let mut problems = Vec::new();
+        if let Err(e) = self.validate() {
+            problems.push(format!("{:#}", e));
+        }
+        #[cfg(not(feature = "diarization"))]
+        if self.diarization.enabled {
+            problems.push("Diarization is on, but this build has no diarization (the diarization feature)".to_string());
+        }
+        #[cfg(not(feature = "remote-formatter"))]
+        if let FormatterBackend::Remote { base_url, .. } = &self.formatter {
+            problems.push(format!(
+                "Formatting is set to run on {}, but this build has no remote formatting (the remote-formatter feature)",
+                base_url
+            ));
+        }
+
+        // `models_dir` would create the directory
+        let models_dir = match &self.models_dir_override {
+            Some(dir) => dir.clone(),
+            None => Self::default_models_dir().unwrap_or_else(|e| {
+                problems.push(format!("{:#}", e));
+                PathBuf::new()
+            }),
+        };
+
+        let stt = match self.stt_engine {
+            SttEngine::Whisper => DownloadableModel::Whisper(self.whisper_model.clone(), self.whisper_precision),
+            SttEngine::Moonshine => DownloadableModel::Moonshine(self.moonshine_model.clone(), self.moonshine_precision),
+        };
+        let mut components = vec![model_report("stt", &stt, &models_dir)];
+        components.push(match (&self.formatter, &self.llm_model) {
+            (FormatterBackend::Remote { model, .. }, _) => ComponentReport {
+                component: "llm",
+                id: model.clone(),
+                name: self.llm_display_name(),
+                status: ModelStatus::Remote,
+                download_bytes: 0,
+                memory_bytes: 0,
+            },
+            (FormatterBackend::Local, LlmModel::Custom(path)) => ComponentReport {
+                component: "llm",
+                id: path.clone(),
+                name: self.llm_display_name(),
+                status: if models_dir.join(path).exists() { ModelStatus::Downloaded } else { ModelStatus::Missing },
+                download_bytes: 0,
+                memory_bytes: 0,
+            },
+            (FormatterBackend::Local, model) => model_report("llm", &DownloadableModel::Llm(model.clone()), &models_dir),
+        });
+        if self.diarization.enabled {
+            let speaker = DownloadableModel::Speaker(self.diarization.model.clone());
+            components.push(model_report("speaker", &speaker, &models_dir));
+        }
+
+        let ready = problems.is_empty()
+            && components
+                .iter()
+                .all(|component| matches!(component.status, ModelStatus::Downloaded | ModelStatus::Remote));
+        PreflightReport {
+            ready,
+            download_bytes: components.iter().map(|component| component.download_bytes).sum(),
+            memory_bytes: components.iter().map(|component| component.memory_bytes).sum(),
+            components,
+            problems,
+            models_dir: models_dir.display().to_string(),
+        }
+    }
+
+    /// Read the configuration like `load` and check it with `preflight`
+    ///
+    /// Settings `load` would reject are reported as problems instead; only
+    /// a file that can't be read or parsed, or an environment override
+    /// that doesn't parse, is an error.
+    pub fn preflight_file(path: Option<&str>) -> Result<PreflightReport> {
+        let mut config = Self::read_file(path)?;
+        config.apply_env_overrides()?;
+        config.llm_options = config.llm_options.clamped();
+        Ok(config.preflight())
+    }
+}
+
+/// State of a downloadable model's files in `models_dir`
+fn model_report(component: &'static str, model: &DownloadableModel, models_dir: &Path) -> ComponentReport {
+    let mut status = ModelStatus::Downloaded;
+    let mut present = 0;
+    for file in model.files() {
+        let path = models_dir.join(&file.path);
+        match fs::metadata(&path) {
+            Ok(_) if is_corrupt(models_dir, &path) => status = ModelStatus::Corrupt,
+            Ok(metadata) => present += metadata.len(),
+            Err(_) => {
+                present += fs::metadata(partial_path(&path)).map_or(0, |metadata| metadata.len());
+                if status == ModelStatus::Downloaded {
+                    status = ModelStatus::Missing;
+                }
+            }
+        }
+    }
+
+    let download_bytes = match status {
+        ModelStatus::Downloaded => 0,
+        _ => model.download_bytes().saturating_sub(present),
+    };
+    ComponentReport {
+        component,
+        id: model.id(),
+        name: model.display_name().to_string(),
+        status,
+        download_bytes,
+        memory_bytes: model.memory_bytes(),
+    }
+}
+
+/// Check a file's size against the checksum manifest
+fn is_corrupt(models_dir: &Path, path: &Path) -> bool {
+    verify_file(models_dir, path, false).is_err_and(|e| {
+        matches!(e.downcast_ref::<PipelineError>(), Some(PipelineError::ModelCorrupted { .. }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Diarization, ModelPrecision, MoonshineModel};
+    use crate::integrity::{ChecksumManifest, FileChecksum};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voiceflow-preflight-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn moonshine_config(dir: &Path) -> Config {
+        Config {
+            stt_engine: SttEngine::Moonshine,
+            moonshine_model: MoonshineModel::Tiny,
+            llm_model: LlmModel::Qwen3_1_7B,
+            models_dir_override: Some(dir.to_path_buf()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_preflight_reports_each_component() {
+        let dir = temp_dir("components");
+        let stt = DownloadableModel::Moonshine(MoonshineModel::Tiny, ModelPrecision::Float32);
+        for file in stt.files() {
+            fs::create_dir_all(dir.join(&file.path).parent().unwrap()).unwrap();
+            fs::write(dir.join(&file.path), b"onnx").unwrap();
+        }
+        let llm = DownloadableModel::Llm(LlmModel::Qwen3_1_7B);
+        let llm_path = dir.join(&llm.files()[0].path);
+        fs::write(partial_path(&llm_path), vec![0u8; 1000]).unwrap();
+
+        let report = moonshine_config(&dir).preflight();
+
+        assert!(!report.ready);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+        assert_eq!(report.components.len(), 2);
+        assert_eq!(report.components[0].id, "moonshine-tiny");
+        assert_eq!(report.components[0].status, ModelStatus::Downloaded);
+        assert_eq!(report.components[0].download_bytes, 0);
+        assert_eq!(report.components[1].status, ModelStatus::Missing);
+        assert_eq!(report.components[1].download_bytes, llm.download_bytes() - 1000);
+        assert_eq!(report.download_bytes, llm.download_bytes() - 1000);
+        assert_eq!(report.memory_bytes, stt.memory_bytes() + llm.memory_bytes());
+
+        // Complete download, but not the size recorded for it
+        fs::remove_file(partial_path(&llm_path)).unwrap();
+        fs::write(&llm_path, b"gguf").unwrap();
+        let record = FileChecksum { size: 5, sha256: "00".repeat(32) };
+        ChecksumManifest::record(&dir, &llm.files()[0].path, record).unwrap();
+        let report = moonshine_config(&dir).preflight();
+        assert_eq!(report.components[1].status, ModelStatus::Corrupt);
+        assert_eq!(report.components[1].download_bytes, llm.download_bytes());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preflight_reports_conflicting_settings() {
+        let dir = temp_dir("conflicts");
+        let config = Config {
+            language: "de".to_string(),
+            diarization: Diarization { enabled: true, ..Diarization::default() },
+            ..moonshine_config(&dir)
+        };
+
+        let report = config.preflight();
+
+        assert!(!report.ready);
+        assert!(report.problems[0].contains("Moonshine"), "{:?}", report.problems);
+        assert_eq!(report.components.len(), 3);
+        assert_eq!(report.components[2].component, "speaker");
+        assert_eq!(report.components[2].status, ModelStatus::Missing);
+        // Checking doesn't create anything
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remote_formatter_needs_no_download() {
+        let dir = temp_dir("remote");
+        let config = Config {
+            formatter: FormatterBackend::Remote {
+                base_url: "http://localhost:11434".to_string(),
+                model: "qwen3:4b".to_string(),
+                api_key_env: None,
+                timeout_secs: 30,
+            },
+            ..moonshine_config(&dir)
+        };
+
+        let report = config.preflight();
+
+        assert_eq!(report.components[1].status, ModelStatus::Remote);
+        assert_eq!(report.components[1].id, "qwen3:4b");
+        assert_eq!(report.download_bytes, report.components[0].download_bytes);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
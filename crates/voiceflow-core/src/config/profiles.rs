@@ -0,0 +1,203 @@
+//! Named profiles: sets of settings stored in the `[profiles]` table of the
+//! config file and switched between as a whole
+//!
+//! A profile holds only the fields it changes, in the format of the config
+//! file, and is applied over the rest of the config:
+//!
+//! ```toml
+//! [profiles."meeting notes"]
+//! whisper_model = "small"
+//! default_preset = "notes"
+//! ```
+
+use super::{Config, ConfigError};
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+/// Fields a profile doesn't hold: file bookkeeping and where files go
+const NOT_IN_PROFILES: &[&str] = &["schema_version", "profiles", "log_file", "models_dir_override"];
+
+impl Config {
+    /// Load the configuration like `load`, with profile `name` applied over
+    /// the config file and under the environment overrides
+    pub fn load_profile(path: Option<&str>, name: &str) -> Result<Self> {
+        let mut config = Self::read_file(path)?.with_profile(name)?;
+        config.apply_env_overrides()?;
+        config.checked()
+    }
+
+    /// Names of the profiles, sorted
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.keys().map(String::as_str).collect()
+    }
+
+    /// This config with the fields of profile `name` applied
+    ///
+    /// Unknown fields are rejected like in `apply_json`; fields a profile
+    /// can't set are ignored with a warning.
+    pub fn with_profile(&self, name: &str) -> Result<Self> {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownProfile { name: name.to_string() })?;
+        let mut fields = serde_json::to_value(profile)?;
+        if let Some(fields) = fields.as_object_mut() {
+            for field in NOT_IN_PROFILES {
+                if fields.remove(*field).is_some() {
+                    tracing::warn!("Ignoring {} in profile {:?}: it can't be set by a profile", field, name);
+                }
+            }
+        }
+        self.overlaid(&fields, &format!("profiles.{}", name))
+            .with_context(|| format!("Invalid profile {:?}", name))
+    }
+
+    /// The settings of this config that differ from `base`, as a profile to
+    /// apply over `base`
+    ///
+    /// Lists that are empty here are stored empty. TOML has no null, so an
+    /// optional field unset here but set in `base` is left out with a
+    /// warning.
+    pub fn profile_from(&self, base: &Config) -> Result<toml::Table> {
+        let mut current = serde_json::to_value(self)?;
+        let mut base = serde_json::to_value(base)?;
+        for json in [&mut current, &mut base] {
+            if let Some(fields) = json.as_object_mut() {
+                for field in NOT_IN_PROFILES {
+                    fields.remove(*field);
+                }
+            }
+        }
+        Ok(serde_json::from_value(Value::Object(changed_fields(&current, &base, "")))?)
+    }
+
+    /// Store the settings of `current` that differ from this config as
+    /// profile `name`, replacing any profile of that name
+    pub fn save_profile(&mut self, name: &str, current: &Config) -> Result<()> {
+        if name.trim().is_empty() {
+            return Err(ConfigError::InvalidValue {
+                key: "profile".to_string(),
+                message: "the name must not be empty".to_string(),
+            }
+            .into());
+        }
+        let profile = current.profile_from(self)?;
+        self.profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+}
+
+/// The fields of `current` that differ from `base`, descending into objects
+/// present in both
+fn changed_fields(current: &Value, base: &Value, prefix: &str) -> Map<String, Value> {
+    let mut changed = Map::new();
+    let (Some(current), Some(base)) = (current.as_object(), base.as_object()) else {
+        return changed;
+    };
+    for (key, value) in current {
+        match (value, base.get(key)) {
+            (Value::Object(_), Some(base @ Value::Object(_))) => {
+                let inner = changed_fields(value, base, &format!("{}{}.", prefix, key));
+                if !inner.is_empty() {
+                    changed.insert(key.clone(), Value::Object(inner));
+                }
+            }
+            (value, Some(base)) if value == base => {}
+            (Value::Null, _) => {
+                tracing::warn!("A profile can't unset {}{}; leaving it out", prefix, key);
+            }
+            _ => {
+                changed.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    // Unset and empty fields aren't serialized
+    for (key, value) in base {
+        match value {
+            _ if current.contains_key(key) => {}
+            Value::Array(_) => {
+                changed.insert(key.clone(), Value::Array(Vec::new()));
+            }
+            _ => tracing::warn!("A profile can't unset {}{}; leaving it out", prefix, key),
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LlmModel, MoonshineModel, SttEngine, WhisperModel};
+    use crate::FormattingPreset;
+
+    const PROFILES: &str = r#"
+personal_dictionary = ["VoiceFlow"]
+
+[llm_options]
+temperature = 0.2
+
+[profiles."meeting notes"]
+whisper_model = "small"
+llm_model = "phi2"
+default_preset = "notes"
+
+[profiles."quick commands"]
+stt_engine = "moonshine"
+moonshine_model = "tiny"
+[profiles."quick commands".llm_options]
+max_tokens = 64
+"#;
+
+    #[test]
+    fn test_profile_is_applied_over_the_file() {
+        let base = crate::config::migrate::parse_config(PROFILES).unwrap();
+        assert_eq!(base.profile_names(), ["meeting notes", "quick commands"]);
+
+        let notes = base.with_profile("meeting notes").unwrap();
+        assert_eq!(notes.whisper_model, WhisperModel::Small);
+        assert_eq!(notes.llm_model, LlmModel::Phi2);
+        assert_eq!(notes.default_preset, Some(FormattingPreset::Notes));
+        assert_eq!(notes.personal_dictionary, ["VoiceFlow"]);
+
+        let commands = notes.with_profile("quick commands").unwrap();
+        assert_eq!(commands.stt_engine, SttEngine::Moonshine);
+        assert_eq!(commands.moonshine_model, MoonshineModel::Tiny);
+        assert_eq!(commands.llm_options.max_tokens, 64);
+        assert_eq!(commands.llm_options.temperature, 0.2);
+
+        let err = base.with_profile("dictation").unwrap_err();
+        assert!(matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::UnknownProfile { .. })), "{}", err);
+    }
+
+    #[test]
+    fn test_saved_profile_round_trips() {
+        let mut base = crate::config::migrate::parse_config(PROFILES).unwrap();
+        let mut current = base.with_profile("meeting notes").unwrap();
+        current.llm_options.temperature = 0.5;
+        current.personal_dictionary.clear();
+        current.log_file = Some("voiceflow.log".into());
+
+        base.save_profile("notes v2", &current).unwrap();
+        let profile = &base.profiles["notes v2"];
+        let mut fields: Vec<_> = profile.keys().map(String::as_str).collect();
+        fields.sort();
+        assert_eq!(fields, ["default_preset", "llm_model", "llm_options", "personal_dictionary", "whisper_model"]);
+
+        let saved = toml::to_string_pretty(&base).unwrap();
+        let reloaded = crate::config::migrate::parse_config(&saved).unwrap();
+        let applied = reloaded.with_profile("notes v2").unwrap();
+        assert_eq!(applied.llm_options.temperature, 0.5);
+        assert!(applied.personal_dictionary.is_empty());
+        assert_eq!(applied.llm_model, LlmModel::Phi2);
+        assert_eq!(applied.log_file, None);
+
+        assert!(base.save_profile(" ", &current).is_err());
+    }
+
+    #[test]
+    fn test_unknown_profile_field_is_rejected() {
+        let base = crate::config::migrate::parse_config("[profiles.typo]\nwhisper_modle = \"small\"\n").unwrap();
+        let err = base.with_profile("typo").unwrap_err();
+        assert!(format!("{:#}", err).contains("whisper_modle"), "{:#}", err);
+    }
+}
@@ -0,0 +1,228 @@
+//! Where a dictation goes: the app, the kind of document, the recipient and
+//! the text around the cursor
+//!
+//! A request's context is either a JSON object with these fields or, as
+//! before they existed, a plain string such as "email", which is taken as
+//! `user_instructions`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Fields of a structured context; prompt templates can use each as a
+/// placeholder
+pub const CONTEXT_FIELDS: [&str; 6] = [
+    "app_name",
+    "document_kind",
+    "recipient",
+    "text_before_cursor",
+    "text_after_cursor",
+    "user_instructions",
+];
+
+/// Most characters of the text on either side of the cursor given to the
+/// LLM, those nearest the cursor
+const MAX_SURROUNDING_CHARS: usize = 500;
+
+/// Structured context of a request
+///
+/// Every field is optional; empty strings count as unset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DictationContext {
+    /// App the text is typed into, e.g. "Mail"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    /// Kind of document, e.g. "email" or "code"; picks the prompt as a
+    /// context name does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_kind: Option<String>,
+    /// Who the text is addressed to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+    /// Text before the insertion point (its end is enough)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_before_cursor: Option<String>,
+    /// Text after the insertion point (its start is enough)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_after_cursor: Option<String>,
+    /// Anything else the formatter should follow, e.g. "British spelling"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_instructions: Option<String>,
+}
+
+impl DictationContext {
+    /// Read a request's context: a JSON object, or a plain string taken as
+    /// `user_instructions`
+    ///
+    /// Unknown fields are ignored with a warning, so contexts written for a
+    /// newer version still work.
+    pub fn parse(context: Option<&str>) -> Result<Self, serde_json::Error> {
+        let Some(context) = context.map(str::trim).filter(|c| !c.is_empty()) else {
+            return Ok(Self::default());
+        };
+        if !context.starts_with('{') {
+            return Ok(Self { user_instructions: Some(context.to_string()), ..Self::default() });
+        }
+
+        let fields: Map<String, Value> = serde_json::from_str(context)?;
+        let unknown: Vec<&str> =
+            fields.keys().map(String::as_str).filter(|key| !CONTEXT_FIELDS.contains(key)).collect();
+        if !unknown.is_empty() {
+            tracing::warn!("Ignoring unknown context fields: {}", unknown.join(", "));
+        }
+        serde_json::from_value(Value::Object(fields))
+    }
+
+    /// The context name, which picks the prompt and fills its `{context}`:
+    /// `document_kind`, or else `user_instructions` as a plain-string
+    /// context always did
+    pub fn kind(&self) -> Option<&str> {
+        non_empty(&self.document_kind).or_else(|| non_empty(&self.user_instructions))
+    }
+
+    /// The text before and after the cursor, when either is given; the
+    /// other is taken as empty
+    pub fn cursor(&self) -> Option<(&str, &str)> {
+        if self.text_before_cursor.is_none() && self.text_after_cursor.is_none() {
+            return None;
+        }
+        Some((self.text_before_cursor.as_deref().unwrap_or(""), self.text_after_cursor.as_deref().unwrap_or("")))
+    }
+
+    /// Value of a field, as it's given to the LLM: the text around the
+    /// cursor is cut to the part nearest it
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "app_name" => non_empty(&self.app_name).map(str::to_string),
+            "document_kind" => non_empty(&self.document_kind).map(str::to_string),
+            "recipient" => non_empty(&self.recipient).map(str::to_string),
+            "text_before_cursor" => non_empty(&self.text_before_cursor).map(|text| {
+                let skip = text.chars().count().saturating_sub(MAX_SURROUNDING_CHARS);
+                text.chars().skip(skip).collect()
+            }),
+            "text_after_cursor" => {
+                non_empty(&self.text_after_cursor).map(|text| text.chars().take(MAX_SURROUNDING_CHARS).collect())
+            }
+            "user_instructions" => non_empty(&self.user_instructions).map(str::to_string),
+            _ => None,
+        }
+    }
+
+    /// `template` with its field placeholders filled in, unset fields with
+    /// nothing
+    pub(crate) fn fill_placeholders(&self, template: &str) -> String {
+        let mut filled = template.to_string();
+        for name in CONTEXT_FIELDS {
+            let placeholder = format!("{{{}}}", name);
+            if filled.contains(&placeholder) {
+                filled = filled.replace(&placeholder, &self.field(name).unwrap_or_default());
+            }
+        }
+        filled
+    }
+
+    /// Whether `template` places any of the fields itself
+    pub(crate) fn placed_in(template: &str) -> bool {
+        CONTEXT_FIELDS.iter().any(|name| template.contains(&format!("{{{}}}", name)))
+    }
+
+    /// The fields for a prompt that doesn't place them, appended to it like
+    /// prosody hints (empty when there are none)
+    ///
+    /// `user_instructions` is left out when it's the context name, which
+    /// the prompt already has.
+    pub(crate) fn to_llm_context(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(app_name) = self.field("app_name") {
+            lines.push(format!("The text is typed into {}.", app_name));
+        }
+        if let Some(recipient) = self.field("recipient") {
+            lines.push(format!("It is addressed to {}.", recipient));
+        }
+        if non_empty(&self.document_kind).is_some() {
+            if let Some(instructions) = self.field("user_instructions") {
+                lines.push(format!("Follow these instructions: {}.", instructions.trim_end_matches('.')));
+            }
+        }
+        if let Some(before) = self.field("text_before_cursor") {
+            lines.push(format!(
+                "It is inserted after this text, and must read on from it: \"{}\"",
+                before
+            ));
+        }
+        if let Some(after) = self.field("text_after_cursor") {
+            lines.push(format!("It is followed by this text: \"{}\"", after));
+        }
+
+        if lines.is_empty() {
+            String::new()
+        } else {
+            format!("\n[Context: {}]", lines.join(" "))
+        }
+    }
+}
+
+fn non_empty(field: &Option<String>) -> Option<&str> {
+    field.as_deref().filter(|value| !value.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_string_is_user_instructions() {
+        let context = DictationContext::parse(Some("email")).unwrap();
+        assert_eq!(context.user_instructions.as_deref(), Some("email"));
+        assert_eq!(context.kind(), Some("email"));
+        assert_eq!(context.to_llm_context(), "");
+
+        assert_eq!(DictationContext::parse(None).unwrap(), DictationContext::default());
+        assert_eq!(DictationContext::parse(Some("  ")).unwrap(), DictationContext::default());
+    }
+
+    #[test]
+    fn test_json_fields_and_unknown_ones() {
+        let context = DictationContext::parse(Some(
+            r#"{"app_name": "Mail", "document_kind": "email", "recipient": "Sam", "mood": "cheerful"}"#,
+        ))
+        .unwrap();
+        assert_eq!(context.app_name.as_deref(), Some("Mail"));
+        assert_eq!(context.recipient.as_deref(), Some("Sam"));
+        assert_eq!(context.kind(), Some("email"));
+
+        assert!(DictationContext::parse(Some(r#"{"app_name": 3}"#)).is_err());
+        assert!(DictationContext::parse(Some("{not json")).is_err());
+    }
+
+    #[test]
+    fn test_placeholders_are_filled() {
+        let context = DictationContext {
+            recipient: Some("Sam".to_string()),
+            text_before_cursor: Some("x".repeat(MAX_SURROUNDING_CHARS) + "Dear Sam,"),
+            ..DictationContext::default()
+        };
+        let template = "To {recipient} in {app_name}, after \"{text_before_cursor}\":\n{transcript}";
+        assert!(DictationContext::placed_in(template));
+        let filled = context.fill_placeholders(template);
+        assert!(filled.starts_with("To Sam in , after \"xxx"));
+        assert!(filled.ends_with("Dear Sam,\":\n{transcript}"));
+        assert_eq!(filled.matches('x').count(), MAX_SURROUNDING_CHARS - "Dear Sam,".len());
+    }
+
+    #[test]
+    fn test_llm_context_lists_the_fields() {
+        let context = DictationContext {
+            app_name: Some("Slack".to_string()),
+            document_kind: Some("slack".to_string()),
+            text_before_cursor: Some("See you at".to_string()),
+            user_instructions: Some("No emoji".to_string()),
+            ..DictationContext::default()
+        };
+        assert_eq!(
+            context.to_llm_context(),
+            "\n[Context: The text is typed into Slack. Follow these instructions: No emoji. \
+             It is inserted after this text, and must read on from it: \"See you at\"]"
+        );
+    }
+}
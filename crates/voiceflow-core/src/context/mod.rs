@@ -1,5 +1,8 @@
-//! Context detection (active app, etc.)
+//! Context detection (active app, etc.) and the structured context of a
+//! request
 
 mod detector;
+mod dictation;
 
 pub use detector::detect_active_app;
+pub use dictation::{DictationContext, CONTEXT_FIELDS};
@@ -0,0 +1,59 @@
+//! Speaker embeddings from a WeSpeaker ONNX model
+
+use super::fbank::{fbank, MEL_BINS};
+use super::SpeakerEmbedder;
+use crate::config::{thread_count, Config};
+use crate::integrity::verify_file;
+use crate::PipelineError;
+use anyhow::{Context, Result};
+use ort::{
+    session::{builder::GraphOptimizationLevel, Session},
+    value::TensorRef,
+};
+
+/// Speaker-embedding model run with ONNX Runtime on the CPU
+pub struct OnnxSpeakerEmbedder {
+    session: Session,
+}
+
+impl OnnxSpeakerEmbedder {
+    /// Load the speaker model selected by `config.diarization.model`
+    pub fn new(config: &Config) -> Result<Self> {
+        let path = config.speaker_model_path()?;
+        if !path.exists() {
+            return Err(PipelineError::SpeakerModelNotFound {
+                path: path.display().to_string(),
+            }
+            .into());
+        }
+        verify_file(&config.models_dir()?, &path, config.verify_models)?;
+
+        tracing::info!("Loading speaker model from {:?}", path);
+        let threads = thread_count(config.stt_threads);
+        let build = || -> ort::Result<Session> {
+            Session::builder()?
+                .with_optimization_level(GraphOptimizationLevel::Level3)?
+                .with_intra_threads(threads)?
+                .commit_from_file(&path)
+        };
+        let session = build().map_err(|e| PipelineError::OnnxLoadFailed {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+
+        Ok(Self { session })
+    }
+}
+
+impl SpeakerEmbedder for OnnxSpeakerEmbedder {
+    fn embed(&mut self, audio: &[f32]) -> Result<Vec<f32>> {
+        let features: Vec<f32> = fbank(audio).into_iter().flatten().collect();
+        let frames = features.len() / MEL_BINS;
+        let input = TensorRef::from_array_view(([1usize, frames, MEL_BINS], features.as_slice()))?;
+
+        let outputs = self.session.run(ort::inputs!["feats" => input])?;
+        let (_, output) = outputs.iter().next().context("Speaker model produced no output")?;
+        let (_, embedding) = output.try_extract_tensor::<f32>()?;
+        Ok(embedding.to_vec())
+    }
+}
@@ -0,0 +1,163 @@
+//! Kaldi-style log mel filterbank features, the input WeSpeaker models are
+//! trained on
+
+use std::f32::consts::PI;
+
+/// Frame length (25ms at 16kHz)
+const FRAME_SAMPLES: usize = 400;
+
+/// Frame shift (10ms at 16kHz)
+const HOP_SAMPLES: usize = 160;
+
+/// FFT size: the frame length rounded up to a power of two
+const FFT_SIZE: usize = 512;
+
+/// Mel bins per frame
+pub const MEL_BINS: usize = 80;
+
+const PREEMPHASIS: f32 = 0.97;
+
+/// Log mel filterbank of 16kHz mono samples: one row of `MEL_BINS` per
+/// 10ms frame, with the mean of each bin over the whole input subtracted
+///
+/// Empty when the input is shorter than one frame.
+pub fn fbank(audio: &[f32]) -> Vec<[f32; MEL_BINS]> {
+    if audio.len() < FRAME_SAMPLES {
+        return Vec::new();
+    }
+    let window = povey_window();
+    let filters = mel_filters();
+    let frame_count = 1 + (audio.len() - FRAME_SAMPLES) / HOP_SAMPLES;
+
+    let mut features = Vec::with_capacity(frame_count);
+    let mut re = vec![0.0f32; FFT_SIZE];
+    let mut im = vec![0.0f32; FFT_SIZE];
+    for frame in 0..frame_count {
+        // Kaldi works on 16-bit sample values
+        let samples = &audio[frame * HOP_SAMPLES..frame * HOP_SAMPLES + FRAME_SAMPLES];
+        let mean = samples.iter().sum::<f32>() / FRAME_SAMPLES as f32;
+        re.fill(0.0);
+        im.fill(0.0);
+        for (i, &sample) in samples.iter().enumerate() {
+            let previous = if i > 0 { samples[i - 1] } else { sample };
+            let emphasized = (sample - mean) - PREEMPHASIS * (previous - mean);
+            re[i] = emphasized * 32768.0 * window[i];
+        }
+        fft(&mut re, &mut im);
+
+        let power: Vec<f32> = (0..=FFT_SIZE / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect();
+        let mut row = [0.0f32; MEL_BINS];
+        for (bin, filter) in filters.iter().enumerate() {
+            let energy: f32 = filter.iter().map(|&(k, weight)| power[k] * weight).sum();
+            row[bin] = energy.max(f32::EPSILON).ln();
+        }
+        features.push(row);
+    }
+
+    for bin in 0..MEL_BINS {
+        let mean = features.iter().map(|row| row[bin]).sum::<f32>() / features.len() as f32;
+        for row in &mut features {
+            row[bin] -= mean;
+        }
+    }
+    features
+}
+
+/// Kaldi's "povey" window: a Hann window raised to the power 0.85
+fn povey_window() -> Vec<f32> {
+    (0..FRAME_SAMPLES)
+        .map(|i| (0.5 - 0.5 * (2.0 * PI * i as f32 / (FRAME_SAMPLES - 1) as f32).cos()).powf(0.85))
+        .collect()
+}
+
+fn mel(hz: f32) -> f32 {
+    1127.0 * (1.0 + hz / 700.0).ln()
+}
+
+/// Triangular filters evenly spaced on the mel scale from 20Hz to 8kHz, as
+/// (FFT bin, weight) pairs
+fn mel_filters() -> Vec<Vec<(usize, f32)>> {
+    let (low, high) = (mel(20.0), mel(8000.0));
+    let step = (high - low) / (MEL_BINS + 1) as f32;
+    let bin_hz = 16000.0 / FFT_SIZE as f32;
+
+    (0..MEL_BINS)
+        .map(|bin| {
+            let (left, center, right) =
+                (low + bin as f32 * step, low + (bin + 1) as f32 * step, low + (bin + 2) as f32 * step);
+            (0..FFT_SIZE / 2)
+                .filter_map(|k| {
+                    let m = mel(k as f32 * bin_hz);
+                    let weight = if m > left && m <= center {
+                        (m - left) / (center - left)
+                    } else if m > center && m < right {
+                        (right - m) / (right - center)
+                    } else {
+                        return None;
+                    };
+                    Some((k, weight))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// In-place radix-2 FFT; `re` and `im` have a power-of-two length
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tre = re[b] * cos - im[b] * sin;
+                let tim = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tre;
+                im[b] = im[a] - tim;
+                re[a] += tre;
+                im[a] += tim;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fbank_frame_count() {
+        assert!(fbank(&[0.0; 399]).is_empty());
+        assert_eq!(fbank(&[0.0; 400]).len(), 1);
+        assert_eq!(fbank(&vec![0.0; 16000]).len(), 98);
+    }
+
+    #[test]
+    fn test_tone_peaks_in_its_mel_bin() {
+        let tone: Vec<f32> = (0..16000).map(|i| (2.0 * PI * 1000.0 * i as f32 / 16000.0).sin() * 0.3).collect();
+        let mixed: Vec<f32> = tone.iter().enumerate().map(|(i, s)| if i < 8000 { *s } else { 0.0 }).collect();
+        let features = fbank(&mixed);
+        // Frames with the tone are above the mean at 1kHz, silent ones below
+        let bin = (mel(1000.0) - mel(20.0)) / ((mel(8000.0) - mel(20.0)) / (MEL_BINS + 1) as f32);
+        let bin = bin.round() as usize - 1;
+        assert!(features[10][bin] > 0.0);
+        assert!(features[90][bin] < 0.0);
+    }
+}
@@ -0,0 +1,352 @@
+//! Speaker diarization: who spoke when, for recordings of two or three
+//! people
+//!
+//! A voice embedding is taken from every 1.5s window of the speech, the
+//! windows are clustered by cosine similarity (agglomerative, average
+//! linkage), and each word is given the speaker of the window nearest to
+//! it. Runs of a single window are merged into the speaker around them, so
+//! a cough or a change of tone doesn't read as a new speaker.
+
+#[cfg(feature = "diarization")]
+mod embedding;
+#[cfg(feature = "diarization")]
+mod fbank;
+
+#[cfg(feature = "diarization")]
+pub use embedding::OnnxSpeakerEmbedder;
+
+use crate::audio::rms;
+use crate::config::{Config, Diarization};
+use crate::segment::Segment;
+use crate::transcribe::WordTimestamp;
+use anyhow::Result;
+use std::ops::Range;
+
+/// Samples per millisecond at 16kHz
+const SAMPLES_PER_MS: usize = 16;
+
+/// Length of the audio each embedding is taken from
+const WINDOW_MS: usize = 1500;
+
+/// Step between embedding windows
+const HOP_MS: usize = 750;
+
+/// Shortest speech given an embedding of its own
+const MIN_WINDOW_MS: usize = 500;
+
+/// Windows quieter than this (RMS) hold no voice to embed
+const MIN_WINDOW_RMS: f32 = 0.005;
+
+/// Speaker runs shorter than this many windows are merged into the
+/// speaker around them
+const MIN_TURN_WINDOWS: usize = 2;
+
+/// Produces a voice embedding: a vector that is close (by cosine
+/// similarity) for the same voice and far for different ones
+///
+/// `OnnxSpeakerEmbedder` with the `diarization` feature, or one supplied to
+/// `PipelineBuilder::speaker_embedder`.
+pub trait SpeakerEmbedder: Send {
+    /// Embed the voice in 16kHz mono samples
+    fn embed(&mut self, audio: &[f32]) -> Result<Vec<f32>>;
+}
+
+/// Load the speaker-embedding model the config selects
+pub fn load_embedder(config: &Config) -> Result<Box<dyn SpeakerEmbedder>> {
+    #[cfg(feature = "diarization")]
+    {
+        Ok(Box::new(OnnxSpeakerEmbedder::new(config)?))
+    }
+    #[cfg(not(feature = "diarization"))]
+    {
+        let _ = config;
+        Err(crate::PipelineError::DiarizationUnavailable.into())
+    }
+}
+
+/// Label each word of a transcript of 16kHz `audio` with its speaker
+/// (0-based, in order of first appearance)
+///
+/// Word timestamps are in milliseconds from the start of `audio`. Returns
+/// one label per word; all zeros when there is too little speech to tell
+/// voices apart.
+pub fn label_words(
+    embedder: &mut dyn SpeakerEmbedder,
+    audio: &[f32],
+    words: &[WordTimestamp],
+    settings: &Diarization,
+) -> Result<Vec<u8>> {
+    let (Some(first), Some(last)) = (words.first(), words.last()) else {
+        return Ok(Vec::new());
+    };
+    let start = (first.start_ms.max(0) as usize * SAMPLES_PER_MS).min(audio.len());
+    let end = (last.end_ms.max(0) as usize * SAMPLES_PER_MS).clamp(start, audio.len());
+
+    let windows: Vec<Range<usize>> = embedding_windows(start..end)
+        .into_iter()
+        .filter(|window| rms(&audio[window.clone()]) >= MIN_WINDOW_RMS)
+        .collect();
+    if windows.len() < 2 {
+        return Ok(vec![0; words.len()]);
+    }
+    let embeddings = windows
+        .iter()
+        .map(|window| embedder.embed(&audio[window.clone()]))
+        .collect::<Result<Vec<_>>>()?;
+    let labels = cluster_speakers(&embeddings, settings.max_speakers as usize, settings.similarity_threshold);
+
+    let centers: Vec<i64> = windows
+        .iter()
+        .map(|window| ((window.start + window.end) / 2 / SAMPLES_PER_MS) as i64)
+        .collect();
+    Ok(words
+        .iter()
+        .map(|word| {
+            let middle = (word.start_ms + word.end_ms) / 2;
+            let nearest = (0..centers.len()).min_by_key(|&i| (centers[i] - middle).abs()).unwrap_or(0);
+            labels[nearest]
+        })
+        .collect())
+}
+
+/// Windows of `WINDOW_MS` stepping by `HOP_MS` over `span`, the last one
+/// ending with it; one window for a span shorter than a window, none for
+/// one shorter than `MIN_WINDOW_MS`
+fn embedding_windows(span: Range<usize>) -> Vec<Range<usize>> {
+    let (window, hop) = (WINDOW_MS * SAMPLES_PER_MS, HOP_MS * SAMPLES_PER_MS);
+    if span.len() < MIN_WINDOW_MS * SAMPLES_PER_MS {
+        return Vec::new();
+    }
+    if span.len() <= window {
+        return vec![span];
+    }
+    let mut windows: Vec<Range<usize>> =
+        (span.start..=span.end - window).step_by(hop).map(|start| start..start + window).collect();
+    if windows.last().is_some_and(|last| last.end < span.end) {
+        windows.push(span.end - window..span.end);
+    }
+    windows
+}
+
+/// Group embeddings into at most `max_speakers` speakers, returning the
+/// speaker of each (0-based, in order of first appearance)
+///
+/// The two most similar groups are merged until no two groups are at least
+/// `threshold` similar and there are no more than `max_speakers`. Runs
+/// shorter than `MIN_TURN_WINDOWS` then take the speaker before them.
+pub fn cluster_speakers(embeddings: &[Vec<f32>], max_speakers: usize, threshold: f32) -> Vec<u8> {
+    let mut clusters: Vec<(Vec<usize>, Vec<f32>)> = embeddings
+        .iter()
+        .enumerate()
+        .map(|(i, embedding)| (vec![i], normalized(embedding)))
+        .collect();
+
+    while clusters.len() > 1 {
+        let mut best = (0, 1, f32::MIN);
+        for a in 0..clusters.len() {
+            for b in a + 1..clusters.len() {
+                let similarity = cosine(&clusters[a].1, &clusters[b].1);
+                if similarity > best.2 {
+                    best = (a, b, similarity);
+                }
+            }
+        }
+        let (a, b, similarity) = best;
+        if similarity < threshold && clusters.len() <= max_speakers.max(1) {
+            break;
+        }
+        // Clusters keep the sum of their normalized members, which points
+        // the same way as their mean
+        let (members, sum) = clusters.remove(b);
+        let merged = &mut clusters[a];
+        merged.0.extend(members);
+        for (x, y) in merged.1.iter_mut().zip(&sum) {
+            *x += y;
+        }
+    }
+
+    // Speakers numbered in order of first appearance
+    clusters.sort_by_key(|(members, _)| members.iter().copied().min().unwrap_or(0));
+    let mut labels = vec![0u8; embeddings.len()];
+    for (speaker, (members, _)) in clusters.iter().enumerate() {
+        for &member in members {
+            labels[member] = speaker as u8;
+        }
+    }
+    smooth(&mut labels);
+    renumber(&mut labels);
+    labels
+}
+
+/// Give runs shorter than `MIN_TURN_WINDOWS` the speaker before them (or
+/// after them, at the start)
+fn smooth(labels: &mut [u8]) {
+    let mut runs: Vec<(u8, Range<usize>)> = Vec::new();
+    for (i, &label) in labels.iter().enumerate() {
+        match runs.last_mut() {
+            Some((current, run)) if *current == label => run.end = i + 1,
+            _ => runs.push((label, i..i + 1)),
+        }
+    }
+    for i in 0..runs.len() {
+        if runs.len() < 2 || runs[i].1.len() >= MIN_TURN_WINDOWS {
+            continue;
+        }
+        let replacement = if i > 0 { runs[i - 1].0 } else { runs[1].0 };
+        runs[i].0 = replacement;
+        for label in &mut labels[runs[i].1.clone()] {
+            *label = replacement;
+        }
+    }
+}
+
+/// Number speakers in order of first appearance again, after smoothing may
+/// have removed one
+fn renumber(labels: &mut [u8]) {
+    let mut seen: Vec<u8> = Vec::new();
+    for label in labels.iter_mut() {
+        let index = match seen.iter().position(|speaker| speaker == label) {
+            Some(index) => index,
+            None => {
+                seen.push(*label);
+                seen.len() - 1
+            }
+        };
+        *label = index as u8;
+    }
+}
+
+/// Number of different speakers in `segments`
+pub fn speaker_count(segments: &[Segment]) -> usize {
+    let mut speakers: Vec<u8> = segments.iter().filter_map(|segment| segment.speaker).collect();
+    speakers.sort_unstable();
+    speakers.dedup();
+    speakers.len()
+}
+
+fn normalized(embedding: &[f32]) -> Vec<f32> {
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return embedding.to_vec();
+    }
+    embedding.iter().map(|x| x / norm).collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Embeddings of one voice: a fixed direction plus per-window noise, as
+    /// a real embedder gives for one person changing pitch and volume
+    fn one_speaker_fixture(windows: usize) -> Vec<Vec<f32>> {
+        voice_fixture(&[(0, windows)])
+    }
+
+    /// Embeddings for runs of (speaker, windows), each speaker a different
+    /// direction, with the same noise as `one_speaker_fixture`
+    fn voice_fixture(runs: &[(usize, usize)]) -> Vec<Vec<f32>> {
+        // Fixed LCG so the fixture is identical on every run
+        let mut state = 0x2545_f491u32;
+        let mut noise = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+        };
+        runs.iter()
+            .flat_map(|&(speaker, windows)| std::iter::repeat_n(speaker, windows))
+            .map(|speaker| {
+                (0..32)
+                    .map(|dim| {
+                        let voice = if dim % 3 == speaker { 1.0 } else { 0.0 };
+                        voice + noise() * 0.6
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn word(text: &str, start_ms: i64, end_ms: i64) -> WordTimestamp {
+        WordTimestamp { word: text.to_string(), start_ms, end_ms, probability: 0.9 }
+    }
+
+    #[test]
+    fn test_single_speaker_never_switches() {
+        for windows in [2, 5, 20, 80] {
+            let labels = cluster_speakers(&one_speaker_fixture(windows), 2, 0.5);
+            assert!(labels.iter().all(|&label| label == 0), "{} windows: {:?}", windows, labels);
+        }
+    }
+
+    #[test]
+    fn test_two_speakers_are_told_apart() {
+        let embeddings = voice_fixture(&[(0, 6), (1, 5), (0, 4)]);
+        let labels = cluster_speakers(&embeddings, 2, 0.5);
+        assert_eq!(labels, [[0; 6].as_slice(), &[1; 5], &[0; 4]].concat());
+
+        // A third voice joins the closest of two
+        let embeddings = voice_fixture(&[(0, 4), (1, 4), (2, 4)]);
+        assert_eq!(cluster_speakers(&embeddings, 2, 0.5).iter().max(), Some(&1));
+        assert_eq!(cluster_speakers(&embeddings, 3, 0.5).iter().max(), Some(&2));
+    }
+
+    #[test]
+    fn test_single_window_runs_are_smoothed() {
+        let mut labels = vec![0, 0, 0, 1, 0, 0, 1, 1, 1];
+        smooth(&mut labels);
+        assert_eq!(labels, [0, 0, 0, 0, 0, 0, 1, 1, 1]);
+
+        let mut labels = vec![1, 0, 0, 0];
+        smooth(&mut labels);
+        renumber(&mut labels);
+        assert_eq!(labels, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_embedding_windows_cover_the_span() {
+        assert!(embedding_windows(0..4000).is_empty());
+        let one = embedding_windows(0..16000);
+        assert_eq!(one.len(), 1);
+        assert_eq!(one[0], 0..16000);
+
+        let windows = embedding_windows(1000..60000);
+        assert_eq!(windows.first().unwrap().start, 1000);
+        assert_eq!(windows.last().unwrap().end, 60000);
+        assert!(windows.iter().all(|window| window.len() == WINDOW_MS * SAMPLES_PER_MS));
+        assert!(windows.windows(2).all(|pair| pair[1].start - pair[0].start <= HOP_MS * SAMPLES_PER_MS));
+    }
+
+    /// Embeds a window by its rate of zero crossings, so two tones are two
+    /// voices
+    struct PitchEmbedder;
+
+    impl SpeakerEmbedder for PitchEmbedder {
+        fn embed(&mut self, audio: &[f32]) -> Result<Vec<f32>> {
+            let crossings = audio.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count();
+            let high = crossings as f32 / audio.len() as f32 > 0.05;
+            Ok(if high { vec![0.0, 1.0] } else { vec![1.0, 0.0] })
+        }
+    }
+
+    #[test]
+    fn test_words_take_the_speaker_of_their_window() {
+        let audio: Vec<f32> = (0..96000)
+            .map(|i| (i as f32 * if i < 48000 { 0.1 } else { 0.5 }).sin() * 0.5)
+            .collect();
+        let words: Vec<WordTimestamp> = (0..6).map(|i| word("word", i * 1000, (i + 1) * 1000)).collect();
+        let labels = label_words(&mut PitchEmbedder, &audio, &words, &Diarization::default()).unwrap();
+        assert_eq!(labels, [0, 0, 0, 1, 1, 1]);
+
+        // Too little speech to compare voices
+        let labels = label_words(&mut PitchEmbedder, &audio, &words[..1], &Diarization::default()).unwrap();
+        assert_eq!(labels, [0]);
+    }
+}
@@ -0,0 +1,716 @@
+//! Model downloads: resumable HTTP downloads verified against SHA-256
+//! checksums
+//!
+//! Files are written to `<name>.part` next to their destination and renamed
+//! once complete and verified, so a model file that exists is never partial.
+//! A `.part` file left by an interrupted or killed download is resumed with
+//! an HTTP range request on the next attempt, or removed if it turns out to
+//! be corrupt. The size and digest of each verified file are recorded in
+//! the checksum manifest (see `integrity`) for checking at load time.
+
+use crate::cancel::CancelToken;
+use crate::config::{LlmModel, ModelPrecision, MoonshineModel, SpeakerModel, WhisperModel};
+use crate::integrity::{verify_file, ChecksumManifest, FileChecksum};
+use crate::PipelineError;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const HF_BASE_URL: &str = "https://huggingface.co";
+
+/// Suffix of files still being downloaded
+const PARTIAL_SUFFIX: &str = ".part";
+
+/// Read buffer size, and so the progress reporting granularity
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Download error with actionable context
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("Unknown model id: {id}")]
+    UnknownModel { id: String },
+
+    #[error("Download of {url} failed with HTTP status {status}")]
+    HttpStatus { url: String, status: u16 },
+
+    #[error("Download of {url} ended after {received} of {expected} bytes. Retry to resume")]
+    Incomplete { url: String, received: u64, expected: u64 },
+
+    #[error("Checksum mismatch for {file}: expected {expected}, got {actual}. The download was removed, retry to start over")]
+    ChecksumMismatch { file: String, expected: String, actual: String },
+
+    #[error("Download cancelled")]
+    Cancelled,
+}
+
+/// A model that can be downloaded into the models directory
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadableModel {
+    Whisper(WhisperModel, ModelPrecision),
+    Moonshine(MoonshineModel, ModelPrecision),
+    Llm(LlmModel),
+    /// Speaker embeddings for diarization
+    Speaker(SpeakerModel),
+}
+
+/// One file of a model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelFile {
+    pub url: String,
+    /// Destination, relative to the models directory
+    pub path: PathBuf,
+}
+
+impl DownloadableModel {
+    /// Look up a model by id: an LLM id ("qwen3-1.7b"), "whisper-<size>",
+    /// "moonshine-<size>" (with "-quantized" or "-int8" for those variants)
+    /// or a speaker model id ("wespeaker-resnet34")
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::all_models().into_iter().find(|model| model.id() == id)
+    }
+
+    /// Stable identifier used over FFI
+    pub fn id(&self) -> String {
+        let id = match self {
+            Self::Whisper(model, precision) => return precision.variant_id(&format!("whisper-{}", model.id())),
+            Self::Moonshine(model, precision) => return model.dir_name(*precision),
+            Self::Llm(LlmModel::Qwen3_1_7B) => "qwen3-1.7b",
+            Self::Llm(LlmModel::Qwen3_4B) => "qwen3-4b",
+            Self::Llm(LlmModel::SmolLM3_3B) => "smollm3-3b",
+            Self::Llm(LlmModel::Gemma2_2B) => "gemma2-2b",
+            Self::Llm(LlmModel::Phi2) => "phi-2",
+            Self::Llm(LlmModel::Custom(_)) => "custom",
+            Self::Speaker(SpeakerModel::WespeakerResnet34) => "wespeaker-resnet34",
+        };
+        id.to_string()
+    }
+
+    /// Get all downloadable models, every precision of each speech model
+    pub fn all_models() -> Vec<DownloadableModel> {
+        WhisperModel::all_models()
+            .into_iter()
+            .flat_map(|model| {
+                ModelPrecision::all().into_iter().map(move |precision| Self::Whisper(model.clone(), precision))
+            })
+            .chain(MoonshineModel::all_models().into_iter().flat_map(|model| {
+                ModelPrecision::all().into_iter().map(move |precision| Self::Moonshine(model.clone(), precision))
+            }))
+            .chain(LlmModel::all_models().into_iter().map(Self::Llm))
+            .chain(std::iter::once(Self::Llm(LlmModel::Phi2)))
+            .chain(SpeakerModel::all_models().into_iter().map(Self::Speaker))
+            .collect()
+    }
+
+    /// Files making up the model (none for a custom LLM)
+    pub fn files(&self) -> Vec<ModelFile> {
+        match self {
+            Self::Whisper(model, precision) => vec![ModelFile {
+                url: format!("{}/{}/resolve/main/{}", HF_BASE_URL, model.hf_repo(), model.filename(*precision)),
+                path: PathBuf::from(model.filename(*precision)),
+            }],
+            Self::Moonshine(model, precision) => model
+                .required_files()
+                .into_iter()
+                .map(|file| ModelFile {
+                    url: format!(
+                        "{}/{}/resolve/main/{}/{}",
+                        HF_BASE_URL,
+                        model.hf_repo(),
+                        model.onnx_path(*precision),
+                        file
+                    ),
+                    path: Path::new(&model.dir_name(*precision)).join(file),
+                })
+                .collect(),
+            Self::Llm(model) => model
+                .hf_repo()
+                .map(|repo| ModelFile {
+                    url: format!("{}/{}/resolve/main/{}", HF_BASE_URL, repo, model.filename()),
+                    path: PathBuf::from(model.filename()),
+                })
+                .into_iter()
+                .collect(),
+            Self::Speaker(model) => vec![ModelFile {
+                url: format!("{}/{}/resolve/main/{}", HF_BASE_URL, model.hf_repo(), model.hf_filename()),
+                path: PathBuf::from(model.filename()),
+            }],
+        }
+    }
+
+    /// Check if every file of the model is in `models_dir`
+    pub fn is_downloaded(&self, models_dir: &Path) -> bool {
+        let files = self.files();
+        !files.is_empty() && files.iter().all(|file| models_dir.join(&file.path).exists())
+    }
+
+    /// Rough peak memory needed to run the model, weights included, in bytes
+    ///
+    /// Whisper figures are whisper.cpp's, less the weights saved by a
+    /// quantized variant; Moonshine and LLMs add runtime and KV cache
+    /// overhead to the weights. Zero for a custom LLM (unknown).
+    pub fn memory_bytes(&self) -> u64 {
+        const MB: u64 = 1024 * 1024;
+        match self {
+            Self::Whisper(model, precision) => {
+                let full = match model {
+                    WhisperModel::Tiny => 273 * MB,
+                    WhisperModel::Base => 388 * MB,
+                    WhisperModel::Small => 852 * MB,
+                    WhisperModel::Medium => 2100 * MB,
+                    WhisperModel::LargeV3Turbo => 2000 * MB,
+                };
+                let saved = model.size_mb(ModelPrecision::Float32) - model.size_mb(*precision);
+                full - saved as u64 * MB
+            }
+            Self::Moonshine(model, precision) => model.size_mb(*precision) as u64 * MB * 3 / 2,
+            Self::Llm(LlmModel::Custom(_)) => 0,
+            Self::Llm(model) => (model.size_gb() as f64 * 1024.0) as u64 * MB + 512 * MB,
+            Self::Speaker(model) => model.size_mb() as u64 * MB * 2,
+        }
+    }
+
+    /// Approximate size of the model's files in bytes. Zero for a custom LLM
+    /// (unknown).
+    pub fn download_bytes(&self) -> u64 {
+        const MB: u64 = 1024 * 1024;
+        match self {
+            Self::Whisper(model, precision) => model.size_mb(*precision) as u64 * MB,
+            Self::Moonshine(model, precision) => model.size_mb(*precision) as u64 * MB,
+            Self::Llm(LlmModel::Custom(_)) => 0,
+            Self::Llm(model) => (model.size_gb() as f64 * 1024.0) as u64 * MB,
+            Self::Speaker(model) => model.size_mb() as u64 * MB,
+        }
+    }
+
+    /// Display name of the model
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::Whisper(model, _) => model.display_name(),
+            Self::Moonshine(model, _) => model.display_name(),
+            Self::Llm(model) => model.display_name(),
+            Self::Speaker(model) => model.display_name(),
+        }
+    }
+
+    fn is_speech(&self) -> bool {
+        matches!(self, Self::Whisper(..) | Self::Moonshine(..))
+    }
+
+    /// Precision of a speech model's weights (none for other models)
+    pub fn precision(&self) -> Option<ModelPrecision> {
+        match self {
+            Self::Whisper(_, precision) | Self::Moonshine(_, precision) => Some(*precision),
+            Self::Llm(_) | Self::Speaker(_) => None,
+        }
+    }
+}
+
+/// Models to suggest for a device, see [`recommended_models`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recommendation {
+    /// Speech-to-text model (Whisper or Moonshine)
+    pub stt: Option<DownloadableModel>,
+    /// Formatting LLM, sized to fit alongside `stt`
+    pub llm: Option<DownloadableModel>,
+}
+
+/// Downloadable models that each fit in `max_ram_bytes` (0 for no limit)
+pub fn models_within(max_ram_bytes: u64) -> Vec<DownloadableModel> {
+    DownloadableModel::all_models()
+        .into_iter()
+        .filter(|model| max_ram_bytes == 0 || model.memory_bytes() <= max_ram_bytes)
+        .collect()
+}
+
+/// Pick a speech and a formatting model that run together in
+/// `max_ram_bytes` (0 for no limit)
+///
+/// Speech gets up to a third of the budget (or the smallest model that fits
+/// at all), the LLM the largest of the rest. Legacy models aren't suggested.
+pub fn recommended_models(max_ram_bytes: u64) -> Recommendation {
+    let budget = if max_ram_bytes == 0 { u64::MAX } else { max_ram_bytes };
+    let candidates: Vec<_> = models_within(max_ram_bytes)
+        .into_iter()
+        .filter(|model| *model != DownloadableModel::Llm(LlmModel::Phi2))
+        .collect();
+    let speech = || candidates.iter().filter(|model| model.is_speech());
+
+    let stt = largest(speech().filter(|model| model.memory_bytes() <= budget / 3))
+        .or_else(|| speech().min_by_key(|model| model.memory_bytes()).cloned());
+    let remaining = budget - stt.as_ref().map_or(0, |model| model.memory_bytes());
+    let llm = largest(
+        candidates
+            .iter()
+            .filter(|model| matches!(model, DownloadableModel::Llm(_)) && model.memory_bytes() <= remaining),
+    );
+    Recommendation { stt, llm }
+}
+
+fn largest<'a>(models: impl Iterator<Item = &'a DownloadableModel>) -> Option<DownloadableModel> {
+    models.max_by_key(|model| model.memory_bytes()).cloned()
+}
+
+/// Size and checksum of a remote file, as published by the server
+#[derive(Debug, Default)]
+struct RemoteFile {
+    size: Option<u64>,
+    sha256: Option<String>,
+}
+
+/// Download every missing file of `model` into `models_dir`
+///
+/// `progress` is called with the bytes downloaded so far and the total (if
+/// the server reported every file size), counting files that were already
+/// present. A present file that fails its size check is downloaded again.
+/// Cancelling keeps the partial file so the next attempt resumes.
+pub fn download_model(
+    model: &DownloadableModel,
+    models_dir: &Path,
+    cancel: &CancelToken,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<()> {
+    let files = model.files();
+    if files.is_empty() {
+        return Err(DownloadError::UnknownModel { id: model.id() }.into());
+    }
+
+    let agent = agent();
+    let remotes = files
+        .iter()
+        .map(|file| probe(&agent, &file.url))
+        .collect::<Result<Vec<_>>>()?;
+    let total: Option<u64> = remotes.iter().map(|remote| remote.size).sum();
+
+    let mut done = 0;
+    for (file, remote) in files.iter().zip(remotes) {
+        let dest = models_dir.join(&file.path);
+        if dest.exists() {
+            match verify_file(models_dir, &dest, false) {
+                Ok(()) => {
+                    done += fs::metadata(&dest)?.len();
+                    progress(done, total);
+                    continue;
+                }
+                Err(e) if matches!(e.downcast_ref::<PipelineError>(), Some(PipelineError::ModelCorrupted { .. })) => {
+                    tracing::warn!("{}", e);
+                    fs::remove_file(&dest).with_context(|| format!("Failed to remove {:?}", dest))?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        tracing::info!("Downloading {} to {:?}", file.url, dest);
+        let base = done;
+        let checksum = download_file(
+            &agent,
+            &file.url,
+            &dest,
+            remote.sha256.as_deref(),
+            remote.size,
+            cancel,
+            |received| progress(base + received, total),
+        )?;
+        done += checksum.size;
+
+        // Only vouch for files the server's size or checksum confirmed
+        if remote.sha256.is_some() || remote.size.is_some() {
+            ChecksumManifest::record(models_dir, &file.path, checksum)?;
+        }
+    }
+
+    tracing::info!("Downloaded {}", model.id());
+    Ok(())
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(Duration::from_secs(60))
+        .build()
+}
+
+/// Look up a file's size and SHA-256 without downloading it
+///
+/// Hugging Face answers the un-followed redirect of a large (LFS) file with
+/// `X-Linked-Size` and `X-Linked-Etag`, the etag being the file's SHA-256.
+/// Other servers only give the size.
+fn probe(agent: &ureq::Agent, url: &str) -> Result<RemoteFile> {
+    let no_redirects = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .redirects(0)
+        .build();
+    let response = match no_redirects.head(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(DownloadError::HttpStatus { url: url.to_string(), status }.into());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to reach {}", url)),
+    };
+
+    let sha256 = response.header("x-linked-etag").and_then(sha256_from_etag);
+    let linked_size = response.header("x-linked-size").and_then(|size| size.parse().ok());
+    let size = match linked_size {
+        Some(size) => Some(size),
+        // Without linked headers, follow the redirect for the size
+        None if (300..400).contains(&response.status()) => {
+            agent.head(url).call().ok().and_then(|r| content_length(&r))
+        }
+        None => content_length(&response),
+    };
+
+    Ok(RemoteFile { size, sha256 })
+}
+
+fn content_length(response: &ureq::Response) -> Option<u64> {
+    response.header("content-length").and_then(|len| len.parse().ok())
+}
+
+/// The SHA-256 in an etag header, if it is one (git blob etags are SHA-1)
+fn sha256_from_etag(etag: &str) -> Option<String> {
+    let etag = etag.trim().trim_start_matches("W/").trim_matches('"');
+    (etag.len() == 64 && etag.chars().all(|c| c.is_ascii_hexdigit())).then(|| etag.to_ascii_lowercase())
+}
+
+/// Where a file is kept while downloading
+pub(crate) fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    dest.with_file_name(name)
+}
+
+/// Hash an existing partial download, returning its length
+fn hash_partial(partial: &Path, hasher: &mut Sha256) -> Result<u64> {
+    let mut file = match File::open(partial) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {:?}", partial)),
+    };
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut len = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(len);
+        }
+        hasher.update(&buf[..n]);
+        len += n as u64;
+    }
+}
+
+/// Download `url` to `dest` through a `.part` file, resuming a previous
+/// partial download, and verify it against `sha256` if given
+///
+/// Returns the file's size and digest. A file that fails verification is
+/// removed.
+fn download_file(
+    agent: &ureq::Agent,
+    url: &str,
+    dest: &Path,
+    sha256: Option<&str>,
+    size: Option<u64>,
+    cancel: &CancelToken,
+    mut progress: impl FnMut(u64),
+) -> Result<FileChecksum> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+    let partial = partial_path(dest);
+
+    let mut hasher = Sha256::new();
+    let mut received = hash_partial(&partial, &mut hasher)?;
+    if size.is_some_and(|size| received > size) {
+        tracing::warn!("Discarding oversized partial download {:?}", partial);
+        fs::remove_file(&partial)?;
+        hasher = Sha256::new();
+        received = 0;
+    }
+
+    let mut request = agent.get(url);
+    if received > 0 {
+        tracing::info!("Resuming {} at byte {}", url, received);
+        request = request.set("Range", &format!("bytes={}-", received));
+    }
+    let response = match request.call() {
+        Ok(response) => Some(response),
+        // Nothing left to fetch: the partial file is already complete
+        Err(ureq::Error::Status(416, _)) if received > 0 => None,
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(DownloadError::HttpStatus { url: url.to_string(), status }.into());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to reach {}", url)),
+    };
+
+    if let Some(response) = response {
+        // A server that ignores the range sends the whole file again
+        let resumed = response.status() == 206;
+        if !resumed && received > 0 {
+            tracing::info!("Server doesn't support resuming {}; starting over", url);
+            hasher = Sha256::new();
+            received = 0;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&partial)
+            .with_context(|| format!("Failed to open {:?}", partial))?;
+
+        let mut reader = response.into_reader();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        progress(received);
+        loop {
+            if cancel.is_cancelled() {
+                file.flush()?;
+                return Err(DownloadError::Cancelled.into());
+            }
+            let n = reader.read(&mut buf).with_context(|| format!("Download of {} was interrupted", url))?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            hasher.update(&buf[..n]);
+            received += n as u64;
+            progress(received);
+        }
+        file.sync_all()?;
+    }
+
+    if let Some(expected) = size.filter(|&expected| received < expected) {
+        return Err(DownloadError::Incomplete { url: url.to_string(), received, expected }.into());
+    }
+
+    let actual = format!("{:x}", hasher.finalize());
+    match sha256 {
+        Some(expected) if !expected.eq_ignore_ascii_case(&actual) => {
+            let _ = fs::remove_file(&partial);
+            return Err(DownloadError::ChecksumMismatch {
+                file: dest.display().to_string(),
+                expected: expected.to_string(),
+                actual,
+            }
+            .into());
+        }
+        Some(_) => {}
+        None => tracing::warn!("No checksum published for {}; not verified", url),
+    }
+
+    fs::rename(&partial, dest).with_context(|| format!("Failed to move download to {:?}", dest))?;
+    Ok(FileChecksum { size: received, sha256: actual })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    #[test]
+    fn test_models_within_budget() {
+        assert_eq!(models_within(0), DownloadableModel::all_models());
+        let small = models_within(1024 * 1024 * 1024);
+        assert!(small.contains(&DownloadableModel::Whisper(WhisperModel::Base, ModelPrecision::Float32)));
+        assert!(!small.contains(&DownloadableModel::Whisper(WhisperModel::Medium, ModelPrecision::Float32)));
+        // Quantized weights take less memory
+        let medium = |precision| DownloadableModel::Whisper(WhisperModel::Medium, precision).memory_bytes();
+        assert!(medium(ModelPrecision::Quantized) < medium(ModelPrecision::Float32));
+        assert!(small.iter().all(|model| model.memory_bytes() <= 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_recommended_models() {
+        const GB: u64 = 1024 * 1024 * 1024;
+
+        let phone = recommended_models(3 * GB);
+        let (stt, llm) = (phone.stt.unwrap(), phone.llm.unwrap());
+        assert!(stt.memory_bytes() <= GB);
+        assert!(stt.memory_bytes() + llm.memory_bytes() <= 3 * GB);
+        assert_eq!(llm, DownloadableModel::Llm(LlmModel::Qwen3_1_7B));
+
+        // Too small for any LLM: still transcribe
+        let tiny = recommended_models(GB / 2);
+        assert!(tiny.stt.is_some());
+        assert_eq!(tiny.llm, None);
+
+        assert_eq!(recommended_models(1), Recommendation { stt: None, llm: None });
+
+        let unlimited = recommended_models(0);
+        assert_eq!(unlimited.llm, Some(DownloadableModel::Llm(LlmModel::Qwen3_4B)));
+    }
+
+    /// Serve `body` for `requests` GETs, honouring `Range: bytes=N-`;
+    /// returns the URL and a handle yielding the range start of each request
+    fn serve(body: Vec<u8>, requests: usize) -> (String, JoinHandle<Vec<Option<u64>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/model.bin", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut ranges = Vec::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        range = value.trim().trim_end_matches('-').parse::<u64>().ok();
+                    }
+                }
+                ranges.push(range);
+
+                let start = range.unwrap_or(0) as usize;
+                let status = if range.is_some() { "206 Partial Content" } else { "200 OK" };
+                let header = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len() - start,
+                    start,
+                    body.len() - 1,
+                    body.len()
+                );
+                // The client may hang up early (cancellation)
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&body[start..]);
+            }
+            ranges
+        });
+        (url, handle)
+    }
+
+    fn body() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    fn fetch(
+        url: &str,
+        dest: &Path,
+        body: &[u8],
+        cancel: &CancelToken,
+        progress: impl FnMut(u64),
+    ) -> Result<FileChecksum> {
+        let sha256 = sha256_hex(body);
+        download_file(&agent(), url, dest, Some(&sha256), Some(body.len() as u64), cancel, progress)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voiceflow-download-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_download_verifies_and_renames() {
+        let body = body();
+        let (url, server) = serve(body.clone(), 1);
+        let dest = temp_dir("fresh").join("model.bin");
+
+        let mut last = 0;
+        let checksum = fetch(&url, &dest, &body, &CancelToken::new(), |n| last = n).unwrap();
+
+        assert_eq!(checksum.size, body.len() as u64);
+        assert_eq!(checksum.sha256, sha256_hex(&body));
+        assert_eq!(last, checksum.size);
+        assert_eq!(fs::read(&dest).unwrap(), body);
+        assert!(!partial_path(&dest).exists());
+        assert_eq!(server.join().unwrap(), [None]);
+    }
+
+    #[test]
+    fn test_partial_download_is_resumed() {
+        let body = body();
+        let (url, server) = serve(body.clone(), 1);
+        let dest = temp_dir("resume").join("model.bin");
+        // Left behind by a killed process
+        fs::write(partial_path(&dest), &body[..70_000]).unwrap();
+
+        fetch(&url, &dest, &body, &CancelToken::new(), |_| {}).unwrap();
+
+        assert_eq!(fs::read(&dest).unwrap(), body);
+        assert_eq!(server.join().unwrap(), [Some(70_000)]);
+    }
+
+    #[test]
+    fn test_corrupt_partial_is_removed() {
+        let body = body();
+        let (url, server) = serve(body.clone(), 1);
+        let dest = temp_dir("corrupt").join("model.bin");
+        fs::write(partial_path(&dest), vec![0xffu8; 70_000]).unwrap();
+
+        let err = fetch(&url, &dest, &body, &CancelToken::new(), |_| {}).unwrap_err();
+
+        assert!(
+            matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::ChecksumMismatch { .. })),
+            "{}",
+            err
+        );
+        assert!(!dest.exists());
+        assert!(!partial_path(&dest).exists(), "next attempt should start over");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_cancel_keeps_partial() {
+        let body = body();
+        let (url, server) = serve(body.clone(), 1);
+        let dest = temp_dir("cancel").join("model.bin");
+        let cancel = CancelToken::new();
+
+        let err = fetch(&url, &dest, &body, &cancel, |_| cancel.cancel()).unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::Cancelled)), "{}", err);
+        assert!(!dest.exists());
+        assert!(partial_path(&dest).exists());
+        drop(server);
+    }
+
+    #[test]
+    fn test_sha256_from_etag() {
+        let sha = "A3F1".repeat(16);
+        assert_eq!(sha256_from_etag(&format!("\"{}\"", sha)), Some(sha.to_ascii_lowercase()));
+        assert_eq!(sha256_from_etag(&format!("W/\"{}\"", sha)), Some(sha.to_ascii_lowercase()));
+        // Git blob SHA-1 of a small file
+        assert_eq!(sha256_from_etag("\"5d4c3e8b0b6b3a9f2d1e0c9b8a7f6e5d4c3b2a19\""), None);
+    }
+
+    #[test]
+    fn test_model_ids_round_trip() {
+        for model in DownloadableModel::all_models() {
+            assert_eq!(DownloadableModel::from_id(&model.id()), Some(model.clone()));
+            assert!(!model.files().is_empty(), "{}", model.id());
+        }
+        assert_eq!(
+            DownloadableModel::from_id("whisper-base"),
+            Some(DownloadableModel::Whisper(WhisperModel::Base, ModelPrecision::Float32))
+        );
+        assert_eq!(
+            DownloadableModel::from_id("moonshine-tiny-int8"),
+            Some(DownloadableModel::Moonshine(MoonshineModel::Tiny, ModelPrecision::Int8))
+        );
+        assert_eq!(DownloadableModel::from_id("custom"), None);
+    }
+
+    #[test]
+    fn test_moonshine_files_live_in_model_dir() {
+        let files = DownloadableModel::Moonshine(MoonshineModel::Tiny, ModelPrecision::Float32).files();
+        assert_eq!(files.len(), 4);
+        assert_eq!(files[0].path, Path::new("moonshine-tiny/preprocess.onnx"));
+        assert!(files[0].url.ends_with("/UsefulSensors/moonshine/resolve/main/onnx/tiny/preprocess.onnx"));
+
+        // Each precision has its own directory
+        let files = DownloadableModel::Moonshine(MoonshineModel::Tiny, ModelPrecision::Quantized).files();
+        assert_eq!(files[0].path, Path::new("moonshine-tiny-quantized/preprocess.onnx"));
+        assert!(files[0].url.ends_with("/onnx/tiny/quantized/preprocess.onnx"));
+    }
+}
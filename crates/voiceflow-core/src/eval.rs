@@ -0,0 +1,279 @@
+//! Accuracy and latency evaluation against reference transcripts
+//!
+//! `evaluate` runs a pipeline over recordings with known transcripts and
+//! reports the word and character error rates of the raw transcripts, how
+//! far formatting moved away from them, and latency percentiles, so engines
+//! and models can be compared on the same recordings. Datasets are usually
+//! read from a JSON Lines manifest with `load_manifest`; `voiceflow eval`
+//! does both from the command line.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::llm::normalized_words;
+use crate::pipeline::Pipeline;
+
+/// A line of a manifest
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    audio: PathBuf,
+    reference: String,
+}
+
+/// Read a dataset from a JSON Lines manifest, one
+/// `{"audio": "clip.wav", "reference": "what was said"}` object per line
+///
+/// Relative audio paths are relative to the manifest's directory. Blank
+/// lines are skipped.
+pub fn load_manifest(path: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read manifest {:?}", path))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut dataset = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: ManifestEntry = serde_json::from_str(line)
+            .with_context(|| format!("Invalid entry on line {} of {:?}", index + 1, path))?;
+        dataset.push((dir.join(entry.audio), entry.reference));
+    }
+    Ok(dataset)
+}
+
+/// How one recording came out
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalItem {
+    pub audio: PathBuf,
+    pub reference: String,
+    pub raw_transcript: String,
+    pub formatted_text: String,
+    /// Words substituted, deleted and inserted in the raw transcript,
+    /// compared with the reference (case and punctuation ignored)
+    pub word_errors: usize,
+    pub reference_words: usize,
+    /// Characters substituted, deleted and inserted, likewise
+    pub char_errors: usize,
+    pub reference_chars: usize,
+    /// `word_errors` per reference word
+    pub wer: f32,
+    /// `char_errors` per reference character
+    pub cer: f32,
+    /// Words of the raw transcript that formatting changed, dropped or
+    /// added, per transcript word (0.0 when formatting only punctuated)
+    pub format_divergence: f32,
+    pub total_ms: u64,
+    pub transcription_ms: u64,
+    pub llm_formatting_ms: u64,
+    /// Why the recording couldn't be processed; it's left out of the totals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Distribution of a stage's time over the recordings, in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Latency {
+    pub mean_ms: u64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Latency {
+    /// Nearest-rank percentiles of `times`
+    fn of(mut times: Vec<u64>) -> Self {
+        if times.is_empty() {
+            return Self::default();
+        }
+        times.sort_unstable();
+        let percentile = |p: usize| times[(p * times.len()).div_ceil(100).max(1) - 1];
+        Self {
+            mean_ms: times.iter().sum::<u64>() / times.len() as u64,
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p99_ms: percentile(99),
+            max_ms: times[times.len() - 1],
+        }
+    }
+}
+
+/// Result of `evaluate`
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    /// Word error rate over the dataset: all word errors over all
+    /// reference words
+    pub wer: f32,
+    /// Character error rate over the dataset
+    pub cer: f32,
+    /// Mean `format_divergence` of the recordings
+    pub format_divergence: f32,
+    /// Whole requests (`Timings::total_ms`)
+    pub latency: Latency,
+    pub transcription_latency: Latency,
+    pub formatting_latency: Latency,
+    /// Recordings processed
+    pub evaluated: usize,
+    /// Recordings that failed, see `EvalItem::error`
+    pub failed: usize,
+    pub items: Vec<EvalItem>,
+}
+
+/// Run `pipeline` over the recordings of `dataset` and compare the results
+/// with their reference transcripts
+///
+/// The pipeline is warmed up first, so the first recording isn't charged
+/// for loading models. A recording that fails (unreadable file, STT error)
+/// is reported with its error and left out of the totals; only a failed
+/// warm-up is an error.
+pub fn evaluate(pipeline: &mut Pipeline, dataset: &[(PathBuf, String)]) -> Result<EvalReport> {
+    pipeline.warm_up()?;
+
+    let mut items = Vec::with_capacity(dataset.len());
+    for (audio, reference) in dataset {
+        tracing::info!("Evaluating {:?}", audio);
+        let item = match pipeline.process_file(audio, None) {
+            Ok(result) => {
+                let mut item = score(audio, reference, &result.raw_transcript, &result.formatted_text);
+                item.total_ms = result.timings.total_ms;
+                item.transcription_ms = result.timings.transcription_ms;
+                item.llm_formatting_ms = result.timings.llm_formatting_ms;
+                item
+            }
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                tracing::warn!("Failed to process {:?}: {:#}", audio, e);
+                let mut item = score(audio, reference, "", "");
+                item.error = Some(format!("{:#}", e));
+                item
+            }
+        };
+        items.push(item);
+    }
+    Ok(report(items))
+}
+
+/// Score a transcript and its formatting against a reference
+fn score(audio: &Path, reference: &str, raw_transcript: &str, formatted_text: &str) -> EvalItem {
+    let reference_words = normalized_words(reference);
+    let transcript_words = normalized_words(raw_transcript);
+    let reference_chars: Vec<char> = reference_words.join(" ").chars().collect();
+    let transcript_chars: Vec<char> = transcript_words.join(" ").chars().collect();
+    let word_errors = edit_distance(&reference_words, &transcript_words);
+    let char_errors = edit_distance(&reference_chars, &transcript_chars);
+    let formatted_words = normalized_words(formatted_text);
+
+    EvalItem {
+        audio: audio.to_path_buf(),
+        reference: reference.to_string(),
+        raw_transcript: raw_transcript.to_string(),
+        formatted_text: formatted_text.to_string(),
+        word_errors,
+        reference_words: reference_words.len(),
+        char_errors,
+        reference_chars: reference_chars.len(),
+        wer: rate(word_errors, reference_words.len()),
+        cer: rate(char_errors, reference_chars.len()),
+        format_divergence: rate(edit_distance(&transcript_words, &formatted_words), transcript_words.len()),
+        total_ms: 0,
+        transcription_ms: 0,
+        llm_formatting_ms: 0,
+        error: None,
+    }
+}
+
+fn report(items: Vec<EvalItem>) -> EvalReport {
+    let scored: Vec<&EvalItem> = items.iter().filter(|item| item.error.is_none()).collect();
+    let sum = |field: fn(&EvalItem) -> usize| scored.iter().map(|item| field(item)).sum::<usize>();
+    let times = |field: fn(&EvalItem) -> u64| scored.iter().map(|item| field(item)).collect::<Vec<_>>();
+    let format_divergence = if scored.is_empty() {
+        0.0
+    } else {
+        scored.iter().map(|item| item.format_divergence).sum::<f32>() / scored.len() as f32
+    };
+
+    EvalReport {
+        wer: rate(sum(|item| item.word_errors), sum(|item| item.reference_words)),
+        cer: rate(sum(|item| item.char_errors), sum(|item| item.reference_chars)),
+        format_divergence,
+        latency: Latency::of(times(|item| item.total_ms)),
+        transcription_latency: Latency::of(times(|item| item.transcription_ms)),
+        formatting_latency: Latency::of(times(|item| item.llm_formatting_ms)),
+        evaluated: scored.len(),
+        failed: items.len() - scored.len(),
+        items,
+    }
+}
+
+/// Errors per reference unit; an empty reference scores each extra unit as
+/// a whole error
+fn rate(errors: usize, reference: usize) -> f32 {
+    match (errors, reference) {
+        (0, _) => 0.0,
+        (errors, 0) => errors as f32,
+        (errors, reference) => errors as f32 / reference as f32,
+    }
+}
+
+/// Levenshtein distance: substitutions, deletions and insertions turning
+/// `a` into `b`
+fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, x) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != y);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        let words = |text: &str| normalized_words(text);
+        assert_eq!(edit_distance(&words("set a timer"), &words("Set a timer.")), 0);
+        assert_eq!(edit_distance(&words("set a timer for ten"), &words("set the timer for")), 2);
+        assert_eq!(edit_distance(&words(""), &words("hello there")), 2);
+        assert_eq!(edit_distance(&['k', 'i', 't', 't', 'e', 'n'], &['s', 'i', 't', 't', 'i', 'n', 'g']), 3);
+    }
+
+    #[test]
+    fn test_score() {
+        let item = score(Path::new("a.wav"), "Call mom.", "call mom now", "Call Mom now!");
+        assert_eq!((item.word_errors, item.reference_words), (1, 2));
+        assert_eq!(item.wer, 0.5);
+        // "call mom" -> "call mom now": 4 insertions over 8 characters
+        assert_eq!((item.char_errors, item.reference_chars), (4, 8));
+        assert_eq!(item.format_divergence, 0.0);
+
+        let item = score(Path::new("a.wav"), "call mom", "call mom", "Call your mother.");
+        assert!((item.format_divergence - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_report_totals() {
+        let mut items = vec![
+            score(Path::new("a.wav"), "one two three four", "one two three for", ""),
+            score(Path::new("b.wav"), "five six", "five six", ""),
+            score(Path::new("c.wav"), "seven", "", ""),
+        ];
+        for (item, ms) in items.iter_mut().zip([100, 300, 200]) {
+            item.total_ms = ms;
+        }
+        items[2].error = Some("unreadable".to_string());
+
+        let report = report(items);
+        assert_eq!((report.evaluated, report.failed), (2, 1));
+        assert!((report.wer - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(report.latency, Latency { mean_ms: 200, p50_ms: 100, p90_ms: 300, p99_ms: 300, max_ms: 300 });
+    }
+}
@@ -0,0 +1,430 @@
+//! Processed recordings kept with their results (`Config::history`)
+//!
+//! Each entry is a directory in the history directory, named by its id and
+//! holding the recording as 16kHz mono WAV (audio.wav) and the transcript,
+//! formatted text and metadata as JSON (entry.json). Ids start with the
+//! creation time in milliseconds, so they sort oldest first. The retention
+//! limits are enforced whenever an entry is written.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, HistoryOptions};
+use crate::pipeline::PipelineResult;
+
+/// The recording in an entry's directory
+pub const AUDIO_FILE: &str = "audio.wav";
+
+/// Everything else in an entry's directory, written last
+const ENTRY_FILE: &str = "entry.json";
+
+/// Sample rate of the recordings the pipeline processes
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Characters of the formatted text in a `HistorySummary`
+const PREVIEW_CHARS: usize = 120;
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// What entry.json holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// When the recording was processed, in milliseconds since the Unix epoch
+    pub created_at_ms: u64,
+    /// Length of the recording
+    pub duration_ms: u64,
+    /// The context hint passed with the audio
+    pub context: Option<String>,
+    pub raw_transcript: String,
+    pub formatted_text: String,
+    /// Language the audio was transcribed in
+    pub language: Option<String>,
+    pub no_speech: bool,
+    pub was_fallback: bool,
+    pub formatting_error: Option<String>,
+    /// Time the request took
+    pub total_ms: u64,
+    /// The formatter (see `Config::llm_display_name`)
+    pub llm: String,
+}
+
+impl HistoryRecord {
+    /// Record of `result`, processed now from `audio` (16kHz mono)
+    pub fn new(audio: &[f32], context: Option<&str>, result: &PipelineResult, llm: String) -> Self {
+        Self {
+            created_at_ms: now_ms(),
+            duration_ms: audio.len() as u64 * 1000 / SAMPLE_RATE as u64,
+            context: context.map(str::to_string),
+            raw_transcript: result.raw_transcript.clone(),
+            formatted_text: result.formatted_text.clone(),
+            language: result.language.clone(),
+            no_speech: result.no_speech,
+            was_fallback: result.was_fallback,
+            formatting_error: result.formatting_error.clone(),
+            total_ms: result.timings.total_ms,
+            llm,
+        }
+    }
+}
+
+/// An entry with everything kept for it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    #[serde(flatten)]
+    pub record: HistoryRecord,
+    /// The recording, 16kHz mono WAV
+    pub audio_path: PathBuf,
+    /// Bytes the entry takes up, recording included
+    pub bytes: u64,
+}
+
+/// An entry as listed, without the transcript
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistorySummary {
+    pub id: String,
+    pub created_at_ms: u64,
+    pub duration_ms: u64,
+    /// Start of the formatted text
+    pub preview: String,
+    pub bytes: u64,
+}
+
+/// An entry's directory as found on disk, for pruning
+struct StoredEntry {
+    id: String,
+    created_at_ms: u64,
+    bytes: u64,
+}
+
+/// The entries in a history directory
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    /// The entries in `dir`, which is created when the first one is written
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The entries in `Config::history_dir`
+    pub fn for_config(config: &Config) -> Result<Self> {
+        Ok(Self::new(config.history_dir()?))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Keep `audio` (16kHz mono) with `record`, then remove the oldest
+    /// entries over the limits of `retention`, returning the new entry's id
+    ///
+    /// The new entry itself is never removed, even when it alone is over
+    /// `max_bytes`.
+    pub fn record(&self, audio: &[f32], record: &HistoryRecord, retention: &HistoryOptions) -> Result<String> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create history directory {:?}", self.dir))?;
+        let (id, entry_dir) = self.create_entry_dir(record.created_at_ms)?;
+        let written = write_wav(&entry_dir.join(AUDIO_FILE), audio).and_then(|()| {
+            let json = serde_json::to_vec_pretty(record)?;
+            std::fs::write(entry_dir.join(ENTRY_FILE), json)?;
+            Ok(())
+        });
+        if let Err(e) = written {
+            let _ = std::fs::remove_dir_all(&entry_dir);
+            return Err(e.context(format!("Failed to write history entry {:?}", entry_dir)));
+        }
+
+        let removed = self.prune(retention, &id, now_ms())?;
+        if removed > 0 {
+            tracing::debug!("Removed {} history entries over the retention limits", removed);
+        }
+        Ok(id)
+    }
+
+    /// A directory for a new entry, named by `created_at_ms` with a suffix
+    /// when an entry of the same millisecond exists
+    fn create_entry_dir(&self, created_at_ms: u64) -> Result<(String, PathBuf)> {
+        for suffix in 0.. {
+            let id = match suffix {
+                0 => format!("{:013}", created_at_ms),
+                n => format!("{:013}-{}", created_at_ms, n),
+            };
+            let path = self.dir.join(&id);
+            match std::fs::create_dir(&path) {
+                Ok(()) => return Ok((id, path)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to create {:?}", path)),
+            }
+        }
+        unreachable!()
+    }
+
+    /// The newest entries first, at most `limit` of them (0 for all)
+    pub fn list(&self, limit: usize) -> Result<Vec<HistorySummary>> {
+        let mut summaries = Vec::new();
+        for stored in self.stored_entries()?.into_iter().rev() {
+            if limit > 0 && summaries.len() == limit {
+                break;
+            }
+            // Entries still being written have no entry.json yet
+            let Some(entry) = self.get(&stored.id)? else {
+                continue;
+            };
+            summaries.push(HistorySummary {
+                id: entry.id,
+                created_at_ms: entry.record.created_at_ms,
+                duration_ms: entry.record.duration_ms,
+                preview: preview(&entry.record.formatted_text),
+                bytes: entry.bytes,
+            });
+        }
+        Ok(summaries)
+    }
+
+    /// The entry with `id`, `None` if there is none
+    pub fn get(&self, id: &str) -> Result<Option<HistoryEntry>> {
+        if parse_id(id).is_none() {
+            return Ok(None);
+        }
+        let entry_dir = self.dir.join(id);
+        let json = match std::fs::read(entry_dir.join(ENTRY_FILE)) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read history entry {}", id)),
+        };
+        let record: HistoryRecord =
+            serde_json::from_slice(&json).with_context(|| format!("Failed to parse history entry {}", id))?;
+        Ok(Some(HistoryEntry {
+            id: id.to_string(),
+            record,
+            audio_path: entry_dir.join(AUDIO_FILE),
+            bytes: dir_size(&entry_dir)?,
+        }))
+    }
+
+    /// Remove every entry, returning how many there were
+    ///
+    /// Files in the directory that aren't entries are left alone.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.stored_entries()?;
+        for entry in &entries {
+            self.remove(&entry.id)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Remove the oldest entries over the limits of `retention` at `now_ms`,
+    /// apart from `keep`, returning how many were removed
+    fn prune(&self, retention: &HistoryOptions, keep: &str, now_ms: u64) -> Result<usize> {
+        let entries = self.stored_entries()?;
+        let max_age_ms = retention.max_age_days as u64 * MS_PER_DAY;
+        let mut count = entries.len();
+        let mut bytes: u64 = entries.iter().map(|entry| entry.bytes).sum();
+        let mut removed = 0;
+        for entry in entries.iter().filter(|entry| entry.id != keep) {
+            let too_many = retention.max_entries > 0 && count > retention.max_entries;
+            let too_big = retention.max_bytes > 0 && bytes > retention.max_bytes;
+            let too_old = max_age_ms > 0 && now_ms.saturating_sub(entry.created_at_ms) > max_age_ms;
+            if !(too_many || too_big || too_old) {
+                continue;
+            }
+            self.remove(&entry.id)?;
+            count -= 1;
+            bytes -= entry.bytes;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    fn remove(&self, id: &str) -> Result<()> {
+        let path = self.dir.join(id);
+        match std::fs::remove_dir_all(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove history entry {:?}", path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The entry directories, oldest first (none when the history
+    /// directory doesn't exist)
+    fn stored_entries(&self) -> Result<Vec<StoredEntry>> {
+        let read_dir = match std::fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read history directory {:?}", self.dir)),
+        };
+        let mut entries = Vec::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            let Some(id) = dir_entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let Some(created_at_ms) = parse_id(&id) else {
+                continue;
+            };
+            if dir_entry.file_type()?.is_dir() {
+                let bytes = dir_size(&dir_entry.path())?;
+                entries.push(StoredEntry { id, created_at_ms, bytes });
+            }
+        }
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(entries)
+    }
+}
+
+/// Creation time of the entry with `id`, `None` if it isn't an entry id
+fn parse_id(id: &str) -> Option<u64> {
+    let (millis, suffix) = id.split_once('-').unwrap_or((id, "0"));
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    (millis.len() == 13 && digits(millis) && digits(suffix)).then(|| millis.parse().ok())?
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Total size of the files directly in `dir`
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            bytes += metadata.len();
+        }
+    }
+    Ok(bytes)
+}
+
+/// Write 16kHz mono samples as 16-bit PCM
+fn write_wav(path: &Path, audio: &[f32]) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in audio {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::load_audio_file;
+
+    fn temp_history(name: &str) -> History {
+        let dir = std::env::temp_dir().join(format!("voiceflow-history-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        History::new(dir)
+    }
+
+    fn record(created_at_ms: u64, text: &str) -> HistoryRecord {
+        HistoryRecord {
+            created_at_ms,
+            duration_ms: 500,
+            context: Some("email".to_string()),
+            raw_transcript: text.to_lowercase(),
+            formatted_text: text.to_string(),
+            language: Some("en".to_string()),
+            no_speech: false,
+            was_fallback: false,
+            formatting_error: None,
+            total_ms: 120,
+            llm: "Qwen3 1.7B".to_string(),
+        }
+    }
+
+    fn no_limits() -> HistoryOptions {
+        HistoryOptions { enabled: true, max_entries: 0, max_bytes: 0, max_age_days: 0, ..HistoryOptions::default() }
+    }
+
+    #[test]
+    fn test_entry_round_trips_with_its_recording() {
+        let history = temp_history("round-trip");
+        let audio: Vec<f32> = (0..8000).map(|i| (i as f32 / 20.0).sin() * 0.5).collect();
+        let id = history.record(&audio, &record(now_ms(), "Hello there."), &no_limits()).unwrap();
+
+        let entry = history.get(&id).unwrap().unwrap();
+        assert_eq!(entry.record.formatted_text, "Hello there.");
+        assert_eq!(entry.record.raw_transcript, "hello there.");
+        assert!(entry.bytes > 16_000, "{} bytes", entry.bytes);
+
+        let buffer = load_audio_file(&entry.audio_path).unwrap();
+        assert_eq!((buffer.sample_rate, buffer.channels), (16_000, 1));
+        assert_eq!(buffer.samples.len(), audio.len());
+        assert!((buffer.samples[100] - audio[100]).abs() < 0.001);
+
+        assert_eq!(history.get("1234567890123").unwrap(), None);
+        assert_eq!(history.get("../entry").unwrap(), None);
+        std::fs::remove_dir_all(history.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_list_is_newest_first() {
+        let history = temp_history("list");
+        let start = now_ms();
+        let long = "word ".repeat(100);
+        let ids: Vec<String> = ["First.", "Second.", long.trim()]
+            .iter()
+            .map(|text| history.record(&[0.0; 1600], &record(start, text), &no_limits()).unwrap())
+            .collect();
+        // The same millisecond gets a suffix
+        assert_eq!(ids[1], format!("{}-1", ids[0]));
+
+        let summaries = history.list(2).unwrap();
+        assert_eq!(summaries.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), [&ids[2], &ids[1]]);
+        assert!(summaries[0].preview.ends_with('…'));
+        assert_eq!(summaries[1].preview, "Second.");
+        assert_eq!(history.list(0).unwrap().len(), 3);
+
+        assert_eq!(history.clear().unwrap(), 3);
+        assert!(history.list(0).unwrap().is_empty());
+        std::fs::remove_dir_all(history.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_retention_removes_the_oldest() {
+        let history = temp_history("retention");
+        let now = now_ms();
+        let retention = HistoryOptions { max_entries: 2, ..no_limits() };
+        for i in 0..4 {
+            history.record(&[0.0; 1600], &record(now - 4 + i, "Kept."), &retention).unwrap();
+        }
+        let ids: Vec<String> = history.list(0).unwrap().into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, [format!("{:013}", now - 1), format!("{:013}", now - 2)]);
+
+        // Only the newest entry fits in the byte limit
+        let one_entry = history.list(1).unwrap()[0].bytes;
+        let retention = HistoryOptions { max_bytes: one_entry + 10, ..no_limits() };
+        let id = history.record(&[0.0; 1600], &record(now, "Newest."), &retention).unwrap();
+        assert_eq!(history.list(0).unwrap().into_iter().map(|s| s.id).collect::<Vec<_>>(), [id]);
+
+        // Entries older than max_age_days
+        let retention = HistoryOptions { max_age_days: 1, ..no_limits() };
+        history.record(&[0.0; 1600], &record(now - 2 * MS_PER_DAY, "Old."), &retention).unwrap();
+        let id = history.record(&[0.0; 1600], &record(now, "New."), &retention).unwrap();
+        assert_eq!(history.list(0).unwrap().len(), 2);
+        assert!(history.list(0).unwrap().iter().any(|s| s.id == id));
+        assert!(!history.list(0).unwrap().iter().any(|s| s.preview == "Old."));
+        std::fs::remove_dir_all(history.dir()).unwrap();
+    }
+}
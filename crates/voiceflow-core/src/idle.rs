@@ -0,0 +1,72 @@
+//! Background unloading of models left idle (see `Config::idle_unload_seconds`)
+
+use std::sync::{Arc, Condvar, Mutex, TryLockError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::pipeline::Pipeline;
+
+/// Longest wait between checks, so a timeout set at runtime is picked up
+const MAX_WAIT: Duration = Duration::from_secs(10);
+/// Shortest wait, so a busy pipeline isn't polled in a tight loop
+const MIN_WAIT: Duration = Duration::from_millis(100);
+
+/// Thread that calls `Pipeline::unload_if_idle` when the idle timeout runs
+/// out; dropping it stops the thread
+///
+/// A pipeline that is busy is skipped rather than waited on, so a request is
+/// never held up, and the timeout restarts when the request finishes.
+pub struct IdleUnloader {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IdleUnloader {
+    /// Start watching `pipeline`
+    pub fn spawn(pipeline: Arc<Mutex<Pipeline>>) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = Arc::clone(&stop);
+        let thread = std::thread::Builder::new()
+            .name("voiceflow-idle".into())
+            .spawn(move || watch(&pipeline, &thread_stop))
+            .ok();
+        Self { stop, thread }
+    }
+}
+
+fn watch(pipeline: &Mutex<Pipeline>, stop: &(Mutex<bool>, Condvar)) {
+    let (stopped, wake) = stop;
+    let mut wait = MIN_WAIT;
+    loop {
+        let guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+        let (guard, _) = wake
+            .wait_timeout_while(guard, wait, |stopped| !*stopped)
+            .unwrap_or_else(|e| e.into_inner());
+        if *guard {
+            return;
+        }
+        drop(guard);
+
+        let mut pipeline = match pipeline.try_lock() {
+            Ok(pipeline) => pipeline,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                wait = MIN_WAIT;
+                continue;
+            }
+        };
+        pipeline.unload_if_idle();
+        wait = pipeline.idle_unload_in().unwrap_or(MAX_WAIT).clamp(MIN_WAIT, MAX_WAIT);
+    }
+}
+
+impl Drop for IdleUnloader {
+    fn drop(&mut self) {
+        let (stopped, wake) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
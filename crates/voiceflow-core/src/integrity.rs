@@ -0,0 +1,230 @@
+//! Model file integrity checks before loading
+//!
+//! The size and SHA-256 of each downloaded file are recorded in
+//! `checksums.toml` in the models directory once the download is verified
+//! against the checksum the server publishes. Loading a model compares the
+//! file size against that record, and the full digest when
+//! `Config.verify_models` is set, so a truncated or damaged file fails with
+//! `PipelineError::ModelCorrupted` instead of producing gibberish. Files
+//! without a record (placed by hand, custom models) are loaded unchecked.
+
+use crate::downloads::DownloadableModel;
+use crate::PipelineError;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Name of the checksum manifest in the models directory
+pub const MANIFEST_FILE: &str = "checksums.toml";
+
+/// Serializes manifest updates from concurrent downloads
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Expected size and digest of a model file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub size: u64,
+    /// Lowercase hex SHA-256
+    pub sha256: String,
+}
+
+/// Checksums of the downloaded model files, keyed by path relative to the
+/// models directory (with `/` separators)
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChecksumManifest {
+    files: BTreeMap<String, FileChecksum>,
+}
+
+impl ChecksumManifest {
+    /// Load the manifest of `models_dir` (empty if there is none yet)
+    pub fn load(models_dir: &Path) -> Result<Self> {
+        let path = models_dir.join(MANIFEST_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        toml::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    /// Get the recorded checksum of a file, given its path relative to the
+    /// models directory
+    pub fn get(&self, relative: &Path) -> Option<&FileChecksum> {
+        self.files.get(&manifest_key(relative))
+    }
+
+    /// Record the checksum of a freshly downloaded file
+    pub fn record(models_dir: &Path, relative: &Path, checksum: FileChecksum) -> Result<()> {
+        let _guard = MANIFEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut manifest = Self::load(models_dir)?;
+        manifest.files.insert(manifest_key(relative), checksum);
+        manifest.save(models_dir)
+    }
+
+    /// Drop the checksum of a deleted file
+    pub fn forget(models_dir: &Path, relative: &Path) -> Result<()> {
+        let _guard = MANIFEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut manifest = Self::load(models_dir)?;
+        if manifest.files.remove(&manifest_key(relative)).is_none() {
+            return Ok(());
+        }
+        manifest.save(models_dir)
+    }
+
+    /// Write then rename, so a crash never leaves a truncated manifest
+    fn save(&self, models_dir: &Path) -> Result<()> {
+        let path = models_dir.join(MANIFEST_FILE);
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string_pretty(self)?).with_context(|| format!("Failed to write {:?}", tmp))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {:?}", path))
+    }
+}
+
+fn manifest_key(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// SHA-256 of a file, as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check a model file against its recorded checksum
+///
+/// Compares the size, and the SHA-256 too if `full_hash` (slow for large
+/// models). Files outside `models_dir` or without a record pass unchecked.
+pub fn verify_file(models_dir: &Path, path: &Path, full_hash: bool) -> Result<()> {
+    let Ok(relative) = path.strip_prefix(models_dir) else {
+        return Ok(());
+    };
+    let manifest = ChecksumManifest::load(models_dir)?;
+    let Some(expected) = manifest.get(relative) else {
+        tracing::debug!("No recorded checksum for {:?}; not verified", path);
+        return Ok(());
+    };
+
+    let size = fs::metadata(path).with_context(|| format!("Failed to read {:?}", path))?.len();
+    if size != expected.size {
+        return Err(corrupted(path, format!("{} bytes", expected.size), format!("{} bytes", size)));
+    }
+
+    if full_hash {
+        tracing::info!("Verifying SHA-256 of {:?}", path);
+        let actual = sha256_file(path)?;
+        if !actual.eq_ignore_ascii_case(&expected.sha256) {
+            return Err(corrupted(path, expected.sha256.clone(), actual));
+        }
+    }
+    Ok(())
+}
+
+/// Check every file of a downloadable model, hashing them all
+///
+/// Fails with `SttModelNotFound` / `LlmModelNotFound` /
+/// `SpeakerModelNotFound` if a file is missing
+/// and `ModelCorrupted` if one doesn't match its record.
+pub fn verify_model(model: &DownloadableModel, models_dir: &Path) -> Result<()> {
+    for file in model.files() {
+        let path = models_dir.join(&file.path);
+        if !path.exists() {
+            let path = path.display().to_string();
+            return Err(match model {
+                DownloadableModel::Llm(_) => PipelineError::LlmModelNotFound { path },
+                DownloadableModel::Speaker(_) => PipelineError::SpeakerModelNotFound { path },
+                _ => PipelineError::SttModelNotFound { path },
+            }
+            .into());
+        }
+        verify_file(models_dir, &path, true)?;
+    }
+    Ok(())
+}
+
+fn corrupted(path: &Path, expected: String, actual: String) -> anyhow::Error {
+    PipelineError::ModelCorrupted { path: path.display().to_string(), expected, actual }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voiceflow-integrity-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_recorded(dir: &Path, relative: &str, data: &[u8]) -> PathBuf {
+        let path = dir.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, data).unwrap();
+        let checksum = FileChecksum { size: data.len() as u64, sha256: sha256_file(&path).unwrap() };
+        ChecksumManifest::record(dir, Path::new(relative), checksum).unwrap();
+        path
+    }
+
+    fn corruption(err: &anyhow::Error) -> Option<(&str, &str)> {
+        match err.downcast_ref::<PipelineError>() {
+            Some(PipelineError::ModelCorrupted { expected, actual, .. }) => Some((expected.as_str(), actual.as_str())),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_truncated_file_fails_size_check() {
+        let dir = temp_dir("truncated");
+        let path = write_recorded(&dir, "model.gguf", &[7u8; 4096]);
+        verify_file(&dir, &path, false).unwrap();
+
+        fs::write(&path, [7u8; 1000]).unwrap();
+        let err = verify_file(&dir, &path, false).unwrap_err();
+        assert_eq!(corruption(&err), Some(("4096 bytes", "1000 bytes")), "{}", err);
+    }
+
+    #[test]
+    fn test_damaged_file_fails_only_full_hash() {
+        let dir = temp_dir("damaged");
+        let path = write_recorded(&dir, "moonshine-tiny/encode.onnx", &[7u8; 4096]);
+
+        // Same size, different content
+        fs::write(&path, [8u8; 4096]).unwrap();
+        verify_file(&dir, &path, false).unwrap();
+        let err = verify_file(&dir, &path, true).unwrap_err();
+        assert!(corruption(&err).is_some(), "{}", err);
+    }
+
+    #[test]
+    fn test_unrecorded_files_pass() {
+        let dir = temp_dir("unrecorded");
+        let path = dir.join("custom.gguf");
+        fs::write(&path, b"anything").unwrap();
+        verify_file(&dir, &path, true).unwrap();
+
+        let outside = temp_dir("outside").join("model.gguf");
+        fs::write(&outside, b"anything").unwrap();
+        verify_file(&dir, &outside, true).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_keys_use_forward_slashes() {
+        let dir = temp_dir("keys");
+        write_recorded(&dir, "moonshine-tiny/tokenizer.json", b"{}");
+        let manifest = ChecksumManifest::load(&dir).unwrap();
+        assert!(manifest.get(&Path::new("moonshine-tiny").join("tokenizer.json")).is_some());
+        assert!(fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap().contains("moonshine-tiny/tokenizer.json"));
+    }
+}
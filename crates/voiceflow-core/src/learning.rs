@@ -0,0 +1,353 @@
+//! Corrections the user made to dictated text, learned from
+//! (`Config::learning`)
+//!
+//! A reported correction is cut down to the words that changed and counted
+//! in a JSON file (corrections.json in the data directory). Once reported
+//! often enough, and nearly always corrected the same way, it is promoted:
+//! the corrected words join the vocabulary with the original ones as a
+//! sounds-like alias, and a replacement rule rewrites the original ones.
+//! Only short, plain-text changes are learned, never anything that reads as
+//! a regex.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    Config, LearningOptions, ReplacementRule, VocabularyEntry, MAX_VOCABULARY_ENTRIES, MAX_VOCABULARY_TERM_CHARS,
+};
+
+/// Most words on either side of a learned correction
+const MAX_CORRECTION_WORDS: usize = 4;
+
+/// Shortest original text learned, so corrections of "a" or "to" never
+/// become rules
+const MIN_ORIGINAL_CHARS: usize = 3;
+
+/// Most corrections kept in the file; the least recently reported are
+/// dropped first
+const MAX_STORED: usize = 500;
+
+/// Characters that make text read as a regex or a replacement template
+const SPECIAL_CHARS: &[char] = &['\\', '^', '$', '|', '?', '*', '+', '(', ')', '[', ']', '{', '}'];
+
+/// Serializes the read-modify-write of the file within the process
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Words of a dictation and what the user corrected them to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Correction {
+    /// The words as dictated
+    pub original: String,
+    /// The words as corrected
+    pub corrected: String,
+    /// Times the correction was reported
+    pub count: u32,
+    /// When it was last reported, in milliseconds since the Unix epoch
+    pub last_reported_ms: u64,
+    /// It was added to the vocabulary and the replacement rules
+    pub promoted: bool,
+}
+
+/// The corrections in a corrections file
+#[derive(Debug, Clone)]
+pub struct Corrections {
+    path: PathBuf,
+}
+
+impl Corrections {
+    /// The corrections in `path`, which is created when the first one is
+    /// reported
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The corrections in `Config::corrections_path`
+    pub fn for_config(config: &Config) -> Result<Self> {
+        Ok(Self::new(config.corrections_path()?))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Count the change from `original`, text the pipeline produced, to
+    /// `corrected`, returning the correction as now counted
+    ///
+    /// Only the words that changed are kept (see `changed_words`); `None`
+    /// when there are none, or they can't be learned.
+    pub fn record(&self, original: &str, corrected: &str) -> Result<Option<Correction>> {
+        let Some((original, corrected)) = changed_words(original, corrected) else {
+            return Ok(None);
+        };
+        if !is_learnable(&original, &corrected) {
+            tracing::debug!("Not learning the correction {:?} -> {:?}", original, corrected);
+            return Ok(None);
+        }
+
+        let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut corrections = self.read()?;
+        let index = match corrections.iter().position(|c| c.original == original && c.corrected == corrected) {
+            Some(index) => index,
+            None => {
+                corrections.push(Correction { original, corrected, count: 0, last_reported_ms: 0, promoted: false });
+                corrections.len() - 1
+            }
+        };
+        let correction = &mut corrections[index];
+        correction.count += 1;
+        correction.last_reported_ms = now_ms();
+        let correction = correction.clone();
+
+        if corrections.len() > MAX_STORED {
+            corrections.sort_by_key(|c| std::cmp::Reverse(c.last_reported_ms));
+            corrections.truncate(MAX_STORED);
+        }
+        self.write(&corrections)?;
+        Ok(Some(correction))
+    }
+
+    /// Every correction, the most reported first
+    pub fn list(&self) -> Result<Vec<Correction>> {
+        let mut corrections = self.read()?;
+        corrections.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_reported_ms.cmp(&a.last_reported_ms)));
+        Ok(corrections)
+    }
+
+    /// Forget every correction, returning how many there were
+    ///
+    /// What was promoted stays in the config.
+    pub fn clear(&self) -> Result<usize> {
+        let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let count = self.read()?.len();
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove corrections file {:?}", self.path))
+            }
+            _ => Ok(count),
+        }
+    }
+
+    /// The corrections `options` promote that aren't promoted yet, the most
+    /// reported first, up to the `max_learned` of them all
+    pub fn ready(&self, options: &LearningOptions) -> Result<Vec<Correction>> {
+        let corrections = self.list()?;
+        let promoted = corrections.iter().filter(|c| c.promoted).count();
+        let reports_of = |original: &str| -> u32 {
+            corrections.iter().filter(|c| c.original.eq_ignore_ascii_case(original)).map(|c| c.count).sum()
+        };
+        Ok(corrections
+            .iter()
+            .filter(|c| {
+                !c.promoted
+                    && c.count >= options.min_reports
+                    && c.count as f32 / reports_of(&c.original) as f32 >= options.min_agreement
+                    && is_learnable(&c.original, &c.corrected)
+            })
+            .take(options.max_learned.saturating_sub(promoted))
+            .cloned()
+            .collect())
+    }
+
+    /// Mark `promoted` as promoted, so they aren't promoted again
+    pub fn mark_promoted(&self, promoted: &[Correction]) -> Result<()> {
+        let _lock = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut corrections = self.read()?;
+        for correction in &mut corrections {
+            if promoted.iter().any(|p| p.original == correction.original && p.corrected == correction.corrected) {
+                correction.promoted = true;
+            }
+        }
+        self.write(&corrections)
+    }
+
+    /// The corrections in the file (none when there is no file)
+    fn read(&self) -> Result<Vec<Correction>> {
+        let json = match std::fs::read(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read corrections file {:?}", self.path)),
+        };
+        serde_json::from_slice(&json).with_context(|| format!("Failed to parse corrections file {:?}", self.path))
+    }
+
+    fn write(&self, corrections: &[Correction]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec_pretty(corrections)?;
+        std::fs::write(&self.path, json).with_context(|| format!("Failed to write corrections file {:?}", self.path))
+    }
+}
+
+/// Add `corrections` to the vocabulary and the replacement rules of
+/// `config`, leaving out what it has already
+///
+/// Each corrected text becomes a vocabulary term (while there is room) with
+/// its original as a sounds-like alias, and a literal, case-insensitive
+/// rule replaces the original in the transcript and the formatted text.
+pub fn learn(config: &mut Config, corrections: &[Correction]) {
+    for correction in corrections {
+        let (original, corrected) = (&correction.original, &correction.corrected);
+        let vocabulary = &mut config.vocabulary;
+        let room = vocabulary.len() < MAX_VOCABULARY_ENTRIES;
+        match vocabulary.iter_mut().find(|entry| entry.term.eq_ignore_ascii_case(corrected)) {
+            Some(entry) if !entry.sounds_like.iter().any(|alias| alias.eq_ignore_ascii_case(original)) => {
+                entry.sounds_like.push(original.clone());
+            }
+            Some(_) => {}
+            None if room => {
+                vocabulary.push(VocabularyEntry { term: corrected.clone(), sounds_like: vec![original.clone()] });
+            }
+            None => tracing::debug!("The vocabulary is full; not adding {:?}", corrected),
+        }
+
+        if !config.replacements.iter().any(|rule| !rule.is_regex && rule.pattern.eq_ignore_ascii_case(original)) {
+            config.replacements.push(ReplacementRule {
+                pattern: original.clone(),
+                replacement: corrected.clone(),
+                apply_to_raw: true,
+                ..ReplacementRule::default()
+            });
+        }
+    }
+}
+
+/// The words that differ between `original` and `corrected`, without the
+/// words both start and end with and the punctuation around them
+///
+/// `None` when nothing changed, or words were only added or removed.
+pub fn changed_words(original: &str, corrected: &str) -> Option<(String, String)> {
+    let before: Vec<&str> = original.split_whitespace().collect();
+    let after: Vec<&str> = corrected.split_whitespace().collect();
+    let prefix = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..].iter().rev().zip(after[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+
+    let span = |words: &[&str]| {
+        let text = words.join(" ");
+        text.trim_matches(|c: char| !c.is_alphanumeric()).to_string()
+    };
+    let original = span(&before[prefix..before.len() - suffix]);
+    let corrected = span(&after[prefix..after.len() - suffix]);
+    (!original.is_empty() && !corrected.is_empty() && original != corrected).then_some((original, corrected))
+}
+
+/// Whether a correction is short plain text, safe to turn into a
+/// vocabulary term and a literal rule
+///
+/// Changes of case alone are only learned for terms cased inside a word
+/// ("GitHub", "iPhone"), so the start of a sentence isn't.
+fn is_learnable(original: &str, corrected: &str) -> bool {
+    let plain = |text: &str| {
+        let chars = text.chars().count();
+        (1..=MAX_VOCABULARY_TERM_CHARS).contains(&chars)
+            && text.split_whitespace().count() <= MAX_CORRECTION_WORDS
+            && !text.contains(SPECIAL_CHARS)
+            && !text.contains(char::is_control)
+    };
+    let cased_inside = corrected.split_whitespace().any(|word| word.chars().skip(1).any(char::is_uppercase));
+    plain(original)
+        && plain(corrected)
+        && original.chars().count() >= MIN_ORIGINAL_CHARS
+        && (original.to_lowercase() != corrected.to_lowercase() || cased_inside)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_corrections(name: &str) -> Corrections {
+        let path = std::env::temp_dir().join(format!("voiceflow-corrections-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Corrections::new(path)
+    }
+
+    fn options(min_reports: u32) -> LearningOptions {
+        LearningOptions { enabled: true, min_reports, ..LearningOptions::default() }
+    }
+
+    #[test]
+    fn test_changed_words_are_cut_out_of_the_text() {
+        let changed = |a, b| changed_words(a, b).map(|(a, b)| format!("{} -> {}", a, b));
+        assert_eq!(
+            changed("Scale the cooper netties deployment.", "Scale the Kubernetes deployment."),
+            Some("cooper netties -> Kubernetes".to_string())
+        );
+        assert_eq!(changed("We use cooper netties.", "We use Kubernetes."), Some("cooper netties -> Kubernetes".to_string()));
+        assert_eq!(changed("Same text.", "Same text."), None);
+        assert_eq!(changed("Ends here.", "Ends here!"), None);
+        assert_eq!(changed("Send it now.", "Send it right now."), None);
+    }
+
+    #[test]
+    fn test_regex_like_and_long_corrections_are_never_learned() {
+        assert!(is_learnable("cooper netties", "Kubernetes"));
+        assert!(is_learnable("node js", "Node.js"));
+        assert!(is_learnable("github", "GitHub"));
+        assert!(!is_learnable("hello", "Hello"));
+        assert!(!is_learnable("to", "two"));
+        assert!(!is_learnable("foo", "(bar|baz)+"));
+        assert!(!is_learnable("cost", "$1"));
+        assert!(!is_learnable(&"word ".repeat(20), "short"));
+        assert!(!is_learnable("one two three four five", "one"));
+
+        let corrections = temp_corrections("unlearnable");
+        assert_eq!(corrections.record("I like cats or dogs.", "I like cat|dog.").unwrap(), None);
+        assert!(corrections.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_repeated_corrections_are_promoted_once() {
+        let corrections = temp_corrections("promote");
+        for _ in 0..3 {
+            corrections.record("Scale the cooper netties cluster.", "Scale the Kubernetes cluster.").unwrap();
+        }
+        corrections.record("Check griffon a now.", "Check Grafana now.").unwrap();
+        let counted = corrections.list().unwrap();
+        assert_eq!(counted.len(), 2);
+        assert_eq!((counted[0].original.as_str(), counted[0].count), ("cooper netties", 3));
+
+        // Only the correction reported often enough is ready
+        let ready = corrections.ready(&options(3)).unwrap();
+        assert_eq!(ready.len(), 1);
+        let mut config = Config::default();
+        learn(&mut config, &ready);
+        learn(&mut config, &ready);
+        assert_eq!(config.vocabulary, [VocabularyEntry { term: "Kubernetes".to_string(), sounds_like: vec!["cooper netties".to_string()] }]);
+        assert_eq!(config.replacements.len(), 1);
+        assert_eq!(
+            (config.replacements[0].pattern.as_str(), config.replacements[0].replacement.as_str()),
+            ("cooper netties", "Kubernetes")
+        );
+        assert!(!config.replacements[0].is_regex);
+
+        corrections.mark_promoted(&ready).unwrap();
+        assert!(corrections.ready(&options(3)).unwrap().is_empty());
+        assert!(corrections.ready(&LearningOptions { max_learned: 1, ..options(1) }).unwrap().is_empty());
+        assert_eq!(corrections.ready(&options(1)).unwrap()[0].corrected, "Grafana");
+
+        assert_eq!(corrections.clear().unwrap(), 2);
+        assert!(corrections.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_inconsistent_corrections_are_not_promoted() {
+        let corrections = temp_corrections("agreement");
+        for corrected in ["Write to Anya.", "Write to Anya.", "Write to Anya.", "Rite to Anya."] {
+            corrections.record("Right to Anya.", corrected).unwrap();
+        }
+        // 3 of the 4 reports of "Right" agree, under the default 0.8
+        assert!(corrections.ready(&options(3)).unwrap().is_empty());
+        let lenient = LearningOptions { min_agreement: 0.75, ..options(3) };
+        assert_eq!(corrections.ready(&lenient).unwrap()[0].corrected, "Write");
+        std::fs::remove_file(corrections.path()).unwrap();
+    }
+}
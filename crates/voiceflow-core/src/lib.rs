@@ -8,17 +8,53 @@
 //! - Prosody analysis for punctuation detection
 
 pub mod audio;
+pub mod batch;
+pub mod cancel;
 pub mod config;
 pub mod context;
+pub mod diarize;
+pub mod downloads;
+#[cfg(feature = "eval")]
+pub mod eval;
+pub mod history;
+pub mod idle;
+pub mod integrity;
+pub mod learning;
 pub mod llm;
+pub mod models;
+pub mod output;
+pub mod progress;
 pub mod prosody;
+pub mod self_test;
+pub mod session;
+pub mod streaming;
+pub mod text;
 pub mod transcribe;
 
+mod builder;
 mod pipeline;
+mod segment;
 
-pub use config::{Config, LlmModel, WhisperModel, ConfigError, env_vars};
-pub use pipeline::{Pipeline, PipelineResult, ProsodyOptions, Timings, RecoveryConfig, PipelineError};
+pub use batch::{BatchInput, BatchOptions, BatchProgress};
+pub use builder::{BuildError, PipelineBuilder, VadSettings};
+pub use cancel::{CancelToken, TimeoutStage};
+pub use context::DictationContext;
+pub use config::{Config, EndpointingOptions, LlmModel, ModelPrecision, PreflightReport, WhisperModel, ConfigError, NormalizeMode, ReplacementRule, SttExecutionProvider, SttTask, VocabularyEntry, env_vars};
+pub use history::History;
+pub use idle::IdleUnloader;
+pub use learning::{Correction, Corrections};
+pub use llm::{FormattingPreset, PromptTruncation, TextFormatter, TokenSink};
+pub use pipeline::{
+    FormattingMode, InitProgress, InitStage, Pipeline, PipelineResult, ProcessOptions, ProcessProfile, ProsodyOptions, Timings,
+    QueuedRequest, RecoveryConfig, PipelineError, RESULT_SCHEMA_VERSION,
+};
+pub use progress::{ProcessProgress, ProcessStage, ProgressReporter, ProgressSink, RawTranscriptHook, RawTranscriptSink};
 pub use prosody::{ProsodyHints, PitchContour};
+pub use self_test::SelfTestReport;
+pub use segment::Segment;
+pub use session::SessionState;
+pub use streaming::{StreamingSession, Utterance};
+pub use transcribe::{ActiveSttEngine, SpeechToText};
 
 /// Process audio samples and return formatted text
 ///
@@ -27,7 +63,7 @@ pub fn process_audio(
     audio: &[f32],
     context: Option<&str>,
     config: &Config,
-) -> anyhow::Result<PipelineResult> {
+) -> Result<PipelineResult, PipelineError> {
     let mut pipeline = Pipeline::new(config)?;
     pipeline.process(audio, context)
 }
@@ -0,0 +1,281 @@
+//! Fitting formatting prompts into the LLM's context window
+//!
+//! When a prompt is too long, earlier dictations are cut first, then the
+//! context, and as a last resort the transcript is formatted in pieces.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::PipelineError;
+use crate::session::SessionState;
+
+/// What was cut from a request's formatting prompts so they fit in the
+/// LLM's context window
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PromptTruncation {
+    /// Earlier dictations were shortened or left out
+    pub history_truncated: bool,
+    /// The text for the prompt's `{context}` was shortened or left out
+    pub context_truncated: bool,
+    /// Pieces segments too long for the prompt were split into, each
+    /// formatted on its own and the outputs joined (0 when none was)
+    pub transcript_chunks: usize,
+}
+
+impl PromptTruncation {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn add(&mut self, other: &Self) {
+        self.history_truncated |= other.history_truncated;
+        self.context_truncated |= other.context_truncated;
+        self.transcript_chunks += other.transcript_chunks;
+    }
+}
+
+/// A segment's prompt before it's fitted to the context window
+pub(crate) struct PromptParts<'a> {
+    /// Formatting instructions, `{context}` still in them
+    pub template: &'a str,
+    /// Text for the template's `{context}`
+    pub context: &'a str,
+    /// Earlier dictations of the session and the segments before
+    pub history: &'a SessionState,
+    /// Most (estimated) tokens of `history` to include
+    pub history_tokens: usize,
+}
+
+impl PromptParts<'_> {
+    /// The prompt with nothing cut
+    pub(crate) fn full(&self) -> String {
+        self.with(self.history_tokens, self.context)
+    }
+
+    fn with(&self, history_tokens: usize, context: &str) -> String {
+        format!("{}{}", self.history.prompt_prefix(history_tokens), self.template.replace("{context}", context))
+    }
+}
+
+/// A prompt that fits, and the transcript pieces to format with it
+#[derive(Debug, PartialEq)]
+pub(crate) struct PromptPlan {
+    pub prompt: String,
+    /// The transcript, or the pieces of it to format one at a time
+    pub chunks: Vec<String>,
+    pub truncation: PromptTruncation,
+}
+
+/// Tokens a prompt may take: the context window less the room kept for the
+/// output
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PromptBudget {
+    pub tokens: usize,
+}
+
+impl PromptBudget {
+    pub(crate) fn new(context_window: usize, max_output_tokens: usize) -> Self {
+        Self { tokens: context_window.saturating_sub(max_output_tokens) }
+    }
+
+    /// Fit the prompt for `transcript` into the budget
+    ///
+    /// `count` counts the tokens of a whole prompt as the model gets it,
+    /// given the prompt template and the transcript (`""` for the template
+    /// alone).
+    pub(crate) fn fit(
+        &self,
+        parts: &PromptParts,
+        transcript: &str,
+        count: impl Fn(&str, &str) -> usize,
+    ) -> Result<PromptPlan> {
+        let fits = |prompt: &str, transcript: &str| count(prompt, transcript) <= self.tokens;
+        let plan = |prompt: String, truncation: PromptTruncation| PromptPlan {
+            prompt,
+            chunks: vec![transcript.to_string()],
+            truncation,
+        };
+        let mut truncation = PromptTruncation::default();
+
+        let full = parts.full();
+        if fits(&full, transcript) {
+            return Ok(plan(full, truncation));
+        }
+
+        // The most recent earlier dictations that fit, down to none
+        if !parts.history.is_empty() && parts.history_tokens > 0 {
+            truncation.history_truncated = true;
+            let (mut fitting, mut over) = (0, parts.history_tokens);
+            while fitting + 1 < over {
+                let middle = (fitting + over) / 2;
+                if fits(&parts.with(middle, parts.context), transcript) {
+                    fitting = middle;
+                } else {
+                    over = middle;
+                }
+            }
+            if fitting > 0 {
+                return Ok(plan(parts.with(fitting, parts.context), truncation));
+            }
+        }
+        let mut prompt = parts.with(0, parts.context);
+        if fits(&prompt, transcript) {
+            return Ok(plan(prompt, truncation));
+        }
+
+        // The longest start of the context that fits, down to none
+        if !parts.context.is_empty() && parts.template.contains("{context}") {
+            truncation.context_truncated = true;
+            let words: Vec<&str> = parts.context.split_whitespace().collect();
+            let (mut fitting, mut over) = (0, words.len());
+            while fitting + 1 < over {
+                let middle = (fitting + over) / 2;
+                if fits(&parts.with(0, &words[..middle].join(" ")), transcript) {
+                    fitting = middle;
+                } else {
+                    over = middle;
+                }
+            }
+            prompt = parts.with(0, &words[..fitting].join(" "));
+            if fitting > 0 || fits(&prompt, transcript) {
+                return Ok(plan(prompt, truncation));
+            }
+        }
+
+        // The transcript in pieces, each formatted with the whole prompt
+        let chunks = split_transcript(transcript, |piece| fits(&prompt, piece));
+        if chunks.is_empty() {
+            return Err(PipelineError::PromptTooLong { tokens: count(&prompt, ""), budget: self.tokens }.into());
+        }
+        truncation.transcript_chunks = chunks.len();
+        Ok(PromptPlan { prompt, chunks, truncation })
+    }
+}
+
+/// `transcript` in as few pieces as `fits`, ending at a sentence where one
+/// ends in the second half of the piece (empty when not even one word fits)
+fn split_transcript(transcript: &str, fits: impl Fn(&str) -> bool) -> Vec<String> {
+    let words: Vec<&str> = transcript.split_whitespace().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        // The most words from `start` that fit
+        let (mut fitting, mut over) = (start, words.len() + 1);
+        while fitting + 1 < over {
+            let middle = (fitting + over) / 2;
+            if fits(&words[start..middle].join(" ")) {
+                fitting = middle;
+            } else {
+                over = middle;
+            }
+        }
+        if fitting == start {
+            return Vec::new();
+        }
+        let mut end = fitting;
+        if end < words.len() {
+            let half = start + (end - start).div_ceil(2);
+            if let Some(sentence_end) =
+                (half..end).rev().find(|&i| words[i - 1].ends_with(['.', '?', '!']))
+            {
+                end = sentence_end;
+            }
+        }
+        chunks.push(words[start..end].join(" "));
+        start = end;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::estimate_tokens;
+
+    const TEMPLATE: &str = "Format this for {context}:\n{transcript}";
+
+    fn count(prompt: &str, transcript: &str) -> usize {
+        estimate_tokens(&prompt.replace("{transcript}", transcript))
+    }
+
+    fn history() -> SessionState {
+        let mut history = SessionState::new();
+        history.push("The first paragraph, dictated a minute ago, with a few sentences in it.");
+        history.push("Another one, as long as the first, so there is history to cut.");
+        history
+    }
+
+    #[test]
+    fn test_prompt_that_fits_is_unchanged() {
+        let history = history();
+        let parts = PromptParts { template: TEMPLATE, context: "email", history: &history, history_tokens: 400 };
+        let plan = PromptBudget::new(4096, 256).fit(&parts, "hello there", count).unwrap();
+        assert_eq!(plan.prompt, parts.full());
+        assert_eq!(plan.chunks, ["hello there"]);
+        assert!(plan.truncation.is_empty());
+    }
+
+    #[test]
+    fn test_history_is_cut_first() {
+        let history = history();
+        let parts = PromptParts { template: TEMPLATE, context: "email", history: &history, history_tokens: 400 };
+        let without_history = count(&parts.with(0, "email"), "hello there");
+        // Room for the latest dictation, its header included, but not both
+        let last = "Another one, as long as the first, so there is history to cut.";
+        let with_last = count(&parts.with(estimate_tokens(last) + 1, "email"), "hello there");
+        let plan = PromptBudget { tokens: with_last }.fit(&parts, "hello there", count).unwrap();
+        assert!(plan.prompt.contains("so there is history to cut"));
+        assert!(!plan.prompt.contains("The first paragraph"));
+        assert_eq!(
+            plan.truncation,
+            PromptTruncation { history_truncated: true, context_truncated: false, transcript_chunks: 0 }
+        );
+
+        let plan = PromptBudget { tokens: without_history }.fit(&parts, "hello there", count).unwrap();
+        assert_eq!(plan.prompt, TEMPLATE.replace("{context}", "email"));
+        assert!(plan.truncation.history_truncated);
+    }
+
+    #[test]
+    fn test_context_is_cut_after_history() {
+        let history = history();
+        let context = "a reply to a long thread about the quarterly budget review and hiring plans";
+        let parts = PromptParts { template: TEMPLATE, context, history: &history, history_tokens: 400 };
+        let tokens = count(&parts.with(0, "a reply to a long thread"), "hello there");
+        let plan = PromptBudget { tokens }.fit(&parts, "hello there", count).unwrap();
+        assert_eq!(plan.prompt, TEMPLATE.replace("{context}", "a reply to a long thread"));
+        assert_eq!(plan.chunks, ["hello there"]);
+        assert_eq!(
+            plan.truncation,
+            PromptTruncation { history_truncated: true, context_truncated: true, transcript_chunks: 0 }
+        );
+    }
+
+    #[test]
+    fn test_long_transcript_is_split_at_sentences() {
+        let history = SessionState::new();
+        let parts = PromptParts { template: TEMPLATE, context: "notes", history: &history, history_tokens: 400 };
+        let transcript = "First we met the team. Then we planned the launch and the budget. \
+                          Finally everyone went home early today.";
+        let tokens = count(&parts.with(0, ""), "Then we planned the launch and the budget. Finally");
+        let plan = PromptBudget { tokens }.fit(&parts, transcript, count).unwrap();
+        assert_eq!(plan.prompt, TEMPLATE.replace("{context}", ""));
+        assert_eq!(
+            plan.chunks,
+            ["First we met the team.", "Then we planned the launch and the budget.", "Finally everyone went home early today."]
+        );
+        assert_eq!(
+            plan.truncation,
+            PromptTruncation { history_truncated: false, context_truncated: true, transcript_chunks: 3 }
+        );
+        assert_eq!(plan.chunks.join(" "), transcript.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    #[test]
+    fn test_prompt_too_long_for_any_transcript() {
+        let history = SessionState::new();
+        let parts = PromptParts { template: TEMPLATE, context: "", history: &history, history_tokens: 0 };
+        let err = PromptBudget { tokens: 4 }.fit(&parts, "hello there", count).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PipelineError>(), Some(PipelineError::PromptTooLong { .. })), "{}", err);
+    }
+}
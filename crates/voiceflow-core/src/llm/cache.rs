@@ -0,0 +1,156 @@
+//! Cache of LLM formatting results, so formatting the same transcript the
+//! same way again skips the model
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+use serde::Serialize;
+
+use super::{FormattingPreset, LlmOutput, LlmStats};
+
+/// Everything a formatting result depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FormatCacheKey {
+    /// Transcript with runs of whitespace collapsed to one space
+    transcript: String,
+    preset: Option<String>,
+    /// Context hint, e.g. "email", whether or not the prompt shows it
+    context: String,
+    /// Hash of the prompt as the model gets it, transcript placeholder
+    /// included
+    prompt: u64,
+    /// Formatter backend and model
+    model: String,
+    /// Hash of the sampling parameters and output rules
+    sampling: u64,
+}
+
+impl FormatCacheKey {
+    pub(crate) fn new(
+        transcript: &str,
+        preset: Option<&FormattingPreset>,
+        context: &str,
+        prompt: &str,
+        model: &impl Serialize,
+        sampling: &impl Serialize,
+    ) -> Self {
+        Self {
+            transcript: transcript.split_whitespace().collect::<Vec<_>>().join(" "),
+            preset: preset.map(|preset| preset.id().to_string()),
+            context: context.to_string(),
+            prompt: hash(&prompt),
+            model: serde_json::to_string(model).unwrap_or_default(),
+            sampling: hash(&serde_json::to_string(sampling).unwrap_or_default()),
+        }
+    }
+}
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Least recently used formatting results, up to a fixed number
+#[derive(Debug, Default)]
+pub(crate) struct FormatCache {
+    capacity: usize,
+    /// Most recently used last
+    entries: VecDeque<(FormatCacheKey, LlmOutput)>,
+}
+
+impl FormatCache {
+    /// Cache holding up to `capacity` results (0 disables it)
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity, entries: VecDeque::with_capacity(capacity) }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The result stored for `key`, marked as most recently used
+    pub(crate) fn get(&mut self, key: &FormatCacheKey) -> Option<LlmOutput> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        let output = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(output)
+    }
+
+    /// Store a result, evicting the least recently used one when full
+    ///
+    /// The model's timing isn't kept: a cached result took no generation.
+    pub(crate) fn insert(&mut self, key: FormatCacheKey, output: LlmOutput) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.entries.retain(|(k, _)| *k != key);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, LlmOutput { stats: LlmStats::default(), ..output }));
+    }
+
+    /// Hold up to `capacity` results, dropping the least recently used ones
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let excess = self.entries.len().saturating_sub(capacity);
+        self.entries.drain(..excess);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(transcript: &str) -> FormatCacheKey {
+        FormatCacheKey::new(transcript, None, "default", "Format: {transcript}", &"qwen3-1.7b", &0.1)
+    }
+
+    fn output(text: &str) -> LlmOutput {
+        LlmOutput {
+            text: text.to_string(),
+            raw: text.to_string(),
+            stats: LlmStats { tokens_generated: 3, ..Default::default() },
+        }
+    }
+
+    #[test]
+    fn test_key_normalizes_whitespace_only() {
+        assert_eq!(key("send it"), key("  send \n it "));
+        assert_ne!(key("send it"), key("Send it"));
+        assert_ne!(key("send it"), FormatCacheKey::new("send it", None, "default", "Other: {transcript}", &"qwen3-1.7b", &0.1));
+        assert_ne!(key("send it"), FormatCacheKey::new("send it", None, "default", "Format: {transcript}", &"qwen3-4b", &0.1));
+        assert_ne!(key("send it"), FormatCacheKey::new("send it", None, "default", "Format: {transcript}", &"qwen3-1.7b", &0.7));
+        let preset = Some(&FormattingPreset::Email);
+        assert_ne!(key("send it"), FormatCacheKey::new("send it", preset, "default", "Format: {transcript}", &"qwen3-1.7b", &0.1));
+        assert_ne!(key("send it"), FormatCacheKey::new("send it", None, "email", "Format: {transcript}", &"qwen3-1.7b", &0.1));
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = FormatCache::new(2);
+        cache.insert(key("one"), output("One."));
+        cache.insert(key("two"), output("Two."));
+        // Using "one" leaves "two" as the oldest
+        assert_eq!(cache.get(&key("one")).unwrap().text, "One.");
+        cache.insert(key("three"), output("Three."));
+        assert!(cache.get(&key("two")).is_none());
+        assert_eq!(cache.get(&key("three")).unwrap().stats, LlmStats::default());
+        assert_eq!(cache.entries.len(), 2);
+
+        cache.set_capacity(1);
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.get(&key("three")).is_some());
+
+        cache.set_capacity(0);
+        cache.insert(key("four"), output("Four."));
+        assert_eq!(cache.entries.len(), 0);
+    }
+}
@@ -1,30 +1,172 @@
 //! LLM engine using mistral.rs for cross-platform inference
 //! Supports Metal (macOS), CUDA (Linux), and CPU fallback
 
-use crate::config::Config;
+use crate::cancel::CancelToken;
+use crate::config::{Config, LlmOptions};
+use crate::integrity::verify_file;
+use crate::llm::prefix::{static_prefix, PrefixKey, PrimedPrefix, PREFIX_CACHE_SEQUENCES};
 use crate::llm::prompts::{format_prompt, post_process_output};
+use crate::llm::sanitize::OutputSanitizer;
+use crate::models::shared::{ModelKey, SharedModels};
+use crate::session::estimate_tokens;
+use crate::PipelineError;
 use anyhow::{Context, Result};
-use mistralrs::{GgufModelBuilder, Model, RequestBuilder, TextMessages, TextMessageRole};
+use either::Either;
+use mistralrs::{GgufModelBuilder, Model, RequestBuilder, Response, TextMessages, TextMessageRole};
 use std::sync::Arc;
+use std::time::Instant;
+
+/// Timing of a single LLM request
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LlmStats {
+    /// Prompt processing, until the first token arrives
+    pub prefill_ms: u64,
+    /// Generation of the remaining tokens
+    pub generate_ms: u64,
+    pub tokens_generated: u32,
+    /// Tokens spent in `<think>` blocks (part of `tokens_generated`)
+    pub thinking_tokens: u32,
+}
+
+impl LlmStats {
+    /// Generation speed after the first token (0.0 if too short to measure)
+    pub fn tokens_per_second(&self) -> f32 {
+        if self.generate_ms == 0 || self.tokens_generated < 2 {
+            return 0.0;
+        }
+        (self.tokens_generated - 1) as f32 * 1000.0 / self.generate_ms as f32
+    }
+}
+
+/// Receives the formatted text token by token as the LLM generates it
+///
+/// Fragments are whole UTF-8 characters. Reasoning and anything after a stop
+/// sequence are held back, but the rest of the output cleanup (and a
+/// fallback to the raw transcript) only applies to the final text.
+pub trait TokenSink: Send {
+    fn token(&mut self, text: &str);
+}
+
+impl<F: FnMut(&str) + Send> TokenSink for F {
+    fn token(&mut self, text: &str) {
+        self(text)
+    }
+}
+
+/// Result of a single LLM request
+#[derive(Debug, Clone, Default)]
+pub struct LlmOutput {
+    /// Cleaned-up output
+    pub text: String,
+    /// Output exactly as the model produced it
+    pub raw: String,
+    pub stats: LlmStats,
+}
+
+/// Per-call formatting settings
+#[derive(Debug, Clone, Copy)]
+pub struct FormatContext<'a> {
+    /// Prompt with the transcript placeholder, already chosen for the
+    /// context and preset
+    pub prompt_template: &'a str,
+    /// Decoding parameters
+    pub llm_options: &'a LlmOptions,
+    /// Checked as the formatter goes, to stop early
+    pub cancel: &'a CancelToken,
+}
+
+/// Formats transcripts: `LlmEngine`, or a formatter supplied to
+/// `PipelineBuilder::llm_engine`
+pub trait TextFormatter: Send {
+    /// Format `transcript`, returning the cleaned-up text
+    fn format(&mut self, transcript: &str, ctx: &FormatContext) -> Result<String>;
+
+    /// Same as `format`, also returning the raw output and the request's
+    /// timing, and streaming tokens to `sink` if given
+    ///
+    /// By default the whole text is the raw output, with no timing and no
+    /// tokens streamed.
+    fn format_with_stats(
+        &mut self,
+        transcript: &str,
+        ctx: &FormatContext,
+        _sink: Option<&mut dyn TokenSink>,
+    ) -> Result<LlmOutput> {
+        let text = self.format(transcript, ctx)?;
+        Ok(LlmOutput { raw: text.clone(), text, stats: LlmStats::default() })
+    }
+
+    /// Use the prompts and output rules of `config` from now on
+    fn update_config(&mut self, _config: &Config) -> Result<()> {
+        Ok(())
+    }
+
+    /// Number of tokens the model reads `text` as, used to fit prompts in
+    /// the context window
+    ///
+    /// By default estimated from its length.
+    fn count_tokens(&self, text: &str) -> usize {
+        estimate_tokens(text)
+    }
+
+    /// Prefill the prompt up to the transcript, so requests that start the
+    /// same way only prefill the transcript
+    ///
+    /// By default does nothing.
+    fn prime(&mut self, _ctx: &FormatContext) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Loaded LLMs, shared by the engines that load the same file with the
+/// same chat template, seed and prefix cache; mistral.rs runs each engine's
+/// requests as sequences of their own
+pub(crate) static LLM_MODELS: SharedModels<Model> = SharedModels::new("llm");
 
 /// LLM engine for text formatting using mistral.rs
 pub struct LlmEngine {
+    /// Shared (see `LLM_MODELS`)
     model: Arc<Model>,
     config: Config,
+    sanitizer: OutputSanitizer,
+    /// `None` with `Config::llm_prefix_cache` off
+    primed: Option<PrimedPrefix>,
 }
 
 impl LlmEngine {
     /// Create a new LLM engine with the given configuration (async)
     pub async fn new_async(config: &Config) -> Result<Self> {
+        let sanitizer = OutputSanitizer::compile(&config.llm_output)?;
         let model_path = config.llm_model_path()?;
 
         if !model_path.exists() {
-            anyhow::bail!(
-                "LLM model not found at {:?}. Run 'voiceflow setup' to download models.",
-                model_path
-            );
+            return Err(PipelineError::LlmModelNotFound {
+                path: model_path.display().to_string(),
+            }
+            .into());
         }
+        verify_file(&config.models_dir()?, &model_path, config.verify_models)?;
+
+        // Loads that differ in these load weights of their own
+        let settings = format!(
+            "{:?} seed={:?} prefix_cache={}",
+            config.chat_template,
+            config.llm_seed(),
+            config.llm_prefix_cache
+        );
+        let key = ModelKey::new(&model_path, settings);
+        let model = LLM_MODELS.get_or_load_async(key, || Self::load_model(&model_path, config)).await?;
 
+        Ok(Self {
+            model,
+            config: config.clone(),
+            sanitizer,
+            primed: config.llm_prefix_cache.then(PrimedPrefix::default),
+        })
+    }
+
+    /// Load the model file at `model_path` with the settings of `config`
+    async fn load_model(model_path: &std::path::Path, config: &Config) -> Result<Model> {
         tracing::info!("Loading LLM model from {:?}", model_path);
 
         // Get parent directory and filename
@@ -40,18 +182,28 @@ impl LlmEngine {
 
         // Build model using mistral.rs async API
         // Note: We avoid PagedAttention for now as it can cause Metal shader conflicts
-        let model = GgufModelBuilder::new(model_dir, vec![model_file])
-            .with_logging()
+        let mut builder = GgufModelBuilder::new(model_dir, vec![model_file]).with_logging();
+        if let Some(template) = config.chat_template.jinja() {
+            tracing::info!("Using {} chat template", config.chat_template.id());
+            builder = builder.with_chat_template(template);
+        }
+        if let Some(seed) = config.llm_seed() {
+            builder = builder.with_seed(seed);
+        }
+        builder = builder.with_prefix_cache_n(config.llm_prefix_cache.then_some(PREFIX_CACHE_SEQUENCES));
+        // candle sizes its CPU kernels' parallelism from this variable, for
+        // the whole process; left alone with the default of one per core
+        if let Some(threads) = config.llm_thread_count() {
+            tracing::info!("LLM using {} CPU threads", threads);
+            std::env::set_var("RAYON_NUM_THREADS", threads.to_string());
+        }
+        let model = builder
             .build()
             .await
             .context("Failed to load LLM model with mistral.rs")?;
 
-        tracing::info!("LLM model loaded: {}", config.llm_model.display_name());
-
-        Ok(Self {
-            model: Arc::new(model),
-            config: config.clone(),
-        })
+        tracing::info!("LLM model loaded: {}", config.llm_display_name());
+        Ok(model)
     }
 
     /// Create a new LLM engine (blocking wrapper for sync contexts)
@@ -77,76 +229,204 @@ impl LlmEngine {
         }
     }
 
+    /// Use the prompts and output rules of `config` from now on
+    ///
+    /// The loaded model stays as it is; fields that choose or load the
+    /// model are ignored. A prompt start that changes with it is prefilled
+    /// again by the next `prime` or request.
+    pub fn update_config(&mut self, config: &Config) -> Result<()> {
+        self.sanitizer = OutputSanitizer::compile(&config.llm_output)?;
+        self.config = config.clone();
+        Ok(())
+    }
+
     /// Format a transcript using the LLM (async)
     pub async fn format_async(&self, transcript: &str, prompt_template: &str) -> Result<String> {
+        self.format_async_with_cancel(transcript, prompt_template, &CancelToken::new()).await
+    }
+
+    /// Format a transcript using the LLM (async), stopping if `cancel` is triggered
+    pub async fn format_async_with_cancel(
+        &self,
+        transcript: &str,
+        prompt_template: &str,
+        cancel: &CancelToken,
+    ) -> Result<String> {
+        self.format_async_with_options(transcript, prompt_template, &self.config.llm_options, cancel)
+            .await
+    }
+
+    /// Format a transcript using the LLM (async) with the given decoding
+    /// parameters instead of the configured ones
+    pub async fn format_async_with_options(
+        &self,
+        transcript: &str,
+        prompt_template: &str,
+        llm_options: &LlmOptions,
+        cancel: &CancelToken,
+    ) -> Result<String> {
+        let output = self
+            .format_async_with_stats(transcript, prompt_template, llm_options, cancel, None)
+            .await?;
+        Ok(output.text)
+    }
+
+    /// Same as `format_async_with_options`, also returning the raw output
+    /// and the request's timing, and streaming tokens to `sink` if given
+    pub async fn format_async_with_stats(
+        &self,
+        transcript: &str,
+        prompt_template: &str,
+        llm_options: &LlmOptions,
+        cancel: &CancelToken,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<LlmOutput> {
         let prompt = format_prompt(prompt_template, transcript, &self.config);
 
         tracing::debug!("LLM prompt length: {} chars", prompt.len());
 
-        // Build messages with thinking disabled for fast inference (enable_thinking defaults to false)
-        let messages = TextMessages::new()
-            .enable_thinking(self.config.llm_options.enable_thinking)
-            .add_message(TextMessageRole::User, &prompt);
+        let visible = sink.map(|sink| VisibleTokens::new(sink, self.sanitizer.stop_sequences()));
+        let (raw, stats) = run_chat(&self.model, llm_options, &prompt, cancel, visible).await?;
+        // The model keeps this request's prefill, prompt start included
+        if let Some(key) = self.prefix_key(prompt_template, llm_options) {
+            if self.primed.as_ref().is_some_and(|primed| primed.replace(key)) {
+                tracing::debug!("LLM reused the prefill of the prompt's start");
+            }
+        }
+        let text = post_process_output(&self.sanitizer.sanitize(&strip_thinking_tags(&raw)));
+
+        tracing::debug!(
+            "LLM output length: {} chars ({} tokens, prefill {}ms, generation {}ms)",
+            text.len(),
+            stats.tokens_generated,
+            stats.prefill_ms,
+            stats.generate_ms
+        );
 
-        // Build request with sampling parameters
-        let request = RequestBuilder::from(messages)
-            .set_sampler_max_len(self.config.llm_options.max_tokens as usize)
-            .set_sampler_temperature(self.config.llm_options.temperature as f64)
-            .set_sampler_topp(self.config.llm_options.top_p as f64);
+        Ok(LlmOutput { text, raw, stats })
+    }
 
-        // Run inference
-        let response = self.model.send_chat_request(request).await
-            .context("LLM inference failed")?;
+    /// Which start of the prompt the prefix cache keeps, `None` with the
+    /// cache off
+    fn prefix_key(&self, prompt_template: &str, llm_options: &LlmOptions) -> Option<PrefixKey> {
+        self.primed.as_ref()?;
+        static_prefix(prompt_template, &self.config).map(|prefix| PrefixKey::new(&prefix, llm_options))
+    }
 
-        // Extract response text and strip any thinking tags
-        let output = response
-            .choices
-            .first()
-            .and_then(|c| c.message.content.as_ref())
-            .map(|s| strip_thinking_tags(s.trim()))
-            .unwrap_or_default();
+    /// Prefill the prompt up to the transcript and keep it in the model's
+    /// prefix cache (async)
+    ///
+    /// Does nothing if that start is already kept, or with
+    /// `Config::llm_prefix_cache` off. Requests that start the same way,
+    /// with the same sampling parameters, then only prefill the transcript.
+    pub async fn prime_async(&self, prompt_template: &str, llm_options: &LlmOptions, cancel: &CancelToken) -> Result<()> {
+        let Some(primed) = self.primed.as_ref() else {
+            return Ok(());
+        };
+        let Some(prefix) = static_prefix(prompt_template, &self.config) else {
+            return Ok(());
+        };
+        let key = PrefixKey::new(&prefix, llm_options);
+        if primed.is(key) {
+            return Ok(());
+        }
 
-        tracing::debug!("LLM output length: {} chars", output.len());
+        let llm_options = LlmOptions { max_tokens: 1, ..llm_options.clone() };
+        let (_, stats) = run_chat(&self.model, &llm_options, &prefix, cancel, None).await?;
+        primed.replace(key);
+        tracing::debug!("LLM prefilled the prompt's start ({} chars) in {}ms", prefix.len(), stats.prefill_ms);
+        Ok(())
+    }
 
-        Ok(output)
+    /// Prefill the prompt up to the transcript (blocking wrapper, see
+    /// `prime_async`)
+    pub fn prime(&self, prompt_template: &str, llm_options: &LlmOptions, cancel: &CancelToken) -> Result<()> {
+        match tokio::runtime::Handle::try_current() {
+            Ok(_handle) => {
+                // We're in an async context - use spawn_blocking
+                std::thread::scope(|s| {
+                    s.spawn(|| {
+                        let rt = tokio::runtime::Runtime::new()?;
+                        rt.block_on(self.prime_async(prompt_template, llm_options, cancel))
+                    }).join().unwrap()
+                })
+            }
+            Err(_) => {
+                // No runtime, create one
+                let rt = tokio::runtime::Runtime::new()
+                    .context("Failed to create tokio runtime")?;
+                rt.block_on(self.prime_async(prompt_template, llm_options, cancel))
+            }
+        }
     }
 
     /// Format a transcript using the LLM (blocking wrapper)
     pub fn format(&self, transcript: &str, prompt_template: &str) -> Result<String> {
+        self.format_with_cancel(transcript, prompt_template, &CancelToken::new())
+    }
+
+    /// Format a transcript using the LLM (blocking wrapper), stopping if
+    /// `cancel` is triggered
+    pub fn format_with_cancel(
+        &self,
+        transcript: &str,
+        prompt_template: &str,
+        cancel: &CancelToken,
+    ) -> Result<String> {
+        self.format_with_options(transcript, prompt_template, &self.config.llm_options, cancel)
+    }
+
+    /// Format a transcript using the LLM (blocking wrapper) with the given
+    /// decoding parameters instead of the configured ones
+    pub fn format_with_options(
+        &self,
+        transcript: &str,
+        prompt_template: &str,
+        llm_options: &LlmOptions,
+        cancel: &CancelToken,
+    ) -> Result<String> {
+        let output = self.format_with_stats(transcript, prompt_template, llm_options, cancel, None)?;
+        Ok(output.text)
+    }
+
+    /// Number of tokens the model's tokenizer splits `text` into (estimated
+    /// if tokenizing fails)
+    pub fn count_tokens(&self, text: &str) -> usize {
+        let tokenize = || -> Result<usize> {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .context("Failed to create tokio runtime")?;
+            let tokens = rt.block_on(self.model.tokenize(Either::Right(text.to_string()), None, false, false, None))?;
+            Ok(tokens.len())
+        };
+        let counted = match tokio::runtime::Handle::try_current() {
+            // We're in an async context - tokenize on a thread of its own
+            Ok(_handle) => std::thread::scope(|s| s.spawn(tokenize).join().unwrap()),
+            Err(_) => tokenize(),
+        };
+        counted.unwrap_or_else(|e| {
+            tracing::debug!("Tokenizing failed, estimating the token count: {:#}", e);
+            estimate_tokens(text)
+        })
+    }
+
+    /// Same as `format_with_options`, also returning the raw output and the
+    /// request's timing, and streaming tokens to `sink` if given
+    pub fn format_with_stats(
+        &self,
+        transcript: &str,
+        prompt_template: &str,
+        llm_options: &LlmOptions,
+        cancel: &CancelToken,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<LlmOutput> {
         match tokio::runtime::Handle::try_current() {
             Ok(_handle) => {
                 // We're in an async context - use spawn_blocking
-                let model = Arc::clone(&self.model);
-                let config = self.config.clone();
-                let transcript = transcript.to_string();
-                let prompt_template = prompt_template.to_string();
-
                 std::thread::scope(|s| {
                     s.spawn(|| {
                         let rt = tokio::runtime::Runtime::new()?;
-                        rt.block_on(async {
-                            let prompt = format_prompt(&prompt_template, &transcript, &config);
-                            let messages = TextMessages::new()
-                                .enable_thinking(config.llm_options.enable_thinking)
-                                .add_message(TextMessageRole::User, &prompt);
-
-                            let request = RequestBuilder::from(messages)
-                                .set_sampler_max_len(config.llm_options.max_tokens as usize)
-                                .set_sampler_temperature(config.llm_options.temperature as f64)
-                                .set_sampler_topp(config.llm_options.top_p as f64);
-
-                            let response = model.send_chat_request(request).await
-                                .context("LLM inference failed")?;
-
-                            let output = response
-                                .choices
-                                .first()
-                                .and_then(|c| c.message.content.as_ref())
-                                .map(|s| strip_thinking_tags(s.trim()))
-                                .unwrap_or_default();
-
-                            Ok(output)
-                        })
+                        rt.block_on(self.format_async_with_stats(transcript, prompt_template, llm_options, cancel, sink))
                     }).join().unwrap()
                 })
             }
@@ -154,18 +434,211 @@ impl LlmEngine {
                 // No runtime, create one
                 let rt = tokio::runtime::Runtime::new()
                     .context("Failed to create tokio runtime")?;
-                rt.block_on(self.format_async(transcript, prompt_template))
+                rt.block_on(self.format_async_with_stats(transcript, prompt_template, llm_options, cancel, sink))
+            }
+        }
+    }
+}
+
+impl TextFormatter for LlmEngine {
+    fn format(&mut self, transcript: &str, ctx: &FormatContext) -> Result<String> {
+        self.format_with_options(transcript, ctx.prompt_template, ctx.llm_options, ctx.cancel)
+    }
+
+    fn format_with_stats(
+        &mut self,
+        transcript: &str,
+        ctx: &FormatContext,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<LlmOutput> {
+        LlmEngine::format_with_stats(self, transcript, ctx.prompt_template, ctx.llm_options, ctx.cancel, sink)
+    }
+
+    fn update_config(&mut self, config: &Config) -> Result<()> {
+        LlmEngine::update_config(self, config)
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        LlmEngine::count_tokens(self, text)
+    }
+
+    fn prime(&mut self, ctx: &FormatContext) -> Result<()> {
+        LlmEngine::prime(self, ctx.prompt_template, ctx.llm_options, ctx.cancel)
+    }
+}
+
+/// Forwards the visible part of the output to a `TokenSink`: no reasoning,
+/// and nothing from the first stop sequence on
+struct VisibleTokens<'a> {
+    sink: &'a mut dyn TokenSink,
+    stop_sequences: &'a [String],
+    forwarded: bool,
+    stopped: bool,
+}
+
+impl<'a> VisibleTokens<'a> {
+    fn new(sink: &'a mut dyn TokenSink, stop_sequences: &'a [String]) -> Self {
+        Self { sink, stop_sequences, forwarded: false, stopped: false }
+    }
+
+    fn push(&mut self, token: &str, thinking: bool) {
+        if self.stopped || thinking || token.contains(THINK_CLOSE) {
+            return;
+        }
+        let mut token = token;
+        if let Some(stop) = self.stop_sequences.iter().filter_map(|stop| token.find(stop.as_str())).min() {
+            // A leading stop sequence is dropped, like the sanitizer does
+            if !self.forwarded && token[..stop].trim().is_empty() {
+                return;
+            }
+            self.stopped = true;
+            token = &token[..stop];
+        }
+        if !self.forwarded {
+            token = token.trim_start();
+        }
+        if !token.is_empty() {
+            self.forwarded = true;
+            self.sink.token(token);
+        }
+    }
+}
+
+/// Run a single chat request, streaming tokens so cancellation is checked
+/// between them
+///
+/// Dropping the stream on cancel closes the response channel, which makes
+/// mistral.rs stop generating for this request. Each content chunk carries
+/// one token; mistral.rs holds back bytes until they decode to whole
+/// characters.
+async fn run_chat(
+    model: &Model,
+    llm_options: &LlmOptions,
+    prompt: &str,
+    cancel: &CancelToken,
+    mut visible: Option<VisibleTokens<'_>>,
+) -> Result<(String, LlmStats)> {
+    let llm_options = llm_options.clamped();
+
+    // Build messages with thinking disabled for fast inference (enable_thinking defaults to false)
+    let messages = TextMessages::new()
+        .enable_thinking(llm_options.enable_thinking)
+        .add_message(TextMessageRole::User, prompt);
+
+    // Build request with sampling parameters
+    let mut request = RequestBuilder::from(messages)
+        .set_sampler_max_len(llm_options.max_tokens as usize)
+        .set_sampler_topp(llm_options.top_p as f64);
+    request = if llm_options.temperature == 0.0 {
+        request.set_deterministic_sampler()
+    } else {
+        request.set_sampler_temperature(llm_options.temperature as f64)
+    };
+    if llm_options.top_k > 0 {
+        request = request.set_sampler_topk(llm_options.top_k as usize);
+    }
+    if llm_options.repeat_penalty != 1.0 {
+        request = request.set_sampler_repetition_penalty(llm_options.repeat_penalty);
+    }
+
+    // Run inference
+    let start = Instant::now();
+    let mut first_token_at = None;
+    let mut tokens_generated = 0u32;
+    let mut thinking_tokens = 0u32;
+    let mut thinking = false;
+    let mut stream = model.stream_chat_request(request).await
+        .context("LLM inference failed")?;
+
+    let mut raw = String::new();
+    loop {
+        if cancel.is_cancelled() {
+            return Err(PipelineError::cancelled().into());
+        }
+
+        match stream.next().await {
+            Some(Response::Chunk(chunk)) => {
+                if let Some(choice) = chunk.choices.first() {
+                    if let Some(content) = choice.delta.content.as_deref() {
+                        first_token_at.get_or_insert_with(Instant::now);
+                        tokens_generated += 1;
+                        // <think> and </think> are single tokens
+                        if content.contains(THINK_OPEN) {
+                            thinking = true;
+                        }
+                        if thinking {
+                            thinking_tokens += 1;
+                        }
+                        if let Some(visible) = visible.as_mut() {
+                            visible.push(content, thinking);
+                        }
+                        if content.contains(THINK_CLOSE) {
+                            if !thinking && !raw.contains(THINK_OPEN) {
+                                // The chat template opened the block
+                                thinking_tokens = tokens_generated;
+                            }
+                            thinking = false;
+                        }
+                        raw.push_str(content);
+                    }
+                    if choice.finish_reason.is_some() {
+                        break;
+                    }
+                }
+            }
+            Some(Response::Done(response)) => {
+                // Some backends deliver the full message once instead of chunks
+                if raw.is_empty() {
+                    if let Some(content) = response.choices.first().and_then(|c| c.message.content.as_ref()) {
+                        first_token_at.get_or_insert_with(Instant::now);
+                        tokens_generated = response.usage.completion_tokens as u32;
+                        if let Some(visible) = visible.as_mut() {
+                            visible.push(&strip_thinking_tags(content), false);
+                        }
+                        raw.push_str(content);
+                    }
+                }
+                break;
+            }
+            Some(Response::ModelError(message, _)) => {
+                anyhow::bail!("LLM inference failed: {}", message);
+            }
+            Some(Response::InternalError(e)) => {
+                anyhow::bail!("LLM inference failed: {}", e);
             }
+            Some(Response::ValidationError(e)) => {
+                anyhow::bail!("LLM inference failed: {}", e);
+            }
+            Some(_) => {}
+            None => break,
         }
     }
+
+    let end = Instant::now();
+    let first_token_at = first_token_at.unwrap_or(end);
+    let stats = LlmStats {
+        prefill_ms: first_token_at.duration_since(start).as_millis() as u64,
+        generate_ms: end.duration_since(first_token_at).as_millis() as u64,
+        tokens_generated,
+        thinking_tokens,
+    };
+    if thinking_tokens > 0 {
+        tracing::debug!("LLM spent {} of {} tokens thinking", thinking_tokens, tokens_generated);
+    }
+
+    Ok((raw, stats))
 }
 
 /// Detect available hardware acceleration
 pub fn detect_hardware() -> &'static str {
-    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+    #[cfg(all(target_os = "macos", target_arch = "aarch64", feature = "metal"))]
     {
         "Metal (Apple Silicon)"
     }
+    #[cfg(all(target_os = "macos", target_arch = "aarch64", not(feature = "metal")))]
+    {
+        "CPU (Apple Silicon)"
+    }
     #[cfg(all(target_os = "macos", not(target_arch = "aarch64")))]
     {
         "CPU (Intel Mac)"
@@ -184,27 +657,40 @@ pub fn detect_hardware() -> &'static str {
     }
 }
 
-/// Strip <think>...</think> tags from model output and apply post-processing
-fn strip_thinking_tags(text: &str) -> String {
-    // Remove <think>...</think> blocks (including empty ones)
-    let mut result = text.to_string();
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// Strip <think>...</think> blocks from model output
+///
+/// A block cut off by max_tokens runs to the end of the output, and a
+/// closing tag without an opening one ends reasoning the chat template
+/// opened at the start.
+pub(super) fn strip_thinking_tags(text: &str) -> String {
+    let mut result = text;
+
+    // Reasoning opened by the chat template
+    if let Some(end) = result.find(THINK_CLOSE) {
+        if result.find(THINK_OPEN).is_none_or(|start| start > end) {
+            result = &result[end + THINK_CLOSE.len()..];
+        }
+    }
 
-    // Handle <think>content</think> pattern
-    while let Some(start) = result.find("<think>") {
-        if let Some(end) = result.find("</think>") {
-            let end_tag_end = end + "</think>".len();
+    // Remove <think>...</think> blocks (including empty ones)
+    let mut result = result.to_string();
+    while let Some(start) = result.find(THINK_OPEN) {
+        if let Some(len) = result[start..].find(THINK_CLOSE) {
+            let end_tag_end = start + len + THINK_CLOSE.len();
             result = format!(
                 "{}{}",
                 &result[..start],
                 result[end_tag_end..].trim_start()
             );
         } else {
-            break;
+            // Unclosed block: the model ran out of tokens while thinking
+            result.truncate(start);
         }
     }
-
-    // Apply shared post-processing (punctuation spacing, UI quoting, ellipsis normalization)
-    post_process_output(&result)
+    result.trim().to_string()
 }
 
 // Post-processing functions moved to prompts.rs for better separation of concerns.
@@ -214,7 +700,7 @@ fn strip_thinking_tags(text: &str) -> String {
 mod tests {
     use super::*;
 
-    // Tests for strip_thinking_tags (which now uses post_process_output internally)
+    // Tests for strip_thinking_tags
     #[test]
     fn test_strip_thinking_tags_empty() {
         assert_eq!(strip_thinking_tags("Hello world"), "Hello world");
@@ -244,11 +730,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_strip_thinking_tags_partial_blocks() {
+        assert_eq!(strip_thinking_tags("Hello world.<think>The user wants"), "Hello world.");
+        assert_eq!(strip_thinking_tags("The user said hello.</think>\n\nHello world."), "Hello world.");
+        assert_eq!(strip_thinking_tags("plan</think>Hello <think>again</think>world"), "Hello world");
+    }
+
+    #[test]
+    fn test_visible_tokens_skip_reasoning_and_stop_sequences() {
+        let mut streamed = String::new();
+        let mut sink = |text: &str| streamed.push_str(text);
+        let stops = ["<|im_end|>".to_string(), "<|assistant|>".to_string()];
+        let mut visible = VisibleTokens::new(&mut sink, &stops);
+        let tokens = ["<think>", "hmm", "</think>", "<|assistant|>", "\n\nCaf", "é at", " noon.", "<|im_end|>", "\nuser"];
+        let mut thinking = false;
+        for token in tokens {
+            thinking |= token == "<think>";
+            visible.push(token, thinking);
+            thinking &= token != "</think>";
+        }
+        assert_eq!(streamed, "Café at noon.");
+    }
+
+    #[test]
+    fn test_tokens_per_second_excludes_first_token() {
+        let stats = LlmStats { prefill_ms: 300, generate_ms: 500, tokens_generated: 21, thinking_tokens: 0 };
+        assert_eq!(stats.tokens_per_second(), 40.0);
+        assert_eq!(LlmStats { tokens_generated: 1, ..stats }.tokens_per_second(), 0.0);
+        assert_eq!(LlmStats::default().tokens_per_second(), 0.0);
+    }
+
     // Integration test for strip_thinking_tags with post-processing
     #[test]
     fn test_strip_thinking_tags_full_pipeline() {
         let input = "<think>Let me think</think>go to Settings.Click Submit";
-        let result = strip_thinking_tags(input);
+        let result = post_process_output(&strip_thinking_tags(input));
         assert!(result.contains("'Settings'"));
         assert!(result.contains("'Submit'"));
         assert!(!result.contains("<think>"));
@@ -0,0 +1,265 @@
+//! GGUF header inspection, to vet custom models before they are configured
+//!
+//! Only the metadata key-value section at the start of the file is read;
+//! tensor data is never touched.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+/// GGUF format versions mistral.rs can load
+pub const SUPPORTED_GGUF_VERSIONS: RangeInclusive<u32> = 2..=3;
+
+/// Model architectures mistral.rs can load from GGUF
+pub const SUPPORTED_ARCHITECTURES: &[&str] =
+    &["llama", "mistral", "phi2", "phi3", "qwen2", "qwen3", "smollm3", "gemma2", "starcoder2"];
+
+/// GGUF error with actionable context
+#[derive(Debug, thiserror::Error)]
+pub enum GgufError {
+    #[error("{path} is not a GGUF model file")]
+    NotGguf { path: String },
+
+    #[error("{path} uses GGUF version {version}; only versions 2 and 3 are supported")]
+    UnsupportedVersion { path: String, version: u32 },
+
+    #[error("{path} has no general.architecture metadata")]
+    MissingArchitecture { path: String },
+
+    #[error("Unsupported model architecture '{architecture}'. Supported: {}", SUPPORTED_ARCHITECTURES.join(", "))]
+    UnsupportedArchitecture { architecture: String },
+}
+
+/// Metadata read from a GGUF header
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufInfo {
+    /// `general.architecture`, e.g. "llama"
+    pub architecture: String,
+    /// `general.name`, if set
+    pub name: Option<String>,
+    /// `<architecture>.context_length`, if set
+    pub context_length: Option<u64>,
+}
+
+/// Read the architecture, name and context length from a GGUF file
+pub fn read_gguf_info(path: &Path) -> Result<GgufInfo> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut reader = BufReader::new(file);
+    let display = || path.display().to_string();
+
+    let mut magic = [0u8; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != GGUF_MAGIC {
+        return Err(GgufError::NotGguf { path: display() }.into());
+    }
+    let version = read_u32(&mut reader)?;
+    if !SUPPORTED_GGUF_VERSIONS.contains(&version) {
+        return Err(GgufError::UnsupportedVersion { path: display(), version }.into());
+    }
+    let _tensor_count = read_u64(&mut reader)?;
+    let kv_count = read_u64(&mut reader)?;
+
+    let mut architecture = None;
+    let mut name = None;
+    let mut context_length = None;
+    for _ in 0..kv_count {
+        let key = read_string(&mut reader)?;
+        let value_type = read_u32(&mut reader)?;
+        match key.as_str() {
+            "general.architecture" if value_type == TYPE_STRING => architecture = Some(read_string(&mut reader)?),
+            "general.name" if value_type == TYPE_STRING => name = Some(read_string(&mut reader)?),
+            key if key.ends_with(".context_length") => context_length = read_integer(&mut reader, value_type)?,
+            _ => skip_value(&mut reader, value_type)?,
+        }
+        // Skip the rest, notably the tokenizer vocabulary
+        if architecture.is_some() && name.is_some() && context_length.is_some() {
+            break;
+        }
+    }
+
+    let architecture = architecture.ok_or_else(|| GgufError::MissingArchitecture { path: display() })?;
+    Ok(GgufInfo { architecture, name, context_length })
+}
+
+/// Check that `path` is a GGUF model with a supported architecture
+pub fn check_custom_model(path: &Path) -> Result<GgufInfo> {
+    if !path.is_file() {
+        anyhow::bail!("Model file not found: {}", path.display());
+    }
+    let info = read_gguf_info(path)?;
+    if !SUPPORTED_ARCHITECTURES.contains(&info.architecture.as_str()) {
+        return Err(GgufError::UnsupportedArchitecture { architecture: info.architecture }.into());
+    }
+    Ok(info)
+}
+
+// GGUF metadata value types
+const TYPE_UINT8: u32 = 0;
+const TYPE_INT8: u32 = 1;
+const TYPE_UINT16: u32 = 2;
+const TYPE_INT16: u32 = 3;
+const TYPE_UINT32: u32 = 4;
+const TYPE_INT32: u32 = 5;
+const TYPE_FLOAT32: u32 = 6;
+const TYPE_BOOL: u32 = 7;
+const TYPE_STRING: u32 = 8;
+const TYPE_ARRAY: u32 = 9;
+const TYPE_UINT64: u32 = 10;
+const TYPE_INT64: u32 = 11;
+const TYPE_FLOAT64: u32 = 12;
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf).context("Truncated GGUF header")?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf).context("Truncated GGUF header")?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = read_u64(reader)?;
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        anyhow::bail!("Truncated GGUF header");
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Read an integer value of any width, skipping values of other types
+fn read_integer(reader: &mut impl Read, value_type: u32) -> Result<Option<u64>> {
+    Ok(match value_type {
+        TYPE_UINT32 | TYPE_INT32 => Some(read_u32(reader)? as u64),
+        TYPE_UINT64 | TYPE_INT64 => Some(read_u64(reader)?),
+        _ => {
+            skip_value(reader, value_type)?;
+            None
+        }
+    })
+}
+
+fn skip_value(reader: &mut impl Read, value_type: u32) -> Result<()> {
+    let size = match value_type {
+        TYPE_UINT8 | TYPE_INT8 | TYPE_BOOL => 1,
+        TYPE_UINT16 | TYPE_INT16 => 2,
+        TYPE_UINT32 | TYPE_INT32 | TYPE_FLOAT32 => 4,
+        TYPE_UINT64 | TYPE_INT64 | TYPE_FLOAT64 => 8,
+        TYPE_STRING => read_u64(reader)?,
+        TYPE_ARRAY => {
+            let item_type = read_u32(reader)?;
+            let count = read_u64(reader)?;
+            for _ in 0..count {
+                skip_value(reader, item_type)?;
+            }
+            return Ok(());
+        }
+        other => anyhow::bail!("Unknown GGUF value type {}", other),
+    };
+    let skipped = std::io::copy(&mut reader.take(size), &mut std::io::sink())?;
+    if skipped != size {
+        anyhow::bail!("Truncated GGUF header");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Value of a synthetic metadata entry
+    enum Value<'a> {
+        Str(&'a str),
+        U32(u32),
+        StrArray(&'a [&'a str]),
+    }
+
+    fn push_str(buf: &mut Vec<u8>, s: &str) {
+        buf.extend((s.len() as u64).to_le_bytes());
+        buf.extend(s.as_bytes());
+    }
+
+    fn write_gguf(name: &str, entries: &[(&str, Value)]) -> PathBuf {
+        let mut buf = GGUF_MAGIC.to_vec();
+        buf.extend(3u32.to_le_bytes());
+        buf.extend(0u64.to_le_bytes());
+        buf.extend((entries.len() as u64).to_le_bytes());
+        for (key, value) in entries {
+            push_str(&mut buf, key);
+            match value {
+                Value::Str(s) => {
+                    buf.extend(TYPE_STRING.to_le_bytes());
+                    push_str(&mut buf, s);
+                }
+                Value::U32(n) => {
+                    buf.extend(TYPE_UINT32.to_le_bytes());
+                    buf.extend(n.to_le_bytes());
+                }
+                Value::StrArray(items) => {
+                    buf.extend(TYPE_ARRAY.to_le_bytes());
+                    buf.extend(TYPE_STRING.to_le_bytes());
+                    buf.extend((items.len() as u64).to_le_bytes());
+                    for item in *items {
+                        push_str(&mut buf, item);
+                    }
+                }
+            }
+        }
+        let path = std::env::temp_dir().join(format!("voiceflow-gguf-{}-{}.gguf", name, std::process::id()));
+        std::fs::write(&path, buf).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_reads_architecture_and_context_length() {
+        let path = write_gguf(
+            "llama",
+            &[
+                ("general.architecture", Value::Str("llama")),
+                ("general.alignment", Value::U32(32)),
+                ("tokenizer.ggml.tokens", Value::StrArray(&["<s>", "</s>", "hello"])),
+                ("llama.context_length", Value::U32(8192)),
+                ("general.name", Value::Str("Llama 3.2 3B Instruct")),
+            ],
+        );
+        let info = check_custom_model(&path).unwrap();
+        assert_eq!(
+            info,
+            GgufInfo {
+                architecture: "llama".to_string(),
+                name: Some("Llama 3.2 3B Instruct".to_string()),
+                context_length: Some(8192),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unsupported_architecture_is_rejected() {
+        let path = write_gguf("mamba", &[("general.architecture", Value::Str("mamba"))]);
+        let err = check_custom_model(&path).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<GgufError>(), Some(GgufError::UnsupportedArchitecture { .. })),
+            "{}",
+            err
+        );
+        assert!(err.to_string().contains("qwen3"));
+    }
+
+    #[test]
+    fn test_non_gguf_file_is_rejected() {
+        let path = std::env::temp_dir().join(format!("voiceflow-gguf-text-{}.gguf", std::process::id()));
+        std::fs::write(&path, b"<html>404 Not Found</html>").unwrap();
+        let err = read_gguf_info(&path).unwrap_err();
+        assert!(matches!(err.downcast_ref::<GgufError>(), Some(GgufError::NotGguf { .. })), "{}", err);
+
+        let missing = path.with_file_name("voiceflow-gguf-missing.gguf");
+        assert!(check_custom_model(&missing).is_err());
+    }
+}
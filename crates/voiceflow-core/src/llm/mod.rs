@@ -1,7 +1,26 @@
 //! LLM-based text formatting
 
+mod budget;
+mod cache;
 mod engine;
+pub mod gguf;
+mod prefix;
+mod presets;
 mod prompts;
+#[cfg(feature = "remote-formatter")]
+mod remote;
+mod sanitize;
+mod templates;
 
-pub use engine::{detect_hardware, LlmEngine};
+pub use budget::PromptTruncation;
+pub(crate) use budget::{PromptBudget, PromptParts, PromptPlan};
+pub(crate) use cache::{FormatCache, FormatCacheKey};
+pub(crate) use engine::LLM_MODELS;
+pub use engine::{detect_hardware, FormatContext, LlmEngine, LlmOutput, LlmStats, TextFormatter, TokenSink};
+pub use presets::FormattingPreset;
+#[cfg(feature = "remote-formatter")]
+pub use remote::RemoteFormatter;
+pub use templates::ChatTemplate;
 pub use prompts::format_prompt;
+pub use sanitize::OutputSanitizer;
+pub(crate) use prompts::{normalized_words, personal_dictionary, same_words, word_similarity, PUNCTUATION_ONLY_PROMPT};
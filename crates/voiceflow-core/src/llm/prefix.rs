@@ -0,0 +1,117 @@
+//! Reusing the LLM's prefill of the prompt's static start
+//!
+//! Everything before the transcript (instructions, examples, vocabulary) is
+//! the same on every call. mistral.rs keeps the KV cache of recent sequences
+//! and restores it for a request whose tokens start the same way, so once
+//! that start has been prefilled, requests only prefill the transcript.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use crate::config::{Config, LlmOptions};
+use crate::llm::prompts::format_prompt;
+
+/// Sequences mistral.rs keeps the KV cache of
+pub(crate) const PREFIX_CACHE_SEQUENCES: usize = 4;
+
+/// Stands in for the transcript to find where it starts in the prompt
+const TRANSCRIPT_MARK: &str = "\u{0}transcript\u{0}";
+
+/// The prompt up to the transcript, with the vocabulary filled in (`None`
+/// when the template has no transcript placeholder or nothing before it)
+pub(crate) fn static_prefix(template: &str, config: &Config) -> Option<String> {
+    let prompt = format_prompt(template, TRANSCRIPT_MARK, config);
+    let (prefix, _) = prompt.split_once(TRANSCRIPT_MARK)?;
+    (!prefix.trim().is_empty()).then(|| prefix.to_string())
+}
+
+/// Everything a primed prefix depends on: its text (from the prompt
+/// template, preset and vocabulary) and the sampling parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PrefixKey(u64);
+
+impl PrefixKey {
+    pub(crate) fn new(prefix: &str, llm_options: &LlmOptions) -> Self {
+        let mut hasher = DefaultHasher::new();
+        prefix.hash(&mut hasher);
+        // Not `max_tokens`: the warm-up primes with a short request
+        llm_options.temperature.to_bits().hash(&mut hasher);
+        llm_options.top_p.to_bits().hash(&mut hasher);
+        llm_options.top_k.hash(&mut hasher);
+        llm_options.repeat_penalty.to_bits().hash(&mut hasher);
+        llm_options.enable_thinking.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+/// The prefix whose prefill the model last kept
+#[derive(Debug, Default)]
+pub(crate) struct PrimedPrefix {
+    key: Mutex<Option<PrefixKey>>,
+}
+
+impl PrimedPrefix {
+    pub(crate) fn is(&self, key: PrefixKey) -> bool {
+        *self.key.lock().unwrap() == Some(key)
+    }
+
+    /// Record `key` as primed, returning whether it already was
+    pub(crate) fn replace(&self, key: PrefixKey) -> bool {
+        self.key.lock().unwrap().replace(key) == Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::VocabularyEntry;
+
+    const TEMPLATE: &str = "Format this dictation.{personal_dictionary}\n\nText: {transcript}";
+
+    fn key(template: &str, config: &Config) -> PrefixKey {
+        PrefixKey::new(&static_prefix(template, config).unwrap(), &config.llm_options)
+    }
+
+    #[test]
+    fn test_static_prefix_ends_at_the_transcript() {
+        let config = Config { personal_dictionary: vec!["VoiceFlow".to_string()], ..Config::default() };
+        assert_eq!(
+            static_prefix(TEMPLATE, &config).unwrap(),
+            "Format this dictation.\nPersonal vocabulary: VoiceFlow\n\nText: "
+        );
+        assert_eq!(static_prefix("{transcript}", &config), None);
+        assert_eq!(static_prefix("No placeholder", &config), None);
+    }
+
+    #[test]
+    fn test_key_changes_with_prompt_vocabulary_and_sampling() {
+        let config = Config::default();
+        let primed = key(TEMPLATE, &config);
+        assert_eq!(key(TEMPLATE, &config), primed);
+
+        assert_ne!(key("Clean up this dictation.\n\nText: {transcript}", &config), primed);
+        let vocabulary = Config { vocabulary: vec![VocabularyEntry::new("Kubernetes")], ..config.clone() };
+        assert_ne!(key(TEMPLATE, &vocabulary), primed);
+        let mut sampling = config.clone();
+        sampling.llm_options.temperature = 0.7;
+        assert_ne!(key(TEMPLATE, &sampling), primed);
+
+        // Neither the output length nor anything after the transcript
+        let mut longer = config.clone();
+        longer.llm_options.max_tokens = 4;
+        assert_eq!(key(TEMPLATE, &longer), primed);
+        assert_eq!(key(&format!("{}\nTone: calm", TEMPLATE), &config), primed);
+    }
+
+    #[test]
+    fn test_primed_prefix_tracks_the_last_key() {
+        let primed = PrimedPrefix::default();
+        let (a, b) = (PrefixKey(1), PrefixKey(2));
+        assert!(!primed.replace(a));
+        assert!(primed.is(a));
+        assert!(primed.replace(a));
+        assert!(!primed.replace(b));
+        assert!(!primed.is(a));
+    }
+}
@@ -0,0 +1,190 @@
+//! Formatting presets: prompt templates and decoding parameters tuned for
+//! where the text is going
+
+use crate::config::LlmOptions;
+use serde::{Deserialize, Serialize};
+
+const EMAIL_PROMPT: &str = "Format this dictated text as an email body. \
+Add a greeting and sign-off only if they were dictated. Split it into short paragraphs, \
+fix punctuation and capitalization, and remove filler words. Keep the meaning and wording otherwise.\
+{personal_dictionary}
+
+Text: {transcript}";
+
+const MESSAGE_PROMPT: &str = "Format this dictated text as a chat message. \
+Keep it casual and short: fix punctuation, remove filler words, no greeting or sign-off, \
+no paragraphs unless dictated.{personal_dictionary}
+
+Text: {transcript}";
+
+const NOTES_PROMPT: &str = "Format this dictated text as notes. \
+Turn lists and separate points into bullet points, keep sentences concise, \
+and fix punctuation and capitalization. Do not add information.{personal_dictionary}
+
+Text: {transcript}";
+
+const CODE_COMMENT_PROMPT: &str = "Format this dictated text as a code comment. \
+Keep identifiers, function names, file paths and technical terms exactly as written, \
+use plain sentences with punctuation, and output only the comment text without comment markers.\
+{personal_dictionary}
+
+Text: {transcript}";
+
+/// Output style for a destination, selecting the LLM prompt and decoding
+/// parameters
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FormattingPreset {
+    /// Paragraphs, greetings and sign-offs as dictated
+    Email,
+    /// Casual chat message (Slack, iMessage)
+    Message,
+    /// Concise bullet-point notes
+    Notes,
+    /// Code comment with identifiers kept verbatim
+    CodeComment,
+    /// User-provided prompt template (same placeholders as `formatting_prompt`)
+    Custom(String),
+}
+
+impl FormattingPreset {
+    /// Stable identifier used in config files and over FFI
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Email => "email",
+            Self::Message => "message",
+            Self::Notes => "notes",
+            Self::CodeComment => "code-comment",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    /// Look up a built-in preset by id
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::all_presets().into_iter().find(|preset| preset.id() == id)
+    }
+
+    /// Get display name
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::Email => "Email",
+            Self::Message => "Message",
+            Self::Notes => "Notes",
+            Self::CodeComment => "Code Comment",
+            Self::Custom(_) => "Custom",
+        }
+    }
+
+    /// Prompt template, with `{transcript}` and `{personal_dictionary}` placeholders
+    pub fn prompt_template(&self) -> &str {
+        match self {
+            Self::Email => EMAIL_PROMPT,
+            Self::Message => MESSAGE_PROMPT,
+            Self::Notes => NOTES_PROMPT,
+            Self::CodeComment => CODE_COMMENT_PROMPT,
+            Self::Custom(template) => template,
+        }
+    }
+
+    /// Decoding parameters for this preset, based on the configured ones
+    ///
+    /// Emails and notes get room for longer output; code comments decode
+    /// near-greedily so identifiers aren't paraphrased.
+    pub fn llm_options(&self, base: &LlmOptions) -> LlmOptions {
+        let (temperature, top_p, max_tokens) = match self {
+            Self::Email => (0.4, 0.9, 1024),
+            Self::Message => (0.3, 0.9, 256),
+            Self::Notes => (0.2, 0.9, 1024),
+            Self::CodeComment => (0.1, 0.8, 512),
+            Self::Custom(_) => return base.clone(),
+        };
+        LlmOptions {
+            temperature,
+            top_p,
+            max_tokens,
+            ..base.clone()
+        }
+    }
+
+    /// Get all built-in presets (excluding Custom)
+    pub fn all_presets() -> Vec<FormattingPreset> {
+        vec![Self::Email, Self::Message, Self::Notes, Self::CodeComment]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::llm::format_prompt;
+
+    fn golden(preset: FormattingPreset) -> String {
+        format_prompt(preset.prompt_template(), "um so lunch at noon works", &Config::default())
+    }
+
+    #[test]
+    fn test_email_golden_prompt() {
+        assert_eq!(
+            golden(FormattingPreset::Email),
+            "Format this dictated text as an email body. Add a greeting and sign-off only if they were dictated. \
+Split it into short paragraphs, fix punctuation and capitalization, and remove filler words. \
+Keep the meaning and wording otherwise.\n\nText: um so lunch at noon works /no_think"
+        );
+    }
+
+    #[test]
+    fn test_message_golden_prompt() {
+        assert_eq!(
+            golden(FormattingPreset::Message),
+            "Format this dictated text as a chat message. Keep it casual and short: fix punctuation, \
+remove filler words, no greeting or sign-off, no paragraphs unless dictated.\n\n\
+Text: um so lunch at noon works /no_think"
+        );
+    }
+
+    #[test]
+    fn test_notes_golden_prompt() {
+        assert_eq!(
+            golden(FormattingPreset::Notes),
+            "Format this dictated text as notes. Turn lists and separate points into bullet points, \
+keep sentences concise, and fix punctuation and capitalization. Do not add information.\n\n\
+Text: um so lunch at noon works /no_think"
+        );
+    }
+
+    #[test]
+    fn test_code_comment_golden_prompt() {
+        assert_eq!(
+            golden(FormattingPreset::CodeComment),
+            "Format this dictated text as a code comment. Keep identifiers, function names, file paths \
+and technical terms exactly as written, use plain sentences with punctuation, and output only the \
+comment text without comment markers.\n\nText: um so lunch at noon works /no_think"
+        );
+    }
+
+    #[test]
+    fn test_custom_preset_uses_user_template() {
+        let preset = FormattingPreset::Custom("Uppercase this: {transcript}".to_string());
+        assert_eq!(golden(preset), "Uppercase this: um so lunch at noon works /no_think");
+    }
+
+    #[test]
+    fn test_preset_ids_round_trip() {
+        for preset in FormattingPreset::all_presets() {
+            assert_eq!(FormattingPreset::from_id(preset.id()), Some(preset));
+        }
+        assert_eq!(FormattingPreset::from_id("custom"), None);
+    }
+
+    #[test]
+    fn test_preset_decoding_parameters() {
+        let base = LlmOptions::default();
+        let code = FormattingPreset::CodeComment.llm_options(&base);
+        assert!(code.temperature < base.temperature);
+        assert_eq!(code.n_gpu_layers, base.n_gpu_layers);
+
+        let custom = FormattingPreset::Custom("{transcript}".to_string()).llm_options(&base);
+        assert_eq!(custom.temperature, base.temperature);
+        assert_eq!(custom.max_tokens, base.max_tokens);
+    }
+}
@@ -1,30 +1,42 @@
 //! Prompt formatting and output post-processing utilities
 
-use crate::config::Config;
+use crate::config::{Config, VocabularyEntry};
+use std::collections::HashMap;
 
 /// Format a prompt template with the transcript and config
 pub fn format_prompt(template: &str, transcript: &str, config: &Config) -> String {
     let mut prompt = template.replace("{transcript}", transcript);
 
-    // Add personal dictionary if present
-    if !config.personal_dictionary.is_empty() {
-        let dict_str = config.personal_dictionary.join(", ");
-        prompt = prompt.replace(
-            "{personal_dictionary}",
-            &format!("\nPersonal vocabulary: {}", dict_str),
-        );
-    } else {
-        prompt = prompt.replace("{personal_dictionary}", "");
-    }
+    // Add personal dictionary and vocabulary (with misrecognitions to fix) if present
+    prompt = prompt.replace(
+        "{personal_dictionary}",
+        &personal_dictionary(&config.personal_dictionary, &config.vocabulary),
+    );
 
-    // Add /no_think for Qwen3 models when thinking is disabled (faster inference)
-    if !config.llm_options.enable_thinking {
+    // Add /no_think for models with a thinking mode when thinking is
+    // disabled (faster inference); other models would take it literally
+    if !config.llm_options.enable_thinking && config.llm_model.has_thinking_mode() {
         prompt.push_str(" /no_think");
     }
 
     prompt
 }
 
+/// What `{personal_dictionary}` is replaced with: the dictionary and the
+/// vocabulary (with misrecognitions to fix), or nothing if both are empty
+pub(crate) fn personal_dictionary(dictionary: &[String], vocabulary: &[VocabularyEntry]) -> String {
+    let vocabulary: Vec<String> = dictionary
+        .iter()
+        .cloned()
+        .chain(vocabulary.iter().map(|entry| entry.prompt_hint()))
+        .collect();
+    if vocabulary.is_empty() {
+        String::new()
+    } else {
+        format!("\nPersonal vocabulary: {}", vocabulary.join(", "))
+    }
+}
+
 /// Build a chat-formatted prompt for Qwen3/SmolLM3
 #[allow(dead_code)]
 pub fn build_chat_prompt(system: &str, user: &str, enable_thinking: bool) -> String {
@@ -43,6 +55,67 @@ pub fn build_chat_prompt(system: &str, user: &str, enable_thinking: bool) -> Str
     }
 }
 
+/// Prompt for punctuation-only formatting: add punctuation and
+/// capitalization without changing any words
+pub const PUNCTUATION_ONLY_PROMPT: &str = "Add punctuation and capitalization to this dictated text. \
+Do not add, remove, reorder, or change any words. Do not fix grammar or remove filler words. \
+Output only the punctuated text.{personal_dictionary}
+
+Text: {transcript}";
+
+/// Hesitations the formatter is expected to drop
+const FILLER_WORDS: [&str; 9] = ["um", "umm", "uh", "uhm", "er", "erm", "ah", "hmm", "mm"];
+
+/// Lowercase words of a text, without punctuation
+pub(crate) fn normalized_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Whether two texts have the same words, ignoring case and punctuation
+///
+/// Used to reject punctuation-only output that reworded the transcript.
+pub fn same_words(a: &str, b: &str) -> bool {
+    normalized_words(a) == normalized_words(b)
+}
+
+/// How much of the transcript survives in the formatted text (0.0 - 1.0)
+///
+/// The lower of the share of transcript words kept (filler words aside) and
+/// the share of formatted words taken from the transcript, so both a
+/// dropped sentence and an answer to a dictated question score low.
+pub fn word_similarity(transcript: &str, formatted: &str) -> f32 {
+    let mut transcript_words = normalized_words(transcript);
+    transcript_words.retain(|word| !FILLER_WORDS.contains(&word.as_str()));
+    let formatted_words = normalized_words(formatted);
+    if transcript_words.is_empty() || formatted_words.is_empty() {
+        return if transcript_words.len() == formatted_words.len() { 1.0 } else { 0.0 };
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for word in &transcript_words {
+        *counts.entry(word.as_str()).or_default() += 1;
+    }
+    let mut common = 0;
+    for word in &formatted_words {
+        if let Some(count) = counts.get_mut(word.as_str()).filter(|count| **count > 0) {
+            *count -= 1;
+            common += 1;
+        }
+    }
+
+    let kept = common as f32 / transcript_words.len() as f32;
+    let taken = common as f32 / formatted_words.len() as f32;
+    kept.min(taken)
+}
+
 //=============================================================================
 // Output Post-Processing
 //=============================================================================
@@ -289,6 +362,71 @@ mod tests {
         assert_eq!(fix_punctuation_spacing("Hello.World"), "Hello. World");
     }
 
+    #[test]
+    fn test_format_prompt_includes_vocabulary() {
+        use crate::config::VocabularyEntry;
+
+        let mut config = Config::default();
+        config.personal_dictionary = vec!["Era".to_string()];
+        config.vocabulary = vec![VocabularyEntry {
+            term: "Kubernetes".to_string(),
+            sounds_like: vec!["cooper netties".to_string()],
+        }];
+        let prompt = format_prompt("Fix:{personal_dictionary}\n{transcript}", "deploy to cooper netties", &config);
+        assert_eq!(
+            prompt,
+            "Fix:\nPersonal vocabulary: Era, Kubernetes (may be transcribed as \"cooper netties\")\n\
+deploy to cooper netties /no_think"
+        );
+    }
+
+    #[test]
+    fn test_no_think_only_for_models_with_thinking_mode() {
+        use crate::config::LlmModel;
+
+        for llm_model in [LlmModel::Custom("/models/llama-3.2-3b.gguf".to_string()), LlmModel::Gemma2_2B] {
+            let config = Config { llm_model, ..Config::default() };
+            assert_eq!(format_prompt("Fix: {transcript}", "hello", &config), "Fix: hello");
+        }
+
+        let mut config = Config { llm_model: LlmModel::Qwen3_4B, ..Config::default() };
+        assert_eq!(format_prompt("Fix: {transcript}", "hello", &config), "Fix: hello /no_think");
+        config.llm_options.enable_thinking = true;
+        assert_eq!(format_prompt("Fix: {transcript}", "hello", &config), "Fix: hello");
+    }
+
+    #[test]
+    fn test_same_words_ignores_punctuation_and_case() {
+        assert!(same_words("so um I think we're done", "So, um, I think we're done."));
+        assert!(same_words("go to settings", "Go to 'Settings'."));
+        assert!(!same_words("so um I think we're done", "I think we're done."));
+        assert!(!same_words("its fine", "It is fine."));
+    }
+
+    #[test]
+    fn test_similarity_allows_formatting() {
+        let raw = "um so I think uh we should ship on friday and then we can review it monday";
+        let formatted = "So I think we should ship on Friday, and then we can review it Monday.";
+        assert_eq!(word_similarity(raw, formatted), 1.0);
+        assert_eq!(word_similarity("", ""), 1.0);
+    }
+
+    #[test]
+    fn test_similarity_catches_answered_question() {
+        let raw = "what time is the meeting";
+        let formatted = "The meeting is at 3pm.";
+        assert!(word_similarity(raw, formatted) < 0.7);
+    }
+
+    #[test]
+    fn test_similarity_catches_dropped_sentence() {
+        let raw = "send the draft to legal today. they need it before the board call. \
+                   also remind them about the budget numbers";
+        let formatted = "Send the draft to legal today. Also remind them about the budget numbers.";
+        assert!(word_similarity(raw, formatted) < 0.7);
+        assert_eq!(word_similarity(raw, ""), 0.0);
+    }
+
     #[test]
     fn test_post_process_output() {
         let result = post_process_output("  go to Settings.Click Submit  ");
@@ -0,0 +1,249 @@
+//! Formatting on an OpenAI-compatible server (Ollama, llama.cpp's server,
+//! ...) instead of a local model
+
+use crate::config::{Config, FormatterBackend};
+use crate::llm::engine::{strip_thinking_tags, FormatContext, LlmOutput, LlmStats, TextFormatter, TokenSink};
+use crate::llm::prompts::{format_prompt, post_process_output};
+use crate::llm::sanitize::OutputSanitizer;
+use crate::PipelineError;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// Longest part of an error response kept in the error message
+const MAX_ERROR_BODY_CHARS: usize = 200;
+
+/// Formats transcripts with one chat completion request each
+pub struct RemoteFormatter {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    agent: ureq::Agent,
+    config: Config,
+    sanitizer: OutputSanitizer,
+}
+
+impl RemoteFormatter {
+    /// Formatter for the `FormatterBackend::Remote` of `config`
+    ///
+    /// Nothing is sent until the first transcript. Fails if the API key
+    /// variable is named but not set.
+    pub fn new(config: &Config) -> Result<Self> {
+        let FormatterBackend::Remote { base_url, model, api_key_env, timeout_secs } = &config.formatter else {
+            anyhow::bail!("The config doesn't select a remote formatter");
+        };
+        let url = format!("{}/v1/chat/completions", base_url.trim_end_matches('/'));
+        let api_key = match api_key_env {
+            Some(var) => Some(std::env::var(var).map_err(|_| PipelineError::RemoteFormatter {
+                url: url.clone(),
+                message: format!("the API key variable {} is not set", var),
+            })?),
+            None => None,
+        };
+        tracing::info!("Formatting with {} at {}", model, url);
+
+        Ok(Self {
+            url,
+            model: model.clone(),
+            api_key,
+            agent: ureq::AgentBuilder::new().timeout(Duration::from_secs(*timeout_secs)).build(),
+            config: config.clone(),
+            sanitizer: OutputSanitizer::compile(&config.llm_output)?,
+        })
+    }
+
+    /// Send a chat completion request, once more if the server answers
+    /// with a 5xx error
+    fn complete(&self, body: &Value) -> Result<Value> {
+        let body = body.to_string();
+        let mut retried = false;
+        loop {
+            let mut request = self.agent.post(&self.url).set("Content-Type", "application/json");
+            if let Some(key) = &self.api_key {
+                request = request.set("Authorization", &format!("Bearer {}", key));
+            }
+            match request.send_string(&body) {
+                Ok(response) => {
+                    let text = response.into_string().map_err(|e| self.error(e))?;
+                    return serde_json::from_str(&text).map_err(|e| self.error(format!("invalid response: {}", e)));
+                }
+                Err(ureq::Error::Status(status, _)) if status >= 500 && !retried => {
+                    tracing::warn!("Remote formatter answered {}, retrying once", status);
+                    retried = true;
+                }
+                Err(ureq::Error::Status(status, response)) => {
+                    let text = response.into_string().unwrap_or_default();
+                    let text: String = text.chars().take(MAX_ERROR_BODY_CHARS).collect();
+                    return Err(self.error(format!("HTTP {}: {}", status, text.trim())));
+                }
+                Err(e) => return Err(self.error(e)),
+            }
+        }
+    }
+
+    fn error(&self, message: impl ToString) -> anyhow::Error {
+        PipelineError::RemoteFormatter { url: self.url.clone(), message: message.to_string() }.into()
+    }
+}
+
+impl TextFormatter for RemoteFormatter {
+    fn format(&mut self, transcript: &str, ctx: &FormatContext) -> Result<String> {
+        Ok(self.format_with_stats(transcript, ctx, None)?.text)
+    }
+
+    /// The server's answer arrives whole: it is passed to `sink` as a single
+    /// token, and the whole request counts as prefill
+    fn format_with_stats(
+        &mut self,
+        transcript: &str,
+        ctx: &FormatContext,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<LlmOutput> {
+        if ctx.cancel.is_cancelled() {
+            return Err(PipelineError::cancelled().into());
+        }
+        let prompt = format_prompt(ctx.prompt_template, transcript, &self.config);
+        let options = ctx.llm_options.clamped();
+        let mut body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt }],
+            "max_tokens": options.max_tokens,
+            "temperature": options.temperature,
+            "top_p": options.top_p,
+            "stream": false,
+        });
+        if let Some(seed) = options.seed {
+            body["seed"] = json!(seed);
+        }
+
+        let start = Instant::now();
+        let response = self.complete(&body)?;
+        if ctx.cancel.is_cancelled() {
+            return Err(PipelineError::cancelled().into());
+        }
+        let raw = response["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| self.error("the response has no message"))?
+            .to_string();
+        let text = post_process_output(&self.sanitizer.sanitize(&strip_thinking_tags(&raw)));
+        if let Some(sink) = sink {
+            if !text.is_empty() {
+                sink.token(&text);
+            }
+        }
+
+        let stats = LlmStats {
+            prefill_ms: start.elapsed().as_millis() as u64,
+            generate_ms: 0,
+            tokens_generated: response["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
+            thinking_tokens: 0,
+        };
+        tracing::debug!("Remote formatter output length: {} chars in {}ms", text.len(), stats.prefill_ms);
+        Ok(LlmOutput { text, raw, stats })
+    }
+
+    fn update_config(&mut self, config: &Config) -> Result<()> {
+        self.sanitizer = OutputSanitizer::compile(&config.llm_output)?;
+        self.config = config.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::config::LlmOptions;
+    use std::io::{BufRead, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// Answer one request per response with its status and body; returns
+    /// the base URL and a handle yielding the request bodies
+    fn serve(responses: Vec<(u16, &'static str)>) -> (String, JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for ((status, body), stream) in responses.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut request = vec![0; length];
+                reader.read_exact(&mut request).unwrap();
+                requests.push(serde_json::from_slice(&request).unwrap());
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn formatter(base_url: &str) -> RemoteFormatter {
+        let config = Config {
+            formatter: FormatterBackend::Remote {
+                base_url: base_url.to_string(),
+                model: "qwen3:4b".to_string(),
+                api_key_env: None,
+                timeout_secs: 5,
+            },
+            ..Config::default()
+        };
+        RemoteFormatter::new(&config).unwrap()
+    }
+
+    fn format(formatter: &mut RemoteFormatter) -> Result<LlmOutput> {
+        let ctx = FormatContext {
+            prompt_template: "Fix punctuation: {transcript}",
+            llm_options: &LlmOptions::default(),
+            cancel: &CancelToken::new(),
+        };
+        formatter.format_with_stats("hello world", &ctx, None)
+    }
+
+    #[test]
+    fn test_server_error_is_retried_once() {
+        let answer = r#"{"choices":[{"message":{"content":"Hello world."}}],"usage":{"completion_tokens":4}}"#;
+        let (url, server) = serve(vec![(503, "{}"), (200, answer)]);
+
+        let output = format(&mut formatter(&url)).unwrap();
+        assert_eq!(output.text, "Hello world.");
+        assert_eq!(output.stats.tokens_generated, 4);
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["model"], "qwen3:4b");
+        assert!(requests[1]["messages"][0]["content"].as_str().unwrap().contains("hello world"));
+    }
+
+    #[test]
+    fn test_failures_are_remote_formatter_errors() {
+        let (url, server) = serve(vec![(500, "{}"), (502, "bad gateway")]);
+        let err = format(&mut formatter(&url)).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PipelineError>(), Some(PipelineError::RemoteFormatter { .. })), "{}", err);
+        assert!(err.to_string().contains("502"), "{}", err);
+        server.join().unwrap();
+
+        // Nothing listens on a port just released
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let err = format(&mut formatter(&format!("http://127.0.0.1:{}", port))).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PipelineError>(), Some(PipelineError::RemoteFormatter { .. })), "{}", err);
+    }
+}
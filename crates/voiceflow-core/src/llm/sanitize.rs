@@ -0,0 +1,240 @@
+//! Cleanup of model quirks in LLM output: leaked chat-template tokens,
+//! chatter around the text and markdown code fences
+//!
+//! The patterns come from `Config::llm_output`, so a new quirk can be
+//! handled by editing the config.
+
+use crate::config::{ConfigError, LlmOutputRules};
+use regex::{Regex, RegexBuilder};
+
+/// `LlmOutputRules` compiled for matching
+#[derive(Debug, Clone, Default)]
+pub struct OutputSanitizer {
+    stop_sequences: Vec<String>,
+    preambles: Vec<Regex>,
+    epilogues: Vec<Regex>,
+    strip_code_fences: bool,
+}
+
+impl OutputSanitizer {
+    /// Compile the rules, failing on the first invalid pattern
+    pub fn compile(rules: &LlmOutputRules) -> Result<Self, ConfigError> {
+        let compile = |pattern: &String, anchored: fn(&str) -> String| {
+            RegexBuilder::new(&anchored(pattern))
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| ConfigError::InvalidOutputPattern {
+                    pattern: pattern.clone(),
+                    message: e.to_string(),
+                })
+        };
+        Ok(Self {
+            stop_sequences: rules.stop_sequences.iter().filter(|s| !s.is_empty()).cloned().collect(),
+            preambles: rules
+                .preamble_patterns
+                .iter()
+                .map(|p| compile(p, |p| format!(r"\A(?:{})", p)))
+                .collect::<Result<_, _>>()?,
+            epilogues: rules
+                .epilogue_patterns
+                .iter()
+                .map(|p| compile(p, |p| format!(r"\A\s*(?:{})\s*\z", p)))
+                .collect::<Result<_, _>>()?,
+            strip_code_fences: rules.strip_code_fences,
+        })
+    }
+
+    /// Sequences the output is cut at
+    pub fn stop_sequences(&self) -> &[String] {
+        &self.stop_sequences
+    }
+
+    /// Clean up LLM output
+    ///
+    /// Cuts the text at the first stop sequence (after dropping leading
+    /// ones), removes a matching preamble, and an epilogue set apart by a
+    /// closing code fence or a `---` line, unwraps a code fence around the
+    /// whole text and collapses runs of whitespace.
+    pub fn sanitize(&self, text: &str) -> String {
+        let mut text = self.cut_at_stop_sequence(text).trim().to_string();
+        for preamble in &self.preambles {
+            text = preamble.replace(&text, "").trim_start().to_string();
+        }
+        if let Some((output, remarks)) = split_at_delimiter(&text) {
+            if self.epilogues.iter().any(|epilogue| epilogue.is_match(remarks)) {
+                text = output.trim_end().to_string();
+            }
+        }
+        if self.strip_code_fences {
+            text = unwrap_code_fence(&text).to_string();
+        }
+        collapse_whitespace(&text)
+    }
+
+    fn cut_at_stop_sequence<'a>(&self, text: &'a str) -> &'a str {
+        let mut text = text.trim_start();
+        while let Some(stop) = self.stop_sequences.iter().find(|stop| text.starts_with(stop.as_str())) {
+            text = text[stop.len()..].trim_start();
+        }
+        let end = self
+            .stop_sequences
+            .iter()
+            .filter_map(|stop| text.find(stop.as_str()))
+            .min()
+            .unwrap_or(text.len());
+        &text[..end]
+    }
+}
+
+/// The text up to the last line that ends the model's output, and the rest
+///
+/// The line is a closing ``` fence, kept with the text, or a `---` (`***`,
+/// `___`) rule, left out of both.
+fn split_at_delimiter(text: &str) -> Option<(&str, &str)> {
+    let mut split = None;
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let end = start + line.len();
+        match line.trim() {
+            // A fence on the first line opens one
+            "```" if start > 0 => split = Some((&text[..end], &text[end..])),
+            "---" | "***" | "___" => split = Some((&text[..start], &text[end..])),
+            _ => {}
+        }
+        start = end;
+    }
+    split
+}
+
+/// The contents of a text wrapped whole in a single ``` fence, or the text
+fn unwrap_code_fence(text: &str) -> &str {
+    if text.matches("```").count() != 2 || !text.starts_with("```") || !text.ends_with("```") {
+        return text;
+    }
+    let inner = &text[3..text.len() - 3];
+    // The opening fence may name a language: ```text
+    match inner.split_once('\n') {
+        Some((info, body)) if !info.trim().contains(' ') => body.trim(),
+        _ => inner.trim(),
+    }
+}
+
+/// Collapse runs of spaces and tabs, strip trailing spaces and keep at most
+/// one blank line between paragraphs
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.trim().lines() {
+        let line = line.split([' ', '\t']).filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        blank_lines = 0;
+        result.push_str(&line);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitize(text: &str) -> String {
+        OutputSanitizer::compile(&LlmOutputRules::default()).unwrap().sanitize(text)
+    }
+
+    // The quirks the default rules cover
+
+    #[test]
+    fn test_template_leak() {
+        assert_eq!(
+            sanitize("Let's meet at noon tomorrow.<|im_end|>\n<|im_start|>user\nThanks"),
+            "Let's meet at noon tomorrow."
+        );
+    }
+
+    #[test]
+    fn test_preamble() {
+        assert_eq!(
+            sanitize("Sure, here's the formatted text:\n\nI'll send the report by Friday."),
+            "I'll send the report by Friday."
+        );
+    }
+
+    #[test]
+    fn test_code_fence_and_epilogue() {
+        assert_eq!(
+            sanitize("```text\nThe build is green.  Ship it.\n```\n\nI removed the filler words and fixed the punctuation."),
+            "The build is green. Ship it."
+        );
+    }
+
+    #[test]
+    fn test_turn_leak() {
+        assert_eq!(
+            sanitize("Here is the corrected transcript:\nCall me when you land. <end_of_turn>\n<start_of_turn>user"),
+            "Call me when you land."
+        );
+    }
+
+    #[test]
+    fn test_role_header_and_note_after_a_rule() {
+        assert_eq!(
+            sanitize("<|assistant|>\nThe meeting moved to 3 PM.\n\n---\n\nNote: I removed the filler words."),
+            "The meeting moved to 3 PM."
+        );
+    }
+
+    #[test]
+    fn test_clean_output_is_unchanged() {
+        let text = "Hi Sam,\n\nSure, here's the plan: we ship Friday.\n\nThanks,\nAlex";
+        assert_eq!(sanitize(text), text);
+        assert_eq!(sanitize("Use ```cargo test``` to run them."), "Use ```cargo test``` to run them.");
+    }
+
+    #[test]
+    fn test_dictated_text_is_not_taken_for_chatter() {
+        // Closing lines of an email, and a role word opening the text
+        let email = "Hi Sam,\n\nThe draft is attached.\n\nLet me know if you have any questions.";
+        assert_eq!(sanitize(email), email);
+        assert_eq!(sanitize("The fix is in.\nI hope this helps the release."), "The fix is in.\nI hope this helps the release.");
+        assert_eq!(sanitize("The tests pass.\nI fixed the bug in the parser."), "The tests pass.\nI fixed the bug in the parser.");
+        // Remarks about formatting too, unless set apart from the text
+        let typos = "The tests pass.\n\nI fixed the typos in the README.";
+        assert_eq!(sanitize(typos), typos);
+        assert_eq!(sanitize("Done.\nNote: I removed the old flag."), "Done.\nNote: I removed the old flag.");
+        assert_eq!(sanitize("Model\nThe new one ships in May."), "Model\nThe new one ships in May.");
+        assert_eq!(sanitize("Assistant\nBook the room."), "Assistant\nBook the room.");
+
+        // Remarks about the formatting past a delimiter still go, and a
+        // rule followed by dictated text stays
+        assert_eq!(sanitize("model\nBook the room."), "Book the room.");
+        assert_eq!(sanitize("Book the room.\n---\nThe text has been formatted for clarity."), "Book the room.");
+        assert_eq!(sanitize("Agenda\n---\nBudget review."), "Agenda\n---\nBudget review.");
+    }
+
+    #[test]
+    fn test_whitespace_is_collapsed() {
+        assert_eq!(sanitize("  One  two\t three.  \n\n\n\nFour.\n"), "One two three.\n\nFour.");
+    }
+
+    #[test]
+    fn test_configured_rules() {
+        let rules = LlmOutputRules {
+            stop_sequences: vec!["###".to_string()],
+            preamble_patterns: vec![r"answer:\s*".to_string()],
+            epilogue_patterns: vec![],
+            ..LlmOutputRules::default()
+        };
+        let sanitizer = OutputSanitizer::compile(&rules).unwrap();
+        assert_eq!(sanitizer.sanitize("ANSWER: Done.### extra <|im_end|>"), "Done.");
+
+        let invalid = LlmOutputRules { preamble_patterns: vec!["(unclosed".to_string()], ..rules };
+        let err = OutputSanitizer::compile(&invalid).unwrap_err();
+        assert!(err.to_string().contains("(unclosed"), "{}", err);
+    }
+}
@@ -0,0 +1,84 @@
+//! Chat templates for custom LLM models, overriding the one embedded in the
+//! GGUF file
+
+use serde::{Deserialize, Serialize};
+
+const CHATML_TEMPLATE: &str = r#"{% for message in messages %}{{ '<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>\n' }}{% endfor %}{% if add_generation_prompt %}{{ '<|im_start|>assistant\n' }}{% endif %}"#;
+
+const LLAMA3_TEMPLATE: &str = r#"{{ bos_token }}{% for message in messages %}{{ '<|start_header_id|>' + message['role'] + '<|end_header_id|>\n\n' + message['content'] | trim + '<|eot_id|>' }}{% endfor %}{% if add_generation_prompt %}{{ '<|start_header_id|>assistant<|end_header_id|>\n\n' }}{% endif %}"#;
+
+const GEMMA_TEMPLATE: &str = r#"{{ bos_token }}{% for message in messages %}{% set role = 'model' if message['role'] == 'assistant' else 'user' %}{{ '<start_of_turn>' + role + '\n' + message['content'] | trim + '<end_of_turn>\n' }}{% endfor %}{% if add_generation_prompt %}{{ '<start_of_turn>model\n' }}{% endif %}"#;
+
+const MISTRAL_TEMPLATE: &str = r#"{{ bos_token }}{% for message in messages %}{% if message['role'] == 'assistant' %}{{ message['content'] + eos_token }}{% else %}{{ '[INST] ' + message['content'] + ' [/INST]' }}{% endif %}{% endfor %}"#;
+
+const PHI3_TEMPLATE: &str = r#"{% for message in messages %}{{ '<|' + message['role'] + '|>\n' + message['content'] + '<|end|>\n' }}{% endfor %}{% if add_generation_prompt %}{{ '<|assistant|>\n' }}{% endif %}"#;
+
+/// Prompt format the LLM was trained on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatTemplate {
+    /// The template embedded in the GGUF file
+    #[default]
+    Auto,
+    /// `<|im_start|>` turns (Qwen, SmolLM, many fine-tunes)
+    ChatMl,
+    /// Llama 3 header turns
+    Llama3,
+    /// Gemma `<start_of_turn>` turns
+    Gemma,
+    /// Mistral `[INST]` turns
+    Mistral,
+    /// Phi-3 `<|user|>` turns
+    Phi3,
+}
+
+impl ChatTemplate {
+    /// Stable identifier used in config files and over FFI
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Auto => "auto",
+            Self::ChatMl => "chatml",
+            Self::Llama3 => "llama3",
+            Self::Gemma => "gemma",
+            Self::Mistral => "mistral",
+            Self::Phi3 => "phi3",
+        }
+    }
+
+    /// Look up a template by id
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::all_templates().into_iter().find(|template| template.id() == id)
+    }
+
+    /// Get all templates
+    pub fn all_templates() -> Vec<ChatTemplate> {
+        vec![Self::Auto, Self::ChatMl, Self::Llama3, Self::Gemma, Self::Mistral, Self::Phi3]
+    }
+
+    /// Jinja source passed to mistral.rs (none to keep the embedded one)
+    pub fn jinja(&self) -> Option<&'static str> {
+        match self {
+            Self::Auto => None,
+            Self::ChatMl => Some(CHATML_TEMPLATE),
+            Self::Llama3 => Some(LLAMA3_TEMPLATE),
+            Self::Gemma => Some(GEMMA_TEMPLATE),
+            Self::Mistral => Some(MISTRAL_TEMPLATE),
+            Self::Phi3 => Some(PHI3_TEMPLATE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_match_serde_names() {
+        for template in ChatTemplate::all_templates() {
+            assert_eq!(ChatTemplate::from_id(template.id()), Some(template));
+            let value = toml::Value::try_from(template).unwrap();
+            assert_eq!(value.as_str(), Some(template.id()));
+        }
+        assert_eq!(ChatTemplate::from_id("qwen"), None);
+    }
+}
@@ -0,0 +1,4 @@
+//! Management of the downloaded model files and the loaded weights
+
+pub mod shared;
+pub mod storage;
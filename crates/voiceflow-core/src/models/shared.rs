@@ -0,0 +1,350 @@
+//! Model weights shared by every pipeline in the process
+//!
+//! Pipelines loading the same model file with the same load settings get
+//! the same weights; each keeps its own inference state (Whisper's decoder
+//! state, Moonshine's KV cache, its own LLM sequences). The registry holds
+//! weak references, so the weights are freed with the last pipeline using
+//! them, and loaded again by the next one. A model file replaced on disk,
+//! e.g. downloaded again, is loaded anew rather than shared.
+//!
+//! - Whisper: one `WhisperContext`, and a `WhisperState` per engine.
+//!   whisper.cpp runs different states of a context on different threads.
+//! - Moonshine: the ONNX Runtime sessions, each behind a mutex since `ort`
+//!   runs a session through `&mut`. Engines sharing them take turns per
+//!   session run (one decoder step), not per transcription.
+//! - LLM: the mistral.rs model, whose scheduler runs requests from several
+//!   engines as separate sequences, each with its own KV cache.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+/// What a model was loaded from: its file or directory, and the settings
+/// that change what gets loaded
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct ModelKey {
+    path: PathBuf,
+    /// Size and modification time of the file, or of each file in the
+    /// directory
+    files: Vec<(u64, Option<SystemTime>)>,
+    settings: String,
+}
+
+impl ModelKey {
+    pub(crate) fn new(path: &Path, settings: impl Into<String>) -> Self {
+        Self { path: path.to_path_buf(), files: file_stamps(path), settings: settings.into() }
+    }
+}
+
+fn file_stamps(path: &Path) -> Vec<(u64, Option<SystemTime>)> {
+    let stamp = |metadata: std::fs::Metadata| (metadata.len(), metadata.modified().ok());
+    if !path.is_dir() {
+        return std::fs::metadata(path).map(stamp).into_iter().collect();
+    }
+    let mut files: Vec<(PathBuf, (u64, Option<SystemTime>))> = std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| Some((entry.path(), stamp(entry.metadata().ok()?))))
+        .collect();
+    files.sort();
+    files.into_iter().map(|(_, stamp)| stamp).collect()
+}
+
+/// A model of the registry
+enum Slot<T> {
+    /// A pipeline is loading it; the others asking for it wait
+    Loading,
+    Loaded(Weak<T>),
+}
+
+/// What a pipeline asking for a model does
+enum Claim<T> {
+    Share(Arc<T>),
+    /// Wait for the pipeline loading it
+    Wait,
+    /// Load it; the others asking meanwhile wait
+    Load,
+}
+
+/// Loaded models of one kind, by what they were loaded from
+pub(crate) struct SharedModels<T> {
+    kind: &'static str,
+    models: Mutex<BTreeMap<ModelKey, Slot<T>>>,
+    /// Signalled when a load ends, for the pipelines waiting on it
+    load_ended: Condvar,
+    load_ended_async: Notify,
+}
+
+impl<T> SharedModels<T> {
+    pub(crate) const fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            models: Mutex::new(BTreeMap::new()),
+            load_ended: Condvar::new(),
+            load_ended_async: Notify::const_new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<ModelKey, Slot<T>>> {
+        self.models.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn claim(&self, models: &mut BTreeMap<ModelKey, Slot<T>>, key: &ModelKey) -> Claim<T> {
+        models.retain(|_, slot| !matches!(slot, Slot::Loaded(model) if model.strong_count() == 0));
+        match models.get(key) {
+            Some(Slot::Loading) => return Claim::Wait,
+            Some(Slot::Loaded(model)) => {
+                // Its last user may have dropped it since
+                if let Some(model) = model.upgrade() {
+                    tracing::info!("Sharing the loaded {} model {:?}", self.kind, key.path);
+                    return Claim::Share(model);
+                }
+            }
+            None => {}
+        }
+        models.insert(key.clone(), Slot::Loading);
+        Claim::Load
+    }
+
+    /// The model loaded from `key`, if a pipeline still uses it
+    #[cfg(test)]
+    fn get(&self, key: &ModelKey) -> Option<Arc<T>> {
+        match self.lock().get(key)? {
+            Slot::Loaded(model) => model.upgrade(),
+            Slot::Loading => None,
+        }
+    }
+
+    /// The model loaded from `key`, loading it with `load` unless a
+    /// pipeline still uses it
+    ///
+    /// Pipelines asking for a model while it loads wait for that load
+    /// rather than loading the weights again; other models load alongside.
+    pub(crate) fn get_or_load(&self, key: ModelKey, load: impl FnOnce() -> Result<T>) -> Result<Arc<T>> {
+        let mut models = self.lock();
+        loop {
+            match self.claim(&mut models, &key) {
+                Claim::Share(model) => return Ok(model),
+                Claim::Wait => models = self.load_ended.wait(models).unwrap_or_else(|e| e.into_inner()),
+                Claim::Load => break,
+            }
+        }
+        drop(models);
+        let pending = PendingLoad { models: self, key: Some(key) };
+        Ok(pending.finish(load()?))
+    }
+
+    /// `get_or_load` for a model loaded asynchronously
+    pub(crate) async fn get_or_load_async<F>(&self, key: ModelKey, load: impl FnOnce() -> F) -> Result<Arc<T>>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        loop {
+            // Created before the check, so a load ending right after it
+            // still wakes this one
+            let load_ended = self.load_ended_async.notified();
+            let claim = self.claim(&mut self.lock(), &key);
+            match claim {
+                Claim::Share(model) => return Ok(model),
+                Claim::Wait => load_ended.await,
+                Claim::Load => break,
+            }
+        }
+        let pending = PendingLoad { models: self, key: Some(key) };
+        Ok(pending.finish(load().await?))
+    }
+
+    fn end_load(&self, key: ModelKey, model: Option<&Arc<T>>) {
+        let mut models = self.lock();
+        match model {
+            Some(model) => models.insert(key, Slot::Loaded(Arc::downgrade(model))),
+            None => models.remove(&key),
+        };
+        drop(models);
+        self.load_ended.notify_all();
+        self.load_ended_async.notify_waiters();
+    }
+
+    fn loaded(&self) -> Vec<LoadedModel> {
+        self.lock()
+            .iter()
+            .filter_map(|(key, slot)| match slot {
+                Slot::Loaded(model) if model.strong_count() > 0 => {
+                    Some(LoadedModel { kind: self.kind, path: key.path.clone(), users: model.strong_count() })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A load in progress; dropped before it finishes (the load failed,
+/// panicked or was cancelled), it lets the next pipeline waiting load
+/// the model
+struct PendingLoad<'a, T> {
+    models: &'a SharedModels<T>,
+    key: Option<ModelKey>,
+}
+
+impl<T> PendingLoad<'_, T> {
+    fn finish(mut self, model: T) -> Arc<T> {
+        let model = Arc::new(model);
+        if let Some(key) = self.key.take() {
+            self.models.end_load(key, Some(&model));
+        }
+        model
+    }
+}
+
+impl<T> Drop for PendingLoad<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.models.end_load(key, None);
+        }
+    }
+}
+
+/// A model whose weights are loaded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoadedModel {
+    /// "whisper", "moonshine" or "llm"
+    pub kind: &'static str,
+    /// The model file, or directory for Moonshine
+    pub path: PathBuf,
+    /// Engines using the weights, about one per pipeline (an engine being
+    /// reloaded briefly counts twice)
+    pub users: usize,
+}
+
+/// Models whose weights are loaded in this process, with how many engines
+/// share each
+pub fn loaded_models() -> Vec<LoadedModel> {
+    let mut loaded = crate::transcribe::WHISPER_MODELS.loaded();
+    loaded.extend(crate::transcribe::MOONSHINE_MODELS.loaded());
+    loaded.extend(crate::llm::LLM_MODELS.loaded());
+    loaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn key(path: &str) -> ModelKey {
+        ModelKey::new(Path::new(path), "threads=4")
+    }
+
+    #[test]
+    fn test_same_key_shares_the_weights() {
+        let models = SharedModels::new("test");
+        let loads = AtomicUsize::new(0);
+        let load = || {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![0u8; 16])
+        };
+        let a = models.get_or_load(key("/models/a.bin"), load).unwrap();
+        let b = models.get_or_load(key("/models/a.bin"), load).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(models.loaded()[0].users, 2);
+
+        // Another file, or other load settings, load their own
+        let c = models.get_or_load(key("/models/c.bin"), load).unwrap();
+        let d = models.get_or_load(ModelKey::new(Path::new("/models/a.bin"), "threads=2"), load).unwrap();
+        assert!(!Arc::ptr_eq(&a, &c) && !Arc::ptr_eq(&a, &d));
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_last_user_releases_the_weights() {
+        let models = SharedModels::new("test");
+        let a = models.get_or_load(key("/models/a.bin"), || Ok(1)).unwrap();
+        let b = models.get(&key("/models/a.bin")).unwrap();
+        drop(a);
+        assert_eq!(models.loaded()[0].users, 1);
+        let weak = Arc::downgrade(&b);
+        drop(b);
+        assert!(weak.upgrade().is_none());
+        assert!(models.loaded().is_empty());
+        assert!(models.get(&key("/models/a.bin")).is_none());
+
+        // Loaded again for the next user
+        assert_eq!(*models.get_or_load(key("/models/a.bin"), || Ok(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_replaced_file_is_loaded_anew() {
+        let dir = std::env::temp_dir().join(format!("voiceflow-shared-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.bin");
+        std::fs::write(&path, b"weights").unwrap();
+        let models = SharedModels::new("test");
+        let a = models.get_or_load(ModelKey::new(&path, "cpu"), || Ok(1)).unwrap();
+        assert!(models.get(&ModelKey::new(&path, "cpu")).is_some());
+        assert!(models.get(&ModelKey::new(&dir, "cpu")).is_none());
+
+        std::fs::write(&path, b"downloaded again").unwrap();
+        let b = models.get_or_load(ModelKey::new(&path, "cpu"), || Ok(2)).unwrap();
+        assert!(!Arc::ptr_eq(&a, &b));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_loads_share_one_load() {
+        let (models, loads) = (&SharedModels::new("test"), &AtomicUsize::new(0));
+        let (started, wait_started) = mpsc::channel();
+        let (release, wait_release) = mpsc::channel::<()>();
+        let (first, second) = std::thread::scope(|s| {
+            let first = s.spawn(move || {
+                models.get_or_load(key("/models/a.bin"), move || {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    started.send(()).unwrap();
+                    wait_release.recv().unwrap();
+                    Ok(1)
+                })
+            });
+            wait_started.recv().unwrap();
+            // Other models load meanwhile
+            assert_eq!(*models.get_or_load(key("/models/b.bin"), || Ok(2)).unwrap(), 2);
+            let second = s.spawn(move || {
+                models.get_or_load(key("/models/a.bin"), move || {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    Ok(3)
+                })
+            });
+            release.send(()).unwrap();
+            (first.join().unwrap().unwrap(), second.join().unwrap().unwrap())
+        });
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_async_load_is_shared_and_cancellable() {
+        let models = SharedModels::new("test");
+        let a = models.get_or_load_async(key("/models/a.bin"), || async { Ok(1) }).await.unwrap();
+        let b = models.get_or_load(key("/models/a.bin"), || Ok(2)).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+
+        // A load given up on lets the next pipeline load the model
+        let load = models.get_or_load_async(key("/models/c.bin"), std::future::pending);
+        assert!(tokio::time::timeout(Duration::from_millis(10), load).await.is_err());
+        assert_eq!(*models.get_or_load(key("/models/c.bin"), || Ok(3)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_failed_load_is_not_kept() {
+        let models: SharedModels<u32> = SharedModels::new("test");
+        assert!(models.get_or_load(key("/models/a.bin"), || anyhow::bail!("corrupt")).is_err());
+        assert!(models.loaded().is_empty());
+    }
+}
@@ -0,0 +1,467 @@
+//! Download state, disk usage, removal and moving of downloaded models
+
+use crate::config::{Config, LlmModel, SttEngine};
+use crate::downloads::{partial_path, DownloadableModel};
+use crate::integrity::{sha256_file, ChecksumManifest};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes copied between progress reports while moving models
+const COPY_CHUNK: usize = 1 << 20;
+
+/// Storage error with actionable context
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("{id} is the configured model. Select another model first, or force the deletion")]
+    ModelInUse { id: String },
+
+    #[error("Can't move models from {from} into {to}, which is inside it")]
+    NestedModelsDir { from: String, to: String },
+
+    #[error("The copy of {path} doesn't match the original")]
+    CopyMismatch { path: String },
+
+    #[error("{path} is not a writable directory: {message}")]
+    NotWritable { path: String, message: String },
+}
+
+/// How much of a model is in the models directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+    NotDownloaded,
+    /// Some files or partial files are there; downloading again resumes
+    Partial,
+    Downloaded,
+    /// A file doesn't have the size recorded when it was downloaded;
+    /// downloading again replaces it
+    Corrupt,
+}
+
+/// What of a model is on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadState {
+    pub status: DownloadStatus,
+    /// Size of the model's files, partial downloads included
+    pub downloaded_bytes: u64,
+    /// Size of the complete model: the recorded sizes once every file has
+    /// one, the catalogue's estimate until then (0 for a custom LLM)
+    pub expected_bytes: u64,
+}
+
+/// Check how much of `model` is in `models_dir`
+///
+/// A model counts as downloaded once each of its files is; files without a
+/// recorded checksum (placed by hand) are taken as complete, as loading
+/// does. Only the sizes are compared, so this is cheap enough for a model
+/// list: `integrity::verify_model` hashes the files.
+pub fn download_state(model: &DownloadableModel, models_dir: &Path) -> DownloadState {
+    let manifest = ChecksumManifest::load(models_dir).unwrap_or_else(|e| {
+        tracing::warn!("{:#}; model sizes not checked", e);
+        ChecksumManifest::default()
+    });
+
+    let files = model.files();
+    let (mut complete, mut corrupt, mut downloaded_bytes) = (0, false, 0);
+    let recorded: Option<u64> = files.iter().map(|file| manifest.get(&file.path).map(|c| c.size)).sum();
+    for file in &files {
+        let path = models_dir.join(&file.path);
+        match fs::metadata(&path) {
+            Ok(metadata) => {
+                complete += 1;
+                downloaded_bytes += metadata.len();
+                corrupt |= manifest.get(&file.path).is_some_and(|checksum| checksum.size != metadata.len());
+            }
+            Err(_) => downloaded_bytes += fs::metadata(partial_path(&path)).map_or(0, |m| m.len()),
+        }
+    }
+
+    let status = if corrupt {
+        DownloadStatus::Corrupt
+    } else if !files.is_empty() && complete == files.len() {
+        DownloadStatus::Downloaded
+    } else if downloaded_bytes > 0 {
+        DownloadStatus::Partial
+    } else {
+        DownloadStatus::NotDownloaded
+    };
+    let expected_bytes = recorded.filter(|_| !files.is_empty()).unwrap_or_else(|| model.download_bytes());
+    DownloadState { status, downloaded_bytes, expected_bytes }
+}
+
+/// Total size in bytes of everything in the models directory, partial
+/// downloads included (0 if the directory doesn't exist)
+pub fn disk_usage(models_dir: &Path) -> Result<u64> {
+    let entries = match fs::read_dir(models_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", models_dir)),
+    };
+
+    let mut total = 0;
+    for entry in entries {
+        let entry = entry?;
+        // Don't follow symlinks out of the directory
+        let metadata = entry.path().symlink_metadata()?;
+        if metadata.is_dir() {
+            total += disk_usage(&entry.path())?;
+        } else {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
+
+/// Check if `model` is the one the config selects (for Whisper and
+/// Moonshine, at the selected precision and only while that engine is
+/// selected, and for the speaker model, only while diarization is on)
+pub fn is_configured(model: &DownloadableModel, config: &Config) -> bool {
+    match model {
+        DownloadableModel::Whisper(whisper, precision) => {
+            config.stt_engine == SttEngine::Whisper
+                && config.whisper_model == *whisper
+                && config.whisper_precision == *precision
+        }
+        DownloadableModel::Moonshine(moonshine, precision) => {
+            config.stt_engine == SttEngine::Moonshine
+                && config.moonshine_model == *moonshine
+                && config.moonshine_precision == *precision
+        }
+        DownloadableModel::Llm(llm) => !matches!(llm, LlmModel::Custom(_)) && config.llm_model == *llm,
+        DownloadableModel::Speaker(speaker) => config.diarization.enabled && config.diarization.model == *speaker,
+    }
+}
+
+/// Delete the files of a model, including partial downloads, and return
+/// the number of bytes freed
+///
+/// Files that are already missing are skipped. Refuses to delete the
+/// configured model unless `force` is set.
+pub fn delete_model(model: &DownloadableModel, models_dir: &Path, config: &Config, force: bool) -> Result<u64> {
+    if !force && is_configured(model, config) {
+        return Err(StorageError::ModelInUse { id: model.id() }.into());
+    }
+
+    let mut freed = 0;
+    for file in model.files() {
+        let dest = models_dir.join(&file.path);
+        for path in [partial_path(&dest), dest] {
+            freed += remove_if_exists(&path)?;
+        }
+        ChecksumManifest::forget(models_dir, &file.path)?;
+    }
+
+    // Moonshine files live in their own directory; keep it if anything else is in there
+    if let DownloadableModel::Moonshine(moonshine, precision) = model {
+        let dir = models_dir.join(moonshine.dir_name(*precision));
+        if fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none()) {
+            fs::remove_dir(&dir).with_context(|| format!("Failed to remove {:?}", dir))?;
+        }
+    }
+
+    tracing::info!("Deleted {} ({} bytes)", model.id(), freed);
+    Ok(freed)
+}
+
+/// Check that `dir` is (or can be created as) a directory models can be
+/// written to
+pub fn check_writable(dir: &Path) -> Result<()> {
+    let not_writable = |e: std::io::Error| StorageError::NotWritable {
+        path: dir.display().to_string(),
+        message: e.to_string(),
+    };
+    fs::create_dir_all(dir).map_err(not_writable)?;
+    let probe = dir.join(format!(".voiceflow-write-test-{}", std::process::id()));
+    fs::write(&probe, b"").map_err(not_writable)?;
+    fs::remove_file(&probe).map_err(not_writable)?;
+    Ok(())
+}
+
+/// Move everything in the models directory `from` to `to`, reporting the
+/// bytes copied so far and the total to `progress`; returns the total
+///
+/// Each file is copied, then read back and compared with the original by
+/// SHA-256. The originals are only removed once every copy matches, so a
+/// failure leaves `from` intact. Files already in `to` with the same
+/// contents are not copied again.
+pub fn migrate_models_dir(from: &Path, to: &Path, mut progress: impl FnMut(u64, u64)) -> Result<u64> {
+    if !from.is_dir() {
+        return Ok(0);
+    }
+    check_writable(to)?;
+    let (from_real, to_real) = (from.canonicalize()?, to.canonicalize()?);
+    if from_real == to_real {
+        return Ok(0);
+    }
+    if to_real.starts_with(&from_real) {
+        return Err(StorageError::NestedModelsDir {
+            from: from.display().to_string(),
+            to: to.display().to_string(),
+        }
+        .into());
+    }
+
+    let mut files = Vec::new();
+    list_files(from, Path::new(""), &mut files)?;
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+    tracing::info!("Moving {} files ({} bytes) from {:?} to {:?}", files.len(), total, from, to);
+
+    let mut copied = 0;
+    for (relative, size) in &files {
+        let (source, dest) = (from.join(relative), to.join(relative));
+        if dest.is_file() && fs::metadata(&dest)?.len() == *size && sha256_file(&dest)? == sha256_file(&source)? {
+            copied += size;
+            progress(copied, total);
+            continue;
+        }
+        copy_verified(&source, &dest, |n| {
+            copied += n;
+            progress(copied, total);
+        })?;
+    }
+
+    for (relative, _) in &files {
+        fs::remove_file(from.join(relative)).with_context(|| format!("Failed to remove {:?}", from.join(relative)))?;
+    }
+    remove_empty_dirs(from);
+    Ok(total)
+}
+
+/// Files under `dir`, as (path relative to the models directory, size)
+///
+/// Symlinks are skipped, so nothing outside the directory is moved.
+fn list_files(dir: &Path, relative: &Path, files: &mut Vec<(PathBuf, u64)>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        let path = relative.join(entry.file_name());
+        if metadata.is_dir() {
+            list_files(&entry.path(), &path, files)?;
+        } else if metadata.is_file() {
+            files.push((path, metadata.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Copy a file through a partial file, then check the copy against the
+/// digest of what was read
+fn copy_verified(source: &Path, dest: &Path, mut on_copied: impl FnMut(u64)) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = partial_path(dest);
+    let mut reader = File::open(source).with_context(|| format!("Failed to open {:?}", source))?;
+    let mut writer = File::create(&partial).with_context(|| format!("Failed to create {:?}", partial))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; COPY_CHUNK];
+    loop {
+        let n = reader.read(&mut buf).with_context(|| format!("Failed to read {:?}", source))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n]).with_context(|| format!("Failed to write {:?}", partial))?;
+        on_copied(n as u64);
+    }
+    writer.sync_all()?;
+    drop(writer);
+
+    if sha256_file(&partial)? != format!("{:x}", hasher.finalize()) {
+        let _ = fs::remove_file(&partial);
+        return Err(StorageError::CopyMismatch { path: source.display().to_string() }.into());
+    }
+    fs::rename(&partial, dest).with_context(|| format!("Failed to move {:?} into place", partial))?;
+    Ok(())
+}
+
+/// Remove `dir` and the directories in it that are empty
+fn remove_empty_dirs(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.path().symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+                remove_empty_dirs(&entry.path());
+            }
+        }
+    }
+    let _ = fs::remove_dir(dir);
+}
+
+/// Remove a file, returning its size (0 if it didn't exist)
+fn remove_if_exists(path: &Path) -> Result<u64> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ModelPrecision, MoonshineModel, WhisperModel};
+    use crate::integrity::{FileChecksum, MANIFEST_FILE};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voiceflow-storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, len: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; len]).unwrap();
+    }
+
+    fn whisper_engine_config() -> Config {
+        Config { stt_engine: SttEngine::Whisper, whisper_model: WhisperModel::Base, ..Config::default() }
+    }
+
+    #[test]
+    fn test_download_state_of_interrupted_moonshine_download() {
+        let dir = temp_dir("state-partial");
+        let model = DownloadableModel::Moonshine(MoonshineModel::Tiny, ModelPrecision::Float32);
+        let files = model.files();
+        assert_eq!(download_state(&model, &dir).status, DownloadStatus::NotDownloaded);
+
+        // One file done, one partial, the rest missing
+        write(&dir.join(&files[0].path), 100);
+        write(&partial_path(&dir.join(&files[1].path)), 50);
+        let state = download_state(&model, &dir);
+        assert_eq!(state.status, DownloadStatus::Partial);
+        assert_eq!(state.downloaded_bytes, 150);
+        assert_eq!(state.expected_bytes, model.download_bytes());
+
+        for (i, file) in files.iter().enumerate() {
+            write(&dir.join(&file.path), 100);
+            ChecksumManifest::record(&dir, &file.path, FileChecksum { size: 100, sha256: "00".repeat(32) }).unwrap();
+            let status = download_state(&model, &dir).status;
+            let expected = if i + 1 == files.len() { DownloadStatus::Downloaded } else { DownloadStatus::Partial };
+            assert_eq!(status, expected, "after {} of {} files", i + 1, files.len());
+        }
+        let state = download_state(&model, &dir);
+        assert_eq!(state.expected_bytes, 100 * files.len() as u64);
+    }
+
+    #[test]
+    fn test_download_state_flags_size_mismatch() {
+        let dir = temp_dir("state-corrupt");
+        let model = DownloadableModel::Llm(LlmModel::Gemma2_2B);
+        let relative = PathBuf::from(LlmModel::Gemma2_2B.filename());
+        write(&dir.join(&relative), 1000);
+        // Placed by hand: no record to compare with
+        assert_eq!(download_state(&model, &dir).status, DownloadStatus::Downloaded);
+
+        ChecksumManifest::record(&dir, &relative, FileChecksum { size: 4000, sha256: "00".repeat(32) }).unwrap();
+        let state = download_state(&model, &dir);
+        assert_eq!(state.status, DownloadStatus::Corrupt);
+        assert_eq!((state.downloaded_bytes, state.expected_bytes), (1000, 4000));
+    }
+
+    #[test]
+    fn test_disk_usage_counts_nested_and_partial_files() {
+        let dir = temp_dir("usage");
+        write(&dir.join("ggml-base.bin"), 1000);
+        write(&dir.join("moonshine-tiny/encode.onnx"), 300);
+        write(&partial_path(&dir.join("qwen3.gguf")), 20);
+
+        assert_eq!(disk_usage(&dir).unwrap(), 1320);
+        assert_eq!(disk_usage(&dir.join("missing")).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_delete_removes_files_and_partials() {
+        let dir = temp_dir("delete");
+        let model = DownloadableModel::Moonshine(MoonshineModel::Tiny, ModelPrecision::Float32);
+        let files = model.files();
+        // Interrupted download: two files done, one partial, one missing
+        write(&dir.join(&files[0].path), 100);
+        write(&dir.join(&files[1].path), 200);
+        write(&partial_path(&dir.join(&files[2].path)), 50);
+        ChecksumManifest::record(&dir, &files[0].path, FileChecksum { size: 100, sha256: "00".repeat(32) }).unwrap();
+        write(&dir.join("ggml-base.bin"), 1000);
+
+        let freed = delete_model(&model, &dir, &whisper_engine_config(), false).unwrap();
+
+        assert_eq!(freed, 350);
+        assert!(!dir.join(MoonshineModel::Tiny.dir_name(ModelPrecision::Float32)).exists());
+        assert!(ChecksumManifest::load(&dir).unwrap().get(&files[0].path).is_none());
+        assert_eq!(disk_usage(&dir).unwrap(), 1000 + fs::metadata(dir.join(MANIFEST_FILE)).unwrap().len());
+    }
+
+    #[test]
+    fn test_delete_missing_model_frees_nothing() {
+        let dir = temp_dir("missing");
+        let model = DownloadableModel::Llm(LlmModel::Gemma2_2B);
+        assert_eq!(delete_model(&model, &dir, &Config::default(), false).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_configured_model_needs_force() {
+        let dir = temp_dir("in-use");
+        let config = whisper_engine_config();
+        let model = DownloadableModel::Whisper(WhisperModel::Base, ModelPrecision::Float32);
+        let path = dir.join(WhisperModel::Base.filename(ModelPrecision::Float32));
+        write(&path, 1000);
+
+        let err = delete_model(&model, &dir, &config, false).unwrap_err();
+        assert!(matches!(err.downcast_ref::<StorageError>(), Some(StorageError::ModelInUse { .. })), "{}", err);
+        assert!(path.exists());
+
+        // Another precision of the configured model isn't in use
+        let int8 = DownloadableModel::Whisper(WhisperModel::Base, ModelPrecision::Int8);
+        assert_eq!(delete_model(&int8, &dir, &config, false).unwrap(), 0);
+
+        assert_eq!(delete_model(&model, &dir, &config, true).unwrap(), 1000);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_migrate_copies_verifies_and_removes_originals() {
+        let from = temp_dir("migrate-from");
+        let to = temp_dir("migrate-to");
+        write(&from.join("ggml-base.bin"), 3 * COPY_CHUNK + 5);
+        write(&from.join("moonshine-tiny/encode.onnx"), 300);
+        ChecksumManifest::record(&from, Path::new("ggml-base.bin"), FileChecksum { size: 5, sha256: "00".repeat(32) })
+            .unwrap();
+        // Already copied by an earlier, interrupted attempt
+        write(&to.join("moonshine-tiny/encode.onnx"), 300);
+
+        let mut reports = Vec::new();
+        let total = migrate_models_dir(&from, &to, |done, total| reports.push((done, total))).unwrap();
+
+        let expected = disk_usage(&to).unwrap();
+        assert_eq!(total, expected);
+        assert_eq!(reports.last(), Some(&(total, total)));
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(fs::metadata(to.join("ggml-base.bin")).unwrap().len(), 3 * COPY_CHUNK as u64 + 5);
+        assert!(ChecksumManifest::load(&to).unwrap().get(Path::new("ggml-base.bin")).is_some());
+        assert!(!from.exists());
+    }
+
+    #[test]
+    fn test_migrate_into_itself_is_rejected() {
+        let from = temp_dir("migrate-nested");
+        write(&from.join("ggml-base.bin"), 10);
+
+        let err = migrate_models_dir(&from, &from.join("sub"), |_, _| {}).unwrap_err();
+        assert!(matches!(err.downcast_ref::<StorageError>(), Some(StorageError::NestedModelsDir { .. })), "{}", err);
+        assert_eq!(migrate_models_dir(&from, &from, |_, _| {}).unwrap(), 0);
+        assert!(from.join("ggml-base.bin").exists());
+    }
+
+    #[test]
+    fn test_stt_model_of_other_engine_is_not_in_use() {
+        let config = Config { stt_engine: SttEngine::Moonshine, ..whisper_engine_config() };
+        assert!(!is_configured(&DownloadableModel::Whisper(WhisperModel::Base, ModelPrecision::Float32), &config));
+        let moonshine = DownloadableModel::Moonshine(config.moonshine_model.clone(), config.moonshine_precision);
+        assert!(is_configured(&moonshine, &config));
+        assert!(is_configured(&DownloadableModel::Llm(config.llm_model.clone()), &config));
+    }
+}
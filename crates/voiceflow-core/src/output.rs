@@ -0,0 +1,300 @@
+//! Subtitles (SRT and WebVTT) from the word timestamps of a result
+
+use anyhow::Result;
+
+use crate::pipeline::PipelineResult;
+use crate::transcribe::WordTimestamp;
+
+/// Why subtitles can't be made from a result
+#[derive(Debug, thiserror::Error)]
+pub enum SubtitleError {
+    #[error("The result has a transcript but no word timestamps (streaming results have none)")]
+    NoTimestamps,
+}
+
+/// How words are grouped into captions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubtitleOptions {
+    /// Longest line, in characters; a longer single word gets a line of its own
+    pub max_chars_per_line: usize,
+    /// Most lines shown at once
+    pub max_lines: usize,
+    /// Longest time a caption stays on screen (ms)
+    pub max_duration_ms: u64,
+}
+
+impl Default for SubtitleOptions {
+    fn default() -> Self {
+        Self {
+            max_chars_per_line: 42,
+            max_lines: 2,
+            max_duration_ms: 7000,
+        }
+    }
+}
+
+/// One caption: its lines, and when it is shown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caption {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub lines: Vec<String>,
+}
+
+/// Group words into captions
+///
+/// A caption ends when the next word doesn't fit in `max_lines` lines, would
+/// keep it on screen longer than `max_duration_ms`, or after a word ending a
+/// sentence. Captions never overlap and always last at least 1ms.
+pub fn captions(words: &[WordTimestamp], options: &SubtitleOptions) -> Vec<Caption> {
+    let max_chars = options.max_chars_per_line.max(1);
+    let max_lines = options.max_lines.max(1);
+    let mut captions: Vec<Caption> = Vec::new();
+    let mut current: Option<Caption> = None;
+    let mut last_end = 0;
+
+    for word in words {
+        let text = word.word.split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.is_empty() {
+            continue;
+        }
+        let start = (word.start_ms.max(0) as u64).max(last_end);
+        let end = (word.end_ms.max(0) as u64).max(start + 1);
+
+        let added = match current.as_mut() {
+            Some(caption) if end - caption.start_ms <= options.max_duration_ms => {
+                let line = caption.lines.last_mut().unwrap();
+                if line.chars().count() + 1 + text.chars().count() <= max_chars {
+                    line.push(' ');
+                    line.push_str(&text);
+                    true
+                } else if caption.lines.len() < max_lines {
+                    caption.lines.push(text.clone());
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        };
+        if added {
+            current.as_mut().unwrap().end_ms = end;
+        } else {
+            captions.extend(current.take());
+            current = Some(Caption { start_ms: start, end_ms: end, lines: vec![text.clone()] });
+        }
+        last_end = end;
+
+        if text.ends_with(['.', '?', '!']) {
+            captions.extend(current.take());
+        }
+    }
+    captions.extend(current);
+    captions
+}
+
+/// SubRip subtitles of the raw transcript with the default options
+pub fn to_srt(result: &PipelineResult) -> Result<String> {
+    to_srt_with_options(result, &SubtitleOptions::default())
+}
+
+/// SubRip subtitles of the raw transcript
+///
+/// Fails with `SubtitleError::NoTimestamps` if the result has a transcript
+/// but no word timestamps; a result without speech gives an empty file.
+pub fn to_srt_with_options(result: &PipelineResult, options: &SubtitleOptions) -> Result<String> {
+    Ok(srt(&captions(timed_words(result)?, options)))
+}
+
+/// WebVTT subtitles of the raw transcript with the default options
+pub fn to_vtt(result: &PipelineResult) -> Result<String> {
+    to_vtt_with_options(result, &SubtitleOptions::default())
+}
+
+/// WebVTT subtitles of the raw transcript
+///
+/// Fails like `to_srt_with_options`.
+pub fn to_vtt_with_options(result: &PipelineResult, options: &SubtitleOptions) -> Result<String> {
+    Ok(vtt(&captions(timed_words(result)?, options)))
+}
+
+/// Format captions as SubRip
+///
+/// Text that would end a cue early ("-->") is replaced, since SubRip has no
+/// escapes.
+pub fn srt(captions: &[Caption]) -> String {
+    let mut out = String::new();
+    for (i, caption) in captions.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n",
+            i + 1,
+            timestamp(caption.start_ms, ','),
+            timestamp(caption.end_ms, ',')
+        ));
+        for line in &caption.lines {
+            out.push_str(&line.replace("-->", "->"));
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Format captions as WebVTT, escaping `&`, `<` and `>`
+pub fn vtt(captions: &[Caption]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for caption in captions {
+        out.push_str(&format!("{} --> {}\n", timestamp(caption.start_ms, '.'), timestamp(caption.end_ms, '.')));
+        for line in &caption.lines {
+            out.push_str(&line.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"));
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn timed_words(result: &PipelineResult) -> Result<&[WordTimestamp]> {
+    if result.word_timestamps.is_empty() && !result.raw_transcript.trim().is_empty() {
+        return Err(SubtitleError::NoTimestamps.into());
+    }
+    Ok(&result.word_timestamps)
+}
+
+/// `HH:MM:SS,mmm` (SubRip) or `HH:MM:SS.mmm` (WebVTT)
+fn timestamp(ms: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(word: &str, start_ms: i64, end_ms: i64) -> WordTimestamp {
+        WordTimestamp { word: word.to_string(), start_ms, end_ms, probability: 0.9 }
+    }
+
+    fn fixture_words() -> Vec<WordTimestamp> {
+        vec![
+            word("Welcome", 120, 480),
+            word("to", 480, 600),
+            word("the", 600, 720),
+            word("quarterly", 720, 1250),
+            word("review.", 1250, 1800),
+            word("Today", 2400, 2760),
+            word("we'll", 2760, 3000),
+            word("cover", 3000, 3300),
+            word("revenue", 3300, 3800),
+            word("growth", 3800, 4200),
+            word("across", 4200, 4600),
+            word("all", 4600, 4800),
+            word("regions", 4800, 5300),
+            word("and", 5300, 5450),
+            word("the", 5450, 5550),
+            word("new", 5550, 5800),
+            word("R&D", 5800, 6400),
+            word("<budget>", 6400, 7100),
+            word("-->", 7100, 7200),
+            word("plans", 7200, 7700),
+            word("for", 9000, 9200),
+            word("3661", 3_661_000, 3_661_500),
+        ]
+    }
+
+    /// Parse SubRip back into captions
+    fn parse_srt(srt: &str) -> Vec<Caption> {
+        srt.split_terminator("\n\n")
+            .enumerate()
+            .map(|(i, block)| {
+                let mut lines = block.lines();
+                assert_eq!(lines.next().unwrap(), (i + 1).to_string());
+                let (start, end) = lines.next().unwrap().split_once(" --> ").unwrap();
+                let ms = |t: &str| {
+                    let (hms, millis) = t.split_once(',').unwrap();
+                    let parts: Vec<u64> = hms.split(':').map(|p| p.parse().unwrap()).collect();
+                    ((parts[0] * 60 + parts[1]) * 60 + parts[2]) * 1000 + millis.parse::<u64>().unwrap()
+                };
+                Caption { start_ms: ms(start), end_ms: ms(end), lines: lines.map(str::to_string).collect() }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_srt_matches_fixture_and_round_trips() {
+        let options = SubtitleOptions { max_chars_per_line: 24, ..SubtitleOptions::default() };
+        let captions = captions(&fixture_words(), &options);
+        let srt = srt(&captions);
+        assert_eq!(srt, include_str!("../testdata/captions.srt"));
+
+        let mut expected = captions.clone();
+        expected[2].lines[1] = expected[2].lines[1].replace("-->", "->");
+        assert_eq!(parse_srt(&srt), expected);
+    }
+
+    #[test]
+    fn test_vtt_escapes_and_uses_dots() {
+        let options = SubtitleOptions { max_chars_per_line: 24, ..SubtitleOptions::default() };
+        let vtt = vtt(&captions(&fixture_words(), &options));
+        let first = "WEBVTT\n\n00:00:00.120 --> 00:00:01.800\nWelcome to the quarterly\nreview.\n\n";
+        assert!(vtt.starts_with(first), "{}", vtt);
+        assert!(vtt.contains("R&amp;D &lt;budget&gt; --&gt;"), "{}", vtt);
+        assert!(vtt.contains("01:01:01.000 --> 01:01:01.500"), "{}", vtt);
+    }
+
+    #[test]
+    fn test_captions_respect_limits() {
+        let words: Vec<_> = (0..40).map(|i| word("word", i * 400, i * 400 + 300)).collect();
+        let options = SubtitleOptions { max_chars_per_line: 20, max_lines: 1, max_duration_ms: 2000 };
+        let captions = captions(&words, &options);
+        for pair in captions.windows(2) {
+            assert!(pair[0].end_ms <= pair[1].start_ms);
+        }
+        for caption in &captions {
+            assert_eq!(caption.lines.len(), 1);
+            assert!(caption.lines[0].len() <= 20);
+            assert!(caption.end_ms - caption.start_ms <= 2000, "{:?}", caption);
+        }
+        assert_eq!(captions.iter().map(|c| c.lines[0].split(' ').count()).sum::<usize>(), 40);
+    }
+
+    #[test]
+    fn test_missing_timestamps() {
+        let mut result = PipelineResult {
+            raw_transcript: "hello world".to_string(),
+            formatted_text: "Hello world.".to_string(),
+            timings: Default::default(),
+            prosody_hints: None,
+            word_timestamps: Vec::new(),
+            confidence: 0.9,
+            alternatives: Vec::new(),
+            no_speech_probability: 0.0,
+            no_speech: false,
+            language: Some("en".to_string()),
+            original_transcript: None,
+            raw_llm_output: None,
+            was_fallback: false,
+            formatting_error: None,
+            prompt_truncation: None,
+            format_cache_hit: false,
+            clipped_percent: 0.0,
+            clipping: false,
+            repaired_samples: 0,
+            filtered_segments: Vec::new(),
+            scratch_previous: false,
+            segments: Vec::new(),
+            fast_path: false,
+        };
+        assert!(to_srt(&result).unwrap_err().downcast_ref::<SubtitleError>().is_some());
+
+        result.raw_transcript.clear();
+        assert_eq!(to_srt(&result).unwrap(), "");
+        assert_eq!(to_vtt(&result).unwrap(), "WEBVTT\n\n");
+    }
+}
@@ -1,13 +1,37 @@
 //! Main processing pipeline: Audio → Transcription → LLM Formatting
+//!
+//! Each request runs in a `request` span, with a child span per stage
+//! (`stt_load`, `vad`, `stt`, `prosody`, `diarize`, `format`, `llm_load`)
+//! carrying sizes and counts, never text; resampling the input before that
+//! is `audio_prep`. Spans are only recorded when a subscriber asks for
+//! them, e.g. voiceflow-ffi's event callback.
 
 use crate::{
-    config::{Config, SttEngine as SttEngineConfig},
-    llm::LlmEngine,
-    prosody::{self, ProsodyHints, replace_voice_commands, concatenate_spelled_words_aggressive, ReplacementDictionary},
-    transcribe::{WhisperEngine, MoonshineEngine, TranscriptionResult},
+    audio::{load_audio_file, non_finite_count, AudioFileError, preprocess, speech_regions, AudioInput, PreprocessStats, ResampleState, TARGET_SAMPLE_RATE},
+    builder::PipelineBuilder,
+    cancel::{CancelToken, TimeoutStage},
+    context::DictationContext,
+    diarize::{self, SpeakerEmbedder},
+    history::{History, HistoryRecord},
+    learning::{self, Correction, Corrections},
+    progress::{self, FormatProgress, ProcessStage, ProgressReporter, RawTranscriptHook},
+    config::{check_language, check_prompt_template, check_stt_task, AudioOptions, Config, ConfigError, FormatterBackend, LlmModel, LlmOptions, SttEngine as SttEngineConfig, SttTask, AUTO_LANGUAGE},
+    llm::{format_prompt, personal_dictionary, same_words, word_similarity, ChatTemplate, FormatCache, FormatCacheKey, FormatContext, FormattingPreset, LlmEngine, LlmOutput, LlmStats, PromptBudget, PromptParts, PromptPlan, PromptTruncation, TextFormatter, TokenSink, PUNCTUATION_ONLY_PROMPT},
+    prosody::{self, ProsodyHints, apply_voice_commands, concatenate_spelled_words_aggressive, ReplacementDictionary, VoiceCommandOutput},
+    segment::{self, Segment},
+    session::SessionState,
+    text::{join, normalize_numbers, ReplacementRules},
+    transcribe::{filter_hallucinations, plan_chunks, stitch_transcriptions, substitutes, ActiveSttEngine, Alternative, FilteredSegment, SpeechToText, SttOptions, WhisperEngine, MoonshineEngine, TranscriptionResult, WordTimestamp},
 };
+#[cfg(feature = "remote-formatter")]
+use crate::llm::RemoteFormatter;
 use anyhow::{Context, Result};
-use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 /// Error recovery configuration
 #[derive(Debug, Clone)]
@@ -30,8 +54,80 @@ impl Default for RecoveryConfig {
     }
 }
 
+/// Half a second of silence at 16kHz, transcribed to warm up the STT engine
+const WARMUP_SAMPLES: usize = 8_000;
+
+/// Transcript formatted to warm up the LLM
+const WARMUP_TRANSCRIPT: &str = "hello world";
+
+/// Tokens generated when warming up the LLM
+const WARMUP_MAX_TOKENS: u32 = 4;
+
+/// Shortest audio worth transcribing, in milliseconds
+const MIN_AUDIO_MS: u64 = 100;
+
+/// The `RELOAD_FIELDS` only the LLM loads with; the others are the STT
+/// engine's, and `models_dir_override` and `deterministic` are both's
+const LLM_RELOAD_FIELDS: &[&str] = &[
+    "llm_model",
+    "custom_model_name",
+    "chat_template",
+    "formatter",
+    "llm_options.seed",
+    "llm_options.n_gpu_layers",
+    "llm_threads",
+    "llm_prefix_cache",
+];
+
+/// Stage of pipeline initialization, reported to an `InitProgress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitStage {
+    /// Reading the config file (reported by callers that load it)
+    LoadingConfig,
+    /// Loading the STT encoder (the whole model for Whisper)
+    LoadingSttEncoder,
+    /// Loading the STT decoder (Moonshine only)
+    LoadingSttDecoder,
+    LoadingLlm,
+    /// Running `Pipeline::warm_up`
+    WarmingUp,
+    Ready,
+}
+
+impl InitStage {
+    /// Overall progress (0-100) when the stage starts
+    pub fn percent(&self) -> u8 {
+        match self {
+            Self::LoadingConfig => 0,
+            Self::LoadingSttEncoder => 5,
+            Self::LoadingSttDecoder => 25,
+            Self::LoadingLlm => 45,
+            Self::WarmingUp => 90,
+            Self::Ready => 100,
+        }
+    }
+}
+
+/// Receives the stages of `Pipeline::new_with_progress` as they start
+pub trait InitProgress {
+    fn report(&self, stage: InitStage);
+}
+
+impl<F: Fn(InitStage)> InitProgress for F {
+    fn report(&self, stage: InitStage) {
+        self(stage)
+    }
+}
+
 /// Pipeline error with actionable context
+///
+/// Returned by `Pipeline::new` and the `process` methods. Match on
+/// `without_context()` to see the cause under any context the pipeline
+/// added; its `Display` is one link of the chain, so convert it to an
+/// `anyhow::Error` and format that with `{:#}` for the whole message. New
+/// variants may be added.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum PipelineError {
     #[error("STT model not found: {path}. Run 'voiceflow setup' or download from the app's Settings → Models tab")]
     SttModelNotFound { path: String },
@@ -45,65 +141,786 @@ pub enum PipelineError {
     #[error("LLM initialization failed after {attempts} attempts: {message}")]
     LlmInitFailed { attempts: u32, message: String },
 
-    #[error("Transcription failed: {message}")]
-    TranscriptionFailed { message: String },
+    #[error("{engine} transcription failed")]
+    TranscriptionFailed {
+        engine: String,
+        #[source]
+        source: anyhow::Error,
+    },
 
-    #[error("LLM formatting failed: {message}. Returning raw transcript.")]
+    #[error("LLM formatting failed: {message}")]
     LlmFormattingFailed { message: String },
 
+    #[error("The formatting prompt takes {tokens} tokens, more than the {budget} the LLM's context window leaves it. Shorten the prompt or the vocabulary, or lower llm.max_tokens")]
+    PromptTooLong { tokens: usize, budget: usize },
+
     #[error("Audio too short: {duration_ms}ms (minimum: 100ms)")]
     AudioTooShort { duration_ms: u64 },
+
+    #[error("Audio is empty: no samples were recorded")]
+    EmptyAudio,
+
+    #[error("Audio has {count} NaN or infinite samples. Turn off audio.strict to replace them with silence")]
+    InvalidSamples { count: usize },
+
+    #[error("Audio too long: {duration_secs}s (maximum: {max_secs}s). Split it or raise audio.max_duration_secs")]
+    AudioTooLong { duration_secs: u64, max_secs: u32 },
+
+    #[error("Model file {path} is corrupted: expected {expected}, found {actual}. Delete it and download it again")]
+    ModelCorrupted { path: String, expected: String, actual: String },
+
+    #[error("Failed to load ONNX model {path}: {message}")]
+    OnnxLoadFailed { path: String, message: String },
+
+    #[error("Remote formatter {url} failed: {message}")]
+    RemoteFormatter { url: String, message: String },
+
+    #[error("Speaker model not found: {path}. Run 'voiceflow models download wespeaker-resnet34' or download it from the app's Settings → Models tab")]
+    SpeakerModelNotFound { path: String },
+
+    #[error("Diarization is enabled, but this build has no speaker diarization (the diarization feature). Turn off diarization.enabled")]
+    DiarizationUnavailable,
+
+    #[error("Invalid context: {message}. Pass a JSON object with app_name, document_kind, recipient, text_before_cursor, text_after_cursor and user_instructions, or a plain string")]
+    InvalidContext { message: String },
+
+    #[error("Processing cancelled after {}ms", timings.total_ms)]
+    Cancelled { timings: Box<Timings> },
+
+    #[error("The {stage} time limit ran out after {elapsed_ms}ms. Raise timeouts.{stage}_ms, or set it to 0 for no limit")]
+    Timeout { stage: TimeoutStage, elapsed_ms: u64 },
+
+    #[error("Invalid configuration")]
+    Config(#[from] ConfigError),
+
+    #[error("Failed to load the audio file")]
+    AudioFile(#[from] AudioFileError),
+
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+
+    /// What the pipeline was doing when `source` happened
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<PipelineError>,
+    },
+
+    /// A failure of no kind above, e.g. from a custom engine
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl PipelineError {
+    /// Cancellation raised inside an engine, before the pipeline knows the timings
+    pub(crate) fn cancelled() -> Self {
+        Self::Cancelled { timings: Box::default() }
+    }
+
+    /// The error under any `Context` layers
+    pub fn without_context(&self) -> &PipelineError {
+        match self {
+            Self::Context { source, .. } => source.without_context(),
+            other => other,
+        }
+    }
+}
+
+impl From<anyhow::Error> for PipelineError {
+    /// Lift an error from the pipeline's internals: its first cause of a
+    /// known type becomes the variant, with the messages added above it
+    /// kept as `Context` layers, and anything else is `Other`
+    fn from(err: anyhow::Error) -> Self {
+        let Some(position) = err.chain().position(|cause| lift(cause).is_some()) else {
+            return Self::Other(err);
+        };
+        let context: Vec<String> = err.chain().take(position).map(ToString::to_string).collect();
+        let take = err.chain().nth(position).and_then(lift).expect("cause found above");
+        match take(err) {
+            Ok(cause) => context
+                .into_iter()
+                .rev()
+                .fold(cause, |source, context| Self::Context { context, source: Box::new(source) }),
+            // Under a source of some other error, where anyhow can't reach it
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+/// Take the error a `PipelineError` variant holds out of an `anyhow` chain
+type Lift = fn(anyhow::Error) -> Result<PipelineError, anyhow::Error>;
+
+/// How to lift `cause` into a `PipelineError`, if it has a variant
+fn lift(cause: &(dyn std::error::Error + 'static)) -> Option<Lift> {
+    if cause.is::<PipelineError>() {
+        Some(|err| err.downcast())
+    } else if cause.is::<ConfigError>() {
+        Some(|err| err.downcast().map(PipelineError::Config))
+    } else if cause.is::<AudioFileError>() {
+        Some(|err| err.downcast().map(PipelineError::AudioFile))
+    } else if cause.is::<std::io::Error>() {
+        Some(|err| err.downcast().map(PipelineError::Io))
+    } else {
+        None
+    }
+}
+
+/// Wrap a failure of the STT engine, unless it already says what happened
+/// (a cancellation, say)
+fn stt_error(config: &Config, err: anyhow::Error) -> anyhow::Error {
+    if err.downcast_ref::<PipelineError>().is_some() {
+        return err;
+    }
+    PipelineError::TranscriptionFailed { engine: config.stt_engine.display_name().to_string(), source: err }.into()
+}
+
+/// Read a request's context (see `DictationContext::parse`)
+pub(crate) fn parse_context(context: Option<&str>) -> Result<DictationContext> {
+    DictationContext::parse(context).map_err(|e| PipelineError::InvalidContext { message: e.to_string() }.into())
+}
+
+/// Reject 16kHz mono audio the pipeline shouldn't transcribe, returning how
+/// many of its samples are NaN or infinite
+///
+/// Those are replaced with silence later, unless `AudioOptions::strict`
+/// makes them an error here, ahead of any other problem with the audio.
+fn check_audio(audio: &[f32], options: &AudioOptions) -> Result<usize> {
+    if audio.is_empty() {
+        return Err(PipelineError::EmptyAudio.into());
+    }
+    let count = non_finite_count(audio);
+    if count > 0 && options.strict {
+        return Err(PipelineError::InvalidSamples { count }.into());
+    }
+    let duration_ms = audio.len() as u64 * 1000 / TARGET_SAMPLE_RATE as u64;
+    let max_secs = options.max_duration_secs;
+    if max_secs > 0 && duration_ms > max_secs as u64 * 1000 {
+        return Err(PipelineError::AudioTooLong { duration_secs: duration_ms / 1000, max_secs }.into());
+    }
+    if duration_ms < MIN_AUDIO_MS {
+        return Err(PipelineError::AudioTooShort { duration_ms }.into());
+    }
+    if count > 0 {
+        tracing::warn!("Replacing {} NaN or infinite samples with silence", count);
+    }
+    Ok(count)
+}
+
+/// Build a `PipelineError::Cancelled` with the timings reached so far, or
+/// the `PipelineError::Timeout` if `cancel` was stopped by a time limit
+fn cancelled_error(
+    cancel: &CancelToken,
+    start: Instant,
+    transcription_ms: u64,
+    prosody_ms: u64,
+    llm_formatting_ms: u64,
+) -> anyhow::Error {
+    if let Some(timeout) = cancel.timeout() {
+        tracing::warn!("{}", timeout);
+        return timeout.into();
+    }
+    let timings = Timings {
+        transcription_ms,
+        prosody_ms,
+        llm_formatting_ms,
+        total_ms: start.elapsed().as_millis() as u64,
+        ..Default::default()
+    };
+    tracing::info!("Pipeline cancelled after {}ms", timings.total_ms);
+    PipelineError::Cancelled { timings: Box::new(timings) }.into()
+}
+
+/// A recording queued for `Pipeline::process_queued`, with its own options
+pub struct QueuedRequest<K> {
+    /// Handed back with the result
+    key: K,
+    /// 16kHz mono samples with the milliseconds preparing them took, or why
+    /// that failed
+    audio: Result<(Vec<f32>, u64)>,
+    context: Option<String>,
+    options: ProcessOptions,
+    /// Session the recording is the next dictation of
+    session: Option<Arc<Mutex<SessionState>>>,
+}
+
+impl<K> QueuedRequest<K> {
+    /// 16kHz mono `audio` with its context hint, processed as by
+    /// `Pipeline::process_with_options`
+    pub fn new(key: K, audio: Vec<f32>, context: Option<String>, options: ProcessOptions) -> Self {
+        Self::decoded(key, Ok((audio, 0)), context, options)
+    }
+
+    /// Audio read and converted in the given milliseconds, or why that failed
+    pub(crate) fn decoded(
+        key: K,
+        audio: Result<(Vec<f32>, u64)>,
+        context: Option<String>,
+        options: ProcessOptions,
+    ) -> Self {
+        Self { key, audio, context, options, session: None }
+    }
+
+    /// Process it as the next dictation of `session` (see
+    /// `Pipeline::process_in_session`)
+    pub fn in_session(self, session: Arc<Mutex<SessionState>>) -> Self {
+        Self { session: Some(session), ..self }
+    }
+}
+
+/// A queued recording, transcribed and waiting for the LLM
+struct QueuedTranscript {
+    audio: Vec<f32>,
+    audio_prep_ms: u64,
+    transcribed: Transcribed,
+    dictation: DictationContext,
+    /// The request's options, as `prepare` returns them
+    options: ProcessOptions,
+    /// Taking the short-command fast path
+    quick: bool,
+}
+
+/// Transcribe a queued recording as `Pipeline::run` would, on the STT
+/// thread of `Pipeline::process_queued`
+fn transcribe_queued(
+    stt: &mut dyn SpeechToText,
+    config: &Config,
+    formatting: FormattingMode,
+    (mut audio, audio_prep_ms): (Vec<f32>, u64),
+    context: Option<&str>,
+    options: &ProcessOptions,
+) -> Result<QueuedTranscript> {
+    let dictation = parse_context(context)?;
+    check_audio(&audio, &config.audio)?;
+    let (mut options, language, task) = prepare(config, formatting, options)?;
+    let start = Instant::now();
+    if options.cancel.is_cancelled() {
+        return Err(cancelled_error(&options.cancel, start, 0, 0, 0));
+    }
+    let quick = is_command(config, &audio, options.profile);
+    if quick {
+        tracing::debug!("Short command: transcribed whole, without the LLM");
+        options.formatting = FormattingMode::None;
+    }
+    let preprocessed = preprocess(&mut audio, &config.audio);
+    let mut stage = SttStage { stt, config, context: options.stt_context.as_deref(), quick };
+    let mut transcribed = stage.transcribe(&audio, &language, task, &options.cancel, options.progress.as_ref(), start)?;
+    transcribed.preprocess = preprocessed;
+    Ok(QueuedTranscript { audio, audio_prep_ms, transcribed, dictation, options, quick })
 }
 
-/// Unified STT engine wrapper
-enum SttEngine {
-    Whisper(WhisperEngine),
-    Moonshine(MoonshineEngine),
+/// Add a dictation's formatted text to its session, removing the one
+/// before it on "scratch that"
+fn continue_session(session: &mut SessionState, result: &PipelineResult) {
+    if result.scratch_previous {
+        session.pop();
+    }
+    session.push(&result.formatted_text);
+}
+
+/// Lock a session, recovering it from a panic while it was held
+fn lock_session(session: &Mutex<SessionState>) -> MutexGuard<'_, SessionState> {
+    session.lock().unwrap_or_else(|e| e.into_inner())
 }
 
-impl SttEngine {
-    fn new(config: &Config) -> Result<Self> {
-        match config.stt_engine {
-            SttEngineConfig::Whisper => {
-                tracing::info!("Using Whisper STT engine: {:?}", config.whisper_model);
-                Ok(Self::Whisper(WhisperEngine::new(config)?))
+/// Check per-call options against the loaded models, returning them with
+/// formatting capped at the pipeline's `formatting`, the app's override
+/// merged in (see `AppOverride`), and the language and task to use
+fn prepare(
+    config: &Config,
+    formatting: FormattingMode,
+    options: &ProcessOptions,
+) -> Result<(ProcessOptions, String, SttTask)> {
+    let mut options = ProcessOptions { formatting: options.formatting.at_most(formatting), ..options.clone() };
+    if let Some(app_id) = options.app_id.clone() {
+        if let Some(app) = config.app_override(&app_id) {
+            if let Some(most) = app.formatting_mode {
+                options.formatting = options.formatting.at_most(most);
             }
-            SttEngineConfig::Moonshine => {
-                tracing::info!("Using Moonshine STT engine: {:?}", config.moonshine_model);
-                Ok(Self::Moonshine(MoonshineEngine::new(config)?))
+            options.preset = options.preset.or_else(|| app.preset.clone());
+            // The configured terms are in the initial prompt already
+            if let Some(glossary) = config.app_stt_glossary(&app_id) {
+                options.stt_context = Some(match options.stt_context.take() {
+                    Some(context) => format!("{} {}", context.trim_end(), glossary),
+                    None => glossary,
+                });
             }
         }
     }
+    if let Some(FormattingPreset::Custom(template)) = &options.preset {
+        check_prompt_template(template)?;
+    }
+    let language = options.language.clone().unwrap_or_else(|| config.language.clone());
+    check_language(&language, &config.stt_engine)?;
+    let task = options.task.unwrap_or(config.stt_task);
+    check_stt_task(task, &config.stt_engine)?;
+    Ok((options, language, task))
+}
 
-    fn transcribe(&mut self, audio: &[f32]) -> Result<String> {
-        match self {
-            Self::Whisper(engine) => engine.transcribe(audio),
-            Self::Moonshine(engine) => engine.transcribe(audio),
+/// Whether `audio` takes the short-command fast path under `profile`
+fn is_command(config: &Config, audio: &[f32], profile: ProcessProfile) -> bool {
+    let audio_ms = duration_ms(audio) as u64;
+    match profile {
+        ProcessProfile::Auto => audio_ms < config.command_max_ms as u64,
+        ProcessProfile::Dictation => false,
+        // Asked for, but never on more than the engine takes in one go
+        ProcessProfile::Command => audio_ms <= config.audio.max_chunk_ms as u64,
+    }
+}
+
+/// Count the time spent reading and converting the audio in `result`
+fn with_audio_prep(mut result: PipelineResult, audio_prep_ms: u64) -> PipelineResult {
+    result.timings.audio_prep_ms = audio_prep_ms;
+    result.timings.total_ms += audio_prep_ms;
+    result
+}
+
+/// A transcript on its way to formatting
+pub(crate) struct Transcribed {
+    pub(crate) result: TranscriptionResult,
+    /// Timings of the transcription stage; formatting fills in the rest
+    pub(crate) timings: Timings,
+    pub(crate) original_transcript: Option<String>,
+    /// Whether the word timestamps come from the decoder, so pauses between
+    /// them can be trusted
+    pub(crate) decoder_timestamps: bool,
+    /// When the request began, so `total_ms` also covers transcription
+    pub(crate) start: Instant,
+    /// What preprocessing found in the audio
+    pub(crate) preprocess: PreprocessStats,
+}
+
+/// Whether a transcription should be discarded as silence or noise
+fn is_no_speech(config: &Config, transcription: &TranscriptionResult) -> bool {
+    transcription.text.trim().is_empty()
+        || config.is_no_speech(transcription.confidence, transcription.no_speech_probability)
+}
+
+/// Apply spoken punctuation and editing commands, in the transcript's
+/// language unless `VoiceCommands::language` sets one
+fn apply_commands(
+    config: &Config,
+    transcription: &TranscriptionResult,
+    task: SttTask,
+    text: &str,
+) -> VoiceCommandOutput {
+    let spoken = (config.language != AUTO_LANGUAGE).then_some(config.language.as_str());
+    let language = match (config.voice_commands.language.as_str(), task) {
+        (AUTO_LANGUAGE, SttTask::Translate) => "en",
+        (AUTO_LANGUAGE, _) => transcription.language.as_deref().or(spoken).unwrap_or("en"),
+        (language, _) => language,
+    };
+    apply_voice_commands(text, &config.voice_commands, language)
+}
+
+/// Write spoken numbers in an English transcript as digits; other
+/// languages are left alone
+fn format_numbers(config: &Config, transcription: &TranscriptionResult, task: SttTask, text: &str) -> String {
+    let english =
+        task == SttTask::Translate || transcription.language.as_deref().is_none_or(|language| language == "en");
+    if !english {
+        return text.to_string();
+    }
+    let formatted = normalize_numbers(text, &config.number_formatting);
+    if formatted != text {
+        tracing::debug!("After number formatting: {}", formatted);
+    }
+    formatted
+}
+
+/// The STT decoder's other readings, for the LLM to weigh words it may have
+/// misheard (empty when there are none)
+fn alternatives_hint(alternatives: &[Alternative]) -> String {
+    if alternatives.is_empty() {
+        return String::new();
+    }
+    let readings: Vec<String> = alternatives
+        .iter()
+        .map(|alternative| format!("\"{}\" ({:.2})", alternative.text, alternative.confidence))
+        .collect();
+    format!("\n[Speech recognition alternatives, most likely first: {}]", readings.join(", "))
+}
+
+/// Formatting results to keep: none in deterministic mode, so every result
+/// comes from the model
+fn format_cache_size(config: &Config) -> usize {
+    if config.deterministic {
+        0
+    } else {
+        config.format_cache_size
+    }
+}
+
+/// The find-and-replace rules of each app override that adds some, after
+/// the configured ones
+fn compile_app_rules(config: &Config) -> Result<BTreeMap<String, ReplacementRules>> {
+    let mut app_rules = BTreeMap::new();
+    for (app_id, app) in &config.app_overrides {
+        if !app.replacements_extra.is_empty() {
+            let rules = ReplacementRules::compile(&config.replacements_for_app(app_id))
+                .with_context(|| format!("Invalid app override {:?}", app_id))?;
+            app_rules.insert(app_id.clone(), rules);
         }
     }
+    Ok(app_rules)
+}
 
-    fn transcribe_with_timestamps(&mut self, audio: &[f32], enable_timestamps: bool) -> Result<TranscriptionResult> {
-        eprintln!("SttEngine: transcribe_with_timestamps called, {} samples", audio.len());
-        match self {
-            Self::Whisper(engine) => {
-                eprintln!("SttEngine: Using Whisper engine");
-                engine.transcribe_with_timestamps(audio, enable_timestamps)
-            },
-            Self::Moonshine(engine) => {
-                eprintln!("SttEngine: Using Moonshine engine");
-                engine.transcribe_with_timestamps(audio, enable_timestamps)
+/// Length of 16kHz audio in milliseconds
+fn duration_ms(audio: &[f32]) -> i64 {
+    (audio.len() as u64 * 1000 / TARGET_SAMPLE_RATE as u64) as i64
+}
+
+/// Formatting settings shared by the segments of a request
+struct FormatRequest<'a> {
+    mode: FormattingMode,
+    preset: Option<&'a FormattingPreset>,
+    llm_options: &'a LlmOptions,
+    /// Outputs may come from and go to the format cache
+    cacheable: bool,
+    cancel: &'a CancelToken,
+}
+
+/// What formatting the segments of a request took, added up
+#[derive(Debug, Default)]
+struct FormatTally {
+    llm_formatting_ms: u64,
+    llm_load_ms: u64,
+    stats: LlmStats,
+    was_fallback: bool,
+    /// The LLM failed and fallback is allowed, so the remaining segments
+    /// stay unformatted
+    llm_failed: bool,
+    /// Why the LLM failed
+    formatting_error: Option<String>,
+    /// What was cut from the prompts to fit the context window
+    truncation: PromptTruncation,
+    /// Segments taken from the format cache
+    cache_hits: usize,
+}
+
+impl FormatTally {
+    fn add_stats(&mut self, stats: &LlmStats) {
+        self.stats.prefill_ms += stats.prefill_ms;
+        self.stats.generate_ms += stats.generate_ms;
+        self.stats.tokens_generated += stats.tokens_generated;
+        self.stats.thinking_tokens += stats.thinking_tokens;
+    }
+}
+
+/// `sink` lent out for one call, leaving it usable afterwards
+fn reborrow<'s>(sink: &'s mut Option<&mut dyn TokenSink>) -> Option<&'s mut dyn TokenSink> {
+    sink.as_mut().map(|sink| &mut **sink as &mut dyn TokenSink)
+}
+
+/// Format the transcript pieces of `plan` one at a time, joining the outputs
+fn format_plan(
+    llm: &mut dyn TextFormatter,
+    plan: &PromptPlan,
+    request: &FormatRequest,
+    mut sink: Option<&mut dyn TokenSink>,
+) -> Result<LlmOutput> {
+    let ctx = FormatContext { prompt_template: &plan.prompt, llm_options: request.llm_options, cancel: request.cancel };
+    if let [transcript] = plan.chunks.as_slice() {
+        return llm.format_with_stats(transcript, &ctx, sink);
+    }
+    let mut joined = LlmOutput::default();
+    for (index, chunk) in plan.chunks.iter().enumerate() {
+        if index > 0 {
+            if let Some(sink) = reborrow(&mut sink) {
+                sink.token(" ");
+            }
+            joined.text.push(' ');
+            joined.raw.push_str("\n\n");
+        }
+        let output = llm.format_with_stats(chunk, &ctx, reborrow(&mut sink))?;
+        joined.text.push_str(&output.text);
+        joined.raw.push_str(&output.raw);
+        joined.stats.prefill_ms += output.stats.prefill_ms;
+        joined.stats.generate_ms += output.stats.generate_ms;
+        joined.stats.tokens_generated += output.stats.tokens_generated;
+        joined.stats.thinking_tokens += output.stats.thinking_tokens;
+    }
+    Ok(joined)
+}
+
+/// The STT engine and the settings it runs with, borrowed apart from the
+/// rest of the pipeline so transcription can run beside formatting
+struct SttStage<'a> {
+    stt: &'a mut dyn SpeechToText,
+    config: &'a Config,
+    /// `ProcessOptions::stt_context`
+    context: Option<&'a str>,
+    /// Transcribe the recording whole as a short command (see
+    /// `ProcessProfile::Command`)
+    quick: bool,
+}
+
+impl SttStage<'_> {
+    /// Trim silence from `audio` and transcribe the speech that's left
+    fn transcribe(
+        &mut self,
+        audio: &[f32],
+        language: &str,
+        task: SttTask,
+        cancel: &CancelToken,
+        progress: Option<&ProgressReporter>,
+        start: Instant,
+    ) -> Result<Transcribed> {
+        let timeouts = &self.config.timeouts;
+        let audio_ms = duration_ms(audio) as u64;
+        let cancel = &cancel
+            .with_deadline(TimeoutStage::Total, start, timeouts.total_limit_ms(audio_ms))
+            .with_deadline(TimeoutStage::Stt, Instant::now(), timeouts.stt_limit_ms(audio_ms));
+        let cancelled = |transcription_ms| cancelled_error(cancel, start, transcription_ms, 0, 0);
+        if cancel.is_cancelled() {
+            return Err(cancelled(0));
+        }
+
+        // Trim silence (and split on long pauses) so the STT engine only sees speech
+        progress::report(progress, ProcessStage::DetectingSpeech, 0, 0);
+        let audio_options = &self.config.audio;
+        let t0 = Instant::now();
+        let vad = tracing::info_span!("vad", audio_ms = duration_ms(audio), regions = tracing::field::Empty);
+        let regions = vad.in_scope(|| {
+            if audio_options.vad_enabled && !self.quick {
+                speech_regions(audio, audio_options.vad_threshold, audio_options.min_silence_ms)
+            } else {
+                std::iter::once(0..audio.len()).collect()
+            }
+        });
+        vad.record("regions", regions.len());
+        drop(vad);
+        let vad_ms = t0.elapsed().as_millis() as u64;
+        let kept_samples: usize = regions.iter().map(|r| r.len()).sum();
+        let trimmed_ms = ((audio.len() - kept_samples) * 1000 / 16000) as u64;
+        tracing::debug!("VAD trimmed {}ms of silence, {} speech region(s)", trimmed_ms, regions.len());
+
+        // Step 1: Transcribe audio with STT engine, with an optional second
+        // pass in the spoken language
+        tracing::debug!("Transcribing {} samples", kept_samples);
+        let t1 = Instant::now();
+        let transcribed = self
+            .transcribe_regions(audio, &regions, language, task, cancel, progress)
+            .and_then(|(result, chunk_ms)| {
+                let original = self.original_transcript(audio, &regions, &result, task, cancel, progress)?;
+                Ok((result, chunk_ms, original))
+            });
+        let (result, chunk_transcription_ms, original_transcript) = match transcribed {
+            Ok(transcribed) => transcribed,
+            Err(_) if cancel.is_cancelled() => return Err(cancelled(t1.elapsed().as_millis() as u64)),
+            Err(e) => return Err(e),
+        };
+        let transcription_ms = t1.elapsed().as_millis() as u64;
+
+        // A transcript that made it in time is kept, to fall back to
+        if cancel.is_cancelled() && cancel.timeout().is_none() {
+            return Err(cancelled(transcription_ms));
+        }
+        tracing::debug!("Transcription took {}ms: {}", transcription_ms, result.text);
+
+        Ok(Transcribed {
+            timings: Timings {
+                transcription_ms,
+                trimmed_ms,
+                vad_ms,
+                chunk_transcription_ms,
+                stt_encode_ms: result.encode_ms,
+                stt_decode_ms: result.decode_ms,
+                stt_provider: self.stt.execution_provider(),
+                stt_temperature: result.temperature,
+                stt_temperature_fallback: result.temperature_fallback,
+                ..Default::default()
             },
+            decoder_timestamps: self.stt.supports_timestamps(),
+            result,
+            original_transcript,
+            start,
+            preprocess: PreprocessStats::default(),
+        })
+    }
+
+    /// Transcribe the given regions of `audio` and stitch the results
+    ///
+    /// Regions longer than `max_chunk_ms` are cut into overlapping chunks.
+    /// Chunks that come out as no speech (a cough, a door) are dropped when
+    /// others contain speech. With "auto", the language detected in the
+    /// first chunk with speech is used for the rest. Also returns the time
+    /// spent on each chunk.
+    fn transcribe_regions(
+        &mut self,
+        audio: &[f32],
+        regions: &[Range<usize>],
+        language: &str,
+        task: SttTask,
+        cancel: &CancelToken,
+        progress: Option<&ProgressReporter>,
+    ) -> Result<(TranscriptionResult, Vec<u64>)> {
+        let audio_options = &self.config.audio;
+        let chunks: Vec<Range<usize>> = regions
+            .iter()
+            .flat_map(|region| {
+                plan_chunks(&audio[region.clone()], audio_options.max_chunk_ms, audio_options.chunk_overlap_ms)
+                    .into_iter()
+                    .map(move |chunk| region.start + chunk.start..region.start + chunk.end)
+            })
+            .collect();
+
+        let kept_samples: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+        let _stt = tracing::info_span!(
+            "stt",
+            audio_ms = kept_samples as u64 * 1000 / TARGET_SAMPLE_RATE as u64,
+            chunks = chunks.len(),
+            provider = self.stt.execution_provider(),
+            language,
+        )
+        .entered();
+        let mut language = language.to_string();
+        let mut parts = Vec::with_capacity(chunks.len());
+        let mut chunk_ms = Vec::with_capacity(chunks.len());
+        let total = chunks.len() as u32;
+        progress::report(progress, ProcessStage::Transcribing, 0, total);
+        for chunk in chunks {
+            let t = Instant::now();
+            let opts = SttOptions {
+                enable_timestamps: true,
+                language: &language,
+                task,
+                context: self.context,
+                cancel,
+                quick: self.quick,
+            };
+            let part = self.stt.transcribe(&audio[chunk.clone()], &opts).map_err(|e| stt_error(self.config, e))?;
+            chunk_ms.push(t.elapsed().as_millis() as u64);
+            tracing::debug!(
+                "Chunk {:.1}s-{:.1}s transcribed in {}ms",
+                chunk.start as f32 / 16000.0,
+                chunk.end as f32 / 16000.0,
+                chunk_ms.last().unwrap()
+            );
+            progress::report(progress, ProcessStage::Transcribing, chunk_ms.len() as u32, total);
+            if language == AUTO_LANGUAGE && !is_no_speech(self.config, &part) {
+                if let Some(detected) = &part.language {
+                    language = detected.clone();
+                }
+            }
+            parts.push((chunk, part));
+        }
+
+        if parts.len() > 1 && parts.iter().any(|(_, part)| !is_no_speech(self.config, part)) {
+            parts.retain(|(_, part)| !is_no_speech(self.config, part));
+        }
+        Ok((stitch_transcriptions(parts), chunk_ms))
+    }
+
+    /// Transcribe the regions again in the spoken language, if translating
+    /// with `keep_original_transcript` set
+    fn original_transcript(
+        &mut self,
+        audio: &[f32],
+        regions: &[Range<usize>],
+        translation: &TranscriptionResult,
+        task: SttTask,
+        cancel: &CancelToken,
+        progress: Option<&ProgressReporter>,
+    ) -> Result<Option<String>> {
+        if task != SttTask::Translate
+            || !self.config.keep_original_transcript
+            || is_no_speech(self.config, translation)
+        {
+            return Ok(None);
+        }
+        let language = translation.language.clone().unwrap_or_else(|| self.config.language.clone());
+        let (original, _) =
+            self.transcribe_regions(audio, regions, &language, SttTask::Transcribe, cancel, progress)?;
+        Ok(Some(original.text))
+    }
+}
+
+/// Load the configured STT engine, or Whisper from `whisper_model_path`
+/// instead of the configured Whisper model if given
+fn load_stt(
+    config: &Config,
+    whisper_model_path: Option<&Path>,
+    progress: Option<&dyn InitProgress>,
+) -> Result<Box<dyn SpeechToText>> {
+    match config.stt_engine {
+        SttEngineConfig::Whisper => {
+            tracing::info!("Using Whisper STT engine: {:?} ({})", config.whisper_model, config.whisper_precision.id());
+            // whisper.cpp loads the encoder and decoder in one go
+            if let Some(progress) = progress {
+                progress.report(InitStage::LoadingSttEncoder);
+            }
+            match whisper_model_path {
+                Some(path) => Ok(Box::new(WhisperEngine::from_path(path, config)?)),
+                None => Ok(Box::new(WhisperEngine::new(config)?)),
+            }
+        }
+        SttEngineConfig::Moonshine => {
+            tracing::info!("Using Moonshine STT engine: {:?} ({})", config.moonshine_model, config.moonshine_precision.id());
+            Ok(Box::new(MoonshineEngine::new_with_progress(config, progress)?))
+        }
+    }
+}
+
+/// Load the configured STT engine as `load_stt` does, or with
+/// `Config::stt_fallback_enabled` the first of its `substitutes` that loads
+/// if it fails
+///
+/// A substitute is logged as a warning; `config` is left as it is, so it
+/// never ends up saved.
+fn load_stt_or_substitute(
+    config: &Config,
+    whisper_model_path: Option<&Path>,
+    progress: Option<&dyn InitProgress>,
+) -> Result<(Box<dyn SpeechToText>, ActiveSttEngine)> {
+    let configured = ActiveSttEngine::configured(config);
+    let error = match load_stt(config, whisper_model_path, progress) {
+        Ok(stt) => return Ok((stt, configured)),
+        Err(e) if !config.stt_fallback_enabled => return Err(e),
+        Err(e) => e,
+    };
+    for substitute in substitutes(config) {
+        match load_stt(&substitute.apply_to(config), None, progress) {
+            Ok(stt) => {
+                tracing::warn!(
+                    "{} failed to load ({:#}); using {} instead. The config still selects {}",
+                    configured,
+                    error,
+                    substitute,
+                    configured
+                );
+                return Ok((stt, substitute));
+            }
+            Err(e) => tracing::warn!("Fallback STT engine {} failed to load too: {:#}", substitute, e),
         }
     }
+    Err(error)
+}
 
-    /// Check if this engine supports word-level timestamps
-    fn supports_timestamps(&self) -> bool {
-        matches!(self, Self::Whisper(_))
+/// Load the configured formatter: the local LLM, or a remote server
+fn load_llm(config: &Config) -> Result<Box<dyn TextFormatter>> {
+    match &config.formatter {
+        FormatterBackend::Local => Ok(Box::new(LlmEngine::new(config)?)),
+        #[cfg(feature = "remote-formatter")]
+        FormatterBackend::Remote { .. } => Ok(Box::new(RemoteFormatter::new(config)?)),
+        #[cfg(not(feature = "remote-formatter"))]
+        FormatterBackend::Remote { base_url, .. } => Err(PipelineError::RemoteFormatter {
+            url: base_url.clone(),
+            message: "this build has no remote formatting (the remote-formatter feature)".to_string(),
+        }
+        .into()),
     }
 }
 
+/// Version of the JSON form of `PipelineResult`, raised whenever a field is
+/// renamed, removed or changes type (new fields don't raise it)
+pub const RESULT_SCHEMA_VERSION: u32 = 1;
+
 /// Result from the processing pipeline
-#[derive(Debug, Clone)]
+///
+/// Serializes to JSON with every field present (`null` when unset); see
+/// `RESULT_SCHEMA_VERSION`.
+#[derive(Debug, Clone, Serialize)]
 pub struct PipelineResult {
     /// Raw transcript from Whisper
     pub raw_transcript: String,
@@ -113,15 +930,148 @@ pub struct PipelineResult {
     pub timings: Timings,
     /// Prosody analysis results (if enabled)
     pub prosody_hints: Option<ProsodyHints>,
+    /// Word timings from the STT engine, in milliseconds from the start of
+    /// the audio (before voice command and dictionary replacement)
+    pub word_timestamps: Vec<WordTimestamp>,
+    /// STT decoder confidence (0.0 - 1.0)
+    pub confidence: f32,
+    /// Other readings of the audio from the STT decoder, most likely first,
+    /// before voice commands and formatting (empty unless
+    /// `SttDecodeParams::alternatives` is above 1)
+    pub alternatives: Vec<Alternative>,
+    /// Probability that the audio contains no speech (0.0 - 1.0)
+    pub no_speech_probability: f32,
+    /// The audio was judged to contain no speech; both texts are empty
+    pub no_speech: bool,
+    /// Language the audio was transcribed in (ISO 639-1 code), detected
+    /// when the language is "auto"
+    pub language: Option<String>,
+    /// Transcript in the spoken language when translating with
+    /// `keep_original_transcript` set (both texts are then English)
+    pub original_transcript: Option<String>,
+    /// LLM output before cleanup, when `llm_output.keep_raw_output` is set
+    pub raw_llm_output: Option<String>,
+    /// `formatted_text` is the raw transcript because LLM formatting failed
+    /// or its output was rejected
+    pub was_fallback: bool,
+    /// Why LLM formatting failed, when it did and `Config::llm_strict` is
+    /// off; `formatted_text` is then the raw transcript (None when the
+    /// output was only rejected)
+    pub formatting_error: Option<String>,
+    /// What was cut from the formatting prompts to fit the LLM's context
+    /// window (None when nothing was)
+    pub prompt_truncation: Option<PromptTruncation>,
+    /// The LLM output was reused from an earlier identical request, so
+    /// `llm_formatting_ms` is 0
+    pub format_cache_hit: bool,
+    /// Share of the audio's samples at full scale, in percent (0 when
+    /// `AudioOptions::preprocess` is off)
+    pub clipped_percent: f32,
+    /// So much of the audio is clipped that the input gain should be
+    /// lowered (see `AudioOptions::warn_on_clipping`)
+    pub clipping: bool,
+    /// NaN or infinite samples in the audio, transcribed as silence
+    pub repaired_samples: usize,
+    /// Text taken out of the transcript as made up by the STT engine (see
+    /// `Config::hallucination_filter`)
+    pub filtered_segments: Vec<FilteredSegment>,
+    /// "Scratch that" opened the dictation: the previous one should be
+    /// removed (sessions drop it themselves, see
+    /// `Pipeline::process_in_session`)
+    pub scratch_previous: bool,
+    /// The transcript split at long pauses (and speaker turns, when
+    /// `Config::diarization` is enabled), each formatted on its own;
+    /// `formatted_text` is their formatted text joined
+    pub segments: Vec<Segment>,
+    /// The recording was taken as a short command (see `ProcessProfile`):
+    /// transcribed whole with a quick decode, and not formatted by the LLM
+    pub fast_path: bool,
+}
+
+impl PipelineResult {
+    /// Empty result for audio without speech
+    fn no_speech(transcription: &TranscriptionResult, timings: Timings) -> Self {
+        Self {
+            raw_transcript: String::new(),
+            formatted_text: String::new(),
+            timings,
+            prosody_hints: None,
+            word_timestamps: transcription.word_timestamps.clone(),
+            confidence: transcription.confidence,
+            alternatives: Vec::new(),
+            no_speech_probability: transcription.no_speech_probability,
+            no_speech: true,
+            language: transcription.language.clone(),
+            original_transcript: None,
+            raw_llm_output: None,
+            was_fallback: false,
+            formatting_error: None,
+            prompt_truncation: None,
+            format_cache_hit: false,
+            clipped_percent: 0.0,
+            clipping: false,
+            repaired_samples: 0,
+            filtered_segments: Vec::new(),
+            scratch_previous: false,
+            segments: Vec::new(),
+            fast_path: false,
+        }
+    }
 }
 
 /// Processing time breakdown
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Timings {
     pub transcription_ms: u64,
     pub prosody_ms: u64,
     pub llm_formatting_ms: u64,
     pub total_ms: u64,
+    /// Length of the silence trimmed by VAD before transcription
+    pub trimmed_ms: u64,
+    /// Transcription time of each chunk handed to the STT engine, in order
+    pub chunk_transcription_ms: Vec<u64>,
+    /// Downmixing and resampling the input to 16kHz mono
+    pub audio_prep_ms: u64,
+    /// Finding the speech regions to transcribe
+    pub vad_ms: u64,
+    /// Reloading the STT engine after an idle unload, included in
+    /// `transcription_ms`
+    pub stt_load_ms: u64,
+    /// STT encoder time (see `TranscriptionResult::encode_ms`)
+    pub stt_encode_ms: u64,
+    /// STT decoder time (see `TranscriptionResult::decode_ms`)
+    pub stt_decode_ms: u64,
+    /// Loading the LLM on its first use, included in `llm_formatting_ms`
+    pub llm_load_ms: u64,
+    /// LLM prompt processing, until the first token
+    pub llm_prefill_ms: u64,
+    /// LLM generation of the remaining tokens
+    pub llm_generate_ms: u64,
+    pub llm_tokens_generated: u32,
+    /// Tokens spent in `<think>` blocks, included in `llm_tokens_generated`
+    pub llm_thinking_tokens: u32,
+    /// LLM generation speed after the first token
+    pub tokens_per_second: f32,
+    /// Hardware the STT engine ran on (see `SpeechToText::execution_provider`)
+    pub stt_provider: Option<&'static str>,
+    /// Temperature of the STT decode that was kept
+    /// (see `TranscriptionResult::temperature`)
+    pub stt_temperature: f32,
+    /// The STT decode fell back to a higher temperature
+    pub stt_temperature_fallback: bool,
+}
+
+impl Timings {
+    fn with_llm_stats(self, stats: &LlmStats) -> Self {
+        Self {
+            llm_prefill_ms: stats.prefill_ms,
+            llm_generate_ms: stats.generate_ms,
+            llm_tokens_generated: stats.tokens_generated,
+            llm_thinking_tokens: stats.thinking_tokens,
+            tokens_per_second: stats.tokens_per_second(),
+            ..self
+        }
+    }
 }
 
 /// Prosody analysis options
@@ -159,45 +1109,320 @@ impl ProsodyOptions {
     }
 }
 
+/// How much LLM formatting to apply to a transcript
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FormattingMode {
+    /// Skip the LLM: the formatted text is the raw transcript
+    None,
+    /// Full context-aware formatting
+    #[default]
+    Full,
+    /// Only add punctuation and capitalization, never change words
+    PunctuationOnly,
+}
+
+impl FormattingMode {
+    /// This mode, or `most` if that formats less
+    pub fn at_most(self, most: Self) -> Self {
+        match (self, most) {
+            (Self::None, _) | (_, Self::None) => Self::None,
+            (Self::PunctuationOnly, _) | (_, Self::PunctuationOnly) => Self::PunctuationOnly,
+            (Self::Full, Self::Full) => Self::Full,
+        }
+    }
+}
+
+/// How a request is processed: as dictation, or as a short voice command
+/// ("undo", "next tab") where the fixed cost of the full pipeline would
+/// dominate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProcessProfile {
+    /// A command if shorter than `Config::command_max_ms`, dictation
+    /// otherwise
+    #[default]
+    Auto,
+    /// The full pipeline, however short the recording
+    Dictation,
+    /// The fast path: no silence trimming or chunking, a greedy decode of
+    /// a few tokens (see `SttOptions::quick`) and no LLM, only the
+    /// deterministic rules (voice commands, numbers, replacements).
+    /// Recordings longer than `AudioOptions::max_chunk_ms` are still
+    /// processed as dictation
+    Command,
+}
+
+/// Per-call options for `Pipeline::process_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptions {
+    /// LLM formatting to apply
+    pub formatting: FormattingMode,
+    /// Preset for this call, overriding `Config::default_preset`
+    pub preset: Option<FormattingPreset>,
+    /// Spoken language for this call ("auto" to detect it), overriding
+    /// `Config::language`
+    pub language: Option<String>,
+    /// STT task for this call, overriding `Config::stt_task`
+    pub task: Option<SttTask>,
+    /// Names, jargon or the topic of the recording, to bias recognition
+    /// towards on top of the configured vocabulary (see
+    /// `SttOptions::context`); the context hint passed with the audio only
+    /// steers formatting
+    pub stt_context: Option<String>,
+    /// LLM decoding parameters for this call, overriding the configured and
+    /// preset ones (out-of-range values are clamped)
+    pub llm_options: Option<LlmOptions>,
+    /// Earlier dictations of a session, given to the LLM as context (see
+    /// `Pipeline::process_in_session`)
+    pub session: Option<SessionState>,
+    /// Apply spoken punctuation and editing commands, overriding
+    /// `VoiceCommands::enabled`
+    pub voice_commands: Option<bool>,
+    /// Bundle id of the app the text goes into, selecting its
+    /// `Config::app_overrides` entry
+    pub app_id: Option<String>,
+    /// Dictation or a short command, by default told apart by length
+    pub profile: ProcessProfile,
+    /// Token to stop the run early
+    pub cancel: CancelToken,
+    /// Receives the progress of the run: the stages it reaches, chunks
+    /// transcribed and tokens generated
+    pub progress: Option<ProgressReporter>,
+    /// Receives the raw transcript as soon as it's ready, with voice
+    /// commands, numbers and replacements applied, before the LLM formats
+    /// it; not called when there is no speech
+    pub on_raw: Option<RawTranscriptHook>,
+}
+
 /// The main VoiceFlow pipeline
 pub struct Pipeline {
-    stt: SttEngine,
-    llm: Option<LlmEngine>,
+    /// Reloaded on next use after an idle unload
+    stt: Option<Box<dyn SpeechToText>>,
+    llm: Option<Box<dyn TextFormatter>>,
     config: Config,
+    /// Most formatting applied, whatever a call asks for
+    formatting: FormattingMode,
+    /// Whisper model file used instead of the configured model
+    whisper_model_path: Option<PathBuf>,
     prosody_options: ProsodyOptions,
     replacements: ReplacementDictionary,
+    /// User find-and-replace rules, applied last
+    rules: ReplacementRules,
+    /// The rules for each app that adds some, the configured ones included
+    app_rules: BTreeMap<String, ReplacementRules>,
     recovery_config: RecoveryConfig,
     /// Tracks if LLM initialization has permanently failed
     llm_permanently_failed: bool,
+    /// The LLM was passed in, so it can't be reloaded once dropped
+    llm_supplied: bool,
+    /// The STT engine was passed in, so it's never unloaded
+    stt_supplied: bool,
+    /// Engine and model of the loaded STT engine, unless supplied
+    active_stt: Option<ActiveSttEngine>,
+    /// When the last request finished, for `unload_if_idle`
+    last_used: Instant,
+    scratch: ScratchBuffers,
+    /// Recent LLM outputs, reused when the same transcript is formatted
+    /// the same way again
+    format_cache: FormatCache,
+    /// Loaded on first use when diarization is enabled, unless supplied
+    speaker_embedder: Option<Box<dyn SpeakerEmbedder>>,
+    /// The speaker embedder was passed in, so it's kept when the speaker
+    /// model setting changes
+    speaker_embedder_supplied: bool,
+}
+
+/// Buffers reused across requests, so preparing a warm pipeline's input
+/// doesn't allocate in proportion to the audio length
+///
+/// The STT engines keep their own (Whisper's mel and KV state inside
+/// whisper.cpp, Moonshine's token and cache buffers).
+#[derive(Debug, Default)]
+struct ScratchBuffers {
+    /// Input converted to 16kHz mono by `process_input`
+    audio: Vec<f32>,
+    /// Resampler for `process_input`, kept while the input rate stays the same
+    resample: ResampleState,
+    /// Copy of a request's audio for preprocessing to change
+    preprocessed: Vec<f32>,
+}
+
+impl ScratchBuffers {
+    /// Buffers sized for chunks of up to `max_chunk_ms`; longer input grows
+    /// them once
+    fn new(max_chunk_ms: u32) -> Self {
+        Self {
+            audio: Vec::with_capacity(max_chunk_ms as usize * 16),
+            resample: ResampleState::default(),
+            preprocessed: Vec::with_capacity(max_chunk_ms as usize * 16),
+        }
+    }
 }
 
 impl Pipeline {
     /// Create a new pipeline with the given configuration
     /// LLM is lazily initialized on first use
-    pub fn new(config: &Config) -> Result<Self> {
+    pub fn new(config: &Config) -> Result<Self, PipelineError> {
         Self::new_with_recovery(config, RecoveryConfig::default())
     }
 
     /// Create a new pipeline with custom recovery configuration
-    pub fn new_with_recovery(config: &Config, recovery_config: RecoveryConfig) -> Result<Self> {
+    pub fn new_with_recovery(config: &Config, recovery_config: RecoveryConfig) -> Result<Self, PipelineError> {
+        Self::new_with_progress(config, recovery_config, None)
+    }
+
+    /// Create a new pipeline, reporting each loading stage to `progress`
+    ///
+    /// With a progress sink, or with `Config::warm_up_on_init` set, the LLM
+    /// is loaded and the pipeline warmed up before returning, so the whole
+    /// wait is reported; otherwise the LLM loads on first use, as with `new`.
+    /// An LLM that fails to load is not fatal if the recovery config allows
+    /// transcription-only mode.
+    pub fn new_with_progress(
+        config: &Config,
+        recovery_config: RecoveryConfig,
+        progress: Option<&dyn InitProgress>,
+    ) -> Result<Self, PipelineError> {
+        let pipeline = PipelineBuilder::from(config.clone())
+            .recovery(recovery_config)
+            .build_with_progress(progress)?;
+        Ok(pipeline)
+    }
+
+    /// Create a pipeline with the default configuration around the given
+    /// engines instead of the built-in ones
+    ///
+    /// Use `PipelineBuilder` to also change the configuration.
+    pub fn with_engines(stt: Box<dyn SpeechToText>, llm: Box<dyn TextFormatter>) -> Result<Self, PipelineError> {
+        Ok(PipelineBuilder::new().stt(stt).llm_engine(llm).build()?)
+    }
+
+    /// Create the pipeline a checked builder describes; see
+    /// `new_with_progress`
+    pub(crate) fn from_builder(builder: PipelineBuilder, progress: Option<&dyn InitProgress>) -> Result<Self> {
+        let PipelineBuilder { config, recovery, formatting, whisper_model_path, stt, llm, speaker_embedder } = builder;
         tracing::info!("Initializing VoiceFlow pipeline");
         tracing::info!("  STT engine: {}", config.stt_engine.display_name());
-        tracing::info!("  LLM model: {}", config.llm_model.display_name());
+        tracing::info!("  LLM model: {}", config.llm_display_name());
 
-        let stt = SttEngine::new(config)
-            .context("Failed to initialize speech-to-text engine")?;
+        let stt_supplied = stt.is_some();
+        let (stt, active_stt) = match stt {
+            Some(engine) => (engine, None),
+            None => {
+                let (engine, active) = load_stt_or_substitute(&config, whisper_model_path.as_deref(), progress)
+                    .context("Failed to initialize speech-to-text engine")?;
+                (engine, Some(active))
+            }
+        };
         let replacements = ReplacementDictionary::load_default();
         tracing::info!("  Loaded {} text replacements", replacements.len());
+        let rules = ReplacementRules::compile(&config.replacements)?;
+        let app_rules = compile_app_rules(&config)?;
 
-        Ok(Self {
-            stt,
-            llm: None, // Lazy initialization
-            config: config.clone(),
+        let warm_up = progress.is_some() || config.warm_up_on_init;
+        let llm_supplied = llm.is_some();
+        let scratch = ScratchBuffers::new(config.audio.max_chunk_ms);
+        let format_cache = FormatCache::new(format_cache_size(&config));
+        let speaker_embedder_supplied = speaker_embedder.is_some();
+        let mut pipeline = Self {
+            stt: Some(stt),
+            llm, // Loaded on first use unless supplied
+            config,
+            formatting,
+            whisper_model_path,
             prosody_options: ProsodyOptions::all(), // Enable all by default
             replacements,
-            recovery_config,
+            rules,
+            app_rules,
+            recovery_config: recovery,
             llm_permanently_failed: false,
-        })
+            llm_supplied,
+            stt_supplied,
+            active_stt,
+            last_used: Instant::now(),
+            scratch,
+            format_cache,
+            speaker_embedder,
+            speaker_embedder_supplied,
+        };
+        if warm_up {
+            pipeline.preload(progress)?;
+        } else if pipeline.config.llm_preload {
+            pipeline.load_llm_or_fallback()?;
+        }
+        Ok(pipeline)
+    }
+
+    /// Load the LLM and warm up the pipeline
+    fn preload(&mut self, progress: Option<&dyn InitProgress>) -> Result<()> {
+        let report = |stage| {
+            if let Some(progress) = progress {
+                progress.report(stage);
+            }
+        };
+
+        report(InitStage::LoadingLlm);
+        self.load_llm_or_fallback()?;
+        report(InitStage::WarmingUp);
+        self.warm_up()?;
+        report(InitStage::Ready);
+        Ok(())
+    }
+
+    /// Whether any call gets LLM formatting (see `PipelineBuilder::formatting`)
+    pub(crate) fn formats(&self) -> bool {
+        self.formatting != FormattingMode::None
+    }
+
+    /// Load the LLM, tolerating a failure if transcription-only mode is allowed
+    fn load_llm_or_fallback(&mut self) -> Result<()> {
+        if self.formatting == FormattingMode::None {
+            return Ok(());
+        }
+        if let Err(e) = self.get_llm() {
+            if !self.can_fallback() {
+                return Err(e);
+            }
+            tracing::warn!("LLM failed to load, continuing in transcription-only mode: {:#}", e);
+        }
+        Ok(())
+    }
+
+    /// Run a short synthetic inference through the STT engine and the LLM,
+    /// so the first real request doesn't pay for lazy initialization, and
+    /// return how long it took
+    ///
+    /// Loads the LLM if needed, and has it prefill the start of the default
+    /// prompt (`Config::llm_prefix_cache`). Requests are independent, so
+    /// nothing else from the warm-up carries over into them.
+    pub fn warm_up(&mut self) -> Result<Duration> {
+        let start = Instant::now();
+
+        let silence = vec![0.0; WARMUP_SAMPLES];
+        let cancel = CancelToken::new();
+        let language = self.config.language.clone();
+        let opts = SttOptions {
+            enable_timestamps: false,
+            language: &language,
+            task: self.config.stt_task,
+            context: None,
+            cancel: &cancel,
+            quick: false,
+        };
+        self.stt()?.transcribe(&silence, &opts).context("STT warm-up failed")?;
+
+        self.load_llm_or_fallback()?;
+        let llm_options = LlmOptions { max_tokens: WARMUP_MAX_TOKENS, ..self.config.llm_options.clone() };
+        if let Some(llm) = self.llm.as_mut() {
+            let ctx = FormatContext { prompt_template: PUNCTUATION_ONLY_PROMPT, llm_options: &llm_options, cancel: &cancel };
+            llm.format_with_stats(WARMUP_TRANSCRIPT, &ctx, None).context("LLM warm-up failed")?;
+        }
+        self.prime_llm(&cancel).context("LLM warm-up failed")?;
+
+        let elapsed = start.elapsed();
+        tracing::info!("Pipeline warmed up in {}ms", elapsed.as_millis());
+        Ok(elapsed)
     }
 
     /// Set prosody analysis options
@@ -205,13 +1430,93 @@ impl Pipeline {
         self.prosody_options = options;
     }
 
+    /// Get the configuration the pipeline is running with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Apply the runtime-changeable fields of `config` (sampling, prompts,
+    /// audio and VAD, replacements) without reloading any model
+    ///
+    /// Returns the names of the fields that differ but only take effect
+    /// after a model reload (see `config::RELOAD_FIELDS`); those keep their
+    /// current value. Nothing changes if `config` doesn't validate.
+    pub fn update_config(&mut self, config: &Config) -> Result<Vec<&'static str>> {
+        config.validate()?;
+        let (config, needs_reload) = config.runtime_changes(&self.config)?;
+        let rules = ReplacementRules::compile(&config.replacements)?;
+        let app_rules = compile_app_rules(&config)?;
+        if let Some(llm) = self.llm.as_mut() {
+            llm.update_config(&config)?;
+        }
+        self.rules = rules;
+        self.app_rules = app_rules;
+        self.format_cache.set_capacity(format_cache_size(&config));
+        if config.diarization.model != self.config.diarization.model && !self.speaker_embedder_supplied {
+            self.speaker_embedder = None;
+        }
+        self.config = config;
+        // A changed prompt, preset, vocabulary or sampling parameters start
+        // the default prompt differently
+        if let Err(e) = self.prime_llm(&CancelToken::new()) {
+            tracing::warn!("LLM failed to prefill the prompt's start: {:#}", e);
+        }
+        if !needs_reload.is_empty() {
+            tracing::info!("Config updated; needs a model reload: {}", needs_reload.join(", "));
+        }
+        Ok(needs_reload)
+    }
+
+    /// Switch to `config`, reloading the STT engine and the LLM only if
+    /// settings they load with changed
+    ///
+    /// Runtime-changeable fields are applied as by `update_config`. Returns
+    /// the components reloaded, "stt" and/or "llm". If a reload fails, the
+    /// changes applied so far are kept and that component keeps its
+    /// previous model.
+    pub fn apply_config(&mut self, config: &Config) -> Result<Vec<&'static str>> {
+        let needs_reload = self.update_config(config)?;
+        let reloads_llm = |field: &&str| {
+            LLM_RELOAD_FIELDS.contains(field) || *field == "models_dir_override" || *field == "deterministic"
+        };
+        let mut reloaded = Vec::new();
+
+        if needs_reload.iter().any(reloads_llm) {
+            let mut next = self.config.clone();
+            next.llm_model = config.llm_model.clone();
+            next.custom_model_name = config.custom_model_name.clone();
+            next.chat_template = config.chat_template;
+            next.formatter = config.formatter.clone();
+            next.llm_options.seed = config.llm_options.seed;
+            next.llm_options.n_gpu_layers = config.llm_options.n_gpu_layers;
+            next.llm_threads = config.llm_threads;
+            next.llm_prefix_cache = config.llm_prefix_cache;
+            next.models_dir_override = config.models_dir_override.clone();
+            next.deterministic = config.deterministic;
+            self.swap_llm(next)?;
+            reloaded.push("llm");
+        }
+        if needs_reload.iter().any(|field| !LLM_RELOAD_FIELDS.contains(field)) {
+            tracing::info!("Reloading STT engine: {}", config.stt_engine.display_name());
+            self.stt = Some(
+                load_stt(config, self.whisper_model_path.as_deref(), None)
+                    .context("Failed to initialize speech-to-text engine")?,
+            );
+            self.stt_supplied = false;
+            self.active_stt = Some(ActiveSttEngine::configured(config));
+            self.config = config.clone();
+            reloaded.push("stt");
+        }
+        Ok(reloaded)
+    }
+
     /// Set recovery configuration
     pub fn set_recovery_config(&mut self, config: RecoveryConfig) {
         self.recovery_config = config;
     }
 
     /// Get or initialize the LLM engine with retry logic
-    fn get_llm(&mut self) -> Result<&mut LlmEngine> {
+    fn get_llm(&mut self) -> Result<&mut dyn TextFormatter> {
         // If LLM has permanently failed, return error immediately
         if self.llm_permanently_failed {
             anyhow::bail!(PipelineError::LlmInitFailed {
@@ -222,16 +1527,26 @@ impl Pipeline {
 
         if self.llm.is_none() {
             let mut last_error = None;
+            let load_start = Instant::now();
+            let _span = tracing::info_span!("llm_load", model = self.config.llm_model.filename()).entered();
 
             for attempt in 1..=self.recovery_config.llm_max_retries {
                 tracing::info!("Initializing LLM engine (attempt {}/{})", attempt, self.recovery_config.llm_max_retries);
 
-                match LlmEngine::new(&self.config) {
+                match load_llm(&self.config) {
                     Ok(engine) => {
                         self.llm = Some(engine);
-                        tracing::info!("LLM engine initialized successfully");
+                        tracing::info!("LLM engine loaded in {}ms", load_start.elapsed().as_millis());
                         break;
                     }
+                    // Retrying won't repair the file or set the API key
+                    Err(e) if matches!(
+                        e.downcast_ref::<PipelineError>(),
+                        Some(PipelineError::ModelCorrupted { .. } | PipelineError::RemoteFormatter { .. })
+                    ) => {
+                        self.llm_permanently_failed = true;
+                        return Err(e);
+                    }
                     Err(e) => {
                         tracing::warn!("LLM initialization attempt {} failed: {}", attempt, e);
                         last_error = Some(e);
@@ -258,75 +1573,668 @@ impl Pipeline {
             }
         }
 
-        Ok(self.llm.as_mut().unwrap())
+        Ok(self.llm.as_deref_mut().unwrap())
     }
 
     /// Reset the LLM state, allowing re-initialization attempts
     pub fn reset_llm(&mut self) {
         self.llm = None;
         self.llm_permanently_failed = false;
+        self.llm_supplied = false;
+        self.format_cache.clear();
         tracing::info!("LLM state reset, will attempt re-initialization on next use");
     }
 
-    /// Check if the LLM is ready for use
-    pub fn is_llm_ready(&self) -> bool {
-        self.llm.is_some() && !self.llm_permanently_failed
-    }
+    /// Swap the LLM for `model`, keeping the STT engine loaded
+    ///
+    /// The old model is dropped before the new one loads, so both are never
+    /// in memory at once. If loading fails, the previous model stays
+    /// selected and is reloaded on next use. Switching to a built-in model
+    /// clears the custom model name and chat template.
+    pub fn reload_llm(&mut self, model: &LlmModel) -> Result<()> {
+        let mut config = self.config.clone();
+        config.llm_model = model.clone();
+        if !matches!(model, LlmModel::Custom(_)) {
+            config.custom_model_name = None;
+            config.chat_template = ChatTemplate::default();
+        }
+        self.swap_llm(config)
+    }
+
+    /// Swap the LLM for the one `config` selects, with its custom model name
+    /// and chat template; see `reload_llm`
+    pub fn reload_llm_from(&mut self, config: &Config) -> Result<()> {
+        let mut new_config = self.config.clone();
+        new_config.llm_model = config.llm_model.clone();
+        new_config.custom_model_name = config.custom_model_name.clone();
+        new_config.chat_template = config.chat_template;
+        new_config.formatter = config.formatter.clone();
+        self.swap_llm(new_config)
+    }
+
+    fn swap_llm(&mut self, config: Config) -> Result<()> {
+        tracing::info!("Reloading LLM: {}", config.llm_display_name());
+        self.llm = None;
+        self.llm_permanently_failed = false;
+        self.llm_supplied = false;
+        self.llm = Some(load_llm(&config)?);
+        self.config = config;
+        self.format_cache.clear();
+        Ok(())
+    }
+
+    /// Forget the formatting results kept for reuse (see
+    /// `Config::format_cache_size`)
+    pub fn clear_format_cache(&mut self) {
+        self.format_cache.clear();
+    }
+
+    /// Free memory under pressure without tearing down the pipeline
+    ///
+    /// Drops the built-in LLM, its weights and KV cache; it reloads on the
+    /// next call that formats. Returns whether anything was freed (not if
+    /// the LLM isn't loaded or was supplied by the caller).
+    pub fn trim_memory(&mut self) -> bool {
+        self.unload_llm()
+    }
+
+    /// Drop the built-in LLM to release its memory, for when formatting is
+    /// turned off; it reloads on the next call that formats
+    ///
+    /// Returns false if it wasn't loaded or was supplied by the caller.
+    pub fn unload_llm(&mut self) -> bool {
+        if self.llm_supplied || self.llm.is_none() {
+            return false;
+        }
+        self.llm = None;
+        tracing::info!("LLM unloaded, will reload on next use");
+        true
+    }
+
+    /// Swap the STT engine (Whisper or Moonshine), keeping the LLM loaded
+    ///
+    /// The new engine loads before the old one is dropped, so on failure
+    /// (such as Moonshine with a non-English language) the pipeline keeps
+    /// working with the previous engine.
+    pub fn reload_stt(&mut self, engine: SttEngineConfig) -> Result<()> {
+        let mut config = self.config.clone();
+        config.stt_engine = engine;
+        config.validate_language()?;
+
+        tracing::info!("Reloading STT engine: {}", config.stt_engine.display_name());
+        self.stt = Some(
+            load_stt(&config, self.whisper_model_path.as_deref(), None)
+                .context("Failed to initialize speech-to-text engine")?,
+        );
+        self.stt_supplied = false;
+        self.active_stt = Some(ActiveSttEngine::configured(&config));
+        self.config = config;
+        Ok(())
+    }
+
+    /// Hardware the STT engine runs on ("cpu", "coreml", "metal" or
+    /// "cuda"), or `None` for an engine that doesn't say or while it's
+    /// unloaded
+    pub fn stt_provider(&self) -> Option<&'static str> {
+        self.stt.as_ref().and_then(|stt| stt.execution_provider())
+    }
+
+    /// The STT engine and model in use, or `None` for a supplied engine
+    ///
+    /// With `Config::stt_fallback_enabled` this is a substitute, marked
+    /// `substituted`, while the configured engine fails to load.
+    pub fn active_stt_engine(&self) -> Option<&ActiveSttEngine> {
+        self.active_stt.as_ref()
+    }
+
+    /// Load the STT engine if it was unloaded while idle, returning the time
+    /// that took in milliseconds (0 if it was loaded)
+    fn ensure_stt(&mut self) -> Result<u64> {
+        if self.stt.is_some() {
+            return Ok(0);
+        }
+        let start = Instant::now();
+        let _span = tracing::info_span!("stt_load", engine = ?self.config.stt_engine).entered();
+        // The configured engine is tried again, in case it was fixed
+        let (stt, active) = load_stt_or_substitute(&self.config, self.whisper_model_path.as_deref(), None)
+            .context("Failed to initialize speech-to-text engine")?;
+        self.stt = Some(stt);
+        self.active_stt = Some(active);
+        let ms = start.elapsed().as_millis() as u64;
+        tracing::info!("STT engine reloaded in {}ms", ms);
+        Ok(ms)
+    }
+
+    /// The STT engine, loaded if needed
+    fn stt(&mut self) -> Result<&mut dyn SpeechToText> {
+        self.ensure_stt()?;
+        Ok(self.stt.as_deref_mut().unwrap())
+    }
+
+    /// How long until `unload_if_idle` would unload something, or `None` if
+    /// `Config::idle_unload_seconds` is unset, `Config::deterministic` is
+    /// set or nothing can be unloaded
+    pub fn idle_unload_in(&self) -> Option<Duration> {
+        if self.config.deterministic {
+            return None;
+        }
+        let timeout = Duration::from_secs(self.config.idle_unload_seconds?);
+        let unloadable = (self.llm.is_some() && !self.llm_supplied)
+            || (self.config.idle_unload_stt && self.stt.is_some() && !self.stt_supplied);
+        unloadable.then(|| timeout.saturating_sub(self.last_used.elapsed()))
+    }
+
+    /// Unload the LLM, and the STT engine if `Config::idle_unload_stt` is
+    /// set, once no request has finished for `Config::idle_unload_seconds`
+    ///
+    /// They reload on the next request, which reports the time in
+    /// `Timings::stt_load_ms` and `llm_load_ms`. Engines supplied by the
+    /// caller are kept. Returns whether anything was unloaded.
+    pub fn unload_if_idle(&mut self) -> bool {
+        if self.idle_unload_in() != Some(Duration::ZERO) {
+            return false;
+        }
+        let idle_secs = self.last_used.elapsed().as_secs();
+        let mut unloaded = self.unload_llm();
+        if self.config.idle_unload_stt && !self.stt_supplied && self.stt.take().is_some() {
+            unloaded = true;
+        }
+        if unloaded {
+            tracing::info!("Models unloaded after {}s idle", idle_secs);
+        }
+        unloaded
+    }
+
+    /// Check if the LLM is ready for use
+    pub fn is_llm_ready(&self) -> bool {
+        self.llm.is_some() && !self.llm_permanently_failed
+    }
 
     /// Check if the pipeline can fall back to transcription-only mode
+    /// (allowed by the recovery config and `Config::llm_strict` off)
     pub fn can_fallback(&self) -> bool {
-        self.recovery_config.fallback_to_transcribe_only
+        self.recovery_config.fallback_to_transcribe_only && !self.config.llm_strict
     }
 
     /// Process audio samples and return formatted text
     ///
     /// # Arguments
-    /// * `audio` - PCM audio samples (f32, any sample rate - will be resampled)
-    /// * `context` - Optional context hint (email, slack, code, etc.)
-    pub fn process(&mut self, audio: &[f32], context: Option<&str>) -> Result<PipelineResult> {
-        eprintln!("Pipeline: process() called with {} samples", audio.len());
+    /// * `audio` - PCM f32 samples at 16kHz mono (use `process_input` for other formats)
+    /// * `context` - Optional context: a name (email, slack, code, etc.) or a
+    ///   `DictationContext` as JSON, e.g. with the text before the cursor
+    pub fn process(&mut self, audio: &[f32], context: Option<&str>) -> Result<PipelineResult, PipelineError> {
+        self.process_with_cancel(audio, context, &CancelToken::new())
+    }
+
+    /// Process audio at any sample rate or channel count
+    ///
+    /// The input is downmixed to mono and resampled to 16kHz before
+    /// transcription, into buffers the pipeline keeps for the next call.
+    pub fn process_input(
+        &mut self,
+        input: &AudioInput,
+        context: Option<&str>,
+        cancel: &CancelToken,
+    ) -> Result<PipelineResult, PipelineError> {
+        let options = ProcessOptions {
+            cancel: cancel.clone(),
+            ..Default::default()
+        };
+        self.process_input_with_options(input, context, &options)
+    }
+
+    /// Same as `process_input`, with per-call options
+    pub fn process_input_with_options(
+        &mut self,
+        input: &AudioInput,
+        context: Option<&str>,
+        options: &ProcessOptions,
+    ) -> Result<PipelineResult, PipelineError> {
+        let t = Instant::now();
+        progress::report(options.progress.as_ref(), ProcessStage::Resampling, 0, 0);
+        let mut audio = std::mem::take(&mut self.scratch.audio);
+        let prepared = tracing::info_span!(
+            "audio_prep",
+            sample_rate = input.sample_rate(),
+            channels = input.channels(),
+            audio_ms = (input.duration_secs() * 1000.0) as u64
+        )
+        .in_scope(|| input.to_16khz_mono_into(&mut self.scratch.resample, &mut audio));
+        let audio_prep_ms = t.elapsed().as_millis() as u64;
+
+        let result = prepared.map_err(PipelineError::from).and_then(|()| self.process_with_options(&audio, context, options));
+        self.scratch.audio = audio;
+        let mut result = result?;
+        result.timings.audio_prep_ms = audio_prep_ms;
+        result.timings.total_ms += audio_prep_ms;
+        Ok(result)
+    }
+
+    /// Load a WAV, AIFF or CAF file and process it, or with the
+    /// `compressed-audio` feature an M4A/AAC, MP3, FLAC or Ogg Vorbis file
+    pub fn process_file(&mut self, path: &Path, context: Option<&str>) -> Result<PipelineResult, PipelineError> {
+        self.process_file_with_cancel(path, context, &CancelToken::new())
+    }
+
+    /// Same as `process_file`, stopping early if `cancel` is triggered (see
+    /// `process_with_cancel`)
+    pub fn process_file_with_cancel(
+        &mut self,
+        path: &Path,
+        context: Option<&str>,
+        cancel: &CancelToken,
+    ) -> Result<PipelineResult, PipelineError> {
+        let buffer = load_audio_file(path)?;
+        tracing::debug!(
+            "Loaded {:?}: {} Hz, {} channel(s), {:.1}s",
+            path,
+            buffer.sample_rate,
+            buffer.channels,
+            buffer.as_input().duration_secs()
+        );
+        self.process_input(&buffer.as_input(), context, cancel)
+    }
+
+    /// Process audio samples, stopping early if `cancel` is triggered
+    ///
+    /// The token is checked between STT decode steps, between pipeline
+    /// stages, and between LLM tokens. A cancelled run returns
+    /// `PipelineError::Cancelled` with the timings accumulated so far.
+    pub fn process_with_cancel(
+        &mut self,
+        audio: &[f32],
+        context: Option<&str>,
+        cancel: &CancelToken,
+    ) -> Result<PipelineResult, PipelineError> {
+        let options = ProcessOptions {
+            cancel: cancel.clone(),
+            ..Default::default()
+        };
+        self.process_with_options(audio, context, &options)
+    }
+
+    /// Process audio samples as the next dictation of `session`
+    ///
+    /// The session's earlier outputs are included in the prompt, up to
+    /// `Config::session_context_tokens` and what the model's context window
+    /// leaves room for, and the formatted text is added to the session. A
+    /// dictation opening with "scratch that" removes the one before it.
+    pub fn process_in_session(
+        &mut self,
+        session: &mut SessionState,
+        audio: &[f32],
+        context: Option<&str>,
+        options: &ProcessOptions,
+    ) -> Result<PipelineResult, PipelineError> {
+        let options = ProcessOptions { session: Some(session.clone()), ..options.clone() };
+        let result = self.process_with_options(audio, context, &options)?;
+        continue_session(session, &result);
+        Ok(result)
+    }
+
+    /// Process audio samples with per-call options
+    ///
+    /// With `FormattingMode::None` the LLM is never loaded or run, so
+    /// `formatted_text` equals `raw_transcript` and `llm_formatting_ms` is 0.
+    pub fn process_with_options(
+        &mut self,
+        audio: &[f32],
+        context: Option<&str>,
+        options: &ProcessOptions,
+    ) -> Result<PipelineResult, PipelineError> {
+        self.process_streaming(audio, context, options, None)
+    }
+
+    /// Same as `process_with_options`, passing the LLM output to `sink` as
+    /// it is generated
+    ///
+    /// The fragments are the model's output before cleanup, so the final
+    /// `formatted_text` can differ from their concatenation, e.g. when the
+    /// output is rejected in favour of the transcript. Nothing is streamed
+    /// when formatting is disabled.
+    pub fn process_streaming(
+        &mut self,
+        audio: &[f32],
+        context: Option<&str>,
+        options: &ProcessOptions,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<PipelineResult, PipelineError> {
+        let result = self.run(audio, context, options, sink).map_err(PipelineError::from);
+        self.last_used = Instant::now();
+        if let Ok(result) = &result {
+            self.record_history(audio, context, result);
+            progress::report(options.progress.as_ref(), ProcessStage::Done, 0, 0);
+        }
+        result
+    }
+
+    /// `process_with_options` without keeping the recording in the history
+    pub(crate) fn process_unrecorded(&mut self, audio: &[f32], options: &ProcessOptions) -> Result<PipelineResult> {
+        let result = self.run(audio, None, options, None);
+        self.last_used = Instant::now();
+        result
+    }
+
+    /// Keep the recording and its result when `Config::history` is enabled;
+    /// a failure to is only logged, the request still succeeds
+    fn record_history(&self, audio: &[f32], context: Option<&str>, result: &PipelineResult) {
+        if !self.config.history.enabled {
+            return;
+        }
+        let record = HistoryRecord::new(audio, context, result, self.config.llm_display_name());
+        let recorded =
+            History::for_config(&self.config).and_then(|history| history.record(audio, &record, &self.config.history));
+        match recorded {
+            Ok(id) => tracing::debug!("Kept the recording as history entry {}", id),
+            Err(e) => tracing::warn!("Failed to keep the recording in the history: {:#}", e),
+        }
+    }
+
+    /// Report that the user corrected `original`, text the pipeline
+    /// produced, to `corrected`, for `learn_corrections` to learn from
+    ///
+    /// Returns the correction as counted, `None` when the change isn't one
+    /// that is learned (see `learning`). An error when `Config::learning`
+    /// is off.
+    pub fn report_correction(&self, original: &str, corrected: &str) -> Result<Option<Correction>> {
+        if !self.config.learning.enabled {
+            return Err(ConfigError::LearningDisabled.into());
+        }
+        Corrections::for_config(&self.config)?.record(original, corrected)
+    }
+
+    /// Promote the corrections reported often and consistently enough into
+    /// the vocabulary and the replacement rules, returning them
+    ///
+    /// They're saved to the config file at `config_path` (the default one
+    /// when `None`) and applied to this pipeline: the rules at once, the
+    /// vocabulary when the STT engine is next loaded. Nothing is learned
+    /// while `Config::learning` is off.
+    pub fn learn_corrections(&mut self, config_path: Option<&str>) -> Result<Vec<Correction>> {
+        if !self.config.learning.enabled {
+            return Ok(Vec::new());
+        }
+        let corrections = Corrections::for_config(&self.config)?;
+        let ready = corrections.ready(&self.config.learning)?;
+        if ready.is_empty() {
+            return Ok(ready);
+        }
+
+        let mut saved = Config::load_file(config_path)?;
+        learning::learn(&mut saved, &ready);
+        saved.save(config_path)?;
+        let mut config = self.config.clone();
+        learning::learn(&mut config, &ready);
+        self.update_config(&config)?;
+        corrections.mark_promoted(&ready)?;
+        tracing::info!("Learned {} correction(s) into the vocabulary and replacement rules", ready.len());
+        Ok(ready)
+    }
+
+    fn run(
+        &mut self,
+        audio: &[f32],
+        context: Option<&str>,
+        options: &ProcessOptions,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<PipelineResult> {
+        let request = tracing::info_span!("request", audio_ms = duration_ms(audio), has_context = context.is_some());
+        let _request = request.enter();
+        let dictation = parse_context(context)?;
+        let non_finite = check_audio(audio, &self.config.audio)?;
+        let (mut options, language, task) = prepare(&self.config, self.formatting, options)?;
+        tracing::info!("Processing {} samples", audio.len());
         let start = Instant::now();
+        if options.cancel.is_cancelled() {
+            return Err(cancelled_error(&options.cancel, start, 0, 0, 0));
+        }
+        let quick = is_command(&self.config, audio, options.profile);
+        if quick {
+            tracing::debug!("Short command: transcribed whole, without the LLM");
+            options.formatting = FormattingMode::None;
+        }
 
-        // Determine if we need timestamps for prosody analysis (only if engine supports it)
-        let need_timestamps = self.prosody_options.pause_analysis && self.stt.supports_timestamps();
-        eprintln!("Pipeline: need_timestamps={}", need_timestamps);
+        let stt_load_ms = self.ensure_stt()?;
+        let mut buffer = std::mem::take(&mut self.scratch.preprocessed);
+        let (audio, preprocessed) = self.preprocessed_copy(audio, non_finite > 0, &mut buffer);
+        let transcribed =
+            SttStage { quick, ..self.stt_stage(options.stt_context.as_deref()) }.transcribe(
+                audio,
+                &language,
+                task,
+                &options.cancel,
+                options.progress.as_ref(),
+                start,
+            );
+        let result = transcribed.and_then(|mut transcribed| {
+            transcribed.timings.stt_load_ms = stt_load_ms;
+            transcribed.timings.transcription_ms += stt_load_ms;
+            transcribed.preprocess = preprocessed;
+            let result = self.format_transcription(audio, transcribed, &dictation, &options, sink)?;
+            Ok(PipelineResult { fast_path: quick, ..result })
+        });
+        self.scratch.preprocessed = buffer;
+        result
+    }
 
-        // Step 1: Transcribe audio with STT engine
-        eprintln!("Pipeline: Starting transcription...");
-        tracing::debug!("Transcribing {} samples", audio.len());
-        let t1 = Instant::now();
-        let transcription_result = self.stt.transcribe_with_timestamps(audio, need_timestamps)?;
-        eprintln!("Pipeline: Transcription complete: '{}'", transcription_result.text);
-        let transcription_ms = t1.elapsed().as_millis() as u64;
-        tracing::debug!("Transcription took {}ms: {}", transcription_ms, transcription_result.text);
+    /// Preprocess a copy of `audio` in `buffer` (see `AudioOptions::preprocess`)
+    /// when enabled or when `repair` says it has NaN or infinite samples,
+    /// returning the audio to transcribe and what preprocessing found
+    fn preprocessed_copy<'a>(
+        &self,
+        audio: &'a [f32],
+        repair: bool,
+        buffer: &'a mut Vec<f32>,
+    ) -> (&'a [f32], PreprocessStats) {
+        if !self.config.audio.preprocess && !repair {
+            return (audio, PreprocessStats::default());
+        }
+        buffer.clear();
+        buffer.extend_from_slice(audio);
+        let stats = preprocess(buffer, &self.config.audio);
+        (buffer, stats)
+    }
+
+    /// The transcription half of a request, over the engine loaded by
+    /// `ensure_stt`
+    fn stt_stage<'a>(&'a mut self, context: Option<&'a str>) -> SttStage<'a> {
+        SttStage {
+            stt: self.stt.as_deref_mut().expect("STT engine loaded by ensure_stt"),
+            config: &self.config,
+            context,
+            quick: false,
+        }
+    }
+
+    /// Process queued recordings in order, transcribing each one while the
+    /// one before it is formatted
+    ///
+    /// Each recording runs with its own options, as by
+    /// `process_with_options` (or `process_in_session` when queued in a
+    /// session), and `on_result` gets every key with its result, in the
+    /// order the items come. Items are taken one at a time, only as the
+    /// STT engine gets to them, so `items` may yield requests as they
+    /// arrive and end once none are waiting. Timings leave out the time an
+    /// item spent queued. Without formatting there is nothing to overlap,
+    /// and the items run one after the other.
+    pub fn process_queued<K: Send>(
+        &mut self,
+        items: impl Iterator<Item = QueuedRequest<K>> + Send,
+        mut on_result: impl FnMut(K, Result<PipelineResult, PipelineError>),
+    ) {
+        let stt_load = if self.formats() { self.ensure_stt().ok() } else { None };
+        let Some(stt_load_ms) = stt_load else {
+            for item in items {
+                let QueuedRequest { key, audio, context, options, session } = item;
+                let result = audio.map_err(PipelineError::from).and_then(|(audio, audio_prep_ms)| {
+                    let result = match &session {
+                        Some(session) => {
+                            self.process_in_session(&mut lock_session(session), &audio, context.as_deref(), &options)?
+                        }
+                        None => self.process_with_options(&audio, context.as_deref(), &options)?,
+                    };
+                    Ok(with_audio_prep(result, audio_prep_ms))
+                });
+                on_result(key, result);
+            }
+            return;
+        };
+
+        let mut stt = self.stt.take().expect("STT engine loaded by ensure_stt");
+        let (config, formatting) = (self.config.clone(), self.formatting);
+        std::thread::scope(|scope| {
+            // Bounded to one, so transcription runs a single recording ahead
+            let (tx, rx) = mpsc::sync_channel(1);
+            let (stt, config) = (&mut *stt, &config);
+            scope.spawn(move || {
+                let mut stt_load_ms = stt_load_ms;
+                for item in items {
+                    let QueuedRequest { key, audio, context, options, session } = item;
+                    let transcribed = audio.and_then(|audio| {
+                        let mut queued = transcribe_queued(stt, config, formatting, audio, context.as_deref(), &options)?;
+                        let timings = &mut queued.transcribed.timings;
+                        timings.stt_load_ms = std::mem::take(&mut stt_load_ms);
+                        timings.transcription_ms += timings.stt_load_ms;
+                        Ok(queued)
+                    });
+                    if tx.send((key, transcribed, context, session)).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            for (key, transcribed, context, session) in rx {
+                let result = transcribed.and_then(|queued| {
+                    let QueuedTranscript { audio, audio_prep_ms, mut transcribed, dictation, mut options, quick } =
+                        queued;
+                    // Leave out the time spent waiting behind the previous recording
+                    let transcription = Duration::from_millis(transcribed.timings.transcription_ms);
+                    transcribed.start = Instant::now().checked_sub(transcription).unwrap_or(transcribed.start);
+                    // The session as the dictations before this one left it
+                    if let Some(session) = &session {
+                        options.session = Some(lock_session(session).clone());
+                    }
+                    let result = self.format_transcription(&audio, transcribed, &dictation, &options, None)?;
+                    let result = PipelineResult { fast_path: quick, ..result };
+                    self.record_history(&audio, context.as_deref(), &result);
+                    if let Some(session) = &session {
+                        continue_session(&mut lock_session(session), &result);
+                    }
+                    progress::report(options.progress.as_ref(), ProcessStage::Done, 0, 0);
+                    Ok(with_audio_prep(result, audio_prep_ms))
+                });
+                on_result(key, result.map_err(PipelineError::from));
+            }
+        });
+        self.stt = Some(stt);
+        self.last_used = Instant::now();
+    }
+
+    /// Transcribe one segment of a streaming session (no timestamps, no formatting)
+    ///
+    /// `language` overrides the configured language, e.g. with the one
+    /// detected in an earlier segment.
+    pub(crate) fn transcribe_segment(
+        &mut self,
+        audio: &[f32],
+        language: Option<&str>,
+        cancel: &CancelToken,
+    ) -> Result<TranscriptionResult> {
+        let language = language.unwrap_or(&self.config.language).to_string();
+        let task = self.config.stt_task;
+        let opts = SttOptions { enable_timestamps: false, language: &language, task, context: None, cancel, quick: false };
+        let result = self.stt()?.transcribe(audio, &opts);
+        self.last_used = Instant::now();
+        result
+    }
+
+    /// Whether a transcription should be discarded as silence or noise
+    pub(crate) fn is_no_speech(&self, transcription: &TranscriptionResult) -> bool {
+        is_no_speech(&self.config, transcription)
+    }
+
+    /// Run the post-transcription stages (prosody, prompt, LLM) on a transcript
+    pub(crate) fn format_transcription(
+        &mut self,
+        audio: &[f32],
+        transcribed: Transcribed,
+        context: &DictationContext,
+        options: &ProcessOptions,
+        sink: Option<&mut dyn TokenSink>,
+    ) -> Result<PipelineResult> {
+        let Transcribed {
+            result: mut transcription_result,
+            timings: stt_timings,
+            original_transcript,
+            decoder_timestamps,
+            start,
+            preprocess,
+        } = transcribed;
+        let transcription_ms = stt_timings.transcription_ms;
+        let total_limit_ms = self.config.timeouts.total_limit_ms(duration_ms(audio) as u64);
+        let cancel = &options.cancel.with_deadline(TimeoutStage::Total, start, total_limit_ms);
+        let cancelled = |transcription_ms, prosody_ms, llm_formatting_ms| {
+            cancelled_error(cancel, start, transcription_ms, prosody_ms, llm_formatting_ms)
+        };
+
+        let (text, filtered_segments) = filter_hallucinations(
+            &transcription_result.text,
+            transcription_result.no_speech_probability,
+            &self.config.hallucination_filter,
+        );
+        transcription_result.text = text;
+
+        if self.is_no_speech(&transcription_result) {
+            tracing::info!(
+                "No speech detected (confidence {:.2}, no-speech probability {:.2})",
+                transcription_result.confidence,
+                transcription_result.no_speech_probability
+            );
+            tracing::debug!("Discarded transcript: {}", transcription_result.text);
+            return Ok(PipelineResult {
+                original_transcript,
+                clipped_percent: preprocess.clipped_percent,
+                clipping: preprocess.clipping,
+                repaired_samples: preprocess.repaired_samples,
+                filtered_segments,
+                ..PipelineResult::no_speech(
+                    &transcription_result,
+                    Timings { total_ms: start.elapsed().as_millis() as u64, ..stt_timings },
+                )
+            });
+        }
 
         let mut raw_transcript = transcription_result.text.clone();
+        let task = options.task.unwrap_or(self.config.stt_task);
 
-        if raw_transcript.trim().is_empty() {
+        // Voice commands come first, so nothing after them (the LLM
+        // included) sees "comma" or "scratch that" as words
+        let mut scratch_previous = false;
+        if self.prosody_options.voice_commands && options.voice_commands.unwrap_or(self.config.voice_commands.enabled) {
+            let commands = apply_commands(&self.config, &transcription_result, task, &raw_transcript);
+            tracing::debug!("After voice commands: {}", commands.text);
+            raw_transcript = commands.text;
+            scratch_previous = commands.scratch_previous;
+        }
+        // Only "scratch that" was said
+        if raw_transcript.is_empty() {
             return Ok(PipelineResult {
-                raw_transcript: String::new(),
-                formatted_text: String::new(),
-                timings: Timings {
-                    transcription_ms,
-                    prosody_ms: 0,
-                    llm_formatting_ms: 0,
-                    total_ms: start.elapsed().as_millis() as u64,
-                },
-                prosody_hints: None,
+                no_speech: false,
+                filtered_segments,
+                scratch_previous,
+                ..PipelineResult::no_speech(
+                    &transcription_result,
+                    Timings { total_ms: start.elapsed().as_millis() as u64, ..stt_timings },
+                )
             });
         }
 
         // Step 2: Prosody analysis
         let t2 = Instant::now();
+        let prosody_span = tracing::info_span!("prosody").entered();
         let mut prosody_hints = None;
 
         if self.prosody_options.any_enabled() {
-            // Apply voice commands first (before prosody analysis)
-            if self.prosody_options.voice_commands {
-                raw_transcript = replace_voice_commands(&raw_transcript);
-                tracing::debug!("After voice commands: {}", raw_transcript);
-            }
-
             // Concatenate spelled-out letters (e.g., "S M O L L M" → "SMOLLM")
             // This runs before LLM to catch obvious patterns
             raw_transcript = concatenate_spelled_words_aggressive(&raw_transcript);
@@ -336,9 +2244,16 @@ impl Pipeline {
             raw_transcript = self.replacements.apply(&raw_transcript);
             tracing::debug!("After dictionary replacements: {}", raw_transcript);
 
-            // Run prosody analysis (only use timestamps if available)
-            if self.prosody_options.pause_analysis || self.prosody_options.pitch_analysis {
-                let word_timestamps = if self.prosody_options.pause_analysis && !transcription_result.word_timestamps.is_empty() {
+            // Run prosody analysis (only use timestamps if available); the
+            // hints only feed the LLM, so skip it when the LLM won't run
+            if options.formatting != FormattingMode::None
+                && (self.prosody_options.pause_analysis || self.prosody_options.pitch_analysis)
+            {
+                // Estimated timings would invent pauses, so only use decoder timestamps
+                let word_timestamps = if self.prosody_options.pause_analysis
+                    && decoder_timestamps
+                    && !transcription_result.word_timestamps.is_empty()
+                {
                     Some(WhisperEngine::get_word_timestamp_tuples(&transcription_result))
                 } else {
                     None
@@ -349,10 +2264,64 @@ impl Pipeline {
                 prosody_hints = Some(hints);
             }
         }
+        drop(prosody_span);
         let prosody_ms = t2.elapsed().as_millis() as u64;
 
+        // Numbers are written the same way whether or not the LLM runs
+        raw_transcript = format_numbers(&self.config, &transcription_result, task, &raw_transcript);
+
+        // The first of the two passes: the transcript as it reads without
+        // the LLM, which may cancel the rest
+        if let Some(on_raw) = &options.on_raw {
+            on_raw.send(&self.rules_for(options.app_id.as_deref()).apply_raw(&raw_transcript));
+        }
+
+        // Out of time, the transcript is still kept: the LLM stops at once
+        // and falls back to it
+        if cancel.is_cancelled() && cancel.timeout().is_none() {
+            return Err(cancelled(transcription_ms, prosody_ms, 0));
+        }
+
+        // Segments, at long pauses and (with diarization) speaker turns, are
+        // formatted one at a time
+        let speakers = if self.config.diarization.enabled {
+            self.label_speakers(audio, &transcription_result)?
+        } else {
+            None
+        };
+        let mut segments = segment::split(
+            &raw_transcript,
+            &transcription_result.word_timestamps,
+            speakers.as_deref(),
+            duration_ms(audio),
+            transcription_result.confidence,
+        );
+        let labeled = self.config.diarization.label_speakers && diarize::speaker_count(&segments) > 1;
+
         // Step 3: Get prompt for context
-        let mut prompt_template = self.config.get_prompt_for_context(context);
+        // Owned, as the config is borrowed again to format each segment
+        let preset = options.preset.clone().or_else(|| self.config.default_preset.clone());
+        let preset = preset.as_ref();
+        // `{context}` is filled in when the prompt is fitted to the context
+        // window, which may shorten the context
+        let mut prompt_template = self.prompt_template_for(options.formatting, preset, context.kind());
+        if let Some(app_id) = options.app_id.as_deref().filter(|id| self.config.app_override(id).is_some()) {
+            // The engine fills `{personal_dictionary}` from its own config,
+            // which doesn't have the app's terms
+            let vocabulary = self.config.vocabulary_for_app(app_id);
+            prompt_template = prompt_template
+                .replace("{personal_dictionary}", &personal_dictionary(&self.config.personal_dictionary, &vocabulary));
+        }
+        let context_text = context.kind().unwrap_or(&self.config.default_context).to_string();
+        let llm_options = self.llm_options_for(options.llm_options.as_ref(), preset);
+
+        // The structured context's fields where the prompt places them, or
+        // after it
+        if DictationContext::placed_in(&prompt_template) {
+            prompt_template = context.fill_placeholders(&prompt_template);
+        } else {
+            prompt_template.push_str(&context.to_llm_context());
+        }
 
         // Add prosody hints to prompt if enabled
         if self.prosody_options.llm_hints {
@@ -363,42 +2332,127 @@ impl Pipeline {
                 }
             }
         }
+        if self.config.stt_decode.alternatives_in_prompt {
+            prompt_template.push_str(&alternatives_hint(&transcription_result.alternatives));
+        }
 
-        // Step 4: Format with LLM (lazy init here, with fallback)
-        tracing::debug!("Formatting with LLM (context: {:?})", context);
-        let t3 = Instant::now();
+        // Step 4: Format with LLM, segment by segment, unless disabled for
+        // this call. The same segment formatted the same way gets the same
+        // output, unless earlier dictations of a session are part of the
+        // prompt
+        if options.formatting == FormattingMode::None {
+            tracing::debug!("LLM formatting disabled for this call");
+        } else {
+            tracing::debug!("Formatting {} segment(s) with LLM (context: {:?})", segments.len(), context.kind());
+        }
+        let format_span = tracing::info_span!(
+            "format",
+            mode = ?options.formatting,
+            segments = segments.len(),
+            tokens = tracing::field::Empty,
+            cache_hits = tracing::field::Empty,
+            fallback = tracing::field::Empty,
+        )
+        .entered();
+        let request = FormatRequest {
+            mode: options.formatting,
+            preset,
+            llm_options: &llm_options,
+            cacheable: self.format_cache.is_enabled() && options.session.is_none(),
+            cancel,
+        };
+        // Earlier dictations of the session and the segments before, in
+        // whatever room the prompt and the output leave in the context window
+        let mut history = options.session.clone().unwrap_or_default();
+        let mut tally = FormatTally::default();
+        let mut raw_outputs = Vec::new();
+        let mut sink = sink;
+        let mut format_progress = options
+            .progress
+            .as_ref()
+            .filter(|_| options.formatting != FormattingMode::None)
+            .map(|reporter| {
+                FormatProgress::new(reporter, segments.iter().map(|s| s.raw_text.as_str()), llm_options.max_tokens)
+            });
+        for index in 0..segments.len() {
+            let input = segments[index].raw_text.clone();
+            let prompt = PromptParts {
+                template: &prompt_template,
+                context: &context_text,
+                history: &history,
+                history_tokens: self.config.session_context_tokens as usize,
+            };
+            if index > 0 {
+                if let Some(sink) = reborrow(&mut sink) {
+                    sink.token(if labeled && segments[index - 1].speaker != segments[index].speaker { "\n\n" } else { " " });
+                }
+            }
 
-        let (formatted_text, llm_formatting_ms) = match self.get_llm() {
-            Ok(llm) => {
-                match llm.format(&raw_transcript, &prompt_template) {
-                    Ok(text) => {
-                        let ms = t3.elapsed().as_millis() as u64;
-                        tracing::debug!("LLM formatting took {}ms", ms);
-                        (text, ms)
-                    }
-                    Err(e) => {
-                        // LLM formatting failed - try fallback
-                        tracing::warn!("LLM formatting failed: {}. Falling back to raw transcript.", e);
-                        if self.recovery_config.fallback_to_transcribe_only {
-                            (raw_transcript.clone(), 0)
-                        } else {
-                            return Err(PipelineError::LlmFormattingFailed {
-                                message: e.to_string(),
-                            }.into());
-                        }
-                    }
+            let formatted = match format_progress.as_mut() {
+                Some(progress) => {
+                    let mut tokens = progress.sink(reborrow(&mut sink));
+                    self.format_segment(&input, &prompt, &request, Some(&mut tokens), &mut tally)
                 }
+                None => self.format_segment(&input, &prompt, &request, reborrow(&mut sink), &mut tally),
+            };
+            if let Some(progress) = format_progress.as_mut() {
+                progress.segment_done();
             }
-            Err(e) => {
-                // LLM initialization failed - try fallback
-                tracing::warn!("LLM initialization failed: {}. Falling back to raw transcript.", e);
-                if self.recovery_config.fallback_to_transcribe_only {
-                    (raw_transcript.clone(), 0)
-                } else {
-                    return Err(e);
+            let output = match formatted {
+                Ok(output) => output,
+                Err(_) if cancel.is_cancelled() => {
+                    return Err(cancelled(transcription_ms, prosody_ms, tally.llm_formatting_ms));
                 }
+                Err(e) => return Err(e),
+            };
+            history.push(&output.text);
+            segments[index].formatted_text = output.text;
+            if !output.raw.is_empty() {
+                raw_outputs.push(output.raw);
             }
-        };
+        }
+        let FormatTally {
+            llm_formatting_ms,
+            llm_load_ms,
+            stats: llm_stats,
+            was_fallback,
+            formatting_error,
+            truncation,
+            cache_hits,
+            ..
+        } = tally;
+        format_span.record("tokens", llm_stats.tokens_generated);
+        format_span.record("cache_hits", cache_hits);
+        format_span.record("fallback", was_fallback);
+        drop(format_span);
+        let format_cache_hit = cache_hits > 0 && cache_hits == segments.len();
+        let raw_llm_output = (self.config.llm_output.keep_raw_output && !raw_outputs.is_empty())
+            .then(|| raw_outputs.join("\n\n"));
+
+        // Step 5: User find-and-replace rules, with the app's after them
+        let rules = self.rules_for(options.app_id.as_deref());
+        for segment in &mut segments {
+            segment.formatted_text = rules.apply(&segment.formatted_text);
+            segment.raw_text = rules.apply_raw(&segment.raw_text);
+        }
+
+        // Step 6: Fit the text into what's around the cursor, by rule rather
+        // than trusting the LLM to. Segments keep their own text; only the
+        // whole gets the spaces on either side
+        let cursor = context.cursor().filter(|_| options.formatting != FormattingMode::None && !labeled);
+        if let Some((before, after)) = cursor {
+            if let Some(first) = segments.first_mut() {
+                first.formatted_text = join::fit_start(before, after, first.formatted_text.trim());
+            }
+            if let Some(last) = segments.last_mut() {
+                last.formatted_text = join::fit_end(before, after, last.formatted_text.trim_end());
+            }
+        }
+        let mut formatted_text = segment::join_formatted(&segments, labeled);
+        if let Some((before, after)) = cursor {
+            formatted_text = join::pad(before, after, &formatted_text);
+        }
+        let raw_transcript = rules.apply_raw(&raw_transcript);
 
         let total_ms = start.elapsed().as_millis() as u64;
         tracing::info!(
@@ -412,26 +2466,310 @@ impl Pipeline {
         Ok(PipelineResult {
             raw_transcript,
             formatted_text,
-            timings: Timings {
-                transcription_ms,
-                prosody_ms,
-                llm_formatting_ms,
-                total_ms,
-            },
+            timings: Timings { prosody_ms, llm_formatting_ms, llm_load_ms, total_ms, ..stt_timings }
+                .with_llm_stats(&llm_stats),
             prosody_hints,
+            word_timestamps: transcription_result.word_timestamps,
+            confidence: transcription_result.confidence,
+            alternatives: transcription_result.alternatives,
+            no_speech_probability: transcription_result.no_speech_probability,
+            no_speech: false,
+            language: transcription_result.language,
+            original_transcript,
+            raw_llm_output,
+            was_fallback,
+            formatting_error,
+            prompt_truncation: (!truncation.is_empty()).then_some(truncation),
+            format_cache_hit,
+            clipped_percent: preprocess.clipped_percent,
+            clipping: preprocess.clipping,
+            repaired_samples: preprocess.repaired_samples,
+            filtered_segments,
+            scratch_previous,
+            segments,
+            fast_path: false,
         })
     }
 
+    /// Find-and-replace rules for dictation into app `app_id`
+    fn rules_for(&self, app_id: Option<&str>) -> &ReplacementRules {
+        app_id.and_then(|id| self.app_rules.get(id)).unwrap_or(&self.rules)
+    }
+
+    /// Prompt template for a call, with `{context}` still in it
+    fn prompt_template_for(
+        &self,
+        formatting: FormattingMode,
+        preset: Option<&FormattingPreset>,
+        context: Option<&str>,
+    ) -> String {
+        match (formatting, preset) {
+            (FormattingMode::PunctuationOnly, _) => PUNCTUATION_ONLY_PROMPT.to_string(),
+            (_, Some(preset)) => preset.prompt_template().to_string(),
+            (_, None) => self.config.prompt_template_for_context(context),
+        }
+    }
+
+    /// Decoding parameters for a call
+    fn llm_options_for(&self, llm_options: Option<&LlmOptions>, preset: Option<&FormattingPreset>) -> LlmOptions {
+        let llm_options = match (llm_options, preset) {
+            (Some(llm_options), _) => llm_options.clone(),
+            (None, Some(preset)) => preset.llm_options(&self.config.llm_options),
+            (None, None) => self.config.llm_options.clone(),
+        };
+        if self.config.deterministic { llm_options.deterministic() } else { llm_options }
+    }
+
+    /// Have the LLM, if loaded, prefill the start of the prompt a call with
+    /// the default options and context gets (see `TextFormatter::prime`)
+    fn prime_llm(&mut self, cancel: &CancelToken) -> Result<()> {
+        let preset = self.config.default_preset.clone();
+        let template = self
+            .prompt_template_for(FormattingMode::default(), preset.as_ref(), None)
+            .replace("{context}", &self.config.default_context);
+        let llm_options = self.llm_options_for(None, preset.as_ref());
+        if let Some(llm) = self.llm.as_mut() {
+            let ctx = FormatContext { prompt_template: &template, llm_options: &llm_options, cancel };
+            llm.prime(&ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Format one segment with the LLM (lazy init here, with fallback), or
+    /// take it from the cache
+    ///
+    /// Gives the unformatted segment, setting `was_fallback`, when the LLM
+    /// fails and fallback is allowed or when its output strays from the
+    /// segment's words. Once the LLM has failed, later segments of the
+    /// request aren't tried.
+    fn format_segment(
+        &mut self,
+        input: &str,
+        prompt: &PromptParts,
+        request: &FormatRequest,
+        sink: Option<&mut dyn TokenSink>,
+        tally: &mut FormatTally,
+    ) -> Result<LlmOutput> {
+        let unformatted = || LlmOutput { text: input.to_string(), ..Default::default() };
+        if request.mode == FormattingMode::None || tally.llm_failed {
+            return Ok(unformatted());
+        }
+
+        let mut cache_key = request.cacheable.then(|| {
+            let config = &self.config;
+            FormatCacheKey::new(
+                input,
+                request.preset,
+                prompt.context,
+                // As the engine fills it in, with the personal dictionary
+                &format_prompt(&prompt.full(), "{transcript}", config),
+                &(
+                    &config.formatter,
+                    &config.llm_model,
+                    &config.custom_model_name,
+                    config.chat_template,
+                    config.llm_context_window,
+                ),
+                &(request.mode, request.llm_options, &config.llm_output),
+            )
+        });
+        let output = if let Some(output) = cache_key.as_ref().and_then(|key| self.format_cache.get(key)) {
+            tracing::debug!("Formatted text taken from the cache");
+            if let Some(sink) = sink {
+                sink.token(&output.raw);
+            }
+            tally.cache_hits += 1;
+            output
+        } else {
+            let t = Instant::now();
+            let cancel = request.cancel.with_deadline(TimeoutStage::Llm, t, self.config.timeouts.llm_ms);
+            let request = &FormatRequest { cancel: &cancel, ..*request };
+            let loading = self.llm.is_none();
+            let fallback = self.can_fallback();
+            let budget = PromptBudget::new(self.config.llm_context_tokens(), request.llm_options.max_tokens as usize);
+
+            match self.get_llm().map(|_| ()) {
+                Ok(()) => {
+                    if loading {
+                        tally.llm_load_ms = t.elapsed().as_millis() as u64;
+                    }
+                    // The LLM and the config, borrowed apart
+                    let llm = self.llm.as_deref_mut().expect("loaded by get_llm");
+                    let config = &self.config;
+                    let formatted = budget
+                        .fit(prompt, input, |template, transcript| {
+                            llm.count_tokens(&format_prompt(template, transcript, config))
+                        })
+                        .and_then(|plan| {
+                            if !plan.truncation.is_empty() {
+                                tracing::warn!("Prompt cut to fit the LLM's context window: {:?}", plan.truncation);
+                            }
+                            tally.truncation.add(&plan.truncation);
+                            format_plan(llm, &plan, request, sink)
+                        });
+                    match formatted {
+                        Ok(output) => {
+                            let ms = t.elapsed().as_millis() as u64;
+                            tracing::debug!("LLM formatting took {}ms", ms);
+                            tally.llm_formatting_ms += ms;
+                            if let Some(key) = cache_key.take() {
+                                self.format_cache.insert(key, output.clone());
+                            }
+                            output
+                        }
+                        Err(e) if request.cancel.is_cancelled() => {
+                            tally.llm_formatting_ms += t.elapsed().as_millis() as u64;
+                            // Running out of time is an LLM failure like any other
+                            let Some(timeout) = request.cancel.timeout() else {
+                                return Err(e);
+                            };
+                            tracing::warn!("{}. Falling back to raw transcript.", timeout);
+                            if !fallback {
+                                return Err(timeout.into());
+                            }
+                            tally.was_fallback = true;
+                            tally.llm_failed = true;
+                            tally.formatting_error = Some(timeout.to_string());
+                            return Ok(unformatted());
+                        }
+                        Err(e) => {
+                            // LLM formatting failed - try fallback
+                            tracing::warn!("LLM formatting failed: {}. Falling back to raw transcript.", e);
+                            if fallback {
+                                tally.was_fallback = true;
+                                tally.llm_failed = true;
+                                tally.formatting_error = Some(format!("{:#}", e));
+                                return Ok(unformatted());
+                            } else if matches!(e.downcast_ref::<PipelineError>(), Some(PipelineError::RemoteFormatter { .. })) {
+                                return Err(e);
+                            } else {
+                                return Err(PipelineError::LlmFormattingFailed {
+                                    message: e.to_string(),
+                                }.into());
+                            }
+                        }
+                    }
+                }
+                Err(e) if request.cancel.is_cancelled() && request.cancel.timeout().is_none() => {
+                    tally.llm_formatting_ms += t.elapsed().as_millis() as u64;
+                    return Err(e);
+                }
+                Err(e) => {
+                    // LLM initialization failed - try fallback
+                    tracing::warn!("LLM initialization failed: {}. Falling back to raw transcript.", e);
+                    if !fallback {
+                        return Err(e);
+                    }
+                    tally.was_fallback = true;
+                    tally.llm_failed = true;
+                    tally.formatting_error = Some(format!("{:#}", e));
+                    return Ok(unformatted());
+                }
+            }
+        };
+        tally.add_stats(&output.stats);
+
+        // Punctuation-only output must keep the segment's words, and other
+        // output most of them
+        let rejected = match request.mode {
+            FormattingMode::None => false,
+            FormattingMode::PunctuationOnly => !same_words(input, &output.text),
+            FormattingMode::Full => word_similarity(input, &output.text) < self.config.min_format_similarity,
+        };
+        if rejected {
+            tracing::warn!("LLM formatting diverged from the transcript. Falling back to raw transcript.");
+            tracing::debug!("Rejected LLM output: {:?}", output.text);
+            tally.was_fallback = true;
+            return Ok(LlmOutput { text: input.to_string(), ..output });
+        }
+        Ok(output)
+    }
+
+    /// The speaker of each word of a transcript of `audio`, loading the
+    /// speaker model on first use (None without word timestamps)
+    fn label_speakers(&mut self, audio: &[f32], transcription: &TranscriptionResult) -> Result<Option<Vec<u8>>> {
+        let words = &transcription.word_timestamps;
+        if words.is_empty() {
+            return Ok(None);
+        }
+        if self.speaker_embedder.is_none() {
+            let start = Instant::now();
+            self.speaker_embedder = Some(diarize::load_embedder(&self.config)?);
+            tracing::info!("Speaker model loaded in {}ms", start.elapsed().as_millis());
+        }
+        let Some(embedder) = self.speaker_embedder.as_deref_mut() else {
+            return Ok(None);
+        };
+
+        let t = Instant::now();
+        let _span = tracing::info_span!("diarize", words = words.len()).entered();
+        let speakers = diarize::label_words(embedder, audio, words, &self.config.diarization)?;
+        tracing::debug!("Diarization took {}ms", t.elapsed().as_millis());
+        Ok(Some(speakers))
+    }
+
     /// Process audio without LLM formatting (raw transcription only)
-    pub fn transcribe_only(&mut self, audio: &[f32]) -> Result<PipelineResult> {
+    pub fn transcribe_only(&mut self, audio: &[f32]) -> Result<PipelineResult, PipelineError> {
+        let non_finite = check_audio(audio, &self.config.audio)?;
+        let mut buffer = std::mem::take(&mut self.scratch.preprocessed);
+        let (audio, preprocessed) = self.preprocessed_copy(audio, non_finite > 0, &mut buffer);
+        let result = self.transcribe_without_formatting(audio).map_err(PipelineError::from).map(|result| PipelineResult {
+            clipped_percent: preprocessed.clipped_percent,
+            clipping: preprocessed.clipping,
+            repaired_samples: preprocessed.repaired_samples,
+            ..result
+        });
+        self.scratch.preprocessed = buffer;
+        self.last_used = Instant::now();
+        result
+    }
+
+    fn transcribe_without_formatting(&mut self, audio: &[f32]) -> Result<PipelineResult> {
         let start = Instant::now();
+        let stt_load_ms = self.ensure_stt()?;
 
-        let mut raw_transcript = self.stt.transcribe(audio)?;
+        // Long recordings are still chunked, but silence isn't trimmed
+        let language = self.config.language.clone();
+        let task = self.config.stt_task;
+        let whole = 0..audio.len();
+        let regions = std::slice::from_ref(&whole);
+        let cancel = CancelToken::new();
+        let mut stage = self.stt_stage(None);
+        let (mut transcription, chunk_transcription_ms) =
+            stage.transcribe_regions(audio, regions, &language, task, &cancel, None)?;
+        let original_transcript = stage.original_transcript(audio, regions, &transcription, task, &cancel, None)?;
         let transcription_ms = start.elapsed().as_millis() as u64;
+        let timings = Timings {
+            transcription_ms,
+            total_ms: transcription_ms,
+            chunk_transcription_ms,
+            stt_encode_ms: transcription.encode_ms,
+            stt_decode_ms: transcription.decode_ms,
+            stt_provider: self.stt_provider(),
+            stt_temperature: transcription.temperature,
+            stt_temperature_fallback: transcription.temperature_fallback,
+            stt_load_ms,
+            ..Default::default()
+        };
+
+        let (text, filtered_segments) = filter_hallucinations(
+            &transcription.text,
+            transcription.no_speech_probability,
+            &self.config.hallucination_filter,
+        );
+        transcription.text = text;
+
+        if self.is_no_speech(&transcription) {
+            return Ok(PipelineResult { filtered_segments, ..PipelineResult::no_speech(&transcription, timings) });
+        }
+        let mut raw_transcript = std::mem::take(&mut transcription.text);
 
         // Apply voice commands even in transcribe-only mode
-        if self.prosody_options.voice_commands {
-            raw_transcript = replace_voice_commands(&raw_transcript);
+        let mut scratch_previous = false;
+        if self.prosody_options.voice_commands && self.config.voice_commands.enabled {
+            let commands = apply_commands(&self.config, &transcription, task, &raw_transcript);
+            raw_transcript = commands.text;
+            scratch_previous = commands.scratch_previous;
         }
 
         // Concatenate spelled-out letters
@@ -439,17 +2777,503 @@ impl Pipeline {
 
         // Apply user-defined replacements
         raw_transcript = self.replacements.apply(&raw_transcript);
+        raw_transcript = format_numbers(&self.config, &transcription, task, &raw_transcript);
+
+        let mut segments = segment::split(
+            &raw_transcript,
+            &transcription.word_timestamps,
+            None,
+            duration_ms(audio),
+            transcription.confidence,
+        );
+        for segment in &mut segments {
+            segment.formatted_text = self.rules.apply(&segment.raw_text);
+            segment.raw_text = self.rules.apply_raw(&segment.raw_text);
+        }
 
         Ok(PipelineResult {
-            raw_transcript: raw_transcript.clone(),
-            formatted_text: raw_transcript,
-            timings: Timings {
-                transcription_ms,
-                prosody_ms: 0,
-                llm_formatting_ms: 0,
-                total_ms: transcription_ms,
-            },
+            formatted_text: segment::join_formatted(&segments, false),
+            raw_transcript: self.rules.apply_raw(&raw_transcript),
+            timings,
             prosody_hints: None,
+            word_timestamps: transcription.word_timestamps,
+            confidence: transcription.confidence,
+            alternatives: transcription.alternatives,
+            no_speech_probability: transcription.no_speech_probability,
+            no_speech: false,
+            language: transcription.language,
+            original_transcript,
+            raw_llm_output: None,
+            was_fallback: false,
+            formatting_error: None,
+            prompt_truncation: None,
+            format_cache_hit: false,
+            clipped_percent: 0.0,
+            clipping: false,
+            repaired_samples: 0,
+            filtered_segments,
+            scratch_previous,
+            segments,
+            fast_path: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two seconds of digital silence
+    fn silence_fixture() -> Vec<f32> {
+        vec![0.0; 32000]
+    }
+
+    /// Two seconds of white noise (fan hiss, keyboard rumble)
+    fn noise_fixture() -> Vec<f32> {
+        // Fixed LCG so the fixture is identical on every run
+        let mut state = 0x2545_f491u32;
+        (0..32000)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((state >> 8) as f32 / (1u32 << 24) as f32 - 0.5) * 0.2
+            })
+            .collect()
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_silence_returns_no_speech() {
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        let result = pipeline.process(&silence_fixture(), None).unwrap();
+        assert!(result.no_speech, "hallucinated: {:?}", result.raw_transcript);
+        assert!(result.raw_transcript.is_empty());
+        assert!(result.formatted_text.is_empty());
+    }
+
+    #[test]
+    fn test_init_stages_progress_in_order() {
+        let stages = [
+            InitStage::LoadingConfig,
+            InitStage::LoadingSttEncoder,
+            InitStage::LoadingSttDecoder,
+            InitStage::LoadingLlm,
+            InitStage::WarmingUp,
+            InitStage::Ready,
+        ];
+        assert!(stages.windows(2).all(|pair| pair[0].percent() < pair[1].percent()));
+        assert_eq!(InitStage::Ready.percent(), 100);
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_progress_reports_every_stage() {
+        let stages = std::cell::RefCell::new(Vec::new());
+        let report = |stage: InitStage| stages.borrow_mut().push(stage);
+        let config = Config { stt_engine: SttEngineConfig::Moonshine, ..Config::default() };
+        let pipeline = Pipeline::new_with_progress(&config, RecoveryConfig::default(), Some(&report)).unwrap();
+        assert!(pipeline.is_llm_ready());
+        assert_eq!(
+            stages.into_inner(),
+            [
+                InitStage::LoadingSttEncoder,
+                InitStage::LoadingSttDecoder,
+                InitStage::LoadingLlm,
+                InitStage::WarmingUp,
+                InitStage::Ready,
+            ]
+        );
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_warm_up_leaves_requests_unchanged() {
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        assert!(!pipeline.is_llm_ready());
+        pipeline.warm_up().unwrap();
+        assert!(pipeline.is_llm_ready());
+        assert!(pipeline.process(&silence_fixture(), None).unwrap().no_speech);
+    }
+
+    /// Needs downloaded Whisper and Moonshine models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_reload_stt_swaps_engine() {
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        pipeline.reload_stt(SttEngineConfig::Moonshine).unwrap();
+        assert_eq!(pipeline.config().stt_engine, SttEngineConfig::Moonshine);
+        assert!(!pipeline.stt.as_ref().unwrap().supports_timestamps());
+        assert!(pipeline.process(&silence_fixture(), None).unwrap().no_speech);
+
+        // Moonshine is English-only: the Whisper engine stays in place
+        let config = Config { language: "de".to_string(), ..Config::default() };
+        let mut pipeline = Pipeline::new(&config).unwrap();
+        assert!(pipeline.reload_stt(SttEngineConfig::Moonshine).is_err());
+        assert!(pipeline.stt.as_ref().unwrap().supports_timestamps());
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_noise_returns_no_speech() {
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        let result = pipeline.process(&noise_fixture(), None).unwrap();
+        assert!(result.no_speech, "hallucinated: {:?}", result.raw_transcript);
+        assert!(result.formatted_text.is_empty());
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_speech_is_kept() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        let result = pipeline.transcribe_only(&audio).unwrap();
+        assert!(!result.no_speech);
+        assert!(!result.raw_transcript.is_empty());
+        assert!(result.confidence > 0.3, "confidence {}", result.confidence);
+    }
+
+    /// Needs downloaded models, including the speaker model, and the
+    /// diarization feature: `cargo test --features diarization -- --ignored`
+    #[cfg(feature = "diarization")]
+    #[test]
+    #[ignore]
+    fn test_single_speaker_has_one_turn() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let mut config = Config::default();
+        config.diarization.enabled = true;
+        let mut pipeline = PipelineBuilder::from(config).formatting(FormattingMode::None).build().unwrap();
+        let result = pipeline.process(&audio, None).unwrap();
+        assert_eq!(result.segments.len(), 1, "spurious speaker switch: {:?}", result.segments);
+        assert!(!result.formatted_text.contains("Speaker"));
+    }
+
+    /// Needs downloaded models and a recording of someone saying "deploy it
+    /// to Kubernetes" at tests/fixtures/kubernetes.wav (not checked in):
+    /// `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_vocabulary_hint_fixes_proper_noun() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/fixtures/kubernetes.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let baseline = Pipeline::new(&Config::default()).unwrap().transcribe_only(&audio).unwrap();
+        assert!(!baseline.raw_transcript.contains("Kubernetes"), "fixture should be misrecognized without the hint");
+
+        let mut config = Config::default();
+        config.vocabulary = vec![crate::config::VocabularyEntry {
+            term: "Kubernetes".to_string(),
+            sounds_like: vec!["cooper netties".to_string()],
+        }];
+        let hinted = Pipeline::new(&config).unwrap().transcribe_only(&audio).unwrap();
+        assert!(hinted.raw_transcript.contains("Kubernetes"), "got {:?}", hinted.raw_transcript);
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_formatting_none_skips_llm() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        let options = ProcessOptions {
+            formatting: FormattingMode::None,
+            ..Default::default()
+        };
+        let result = pipeline.process_with_options(&audio, None, &options).unwrap();
+        assert_eq!(result.formatted_text, result.raw_transcript);
+        assert_eq!(result.timings.llm_formatting_ms, 0);
+        assert!(pipeline.llm.is_none(), "LLM should not be loaded");
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_llm_loads_on_first_format() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        assert!(pipeline.llm.is_none());
+        let first = pipeline.process(&audio, None).unwrap();
+        assert!(first.timings.llm_load_ms > 0);
+        assert!(first.timings.llm_formatting_ms >= first.timings.llm_load_ms);
+        assert_eq!(pipeline.process(&audio, None).unwrap().timings.llm_load_ms, 0);
+
+        assert!(pipeline.unload_llm());
+        assert!(pipeline.llm.is_none());
+        assert!(pipeline.process(&audio, None).unwrap().timings.llm_load_ms > 0);
+
+        let config = Config { llm_preload: true, ..Config::default() };
+        assert!(Pipeline::new(&config).unwrap().is_llm_ready());
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_idle_unload_reloads_on_next_request() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let config = Config { idle_unload_seconds: Some(1), idle_unload_stt: true, ..Config::default() };
+        let mut pipeline = Pipeline::new(&config).unwrap();
+        pipeline.process(&audio, None).unwrap();
+        assert!(!pipeline.unload_if_idle());
+        assert!(pipeline.idle_unload_in().unwrap() <= Duration::from_secs(1));
+
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(pipeline.unload_if_idle());
+        assert!(pipeline.llm.is_none() && pipeline.stt.is_none());
+        assert_eq!(pipeline.idle_unload_in(), None);
+
+        let result = pipeline.process(&audio, None).unwrap();
+        assert!(result.timings.stt_load_ms > 0 && result.timings.llm_load_ms > 0);
+        assert!(!result.raw_transcript.is_empty());
+    }
+
+    /// Throughput at 1, 2 and 4 threads, to spot `stt_threads` and
+    /// `llm_threads` no longer reaching the engines. Needs downloaded models:
+    /// `cargo test --release -- --ignored --nocapture test_thread_count_throughput`
+    #[test]
+    #[ignore]
+    fn test_thread_count_throughput() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+        let audio_secs = audio.len() as f32 / 16000.0;
+
+        for threads in [1, 2, 4] {
+            let mut config = Config::default();
+            config.stt_threads = threads;
+            config.llm_threads = threads;
+            let mut pipeline = Pipeline::new(&config).unwrap();
+            pipeline.warm_up().unwrap();
+
+            let result = pipeline.process(&audio, None).unwrap();
+            assert!(!result.raw_transcript.is_empty());
+            println!(
+                "{} thread(s): STT {:.1}x realtime ({}ms), LLM {:.1} tokens/s",
+                threads,
+                audio_secs * 1000.0 / result.timings.transcription_ms.max(1) as f32,
+                result.timings.transcription_ms,
+                result.timings.tokens_per_second
+            );
+        }
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_auto_language_detects_english() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let mut pipeline = Pipeline::new(&Config::default()).unwrap();
+        let options = ProcessOptions {
+            formatting: FormattingMode::None,
+            language: Some("auto".to_string()),
+            ..Default::default()
+        };
+        let result = pipeline.process_with_options(&audio, None, &options).unwrap();
+        assert_eq!(result.language.as_deref(), Some("en"));
+    }
+
+    /// Needs downloaded models: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_translate_keeps_original_transcript() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+        let buffer = load_audio_file(Path::new(path)).unwrap();
+        let audio = buffer.as_input().to_16khz_mono().unwrap();
+
+        let mut config = Config::default();
+        config.keep_original_transcript = true;
+        let mut pipeline = Pipeline::new(&config).unwrap();
+        let options = ProcessOptions {
+            formatting: FormattingMode::None,
+            task: Some(SttTask::Translate),
+            ..Default::default()
+        };
+        let result = pipeline.process_with_options(&audio, None, &options).unwrap();
+        assert!(!result.raw_transcript.is_empty());
+        assert!(!result.original_transcript.unwrap().is_empty());
+
+        // Only a translation has an original
+        let result = pipeline.process_with_options(&audio, None, &ProcessOptions::default()).unwrap();
+        assert!(result.original_transcript.is_none());
+    }
+
+    /// Snapshot of the JSON form: a change here breaks apps decoding it,
+    /// and needs `RESULT_SCHEMA_VERSION` raised unless it only adds fields
+    #[test]
+    fn test_result_json_schema() {
+        let result = PipelineResult {
+            raw_transcript: "is it ready".to_string(),
+            formatted_text: "Is it ready?".to_string(),
+            timings: Timings {
+                transcription_ms: 120,
+                total_ms: 300,
+                chunk_transcription_ms: vec![120],
+                llm_tokens_generated: 5,
+                tokens_per_second: 42.5,
+                stt_provider: Some("metal"),
+                ..Timings::default()
+            },
+            prosody_hints: Some(ProsodyHints {
+                pause_hints: vec![prosody::PauseHint {
+                    after_word_index: 1,
+                    duration_ms: 450,
+                    suggested_punctuation: prosody::SuggestedPunctuation::Period,
+                    word_before: "it".to_string(),
+                    word_after: Some("ready".to_string()),
+                }],
+                pitch_contour: prosody::PitchContour::Rising,
+                confidence: 0.75,
+            }),
+            word_timestamps: vec![WordTimestamp { word: "is".to_string(), start_ms: 0, end_ms: 250, probability: 0.5 }],
+            confidence: 0.5,
+            alternatives: vec![Alternative { text: "is it read".to_string(), confidence: 0.25 }],
+            no_speech_probability: 0.25,
+            no_speech: false,
+            language: Some("en".to_string()),
+            original_transcript: None,
+            raw_llm_output: None,
+            was_fallback: false,
+            formatting_error: None,
+            prompt_truncation: None,
+            format_cache_hit: false,
+            clipped_percent: 0.0,
+            clipping: false,
+            repaired_samples: 0,
+            filtered_segments: Vec::new(),
+            scratch_previous: false,
+            segments: vec![Segment {
+                start_ms: 0,
+                end_ms: 900,
+                raw_text: "is it ready".to_string(),
+                formatted_text: "Is it ready?".to_string(),
+                confidence: 0.5,
+                speaker: None,
+            }],
+            fast_path: false,
+        };
+
+        let expected = serde_json::json!({
+            "raw_transcript": "is it ready",
+            "formatted_text": "Is it ready?",
+            "timings": {
+                "transcription_ms": 120,
+                "prosody_ms": 0,
+                "llm_formatting_ms": 0,
+                "total_ms": 300,
+                "trimmed_ms": 0,
+                "chunk_transcription_ms": [120],
+                "audio_prep_ms": 0,
+                "vad_ms": 0,
+                "stt_load_ms": 0,
+                "stt_encode_ms": 0,
+                "stt_decode_ms": 0,
+                "llm_load_ms": 0,
+                "llm_prefill_ms": 0,
+                "llm_generate_ms": 0,
+                "llm_tokens_generated": 5,
+                "llm_thinking_tokens": 0,
+                "tokens_per_second": 42.5,
+                "stt_provider": "metal",
+                "stt_temperature": 0.0,
+                "stt_temperature_fallback": false
+            },
+            "prosody_hints": {
+                "pause_hints": [{
+                    "after_word_index": 1,
+                    "duration_ms": 450,
+                    "suggested_punctuation": "period",
+                    "word_before": "it",
+                    "word_after": "ready"
+                }],
+                "pitch_contour": "rising",
+                "confidence": 0.75
+            },
+            "word_timestamps": [{ "word": "is", "start_ms": 0, "end_ms": 250, "probability": 0.5 }],
+            "confidence": 0.5,
+            "alternatives": [{ "text": "is it read", "confidence": 0.25 }],
+            "no_speech_probability": 0.25,
+            "no_speech": false,
+            "language": "en",
+            "original_transcript": null,
+            "raw_llm_output": null,
+            "was_fallback": false,
+            "formatting_error": null,
+            "prompt_truncation": null,
+            "format_cache_hit": false,
+            "clipped_percent": 0.0,
+            "clipping": false,
+            "repaired_samples": 0,
+            "filtered_segments": [],
+            "scratch_previous": false,
+            "segments": [{
+                "start_ms": 0,
+                "end_ms": 900,
+                "raw_text": "is it ready",
+                "formatted_text": "Is it ready?",
+                "confidence": 0.5,
+                "speaker": null
+            }],
+            "fast_path": false
+        });
+        assert_eq!(serde_json::to_value(&result).unwrap(), expected);
+        assert_eq!(RESULT_SCHEMA_VERSION, 1);
+    }
+
+    #[test]
+    fn test_internal_errors_lift_to_their_variant() {
+        let err = anyhow::Error::from(PipelineError::EmptyAudio).context("Preparing the audio");
+        let lifted = PipelineError::from(err);
+        assert!(matches!(&lifted, PipelineError::Context { context, .. } if context == "Preparing the audio"));
+        assert!(matches!(lifted.without_context(), PipelineError::EmptyAudio));
+
+        let err = anyhow::Error::from(ConfigError::InvalidTopP { value: 1.5 })
+            .context("Failed to load the config")
+            .context("Failed to start");
+        let lifted = PipelineError::from(err);
+        assert!(matches!(lifted.without_context(), PipelineError::Config(ConfigError::InvalidTopP { .. })));
+        assert_eq!(
+            format!("{:#}", anyhow::Error::from(lifted)),
+            "Failed to start: Failed to load the config: Invalid configuration: Invalid top_p: 1.5. Must be between 0.0 and 1.0"
+        );
+
+        let err = anyhow::Error::from(std::io::Error::other("disk full")).context("Failed to write");
+        assert!(matches!(PipelineError::from(err).without_context(), PipelineError::Io(_)));
+
+        let lifted = PipelineError::from(anyhow::anyhow!("engine exploded"));
+        assert!(matches!(lifted, PipelineError::Other(_)));
+        assert_eq!(format!("{:#}", anyhow::Error::from(lifted)), "engine exploded");
+    }
+
+    #[test]
+    fn test_stt_errors_keep_their_source() {
+        let config = Config::default();
+        let err = stt_error(&config, anyhow::Error::from(std::io::Error::other("device gone")).context("Decoding"));
+        let message = format!("{:#}", err);
+        assert_eq!(message, "Whisper transcription failed: Decoding: device gone");
+        assert!(err.chain().any(|cause| cause.is::<std::io::Error>()));
+
+        let cancelled = stt_error(&config, PipelineError::cancelled().into());
+        assert!(matches!(cancelled.downcast_ref::<PipelineError>(), Some(PipelineError::Cancelled { .. })));
+    }
+}
@@ -0,0 +1,320 @@
+//! Progress of a single request, for long recordings where a spinner
+//! isn't enough
+//!
+//! The pipeline reports each stage it reaches and its steps (chunks
+//! transcribed, tokens generated) to a `ProgressReporter` passed in
+//! `ProcessOptions::progress`. The reporter turns them into an overall
+//! fraction that never goes back, and passes at most one event per
+//! `MIN_INTERVAL` on to the sink, plus the final one.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::llm::TokenSink;
+
+/// Shortest time between two events passed to the sink
+pub const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stage of a request, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessStage {
+    /// Downmixing and resampling to 16kHz mono (only for audio in another
+    /// format)
+    Resampling,
+    /// Finding the speech to transcribe (voice activity detection)
+    DetectingSpeech,
+    /// Transcribing, one chunk at a time
+    Transcribing,
+    /// Formatting with the LLM, one token at a time
+    Formatting,
+    /// The result is ready
+    Done,
+}
+
+impl ProcessStage {
+    /// Overall fractions the stage runs between
+    fn span(self) -> (f32, f32) {
+        match self {
+            Self::Resampling => (0.0, 0.05),
+            Self::DetectingSpeech => (0.05, 0.1),
+            Self::Transcribing => (0.1, 0.7),
+            Self::Formatting => (0.7, 1.0),
+            Self::Done => (1.0, 1.0),
+        }
+    }
+}
+
+/// A progress event
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ProcessProgress {
+    pub stage: ProcessStage,
+    /// Overall progress of the request, 0 to 1; never lower than in the
+    /// event before
+    pub fraction: f32,
+    /// Steps of the stage done: chunks transcribed, or tokens generated
+    pub done: u32,
+    /// Steps of the stage: chunks to transcribe, or the estimated most
+    /// tokens the LLM generates (0 when the stage has no steps)
+    pub total: u32,
+}
+
+/// Receives the progress of a request (see `ProcessOptions::progress`)
+///
+/// Called on the thread running the request, so it should return quickly.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&self, progress: ProcessProgress);
+}
+
+impl<F: Fn(ProcessProgress) + Send + Sync> ProgressSink for F {
+    fn progress(&self, progress: ProcessProgress) {
+        self(progress)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReporterState {
+    fraction: f32,
+    last_event: Option<Instant>,
+    done: bool,
+}
+
+/// Passes the progress of a request to a `ProgressSink`, throttled to one
+/// event per `MIN_INTERVAL`
+///
+/// Progress never goes back, so use a new reporter for each request. Clones
+/// share the same state.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    sink: Arc<dyn ProgressSink>,
+    state: Arc<Mutex<ReporterState>>,
+}
+
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReporter").field("state", &self.state).finish_non_exhaustive()
+    }
+}
+
+impl ProgressReporter {
+    pub fn new(sink: impl ProgressSink + 'static) -> Self {
+        Self { sink: Arc::new(sink), state: Arc::default() }
+    }
+
+    /// Report `done` of `total` steps of `stage`
+    ///
+    /// Dropped if the last event was less than `MIN_INTERVAL` ago, unless
+    /// it's `Done`, which is passed on once.
+    pub(crate) fn report(&self, stage: ProcessStage, done: u32, total: u32) {
+        let (start, end) = stage.span();
+        let steps = if total == 0 { 0.0 } else { (done as f32 / total as f32).min(1.0) };
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.done {
+            return;
+        }
+        state.fraction = state.fraction.max(start + (end - start) * steps);
+        if stage == ProcessStage::Done {
+            state.done = true;
+        } else if state.last_event.is_some_and(|last| now.duration_since(last) < MIN_INTERVAL) {
+            return;
+        }
+        state.last_event = Some(now);
+        let progress = ProcessProgress { stage, fraction: state.fraction, done, total };
+        drop(state);
+        self.sink.progress(progress);
+    }
+}
+
+/// Receives the raw transcript of a request once transcription is done,
+/// before the LLM formats it (see `ProcessOptions::on_raw`)
+///
+/// Called on the thread running the request, which formats the text only
+/// after this returns. Cancel the request's token here to skip formatting.
+pub trait RawTranscriptSink: Send + Sync {
+    fn raw_transcript(&self, text: &str);
+}
+
+impl<F: Fn(&str) + Send + Sync> RawTranscriptSink for F {
+    fn raw_transcript(&self, text: &str) {
+        self(text)
+    }
+}
+
+/// Passes the raw transcript of a request to a `RawTranscriptSink`
+///
+/// Clones share the same sink.
+#[derive(Clone)]
+pub struct RawTranscriptHook(Arc<dyn RawTranscriptSink>);
+
+impl fmt::Debug for RawTranscriptHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawTranscriptHook").finish_non_exhaustive()
+    }
+}
+
+impl RawTranscriptHook {
+    pub fn new(sink: impl RawTranscriptSink + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    pub(crate) fn send(&self, text: &str) {
+        self.0.raw_transcript(text);
+    }
+}
+
+/// Report on `progress`, if there is a reporter
+pub(crate) fn report(progress: Option<&ProgressReporter>, stage: ProcessStage, done: u32, total: u32) {
+    if let Some(progress) = progress {
+        progress.report(stage, done, total);
+    }
+}
+
+/// Tokens a segment's formatted text is expected to take at most: about
+/// two per word of the transcript, within `max_tokens`
+fn estimated_tokens(transcript: &str, max_tokens: u32) -> u32 {
+    (transcript.split_whitespace().count() as u32 * 2 + 16).min(max_tokens).max(1)
+}
+
+/// Formatting progress of a request's segments, counted in tokens
+pub(crate) struct FormatProgress<'a> {
+    reporter: &'a ProgressReporter,
+    /// `estimated_tokens` of each segment
+    estimates: Vec<u32>,
+    /// Segments formatted
+    segments: usize,
+    tokens: u32,
+}
+
+impl<'a> FormatProgress<'a> {
+    pub(crate) fn new<'t>(
+        reporter: &'a ProgressReporter,
+        segments: impl Iterator<Item = &'t str>,
+        max_tokens: u32,
+    ) -> Self {
+        let estimates = segments.map(|segment| estimated_tokens(segment, max_tokens)).collect();
+        let progress = Self { reporter, estimates, segments: 0, tokens: 0 };
+        progress.report();
+        progress
+    }
+
+    fn total(&self) -> u32 {
+        self.estimates.iter().sum()
+    }
+
+    fn report(&self) {
+        self.reporter.report(ProcessStage::Formatting, self.tokens, self.total());
+    }
+
+    /// Count a token of the segment being formatted, without getting ahead
+    /// of its estimate
+    fn token(&mut self) {
+        let segment_end: u32 = self.estimates[..(self.segments + 1).min(self.estimates.len())].iter().sum();
+        if self.tokens + 1 < segment_end {
+            self.tokens += 1;
+            self.report();
+        }
+    }
+
+    /// The segment being formatted is done, whether or not the LLM ran
+    pub(crate) fn segment_done(&mut self) {
+        self.segments = (self.segments + 1).min(self.estimates.len());
+        self.tokens = self.tokens.max(self.estimates[..self.segments].iter().sum());
+        self.report();
+    }
+
+    /// A sink counting the tokens passed on to `sink`
+    pub(crate) fn sink<'s>(&'s mut self, sink: Option<&'s mut dyn TokenSink>) -> ProgressTokens<'s, 'a> {
+        ProgressTokens { sink, progress: self }
+    }
+}
+
+/// Passes the LLM output on to a sink, counting the tokens as formatting
+/// progress
+pub(crate) struct ProgressTokens<'s, 'a> {
+    sink: Option<&'s mut dyn TokenSink>,
+    progress: &'s mut FormatProgress<'a>,
+}
+
+impl TokenSink for ProgressTokens<'_, '_> {
+    fn token(&mut self, text: &str) {
+        if let Some(sink) = self.sink.as_deref_mut() {
+            sink.token(text);
+        }
+        self.progress.token();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorder() -> (ProgressReporter, Arc<Mutex<Vec<ProcessProgress>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let reporter = ProgressReporter::new(move |progress| recorded.lock().unwrap().push(progress));
+        (reporter, events)
+    }
+
+    #[test]
+    fn test_events_are_throttled() {
+        let (reporter, events) = recorder();
+        reporter.report(ProcessStage::DetectingSpeech, 0, 0);
+        for chunk in 1..=50 {
+            reporter.report(ProcessStage::Transcribing, chunk, 50);
+        }
+        assert_eq!(events.lock().unwrap().len(), 1);
+
+        std::thread::sleep(MIN_INTERVAL);
+        reporter.report(ProcessStage::Transcribing, 50, 50);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[1].stage, events[1].done, events[1].total), (ProcessStage::Transcribing, 50, 50));
+        assert!((events[1].fraction - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fraction_never_goes_back() {
+        let (reporter, events) = recorder();
+        reporter.report(ProcessStage::Transcribing, 4, 4);
+        std::thread::sleep(MIN_INTERVAL);
+        // A second pass over the chunks, e.g. for the original transcript
+        reporter.report(ProcessStage::Transcribing, 1, 4);
+        let events = events.lock().unwrap();
+        assert_eq!(events[1].fraction, events[0].fraction);
+    }
+
+    #[test]
+    fn test_done_is_reported_once() {
+        let (reporter, events) = recorder();
+        reporter.report(ProcessStage::Transcribing, 0, 0);
+        reporter.report(ProcessStage::Done, 0, 0);
+        reporter.report(ProcessStage::Done, 0, 0);
+        reporter.report(ProcessStage::Formatting, 1, 2);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[1].stage, events[1].fraction), (ProcessStage::Done, 1.0));
+    }
+
+    #[test]
+    fn test_tokens_stay_within_the_segment_estimate() {
+        let (reporter, _) = recorder();
+        let mut progress = FormatProgress::new(&reporter, ["one two", "three"].into_iter(), 512);
+        assert_eq!(progress.estimates, [20, 18]);
+        let mut streamed = String::new();
+        let mut forward = |text: &str| streamed.push_str(text);
+        let mut sink = progress.sink(Some(&mut forward));
+        for _ in 0..100 {
+            sink.token("a");
+        }
+        assert_eq!(progress.tokens, 19);
+        progress.segment_done();
+        assert_eq!(progress.tokens, 20);
+        progress.segment_done();
+        assert_eq!((progress.tokens, progress.total()), (38, 38));
+        assert_eq!(streamed.len(), 100);
+    }
+}
@@ -1,7 +1,7 @@
 //! Prosody analysis for punctuation detection
 //!
 //! This module provides four methods for detecting punctuation from speech:
-//! 1. Voice commands - explicit spoken punctuation ("period", "comma", etc.)
+//! 1. Voice commands - explicit spoken punctuation and edits ("period", "scratch that")
 //! 2. Pause analysis - detect pauses between words to infer punctuation
 //! 3. Pitch contour - detect rising/falling pitch for questions vs statements
 //! 4. Combined hints - pass prosodic hints to the LLM for better decisions
@@ -12,14 +12,16 @@ mod pitch_analysis;
 mod spelled_words;
 mod replacements;
 
-pub use voice_commands::replace_voice_commands;
-pub use pause_analysis::{PauseHint, analyze_pauses};
+pub use voice_commands::{apply_voice_commands, replace_voice_commands, VoiceCommandOutput, VOICE_COMMAND_LANGUAGES};
+pub use pause_analysis::{PauseHint, SuggestedPunctuation, analyze_pauses};
 pub use pitch_analysis::{PitchContour, analyze_pitch_contour};
 pub use spelled_words::{concatenate_spelled_words, concatenate_spelled_words_aggressive};
 pub use replacements::ReplacementDictionary;
 
+use serde::Serialize;
+
 /// Combined prosody analysis result
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ProsodyHints {
     /// Detected pauses with their suggested punctuation
     pub pause_hints: Vec<PauseHint>,
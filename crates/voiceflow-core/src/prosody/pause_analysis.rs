@@ -3,6 +3,8 @@
 //! Analyzes gaps between words to infer punctuation.
 //! Uses word-level timestamps from Whisper.
 
+use serde::Serialize;
+
 /// Threshold for a comma-level pause (milliseconds)
 const COMMA_PAUSE_MS: i64 = 150;
 
@@ -13,7 +15,7 @@ const PERIOD_PAUSE_MS: i64 = 400;
 const PARAGRAPH_PAUSE_MS: i64 = 1000;
 
 /// A hint about punctuation based on pause duration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PauseHint {
     /// Word index after which the pause occurs
     pub after_word_index: usize,
@@ -28,7 +30,8 @@ pub struct PauseHint {
 }
 
 /// Suggested punctuation based on prosodic analysis
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum SuggestedPunctuation {
     /// No punctuation needed
     None,
@@ -7,6 +7,7 @@
 
 use pitch_detection::detector::mcleod::McLeodDetector;
 use pitch_detection::detector::PitchDetector;
+use serde::Serialize;
 
 /// Sample rate for analysis (must match input audio)
 const SAMPLE_RATE: usize = 16000;
@@ -26,7 +27,8 @@ const MIN_PITCH_HZ: f32 = 50.0;
 const MAX_PITCH_HZ: f32 = 500.0;
 
 /// Pitch contour classification
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PitchContour {
     /// Pitch rises at the end (typical for questions)
     Rising,
@@ -2,7 +2,6 @@
 //!
 //! Loads replacements from a TOML file and applies them to transcriptions.
 
-use std::collections::HashMap;
 use std::path::Path;
 
 /// A dictionary of text replacements
@@ -113,12 +112,15 @@ impl ReplacementDictionary {
     }
 
     /// Apply all replacements to the given text
+    ///
+    /// A pattern only matches whole words, so "R S" leaves "Dear Sam"
+    /// alone. Spelled-out letters ("G P T") match as written; other
+    /// patterns ignore case.
     pub fn apply(&self, text: &str) -> String {
         let mut result = text.to_string();
 
         for (pattern, replacement) in &self.replacements {
-            // Case-insensitive replacement
-            result = case_insensitive_replace_all(&result, pattern, replacement);
+            result = replace_standalone(&result, pattern, replacement, is_spelled(pattern));
         }
 
         result
@@ -135,20 +137,38 @@ impl ReplacementDictionary {
     }
 }
 
-/// Case-insensitive replace all occurrences
-fn case_insensitive_replace_all(text: &str, pattern: &str, replacement: &str) -> String {
-    let lower_text = text.to_lowercase();
-    let lower_pattern = pattern.to_lowercase();
+/// Whether `pattern` is letters spelled out one at a time, e.g. "G P T"
+fn is_spelled(pattern: &str) -> bool {
+    pattern.split(' ').all(|token| token.chars().count() == 1 && token.chars().all(char::is_alphabetic))
+}
+
+/// Replace every occurrence of `pattern` in `text` that isn't part of a
+/// longer word
+fn replace_standalone(text: &str, pattern: &str, replacement: &str, case_sensitive: bool) -> String {
+    let is_word = |c: char| c.is_alphanumeric();
+    let matches_at = |start: usize| {
+        text.get(start..start + pattern.len()).is_some_and(|candidate| {
+            if case_sensitive {
+                candidate == pattern
+            } else {
+                candidate.eq_ignore_ascii_case(pattern)
+            }
+        })
+    };
 
     let mut result = String::with_capacity(text.len());
     let mut last_end = 0;
-
-    for (start, _) in lower_text.match_indices(&lower_pattern) {
-        // Add text before this match
-        result.push_str(&text[last_end..start]);
-        // Add replacement
-        result.push_str(replacement);
-        last_end = start + pattern.len();
+    let mut start = 0;
+    while start < text.len() {
+        let end = start + pattern.len();
+        if matches_at(start) && !text[..start].ends_with(is_word) && !text[end..].starts_with(is_word) {
+            result.push_str(&text[last_end..start]);
+            result.push_str(replacement);
+            last_end = end;
+            start = end;
+        } else {
+            start += text[start..].chars().next().map_or(1, char::len_utf8);
+        }
     }
 
     // Add remaining text
@@ -174,8 +194,19 @@ mod tests {
     fn test_case_insensitive() {
         let dict = ReplacementDictionary::builtin();
 
-        let result = dict.apply("using g p t");
-        assert_eq!(result, "using GPT");
+        let result = dict.apply("using Whisper c p p");
+        assert_eq!(result, "using whisper.cpp");
+    }
+
+    #[test]
+    fn test_only_whole_words_match() {
+        let dict = ReplacementDictionary::builtin();
+
+        assert_eq!(dict.apply("Dear Sam, the R S file"), "Dear Sam, the .rs file");
+        assert_eq!(dict.apply("Tom L L Mason"), "Tom L L Mason");
+        // Lowercase letters are more likely words than spelling
+        assert_eq!(dict.apply("a i can see"), "a i can see");
+        assert_eq!(dict.apply("an A I model"), "an AI model");
     }
 
     #[test]
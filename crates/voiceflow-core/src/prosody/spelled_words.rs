@@ -23,7 +23,12 @@ pub struct SpelledWordMatch {
 /// - "The S M O L L M model" → "The SMOLLM model"
 /// - "whisper C P P" → "whisper CPP"
 /// - "S M O L L M - 3 B" → "SMOLLM-3B"
+///
+/// Line breaks are kept; sequences never span them.
 pub fn concatenate_spelled_words(text: &str) -> String {
+    if text.contains('\n') {
+        return text.split('\n').map(concatenate_spelled_words).collect::<Vec<_>>().join("\n");
+    }
     let tokens: Vec<&str> = text.split_whitespace().collect();
 
     if tokens.len() < 2 {
@@ -276,6 +281,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_line_breaks_are_kept() {
+        assert_eq!(
+            concatenate_spelled_words("Dear Sam,\n\nThe S M O L L M model"),
+            "Dear Sam,\n\nThe SMOLLM model"
+        );
+    }
+
     #[test]
     fn test_silicon_fix() {
         assert_eq!(
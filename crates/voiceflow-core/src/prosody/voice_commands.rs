@@ -1,220 +1,432 @@
-//! Voice command detection for explicit punctuation
+//! Voice commands: spoken punctuation and editing commands
 //!
-//! Detects spoken punctuation commands like "period", "comma", "question mark"
-//! and replaces them with the actual punctuation characters.
+//! Detects spoken commands like "period", "new paragraph" or "open quote"
+//! and replaces them with the actual characters, and applies editing
+//! commands like "scratch that". Saying the escape word first ("literally
+//! comma") keeps the words themselves.
 
+use crate::config::VoiceCommands;
 use std::collections::HashMap;
 
-/// Replace spoken punctuation commands with actual punctuation
+/// Languages with built-in commands
+pub const VOICE_COMMAND_LANGUAGES: &[&str] = &["en", "de", "fr", "es"];
+
+/// What a spoken command does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action<'a> {
+    /// Insert text, spaced according to the kind of mark it is
+    Insert(&'a str),
+    OpenQuote,
+    CloseQuote,
+    /// Open or close a quote, whichever is due
+    Quote,
+    /// Remove the last sentence, or the previous dictation when nothing
+    /// came before
+    ScratchThat,
+}
+
+use Action::*;
+
+/// Built-in commands of a language
+struct CommandSet {
+    language: &'static str,
+    /// Word said before a command to dictate the command's words instead
+    escape: &'static str,
+    commands: &'static [(&'static str, Action<'static>)],
+}
+
+const COMMAND_SETS: &[CommandSet] = &[
+    CommandSet {
+        language: "en",
+        escape: "literally",
+        commands: &[
+            ("period", Insert(".")),
+            ("full stop", Insert(".")),
+            ("end sentence", Insert(".")),
+            ("comma", Insert(",")),
+            ("question mark", Insert("?")),
+            ("exclamation mark", Insert("!")),
+            ("exclamation point", Insert("!")),
+            ("exclamation", Insert("!")),
+            ("bang", Insert("!")),
+            ("colon", Insert(":")),
+            ("semicolon", Insert(";")),
+            ("semi colon", Insert(";")),
+            ("open quote", OpenQuote),
+            ("close quote", CloseQuote),
+            ("end quote", CloseQuote),
+            ("quote", Quote),
+            ("open single quote", Insert("‘")),
+            ("close single quote", Insert("’")),
+            ("apostrophe", Insert("'")),
+            ("open paren", Insert("(")),
+            ("close paren", Insert(")")),
+            ("open parenthesis", Insert("(")),
+            ("close parenthesis", Insert(")")),
+            ("left paren", Insert("(")),
+            ("right paren", Insert(")")),
+            ("open bracket", Insert("[")),
+            ("close bracket", Insert("]")),
+            ("open brace", Insert("{")),
+            ("close brace", Insert("}")),
+            ("dash", Insert("—")),
+            ("em dash", Insert("—")),
+            ("en dash", Insert("–")),
+            ("hyphen", Insert("-")),
+            ("new line", Insert("\n")),
+            ("newline", Insert("\n")),
+            ("line break", Insert("\n")),
+            ("new paragraph", Insert("\n\n")),
+            ("next paragraph", Insert("\n\n")),
+            ("ellipsis", Insert("...")),
+            ("dot dot dot", Insert("...")),
+            ("ampersand", Insert("&")),
+            ("at sign", Insert("@")),
+            ("at symbol", Insert("@")),
+            ("hashtag", Insert("#")),
+            ("hash", Insert("#")),
+            ("dollar sign", Insert("$")),
+            ("percent", Insert("%")),
+            ("percent sign", Insert("%")),
+            ("asterisk", Insert("*")),
+            ("star", Insert("*")),
+            ("underscore", Insert("_")),
+            ("slash", Insert("/")),
+            ("forward slash", Insert("/")),
+            ("backslash", Insert("\\")),
+            ("back slash", Insert("\\")),
+            ("equals", Insert("=")),
+            ("plus", Insert("+")),
+            ("minus", Insert("-")),
+            ("greater than", Insert(">")),
+            ("less than", Insert("<")),
+            ("pipe", Insert("|")),
+            ("tilde", Insert("~")),
+            ("caret", Insert("^")),
+            ("scratch that", ScratchThat),
+            ("delete that", ScratchThat),
+            ("strike that", ScratchThat),
+        ],
+    },
+    CommandSet {
+        language: "de",
+        escape: "wörtlich",
+        commands: &[
+            ("punkt", Insert(".")),
+            ("komma", Insert(",")),
+            ("fragezeichen", Insert("?")),
+            ("ausrufezeichen", Insert("!")),
+            ("doppelpunkt", Insert(":")),
+            ("semikolon", Insert(";")),
+            ("strichpunkt", Insert(";")),
+            ("anführungszeichen auf", Insert("„")),
+            ("anführungszeichen unten", Insert("„")),
+            ("anführungszeichen zu", Insert("“")),
+            ("anführungszeichen oben", Insert("“")),
+            ("klammer auf", Insert("(")),
+            ("klammer zu", Insert(")")),
+            ("bindestrich", Insert("-")),
+            ("gedankenstrich", Insert("–")),
+            ("schrägstrich", Insert("/")),
+            ("neue zeile", Insert("\n")),
+            ("neuer absatz", Insert("\n\n")),
+            ("lösch das", ScratchThat),
+            ("streich das", ScratchThat),
+        ],
+    },
+    CommandSet {
+        language: "fr",
+        escape: "littéralement",
+        commands: &[
+            ("point", Insert(".")),
+            ("virgule", Insert(",")),
+            ("point d'interrogation", Insert("?")),
+            ("point d'exclamation", Insert("!")),
+            ("deux points", Insert(":")),
+            ("point virgule", Insert(";")),
+            ("ouvrez les guillemets", Insert("«")),
+            ("fermez les guillemets", Insert("»")),
+            ("ouvrez la parenthèse", Insert("(")),
+            ("fermez la parenthèse", Insert(")")),
+            ("trait d'union", Insert("-")),
+            ("à la ligne", Insert("\n")),
+            ("nouvelle ligne", Insert("\n")),
+            ("nouveau paragraphe", Insert("\n\n")),
+            ("efface ça", ScratchThat),
+            ("annule ça", ScratchThat),
+        ],
+    },
+    CommandSet {
+        language: "es",
+        escape: "literalmente",
+        commands: &[
+            ("punto", Insert(".")),
+            ("coma", Insert(",")),
+            ("signo de interrogación", Insert("?")),
+            ("signo de exclamación", Insert("!")),
+            ("dos puntos", Insert(":")),
+            ("punto y coma", Insert(";")),
+            ("abrir comillas", OpenQuote),
+            ("cerrar comillas", CloseQuote),
+            ("abrir paréntesis", Insert("(")),
+            ("cerrar paréntesis", Insert(")")),
+            ("guion", Insert("-")),
+            ("nueva línea", Insert("\n")),
+            ("nuevo párrafo", Insert("\n\n")),
+            ("borra eso", ScratchThat),
+        ],
+    },
+];
+
+/// Transcript with its voice commands applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceCommandOutput {
+    pub text: String,
+    /// "Scratch that" opened the transcript: the previous dictation should
+    /// be removed
+    pub scratch_previous: bool,
+}
+
+/// Replace spoken punctuation commands with actual punctuation, using the
+/// built-in English commands
 ///
 /// Handles various forms like "period", "full stop", "comma", etc.
 /// Also handles "new line", "new paragraph" for formatting.
 pub fn replace_voice_commands(text: &str) -> String {
-    let mut result = text.to_string();
+    apply_voice_commands(text, &VoiceCommands::default(), "en").text
+}
 
-    // Build replacement map with all variations
-    let replacements = build_replacement_map();
+/// Apply the built-in commands of `language` and the custom ones in
+/// `commands` to a transcript
+///
+/// Languages without built-in commands only get the custom ones.
+pub fn apply_voice_commands(text: &str, commands: &VoiceCommands, language: &str) -> VoiceCommandOutput {
+    Interpreter::new(commands, language).apply(text)
+}
 
-    // Sort by length (longest first) to avoid partial replacements
-    let mut sorted_keys: Vec<_> = replacements.keys().collect();
-    sorted_keys.sort_by(|a, b| b.len().cmp(&a.len()));
+/// Commands by their lowercase words, joined by single spaces
+struct Interpreter<'a> {
+    commands: HashMap<String, Action<'a>>,
+    escape: Option<&'static str>,
+    /// Most words in a command
+    longest: usize,
+}
 
-    // Apply replacements (case-insensitive)
-    for key in sorted_keys {
-        let replacement = replacements.get(*key).unwrap();
-        result = replace_case_insensitive(&result, key, replacement);
+impl<'a> Interpreter<'a> {
+    fn new(config: &'a VoiceCommands, language: &str) -> Self {
+        let set = COMMAND_SETS.iter().find(|set| set.language == language);
+        let mut commands: HashMap<String, Action<'a>> = set
+            .map(|set| set.commands.iter().map(|(phrase, action)| (phrase.to_string(), *action)).collect())
+            .unwrap_or_default();
+        for phrase in &config.disabled {
+            commands.remove(&phrase_key(phrase));
+        }
+        for (phrase, text) in &config.custom {
+            commands.insert(phrase_key(phrase), Insert(text));
+        }
+        let longest = commands.keys().map(|phrase| phrase.split(' ').count()).max().unwrap_or(0);
+        Self { commands, escape: set.map(|set| set.escape), longest }
     }
 
-    // Clean up any double spaces created by replacements
-    while result.contains("  ") {
-        result = result.replace("  ", " ");
+    fn apply(&self, text: &str) -> VoiceCommandOutput {
+        let words: Vec<Word> = text.split_whitespace().map(Word::new).collect();
+        let mut out = Output::default();
+        let mut i = 0;
+        while i < words.len() {
+            let escaped = self.escape.is_some_and(|escape| words[i].key == escape && words[i].trail.is_empty());
+            if escaped {
+                if let Some((len, _)) = self.command_at(&words[i + 1..]) {
+                    for word in &words[i + 1..i + 1 + len] {
+                        out.push_word(word.text);
+                    }
+                    i += 1 + len;
+                    continue;
+                }
+            }
+            match self.command_at(&words[i..]) {
+                Some((len, action)) => {
+                    out.apply(action);
+                    i += len;
+                }
+                None => {
+                    out.push_word(words[i].text);
+                    i += 1;
+                }
+            }
+        }
+        VoiceCommandOutput { text: out.text.trim().to_string(), scratch_previous: out.scratch_previous }
     }
 
-    // Clean up space before punctuation
-    result = cleanup_punctuation_spacing(&result);
+    /// The longest command the words start with, and its number of words
+    ///
+    /// Punctuation the STT engine put around a command is ignored, but not
+    /// inside one: "question, mark" is no command.
+    fn command_at(&self, words: &[Word]) -> Option<(usize, Action<'a>)> {
+        (1..=self.longest.min(words.len())).rev().find_map(|len| {
+            let phrase = &words[..len];
+            let joined = phrase[..len - 1].iter().all(|word| word.trail.is_empty())
+                && phrase[1..].iter().all(|word| word.lead.is_empty());
+            if !joined {
+                return None;
+            }
+            let key = phrase.iter().map(|word| word.key.as_str()).collect::<Vec<_>>().join(" ");
+            self.commands.get(&key).map(|action| (len, *action))
+        })
+    }
+}
 
-    result
+/// A whitespace-separated word of the transcript
+struct Word<'t> {
+    text: &'t str,
+    /// Punctuation before and after the letters
+    lead: &'t str,
+    trail: &'t str,
+    /// The letters, lowercased, for matching commands
+    key: String,
 }
 
-/// Build the map of voice commands to punctuation
-fn build_replacement_map() -> HashMap<&'static str, &'static str> {
-    let mut map = HashMap::new();
-
-    // Period / Full stop
-    map.insert(" period", ".");
-    map.insert(" full stop", ".");
-    map.insert(" dot", ".");
-    map.insert(" end sentence", ".");
-
-    // Comma
-    map.insert(" comma", ",");
-
-    // Question mark
-    map.insert(" question mark", "?");
-    map.insert(" question", "?"); // Be careful - might be part of actual word
-
-    // Exclamation
-    map.insert(" exclamation mark", "!");
-    map.insert(" exclamation point", "!");
-    map.insert(" exclamation", "!");
-    map.insert(" bang", "!");
-
-    // Colon and semicolon
-    map.insert(" colon", ":");
-    map.insert(" semicolon", ";");
-    map.insert(" semi colon", ";");
-
-    // Quotes
-    map.insert(" open quote", "\"");
-    map.insert(" close quote", "\"");
-    map.insert(" quote", "\"");
-    map.insert(" open single quote", "'");
-    map.insert(" close single quote", "'");
-    map.insert(" apostrophe", "'");
-
-    // Parentheses and brackets
-    map.insert(" open paren", "(");
-    map.insert(" close paren", ")");
-    map.insert(" open parenthesis", "(");
-    map.insert(" close parenthesis", ")");
-    map.insert(" left paren", "(");
-    map.insert(" right paren", ")");
-    map.insert(" open bracket", "[");
-    map.insert(" close bracket", "]");
-    map.insert(" open brace", "{");
-    map.insert(" close brace", "}");
-
-    // Dashes and hyphens
-    map.insert(" dash", "—");
-    map.insert(" em dash", "—");
-    map.insert(" en dash", "–");
-    map.insert(" hyphen", "-");
-
-    // New lines and paragraphs
-    map.insert(" new line", "\n");
-    map.insert(" newline", "\n");
-    map.insert(" line break", "\n");
-    map.insert(" new paragraph", "\n\n");
-    map.insert(" paragraph", "\n\n");
-    map.insert(" next paragraph", "\n\n");
-
-    // Ellipsis
-    map.insert(" ellipsis", "...");
-    map.insert(" dot dot dot", "...");
-
-    // Special characters
-    map.insert(" ampersand", "&");
-    map.insert(" at sign", "@");
-    map.insert(" at symbol", "@");
-    map.insert(" hashtag", "#");
-    map.insert(" hash", "#");
-    map.insert(" dollar sign", "$");
-    map.insert(" percent", "%");
-    map.insert(" percent sign", "%");
-    map.insert(" asterisk", "*");
-    map.insert(" star", "*");
-    map.insert(" underscore", "_");
-    map.insert(" slash", "/");
-    map.insert(" forward slash", "/");
-    map.insert(" backslash", "\\");
-    map.insert(" back slash", "\\");
-
-    // Programming-specific
-    map.insert(" equals", "=");
-    map.insert(" plus", "+");
-    map.insert(" minus", "-");
-    map.insert(" greater than", ">");
-    map.insert(" less than", "<");
-    map.insert(" pipe", "|");
-    map.insert(" tilde", "~");
-    map.insert(" caret", "^");
-
-    map
+impl<'t> Word<'t> {
+    fn new(text: &'t str) -> Self {
+        let start = text.find(char::is_alphanumeric).unwrap_or(text.len());
+        let end = text
+            .char_indices()
+            .rfind(|(_, c)| c.is_alphanumeric())
+            .map_or(start, |(i, c)| i + c.len_utf8());
+        Self { text, lead: &text[..start], trail: &text[end..], key: phrase_key(&text[start..end]) }
+    }
 }
 
-/// Case-insensitive replacement
-fn replace_case_insensitive(text: &str, pattern: &str, replacement: &str) -> String {
-    let lower_text = text.to_lowercase();
-    let lower_pattern = pattern.to_lowercase();
+/// Lowercase words joined by single spaces, with typographic apostrophes
+/// made straight
+fn phrase_key(phrase: &str) -> String {
+    phrase.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase().replace('’', "'")
+}
 
-    let mut result = String::with_capacity(text.len());
-    let mut last_end = 0;
+/// How an inserted mark is spaced from the words around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Spacing {
+    /// Follows the previous word: "."
+    Left,
+    /// Precedes the next word: "("
+    Right,
+    /// Joins both: "/", new lines
+    Both,
+    /// Spaced like a word: "&"
+    Spaced,
+}
 
-    for (start, _) in lower_text.match_indices(&lower_pattern) {
-        // Add text before this match
-        result.push_str(&text[last_end..start]);
-        // Add replacement
-        result.push_str(replacement);
-        last_end = start + pattern.len();
+fn spacing(mark: &str) -> Spacing {
+    let all = |set: &str| mark.chars().all(|c| set.contains(c));
+    let single = mark.chars().count() == 1;
+    if mark.contains('\n') {
+        Spacing::Both
+    } else if (single && all(",;:)]}%’“»")) || all(".?!…") {
+        Spacing::Left
+    } else if single && all("([{#$‘„«") {
+        Spacing::Right
+    } else if single && all("-—–_/\\'@") {
+        Spacing::Both
+    } else {
+        Spacing::Spaced
     }
+}
 
-    // Add remaining text
-    result.push_str(&text[last_end..]);
-
-    result
+/// Text built word by word
+#[derive(Debug, Default)]
+struct Output {
+    text: String,
+    /// The next word follows the last mark without a space
+    attach_next: bool,
+    capitalize_next: bool,
+    quote_open: bool,
+    scratch_previous: bool,
 }
 
-/// Clean up spacing around punctuation
-fn cleanup_punctuation_spacing(text: &str) -> String {
-    let mut result = text.to_string();
+impl Output {
+    fn push_word(&mut self, word: &str) {
+        self.separate();
+        if std::mem::take(&mut self.capitalize_next) {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                self.text.extend(first.to_uppercase());
+                self.text.push_str(chars.as_str());
+                return;
+            }
+        }
+        self.text.push_str(word);
+    }
 
-    // Remove space before punctuation
-    let punctuation = ['.', ',', '?', '!', ':', ';', ')', ']', '}', '"', '\'', '—', '–', '-'];
-    for p in punctuation {
-        result = result.replace(&format!(" {}", p), &p.to_string());
+    /// Space before a word, unless it starts the text or a line, or follows
+    /// a mark it attaches to
+    fn separate(&mut self) {
+        let attached = std::mem::take(&mut self.attach_next);
+        if !attached && !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
     }
 
-    // Remove space after newlines (but keep the newline)
-    while result.contains("\n ") {
-        result = result.replace("\n ", "\n");
+    fn apply(&mut self, action: Action) {
+        match action {
+            Insert(mark) => self.insert(mark),
+            OpenQuote => self.quote(true),
+            CloseQuote => self.quote(false),
+            Quote => self.quote(!self.quote_open),
+            ScratchThat => self.scratch(),
+        }
     }
 
-    // Ensure space after sentence-ending punctuation (if followed by letter)
-    let chars: Vec<char> = result.chars().collect();
-    let mut final_result = String::with_capacity(result.len());
+    fn quote(&mut self, open: bool) {
+        self.quote_open = open;
+        let spacing = if open { Spacing::Right } else { Spacing::Left };
+        self.insert_spaced("\"", spacing);
+    }
 
-    for i in 0..chars.len() {
-        final_result.push(chars[i]);
+    fn insert(&mut self, mark: &str) {
+        self.insert_spaced(mark, spacing(mark));
+    }
 
-        // After sentence-ending punctuation, ensure space before next letter
-        if matches!(chars[i], '.' | '?' | '!' | ':') {
-            if let Some(&next) = chars.get(i + 1) {
-                if next.is_alphabetic() {
-                    final_result.push(' ');
+    fn insert_spaced(&mut self, mark: &str, spacing: Spacing) {
+        match spacing {
+            Spacing::Left | Spacing::Both => {
+                self.text.truncate(self.text.trim_end_matches(' ').len());
+                // The STT engine often punctuates around the command too:
+                // "Hello, period."
+                if mark.chars().all(|c| ".,;:?!".contains(c)) {
+                    self.text.truncate(self.text.trim_end_matches(|c| ".,;:?!".contains(c)).len());
                 }
+                self.text.push_str(mark);
+                self.attach_next = spacing == Spacing::Both;
+            }
+            Spacing::Right | Spacing::Spaced => {
+                self.separate();
+                self.text.push_str(mark);
+                self.attach_next = spacing == Spacing::Right;
             }
         }
-    }
-
-    // Capitalize after sentence-ending punctuation
-    capitalize_after_punctuation(&final_result)
-}
-
-/// Capitalize the first letter after sentence-ending punctuation
-fn capitalize_after_punctuation(text: &str) -> String {
-    let mut result = String::with_capacity(text.len());
-    let mut capitalize_next = true;
-
-    for c in text.chars() {
-        if capitalize_next && c.is_alphabetic() {
-            result.push(c.to_uppercase().next().unwrap_or(c));
-            capitalize_next = false;
-        } else {
-            result.push(c);
+        if mark.ends_with(['.', '?', '!', '\n']) {
+            self.capitalize_next = true;
         }
+    }
 
-        // Capitalize after sentence endings
-        if matches!(c, '.' | '?' | '!' | '\n') {
-            capitalize_next = true;
+    /// Remove the last sentence, finished or not
+    fn scratch(&mut self) {
+        let body = self.text.trim_end_matches(|c: char| c.is_whitespace() || ".?!".contains(c));
+        if body.is_empty() {
+            self.scratch_previous = true;
+            self.text.clear();
+            return;
         }
+        let kept = body.rfind(['.', '?', '!', '\n']).map_or(0, |i| i + 1);
+        self.text.truncate(kept);
+        self.attach_next = false;
+        self.capitalize_next = true;
     }
-
-    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_basic_punctuation() {
@@ -255,4 +467,63 @@ mod tests {
             "Wow! That's amazing"
         );
     }
+
+    #[test]
+    fn test_whole_words_only() {
+        assert_eq!(replace_voice_commands("I have a question about periods"), "I have a question about periods");
+        assert_eq!(replace_voice_commands("Hello, period. How are you, question mark?"), "Hello. How are you?");
+    }
+
+    #[test]
+    fn test_quotes_brackets_and_paragraphs() {
+        assert_eq!(
+            replace_voice_commands("She said quote hi quote and left new paragraph the end"),
+            "She said \"hi\" and left\n\nThe end"
+        );
+        assert_eq!(
+            replace_voice_commands("call open paren optional close paren and/or hyphen free"),
+            "call (optional) and/or-free"
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(replace_voice_commands("type the word literally comma here"), "type the word comma here");
+        assert_eq!(replace_voice_commands("it was literally new line"), "it was new line");
+        assert_eq!(replace_voice_commands("it was literally amazing"), "it was literally amazing");
+    }
+
+    #[test]
+    fn test_scratch_that() {
+        let commands = VoiceCommands::default();
+        let apply = |text| apply_voice_commands(text, &commands, "en");
+        assert_eq!(apply("Send the report period Call me scratch that"), VoiceCommandOutput {
+            text: "Send the report.".into(),
+            scratch_previous: false,
+        });
+        assert_eq!(apply("Send the report. Scratch that. Send the memo.").text, "Send the memo.");
+        let output = apply("Scratch that. See you Monday.");
+        assert_eq!(output.text, "See you Monday.");
+        assert!(output.scratch_previous);
+    }
+
+    #[test]
+    fn test_languages_and_custom_commands() {
+        let mut commands = VoiceCommands::default();
+        let german = apply_voice_commands("Hallo Komma wie geht's Fragezeichen", &commands, "de");
+        assert_eq!(german.text, "Hallo, wie geht's?");
+        assert_eq!(
+            apply_voice_commands("Bonjour virgule ça va point d'interrogation", &commands, "fr").text,
+            "Bonjour, ça va?"
+        );
+        let languages: Vec<&str> = COMMAND_SETS.iter().map(|set| set.language).collect();
+        assert_eq!(languages, VOICE_COMMAND_LANGUAGES);
+        // English commands don't apply to other languages
+        assert_eq!(apply_voice_commands("hola comma amigo", &commands, "es").text, "hola comma amigo");
+
+        commands.custom = BTreeMap::from([("smiley face".to_string(), ":)".to_string())]);
+        commands.disabled = vec!["Star".to_string()];
+        assert_eq!(apply_voice_commands("great smiley face five star", &commands, "en").text, "great :) five star");
+        assert_eq!(apply_voice_commands("genial smiley face", &commands, "xx").text, "genial :)");
+    }
 }
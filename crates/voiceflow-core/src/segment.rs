@@ -0,0 +1,253 @@
+//! Splitting a transcript into timed segments, formatted one at a time
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::transcribe::WordTimestamp;
+
+/// A pause at least this long starts a new segment (ms)
+const PAUSE_MS: i64 = 1500;
+
+/// A pause at least this long after a sentence starts a new segment (ms)
+const SENTENCE_PAUSE_MS: i64 = 500;
+
+/// Segments are split before they grow longer than this (ms)
+const MAX_SEGMENT_MS: i64 = 30_000;
+
+/// A stretch of the transcript: the words between two long pauses, or a
+/// speaker turn when diarization is on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    /// Start time in milliseconds from the start of the audio
+    pub start_ms: i64,
+    /// End time in milliseconds from the start of the audio
+    pub end_ms: i64,
+    /// The segment's part of `raw_transcript`
+    pub raw_text: String,
+    /// The segment's part of `formatted_text`
+    pub formatted_text: String,
+    /// Mean STT word probability of the segment (the transcript's
+    /// confidence when there are no word timestamps)
+    pub confidence: f32,
+    /// Speaker number (0-based, in order of first appearance), when
+    /// diarization is on
+    pub speaker: Option<u8>,
+}
+
+/// Split `text` into segments, given the STT's words and, with
+/// diarization, the speaker of each word
+///
+/// `text` is the transcript after voice commands and replacements, which
+/// may have a different number of words than `words`; each of its words
+/// goes with the STT word at the same relative position. Without word
+/// timestamps the whole text is one segment lasting `duration_ms`.
+pub(crate) fn split(
+    text: &str,
+    words: &[WordTimestamp],
+    speakers: Option<&[u8]>,
+    duration_ms: i64,
+    confidence: f32,
+) -> Vec<Segment> {
+    let spans = word_spans(text);
+    if spans.is_empty() {
+        return Vec::new();
+    }
+    if words.is_empty() {
+        return vec![Segment {
+            start_ms: 0,
+            end_ms: duration_ms,
+            raw_text: text.trim().to_string(),
+            formatted_text: String::new(),
+            confidence,
+            speaker: None,
+        }];
+    }
+
+    let boundaries = segment_starts(words, speakers);
+    let mut segments: Vec<Segment> = Vec::new();
+    // Where the current segment starts, in `text` and in `words`
+    let mut text_start = 0;
+    let mut first_word = 0;
+    let mut current = usize::MAX;
+    for (i, span) in spans.iter().enumerate() {
+        let index = i * words.len() / spans.len();
+        let word = &words[index];
+        match segments.last_mut() {
+            // Whitespace inside a segment (paragraph breaks) is kept
+            Some(segment) if boundaries[index] == current => {
+                segment.end_ms = segment.end_ms.max(word.end_ms);
+                segment.raw_text = text[text_start..span.end].to_string();
+                segment.confidence = mean_probability(&words[first_word..=index]);
+            }
+            _ => {
+                current = boundaries[index];
+                text_start = span.start;
+                first_word = index;
+                segments.push(Segment {
+                    start_ms: word.start_ms,
+                    end_ms: word.end_ms,
+                    raw_text: text[span.clone()].to_string(),
+                    formatted_text: String::new(),
+                    confidence: word.probability,
+                    speaker: speakers.and_then(|speakers| speakers.get(index).copied()),
+                });
+            }
+        }
+    }
+    segments
+}
+
+/// Byte ranges of the whitespace-separated words of `text`
+fn word_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = start.take() {
+                spans.push(start..i);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    spans.extend(start.map(|start| start..text.len()));
+    spans
+}
+
+/// The segment number of each word: a new segment starts at a long pause,
+/// a pause after a sentence, a change of speaker, or when the segment
+/// would grow longer than `MAX_SEGMENT_MS`
+fn segment_starts(words: &[WordTimestamp], speakers: Option<&[u8]>) -> Vec<usize> {
+    let speaker = |i: usize| speakers.and_then(|speakers| speakers.get(i).copied());
+    let mut numbers = Vec::with_capacity(words.len());
+    let mut number = 0;
+    let mut start_ms = words.first().map_or(0, |word| word.start_ms);
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            let previous = &words[i - 1];
+            let pause = word.start_ms - previous.end_ms;
+            let sentence_end = previous.word.trim_end().ends_with(['.', '?', '!']);
+            if pause >= PAUSE_MS
+                || (sentence_end && pause >= SENTENCE_PAUSE_MS)
+                || speaker(i) != speaker(i - 1)
+                || word.end_ms - start_ms > MAX_SEGMENT_MS
+            {
+                number += 1;
+                start_ms = word.start_ms;
+            }
+        }
+        numbers.push(number);
+    }
+    numbers
+}
+
+fn mean_probability(words: &[WordTimestamp]) -> f32 {
+    if words.is_empty() {
+        return 0.0;
+    }
+    words.iter().map(|word| word.probability).sum::<f32>() / words.len() as f32
+}
+
+/// The segments' formatted text as one string: with `labeled`, each speaker
+/// turn is a "Speaker N:" paragraph (numbered from 1); otherwise segments
+/// are joined with spaces
+pub(crate) fn join_formatted(segments: &[Segment], labeled: bool) -> String {
+    let mut joined = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let text = segment.formatted_text.as_str();
+        let new_turn = labeled && (i == 0 || segments[i - 1].speaker != segment.speaker);
+        if new_turn {
+            if !joined.is_empty() {
+                joined.push_str("\n\n");
+            }
+            joined.push_str(&format!("Speaker {}: ", segment.speaker.unwrap_or(0) + 1));
+        } else if !joined.is_empty() && !text.is_empty() {
+            joined.push(' ');
+        }
+        joined.push_str(text);
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start_ms: i64, end_ms: i64) -> WordTimestamp {
+        WordTimestamp { word: text.to_string(), start_ms, end_ms, probability: 0.8 }
+    }
+
+    fn formatted(mut segments: Vec<Segment>) -> Vec<Segment> {
+        for segment in &mut segments {
+            segment.formatted_text = segment.raw_text.clone();
+        }
+        segments
+    }
+
+    #[test]
+    fn test_split_at_pauses() {
+        let words = [
+            word("Send", 0, 300),
+            word("it.", 300, 600),
+            word("Then", 1200, 1500),
+            word("wait", 1500, 1800),
+            word("and", 4000, 4200),
+            word("see", 4200, 4500),
+        ];
+        let segments = split("Send it. Then wait and see", &words, None, 5000, 0.9);
+        let texts: Vec<&str> = segments.iter().map(|segment| segment.raw_text.as_str()).collect();
+        assert_eq!(texts, ["Send it.", "Then wait", "and see"]);
+        assert_eq!((segments[1].start_ms, segments[1].end_ms), (1200, 1800));
+        assert_eq!(segments[2].confidence, 0.8);
+        assert!(segments.iter().all(|segment| segment.speaker.is_none()));
+
+        // No pause after "then": one segment
+        let words = [word("send", 0, 300), word("it", 300, 600), word("then", 700, 900)];
+        assert_eq!(split("send it then", &words, None, 1000, 0.9).len(), 1);
+    }
+
+    #[test]
+    fn test_split_without_timestamps() {
+        let segments = split(" hello.\n\nworld ", &[], None, 2000, 0.7);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].raw_text, "hello.\n\nworld");
+        assert_eq!((segments[0].start_ms, segments[0].end_ms, segments[0].confidence), (0, 2000, 0.7));
+        assert!(split("", &[], None, 2000, 0.7).is_empty());
+    }
+
+    #[test]
+    fn test_long_speech_is_split() {
+        let words: Vec<WordTimestamp> = (0..100).map(|i| word("word", i * 400, i * 400 + 350)).collect();
+        let text = vec!["word"; 100].join(" ");
+        let segments = split(&text, &words, None, 40_000, 0.9);
+        assert_eq!(segments.len(), 2);
+        assert!(segments.iter().all(|segment| segment.end_ms - segment.start_ms <= MAX_SEGMENT_MS));
+    }
+
+    #[test]
+    fn test_speaker_turns() {
+        let words = [word("hi", 0, 300), word("there", 300, 700), word("hello", 900, 1200), word("again", 1300, 1600)];
+        let segments = formatted(split("Hi there. Hello again", &words, Some(&[0, 0, 1, 0]), 2000, 0.9));
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].raw_text, "Hi there.");
+        assert_eq!((segments[0].start_ms, segments[0].end_ms), (0, 700));
+        assert_eq!(segments[1].speaker, Some(1));
+        assert_eq!(join_formatted(&segments, true), "Speaker 1: Hi there.\n\nSpeaker 2: Hello\n\nSpeaker 1: again");
+        assert_eq!(join_formatted(&segments, false), "Hi there. Hello again");
+
+        // Replacements that change the word count keep the turns in place
+        let segments = split("Hi to you there. Hello once more again.", &words, Some(&[0, 0, 1, 1]), 2000, 0.9);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].raw_text, "Hi to you there.");
+        assert_eq!(segments[1].raw_text, "Hello once more again.");
+    }
+
+    #[test]
+    fn test_turns_of_several_segments_are_one_paragraph() {
+        let words = [word("one.", 0, 300), word("two", 2000, 2300), word("three", 2300, 2600)];
+        let segments = formatted(split("One. Two three", &words, Some(&[0, 0, 1]), 3000, 0.9));
+        assert_eq!(segments.len(), 3);
+        assert_eq!(join_formatted(&segments, true), "Speaker 1: One. Two\n\nSpeaker 2: three");
+    }
+}
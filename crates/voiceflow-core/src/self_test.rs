@@ -0,0 +1,309 @@
+//! Self-test: a short bundled recording through the whole pipeline, to
+//! catch models that load but no longer work, such as a broken execution
+//! provider after an OS upgrade or a model file evicted from disk
+
+use std::time::Instant;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::audio::decode_audio;
+use crate::llm::normalized_words;
+use crate::pipeline::{Pipeline, PipelineResult, ProcessOptions};
+use crate::session::SessionState;
+use crate::transcribe::ActiveSttEngine;
+
+/// About a second of speech, 16kHz mono
+pub const SELF_TEST_WAV: &[u8] = include_bytes!("../testdata/self_test.wav");
+
+/// Words the formatted text of `SELF_TEST_WAV` must contain, one entry per
+/// line, `#` comments
+const SELF_TEST_EXPECTED: &str = include_str!("../testdata/self_test.expected");
+
+/// Outcome of a self-test stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageStatus {
+    Passed,
+    Failed,
+    /// Not run: an earlier stage failed, or there was nothing to check
+    Skipped,
+}
+
+/// One stage of a self-test
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfTestStage {
+    /// "audio", "stt", "llm" or "expected"
+    pub stage: &'static str,
+    pub status: StageStatus,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+impl SelfTestStage {
+    fn new<T>(stage: &'static str, latency_ms: u64, outcome: &Result<T>) -> Self {
+        let (status, error) = match outcome {
+            Ok(_) => (StageStatus::Passed, None),
+            Err(e) => (StageStatus::Failed, Some(format!("{:#}", e))),
+        };
+        Self { stage, status, latency_ms, error }
+    }
+
+    fn skipped(stage: &'static str) -> Self {
+        Self { stage, status: StageStatus::Skipped, latency_ms: 0, error: None }
+    }
+}
+
+/// What `Pipeline::self_test` found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfTestReport {
+    /// No stage failed
+    pub passed: bool,
+    /// Decoding the recording, transcribing it, formatting it, and checking
+    /// the formatted text for the expected words, in order
+    pub stages: Vec<SelfTestStage>,
+    pub total_ms: u64,
+    /// STT engine and model that ran, `None` for a supplied engine
+    pub stt_engine: Option<ActiveSttEngine>,
+    /// Hardware the STT engine ran on (see `Pipeline::stt_provider`)
+    pub stt_provider: Option<&'static str>,
+    /// The formatter (see `Config::llm_display_name`)
+    pub llm: String,
+    pub transcript: String,
+    pub formatted_text: String,
+}
+
+/// Entries of the expected-words list
+fn expected_words() -> Vec<&'static str> {
+    SELF_TEST_EXPECTED
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Entries of `expected` not in `text`, ignoring case and punctuation
+fn missing<'a>(expected: &[&'a str], text: &str) -> Vec<&'a str> {
+    let text = format!(" {} ", normalized_words(text).join(" "));
+    expected
+        .iter()
+        .copied()
+        .filter(|entry| !text.contains(&format!(" {} ", normalized_words(entry).join(" "))))
+        .collect()
+}
+
+impl Pipeline {
+    /// Run `SELF_TEST_WAV` through the pipeline and check each stage
+    ///
+    /// Transcribes the recording, then processes it as a request with the
+    /// default options (the LLM loads if needed) and checks the formatted
+    /// text for the expected words. The format cache is bypassed, so the LLM
+    /// always runs, and nothing is kept in the history. A stage fails on an error, on no speech recognized, or
+    /// when formatting fell back to the raw transcript; the stages after a
+    /// failed one are skipped. Takes a few seconds on a warm pipeline.
+    pub fn self_test(&mut self) -> SelfTestReport {
+        let start = Instant::now();
+        let mut stages = Vec::new();
+        let mut transcript = String::new();
+        let mut formatted_text = String::new();
+
+        let t = Instant::now();
+        let audio: Result<Vec<f32>> = decode_audio(SELF_TEST_WAV, "wav")
+            .map_err(anyhow::Error::from)
+            .and_then(|buffer| Ok(buffer.as_input().to_16khz_mono()?.into_owned()));
+        stages.push(SelfTestStage::new("audio", t.elapsed().as_millis() as u64, &audio));
+        let audio = audio.unwrap_or_default();
+
+        let transcribed = (!audio.is_empty()).then(|| {
+            let t = Instant::now();
+            let result = self.transcribe_only(&audio).map_err(anyhow::Error::from);
+            let latency_ms = result.as_ref().map_or(t.elapsed().as_millis() as u64, |r| r.timings.transcription_ms);
+            let outcome = result.and_then(|result| {
+                if result.no_speech || result.raw_transcript.trim().is_empty() {
+                    bail!("No speech recognized in the test recording");
+                }
+                transcript = result.raw_transcript;
+                Ok(())
+            });
+            stages.push(SelfTestStage::new("stt", latency_ms, &outcome));
+            outcome.is_ok()
+        });
+        if transcribed.is_none() {
+            stages.push(SelfTestStage::skipped("stt"));
+        }
+
+        let formatted = (transcribed == Some(true) && self.formats()).then(|| {
+            let t = Instant::now();
+            // A session, empty, keeps the result out of the format cache
+            let options = ProcessOptions { session: Some(SessionState::new()), ..Default::default() };
+            let result = self.process_unrecorded(&audio, &options);
+            let latency_ms = result.as_ref().map_or(t.elapsed().as_millis() as u64, |r| r.timings.llm_formatting_ms);
+            let outcome = result.and_then(|result: PipelineResult| {
+                if let Some(error) = result.formatting_error {
+                    bail!("{}", error);
+                }
+                if result.was_fallback {
+                    bail!("The formatted text strayed from the transcript");
+                }
+                formatted_text = result.formatted_text;
+                Ok(())
+            });
+            stages.push(SelfTestStage::new("llm", latency_ms, &outcome));
+            outcome.is_ok()
+        });
+        if formatted.is_none() {
+            stages.push(SelfTestStage::skipped("llm"));
+            formatted_text = transcript.clone();
+        }
+
+        let expected = expected_words();
+        if formatted == Some(false) || transcribed != Some(true) || expected.is_empty() {
+            stages.push(SelfTestStage::skipped("expected"));
+        } else {
+            let missing = missing(&expected, &formatted_text);
+            let outcome = if missing.is_empty() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Missing from the formatted text: {}", missing.join(", ")))
+            };
+            stages.push(SelfTestStage::new("expected", 0, &outcome));
+        }
+
+        let report = SelfTestReport {
+            passed: stages.iter().all(|stage| stage.status != StageStatus::Failed),
+            stages,
+            total_ms: start.elapsed().as_millis() as u64,
+            stt_engine: self.active_stt_engine().cloned(),
+            stt_provider: self.stt_provider(),
+            llm: self.config().llm_display_name(),
+            transcript,
+            formatted_text,
+        };
+        if report.passed {
+            tracing::info!("Self-test passed in {}ms", report.total_ms);
+        } else {
+            tracing::warn!("Self-test failed: {:?}", report.stages);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PipelineBuilder;
+    use crate::llm::{FormatContext, TextFormatter};
+    use crate::transcribe::{SpeechToText, SttOptions, TranscriptionResult};
+    use crate::FormattingMode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct FixedTranscript(&'static str);
+
+    impl SpeechToText for FixedTranscript {
+        fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+            Ok(TranscriptionResult {
+                text: self.0.to_string(),
+                word_timestamps: Vec::new(),
+                confidence: 0.9,
+                no_speech_probability: 0.0,
+                language: Some("en".to_string()),
+                encode_ms: 0,
+                decode_ms: 0,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
+            })
+        }
+    }
+
+    /// Formats every transcript as the same text, counting the calls
+    struct FixedFormatting(&'static str, Arc<AtomicUsize>);
+
+    impl TextFormatter for FixedFormatting {
+        fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> Result<String> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn formatting(text: &'static str) -> Box<FixedFormatting> {
+        Box::new(FixedFormatting(text, Arc::default()))
+    }
+
+    fn stages(report: &SelfTestReport) -> Vec<(&str, StageStatus)> {
+        report.stages.iter().map(|stage| (stage.stage, stage.status)).collect()
+    }
+
+    #[test]
+    fn test_bundled_recording_decodes() {
+        let buffer = decode_audio(SELF_TEST_WAV, "wav").unwrap();
+        assert_eq!((buffer.sample_rate, buffer.channels), (16_000, 1));
+        let seconds = buffer.samples.len() as f32 / 16_000.0;
+        assert!((1.0..2.0).contains(&seconds), "{}s", seconds);
+    }
+
+    #[test]
+    fn test_missing_ignores_case_and_punctuation() {
+        let text = "Hello, world. Testing the VoiceFlow app!";
+        assert!(missing(&["hello world", "voiceflow", "Testing, the VoiceFlow app."], text).is_empty());
+        // Entries match whole words, in order
+        assert_eq!(missing(&["hello", "flow", "the app", "VOICEFLOW APP"], text), ["flow", "the app"]);
+    }
+
+    #[test]
+    fn test_every_stage_runs_through_the_pipeline() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut pipeline = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("testing the microphone")))
+            .llm_engine(Box::new(FixedFormatting("Testing the microphone.", calls.clone())))
+            .build()
+            .unwrap();
+        let report = pipeline.self_test();
+        assert!(report.passed, "{:?}", report.stages);
+        assert_eq!(
+            stages(&report)[..3],
+            [("audio", StageStatus::Passed), ("stt", StageStatus::Passed), ("llm", StageStatus::Passed)]
+        );
+        assert_eq!(report.transcript, "testing the microphone");
+        assert_eq!(report.formatted_text, "Testing the microphone.");
+
+        // The format cache is bypassed
+        assert!(pipeline.self_test().passed);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_llm_stage_skipped_without_formatting() {
+        let mut pipeline = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("testing the microphone")))
+            .formatting(FormattingMode::None)
+            .build()
+            .unwrap();
+        let report = pipeline.self_test();
+        assert!(report.passed, "{:?}", report.stages);
+        assert_eq!(stages(&report)[2], ("llm", StageStatus::Skipped));
+        assert_eq!(report.formatted_text, report.transcript);
+    }
+
+    #[test]
+    fn test_no_speech_fails_the_stt_stage() {
+        let mut pipeline = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("")))
+            .llm_engine(formatting("Nothing."))
+            .build()
+            .unwrap();
+        let report = pipeline.self_test();
+        assert!(!report.passed);
+        assert_eq!(
+            stages(&report),
+            [
+                ("audio", StageStatus::Passed),
+                ("stt", StageStatus::Failed),
+                ("llm", StageStatus::Skipped),
+                ("expected", StageStatus::Skipped),
+            ]
+        );
+        assert!(report.stages[1].error.as_deref().unwrap().contains("No speech"));
+    }
+}
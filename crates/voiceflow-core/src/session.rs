@@ -0,0 +1,170 @@
+//! Dictation sessions: consecutive dictations formatted with the earlier
+//! ones as context, so a text dictated in several bursts reads as one
+
+use std::collections::VecDeque;
+
+/// Rough number of characters per LLM token, used to budget the prompt
+/// without running the tokenizer
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Most outputs kept, whatever the token budget
+const MAX_HISTORY: usize = 32;
+
+/// Estimated number of LLM tokens in a text
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Formatted outputs of a session's earlier dictations
+///
+/// Each session has its own state, so sessions sharing a pipeline never see
+/// each other's text.
+#[derive(Debug, Clone, Default)]
+pub struct SessionState {
+    /// Oldest first
+    history: VecDeque<String>,
+}
+
+impl SessionState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the formatted output of a dictation (empty output is skipped)
+    pub fn push(&mut self, formatted: &str) {
+        let formatted = formatted.trim();
+        if formatted.is_empty() {
+            return;
+        }
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(formatted.to_string());
+    }
+
+    /// Forget the most recent dictation, returning its output
+    pub fn pop(&mut self) -> Option<String> {
+        self.history.pop_back()
+    }
+
+    /// Forget all earlier dictations
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Number of dictations recorded
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// The most recent outputs that fit in `budget` tokens, oldest first
+    ///
+    /// When even the last output doesn't fit, its end is kept, starting at a
+    /// word boundary. None when nothing fits.
+    pub fn context(&self, budget: usize) -> Option<String> {
+        let mut kept: Vec<&str> = Vec::new();
+        let mut used = 0;
+        for output in self.history.iter().rev() {
+            // Outputs are joined with a blank line
+            let tokens = estimate_tokens(output) + 1;
+            if used + tokens > budget {
+                if kept.is_empty() {
+                    kept.push(tail(output, budget * CHARS_PER_TOKEN));
+                }
+                break;
+            }
+            used += tokens;
+            kept.push(output);
+        }
+        kept.retain(|output| !output.is_empty());
+        if kept.is_empty() {
+            return None;
+        }
+        kept.reverse();
+        Some(kept.join("\n\n"))
+    }
+
+    /// Prompt section with the earlier dictations, placed before the
+    /// formatting instructions (empty when nothing fits in `budget`)
+    pub fn prompt_prefix(&self, budget: usize) -> String {
+        match self.context(budget) {
+            Some(context) => format!(
+                "Text dictated just before, for context only. Do not repeat or reformat it:\n\"\"\"\n{}\n\"\"\"\n\n",
+                context
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+/// The end of `text`, at most `max_chars` long and starting at a word
+fn tail(text: &str, max_chars: usize) -> &str {
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text;
+    }
+    let start = text.char_indices().nth(count - max_chars).map_or(text.len(), |(i, _)| i);
+    let tail = &text[start..];
+    match tail.find(char::is_whitespace) {
+        Some(space) => tail[space..].trim_start(),
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(outputs: &[&str]) -> SessionState {
+        let mut session = SessionState::new();
+        for output in outputs {
+            session.push(output);
+        }
+        session
+    }
+
+    #[test]
+    fn test_context_keeps_most_recent_outputs() {
+        let session = session(&["Hi Sam,", "", "Thanks for the notes.", "They look good to me."]);
+        assert_eq!(session.len(), 3);
+        assert_eq!(session.context(100).unwrap(), "Hi Sam,\n\nThanks for the notes.\n\nThey look good to me.");
+        // The last two outputs are 6 tokens each, plus a separator
+        assert_eq!(session.context(14).unwrap(), "Thanks for the notes.\n\nThey look good to me.");
+        assert_eq!(session.context(13).unwrap(), "They look good to me.");
+    }
+
+    #[test]
+    fn test_long_output_is_cut_at_a_word() {
+        let session = session(&["The quarterly numbers are in and revenue grew by twelve percent."]);
+        assert_eq!(session.context(5).unwrap(), "by twelve percent.");
+        assert_eq!(session.context(0), None);
+    }
+
+    #[test]
+    fn test_reset_and_empty_session() {
+        let mut session = session(&["First burst."]);
+        session.reset();
+        assert!(session.is_empty());
+        assert_eq!(session.prompt_prefix(100), "");
+
+        session.push("Second burst.");
+        assert!(session.prompt_prefix(100).contains("\"\"\"\nSecond burst.\n\"\"\""));
+
+        assert_eq!(session.pop().as_deref(), Some("Second burst."));
+        assert_eq!(session.pop(), None);
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut session = SessionState::new();
+        for i in 0..MAX_HISTORY + 5 {
+            session.push(&format!("Burst {}.", i));
+        }
+        assert_eq!(session.len(), MAX_HISTORY);
+        assert!(session.context(1000).unwrap().starts_with("Burst 5."));
+    }
+}
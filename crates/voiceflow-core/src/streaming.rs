@@ -0,0 +1,711 @@
+//! Streaming transcription: buffer incoming audio, cut it into utterances
+//! with energy-based VAD, and transcribe each one as soon as it ends
+
+use crate::audio::{rms, PreprocessStats};
+use crate::cancel::CancelToken;
+use crate::config::{AudioOptions, EndpointingOptions};
+use crate::pipeline::{parse_context, Pipeline, PipelineResult, ProcessOptions, Timings, Transcribed};
+use crate::transcribe::TranscriptionResult;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+#[cfg(feature = "capture")]
+use crate::audio::Microphone;
+#[cfg(feature = "capture")]
+use crate::pipeline::PipelineError;
+#[cfg(feature = "capture")]
+use std::time::Duration;
+
+/// Samples per second expected by the STT engines
+const SAMPLE_RATE: usize = 16000;
+
+/// VAD frame length (30ms at 16kHz)
+pub(crate) const FRAME_SAMPLES: usize = 480;
+
+/// Audio kept from before speech starts so the first word isn't clipped (200ms)
+const PREROLL_SAMPLES: usize = 3200;
+
+/// Longest segment handed to the STT engine (Whisper's 30s window)
+const MAX_SEGMENT_SAMPLES: usize = 30 * SAMPLE_RATE;
+
+/// Energy-based voice activity segmenter
+///
+/// Frames whose RMS reaches `vad_threshold` count as speech. A segment
+/// starts at the first speech frame and ends once `silence_duration_ms` of
+/// non-speech frames follow it, or when it reaches 30 seconds.
+#[derive(Debug)]
+pub struct VadSegmenter {
+    threshold: f32,
+    silence_frames: usize,
+    /// Samples not yet making up a full frame
+    pending: Vec<f32>,
+    /// Recent non-speech audio, prepended when speech starts
+    preroll: Vec<f32>,
+    /// Samples of the segment in progress
+    current: Vec<f32>,
+    in_speech: bool,
+    trailing_silence: usize,
+}
+
+impl VadSegmenter {
+    /// Create a segmenter from the audio settings (16kHz input)
+    pub fn new(options: &AudioOptions) -> Self {
+        let frame_ms = (FRAME_SAMPLES * 1000 / SAMPLE_RATE) as u32;
+        Self {
+            threshold: options.vad_threshold,
+            silence_frames: (options.silence_duration_ms / frame_ms).max(1) as usize,
+            pending: Vec::new(),
+            preroll: Vec::new(),
+            current: Vec::new(),
+            in_speech: false,
+            trailing_silence: 0,
+        }
+    }
+
+    /// Feed 16kHz samples, returning any segments that ended
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let mut segments = Vec::new();
+        self.pending.extend_from_slice(samples);
+
+        // Frames are read in place; `pending` is put back to keep its buffer
+        let pending = std::mem::take(&mut self.pending);
+        let mut offset = 0;
+        while pending.len() - offset >= FRAME_SAMPLES {
+            let frame = &pending[offset..offset + FRAME_SAMPLES];
+            offset += FRAME_SAMPLES;
+            if let Some(segment) = self.process_frame(frame) {
+                segments.push(segment);
+            }
+        }
+        self.pending = pending;
+        self.pending.drain(..offset);
+
+        segments
+    }
+
+    /// End of input: return the segment in progress, if speech had started
+    pub fn flush(&mut self) -> Option<Vec<f32>> {
+        let pending = std::mem::take(&mut self.pending);
+        if self.in_speech {
+            self.current.extend_from_slice(&pending);
+            Some(self.end_segment())
+        } else {
+            self.preroll.clear();
+            None
+        }
+    }
+
+    /// Whether a segment is currently in progress
+    pub fn in_speech(&self) -> bool {
+        self.in_speech
+    }
+
+    fn process_frame(&mut self, frame: &[f32]) -> Option<Vec<f32>> {
+        let is_speech = rms(frame) >= self.threshold;
+
+        if !self.in_speech {
+            if is_speech {
+                self.in_speech = true;
+                self.trailing_silence = 0;
+                self.current = std::mem::take(&mut self.preroll);
+                self.current.extend_from_slice(frame);
+            } else {
+                self.preroll.extend_from_slice(frame);
+                if self.preroll.len() > PREROLL_SAMPLES {
+                    let excess = self.preroll.len() - PREROLL_SAMPLES;
+                    self.preroll.drain(..excess);
+                }
+            }
+            return None;
+        }
+
+        self.current.extend_from_slice(frame);
+        if is_speech {
+            self.trailing_silence = 0;
+        } else {
+            self.trailing_silence += 1;
+        }
+
+        if self.trailing_silence >= self.silence_frames || self.current.len() >= MAX_SEGMENT_SAMPLES {
+            Some(self.end_segment())
+        } else {
+            None
+        }
+    }
+
+    fn end_segment(&mut self) -> Vec<f32> {
+        self.in_speech = false;
+        self.trailing_silence = 0;
+        std::mem::take(&mut self.current)
+    }
+}
+
+/// Where an `Endpointer` ended an utterance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Endpoint {
+    /// After its trailing silence
+    Silence,
+    /// At the longest utterance allowed
+    MaxLength,
+}
+
+/// Finds where an utterance ends in 16kHz audio: once enough silence
+/// follows its speech, or once it's gone on long enough
+///
+/// Frames are read like `VadSegmenter` reads them, so a segmenter fed the
+/// same samples has no partial frame left at the end.
+#[derive(Debug)]
+pub(crate) struct Endpointer {
+    threshold: f32,
+    /// Frames of silence after speech that end the utterance (None never)
+    silence_frames: Option<usize>,
+    /// Frames from its first speech that end the utterance (None never)
+    max_frames: Option<usize>,
+    /// Samples not yet making up a full frame
+    pending: Vec<f32>,
+    /// Frames since the utterance's first speech, None before it
+    utterance_frames: Option<usize>,
+    trailing_silence: usize,
+}
+
+impl Endpointer {
+    /// End utterances after `silence_ms` of silence, and after `max_ms`
+    /// from their first speech
+    pub(crate) fn new(threshold: f32, silence_ms: Option<u32>, max_ms: Option<u32>) -> Self {
+        let frames = |ms: u32| (ms as usize * SAMPLE_RATE / 1000 / FRAME_SAMPLES).max(1);
+        Self {
+            threshold,
+            silence_frames: silence_ms.map(frames),
+            max_frames: max_ms.map(frames),
+            pending: Vec::new(),
+            utterance_frames: None,
+            trailing_silence: 0,
+        }
+    }
+
+    /// Whether the utterance in progress has had speech
+    #[cfg_attr(not(feature = "capture"), allow(dead_code))]
+    pub(crate) fn speaking(&self) -> bool {
+        self.utterance_frames.is_some()
+    }
+
+    /// Feed 16kHz samples; when the utterance ends, returns how many of them
+    /// come before its end and starts over for the next one
+    ///
+    /// The samples after the end aren't kept: push them again.
+    pub(crate) fn push(&mut self, samples: &[f32]) -> Option<(usize, Endpoint)> {
+        let carried = self.pending.len();
+        self.pending.extend_from_slice(samples);
+        let mut offset = 0;
+        while self.pending.len() - offset >= FRAME_SAMPLES {
+            let is_speech = rms(&self.pending[offset..offset + FRAME_SAMPLES]) >= self.threshold;
+            offset += FRAME_SAMPLES;
+            let frames = match (self.utterance_frames.as_mut(), is_speech) {
+                (None, false) => continue,
+                (None, true) => self.utterance_frames.insert(0),
+                (Some(frames), _) => frames,
+            };
+            *frames += 1;
+            let frames = *frames;
+            self.trailing_silence = if is_speech { 0 } else { self.trailing_silence + 1 };
+
+            let endpoint = if self.silence_frames.is_some_and(|silence| self.trailing_silence >= silence) {
+                Endpoint::Silence
+            } else if self.max_frames.is_some_and(|max| frames >= max) {
+                Endpoint::MaxLength
+            } else {
+                continue;
+            };
+            self.pending.clear();
+            self.utterance_frames = None;
+            self.trailing_silence = 0;
+            return Some((offset - carried, endpoint));
+        }
+        self.pending.drain(..offset);
+        None
+    }
+}
+
+/// One utterance of a streaming session: its audio and the transcript of
+/// its segments, formatted by `format`
+pub struct Utterance {
+    /// All of its audio, for prosody analysis when formatting
+    audio: Vec<f32>,
+    transcript: String,
+    transcription_ms: u64,
+    /// STT encoder and decoder time over all segments
+    encode_ms: u64,
+    decode_ms: u64,
+    /// Highest temperature a segment's decode fell back to
+    temperature: f32,
+    temperature_fallback: bool,
+    /// Sum of the confidences of the kept segments
+    confidence_sum: f32,
+    kept_segments: usize,
+    /// Lowest no-speech probability over the kept segments
+    no_speech_probability: f32,
+    /// Language of the first kept segment (or of the utterance before);
+    /// later segments are decoded in it
+    language: Option<String>,
+    start: Instant,
+}
+
+impl std::fmt::Debug for Utterance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Utterance")
+            .field("samples", &self.audio.len())
+            .field("transcript", &self.transcript)
+            .field("language", &self.language)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Utterance {
+    fn new(language: Option<String>) -> Self {
+        Self {
+            audio: Vec::new(),
+            transcript: String::new(),
+            transcription_ms: 0,
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature: 0.0,
+            temperature_fallback: false,
+            confidence_sum: 0.0,
+            kept_segments: 0,
+            no_speech_probability: 1.0,
+            language,
+            start: Instant::now(),
+        }
+    }
+
+    /// Raw transcript of its segments
+    pub fn transcript(&self) -> &str {
+        &self.transcript
+    }
+
+    /// Its 16kHz audio
+    pub fn audio(&self) -> &[f32] {
+        &self.audio
+    }
+
+    /// Run the prosody and LLM stages on the transcript
+    pub fn format(self, pipeline: &mut Pipeline, context: Option<&str>, cancel: &CancelToken) -> Result<PipelineResult> {
+        let transcription_result = TranscriptionResult {
+            text: self.transcript,
+            word_timestamps: vec![], // Segments are transcribed separately, so no global timestamps
+            confidence: if self.kept_segments > 0 {
+                self.confidence_sum / self.kept_segments as f32
+            } else {
+                0.0
+            },
+            no_speech_probability: self.no_speech_probability,
+            language: self.language,
+            encode_ms: self.encode_ms,
+            decode_ms: self.decode_ms,
+            temperature: self.temperature,
+            temperature_fallback: self.temperature_fallback,
+            alternatives: Vec::new(),
+        };
+
+        let transcribed = Transcribed {
+            result: transcription_result,
+            timings: Timings {
+                transcription_ms: self.transcription_ms,
+                stt_encode_ms: self.encode_ms,
+                stt_decode_ms: self.decode_ms,
+                stt_provider: pipeline.stt_provider(),
+                stt_temperature: self.temperature,
+                stt_temperature_fallback: self.temperature_fallback,
+                ..Default::default()
+            },
+            original_transcript: None,
+            decoder_timestamps: false,
+            start: self.start,
+            preprocess: PreprocessStats::default(),
+        };
+        pipeline.format_transcription(
+            &self.audio,
+            transcribed,
+            &parse_context(context)?,
+            &ProcessOptions {
+                cancel: cancel.clone(),
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    /// Transcribe a segment and append its text; returns whether it was kept
+    fn transcribe(&mut self, pipeline: &mut Pipeline, segment: &[f32], cancel: &CancelToken) -> Result<bool> {
+        let t = Instant::now();
+        let result = pipeline.transcribe_segment(segment, self.language.as_deref(), cancel)?;
+        self.transcription_ms += t.elapsed().as_millis() as u64;
+        self.encode_ms += result.encode_ms;
+        self.decode_ms += result.decode_ms;
+        self.temperature = self.temperature.max(result.temperature);
+        self.temperature_fallback |= result.temperature_fallback;
+
+        // Drop segments of noise (e.g. keyboard clicks that tripped the VAD)
+        if pipeline.is_no_speech(&result) {
+            tracing::debug!("Dropping no-speech segment: {}", result.text);
+            return Ok(false);
+        }
+        self.confidence_sum += result.confidence;
+        self.kept_segments += 1;
+        self.no_speech_probability = self.no_speech_probability.min(result.no_speech_probability);
+        if self.language.is_none() {
+            self.language = result.language;
+        }
+
+        let text = result.text.trim();
+        tracing::debug!("Streaming segment ({} samples): {}", segment.len(), text);
+
+        if !self.transcript.is_empty() {
+            self.transcript.push(' ');
+        }
+        self.transcript.push_str(text);
+        Ok(true)
+    }
+}
+
+/// An in-progress streaming transcription
+///
+/// Each segment is transcribed as soon as the VAD closes it, so partial text
+/// is available while the user is still speaking. `finish` transcribes the
+/// tail and runs the usual prosody and LLM stages on the joined transcript.
+///
+/// A session started `with_endpointing` is hands-free: it ends each
+/// utterance by itself once the speaker pauses, and starts the next one
+/// with the audio after that point, so nothing said meanwhile is lost.
+/// Ended utterances wait in `take_utterance` until they're formatted.
+pub struct StreamingSession {
+    segmenter: VadSegmenter,
+    /// Ends utterances in hands-free sessions
+    endpointer: Option<Endpointer>,
+    /// The utterance in progress
+    current: Utterance,
+    /// Utterances the endpointer ended, oldest first
+    ended: VecDeque<Utterance>,
+}
+
+impl StreamingSession {
+    /// Start a session using the pipeline's audio settings
+    pub fn new(options: &AudioOptions) -> Self {
+        Self {
+            segmenter: VadSegmenter::new(options),
+            endpointer: None,
+            current: Utterance::new(None),
+            ended: VecDeque::new(),
+        }
+    }
+
+    /// Start a hands-free session, ending utterances as `endpointing` says
+    /// (`Config::endpointing`, or thresholds of its own)
+    pub fn with_endpointing(options: &AudioOptions, endpointing: &EndpointingOptions) -> Self {
+        let max_ms = (endpointing.max_utterance_ms > 0).then_some(endpointing.max_utterance_ms);
+        Self {
+            endpointer: Some(Endpointer::new(options.vad_threshold, Some(endpointing.trailing_silence_ms), max_ms)),
+            ..Self::new(options)
+        }
+    }
+
+    /// Feed 16kHz samples, transcribing any segment that ended
+    ///
+    /// Returns true if the transcript changed. In a hands-free session an
+    /// utterance that ended is then ready in `take_utterance`, and the
+    /// transcript is the next one's.
+    pub fn push(&mut self, pipeline: &mut Pipeline, samples: &[f32], cancel: &CancelToken) -> Result<bool> {
+        let mut samples = samples;
+        while let Some((end, endpoint)) = self.endpointer.as_mut().and_then(|endpointer| endpointer.push(samples)) {
+            let (head, rest) = samples.split_at(end);
+            self.push_segments(pipeline, head, cancel)?;
+            self.end_utterance(pipeline, endpoint, cancel)?;
+            samples = rest;
+        }
+        self.push_segments(pipeline, samples, cancel)
+    }
+
+    /// Raw transcript of the segments recognized so far (of the utterance in
+    /// progress, in a hands-free session)
+    pub fn transcript(&self) -> &str {
+        &self.current.transcript
+    }
+
+    /// The oldest utterance a hands-free session ended and that wasn't taken
+    /// yet
+    ///
+    /// Utterances without speech are dropped rather than ended.
+    pub fn take_utterance(&mut self) -> Option<Utterance> {
+        self.ended.pop_front()
+    }
+
+    /// Transcribe the remaining audio and format the full transcript (of
+    /// the utterance in progress, in a hands-free session)
+    ///
+    /// Utterances still waiting in `take_utterance` are dropped.
+    pub fn finish(
+        mut self,
+        pipeline: &mut Pipeline,
+        context: Option<&str>,
+        cancel: &CancelToken,
+    ) -> Result<PipelineResult> {
+        if let Some(segment) = self.segmenter.flush() {
+            self.current.transcribe(pipeline, &segment, cancel)?;
+        }
+        if !self.ended.is_empty() {
+            tracing::debug!("Dropping {} ended utterance(s) never taken", self.ended.len());
+        }
+        self.current.format(pipeline, context, cancel)
+    }
+
+    /// Add samples to the utterance in progress, transcribing any segment
+    /// that ended; returns whether its transcript changed
+    fn push_segments(&mut self, pipeline: &mut Pipeline, samples: &[f32], cancel: &CancelToken) -> Result<bool> {
+        self.current.audio.extend_from_slice(samples);
+
+        let mut changed = false;
+        for segment in self.segmenter.push(samples) {
+            changed |= self.current.transcribe(pipeline, &segment, cancel)?;
+        }
+        Ok(changed)
+    }
+
+    /// Transcribe the tail of the utterance in progress and queue it,
+    /// starting the next one
+    fn end_utterance(&mut self, pipeline: &mut Pipeline, endpoint: Endpoint, cancel: &CancelToken) -> Result<()> {
+        if let Some(segment) = self.segmenter.flush() {
+            self.current.transcribe(pipeline, &segment, cancel)?;
+        }
+        // After a pause, its end leads into the next utterance's first word;
+        // cut short mid-speech, it's already part of this one
+        if endpoint == Endpoint::Silence {
+            let audio = &self.current.audio;
+            self.segmenter.preroll.extend_from_slice(&audio[audio.len().saturating_sub(PREROLL_SAMPLES)..]);
+        }
+
+        let next = Utterance::new(self.current.language.clone());
+        let utterance = std::mem::replace(&mut self.current, next);
+        if utterance.kept_segments == 0 {
+            tracing::debug!("Dropping utterance without speech ({} samples)", utterance.audio.len());
+        } else {
+            tracing::debug!("Utterance ended ({:?}): {}", endpoint, utterance.transcript);
+            self.ended.push_back(utterance);
+        }
+        Ok(())
+    }
+}
+
+/// How long `listen` waits for audio before checking for cancellation
+#[cfg(feature = "capture")]
+const LISTEN_POLL: Duration = Duration::from_millis(50);
+
+/// Transcribe from a microphone until its capture stops, then format the
+/// transcript
+///
+/// `on_partial` gets the raw transcript each time a segment is recognized.
+/// Cancelling stops the capture and fails with `PipelineError::Cancelled`.
+#[cfg(feature = "capture")]
+pub fn listen(
+    pipeline: &mut Pipeline,
+    microphone: &mut Microphone,
+    context: Option<&str>,
+    cancel: &CancelToken,
+    mut on_partial: impl FnMut(&str),
+) -> Result<PipelineResult> {
+    let mut session = StreamingSession::new(&pipeline.config().audio);
+    while let Some(samples) = microphone.read(LISTEN_POLL)? {
+        if cancel.is_cancelled() {
+            microphone.stop();
+            return Err(PipelineError::cancelled().into());
+        }
+        if session.push(pipeline, &samples, cancel)? {
+            on_partial(session.transcript());
+        }
+    }
+    session.finish(pipeline, context, cancel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> AudioOptions {
+        AudioOptions {
+            sample_rate: 16000,
+            vad_threshold: 0.01,
+            silence_duration_ms: 300,
+            ..AudioOptions::default()
+        }
+    }
+
+    fn tone(ms: usize) -> Vec<f32> {
+        (0..ms * 16).map(|i| (i as f32 * 0.1).sin() * 0.5).collect()
+    }
+
+    fn silence(ms: usize) -> Vec<f32> {
+        vec![0.0; ms * 16]
+    }
+
+    #[test]
+    fn test_silence_produces_no_segments() {
+        let mut vad = VadSegmenter::new(&options());
+        assert!(vad.push(&silence(2000)).is_empty());
+        assert!(vad.flush().is_none());
+    }
+
+    #[test]
+    fn test_segment_ends_after_silence() {
+        let mut vad = VadSegmenter::new(&options());
+        let mut audio = tone(600);
+        audio.extend(silence(600));
+
+        let segments = vad.push(&audio);
+        assert_eq!(segments.len(), 1);
+        assert!(!vad.in_speech());
+        // Speech plus the trailing silence that closed it
+        assert!(segments[0].len() >= 600 * 16);
+    }
+
+    #[test]
+    fn test_two_utterances_split() {
+        let mut vad = VadSegmenter::new(&options());
+        let mut audio = tone(500);
+        audio.extend(silence(500));
+        audio.extend(tone(500));
+        audio.extend(silence(500));
+
+        assert_eq!(vad.push(&audio).len(), 2);
+    }
+
+    #[test]
+    fn test_short_pause_does_not_split() {
+        let mut vad = VadSegmenter::new(&options());
+        let mut audio = tone(500);
+        audio.extend(silence(100));
+        audio.extend(tone(500));
+
+        assert!(vad.push(&audio).is_empty());
+        assert!(vad.in_speech());
+        assert_eq!(vad.flush().map(|s| s.len()), Some(1100 * 16));
+    }
+
+    #[test]
+    fn test_preroll_kept() {
+        let mut vad = VadSegmenter::new(&options());
+        // Whole frames of silence so the speech starts on a frame boundary
+        let mut audio = silence(960);
+        audio.extend(tone(300));
+        vad.push(&audio);
+
+        let segment = vad.flush().unwrap();
+        assert_eq!(segment.len(), PREROLL_SAMPLES + 300 * 16);
+    }
+
+    #[test]
+    fn test_small_pushes_match_single_push() {
+        let mut audio = tone(400);
+        audio.extend(silence(400));
+
+        let mut whole = VadSegmenter::new(&options());
+        let expected = whole.push(&audio);
+
+        let mut chunked = VadSegmenter::new(&options());
+        let mut segments = Vec::new();
+        for chunk in audio.chunks(100) {
+            segments.extend(chunked.push(chunk));
+        }
+
+        assert_eq!(segments, expected);
+    }
+
+    fn endpointer() -> Endpointer {
+        Endpointer::new(0.01, Some(300), Some(2100))
+    }
+
+    #[test]
+    fn test_endpoint_after_trailing_silence() {
+        let mut endpointer = endpointer();
+        assert!(endpointer.push(&silence(1000)).is_none());
+        assert!(!endpointer.speaking());
+
+        // A pause shorter than the trailing silence doesn't end it
+        let mut audio = tone(510);
+        audio.extend(silence(150));
+        audio.extend(tone(510));
+        audio.extend(silence(1000));
+        let (end, endpoint) = endpointer.push(&audio).unwrap();
+        assert_eq!(endpoint, Endpoint::Silence);
+        // The speech ends inside a frame (the 1000ms before are 33 frames
+        // and a third), which counts as speech; 10 frames of silence follow
+        let speech_end: usize = 16000 + 1170 * 16;
+        assert_eq!(16000 + end, speech_end.next_multiple_of(FRAME_SAMPLES) + 10 * FRAME_SAMPLES);
+        assert!(!endpointer.speaking());
+    }
+
+    #[test]
+    fn test_endpoint_at_max_length() {
+        let mut endpointer = endpointer();
+        let mut audio = silence(480);
+        audio.extend(tone(5000));
+        let (end, endpoint) = endpointer.push(&audio).unwrap();
+        assert_eq!(endpoint, Endpoint::MaxLength);
+        // Counted from the first speech
+        assert_eq!(end, 480 * 16 + 2100 * 16);
+
+        // The rest, pushed again, is the next utterance
+        let (next, _) = endpointer.push(&audio[end..]).unwrap();
+        assert_eq!(next, 2100 * 16);
+    }
+
+    #[test]
+    fn test_endpoint_in_small_pushes() {
+        let mut audio = tone(600);
+        audio.extend(silence(600));
+        audio.extend(tone(600));
+        audio.extend(silence(600));
+
+        let mut chunked = endpointer();
+        let mut ends = Vec::new();
+        let mut pushed = 0;
+        for chunk in audio.chunks(100) {
+            let mut chunk = chunk;
+            while let Some((end, _)) = chunked.push(chunk) {
+                ends.push(pushed + end);
+                pushed += end;
+                chunk = &chunk[end..];
+            }
+            pushed += chunk.len();
+        }
+
+        let mut whole = endpointer();
+        let (first, _) = whole.push(&audio).unwrap();
+        let (second, _) = whole.push(&audio[first..]).unwrap();
+        assert_eq!(ends, [first, first + second]);
+    }
+
+    #[test]
+    fn test_segmenter_has_no_partial_frame_at_an_endpoint() {
+        let mut audio = tone(500);
+        audio.extend(silence(500));
+        audio.extend(tone(500));
+
+        // As a hands-free session feeds them, a segmenter whose pauses are
+        // longer than the trailing silence
+        let options = AudioOptions { silence_duration_ms: 1000, ..options() };
+        let mut vad = VadSegmenter::new(&options);
+        let mut endpointer = endpointer();
+        let (end, _) = endpointer.push(&audio).unwrap();
+        vad.push(&audio[..end]);
+        assert!(vad.pending.is_empty());
+        assert_eq!(vad.flush().map(|segment| segment.len()), Some(end));
+    }
+
+    #[test]
+    fn test_max_segment_length() {
+        let mut vad = VadSegmenter::new(&options());
+        let segments = vad.push(&tone(31_000));
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].len(), MAX_SEGMENT_SAMPLES);
+    }
+}
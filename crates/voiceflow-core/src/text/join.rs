@@ -0,0 +1,306 @@
+//! Fitting formatted text into the text around the cursor
+//!
+//! Formatters write each dictation as if it stood on its own. When it's
+//! inserted into existing text, these rules (not the LLM) decide its first
+//! letter, its final punctuation and the spaces on either side.
+
+/// Words that keep their capital mid-sentence
+const ALWAYS_CAPITALIZED: [&str; 5] = ["I", "I'm", "I've", "I'll", "I'd"];
+
+/// Abbreviations whose period doesn't end a sentence, lowercased
+const ABBREVIATIONS: [&str; 8] = ["e.g.", "i.e.", "cf.", "vs.", "approx.", "no.", "fig.", "ca."];
+
+/// Titles, which a name follows, lowercased
+const TITLES: [&str; 6] = ["mr.", "mrs.", "ms.", "dr.", "prof.", "st."];
+
+/// Opening brackets and quotes; straight quotes open when they follow a
+/// space
+const OPENERS: [char; 5] = ['(', '[', '{', '\u{201C}', '\u{2018}'];
+
+/// Closing brackets and quotes
+const CLOSERS: [char; 7] = [')', ']', '}', '"', '\'', '\u{201D}', '\u{2019}'];
+
+/// Characters that join the words on either side without a space
+const JOINERS: [char; 3] = ['-', '/', '$'];
+
+/// `text` as it's inserted between `before` and `after`, the text on either
+/// side of the cursor
+///
+/// - After the end of a sentence, a blank line or at the start of the
+///   document, the first letter is capitalized. Mid-sentence (including
+///   after "e.g." and other abbreviations) the first word is lowercased,
+///   unless it's "I", an acronym, capitalized inside or capitalized
+///   mid-sentence in the surrounding text, as names are. After an opening
+///   quote, an ellipsis, a title such as "Dr." or an initial it's left as
+///   formatted.
+/// - Punctuation the formatter put before the first word is dropped, and
+///   so is its final period when the text after the cursor goes on with
+///   the sentence or has punctuation of its own.
+/// - A space is added on a side only when the text there doesn't already
+///   have one and doesn't end (or start) with a bracket, quote, hyphen or
+///   punctuation that attaches to the word next to it.
+pub fn join_at_cursor(before: &str, after: &str, text: &str) -> String {
+    let text = fit_end(before, after, &fit_start(before, after, text.trim()));
+    pad(before, after, &text)
+}
+
+/// How text after the cursor starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opening {
+    Sentence,
+    MidSentence,
+    /// After an opening quote, an ellipsis or before a name, where either
+    /// can follow
+    AsFormatted,
+}
+
+/// The first letter and leading punctuation of `text` fitted to `before`
+pub(crate) fn fit_start(before: &str, after: &str, text: &str) -> String {
+    // ". and then" or ", and then" from a formatter that didn't know
+    let text = match text.strip_prefix(['.', ',', ';', ':']) {
+        Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+        _ => text,
+    };
+    match opening(before) {
+        Opening::Sentence => capitalize_first(text),
+        Opening::MidSentence => lowercase_first_word(text, before, after),
+        Opening::AsFormatted => text.to_string(),
+    }
+}
+
+/// The final punctuation of `text` fitted to `after`
+pub(crate) fn fit_end(before: &str, after: &str, text: &str) -> String {
+    let rest = after.trim_start_matches([' ', '\t']);
+    // A bracket closed mid-sentence: "(see the notes)"
+    let text = if rest.starts_with([')', ']']) && opening(before) != Opening::Sentence {
+        without_final_period(text)
+    } else {
+        text
+    };
+    match past_closers(rest).trim_start_matches([' ', '\t']).chars().next() {
+        Some('.') => text.trim_end_matches([',', ';', ':', '.']).to_string(),
+        Some(',' | ';' | ':' | '!' | '?') => without_final_period(text.trim_end_matches([',', ';', ':'])).to_string(),
+        Some(c) if c.is_lowercase() || c.is_numeric() => without_final_period(text).to_string(),
+        _ => text.to_string(),
+    }
+}
+
+/// `text` with the spaces it needs between `before` and `after`
+pub(crate) fn pad(before: &str, after: &str, text: &str) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    let lead = if space_between(before, text) { " " } else { "" };
+    let trail = if space_between(text, after) { " " } else { "" };
+    format!("{}{}{}", lead, text, trail)
+}
+
+fn opening(before: &str) -> Opening {
+    let trimmed = before.trim_end_matches([' ', '\t']);
+    if trimmed.trim().is_empty() || trimmed.ends_with('\n') {
+        return Opening::Sentence;
+    }
+    if let Some((rest, opener)) = strip_opener(trimmed) {
+        return match opening(rest) {
+            Opening::Sentence => Opening::Sentence,
+            _ if matches!(opener, '"' | '\'' | '\u{201C}' | '\u{2018}') => Opening::AsFormatted,
+            other => other,
+        };
+    }
+    // Closing quotes and brackets after the sentence's own punctuation
+    let end = trimmed.trim_end_matches(CLOSERS);
+    if end.ends_with("...") || end.ends_with('\u{2026}') || before_name(end) {
+        Opening::AsFormatted
+    } else if ends_sentence(end) {
+        Opening::Sentence
+    } else {
+        Opening::MidSentence
+    }
+}
+
+/// Whether `text` ends with sentence punctuation that isn't an
+/// abbreviation's
+fn ends_sentence(text: &str) -> bool {
+    let word = last_word(text);
+    text.ends_with(['.', '?', '!']) && !ABBREVIATIONS.contains(&word.as_str()) && !before_name(text)
+}
+
+/// Whether `text` ends with a title or an initial, e.g. "Dr." or "J."
+fn before_name(text: &str) -> bool {
+    let word = text.rsplit(char::is_whitespace).next().unwrap_or_default();
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    let mut chars = word.chars();
+    let initial = matches!((chars.next(), chars.next(), chars.next()), (Some(c), Some('.'), None) if c.is_uppercase());
+    initial || TITLES.contains(&word.to_lowercase().as_str())
+}
+
+/// The last word of `text`, lowercased and without the brackets or quotes
+/// before it
+fn last_word(text: &str) -> String {
+    let word = text.rsplit(char::is_whitespace).next().unwrap_or_default();
+    word.trim_start_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+/// `text` without the opening bracket or quote it ends with, and that
+/// opener
+fn strip_opener(text: &str) -> Option<(&str, char)> {
+    let last = text.chars().next_back()?;
+    let rest = &text[..text.len() - last.len_utf8()];
+    let straight_opens = matches!(last, '"' | '\'')
+        && rest.chars().next_back().is_none_or(|c| c.is_whitespace() || OPENERS.contains(&c));
+    (OPENERS.contains(&last) || straight_opens).then_some((rest, last))
+}
+
+/// `text` without its final period, unless the period is an
+/// abbreviation's or part of an ellipsis
+fn without_final_period(text: &str) -> &str {
+    // "etc." usually ends the sentence too, but its period stays
+    let word = last_word(text);
+    let abbreviation = ABBREVIATIONS.contains(&word.as_str()) || word == "etc." || before_name(text);
+    match text.strip_suffix('.') {
+        Some(rest) if !rest.ends_with('.') && !abbreviation => rest,
+        _ => text,
+    }
+}
+
+/// `text` after the closing brackets and quotes it starts with; a straight
+/// quote just before a word opens a quotation instead
+fn past_closers(mut text: &str) -> &str {
+    while let Some(c) = text.chars().next() {
+        let opens = matches!(c, '"' | '\'') && text[1..].starts_with(char::is_alphanumeric);
+        if !CLOSERS.contains(&c) || opens {
+            break;
+        }
+        text = &text[c.len_utf8()..];
+    }
+    text
+}
+
+/// Whether a space goes between `left` and `right`
+fn space_between(left: &str, right: &str) -> bool {
+    let (Some(last), Some(first)) = (left.chars().next_back(), right.chars().next()) else {
+        return false;
+    };
+    if last.is_whitespace() || first.is_whitespace() || JOINERS.contains(&last) || strip_opener(left).is_some() {
+        return false;
+    }
+    match first {
+        // An opening straight quote starts a word; a closing one ends one
+        '"' => right[1..].starts_with(char::is_alphanumeric),
+        '.' | ',' | ';' | ':' | '!' | '?' | '%' | '\'' | '-' | '/' => false,
+        c => !CLOSERS.contains(&c),
+    }
+}
+
+fn capitalize_first(text: &str) -> String {
+    match text.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((i, c)) if c.is_lowercase() => {
+            format!("{}{}{}", &text[..i], c.to_uppercase(), &text[i + c.len_utf8()..])
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn lowercase_first_word(text: &str, before: &str, after: &str) -> String {
+    let word_end = text.find(char::is_whitespace).unwrap_or(text.len());
+    let word = text[..word_end].trim_matches(|c: char| !c.is_alphanumeric());
+    let Some(first) = word.chars().next() else {
+        return text.to_string();
+    };
+    if !first.is_uppercase()
+        || ALWAYS_CAPITALIZED.contains(&word)
+        || word.chars().skip(1).any(char::is_uppercase)
+        || capitalized_nearby(word, before)
+        || capitalized_nearby(word, after)
+    {
+        return text.to_string();
+    }
+    let start = text.find(first).unwrap_or(0);
+    format!("{}{}{}", &text[..start], first.to_lowercase(), &text[start + first.len_utf8()..])
+}
+
+/// Whether `word` is capitalized mid-sentence in `text`, as a name is
+fn capitalized_nearby(word: &str, text: &str) -> bool {
+    let mut previous: Option<&str> = None;
+    text.split_whitespace().any(|token| {
+        let found = token.trim_matches(|c: char| !c.is_alphanumeric()) == word
+            && previous.is_some_and(|previous| !ends_sentence(previous.trim_end_matches(CLOSERS)));
+        previous = Some(token);
+        found
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_at_cursor() {
+        // (text before the cursor, text after it, formatted text, inserted text)
+        let cases: &[(&str, &str, &str, &str)] = &[
+            // Start of the document
+            ("", "", "Hello there.", "Hello there."),
+            ("", "", "hello there.", "Hello there."),
+            ("", "", "  Hello there.  ", "Hello there."),
+            ("", "The rest follows.", "Intro first.", "Intro first. "),
+            ("", "and the rest", "Some text.", "Some text "),
+            ("\n\n", "", "buy milk", "Buy milk"),
+            // After the end of a sentence
+            ("It shipped.", "", "then we celebrated.", " Then we celebrated."),
+            ("It shipped. ", "", "then we celebrated.", "Then we celebrated."),
+            ("Did it ship?", "", "yes.", " Yes."),
+            ("Wow!", "", "great news.", " Great news."),
+            ("He said \"done.\"", "", "good.", " Good."),
+            ("(See the notes.)", "", "then reply.", " Then reply."),
+            ("Notes\n", "", "buy milk", "Buy milk"),
+            ("Dear Sam,\n\n", "", "thanks for the notes.", "Thanks for the notes."),
+            // Mid-sentence
+            ("We met on Tuesday and", "", "Talked about the launch.", " talked about the launch."),
+            ("We met on Tuesday and ", "", "Talked about the launch.", "talked about the launch."),
+            ("Thanks for the notes,", "", ". Appreciate it.", " appreciate it."),
+            ("Thanks for the notes, ", "", ", Appreciate it.", "appreciate it."),
+            ("The plan:", "", "Ship it.", " ship it."),
+            ("Bring", "", "I think two.", " I think two."),
+            ("Ask", "", "NASA first.", " NASA first."),
+            ("Open", "", "GitHub now.", " GitHub now."),
+            ("Hi Sam, can you tell", "", "Sam to call me.", " Sam to call me."),
+            ("We flew to Paris and", "", "Paris was lovely.", " Paris was lovely."),
+            ("Call", "and ask Anna too.", "Anna first.", " Anna first "),
+            ("Bring fruit, e.g.", "", "Apples and pears.", " apples and pears."),
+            ("Ask Dr.", "", "Patel about it.", " Patel about it."),
+            ("Written by J.", "", "Smith.", " Smith."),
+            ("That is, i.e.", "", "The short one.", " the short one."),
+            ("Well...", "", "Maybe not.", " Maybe not."),
+            ("Well...", "", "maybe not.", " maybe not."),
+            // Brackets and quotes
+            ("We'll meet (", ")", "Probably Monday.", "probably Monday"),
+            ("Done. (", ")", "see the notes.", "See the notes."),
+            ("He said \"", "\"", "We're done.", "We're done."),
+            ("He said \"", "\" and left.", "We're done.", "We're done"),
+            ("The \u{201C}", "\u{201D} option", "Fast.", "Fast"),
+            ("She wrote", "\"Soon\" on the card.", "just", " just "),
+            // What follows the cursor
+            ("We need", "for the trip.", "Three bags.", " three bags "),
+            ("We need", " for the trip.", "Three bags.", " three bags"),
+            ("We need", ".", "Three bags.", " three bags"),
+            ("We need", ", I think.", "Three bags.", " three bags"),
+            ("Is it", "?", "Ready.", " ready"),
+            ("Bring", "; or four.", "Three bags,", " three bags"),
+            ("Meet at", "2pm.", "Noon or.", " noon or "),
+            ("We're done", "See you.", "For today.", " for today. "),
+            ("Bring e.g.", ", apples", "Pears etc.", " pears etc."),
+            ("We need", "\n- tents", "Three bags.", " three bags."),
+            // Inside a word, and joining characters
+            ("inter", "tional", "Nation.", " nation "),
+            ("state-of-", "", "The art.", "the art."),
+            ("It costs $", "", "Five.", "five."),
+            ("Score of 90", "", "%", "%"),
+            // Nothing dictated
+            ("Hello", "world", "", ""),
+        ];
+        for &(before, after, text, expected) in cases {
+            assert_eq!(join_at_cursor(before, after, text), expected, "before {:?}, after {:?}, text {:?}", before, after, text);
+        }
+        assert!(cases.len() >= 40);
+    }
+}
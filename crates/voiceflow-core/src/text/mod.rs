@@ -0,0 +1,11 @@
+//! Text post-processing: replacement rules applied after formatting, spoken
+//! numbers written as digits before it, and fitting the result into the
+//! text around the cursor
+
+pub(crate) mod join;
+mod numbers;
+mod rules;
+
+pub use join::join_at_cursor;
+pub use numbers::normalize_numbers;
+pub use rules::ReplacementRules;
@@ -0,0 +1,1017 @@
+//! Inverse text normalization: spoken numbers, times, dates, amounts and
+//! phone numbers rewritten the way they are written ("twelve hundred
+//! dollars" → "$1,200")
+//!
+//! Rule-based, so the result doesn't depend on what the LLM feels like
+//! doing. Only English number words are recognized.
+
+use crate::config::{NumberFormatting, NumberLocale};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Fewest digits read out one by one that make a phone number
+const MIN_PHONE_DIGITS: usize = 7;
+
+/// Words after "twenty second" that keep it an ordinal ("the twenty second
+/// of the month") rather than a length of time before a noun
+const AFTER_ORDINAL: [&str; 13] = ["of", "and", "or", "in", "on", "at", "to", "for", "the", "a", "an", "is", "was"];
+
+/// Rewrite spoken numbers in `text` as digits, following the rule classes
+/// and locale `options` turn on
+///
+/// Standalone numbers below ten stay spelled out ("one of them"), as style
+/// guides recommend; in amounts, times and dates they become digits too.
+pub fn normalize_numbers(text: &str, options: &NumberFormatting) -> String {
+    if !options.enabled {
+        return text.to_string();
+    }
+    if text.contains('\n') {
+        return text.split('\n').map(|line| normalize_numbers(line, options)).collect::<Vec<_>>().join("\n");
+    }
+    let tokens = tokenize(text);
+    let mut out: Vec<(String, bool)> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        match rewrite_at(&tokens, i, options) {
+            Some((end, written)) => {
+                let last = &tokens[end - 1];
+                // "p.m." ending a sentence also ends it with its period
+                let trail = match last.trail {
+                    "" if end == tokens.len() && last.core.ends_with('.') => ".",
+                    trail => trail,
+                };
+                out.push((format!("{}{}{}", tokens[i].lead, written, trail), last.hyphen_after));
+                i = end;
+            }
+            None => {
+                let token = &tokens[i];
+                out.push((format!("{}{}{}", token.lead, token.core, token.trail), token.hyphen_after));
+                i += 1;
+            }
+        }
+    }
+
+    let mut result = String::with_capacity(text.len());
+    for (index, (piece, hyphen_after)) in out.iter().enumerate() {
+        result.push_str(piece);
+        if index + 1 < out.len() {
+            result.push(if *hyphen_after { '-' } else { ' ' });
+        }
+    }
+    result
+}
+
+/// The first rule matching at `tokens[i]`, as the end of the words it
+/// covers and their written form
+fn rewrite_at(tokens: &[Token], i: usize, options: &NumberFormatting) -> Option<(usize, String)> {
+    let locale = options.locale;
+    let rules: [(bool, Rule); 5] = [
+        (options.phone_numbers, phone_number),
+        (options.times, time),
+        (options.dates, date),
+        (options.currencies, currency),
+        (options.percentages, percentage),
+    ];
+    rules
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .find_map(|(_, rule)| rule(tokens, i, locale))
+        .or_else(|| number(tokens, i, options))
+}
+
+/// A rule reading a phrase at a token, returning the index after it and its
+/// written form
+type Rule = fn(&[Token], usize, NumberLocale) -> Option<(usize, String)>;
+
+/// A whitespace-separated word split from its punctuation
+struct Token<'a> {
+    /// Punctuation before the word, like an opening quote
+    lead: &'a str,
+    core: &'a str,
+    /// `core` lowercased
+    word: String,
+    /// Punctuation after the word
+    trail: &'a str,
+    /// The word was joined to the next one by a hyphen ("twenty-one")
+    hyphen_after: bool,
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for piece in text.split_whitespace() {
+        let core_start = piece.find(|c: char| c.is_alphanumeric()).unwrap_or(piece.len());
+        let core_end = piece
+            .rfind(|c: char| c.is_alphanumeric())
+            .map_or(core_start, |i| i + piece[i..].chars().next().map_or(0, char::len_utf8));
+        // Abbreviations keep their last period: "a.m."
+        let abbreviation = piece[core_start..core_end].contains('.') && piece[core_end..].starts_with('.');
+        let core_end = if abbreviation { core_end + 1 } else { core_end };
+        let (lead, core, trail) = (&piece[..core_start], &piece[core_start..core_end], &piece[core_end..]);
+
+        // "twenty-one" is read as two number words
+        let parts: Vec<&str> = core.split('-').collect();
+        if parts.len() > 1 && parts.iter().all(|part| classify(&part.to_lowercase()).is_some()) {
+            let last = parts.len() - 1;
+            for (index, part) in parts.into_iter().enumerate() {
+                tokens.push(Token {
+                    lead: if index == 0 { lead } else { "" },
+                    core: part,
+                    word: part.to_lowercase(),
+                    trail: if index == last { trail } else { "" },
+                    hyphen_after: index != last,
+                });
+            }
+        } else {
+            tokens.push(Token { lead, core, word: core.to_lowercase(), trail, hyphen_after: false });
+        }
+    }
+    tokens
+}
+
+/// The token at `k` when it continues the words from `start`: nothing
+/// but whitespace separates it from the one before
+fn at<'t, 'a>(tokens: &'t [Token<'a>], start: usize, k: usize) -> Option<&'t Token<'a>> {
+    let token = tokens.get(k)?;
+    if k > start && (!tokens[k - 1].trail.is_empty() || !token.lead.is_empty()) {
+        return None;
+    }
+    Some(token)
+}
+
+fn word_at<'t>(tokens: &'t [Token], start: usize, k: usize) -> Option<&'t str> {
+    at(tokens, start, k).map(|token| token.word.as_str())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Word {
+    Unit(u64),
+    Teen(u64),
+    Tens(u64),
+    Hundred,
+    Scale(u64),
+}
+
+/// Number word and whether it is an ordinal
+fn classify(word: &str) -> Option<(Word, bool)> {
+    use Word::*;
+    let class = match word {
+        "zero" => (Unit(0), false),
+        "one" => (Unit(1), false),
+        "two" => (Unit(2), false),
+        "three" => (Unit(3), false),
+        "four" => (Unit(4), false),
+        "five" => (Unit(5), false),
+        "six" => (Unit(6), false),
+        "seven" => (Unit(7), false),
+        "eight" => (Unit(8), false),
+        "nine" => (Unit(9), false),
+        "ten" => (Teen(10), false),
+        "eleven" => (Teen(11), false),
+        "twelve" => (Teen(12), false),
+        "thirteen" => (Teen(13), false),
+        "fourteen" => (Teen(14), false),
+        "fifteen" => (Teen(15), false),
+        "sixteen" => (Teen(16), false),
+        "seventeen" => (Teen(17), false),
+        "eighteen" => (Teen(18), false),
+        "nineteen" => (Teen(19), false),
+        "twenty" => (Tens(20), false),
+        "thirty" => (Tens(30), false),
+        "forty" => (Tens(40), false),
+        "fifty" => (Tens(50), false),
+        "sixty" => (Tens(60), false),
+        "seventy" => (Tens(70), false),
+        "eighty" => (Tens(80), false),
+        "ninety" => (Tens(90), false),
+        "hundred" => (Hundred, false),
+        "thousand" => (Scale(1_000), false),
+        "million" => (Scale(1_000_000), false),
+        "billion" => (Scale(1_000_000_000), false),
+        "trillion" => (Scale(1_000_000_000_000), false),
+        "first" => (Unit(1), true),
+        "second" => (Unit(2), true),
+        "third" => (Unit(3), true),
+        "fourth" => (Unit(4), true),
+        "fifth" => (Unit(5), true),
+        "sixth" => (Unit(6), true),
+        "seventh" => (Unit(7), true),
+        "eighth" => (Unit(8), true),
+        "ninth" => (Unit(9), true),
+        "tenth" => (Teen(10), true),
+        "eleventh" => (Teen(11), true),
+        "twelfth" => (Teen(12), true),
+        "thirteenth" => (Teen(13), true),
+        "fourteenth" => (Teen(14), true),
+        "fifteenth" => (Teen(15), true),
+        "sixteenth" => (Teen(16), true),
+        "seventeenth" => (Teen(17), true),
+        "eighteenth" => (Teen(18), true),
+        "nineteenth" => (Teen(19), true),
+        "twentieth" => (Tens(20), true),
+        "thirtieth" => (Tens(30), true),
+        "fortieth" => (Tens(40), true),
+        "fiftieth" => (Tens(50), true),
+        "sixtieth" => (Tens(60), true),
+        "seventieth" => (Tens(70), true),
+        "eightieth" => (Tens(80), true),
+        "ninetieth" => (Tens(90), true),
+        "hundredth" => (Hundred, true),
+        "thousandth" => (Scale(1_000), true),
+        "millionth" => (Scale(1_000_000), true),
+        _ => return None,
+    };
+    Some(class)
+}
+
+/// A digit read out on its own, "oh" included
+fn digit(word: &str) -> Option<u64> {
+    match word {
+        "oh" | "o" => Some(0),
+        _ => match classify(word) {
+            Some((Word::Unit(n), false)) => Some(n),
+            _ => None,
+        },
+    }
+}
+
+/// A spoken number
+#[derive(Debug, Clone, PartialEq)]
+struct Number {
+    /// Whole part, or what multiplies `scale`
+    value: u64,
+    /// Digits read after "point"
+    decimals: Option<String>,
+    /// "million" or larger, kept as a word: "two million" is written
+    /// "2 million"
+    scale: Option<&'static str>,
+    ordinal: bool,
+    /// Said with "hundred" ("twelve hundred")
+    has_hundred: bool,
+    /// Index after the last word
+    end: usize,
+}
+
+impl Number {
+    /// Whole number without decimals or scale word
+    fn integer(&self) -> Option<u64> {
+        (self.decimals.is_none() && self.scale.is_none() && !self.ordinal).then_some(self.value)
+    }
+
+    /// Written with digits, "1,200" or "1.5 million"
+    fn digits(&self) -> String {
+        let mut written = group_thousands(self.value);
+        if let Some(decimals) = &self.decimals {
+            written = format!("{}.{}", written, decimals);
+        }
+        if let Some(scale) = self.scale {
+            written = format!("{} {}", written, scale);
+        }
+        written
+    }
+}
+
+/// What came last while reading a number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Last {
+    Start,
+    Unit,
+    Teen,
+    Tens,
+    TensUnit,
+    Hundred,
+    Scale,
+    And,
+}
+
+/// Read the number starting at `tokens[start]`
+///
+/// Words must follow each other as in a written-out number: "one two" is
+/// two numbers and "twenty thirty" too.
+fn parse_number(tokens: &[Token], start: usize) -> Option<Number> {
+    let mut total = 0u64;
+    let mut current = 0u64;
+    let mut last = Last::Start;
+    let mut last_scale = u64::MAX;
+    let mut ordinal = false;
+    let mut has_hundred = false;
+    let mut k = start;
+
+    // "a hundred", "a thousand"
+    let scale_follows =
+        |k| matches!(word_at(tokens, start, k).and_then(classify), Some((Word::Hundred | Word::Scale(_), _)));
+    if word_at(tokens, start, start) == Some("a") && scale_follows(start + 1) {
+        current = 1;
+        last = Last::Unit;
+        k += 1;
+    }
+
+    while let Some(token) = at(tokens, start, k) {
+        if token.word == "and" && matches!(last, Last::Hundred | Last::Scale) {
+            let below_hundred = matches!(
+                word_at(tokens, start, k + 1).and_then(classify),
+                Some((Word::Unit(_) | Word::Teen(_) | Word::Tens(_), _))
+            );
+            if !below_hundred {
+                break;
+            }
+            last = Last::And;
+            k += 1;
+            continue;
+        }
+        let Some((word, is_ordinal)) = classify(&token.word) else { break };
+        let (next_current, next_last) = match word {
+            Word::Unit(n) if matches!(last, Last::Start | Last::Tens | Last::Hundred | Last::Scale | Last::And) => {
+                (current + n, if last == Last::Tens { Last::TensUnit } else { Last::Unit })
+            }
+            Word::Teen(n) if matches!(last, Last::Start | Last::Hundred | Last::Scale | Last::And) => {
+                (current + n, Last::Teen)
+            }
+            Word::Tens(n) if matches!(last, Last::Start | Last::Hundred | Last::Scale | Last::And) => {
+                (current + n, Last::Tens)
+            }
+            Word::Hundred if matches!(last, Last::Unit | Last::Teen | Last::Tens | Last::TensUnit) && current < 100 => {
+                has_hundred = true;
+                (current * 100, Last::Hundred)
+            }
+            Word::Scale(scale)
+                if matches!(last, Last::Unit | Last::Teen | Last::Tens | Last::TensUnit | Last::Hundred)
+                    && scale < last_scale =>
+            {
+                total += current * scale;
+                last_scale = scale;
+                (0, Last::Scale)
+            }
+            _ => break,
+        };
+        current = next_current;
+        last = next_last;
+        k += 1;
+        if is_ordinal {
+            ordinal = true;
+            break;
+        }
+    }
+    if last == Last::Start || last == Last::And {
+        return None;
+    }
+
+    let mut number = Number { value: total + current, decimals: None, scale: None, ordinal, has_hundred, end: k };
+    if ordinal {
+        return Some(number);
+    }
+
+    // "three point one four"
+    if word_at(tokens, start, k) == Some("point") {
+        let mut decimals = String::new();
+        let mut d = k + 1;
+        while let Some(n) = word_at(tokens, start, d).and_then(digit) {
+            decimals.push_str(&n.to_string());
+            d += 1;
+        }
+        if !decimals.is_empty() {
+            number.decimals = Some(decimals);
+            number.end = d;
+        }
+    }
+
+    // "two million", "one point five billion"
+    let scale_name = |scale| match scale {
+        1_000_000 => Some("million"),
+        1_000_000_000 => Some("billion"),
+        1_000_000_000_000 => Some("trillion"),
+        _ => None,
+    };
+    if number.decimals.is_some() {
+        if let Some((Word::Scale(scale), false)) = word_at(tokens, start, number.end).and_then(classify) {
+            if let Some(name) = scale_name(scale) {
+                number.scale = Some(name);
+                number.end += 1;
+            }
+        }
+    } else if last == Last::Scale && total.is_multiple_of(last_scale) && total / last_scale < 1000 {
+        if let Some(name) = scale_name(last_scale) {
+            number.value = total / last_scale;
+            number.scale = Some(name);
+        }
+    }
+    Some(number)
+}
+
+/// A number from 10 to 99 starting with a teen or tens word, as read in
+/// years and clock times
+fn parse_two_digits(tokens: &[Token], start: usize, k: usize) -> Option<(u64, usize)> {
+    match classify(word_at(tokens, start, k)?)? {
+        (Word::Teen(n), false) => Some((n, k + 1)),
+        (Word::Tens(n), false) => match word_at(tokens, start, k + 1).and_then(classify) {
+            Some((Word::Unit(u), false)) if u > 0 => Some((n + u, k + 2)),
+            _ => Some((n, k + 1)),
+        },
+        _ => None,
+    }
+}
+
+/// A year read in two halves ("nineteen eighty four", "twenty oh five"),
+/// or in full from 1000 to 2999 ("two thousand and five")
+///
+/// Outside dates only years starting with nineteen or twenty count, so
+/// "twelve fifteen" is left alone.
+fn parse_year(tokens: &[Token], start: usize, k: usize, in_date: bool) -> Option<(u64, usize)> {
+    if let Some((century, next)) =
+        parse_two_digits(tokens, start, k).filter(|(n, next)| *next == k + 1 && (11..=20).contains(n))
+    {
+        if in_date || century >= 19 {
+            if word_at(tokens, start, next) == Some("hundred") {
+                return Some((century * 100, next + 1));
+            }
+            if let (Some(0), Some(n)) =
+                (word_at(tokens, start, next).and_then(digit), word_at(tokens, start, next + 1).and_then(digit))
+            {
+                if n > 0 && word_at(tokens, start, next) != Some("zero") {
+                    return Some((century * 100 + n, next + 2));
+                }
+            }
+            if let Some((n, end)) = parse_two_digits(tokens, start, next) {
+                return Some((century * 100 + n, end));
+            }
+        }
+    }
+    if !in_date {
+        return None;
+    }
+    let number = parse_number(tokens, k)?;
+    let year = number.integer().filter(|year| (1000..3000).contains(year) && !number.has_hundred)?;
+    Some((year, number.end))
+}
+
+/// Digits read one by one: "five five five one two three four"
+fn phone_number(tokens: &[Token], i: usize, locale: NumberLocale) -> Option<(usize, String)> {
+    let mut digits = String::new();
+    let mut k = i;
+    while let Some(word) = word_at(tokens, i, k) {
+        let repeat = match word {
+            "double" => 2,
+            "triple" => 3,
+            _ => 1,
+        };
+        let (d, next) = if repeat > 1 {
+            match word_at(tokens, i, k + 1).and_then(digit) {
+                Some(d) => (d, k + 2),
+                None => break,
+            }
+        } else {
+            match digit(word) {
+                Some(d) => (d, k + 1),
+                None => break,
+            }
+        };
+        for _ in 0..repeat {
+            digits.push_str(&d.to_string());
+        }
+        k = next;
+    }
+    if digits.len() < MIN_PHONE_DIGITS {
+        return None;
+    }
+    let groups: &[usize] = match (locale, digits.len()) {
+        (NumberLocale::EnUs, 7) => &[3, 4],
+        (NumberLocale::EnUs, 10) => &[3, 3, 4],
+        (NumberLocale::EnUs, 11) if digits.starts_with('1') => &[1, 3, 3, 4],
+        (NumberLocale::EnGb, 11) if digits.starts_with("02") => &[3, 4, 4],
+        (NumberLocale::EnGb, 11) if digits.starts_with('0') => &[5, 6],
+        (NumberLocale::EnGb, 10) if digits.starts_with('0') => &[5, 5],
+        _ => &[],
+    };
+    let separator = match locale {
+        NumberLocale::EnUs => "-",
+        NumberLocale::EnGb => " ",
+    };
+    let mut parts = Vec::new();
+    let mut rest = digits.as_str();
+    for &len in groups {
+        let (part, tail) = rest.split_at(len);
+        parts.push(part);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        parts.push(rest);
+    }
+    Some((k, parts.join(separator)))
+}
+
+/// "am" or "pm", with or without periods or spaces, and the index after it
+fn day_half(tokens: &[Token], start: usize, k: usize) -> Option<(bool, usize)> {
+    let word = word_at(tokens, start, k)?.replace('.', "");
+    match word.as_str() {
+        "am" => Some((false, k + 1)),
+        "pm" => Some((true, k + 1)),
+        "a" | "p" if word_at(tokens, start, k + 1) == Some("m") => Some((word == "p", k + 2)),
+        _ => None,
+    }
+}
+
+/// Clock times: "three thirty pm", "seven am", "five o'clock", "at fifteen
+/// forty five", "quarter past nine"
+fn time(tokens: &[Token], i: usize, locale: NumberLocale) -> Option<(usize, String)> {
+    let (hour, minutes, k) = match word_at(tokens, i, i)? {
+        "half" | "quarter" => {
+            let past = match word_at(tokens, i, i + 1)? {
+                "past" => true,
+                "to" if tokens[i].word == "quarter" => false,
+                _ => return None,
+            };
+            let hour = parse_number(tokens, i + 2)?;
+            let hour = hour.integer().filter(|h| (1..=12).contains(h) && hour.end == i + 3)?;
+            match (tokens[i].word.as_str(), past) {
+                ("half", _) => (hour, Some(30), i + 3),
+                (_, true) => (hour, Some(15), i + 3),
+                (_, false) => (if hour == 1 { 12 } else { hour - 1 }, Some(45), i + 3),
+            }
+        }
+        _ => {
+            let number = parse_number(tokens, i)?;
+            let hour = number.integer().filter(|h| *h <= 23 && number.end <= i + 2)?;
+            let k = number.end;
+            let oh_minutes = match (word_at(tokens, i, k).and_then(digit), word_at(tokens, i, k + 1).and_then(digit)) {
+                (Some(0), Some(n)) if n > 0 => Some((n, k + 2)),
+                _ => None,
+            };
+            match oh_minutes.or_else(|| parse_two_digits(tokens, i, k).filter(|(m, _)| *m < 60)) {
+                Some((minutes, end)) => (hour, Some(minutes), end),
+                None => (hour, None, k),
+            }
+        }
+    };
+
+    if let Some((pm, end)) = day_half(tokens, i, k).filter(|_| (1..=12).contains(&hour)) {
+        let marker = match (locale, pm) {
+            (NumberLocale::EnUs, false) => "AM",
+            (NumberLocale::EnUs, true) => "PM",
+            (NumberLocale::EnGb, false) => "am",
+            (NumberLocale::EnGb, true) => "pm",
+        };
+        let written = match minutes {
+            Some(minutes) => format!("{}:{:02} {}", hour, minutes, marker),
+            None => format!("{} {}", hour, marker),
+        };
+        return Some((end, written));
+    }
+    if minutes.is_none() {
+        let oclock = matches!(word_at(tokens, i, k), Some("o'clock" | "oclock" | "o’clock"));
+        return (oclock && (1..=12).contains(&hour)).then(|| (k + 1, format!("{} o'clock", hour)));
+    }
+    // Without "am" or "pm", only after "at": "three thirty" may not be a time
+    let after_at = i > 0 && tokens[i - 1].word == "at" && at(tokens, i - 1, i).is_some();
+    (after_at || matches!(tokens[i].word.as_str(), "half" | "quarter"))
+        .then(|| (k, format!("{}:{:02}", hour, minutes.unwrap_or(0))))
+}
+
+fn month(word: &str) -> Option<&'static str> {
+    MONTHS.iter().copied().find(|month| month.eq_ignore_ascii_case(word))
+}
+
+fn ordinal_suffix(n: u64) -> &'static str {
+    match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    }
+}
+
+/// Dates: "january fifth", "march third twenty twenty five", "the fourth
+/// of july", "june nineteen ninety nine"
+fn date(tokens: &[Token], i: usize, locale: NumberLocale) -> Option<(usize, String)> {
+    // "the fourth of july"
+    if let Some(day) = parse_number(tokens, i).filter(|n| n.ordinal && (1..=31).contains(&n.value)) {
+        let name = word_at(tokens, i, day.end + 1).and_then(month);
+        if word_at(tokens, i, day.end) != Some("of") || name.is_none() {
+            return None;
+        }
+        let mut written = format!("{}{} of {}", day.value, ordinal_suffix(day.value), name?);
+        let mut end = day.end + 2;
+        if let Some((year, year_end)) = parse_year(tokens, i, end, true) {
+            written = format!("{} {}", written, year);
+            end = year_end;
+        }
+        return Some((end, written));
+    }
+
+    let name = month(&tokens[i].word)?;
+    let mut k = i + 1;
+    if let Some((year, end)) = parse_year(tokens, i, k, true) {
+        return Some((end, format!("{} {}", name, year)));
+    }
+    let the = word_at(tokens, i, k) == Some("the");
+    if the {
+        k += 1;
+    }
+    // "may" and "march" are verbs too: "may one day" and "you may first
+    // check" are no dates
+    let verb = matches!(name, "May" | "March");
+    let day = parse_number(tokens, k)
+        .filter(|n| n.decimals.is_none() && n.scale.is_none() && (1..=31).contains(&n.value))
+        .filter(|n| n.ordinal || !(the || verb))?;
+    at(tokens, i, k)?;
+    let mut end = day.end;
+
+    // The year may follow a comma: "january fifth, twenty twenty five"
+    let last = &tokens[end - 1];
+    let year = if last.trail.is_empty() || last.trail == "," {
+        tokens.get(end).filter(|token| token.lead.is_empty()).and_then(|_| parse_year(tokens, end, end, true))
+    } else {
+        None
+    };
+    // ...so only "may the fourth" or a day with its year is one
+    if verb && !the && year.is_none() {
+        return None;
+    }
+    let day = day.value;
+    let written = match (locale, year) {
+        (NumberLocale::EnUs, None) => format!("{} {}{}", name, day, ordinal_suffix(day)),
+        (NumberLocale::EnUs, Some((year, _))) => format!("{} {}{}, {}", name, day, ordinal_suffix(day), year),
+        (NumberLocale::EnGb, None) => format!("{} {}", day, name),
+        (NumberLocale::EnGb, Some((year, _))) => format!("{} {} {}", day, name, year),
+    };
+    if let Some((_, year_end)) = year {
+        end = year_end;
+    }
+    Some((end, written))
+}
+
+/// Money: "twelve hundred dollars", "five dollars and fifty cents", "two
+/// million euros", and in en-GB "ten pounds fifty" and "fifty pence"
+fn currency(tokens: &[Token], i: usize, locale: NumberLocale) -> Option<(usize, String)> {
+    let amount = parse_number(tokens, i).filter(|n| !n.ordinal)?;
+    let (symbol, minor_units): (&str, &[&str]) = match (word_at(tokens, i, amount.end)?, locale) {
+        ("dollar" | "dollars" | "buck" | "bucks", _) => ("$", &["cent", "cents"]),
+        ("euro" | "euros", _) => ("€", &["cent", "cents"]),
+        ("pound" | "pounds", NumberLocale::EnGb) => ("£", &["p", "pence", "penny"]),
+        ("p" | "pence", NumberLocale::EnGb) => {
+            let pence = amount.integer()?;
+            return Some((amount.end + 1, format!("{}p", pence)));
+        }
+        _ => return None,
+    };
+    let mut end = amount.end + 1;
+
+    // "and fifty cents", "fifty"
+    let mut minor = None;
+    if let Some(whole) = amount.integer() {
+        let and = word_at(tokens, i, end) == Some("and");
+        let start = if and { end + 1 } else { end };
+        if at(tokens, i, start).is_some() {
+            if let Some(cents) =
+                parse_number(tokens, start).filter(|n| n.integer().is_some_and(|c| (1..100).contains(&c)))
+            {
+                let unit = word_at(tokens, i, cents.end).is_some_and(|word| minor_units.contains(&word));
+                if unit || (!and && cents.value >= 10) {
+                    minor = Some(cents.value);
+                    end = if unit { cents.end + 1 } else { cents.end };
+                }
+            }
+        }
+        let written = match minor {
+            Some(cents) => format!("{}{}.{:02}", symbol, group_thousands(whole), cents),
+            None => format!("{}{}", symbol, group_thousands(whole)),
+        };
+        return Some((end, written));
+    }
+    let mut digits = amount.digits();
+    if amount.scale.is_none() && amount.decimals.as_ref().is_some_and(|d| d.len() == 1) {
+        digits.push('0');
+    }
+    Some((end, format!("{}{}", symbol, digits)))
+}
+
+/// "five percent", "three point five per cent"
+fn percentage(tokens: &[Token], i: usize, _locale: NumberLocale) -> Option<(usize, String)> {
+    let number = parse_number(tokens, i).filter(|n| !n.ordinal)?;
+    let end = match word_at(tokens, i, number.end) {
+        Some("percent") => number.end + 1,
+        Some("per") if word_at(tokens, i, number.end + 1) == Some("cent") => number.end + 2,
+        // Voice commands may already have turned "percent" into "%"
+        _ if tokens[number.end - 1].trail.starts_with('%') => return Some((number.end, number.digits())),
+        _ => return None,
+    };
+    Some((end, format!("{}%", number.digits())))
+}
+
+/// Plain cardinals and ordinals, and years
+fn number(tokens: &[Token], i: usize, options: &NumberFormatting) -> Option<(usize, String)> {
+    if options.cardinals {
+        if let Some((year, end)) = parse_year(tokens, i, i, false) {
+            return Some((end, year.to_string()));
+        }
+    }
+    // "three thirty" may be a time or two numbers, so it stays as said
+    if let Some(end) = bare_clock_time(tokens, i) {
+        let words: Vec<String> = tokens[i..end]
+            .iter()
+            .map(|token| format!("{}{}", token.core, if token.hyphen_after { "-" } else { " " }))
+            .collect();
+        return Some((end, words.concat().trim_end_matches([' ', '-']).to_string()));
+    }
+    let number = parse_number(tokens, i)?;
+    // "a thirty second delay" is 30 seconds long, not the 32nd
+    if seconds_before_noun(tokens, i, &number) {
+        return options.cardinals.then(|| (number.end - 1, group_thousands(number.value - 2)));
+    }
+    if number.ordinal {
+        return (options.ordinals && number.value >= 10)
+            .then(|| (number.end, format!("{}{}", group_thousands(number.value), ordinal_suffix(number.value))));
+    }
+    if !options.cardinals {
+        return None;
+    }
+    // Small numbers stay words, unless a larger one follows: "1 800", not
+    // "one 800"
+    if number.integer().is_some_and(|n| n < 10) {
+        let next = at(tokens, i, number.end).and_then(|_| parse_number(tokens, number.end));
+        if !next.is_some_and(|next| next.ordinal || next.value >= 10) {
+            return None;
+        }
+    }
+    // "two thousand and five" is a year more often than an amount
+    if let Some(year) = number.integer().filter(|n| (2001..2100).contains(n) && !number.has_hundred) {
+        return Some((number.end, year.to_string()));
+    }
+    Some((number.end, number.digits()))
+}
+
+/// An hour from one to twelve and then minutes from ten, without "am",
+/// "pm" or "at" to make it a time ("three thirty"), returning the index
+/// after it
+fn bare_clock_time(tokens: &[Token], i: usize) -> Option<usize> {
+    let hour = parse_number(tokens, i)?;
+    hour.integer().filter(|h| (1..=12).contains(h) && hour.end == i + 1)?;
+    at(tokens, i, hour.end)?;
+    let minutes = parse_number(tokens, hour.end)?;
+    minutes.integer().filter(|m| (10..60).contains(m) && !minutes.has_hundred)?;
+    Some(minutes.end)
+}
+
+/// A number ending in a tens word and "second" with a noun after it, where
+/// "second" is the unit of time
+///
+/// Only after "a", "an" or another number ("a thirty second delay", "two
+/// thirty second clips"): "the twenty second floor" is the 22nd.
+fn seconds_before_noun(tokens: &[Token], i: usize, number: &Number) -> bool {
+    let end = number.end;
+    if !number.ordinal || tokens[end - 1].word != "second" || end < i + 2 {
+        return false;
+    }
+    let counted = i > 0
+        && at(tokens, i - 1, i).is_some()
+        && (matches!(tokens[i - 1].word.as_str(), "a" | "an")
+            || classify(&tokens[i - 1].word).is_some_and(|(_, ordinal)| !ordinal)
+            || tokens[i - 1].word.chars().all(|c| c.is_ascii_digit()));
+    if !counted {
+        return false;
+    }
+    let tens = matches!(classify(&tokens[end - 2].word), Some((Word::Tens(_), false)));
+    tens && word_at(tokens, i, end).is_some_and(|next| {
+        next.chars().all(char::is_alphabetic) && classify(next).is_none() && !AFTER_ORDINAL.contains(&next)
+    })
+}
+
+/// "1200" → "1,200"
+fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, c) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(locale: NumberLocale, cases: &[(&str, &str)]) {
+        let options = NumberFormatting { locale, ..NumberFormatting::default() };
+        let failures: Vec<String> = cases
+            .iter()
+            .filter_map(|(input, expected)| {
+                let actual = normalize_numbers(input, &options);
+                (actual != *expected).then(|| format!("{:?}: expected {:?}, got {:?}", input, expected, actual))
+            })
+            .collect();
+        assert!(failures.is_empty(), "{} of {} cases failed:\n{}", failures.len(), cases.len(), failures.join("\n"));
+    }
+
+    const EN_US: &[(&str, &str)] = &[
+        // Cardinals
+        ("I have two kids", "I have two kids"),
+        ("one of them", "one of them"),
+        ("we need ten chairs", "we need 10 chairs"),
+        ("about fifteen minutes", "about 15 minutes"),
+        ("twenty one people", "21 people"),
+        ("twenty-one people", "21 people"),
+        ("forty-two", "42"),
+        ("ninety nine problems", "99 problems"),
+        ("one hundred", "100"),
+        ("a hundred times", "100 times"),
+        ("one hundred and twenty", "120"),
+        ("three hundred forty five", "345"),
+        ("twelve hundred people", "1,200 people"),
+        ("a thousand reasons", "1,000 reasons"),
+        ("two thousand five hundred", "2,500"),
+        ("ten thousand", "10,000"),
+        ("one hundred thousand", "100,000"),
+        ("nine hundred ninety nine thousand nine hundred ninety nine", "999,999"),
+        ("one thousand and one nights", "1,001 nights"),
+        ("two million", "2 million"),
+        ("three point five billion", "3.5 billion"),
+        ("two million three hundred thousand", "2,300,000"),
+        ("three point one four", "3.14"),
+        ("zero point five", "0.5"),
+        ("version two point oh", "version 2.0"),
+        ("one two three", "one two three"),
+        ("by twenty thirty", "by 2030"),
+        ("zero", "zero"),
+        ("Twenty people came.", "20 people came."),
+        ("I counted eleven, twelve, thirteen.", "I counted 11, 12, 13."),
+        ("the hundreds of emails", "the hundreds of emails"),
+        ("and so on", "and so on"),
+        ("five and ten", "five and 10"),
+        // Ordinals
+        ("the first time", "the first time"),
+        ("my second attempt", "my second attempt"),
+        ("the tenth floor", "the 10th floor"),
+        ("the twenty first century", "the 21st century"),
+        ("the twenty-second", "the 22nd"),
+        ("the thirty third", "the 33rd"),
+        ("her eleventh birthday", "her 11th birthday"),
+        ("the twelfth", "the 12th"),
+        ("the one hundredth visitor", "the 100th visitor"),
+        ("the one hundred and first", "the 101st"),
+        ("fiftieth anniversary", "50th anniversary"),
+        ("a thirty second delay", "a 30 second delay"),
+        ("a twenty second timeout", "a 20 second timeout"),
+        ("a thirty-second clip", "a 30-second clip"),
+        ("two thirty second clips", "2 30 second clips"),
+        ("the twenty second floor", "the 22nd floor"),
+        ("the twenty second of the month", "the 22nd of the month"),
+        ("it came thirty second.", "it came 32nd."),
+        // Years
+        ("in nineteen eighty four", "in 1984"),
+        ("since twenty twenty", "since 2020"),
+        ("in twenty oh five", "in 2005"),
+        ("in two thousand and five", "in 2005"),
+        ("in two thousand twenty five", "in 2025"),
+        ("nineteen hundred", "1900"),
+        ("twelve fifteen", "twelve fifteen"),
+        // Times
+        ("three thirty pm", "3:30 PM"),
+        ("at seven am", "at 7 AM"),
+        ("at seven a.m. sharp", "at 7 AM sharp"),
+        ("it ends at nine p.m.", "it ends at 9 PM."),
+        ("ten fifteen a m", "10:15 AM"),
+        ("six oh five pm", "6:05 PM"),
+        ("eleven forty five PM", "11:45 PM"),
+        ("five o'clock", "5 o'clock"),
+        ("meet at three thirty", "meet at 3:30"),
+        ("at fifteen forty five", "at 15:45"),
+        ("quarter past nine", "9:15"),
+        ("half past three", "3:30"),
+        ("quarter to one", "12:45"),
+        ("quarter to four pm", "3:45 PM"),
+        ("three thirty", "three thirty"),
+        ("Three thirty-five.", "Three thirty-five."),
+        ("I am here", "I am here"),
+        ("thirteen pm", "13 pm"),
+        // Dates
+        ("january fifth", "January 5th"),
+        ("on march third twenty twenty five", "on March 3rd, 2025"),
+        ("january fifth, twenty twenty five", "January 5th, 2025"),
+        ("february twenty eighth", "February 28th"),
+        ("june nineteen ninety nine", "June 1999"),
+        ("december thirty first", "December 31st"),
+        ("the fourth of july", "the 4th of July"),
+        ("the first of may twenty twenty", "the 1st of May 2020"),
+        ("august the second", "August 2nd"),
+        ("october twelve", "October 12th"),
+        ("may the fourth", "May 4th"),
+        ("you may one day", "you may one day"),
+        ("you may first check", "you may first check"),
+        ("we march second to none", "we march second to none"),
+        ("october twenty second deadline", "October 22nd deadline"),
+        ("march two", "march two"),
+        ("april first", "April 1st"),
+        ("the thirty second", "the 32nd"),
+        ("in march two thousand", "in March 2000"),
+        // Currencies
+        ("twelve hundred dollars", "$1,200"),
+        ("five dollars", "$5"),
+        ("one dollar", "$1"),
+        ("five dollars and fifty cents", "$5.50"),
+        ("twelve dollars fifty", "$12.50"),
+        ("ninety nine cents", "99 cents"),
+        ("two million dollars", "$2 million"),
+        ("one point five billion dollars", "$1.5 billion"),
+        ("five point five dollars", "$5.50"),
+        ("twenty bucks", "$20"),
+        ("ten euros", "€10"),
+        ("a hundred dollars", "$100"),
+        ("five pounds of flour", "five pounds of flour"),
+        ("twenty pounds", "20 pounds"),
+        ("three dollars and five cents", "$3.05"),
+        // Percentages
+        ("five percent", "5%"),
+        ("twenty five per cent", "25%"),
+        ("three point five percent", "3.5%"),
+        ("a hundred percent", "100%"),
+        ("five%", "5%"),
+        // Phone numbers
+        ("five five five one two three four", "555-1234"),
+        ("call five five five one two three four five six seven", "call 555-123-4567"),
+        ("one eight hundred", "1 800"),
+        ("one eight oh oh five five five one two one two", "1-800-555-1212"),
+        ("two oh one double five five one two three four", "201-555-1234"),
+        ("my pin is one two three four", "my pin is one two three four"),
+        // Mixed
+        (
+            "Meet at three thirty pm on january fifth, budget is twelve hundred dollars",
+            "Meet at 3:30 PM on January 5th, budget is $1,200",
+        ),
+        ("\"twenty five\" she said", "\"25\" she said"),
+        ("(fifteen)", "(15)"),
+        ("twenty people\n\nthirty chairs", "20 people\n\n30 chairs"),
+        ("it's 3 PM already", "it's 3 PM already"),
+    ];
+
+    const EN_GB: &[(&str, &str)] = &[
+        ("three thirty pm", "3:30 pm"),
+        ("january fifth", "5 January"),
+        ("march third twenty twenty five", "3 March 2025"),
+        ("the fourth of july", "the 4th of July"),
+        ("twelve hundred dollars", "$1,200"),
+        ("five pounds", "£5"),
+        ("ten pounds fifty", "£10.50"),
+        ("ten pounds and fifty pence", "£10.50"),
+        ("fifty pence", "50p"),
+        ("one hundred and twenty pounds", "£120"),
+        ("zero two zero seven nine four six zero nine five eight", "020 7946 0958"),
+        ("oh seven seven double oh nine double oh one two three", "07700 900123"),
+        ("half past seven", "7:30"),
+        ("twenty five per cent", "25%"),
+    ];
+
+    #[test]
+    fn test_en_us() {
+        check(NumberLocale::EnUs, EN_US);
+    }
+
+    #[test]
+    fn test_en_gb() {
+        check(NumberLocale::EnGb, EN_GB);
+    }
+
+    #[test]
+    fn test_rule_classes_toggle() {
+        let text = "at three pm on june first pay twenty dollars, five percent of twenty five, \
+                    call five five five one two three four";
+        let all = NumberFormatting::default();
+        assert_eq!(normalize_numbers(text, &all), "at 3 PM on June 1st pay $20, 5% of 25, call 555-1234");
+        let only = |set: fn(&mut NumberFormatting)| {
+            let mut options = NumberFormatting {
+                cardinals: false,
+                ordinals: false,
+                times: false,
+                dates: false,
+                currencies: false,
+                percentages: false,
+                phone_numbers: false,
+                ..NumberFormatting::default()
+            };
+            set(&mut options);
+            normalize_numbers(text, &options)
+        };
+        assert_eq!(only(|_| {}), text);
+        assert!(only(|o| o.times = true).starts_with("at 3 PM on june first pay twenty dollars"));
+        assert!(only(|o| o.dates = true).contains("on June 1st pay twenty"));
+        assert!(only(|o| o.currencies = true).contains("pay $20, five percent"));
+        assert!(only(|o| o.percentages = true).contains("dollars, 5% of twenty five"));
+        assert!(only(|o| o.cardinals = true).contains("pay 20 dollars, five percent of 25"));
+        assert!(only(|o| o.phone_numbers = true).ends_with("call 555-1234"));
+        assert_eq!(normalize_numbers(text, &NumberFormatting { enabled: false, ..all }), text);
+    }
+}
@@ -0,0 +1,196 @@
+//! Deterministic find-and-replace rules applied to the final text
+
+use crate::config::{ConfigError, ReplacementRule};
+use regex::{NoExpand, Regex, RegexBuilder};
+
+/// Replacement rules compiled for matching, in config order
+#[derive(Debug, Clone, Default)]
+pub struct ReplacementRules {
+    rules: Vec<CompiledRule>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    regex: Regex,
+    replacement: String,
+    /// Regex rules may refer to capture groups (`$1`) in the replacement
+    expand: bool,
+    apply_to_raw: bool,
+}
+
+impl ReplacementRules {
+    /// Compile rules, failing on the first invalid pattern
+    pub fn compile(rules: &[ReplacementRule]) -> Result<Self, ConfigError> {
+        let rules = rules.iter().map(compile_rule).collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Get the number of rules
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Check if empty
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Apply every rule in order, each to the output of the previous one
+    pub fn apply(&self, text: &str) -> String {
+        apply_rules(self.rules.iter(), text)
+    }
+
+    /// Apply the rules marked `apply_to_raw`, in order
+    pub fn apply_raw(&self, text: &str) -> String {
+        apply_rules(self.rules.iter().filter(|rule| rule.apply_to_raw), text)
+    }
+}
+
+fn apply_rules<'a>(rules: impl Iterator<Item = &'a CompiledRule>, text: &str) -> String {
+    let mut result = text.to_string();
+    for rule in rules {
+        result = if rule.expand {
+            rule.regex.replace_all(&result, rule.replacement.as_str()).into_owned()
+        } else {
+            rule.regex.replace_all(&result, NoExpand(&rule.replacement)).into_owned()
+        };
+    }
+    result
+}
+
+fn compile_rule(rule: &ReplacementRule) -> Result<CompiledRule, ConfigError> {
+    let invalid = |message: String| ConfigError::InvalidReplacementPattern {
+        pattern: rule.pattern.clone(),
+        message,
+    };
+    if rule.pattern.is_empty() {
+        return Err(invalid("pattern is empty".to_string()));
+    }
+
+    let source = if rule.is_regex {
+        rule.pattern.clone()
+    } else {
+        literal_pattern(&rule.pattern)
+    };
+    let regex = RegexBuilder::new(&source)
+        .case_insensitive(!rule.case_sensitive)
+        .build()
+        .map_err(|e| invalid(e.to_string()))?;
+
+    Ok(CompiledRule {
+        regex,
+        replacement: rule.replacement.clone(),
+        expand: rule.is_regex,
+        apply_to_raw: rule.apply_to_raw,
+    })
+}
+
+/// Regex for a literal pattern, matching whole words only (so "eta" doesn't
+/// match inside "beta")
+fn literal_pattern(pattern: &str) -> String {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let start = if pattern.starts_with(is_word_char) { r"\b" } else { "" };
+    let end = if pattern.ends_with(is_word_char) { r"\b" } else { "" };
+    format!("{}{}{}", start, regex::escape(pattern), end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, replacement: &str) -> ReplacementRule {
+        ReplacementRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn regex_rule(pattern: &str, replacement: &str) -> ReplacementRule {
+        ReplacementRule {
+            is_regex: true,
+            ..rule(pattern, replacement)
+        }
+    }
+
+    fn apply(rules: &[ReplacementRule], text: &str) -> String {
+        ReplacementRules::compile(rules).unwrap().apply(text)
+    }
+
+    #[test]
+    fn test_literal_matches_whole_words_ignoring_case() {
+        let rules = [rule("voice flow", "VoiceFlow"), rule("eta", "ETA")];
+        assert_eq!(
+            apply(&rules, "Voice Flow says the eta is near, beta aside"),
+            "VoiceFlow says the ETA is near, beta aside"
+        );
+    }
+
+    #[test]
+    fn test_case_sensitive_literal() {
+        let rules = [ReplacementRule {
+            case_sensitive: true,
+            ..rule("Go", "Golang")
+        }];
+        assert_eq!(apply(&rules, "Go is fun, let's go"), "Golang is fun, let's go");
+    }
+
+    #[test]
+    fn test_literal_replacement_is_not_expanded() {
+        assert_eq!(apply(&[rule("price", "$1")], "the price"), "the $1");
+    }
+
+    #[test]
+    fn test_regex_strips_filler_words() {
+        let rules = [regex_rule(r"\b(um|uh)\b,?\s*", "")];
+        assert_eq!(apply(&rules, "Um, so uh I think, um we're done"), "so I think, we're done");
+    }
+
+    #[test]
+    fn test_regex_capture_groups() {
+        let rules = [regex_rule(r"(\d+) percent", "$1%")];
+        assert_eq!(apply(&rules, "up 20 percent"), "up 20%");
+    }
+
+    #[test]
+    fn test_rules_apply_in_order() {
+        // The second rule sees the output of the first
+        let rules = [rule("voice flow", "VoiceFlow"), rule("VoiceFlow", "VoiceFlow app")];
+        assert_eq!(apply(&rules, "open voice flow"), "open VoiceFlow app");
+
+        let reversed = [rule("VoiceFlow", "VoiceFlow app"), rule("voice flow", "VoiceFlow")];
+        assert_eq!(apply(&reversed, "open voice flow"), "open VoiceFlow");
+    }
+
+    #[test]
+    fn test_overlapping_matches() {
+        // Matches within one rule don't overlap, leftmost first
+        assert_eq!(apply(&[regex_rule("aa", "b")], "aaa"), "ba");
+
+        // Across rules, the earlier rule consumes the shared word
+        let rules = [rule("new york", "NYC"), rule("york city", "YC")];
+        assert_eq!(apply(&rules, "new york city"), "NYC city");
+    }
+
+    #[test]
+    fn test_apply_raw_only_uses_flagged_rules() {
+        let rules = ReplacementRules::compile(&[
+            ReplacementRule {
+                apply_to_raw: true,
+                ..rule("voice flow", "VoiceFlow")
+            },
+            rule("eta", "ETA"),
+        ])
+        .unwrap();
+        assert_eq!(rules.apply_raw("voice flow eta"), "VoiceFlow eta");
+        assert_eq!(rules.apply("voice flow eta"), "VoiceFlow ETA");
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let err = ReplacementRules::compile(&[regex_rule("(unclosed", "x")]).unwrap_err();
+        assert!(err.to_string().contains("(unclosed"), "{}", err);
+
+        assert!(ReplacementRules::compile(&[rule("", "x")]).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Long-form transcription: cut recordings into overlapping chunks at quiet
+//! points, and stitch the chunk transcripts back into one
+
+use super::{TranscriptionResult, WordTimestamp};
+use crate::audio::rms;
+use std::ops::Range;
+
+/// Samples per millisecond at 16kHz
+const SAMPLES_PER_MS: usize = 16;
+
+/// Frame length used to find quiet cut points (30ms)
+const FRAME_SAMPLES: usize = 480;
+
+/// Longest run of words compared when removing overlap duplicates
+const MAX_OVERLAP_WORDS: usize = 30;
+
+/// Split 16kHz audio into chunks of at most `max_chunk_ms`
+///
+/// Each cut is placed at the quietest 30ms frame in the last quarter of the
+/// chunk, so it rarely falls inside a word, and the next chunk starts
+/// `overlap_ms` before the cut (at most half a chunk).
+pub fn plan_chunks(audio: &[f32], max_chunk_ms: u32, overlap_ms: u32) -> Vec<Range<usize>> {
+    let max_len = max_chunk_ms as usize * SAMPLES_PER_MS;
+    if max_len < 4 * FRAME_SAMPLES || audio.len() <= max_len {
+        return std::iter::once(0..audio.len()).collect();
+    }
+    let overlap = (overlap_ms as usize * SAMPLES_PER_MS).min(max_len / 2);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let limit = start + max_len;
+        if limit >= audio.len() {
+            chunks.push(start..audio.len());
+            return chunks;
+        }
+        // The cut is at least 3/4 of a chunk in, so the next start advances
+        let cut = quietest_point(audio, limit - max_len / 4, limit);
+        chunks.push(start..cut);
+        start = cut - overlap;
+    }
+}
+
+/// Middle of the quietest frame between `from` and `to` (the latest, on ties)
+fn quietest_point(audio: &[f32], from: usize, to: usize) -> usize {
+    let mut best = to;
+    let mut best_rms = f32::MAX;
+    let mut pos = from;
+    while pos + FRAME_SAMPLES <= to {
+        let energy = rms(&audio[pos..pos + FRAME_SAMPLES]);
+        if energy <= best_rms {
+            best_rms = energy;
+            best = pos + FRAME_SAMPLES / 2;
+        }
+        pos += FRAME_SAMPLES;
+    }
+    best
+}
+
+/// Stitch transcriptions of parts of one recording, each given with the
+/// sample range it covers, in order
+///
+/// Where a part overlaps the previous one, words it repeats from the
+/// previous transcript are dropped. Word timestamps are shifted to be
+/// relative to the full audio. The confidence is weighted by text length;
+/// the no-speech probability is the lowest of the parts, the language is
+/// the first part's, encoder and decoder times are summed, and the
+/// temperature is the highest any part fell back to. Alternatives are kept
+/// only from a single part, as they read the whole recording.
+pub fn stitch_transcriptions(mut parts: Vec<(Range<usize>, TranscriptionResult)>) -> TranscriptionResult {
+    if parts.len() == 1 && parts[0].0.start == 0 {
+        return parts.into_iter().next().unwrap().1;
+    }
+    let alternatives = match parts.as_mut_slice() {
+        [(_, part)] => std::mem::take(&mut part.alternatives),
+        _ => Vec::new(),
+    };
+
+    let mut words: Vec<String> = Vec::new();
+    let mut word_timestamps: Vec<WordTimestamp> = Vec::new();
+    let mut weighted_confidence = 0.0f32;
+    let mut total_weight = 0usize;
+    let mut no_speech_probability = 1.0f32;
+    let mut previous_end = 0;
+    let language = parts.first().and_then(|(_, part)| part.language.clone());
+    let encode_ms = parts.iter().map(|(_, part)| part.encode_ms).sum();
+    let decode_ms = parts.iter().map(|(_, part)| part.decode_ms).sum();
+    let temperature = parts.iter().map(|(_, part)| part.temperature).fold(0.0, f32::max);
+    let temperature_fallback = parts.iter().any(|(_, part)| part.temperature_fallback);
+
+    for (range, part) in parts {
+        let offset_ms = (range.start / SAMPLES_PER_MS) as i64;
+        let overlaps = range.start < previous_end;
+        previous_end = range.end;
+
+        let mut part_words: Vec<String> = part.text.split_whitespace().map(str::to_string).collect();
+        if overlaps {
+            part_words.drain(..repeated_words(&words, &part_words));
+        }
+        weighted_confidence += part.confidence * part.text.trim().len() as f32;
+        total_weight += part.text.trim().len();
+        no_speech_probability = no_speech_probability.min(part.no_speech_probability);
+        words.extend(part_words);
+
+        // Timed words inside the overlap were already covered by the previous part
+        let covered_until = if overlaps {
+            word_timestamps.last().map_or(i64::MIN, |w| w.end_ms)
+        } else {
+            i64::MIN
+        };
+        word_timestamps.extend(
+            part.word_timestamps
+                .into_iter()
+                .map(|mut word| {
+                    word.start_ms += offset_ms;
+                    word.end_ms += offset_ms;
+                    word
+                })
+                .filter(|word| word.start_ms >= covered_until),
+        );
+    }
+
+    TranscriptionResult {
+        text: words.join(" "),
+        word_timestamps,
+        confidence: if total_weight > 0 {
+            weighted_confidence / total_weight as f32
+        } else {
+            0.0
+        },
+        no_speech_probability,
+        language,
+        encode_ms,
+        decode_ms,
+        temperature,
+        temperature_fallback,
+        alternatives,
+    }
+}
+
+/// Number of leading words of `next` that repeat the end of `previous`
+///
+/// Words are compared case-insensitively, ignoring punctuation, and the
+/// longest match wins.
+fn repeated_words(previous: &[String], next: &[String]) -> usize {
+    let normalize = |word: &String| -> String {
+        word.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let previous: Vec<String> = previous.iter().rev().take(MAX_OVERLAP_WORDS).rev().map(normalize).collect();
+    let next: Vec<String> = next.iter().take(MAX_OVERLAP_WORDS).map(normalize).collect();
+
+    (1..=previous.len().min(next.len()))
+        .rev()
+        .find(|&k| previous[previous.len() - k..] == next[..k])
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe::Alternative;
+
+    fn part(text: &str, words: &[(&str, i64, i64)], confidence: f32, no_speech_probability: f32) -> TranscriptionResult {
+        TranscriptionResult {
+            text: text.to_string(),
+            word_timestamps: words
+                .iter()
+                .map(|&(word, start_ms, end_ms)| WordTimestamp {
+                    word: word.to_string(),
+                    start_ms,
+                    end_ms,
+                    probability: 1.0,
+                })
+                .collect(),
+            confidence,
+            no_speech_probability,
+            language: Some("en".to_string()),
+            encode_ms: 10,
+            decode_ms: 20,
+            temperature: 0.0,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
+        }
+    }
+
+    /// Loud audio with a silent gap of `gap` samples starting at `at`
+    fn audio_with_gap(len: usize, at: usize, gap: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| if (at..at + gap).contains(&i) { 0.0 } else { (i as f32 * 0.1).sin() * 0.3 })
+            .collect()
+    }
+
+    #[test]
+    fn test_short_audio_is_one_chunk() {
+        assert_eq!(plan_chunks(&[0.1; 16000], 30000, 1000), vec![0..16000]);
+    }
+
+    #[test]
+    fn test_chunks_cut_at_silence_and_overlap() {
+        // 70s of speech with a pause at 27s
+        let audio = audio_with_gap(70 * 16000, 27 * 16000, 4800);
+        let chunks = plan_chunks(&audio, 30000, 1000);
+
+        let first_cut = chunks[0].end;
+        assert!((27 * 16000..27 * 16000 + 4800).contains(&first_cut), "cut at {}", first_cut);
+        assert_eq!(chunks[1].start, first_cut - 16000);
+        assert_eq!(chunks.last().unwrap().end, audio.len());
+        for chunk in &chunks {
+            assert!(chunk.len() <= 30 * 16000);
+        }
+        for pair in chunks.windows(2) {
+            assert!(pair[1].start < pair[0].end, "chunks should overlap");
+        }
+    }
+
+    #[test]
+    fn test_stitch_drops_repeated_overlap_words() {
+        let stitched = stitch_transcriptions(vec![
+            (0..480_000, part("so the quarterly numbers look", &[], 0.9, 0.1)),
+            (464_000..800_000, part("Numbers look good, overall.", &[], 0.7, 0.2)),
+        ]);
+        assert_eq!(stitched.text, "so the quarterly numbers look good, overall.");
+        assert_eq!(stitched.no_speech_probability, 0.1);
+        assert_eq!((stitched.encode_ms, stitched.decode_ms), (20, 40));
+    }
+
+    #[test]
+    fn test_stitch_keeps_repeats_between_separate_regions() {
+        // Regions from VAD don't overlap, so a repeated word is real speech
+        let stitched = stitch_transcriptions(vec![
+            (0..16000, part("okay", &[], 0.9, 0.1)),
+            (48000..64000, part("okay", &[], 0.9, 0.1)),
+        ]);
+        assert_eq!(stitched.text, "okay okay");
+    }
+
+    #[test]
+    fn test_stitch_offsets_and_dedupes_word_timestamps() {
+        let stitched = stitch_transcriptions(vec![
+            (8000..40000, part("hello world", &[("hello", 100, 400), ("world", 1600, 1900)], 0.9, 0.1)),
+            (
+                32000..80000,
+                part("world wide", &[("world", 100, 400), ("wide", 500, 800)], 0.6, 0.3),
+            ),
+        ]);
+
+        assert_eq!(stitched.text, "hello world wide");
+        let times: Vec<(&str, i64, i64)> = stitched
+            .word_timestamps
+            .iter()
+            .map(|w| (w.word.as_str(), w.start_ms, w.end_ms))
+            .collect();
+        assert_eq!(times, [("hello", 600, 900), ("world", 2100, 2400), ("wide", 2500, 2800)]);
+        // Weighted by text length: (0.9 * 11 + 0.6 * 10) / 21
+        assert!((stitched.confidence - 15.9 / 21.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_stitch_keeps_alternatives_of_a_single_part() {
+        let alternative = Alternative { text: "hello word".to_string(), confidence: 0.4 };
+        let with_alternative = || TranscriptionResult {
+            alternatives: vec![alternative.clone()],
+            ..part("hello world", &[], 0.9, 0.1)
+        };
+
+        let single = stitch_transcriptions(vec![(8000..40000, with_alternative())]);
+        assert_eq!(single.alternatives, std::slice::from_ref(&alternative));
+
+        let stitched = stitch_transcriptions(vec![(0..16000, with_alternative()), (48000..64000, with_alternative())]);
+        assert!(stitched.alternatives.is_empty());
+    }
+
+    #[test]
+    fn test_stitch_no_parts_is_no_speech() {
+        let stitched = stitch_transcriptions(vec![]);
+        assert!(stitched.text.is_empty());
+        assert_eq!(stitched.confidence, 0.0);
+        assert_eq!(stitched.no_speech_probability, 1.0);
+        assert_eq!(stitched.language, None);
+    }
+}
@@ -0,0 +1,340 @@
+//! Decoding strategies shared by the STT engines: beam search, sampling,
+//! and the checks that send a decode back for another try at a higher
+//! temperature (see `SttDecodeParams`)
+//!
+//! The search itself doesn't know about the model: it is given the logits
+//! after the start token and a function that feeds a token to a decoder
+//! state, so Moonshine's ONNX decoder and the tests drive it alike.
+
+use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// A decode whose mean token log-probability is lower than this is retried
+/// at the next temperature (Whisper's `logprob_threshold`)
+pub(crate) const LOG_PROB_THRESHOLD: f32 = -1.0;
+
+/// A decode repeating itself more than this is retried at the next
+/// temperature; plays the part of Whisper's gzip compression-ratio check
+pub(crate) const REPETITION_THRESHOLD: f32 = 2.4;
+
+/// Length of the runs `repetition_ratio` counts
+const REPETITION_NGRAM: usize = 3;
+
+/// Adjusts a hypothesis' next-token logits: vocabulary bias, banned tokens
+pub(crate) trait LogitsFilter {
+    fn filter<'a>(&self, generated: &[i64], logits: &'a [f32]) -> Cow<'a, [f32]>;
+}
+
+/// Whether a decode looks wrong enough to try again at a higher temperature
+pub(crate) fn needs_fallback(mean_log_prob: f32, repetition_ratio: f32) -> bool {
+    mean_log_prob < LOG_PROB_THRESHOLD || repetition_ratio > REPETITION_THRESHOLD
+}
+
+/// Runs of three tokens (or words) over distinct such runs: about 1.0 for
+/// speech, the number of laps for a decode stuck in a loop
+pub(crate) fn repetition_ratio<T: Eq + Hash>(units: &[T]) -> f32 {
+    if units.len() < REPETITION_NGRAM * 2 {
+        return 1.0;
+    }
+    let distinct: HashSet<&[T]> = units.windows(REPETITION_NGRAM).collect();
+    (units.len() - REPETITION_NGRAM + 1) as f32 / distinct.len() as f32
+}
+
+/// Decode at each temperature in turn until `needs_fallback` passes the
+/// result, returning it with the temperature it was decoded at
+///
+/// When every temperature fails, the last result is kept.
+pub(crate) fn with_fallback<R>(
+    temperatures: &[f32],
+    mut decode: impl FnMut(f32) -> Result<R>,
+    needs_fallback: impl Fn(&R) -> bool,
+) -> Result<(R, f32)> {
+    let (&last, earlier) = temperatures.split_last().unwrap_or((&0.0, &[]));
+    for &temperature in earlier {
+        let result = decode(temperature)?;
+        if !needs_fallback(&result) {
+            return Ok((result, temperature));
+        }
+        tracing::debug!("Decode at temperature {} failed the quality checks, retrying", temperature);
+    }
+    Ok((decode(last)?, last))
+}
+
+/// Tokens that would repeat a run of `n` tokens already in `generated`
+pub(crate) fn repeated_ngram_tokens(generated: &[i64], n: usize) -> Vec<i64> {
+    if n == 0 || generated.len() < n {
+        return Vec::new();
+    }
+    let prefix = &generated[generated.len() - (n - 1)..];
+    let mut banned = Vec::new();
+    for window in generated.windows(n) {
+        if window[..n - 1] == *prefix && !banned.contains(&window[n - 1]) {
+            banned.push(window[n - 1]);
+        }
+    }
+    banned
+}
+
+/// Log-softmax of every logit
+pub(crate) fn log_probs(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum = logits.iter().map(|&x| (x - max).exp()).sum::<f32>().ln();
+    logits.iter().map(|&x| x - max - log_sum).collect()
+}
+
+/// SplitMix64, seeded the same for every decode (as whisper.cpp does), so
+/// sampling at a temperature repeats from run to run
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Draw a token from the softmax of `logits / temperature`
+pub(crate) fn sample(logits: &[f32], temperature: f32, rng: &mut Rng) -> i64 {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let weights: Vec<f32> = logits.iter().map(|&x| ((x - max) / temperature).exp()).collect();
+    let mut target = rng.next_f32() * weights.iter().sum::<f32>();
+    for (token, &weight) in weights.iter().enumerate() {
+        if target < weight {
+            return token as i64;
+        }
+        target -= weight;
+    }
+    // Rounding left the target past the end
+    weights.iter().rposition(|&weight| weight > 0.0).unwrap_or(0) as i64
+}
+
+/// A decoded token sequence, end token excluded
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Hypothesis {
+    pub tokens: Vec<i64>,
+    /// Sum of the tokens' log-probabilities (the end token's included)
+    pub log_prob: f32,
+}
+
+impl Hypothesis {
+    /// Mean log-probability per token, the score hypotheses are ranked by
+    pub(crate) fn mean_log_prob(&self) -> f32 {
+        self.log_prob / self.tokens.len().max(1) as f32
+    }
+}
+
+/// Beam search over a decoder with batch size 1: each hypothesis keeps its
+/// own decoder state
+pub(crate) struct BeamSearch {
+    pub beam_size: usize,
+    /// Search stops once `beam_size * patience` hypotheses have finished
+    pub patience: f32,
+    pub max_tokens: usize,
+    pub eos: i64,
+    /// Hypotheses returned, the best first (see `SttDecodeParams::alternatives`)
+    pub n_best: usize,
+}
+
+struct Beam<S> {
+    tokens: Vec<i64>,
+    log_prob: f32,
+    logits: Vec<f32>,
+    state: S,
+}
+
+impl BeamSearch {
+    /// Search from `first_logits`, the logits after the start token, and
+    /// the decoder state that produced them
+    ///
+    /// `step` feeds the last of the tokens to a copy of the parent
+    /// hypothesis' state, returning the next logits and the new state.
+    /// Returns the best `n_best` hypotheses (at least one), best first.
+    pub(crate) fn run<S: Clone>(
+        &self,
+        first_logits: Vec<f32>,
+        first_state: S,
+        filter: &impl LogitsFilter,
+        mut step: impl FnMut(&[i64], &S) -> Result<(Vec<f32>, S)>,
+    ) -> Result<Vec<Hypothesis>> {
+        let max_finished = ((self.beam_size as f32 * self.patience).round() as usize).max(1);
+        let mut beams = vec![Beam { tokens: Vec::new(), log_prob: 0.0, logits: first_logits, state: first_state }];
+        let mut finished: Vec<Hypothesis> = Vec::new();
+
+        while !beams.is_empty() && finished.len() < max_finished {
+            // The best continuations of every beam, beam_size + 1 each so an
+            // ending can't crowd out the live ones
+            let mut candidates = Vec::new();
+            for (index, beam) in beams.iter().enumerate() {
+                let log_probs = log_probs(&filter.filter(&beam.tokens, &beam.logits));
+                let mut ranked: Vec<usize> = (0..log_probs.len()).collect();
+                ranked.sort_unstable_by(|&a, &b| log_probs[b].total_cmp(&log_probs[a]));
+                for &token in ranked.iter().take(self.beam_size + 1) {
+                    if log_probs[token].is_finite() {
+                        candidates.push((beam.log_prob + log_probs[token], index, token as i64));
+                    }
+                }
+            }
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            let mut next = Vec::with_capacity(self.beam_size);
+            for (log_prob, index, token) in candidates {
+                if next.len() == self.beam_size || finished.len() >= max_finished {
+                    break;
+                }
+                let parent = &beams[index];
+                if token == self.eos {
+                    finished.push(Hypothesis { tokens: parent.tokens.clone(), log_prob });
+                    continue;
+                }
+                let mut tokens = parent.tokens.clone();
+                tokens.push(token);
+                if tokens.len() >= self.max_tokens {
+                    finished.push(Hypothesis { tokens, log_prob });
+                    continue;
+                }
+                let (logits, state) = step(&tokens, &parent.state)?;
+                next.push(Beam { tokens, log_prob, logits, state });
+            }
+            beams = next;
+        }
+
+        finished.extend(beams.into_iter().map(|beam| Hypothesis { tokens: beam.tokens, log_prob: beam.log_prob }));
+        if self.n_best > 1 {
+            finished.sort_by(|a, b| b.mean_log_prob().total_cmp(&a.mean_log_prob()));
+            finished.truncate(self.n_best);
+        } else if let Some(best) = (0..finished.len())
+            .max_by(|&a, &b| finished[a].mean_log_prob().total_cmp(&finished[b].mean_log_prob()))
+        {
+            finished.swap(0, best);
+            finished.truncate(1);
+        }
+        if finished.is_empty() {
+            finished.push(Hypothesis { tokens: Vec::new(), log_prob: 0.0 });
+        }
+        Ok(finished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoFilter;
+
+    impl LogitsFilter for NoFilter {
+        fn filter<'a>(&self, _generated: &[i64], logits: &'a [f32]) -> Cow<'a, [f32]> {
+            Cow::Borrowed(logits)
+        }
+    }
+
+    const EOS: i64 = 0;
+
+    /// Decoder over tokens 0 (end), 1 and 2, as a table of next-token
+    /// probabilities by the previous token; the state is the previous token
+    fn table_step(table: &'static [[f32; 3]]) -> impl FnMut(&[i64], &i64) -> Result<(Vec<f32>, i64)> {
+        move |tokens, _state| {
+            let last = *tokens.last().unwrap();
+            Ok((table[last as usize].iter().map(|p| p.ln()).collect(), last))
+        }
+    }
+
+    #[test]
+    fn test_beam_search_beats_greedy() {
+        // Greedy takes 1 (0.55) and then has nowhere likely to go; 2 (0.45)
+        // leads to a near-certain ending
+        static TABLE: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.34, 0.33, 0.33], [0.99, 0.005, 0.005]];
+        let first: Vec<f32> = [0.0f32, 0.55, 0.45].iter().map(|p| p.ln()).collect();
+
+        let search = BeamSearch { beam_size: 2, patience: 1.0, max_tokens: 10, eos: EOS, n_best: 1 };
+        let ranked = search.run(first.clone(), 0i64, &NoFilter, table_step(&TABLE)).unwrap();
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].tokens, [2]);
+        assert!((ranked[0].log_prob - (0.45f32 * 0.99).ln()).abs() < 1e-5);
+
+        // The runner-up is greedy's pick
+        let two = BeamSearch { n_best: 2, ..search };
+        let ranked = two.run(first.clone(), 0i64, &NoFilter, table_step(&TABLE)).unwrap();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].tokens, [2]);
+        assert_eq!(ranked[1].tokens[0], 1);
+        assert!(ranked[0].mean_log_prob() >= ranked[1].mean_log_prob());
+
+        let greedy = BeamSearch { beam_size: 1, ..search };
+        assert_eq!(greedy.run(first, 0i64, &NoFilter, table_step(&TABLE)).unwrap()[0].tokens, [1]);
+    }
+
+    #[test]
+    fn test_beam_search_stops_at_max_tokens() {
+        static LOOP: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.01, 0.98, 0.01], [0.01, 0.01, 0.98]];
+        let first: Vec<f32> = [0.01f32, 0.98, 0.01].iter().map(|p| p.ln()).collect();
+        let search = BeamSearch { beam_size: 3, patience: 1.0, max_tokens: 4, eos: EOS, n_best: 1 };
+        let best = search.run(first, 0i64, &NoFilter, table_step(&LOOP)).unwrap();
+        assert!(best[0].tokens.len() <= 4);
+    }
+
+    #[test]
+    fn test_repeated_ngram_tokens() {
+        // "1 2" has been followed by 3; after another "1 2", 3 is banned
+        assert_eq!(repeated_ngram_tokens(&[1, 2, 3, 4, 1, 2], 3), [3]);
+        assert!(repeated_ngram_tokens(&[1, 2, 3, 4, 1, 5], 3).is_empty());
+        assert_eq!(repeated_ngram_tokens(&[7, 8, 7], 1), [7, 8]);
+        assert!(repeated_ngram_tokens(&[1, 2, 1, 2], 0).is_empty());
+    }
+
+    #[test]
+    fn test_repetition_ratio() {
+        let speech: Vec<&str> = "please send the report to the team by friday".split(' ').collect();
+        assert_eq!(repetition_ratio(&speech), 1.0);
+        let looped: Vec<&str> = "thank you thank you thank you thank you thank you thank you".split(' ').collect();
+        assert!(repetition_ratio(&looped) > REPETITION_THRESHOLD);
+        assert!(needs_fallback(-0.2, repetition_ratio(&looped)));
+        assert!(needs_fallback(-1.5, 1.0));
+        assert!(!needs_fallback(-0.2, 1.0));
+    }
+
+    #[test]
+    fn test_with_fallback() {
+        let mut tried = Vec::new();
+        let (result, temperature) = with_fallback(
+            &[0.0, 0.2, 0.4],
+            |t| {
+                tried.push(t);
+                Ok(t)
+            },
+            |&t| t < 0.2,
+        )
+        .unwrap();
+        assert_eq!((result, temperature), (0.2, 0.2));
+        assert_eq!(tried, [0.0, 0.2]);
+
+        // Every temperature fails: the last result is kept
+        let (result, temperature) = with_fallback(&[0.0, 0.5], Ok, |_| true).unwrap();
+        assert_eq!((result, temperature), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_sample_follows_temperature() {
+        let logits = [0.0f32, 2.0, 0.0];
+        let mut rng = Rng::new(0);
+        let draws: Vec<i64> = (0..200).map(|_| sample(&logits, 1.0, &mut rng)).collect();
+        let ones = draws.iter().filter(|&&t| t == 1).count();
+        // p(1) = e^2 / (e^2 + 2) ≈ 0.79
+        assert!((130..190).contains(&ones), "{} of 200", ones);
+        // A low temperature is all but greedy
+        assert!((0..50).all(|_| sample(&logits, 0.05, &mut rng) == 1));
+    }
+}
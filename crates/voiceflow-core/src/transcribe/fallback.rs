@@ -0,0 +1,177 @@
+//! Substitute STT engines for when the configured one fails to load
+//! (`Config::stt_fallback_enabled`)
+
+use std::cmp::Reverse;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::config::{check_language, check_stt_task, Config, ModelPrecision, MoonshineModel, SttEngine, WhisperModel};
+
+/// The STT engine and model a pipeline runs
+///
+/// After a fallback this differs from the config, which is left as it was:
+/// saving the config never saves the substitute.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActiveSttEngine {
+    pub engine: SttEngine,
+    /// Model id, as in `Config::whisper_model` or `Config::moonshine_model`
+    pub model: String,
+    pub precision: ModelPrecision,
+    /// Loaded in place of the configured engine or model, which failed to
+    /// load
+    pub substituted: bool,
+}
+
+impl ActiveSttEngine {
+    /// The engine and model `config` selects
+    pub fn configured(config: &Config) -> Self {
+        match config.stt_engine {
+            SttEngine::Whisper => Self::whisper(&config.whisper_model, config.whisper_precision, false),
+            SttEngine::Moonshine => Self::moonshine(&config.moonshine_model, config.moonshine_precision, false),
+        }
+    }
+
+    fn whisper(model: &WhisperModel, precision: ModelPrecision, substituted: bool) -> Self {
+        Self { engine: SttEngine::Whisper, model: model.id().to_string(), precision, substituted }
+    }
+
+    fn moonshine(model: &MoonshineModel, precision: ModelPrecision, substituted: bool) -> Self {
+        Self { engine: SttEngine::Moonshine, model: model.id().to_string(), precision, substituted }
+    }
+
+    /// `config` with its STT engine and model replaced by this one
+    pub(crate) fn apply_to(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.stt_engine = self.engine.clone();
+        match self.engine {
+            SttEngine::Whisper => {
+                config.whisper_model = WhisperModel::from_id(&self.model).unwrap_or_default();
+                config.whisper_precision = self.precision;
+            }
+            SttEngine::Moonshine => {
+                config.moonshine_model = MoonshineModel::from_id(&self.model).unwrap_or_default();
+                config.moonshine_precision = self.precision;
+            }
+        }
+        config
+    }
+
+    /// Estimated size in MB
+    fn size_mb(&self) -> u32 {
+        match self.engine {
+            SttEngine::Whisper => WhisperModel::from_id(&self.model).map_or(0, |model| model.size_mb(self.precision)),
+            SttEngine::Moonshine => {
+                MoonshineModel::from_id(&self.model).map_or(0, |model| model.size_mb(self.precision))
+            }
+        }
+    }
+
+    fn downloaded(&self, config: &Config) -> bool {
+        match self.engine {
+            SttEngine::Whisper => WhisperModel::from_id(&self.model)
+                .is_some_and(|model| config.whisper_model_downloaded_for(&model, self.precision)),
+            SttEngine::Moonshine => MoonshineModel::from_id(&self.model)
+                .is_some_and(|model| config.moonshine_model_downloaded_for(&model, self.precision)),
+        }
+    }
+}
+
+impl fmt::Display for ActiveSttEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.engine.display_name(), self.precision.variant_id(&self.model))
+    }
+}
+
+/// Every variant of an engine, largest first
+fn variants(engine: &SttEngine) -> Vec<ActiveSttEngine> {
+    let mut variants: Vec<ActiveSttEngine> = match engine {
+        SttEngine::Whisper => WhisperModel::all_models()
+            .into_iter()
+            .flat_map(|model| {
+                ModelPrecision::all().into_iter().map(move |precision| ActiveSttEngine::whisper(&model, precision, true))
+            })
+            .collect(),
+        SttEngine::Moonshine => MoonshineModel::all_models()
+            .into_iter()
+            .flat_map(|model| {
+                ModelPrecision::all()
+                    .into_iter()
+                    .map(move |precision| ActiveSttEngine::moonshine(&model, precision, true))
+            })
+            .collect(),
+    };
+    variants.sort_by_key(|variant| Reverse(variant.size_mb()));
+    variants
+}
+
+/// Engines to try in order when the one `config` selects fails to load
+///
+/// Smaller variants of the same engine come first, then the other engine,
+/// largest first; only downloaded variants are listed, and the other engine
+/// only if it handles the configured language and task.
+pub(crate) fn substitutes(config: &Config) -> Vec<ActiveSttEngine> {
+    let configured_mb = ActiveSttEngine::configured(config).size_mb();
+    let mut candidates: Vec<ActiveSttEngine> =
+        variants(&config.stt_engine).into_iter().filter(|variant| variant.size_mb() < configured_mb).collect();
+
+    let other = match config.stt_engine {
+        SttEngine::Whisper => SttEngine::Moonshine,
+        SttEngine::Moonshine => SttEngine::Whisper,
+    };
+    if check_language(&config.language, &other).is_ok() && check_stt_task(config.stt_task, &other).is_ok() {
+        candidates.extend(variants(&other));
+    }
+    candidates.retain(|candidate| candidate.downloaded(config));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitutes_are_downloaded_smaller_then_other_engine() {
+        let dir = std::env::temp_dir().join(format!("voiceflow-stt-fallback-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["ggml-tiny.bin", "ggml-small.bin", "ggml-tiny-q8_0.bin"] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        let moonshine_dir = dir.join("moonshine-tiny-int8");
+        std::fs::create_dir_all(&moonshine_dir).unwrap();
+        for file in MoonshineModel::Tiny.required_files() {
+            std::fs::write(moonshine_dir.join(file), b"").unwrap();
+        }
+
+        let config = Config { models_dir_override: Some(dir.clone()), ..Config::default() };
+        let names: Vec<String> = substitutes(&config).iter().map(ToString::to_string).collect();
+        // Whisper base is configured: small is larger, so it's left out
+        assert_eq!(names, ["Whisper tiny", "Whisper tiny-int8", "Moonshine tiny-int8"]);
+        assert!(substitutes(&config).iter().all(|substitute| substitute.substituted));
+
+        // Moonshine only transcribes English
+        let config = Config { language: "de".to_string(), ..config };
+        let names: Vec<String> = substitutes(&config).iter().map(ToString::to_string).collect();
+        assert_eq!(names, ["Whisper tiny", "Whisper tiny-int8"]);
+
+        // From Moonshine tiny, its int8 variant first, then any downloaded
+        // Whisper model
+        let config = Config { stt_engine: SttEngine::Moonshine, language: "en".to_string(), ..config };
+        let names: Vec<String> = substitutes(&config).iter().map(ToString::to_string).collect();
+        assert_eq!(names, ["Moonshine tiny-int8", "Whisper small", "Whisper tiny", "Whisper tiny-int8"]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_apply_to_leaves_the_rest_of_the_config() {
+        let config = Config { language: "en".to_string(), stt_threads: 2, ..Config::default() };
+        let substitute = ActiveSttEngine::moonshine(&MoonshineModel::Base, ModelPrecision::Quantized, true);
+        let applied = substitute.apply_to(&config);
+        assert_eq!(applied.stt_engine, SttEngine::Moonshine);
+        assert_eq!((&applied.moonshine_model, &applied.moonshine_precision), (&MoonshineModel::Base, &ModelPrecision::Quantized));
+        assert_eq!(applied.stt_threads, 2);
+        assert_eq!(ActiveSttEngine::configured(&applied), ActiveSttEngine { substituted: false, ..substitute });
+    }
+}
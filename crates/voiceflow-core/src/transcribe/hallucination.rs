@@ -0,0 +1,196 @@
+//! Removal of text Whisper makes up on silence or music: loops of the same
+//! words, and stock phrases from the subtitles it was trained on
+
+use crate::config::HallucinationFilter;
+use serde::Serialize;
+
+/// Phrases the STT engine produces on silence or music, dropped as whole
+/// sentences (see `HallucinationFilter::min_no_speech_probability`)
+pub const DEFAULT_HALLUCINATION_PHRASES: &[&str] = &[
+    "you",
+    "bye",
+    "thank you",
+    "thank you very much",
+    "thanks for watching",
+    "thank you for watching",
+    "thank you so much for watching",
+    "thanks for listening",
+    "thank you for listening",
+    "please subscribe",
+    "subscribe to my channel",
+    "please subscribe to my channel",
+    "like and subscribe",
+    "don't forget to like and subscribe",
+    "see you in the next video",
+    "see you next time",
+    "subtitles by the amara.org community",
+    "transcription by castingwords",
+];
+
+/// Longest run of words checked for back-to-back repeats
+const MAX_REPEATED_WORDS: usize = 8;
+
+/// Text taken out of a transcript
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FilteredSegment {
+    pub text: String,
+    pub reason: FilterReason,
+}
+
+/// Why a `FilteredSegment` was taken out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterReason {
+    /// Repeats of a run of words that looped; the first one is kept
+    Repetition,
+    /// A sentence matching a known made-up phrase, on audio likely to hold
+    /// no speech
+    KnownPhrase,
+}
+
+/// Remove loops and known made-up phrases from a transcript, returning the
+/// rest and what was removed
+pub fn filter_hallucinations(
+    text: &str,
+    no_speech_probability: f32,
+    filter: &HallucinationFilter,
+) -> (String, Vec<FilteredSegment>) {
+    let mut removed = Vec::new();
+    if !filter.enabled {
+        return (text.to_string(), removed);
+    }
+    let text = collapse_repeats(text, filter.repeat_threshold as usize, &mut removed);
+    let text = if no_speech_probability >= filter.min_no_speech_probability {
+        drop_known_phrases(&text, filter, &mut removed)
+    } else {
+        text
+    };
+    if !removed.is_empty() {
+        tracing::debug!("Filtered hallucinations: {:?}", removed);
+    }
+    (text, removed)
+}
+
+/// Keep one of each run of words repeated back to back `threshold` times
+/// or more (twice that for a single word, so "no, no, no" survives)
+fn collapse_repeats(text: &str, threshold: usize, removed: &mut Vec<FilteredSegment>) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let keys: Vec<String> = words.iter().map(|word| normalize(word)).collect();
+    let mut kept = Vec::with_capacity(words.len());
+    let mut i = 0;
+    while i < words.len() {
+        let repeat = (1..=MAX_REPEATED_WORDS.min(words.len() - i)).find_map(|len| {
+            let unit = &keys[i..i + len];
+            let count = keys[i..].chunks_exact(len).take_while(|chunk| *chunk == unit).count();
+            let needed = if len == 1 { threshold * 2 } else { threshold };
+            (count >= needed).then_some((len, count))
+        });
+        match repeat {
+            Some((len, count)) => {
+                kept.extend_from_slice(&words[i..i + len]);
+                let text = words[i + len..i + len * count].join(" ");
+                removed.push(FilteredSegment { text, reason: FilterReason::Repetition });
+                i += len * count;
+            }
+            None => {
+                kept.push(words[i]);
+                i += 1;
+            }
+        }
+    }
+    kept.join(" ")
+}
+
+/// Drop the sentences matching a built-in or configured phrase
+fn drop_known_phrases(text: &str, filter: &HallucinationFilter, removed: &mut Vec<FilteredSegment>) -> String {
+    let phrases: Vec<String> = DEFAULT_HALLUCINATION_PHRASES
+        .iter()
+        .copied()
+        .chain(filter.extra_phrases.iter().map(String::as_str))
+        .map(normalize_phrase)
+        .collect();
+    let mut kept = Vec::new();
+    for sentence in sentences(text) {
+        if phrases.contains(&normalize_phrase(sentence)) {
+            removed.push(FilteredSegment { text: sentence.to_string(), reason: FilterReason::KnownPhrase });
+        } else {
+            kept.push(sentence);
+        }
+    }
+    kept.join(" ")
+}
+
+/// Split text after each run of sentence-ending punctuation followed by
+/// whitespace
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text.trim();
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = rest.len();
+        let mut chars = rest.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            let next_is_space = chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+            if matches!(c, '.' | '!' | '?' | '…') && next_is_space {
+                end = index + c.len_utf8();
+                break;
+            }
+        }
+        let sentence = &rest[..end];
+        rest = rest[end..].trim_start();
+        Some(sentence)
+    })
+}
+
+/// Lowercase letters and digits of a word
+fn normalize(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+fn normalize_phrase(phrase: &str) -> String {
+    phrase.split_whitespace().map(normalize).filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(text: &str, no_speech_probability: f32) -> (String, Vec<FilteredSegment>) {
+        filter_hallucinations(text, no_speech_probability, &HallucinationFilter::default())
+    }
+
+    #[test]
+    fn test_loops_collapse_to_one() {
+        let (text, removed) = filter("Thank you. Thank you. Thank you. Thank you.", 0.0);
+        assert_eq!(text, "Thank you.");
+        assert_eq!(removed[0].text, "Thank you. Thank you. Thank you.");
+        assert_eq!(removed[0].reason, FilterReason::Repetition);
+
+        let (text, _) = filter("so I went to the store I went to the store I went to the store and left", 0.0);
+        assert_eq!(text, "so I went to the store and left");
+
+        // Deliberate repeats stay
+        assert_eq!(filter("No, no, no. That's very very wrong.", 0.0).0, "No, no, no. That's very very wrong.");
+        assert_eq!(filter("go go go go go go", 0.0).0, "go");
+    }
+
+    #[test]
+    fn test_known_phrases_need_a_likely_silence() {
+        let (text, removed) = filter("Thanks for watching!", 0.4);
+        assert_eq!(text, "");
+        let dropped = FilteredSegment { text: "Thanks for watching!".into(), reason: FilterReason::KnownPhrase };
+        assert_eq!(removed, [dropped]);
+
+        // Only whole sentences, and only on audio that may hold no speech
+        assert_eq!(filter("Send the report. Thank you.", 0.4).0, "Send the report.");
+        assert_eq!(filter("Thank you for the report.", 0.4).0, "Thank you for the report.");
+        assert_eq!(filter("Send the report. Thank you.", 0.05).0, "Send the report. Thank you.");
+
+        let extra_phrases = vec!["Untertitel im Auftrag des ZDF".to_string()];
+        let custom = HallucinationFilter { extra_phrases, ..Default::default() };
+        assert_eq!(filter_hallucinations("Untertitel im Auftrag des ZDF.", 0.5, &custom).0, "");
+        let off = HallucinationFilter { enabled: false, ..custom };
+        assert_eq!(filter_hallucinations("Thank you.", 0.9, &off).0, "Thank you.");
+    }
+}
@@ -1,7 +1,65 @@
 //! Speech-to-text transcription engines
 
+mod chunk;
+mod decode;
+mod fallback;
+mod hallucination;
 mod whisper;
 mod moonshine;
 
-pub use whisper::{WhisperEngine, WordTimestamp, TranscriptionResult};
+pub use whisper::{Alternative, WhisperEngine, WordTimestamp, TranscriptionResult};
 pub use moonshine::MoonshineEngine;
+pub(crate) use moonshine::MOONSHINE_MODELS;
+pub(crate) use whisper::WHISPER_MODELS;
+pub use chunk::{plan_chunks, stitch_transcriptions};
+pub use fallback::ActiveSttEngine;
+pub(crate) use fallback::substitutes;
+pub use hallucination::{filter_hallucinations, FilterReason, FilteredSegment, DEFAULT_HALLUCINATION_PHRASES};
+
+use crate::cancel::CancelToken;
+use crate::config::SttTask;
+use anyhow::Result;
+
+/// Per-call transcription settings
+#[derive(Debug, Clone, Copy)]
+pub struct SttOptions<'a> {
+    /// Produce word timestamps
+    pub enable_timestamps: bool,
+    /// Spoken language (ISO 639-1 code, or "auto" to detect it)
+    pub language: &'a str,
+    /// Transcribe, or translate to English
+    pub task: SttTask,
+    /// Text about the recording (names, jargon, the topic) to bias
+    /// recognition towards, on top of the configured vocabulary. Whisper
+    /// decodes it as prior text; Moonshine has no prompt and boosts the
+    /// capitalized terms and ones with digits in it instead.
+    pub context: Option<&'a str>,
+    /// Checked as the engine goes, to stop early
+    pub cancel: &'a CancelToken,
+    /// A short command (see `ProcessProfile::Command`): decode greedily at
+    /// one temperature and stop after `QUICK_MAX_TOKENS`, as beam search and
+    /// retries cost more than they fix on a word or two
+    pub quick: bool,
+}
+
+/// Most tokens decoded for a short command (see `SttOptions::quick`)
+pub const QUICK_MAX_TOKENS: usize = 16;
+
+/// A speech-to-text engine: `WhisperEngine`, `MoonshineEngine`, or one
+/// supplied to `PipelineBuilder::stt`
+pub trait SpeechToText: Send {
+    /// Transcribe 16kHz mono samples
+    fn transcribe(&mut self, audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult>;
+
+    /// Whether word timestamps come from the decoder rather than being
+    /// estimated
+    fn supports_timestamps(&self) -> bool {
+        false
+    }
+
+    /// Hardware the engine runs on ("cpu", "coreml", "metal" or "cuda"),
+    /// if known
+    fn execution_provider(&self) -> Option<&'static str> {
+        None
+    }
+}
@@ -1,27 +1,136 @@
 //! Moonshine speech-to-text engine using ONNX Runtime
 
-use crate::config::Config;
-use crate::transcribe::whisper::TranscriptionResult;
+use crate::cancel::CancelToken;
+use crate::config::{check_language, check_stt_task, Config, SttEngine, SttExecutionProvider};
+use crate::integrity::verify_file;
+use crate::models::shared::{ModelKey, SharedModels};
+use crate::transcribe::decode::{
+    needs_fallback, repeated_ngram_tokens, repetition_ratio, sample, with_fallback, BeamSearch, Hypothesis,
+    LogitsFilter, Rng,
+};
+use crate::transcribe::whisper::{Alternative, TranscriptionResult, WordTimestamp};
+use crate::transcribe::{SpeechToText, SttOptions, QUICK_MAX_TOKENS};
+use crate::{InitProgress, InitStage, PipelineError};
 use anyhow::{Context, Result};
 use ort::{
+    execution_providers::{CoreMLExecutionProvider, ExecutionProvider},
     session::{builder::GraphOptimizationLevel, Session},
-    value::Tensor,
+    session::SessionInputValue,
+    value::{Tensor, TensorRef},
 };
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Logit boost for tokens that start or continue a vocabulary term
+const VOCABULARY_BIAS: f32 = 2.0;
+
+/// Loaded Moonshine models, shared by the engines that load the same
+/// variant on the same execution provider and thread count
+pub(crate) static MOONSHINE_MODELS: SharedModels<MoonshineSessions> = SharedModels::new("moonshine");
 
 /// Moonshine ONNX-based speech-to-text engine
 pub struct MoonshineEngine {
-    preprocess: Session,
-    encode: Session,
-    uncached_decode: Session,
-    cached_decode: Session,
+    /// The models, shared (see `MOONSHINE_MODELS`)
+    sessions: Arc<MoonshineSessions>,
+    decoder: Decoder,
+    /// Encoder output of the current call
+    encoded: Vec<f32>,
+}
+
+/// The four ONNX models of a Moonshine variant and its tokenizer
+///
+/// `ort` runs a session through `&mut`, so engines sharing them lock each
+/// session for one run: a decoder step, not a whole transcription.
+pub(crate) struct MoonshineSessions {
+    preprocess: Mutex<Session>,
+    encode: Mutex<Session>,
+    uncached: Mutex<Session>,
+    cached: Mutex<Session>,
     tokenizer: Tokenizer,
+    /// "coreml" if every session runs on Core ML, else "cpu"
+    provider: &'static str,
+}
+
+/// A shared session, to run
+fn lock(session: &Mutex<Session>) -> MutexGuard<'_, Session> {
+    session.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// KV cache of the decoder, one (shape, data) per cache tensor
+type KvCache = Vec<(Vec<usize>, Vec<f32>)>;
+
+/// How an engine decodes (see `SttDecodeParams`), and its KV cache
+struct Decoder {
+    sessions: Arc<MoonshineSessions>,
+    steering: Steering,
+    /// Beam width at temperature 0 (1 decodes greedily)
+    beam_size: usize,
+    patience: f32,
+    /// Hypotheses kept from beam search, the best included
+    /// (`SttDecodeParams::alternatives`)
+    n_best: usize,
+    /// Temperatures tried in order until a decode passes the quality checks
+    temperatures: Vec<f32>,
+    /// The current call is a short command (`SttOptions::quick`)
+    quick: bool,
+    buffers: DecodeBuffers,
+}
+
+/// Decoder buffers kept between calls, so a warm decode only allocates when
+/// a longer one needs more room
+#[derive(Default)]
+struct DecodeBuffers {
+    /// Tokens generated so far
+    tokens: Vec<i64>,
+    /// Logits for the next token, from the last decoder step
+    logits: Vec<f32>,
+    /// KV cache from the last decoder step
+    cache: KvCache,
+    /// Hypotheses beam search ranked below `tokens`, best first
+    runners_up: Vec<Hypothesis>,
+}
+
+/// Adjustments to the logits at every step
+struct Steering {
+    bias: VocabularyBias,
+    /// Terms of the current call's context (`SttOptions::context`)
+    context: VocabularyBias,
+    /// Length of the token runs that may not repeat (0 allows repeats)
+    no_repeat_ngram: usize,
+    /// Token that may not start a transcript (`SttDecodeParams::suppress_blank`)
+    blank_token: Option<i64>,
+}
+
+impl LogitsFilter for Steering {
+    fn filter<'a>(&self, generated: &[i64], logits: &'a [f32]) -> Cow<'a, [f32]> {
+        let mut logits = self.bias.apply(generated, logits);
+        if !self.context.sequences.is_empty() {
+            let boosted = self.context.apply(generated, &logits).into_owned();
+            logits = Cow::Owned(boosted);
+        }
+        let mut banned = repeated_ngram_tokens(generated, self.no_repeat_ngram);
+        if generated.is_empty() {
+            banned.extend(self.blank_token);
+        }
+        if !banned.is_empty() {
+            let filtered = logits.to_mut();
+            for token in banned {
+                if let Some(logit) = filtered.get_mut(token as usize) {
+                    *logit = f32::NEG_INFINITY;
+                }
+            }
+        }
+        logits
+    }
 }
 
 /// Simple tokenizer for Moonshine (vocab.json based)
 struct Tokenizer {
     id_to_token: HashMap<i64, String>,
+    token_to_id: HashMap<String, i64>,
     eos_token_id: i64,
     sos_token_id: i64,
 }
@@ -58,8 +167,11 @@ impl Tokenizer {
             HashMap::new()
         };
 
+        let token_to_id = id_to_token.iter().map(|(id, token)| (token.clone(), *id)).collect();
+
         Ok(Self {
             id_to_token,
+            token_to_id,
             eos_token_id: 2, // Standard EOS token
             sos_token_id: 1, // Standard SOS/BOS token
         })
@@ -79,53 +191,240 @@ impl Tokenizer {
         }
         result.trim().to_string()
     }
+
+    /// Encode text as a word-initial token sequence
+    ///
+    /// Greedy longest match against the vocabulary; close enough to the
+    /// sentencepiece segmentation for biasing. Characters with no token are
+    /// skipped.
+    fn encode(&self, text: &str) -> Vec<i64> {
+        let pieces: Vec<char> = text
+            .split_whitespace()
+            .flat_map(|word| std::iter::once('▁').chain(word.chars()))
+            .collect();
+
+        let mut ids = Vec::new();
+        let mut start = 0;
+        while start < pieces.len() {
+            let matched = (start + 1..=pieces.len().min(start + 32)).rev().find_map(|end| {
+                let piece: String = pieces[start..end].iter().collect();
+                self.token_to_id.get(&piece).map(|&id| (id, end))
+            });
+            match matched {
+                Some((id, end)) => {
+                    ids.push(id);
+                    start = end;
+                }
+                None => start += 1,
+            }
+        }
+        ids
+    }
+}
+
+/// Biases greedy decoding towards vocabulary terms (shallow fusion)
+///
+/// At each step the first token of every term, and the next token of any
+/// term the output is partway through, get a fixed logit boost.
+#[derive(Default)]
+struct VocabularyBias {
+    sequences: Vec<Vec<i64>>,
+}
+
+impl VocabularyBias {
+    fn new(tokenizer: &Tokenizer, config: &Config) -> Self {
+        Self::from_terms(tokenizer, config.vocabulary.iter().map(|entry| entry.term.as_str()))
+    }
+
+    fn from_terms<'a>(tokenizer: &Tokenizer, terms: impl IntoIterator<Item = &'a str>) -> Self {
+        let sequences = terms
+            .into_iter()
+            .map(|term| tokenizer.encode(term))
+            .filter(|tokens| !tokens.is_empty())
+            .collect();
+        Self { sequences }
+    }
+
+    /// Tokens to boost after `generated`
+    fn next_tokens(&self, generated: &[i64]) -> Vec<i64> {
+        let mut next = Vec::new();
+        for sequence in &self.sequences {
+            // Longest proper prefix of the term that the output ends with
+            let matched = (0..sequence.len().min(generated.len() + 1))
+                .rev()
+                .find(|&k| generated.ends_with(&sequence[..k]))
+                .unwrap_or(0);
+            if !next.contains(&sequence[matched]) {
+                next.push(sequence[matched]);
+            }
+        }
+        next
+    }
+
+    /// Logits with the vocabulary boost applied
+    fn apply<'a>(&self, generated: &[i64], logits: &'a [f32]) -> Cow<'a, [f32]> {
+        if self.sequences.is_empty() {
+            return Cow::Borrowed(logits);
+        }
+        let mut biased = logits.to_vec();
+        for token in self.next_tokens(generated) {
+            if let Some(logit) = biased.get_mut(token as usize) {
+                *logit += VOCABULARY_BIAS;
+            }
+        }
+        Cow::Owned(biased)
+    }
 }
 
 impl MoonshineEngine {
     /// Create a new Moonshine engine from the configured model directory
     pub fn new(config: &Config) -> Result<Self> {
+        Self::new_with_progress(config, None)
+    }
+
+    /// Create a new Moonshine engine, reporting the encoder and decoder
+    /// loads to `progress`
+    pub fn new_with_progress(config: &Config, progress: Option<&dyn InitProgress>) -> Result<Self> {
+        // English transcription only: fail up front rather than garble other
+        // languages or ignore the translate task
+        check_language(&config.language, &SttEngine::Moonshine)?;
+        check_stt_task(config.stt_task, &SttEngine::Moonshine)?;
         let model_dir = config.moonshine_model_dir()?;
 
         if !model_dir.exists() {
-            anyhow::bail!(
-                "Moonshine model directory not found at {:?}. Download the model first.",
-                model_dir
-            );
+            return Err(PipelineError::SttModelNotFound {
+                path: model_dir.display().to_string(),
+            }
+            .into());
+        }
+        // Every file of the selected variant is checked before any loads, so
+        // a partly downloaded variant fails at once rather than after the
+        // encoder has loaded
+        let models_dir = config.models_dir()?;
+        for file in config.moonshine_model.required_files() {
+            let path = model_dir.join(file);
+            if !path.exists() {
+                return Err(PipelineError::SttModelNotFound {
+                    path: path.display().to_string(),
+                }
+                .into());
+            }
+            verify_file(&models_dir, &path, config.verify_models)?;
         }
 
-        tracing::info!("Loading Moonshine models from {:?}", model_dir);
+        let coreml = wants_coreml(config.stt_execution_provider);
+        let threads = config.stt_thread_count();
+        let key = ModelKey::new(&model_dir, format!("threads={} coreml={}", threads, coreml));
+        let sessions = MOONSHINE_MODELS.get_or_load(key, || {
+            tracing::info!("Loading Moonshine models ({}) from {:?}", config.moonshine_precision.id(), model_dir);
+            MoonshineSessions::load(&model_dir, threads, coreml, progress)
+        })?;
+
+        let decode = &config.stt_decode;
+        let tokenizer = &sessions.tokenizer;
+        let steering = Steering {
+            bias: VocabularyBias::new(tokenizer, config),
+            context: VocabularyBias::default(),
+            no_repeat_ngram: decode.no_repeat_ngram as usize,
+            blank_token: if decode.suppress_blank { tokenizer.token_to_id.get("▁").copied() } else { None },
+        };
+        let decoder = Decoder {
+            sessions: sessions.clone(),
+            steering,
+            beam_size: decode.beam_size as usize,
+            patience: decode.patience,
+            n_best: decode.alternatives as usize,
+            temperatures: decode.temperatures(config.deterministic).to_vec(),
+            quick: false,
+            buffers: DecodeBuffers::default(),
+        };
 
-        // Load all four ONNX models
-        let preprocess = Self::load_session(&model_dir, "preprocess.onnx")?;
-        let encode = Self::load_session(&model_dir, "encode.onnx")?;
-        let uncached_decode = Self::load_session(&model_dir, "uncached_decode.onnx")?;
-        let cached_decode = Self::load_session(&model_dir, "cached_decode.onnx")?;
+        Ok(Self { sessions, decoder, encoded: Vec::new() })
+    }
+}
 
-        // Load tokenizer
-        let tokenizer = Tokenizer::load(&model_dir)?;
+impl MoonshineSessions {
+    /// Load the models in `model_dir` running on `threads` CPU threads,
+    /// on Core ML if `coreml`
+    fn load(
+        model_dir: &Path,
+        threads: usize,
+        mut coreml: bool,
+        progress: Option<&dyn InitProgress>,
+    ) -> Result<Self> {
+        // Load all four ONNX models; a session Core ML can't take runs on
+        // the CPU, as do the ones after it
+        let mut load = |filename| {
+            if coreml {
+                match Self::load_session(model_dir, filename, threads, true) {
+                    Ok(session) => return Ok(session),
+                    Err(e) if e.downcast_ref::<PipelineError>().is_some_and(|e| {
+                        matches!(e, PipelineError::OnnxLoadFailed { .. })
+                    }) => {
+                        tracing::warn!("Core ML failed for {}, using the CPU: {:#}", filename, e);
+                        coreml = false;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Self::load_session(model_dir, filename, threads, false)
+        };
+        if let Some(progress) = progress {
+            progress.report(InitStage::LoadingSttEncoder);
+        }
+        let preprocess = load("preprocess.onnx")?;
+        let encode = load("encode.onnx")?;
+        if let Some(progress) = progress {
+            progress.report(InitStage::LoadingSttDecoder);
+        }
+        let uncached_decode = load("uncached_decode.onnx")?;
+        let cached_decode = load("cached_decode.onnx")?;
+        let provider = if coreml { "coreml" } else { "cpu" };
+        tracing::info!("Moonshine running on {}", provider);
 
         Ok(Self {
-            preprocess,
-            encode,
-            uncached_decode,
-            cached_decode,
-            tokenizer,
+            preprocess: Mutex::new(preprocess),
+            encode: Mutex::new(encode),
+            uncached: Mutex::new(uncached_decode),
+            cached: Mutex::new(cached_decode),
+            tokenizer: Tokenizer::load(model_dir)?,
+            provider,
         })
     }
 
-    fn load_session(model_dir: &Path, filename: &str) -> Result<Session> {
+    /// Load one ONNX model running on `threads` CPU threads, registering
+    /// Core ML if `coreml` (an error if it can't be registered)
+    fn load_session(model_dir: &Path, filename: &str, threads: usize, coreml: bool) -> Result<Session> {
         let path = model_dir.join(filename);
         if !path.exists() {
-            anyhow::bail!("Model file not found: {:?}", path);
+            return Err(PipelineError::SttModelNotFound {
+                path: path.display().to_string(),
+            }
+            .into());
         }
 
-        Session::builder()?
-            .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(std::thread::available_parallelism()?.get())?
-            .commit_from_file(&path)
-            .with_context(|| format!("Failed to load ONNX model: {:?}", path))
+        let build = || -> ort::Result<Session> {
+            let mut builder = Session::builder()?
+                .with_optimization_level(GraphOptimizationLevel::Level3)?
+                .with_intra_threads(threads)?;
+            if coreml {
+                builder = builder
+                    .with_execution_providers([CoreMLExecutionProvider::default().build().error_on_failure()])?;
+            }
+            builder.commit_from_file(&path)
+        };
+
+        build().map_err(|e| {
+            PipelineError::OnnxLoadFailed {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            }
+            .into()
+        })
     }
+}
 
+impl MoonshineEngine {
     /// Transcribe audio samples to text
     ///
     /// # Arguments
@@ -136,38 +435,65 @@ impl MoonshineEngine {
 
     /// Transcribe audio samples with optional word-level timestamps
     ///
-    /// Note: Moonshine doesn't provide word-level timestamps natively,
-    /// so this returns an empty timestamps vector.
+    /// Note: Moonshine doesn't provide word-level timestamps natively, so
+    /// these are estimated from the speech span (see `estimate_word_timestamps`).
     pub fn transcribe_with_timestamps(
         &mut self,
         audio: &[f32],
-        _enable_timestamps: bool,
+        enable_timestamps: bool,
+    ) -> Result<TranscriptionResult> {
+        self.transcribe_with_cancel(audio, enable_timestamps, &CancelToken::new())
+    }
+
+    /// Transcribe audio samples, checking `cancel` before each decode step
+    pub fn transcribe_with_cancel(
+        &mut self,
+        audio: &[f32],
+        enable_timestamps: bool,
+        cancel: &CancelToken,
     ) -> Result<TranscriptionResult> {
         if audio.is_empty() {
             return Ok(TranscriptionResult {
                 text: String::new(),
                 word_timestamps: vec![],
+                confidence: 0.0,
+                no_speech_probability: 1.0,
+                language: Some("en".to_string()),
+                encode_ms: 0,
+                decode_ms: 0,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
             });
         }
 
         // Calculate max tokens based on audio duration (6 tokens per second is typical)
         let duration_secs = audio.len() as f32 / 16000.0;
         let max_tokens = ((duration_secs * 6.0) as usize).max(10).min(448);
+        let max_tokens = if self.decoder.quick { max_tokens.min(QUICK_MAX_TOKENS) } else { max_tokens };
 
-        // Step 1: Preprocess audio - shape [1, audio_len]
-        let audio_tensor = Tensor::from_array(([1usize, audio.len()], audio.to_vec()))?;
+        // Step 1: Preprocess audio - shape [1, audio_len]. Inputs are views
+        // of existing buffers rather than copies.
+        let t_encode = Instant::now();
+        let audio_tensor = TensorRef::from_array_view(([1usize, audio.len()], audio))?;
 
-        let preprocess_outputs = self.preprocess.run(ort::inputs!["args_0" => audio_tensor])?;
+        let mut preprocess = lock(&self.sessions.preprocess);
+        let preprocess_outputs = preprocess.run(ort::inputs!["args_0" => audio_tensor])?;
         let features_value = &preprocess_outputs["sequential"];
         let (features_shape, features_data) = features_value.try_extract_tensor::<f32>()?;
 
         let seq_len = features_shape[1] as i32;
 
         // Step 2: Encode
-        let features_tensor = Tensor::from_array((features_shape.to_vec(), features_data.to_vec()))?;
+        let features_tensor = TensorRef::from_array_view((features_shape.to_vec(), features_data))?;
         let seq_len_tensor = Tensor::from_array(([1usize], vec![seq_len]))?;
 
-        let encode_outputs = self.encode.run(ort::inputs![
+        if cancel.is_cancelled() {
+            return Err(PipelineError::cancelled().into());
+        }
+
+        let mut encode = lock(&self.sessions.encode);
+        let encode_outputs = encode.run(ort::inputs![
             "args_0" => features_tensor,
             "args_1" => seq_len_tensor
         ])?;
@@ -177,117 +503,508 @@ impl MoonshineEngine {
             .ok_or_else(|| anyhow::anyhow!("No output from encode model"))?
             .1;
         let (context_shape, context_data) = context_value.try_extract_tensor::<f32>()?;
+        let context_shape: Vec<usize> = context_shape.iter().map(|&d| d as usize).collect();
+        // Copied out, so engines sharing the sessions can run them while
+        // this one decodes
+        self.encoded.clear();
+        self.encoded.extend_from_slice(context_data);
+        drop(encode_outputs);
+        drop(encode);
+        drop(preprocess_outputs);
+        drop(preprocess);
+        let encode_ms = t_encode.elapsed().as_millis() as u64;
 
         // Step 3: Uncached decode (first token)
+        let t_decode = Instant::now();
+        let context = (context_shape.as_slice(), self.encoded.as_slice());
+        let decoder = &mut self.decoder;
+        decoder.start(context)?;
+        let eos = decoder.sessions.tokenizer.eos_token_id;
+        // Moonshine has no no-speech token; the chance of ending before the
+        // first word is the closest equivalent
+        let no_speech_probability = Self::log_softmax(&decoder.buffers.logits, eos).exp();
+        let first_token = Self::argmax(&decoder.steering.filter(&[], &decoder.buffers.logits));
+
+        tracing::trace!("Moonshine: first token = {}, EOS = {}", first_token, eos);
+        tracing::trace!("Moonshine: vocab size = {}", decoder.sessions.tokenizer.id_to_token.len());
+
+        if first_token == eos {
+            tracing::debug!("Moonshine: first token is EOS, returning empty");
+            return Ok(TranscriptionResult {
+                text: String::new(),
+                word_timestamps: vec![],
+                confidence: 0.0,
+                no_speech_probability,
+                language: Some("en".to_string()),
+                encode_ms,
+                decode_ms: t_decode.elapsed().as_millis() as u64,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
+            });
+        }
+
+        // Step 4: Decode the rest, again at the next temperature while the
+        // result looks wrong. Each retry starts over from the uncached
+        // decoder.
+        let temperatures = if decoder.quick { decoder.temperatures[..1].to_vec() } else { decoder.temperatures.clone() };
+        let mut retry = false;
+        let ((mean_log_prob, _), temperature) = with_fallback(
+            &temperatures,
+            |temperature| {
+                if std::mem::replace(&mut retry, true) {
+                    decoder.start(context)?;
+                }
+                let log_prob = if temperature == 0.0 && decoder.beam_size > 1 && !decoder.quick {
+                    decoder.beam_search(context, max_tokens, cancel)?
+                } else {
+                    decoder.decode_one(context, temperature, max_tokens, cancel)?
+                };
+                let tokens = &decoder.buffers.tokens;
+                Ok((log_prob / tokens.len().max(1) as f32, repetition_ratio(tokens)))
+            },
+            |&(mean_log_prob, repetition)| needs_fallback(mean_log_prob, repetition),
+        )?;
+
+        let decode_ms = t_decode.elapsed().as_millis() as u64;
+        let tokens = &decoder.buffers.tokens;
+        tracing::trace!("Moonshine: generated {} tokens: {:?}", tokens.len(), &tokens[..tokens.len().min(20)]);
+        let text = decoder.sessions.tokenizer.decode(tokens);
+        tracing::debug!("Moonshine: decoded text = '{}' (temperature {})", text, temperature);
+
+        let word_timestamps = if enable_timestamps {
+            estimate_word_timestamps(&text, audio)
+        } else {
+            vec![]
+        };
+
+        let alternatives = decoder.alternatives(&text);
+
+        Ok(TranscriptionResult {
+            text,
+            word_timestamps,
+            confidence: if tokens.is_empty() { 0.0 } else { mean_log_prob.exp() },
+            no_speech_probability,
+            language: Some("en".to_string()),
+            encode_ms,
+            decode_ms,
+            temperature,
+            temperature_fallback: temperature != temperatures[0],
+            alternatives,
+        })
+    }
+
+    /// Find the index of the maximum value in a slice
+    fn argmax(slice: &[f32]) -> i64 {
+        slice
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, _)| i as i64)
+            .unwrap_or(0)
+    }
+
+    /// Log-probability of `index` under the softmax of `logits`
+    fn log_softmax(logits: &[f32], index: i64) -> f32 {
+        let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let log_sum = logits.iter().map(|&x| (x - max).exp()).sum::<f32>().ln();
+        logits.get(index as usize).map_or(f32::NEG_INFINITY, |&x| x - max - log_sum)
+    }
+}
+
+impl Decoder {
+    /// Run the uncached decoder on the start token, leaving its logits and
+    /// KV cache in the buffers
+    fn start(&mut self, context: (&[usize], &[f32])) -> Result<()> {
         // IMPORTANT: Model expects int32 tensors, not int64
-        let initial_token = Tensor::from_array(([1usize, 1], vec![self.tokenizer.sos_token_id as i32]))?;
-        let context_tensor = Tensor::from_array((context_shape.to_vec(), context_data.to_vec()))?;
+        let initial_token = Tensor::from_array(([1usize, 1], vec![self.sessions.tokenizer.sos_token_id as i32]))?;
+        let context_tensor = TensorRef::from_array_view((context.0.to_vec(), context.1))?;
         let seq_len_decode = Tensor::from_array(([1usize], vec![1i32]))?;
 
-        let uncached_outputs = self.uncached_decode.run(ort::inputs![
+        let mut uncached = lock(&self.sessions.uncached);
+        let outputs = uncached.run(ort::inputs![
             "args_0" => initial_token,
             "args_1" => context_tensor,
             "args_2" => seq_len_decode
         ])?;
 
-        // Collect all outputs - first is logits, rest are cache tensors
-        let output_names: Vec<_> = uncached_outputs.iter().map(|(name, _)| name.to_string()).collect();
-
         // Get logits (first output)
-        let logits_value = uncached_outputs.iter().next()
+        let logits_value = outputs.iter().next()
             .ok_or_else(|| anyhow::anyhow!("No output from uncached_decode model"))?
             .1;
-        let (logits_shape, logits_data) = logits_value.try_extract_tensor::<f32>()?;
+        let (_, logits_data) = logits_value.try_extract_tensor::<f32>()?;
+        let buffers = &mut self.buffers;
+        buffers.logits.clear();
+        buffers.logits.extend_from_slice(logits_data);
+
+        // Copy the cache out (shape + data) to avoid ONNX Runtime mutex
+        // issues, into buffers kept from the last call
+        buffers.cache.resize_with(outputs.iter().count() - 1, Default::default);
+        for ((_, value), (shape, data)) in outputs.iter().skip(1).zip(buffers.cache.iter_mut()) {
+            let (value_shape, value_data) = value.try_extract_tensor::<f32>()?;
+            shape.clear();
+            shape.extend(value_shape.iter().map(|&x| x as usize));
+            data.clear();
+            data.extend_from_slice(value_data);
+        }
+        Ok(())
+    }
 
-        let mut tokens = Vec::new();
-        let first_token = Self::argmax(logits_data);
+    /// Feed `token` at `position` to the cached decoder on top of `cache`,
+    /// replacing the cache with the updated one and `logits` with the
+    /// logits for the next token
+    fn step(
+        session: &Mutex<Session>,
+        token: i64,
+        position: usize,
+        context: (&[usize], &[f32]),
+        cache: &mut KvCache,
+        logits: &mut Vec<f32>,
+    ) -> Result<()> {
+        let token_tensor = Tensor::from_array(([1usize, 1], vec![token as i32]))?;
+        let pos_tensor = Tensor::from_array(([1usize], vec![position as i32]))?;
+
+        // Create input map with token, context, position, and cache tensors
+        let mut inputs: Vec<(Cow<str>, SessionInputValue)> = Vec::with_capacity(cache.len() + 3);
+        inputs.push(("args_0".into(), token_tensor.into()));
+        inputs.push(("args_1".into(), TensorRef::from_array_view((context.0.to_vec(), context.1))?.into()));
+        inputs.push(("args_2".into(), pos_tensor.into()));
+
+        // Add cache tensors as views of the buffers
+        for (i, (shape, data)) in cache.iter().enumerate() {
+            let tensor = TensorRef::from_array_view((shape.clone(), data.as_slice()))?;
+            inputs.push((format!("args_{}", i + 3).into(), tensor.into()));
+        }
 
-        eprintln!("Moonshine: first token = {}, EOS = {}", first_token, self.tokenizer.eos_token_id);
-        eprintln!("Moonshine: vocab size = {}", self.tokenizer.id_to_token.len());
+        let mut session = lock(session);
+        let outputs = session.run(inputs)?;
 
-        if first_token == self.tokenizer.eos_token_id {
-            eprintln!("Moonshine: first token is EOS, returning empty");
-            return Ok(TranscriptionResult {
-                text: String::new(),
-                word_timestamps: vec![],
-            });
+        // Get logits from first output
+        let logits_value = outputs.iter().next()
+            .ok_or_else(|| anyhow::anyhow!("No output from cached_decode"))?
+            .1;
+        let (_, logits_data) = logits_value.try_extract_tensor::<f32>()?;
+        logits.clear();
+        logits.extend_from_slice(logits_data);
+
+        // Update the cache, reusing its buffers
+        cache.resize_with(outputs.iter().count() - 1, Default::default);
+        for ((_, value), (shape, data)) in outputs.iter().skip(1).zip(cache.iter_mut()) {
+            let (value_shape, value_data) = value.try_extract_tensor::<f32>()?;
+            shape.clear();
+            shape.extend(value_shape.iter().map(|&x| x as usize));
+            data.clear();
+            data.extend_from_slice(value_data);
         }
-        tokens.push(first_token);
+        Ok(())
+    }
 
-        // Extract cache data as owned vectors (shape + data) to avoid ONNX Runtime mutex issues
-        let mut cache_data: Vec<(Vec<i64>, Vec<f32>)> = Vec::new();
-        for (_name, value) in uncached_outputs.iter().skip(1) {
-            let (shape, data) = value.try_extract_tensor::<f32>()?;
-            cache_data.push((shape.to_vec(), data.to_vec()));
-        }
-        // Drop uncached_outputs to release ONNX Runtime resources
-        drop(uncached_outputs);
-
-        // Step 4: Cached decode loop for remaining tokens
-        let mut current_token = first_token;
-
-        for step in 2..=max_tokens {
-            // Build inputs for cached_decode
-            let token_tensor = Tensor::from_array(([1usize, 1], vec![current_token as i32]))?;
-            let pos_tensor = Tensor::from_array(([1usize], vec![step as i32]))?;
-
-            // Create input map with token, context, position, and cache tensors
-            let mut inputs: Vec<(std::borrow::Cow<str>, ort::value::DynValue)> = Vec::new();
-            inputs.push(("args_0".into(), token_tensor.into()));
-            inputs.push(("args_1".into(), Tensor::from_array((context_shape.to_vec(), context_data.to_vec()))?.into()));
-            inputs.push(("args_2".into(), pos_tensor.into()));
-
-            // Add cache tensors from owned data
-            for (i, (shape, data)) in cache_data.iter().enumerate() {
-                let shape_usize: Vec<usize> = shape.iter().map(|&x| x as usize).collect();
-                let tensor = Tensor::from_array((shape_usize, data.clone()))?;
-                inputs.push((format!("args_{}", i + 3).into(), tensor.into()));
+    /// Decode one hypothesis on from `start`: greedily at temperature 0,
+    /// otherwise sampling
+    ///
+    /// The tokens are left in `buffers.tokens`; returns the sum of their
+    /// log-probabilities.
+    fn decode_one(
+        &mut self,
+        context: (&[usize], &[f32]),
+        temperature: f32,
+        max_tokens: usize,
+        cancel: &CancelToken,
+    ) -> Result<f32> {
+        let Self { sessions, steering, buffers, .. } = self;
+        let (cached, tokenizer) = (&sessions.cached, &sessions.tokenizer);
+        let mut rng = Rng::new(0);
+        let mut log_prob_sum = 0.0f32;
+        buffers.tokens.clear();
+        buffers.runners_up.clear();
+
+        while buffers.tokens.len() < max_tokens {
+            if cancel.is_cancelled() {
+                return Err(PipelineError::cancelled().into());
             }
 
-            let cached_outputs = self.cached_decode.run(inputs)?;
-
-            // Get logits from first output
-            let logits_value = cached_outputs.iter().next()
-                .ok_or_else(|| anyhow::anyhow!("No output from cached_decode"))?
-                .1;
-            let (_, logits_data) = logits_value.try_extract_tensor::<f32>()?;
-
-            let next_token = Self::argmax(logits_data);
+            let next_token = {
+                let logits = steering.filter(&buffers.tokens, &buffers.logits);
+                if temperature > 0.0 {
+                    sample(&logits, temperature, &mut rng)
+                } else {
+                    MoonshineEngine::argmax(&logits)
+                }
+            };
+            if next_token == tokenizer.eos_token_id {
+                break;
+            }
 
-            if next_token == self.tokenizer.eos_token_id {
+            log_prob_sum += MoonshineEngine::log_softmax(&buffers.logits, next_token);
+            buffers.tokens.push(next_token);
+            if buffers.tokens.len() == max_tokens {
                 break;
             }
+            let position = buffers.tokens.len() + 1;
+            Self::step(cached, next_token, position, context, &mut buffers.cache, &mut buffers.logits)?;
+        }
+        Ok(log_prob_sum)
+    }
 
-            tokens.push(next_token);
-            current_token = next_token;
+    /// Decode with beam search on from `start`, each hypothesis with its own
+    /// copy of the KV cache
+    ///
+    /// The best hypothesis' tokens are left in `buffers.tokens` and the
+    /// next `n_best - 1` in `buffers.runners_up`; returns the sum of the
+    /// best's log-probabilities.
+    fn beam_search(&mut self, context: (&[usize], &[f32]), max_tokens: usize, cancel: &CancelToken) -> Result<f32> {
+        let Self { sessions, steering, buffers, beam_size, patience, n_best, .. } = self;
+        let (cached, tokenizer) = (&sessions.cached, &sessions.tokenizer);
+        let search = BeamSearch {
+            beam_size: *beam_size,
+            patience: *patience,
+            max_tokens,
+            eos: tokenizer.eos_token_id,
+            n_best: *n_best,
+        };
+        let mut ranked = search.run(buffers.logits.clone(), buffers.cache.clone(), &*steering, |tokens, cache: &KvCache| {
+            if cancel.is_cancelled() {
+                return Err(PipelineError::cancelled().into());
+            }
+            let mut cache = cache.clone();
+            let mut logits = Vec::new();
+            Self::step(cached, tokens[tokens.len() - 1], tokens.len() + 1, context, &mut cache, &mut logits)?;
+            Ok((logits, cache))
+        })?;
+        let best = ranked.remove(0);
+        buffers.tokens.clear();
+        buffers.tokens.extend_from_slice(&best.tokens);
+        buffers.runners_up = ranked;
+        Ok(best.log_prob)
+    }
 
-            // Update cache data for next iteration (extract to owned vectors)
-            cache_data.clear();
-            for (_, value) in cached_outputs.iter().skip(1) {
-                let (shape, data) = value.try_extract_tensor::<f32>()?;
-                cache_data.push((shape.to_vec(), data.to_vec()));
+    /// Text of the runners-up of the last beam search, leaving out empty
+    /// ones and any that read the same as `best` or an earlier one
+    fn alternatives(&self, best: &str) -> Vec<Alternative> {
+        let mut alternatives: Vec<Alternative> = Vec::new();
+        for hypothesis in &self.buffers.runners_up {
+            let text = self.sessions.tokenizer.decode(&hypothesis.tokens);
+            if text.is_empty() || text == best || alternatives.iter().any(|alternative| alternative.text == text) {
+                continue;
             }
-            // Drop cached_outputs to release ONNX Runtime resources
-            drop(cached_outputs);
+            alternatives.push(Alternative { text, confidence: hypothesis.mean_log_prob().exp() });
         }
+        alternatives
+    }
+}
 
-        eprintln!("Moonshine: generated {} tokens: {:?}", tokens.len(), &tokens[..tokens.len().min(20)]);
-        let text = self.tokenizer.decode(&tokens);
-        eprintln!("Moonshine: decoded text = '{}'", text);
+/// Transcribes English only, which `check_language` and `check_stt_task`
+/// enforce beforehand
+impl SpeechToText for MoonshineEngine {
+    fn transcribe(&mut self, audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult> {
+        tracing::trace!("Moonshine transcribing {} samples", audio.len());
+        // Moonshine takes no prompt; the context's terms are boosted like
+        // the vocabulary's instead
+        let decoder = &mut self.decoder;
+        decoder.steering.context = VocabularyBias::from_terms(&decoder.sessions.tokenizer, context_terms(opts.context));
+        decoder.quick = opts.quick;
+        let result = self.transcribe_with_cancel(audio, opts.enable_timestamps, opts.cancel);
+        self.decoder.steering.context = VocabularyBias::default();
+        self.decoder.quick = false;
+        result
+    }
 
-        Ok(TranscriptionResult {
-            text,
-            word_timestamps: vec![], // Moonshine doesn't provide timestamps
+    fn execution_provider(&self) -> Option<&'static str> {
+        Some(self.sessions.provider)
+    }
+}
+
+/// Words of an STT context that look like terms: names, acronyms and
+/// product names, with a capital or a digit
+///
+/// Boosting every word would push the decoder towards common words like
+/// "the" wherever they were said.
+fn context_terms(context: Option<&str>) -> Vec<&str> {
+    let mut terms: Vec<&str> = Vec::new();
+    let words = context.unwrap_or_default().split_whitespace();
+    for word in words.map(|word| word.trim_matches(|c: char| !c.is_alphanumeric())) {
+        if word.chars().any(|c| c.is_uppercase() || c.is_ascii_digit()) && !terms.contains(&word) {
+            terms.push(word);
+        }
+    }
+    terms
+}
+
+/// Whether to try Core ML for `provider`, checking that the loaded ONNX
+/// Runtime has it
+fn wants_coreml(provider: SttExecutionProvider) -> bool {
+    let wanted = match provider {
+        SttExecutionProvider::Cpu => false,
+        SttExecutionProvider::CoreMl => true,
+        SttExecutionProvider::Auto => cfg!(target_vendor = "apple"),
+    };
+    if !wanted {
+        return false;
+    }
+    match CoreMLExecutionProvider::default().is_available() {
+        Ok(true) => true,
+        Ok(false) => {
+            tracing::warn!("Core ML isn't available in this ONNX Runtime, using the CPU");
+            false
+        }
+        Err(e) => {
+            tracing::warn!("Couldn't check for Core ML, using the CPU: {}", e);
+            false
+        }
+    }
+}
+
+/// Estimate word timings for a transcript without decoder timestamps
+///
+/// Finds the span between the first and last frame with speech energy and
+/// spreads the words across it in proportion to their length. Estimated
+/// words carry a probability of 0.0.
+pub(crate) fn estimate_word_timestamps(text: &str, audio: &[f32]) -> Vec<WordTimestamp> {
+    const FRAME: usize = 480; // 30ms at 16kHz
+
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() || audio.is_empty() {
+        return vec![];
+    }
+
+    let rms: Vec<f32> = audio
+        .chunks(FRAME)
+        .map(|frame| (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt())
+        .collect();
+    let threshold = rms.iter().cloned().fold(0.0f32, f32::max) * 0.1;
+    let first = rms.iter().position(|&r| r > threshold).unwrap_or(0);
+    let last = rms.iter().rposition(|&r| r > threshold).unwrap_or(rms.len() - 1);
+
+    let to_ms = |frame: usize| ((frame * FRAME).min(audio.len()) as i64) * 1000 / 16000;
+    let span_start = to_ms(first);
+    let span_ms = (to_ms(last + 1) - span_start) as f64;
+
+    // Weight by characters plus one, so short words still get some time
+    let weights: Vec<f64> = words.iter().map(|w| w.chars().count() as f64 + 1.0).collect();
+    let total: f64 = weights.iter().sum();
+
+    let mut elapsed = 0.0;
+    words
+        .iter()
+        .zip(&weights)
+        .map(|(word, weight)| {
+            let start_ms = span_start + (span_ms * elapsed / total).round() as i64;
+            elapsed += weight;
+            let end_ms = span_start + (span_ms * elapsed / total).round() as i64;
+            WordTimestamp {
+                word: word.to_string(),
+                start_ms,
+                end_ms,
+                probability: 0.0,
+            }
         })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_word_timestamps_covers_speech_span() {
+        // 0.5s silence, 1s tone, 0.5s silence
+        let mut audio = vec![0.0f32; 8000];
+        audio.extend((0..16000).map(|i| (i as f32 * 0.1).sin() * 0.5));
+        audio.extend(vec![0.0f32; 8000]);
+
+        let words = estimate_word_timestamps("one three", &audio);
+        assert_eq!(words.len(), 2);
+        assert!((words[0].start_ms - 500).abs() <= 30, "start {}", words[0].start_ms);
+        assert!((words[1].end_ms - 1500).abs() <= 30, "end {}", words[1].end_ms);
+        assert_eq!(words[0].end_ms, words[1].start_ms);
+        // "three" is longer, so it gets more of the span
+        assert!(words[1].end_ms - words[1].start_ms > words[0].end_ms - words[0].start_ms);
     }
 
-    /// Find the index of the maximum value in a slice
-    fn argmax(slice: &[f32]) -> i64 {
-        slice
-            .iter()
-            .enumerate()
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(i, _)| i as i64)
-            .unwrap_or(0)
+    #[test]
+    fn test_log_softmax() {
+        let logits = [1.0f32, 2.0, 3.0];
+        let total: f32 = (0..3).map(|i| MoonshineEngine::log_softmax(&logits, i).exp()).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+        assert!((MoonshineEngine::log_softmax(&[0.0, 0.0], 1) - 0.5f32.ln()).abs() < 1e-6);
+    }
+
+    fn tokenizer(tokens: &[&str]) -> Tokenizer {
+        let id_to_token: HashMap<i64, String> =
+            tokens.iter().enumerate().map(|(i, t)| (i as i64 + 3, t.to_string())).collect();
+        Tokenizer {
+            token_to_id: id_to_token.iter().map(|(id, token)| (token.clone(), *id)).collect(),
+            id_to_token,
+            eos_token_id: 2,
+            sos_token_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_encode_longest_match() {
+        let tok = tokenizer(&["▁K", "▁Kub", "er", "ern", "etes", "e", "t", "s"]);
+        let ids = tok.encode("Kubernetes");
+        assert_eq!(tok.decode(&ids), "Kubernetes");
+        // ▁Kub + ern + etes
+        assert_eq!(ids.len(), 3);
+    }
+
+    #[test]
+    fn test_vocabulary_bias_follows_term() {
+        let bias = VocabularyBias {
+            sequences: vec![vec![10, 11, 12], vec![20]],
+        };
+        // Nothing matched yet: boost the first token of each term
+        assert_eq!(bias.next_tokens(&[5]), vec![10, 20]);
+        // Partway through the first term: boost its continuation
+        assert_eq!(bias.next_tokens(&[5, 10, 11]), vec![12, 20]);
+
+        let logits = vec![0.0f32; 30];
+        let biased = bias.apply(&[10], &logits);
+        assert_eq!(biased[11], VOCABULARY_BIAS);
+        assert_eq!(biased[10], 0.0);
+
+        let unbiased = VocabularyBias::default();
+        assert!(matches!(unbiased.apply(&[], &logits), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_context_terms() {
+        assert_eq!(
+            context_terms(Some("Standup: the k8s rollout, Grafana dashboards and PagerDuty (PagerDuty).")),
+            vec!["Standup", "k8s", "Grafana", "PagerDuty"]
+        );
+        assert!(context_terms(Some("just some notes")).is_empty());
+        assert!(context_terms(None).is_empty());
+    }
+
+    #[test]
+    fn test_partly_downloaded_variant_fails_before_loading() {
+        use crate::config::ModelPrecision;
+
+        let dir = std::env::temp_dir().join(format!("voiceflow-moonshine-partial-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = Config {
+            moonshine_precision: ModelPrecision::Int8,
+            models_dir_override: Some(dir.clone()),
+            ..Config::default()
+        };
+        let model_dir = dir.join("moonshine-tiny-int8");
+        std::fs::create_dir_all(&model_dir).unwrap();
+        // Not a real model: loading it would fail with OnnxLoadFailed instead
+        std::fs::write(model_dir.join("preprocess.onnx"), b"").unwrap();
+
+        let err = MoonshineEngine::new(&config).err().unwrap();
+        match err.downcast_ref::<PipelineError>() {
+            Some(PipelineError::SttModelNotFound { path }) => assert!(path.ends_with("encode.onnx"), "{}", path),
+            _ => panic!("unexpected error: {:#}", err),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_estimate_word_timestamps_empty() {
+        assert!(estimate_word_timestamps("", &[0.1; 160]).is_empty());
+        assert!(estimate_word_timestamps("hello", &[]).is_empty());
     }
 }
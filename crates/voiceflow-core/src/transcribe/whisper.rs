@@ -1,11 +1,29 @@
 //! Whisper speech-to-text engine
 
-use crate::config::Config;
+use crate::cancel::CancelToken;
+use crate::config::{Config, SttDecodeParams, SttExecutionProvider, SttTask, AUTO_LANGUAGE};
+use crate::integrity::verify_file;
+use crate::models::shared::{ModelKey, SharedModels};
+use crate::transcribe::decode::{needs_fallback, repetition_ratio, with_fallback};
+use crate::transcribe::{SpeechToText, SttOptions, QUICK_MAX_TOKENS};
+use crate::PipelineError;
 use anyhow::{Context, Result};
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState, WhisperToken};
+
+/// Prompt tokens Whisper's decoder attends to, half its 448-token text
+/// context; longer prompts lose their start
+const MAX_PROMPT_TOKENS: usize = 224;
+
+/// Loaded Whisper models, shared by the engines that load the same file on
+/// the same backend; each engine decodes with its own state
+pub(crate) static WHISPER_MODELS: SharedModels<WhisperContext> = SharedModels::new("whisper");
 
 /// A word with its timestamp information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct WordTimestamp {
     /// The word text
     pub word: String,
@@ -17,6 +35,16 @@ pub struct WordTimestamp {
     pub probability: f32,
 }
 
+/// Another reading of a recording, from beam search's runners-up (see
+/// `SttDecodeParams::alternatives`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alternative {
+    pub text: String,
+    /// exp of the average token log-probability (0.0 - 1.0), as
+    /// `TranscriptionResult::confidence`
+    pub confidence: f32,
+}
+
 /// Result of transcription with optional word timestamps
 #[derive(Debug, Clone)]
 pub struct TranscriptionResult {
@@ -24,34 +52,191 @@ pub struct TranscriptionResult {
     pub text: String,
     /// Word-level timestamps (if enabled)
     pub word_timestamps: Vec<WordTimestamp>,
+    /// Decoder confidence: exp of the average token log-probability (0.0 - 1.0)
+    pub confidence: f32,
+    /// Probability that the audio contains no speech (0.0 - 1.0)
+    pub no_speech_probability: f32,
+    /// Language the audio was decoded in (ISO 639-1 code), if known
+    pub language: Option<String>,
+    /// Time spent in the encoder, feature extraction included (for
+    /// Whisper, only language detection; see `decode_ms`)
+    pub encode_ms: u64,
+    /// Time spent in the decoder (for Whisper, the whole transcription:
+    /// whisper.cpp encodes and decodes in a single call)
+    pub decode_ms: u64,
+    /// Temperature of the decode that was kept (0.0 for greedy or beam
+    /// search)
+    pub temperature: f32,
+    /// An earlier decode failed the quality checks and was retried at a
+    /// higher temperature (see `SttDecodeParams::temperature_fallback`)
+    pub temperature_fallback: bool,
+    /// Other readings of the audio, most likely first, when more than one
+    /// was asked for (see `SttDecodeParams::alternatives`)
+    pub alternatives: Vec<Alternative>,
+}
+
+/// Timing of a single decoder token
+struct TokenTiming {
+    text: String,
+    start_ms: i64,
+    end_ms: i64,
+    probability: f32,
+    log_probability: f32,
+}
+
+/// Special tokens ([_BEG_], [_TT_n], <|endoftext|>) carry no text
+fn is_special_token(text: &str) -> bool {
+    text.starts_with('[') || text.starts_with("<|")
+}
+
+/// Chance a segment is silence, from the probability of its first text
+/// token
+///
+/// whisper-rs doesn't expose whisper.cpp's no-speech probability of a
+/// decoding state. The first token was picked over the no-speech token at
+/// the same step, so the rest of the probability bounds it.
+fn no_speech_from_first_token(probability: f32) -> f32 {
+    (1.0 - probability).clamp(0.0, 1.0)
+}
+
+/// exp of the mean log-probability of the text tokens (0.0 if there are none)
+fn confidence_from_tokens(tokens: &[TokenTiming]) -> f32 {
+    let log_probs: Vec<f32> = tokens
+        .iter()
+        .filter(|t| !t.text.trim().is_empty() && !is_special_token(t.text.trim()))
+        .map(|t| t.log_probability)
+        .collect();
+    if log_probs.is_empty() {
+        return 0.0;
+    }
+    (log_probs.iter().sum::<f32>() / log_probs.len() as f32).exp()
+}
+
+/// Identify the spoken language with Whisper's language-ID pass over the
+/// start of the audio
+fn detect_language(state: &mut WhisperState, audio: &[f32], n_threads: usize) -> Result<String> {
+    state.pcm_to_mel(audio, n_threads)?;
+    let (lang_id, _probabilities) = state.lang_detect(0, n_threads)?;
+    let language = whisper_rs::get_lang_str(lang_id).context("Whisper detected an unknown language")?;
+    Ok(language.to_string())
+}
+
+/// Merge decoder tokens into words
+///
+/// Whisper marks the start of a word with a leading space; tokens without
+/// one (word pieces, trailing punctuation) extend the previous word. A
+/// word's probability is the mean over its tokens.
+fn words_from_tokens(tokens: &[TokenTiming]) -> Vec<WordTimestamp> {
+    let mut words: Vec<WordTimestamp> = Vec::new();
+    let mut tokens_in_word = 0usize;
+
+    for token in tokens {
+        let trimmed = token.text.trim();
+        if trimmed.is_empty() || is_special_token(trimmed) {
+            continue;
+        }
+
+        match words.last_mut() {
+            Some(word) if !token.text.starts_with(char::is_whitespace) => {
+                word.word.push_str(trimmed);
+                word.end_ms = word.end_ms.max(token.end_ms);
+                word.probability = (word.probability * tokens_in_word as f32 + token.probability)
+                    / (tokens_in_word + 1) as f32;
+                tokens_in_word += 1;
+            }
+            _ => {
+                words.push(WordTimestamp {
+                    word: trimmed.to_string(),
+                    start_ms: token.start_ms,
+                    end_ms: token.end_ms,
+                    probability: token.probability,
+                });
+                tokens_in_word = 1;
+            }
+        }
+    }
+
+    words
 }
 
 /// Whisper-based speech-to-text engine
 pub struct WhisperEngine {
-    ctx: WhisperContext,
+    /// The loaded model, shared (see `WHISPER_MODELS`), for its tokenizer
+    ctx: Arc<WhisperContext>,
+    /// Decoder state with its mel and KV buffers, reused across calls
+    state: WhisperState,
+    /// Configured prompt and vocabulary glossary (`Config::stt_initial_prompt`)
+    initial_prompt: Option<String>,
+    /// Tokens of the prompt of the current call
+    prompt_tokens: Vec<WhisperToken>,
+    /// Configured language code, or "auto"
+    language: String,
+    /// Configured task (transcribe or translate to English)
+    task: SttTask,
+    /// whisper.cpp backend: "metal", "cuda" or "cpu"
+    provider: &'static str,
+    /// CPU threads per decode
+    threads: usize,
+    /// Beam search and the other decoding options
+    decode_params: SttDecodeParams,
+    /// Temperatures tried in order until a decode passes the quality
+    /// checks; only the first in deterministic mode
+    temperatures: Vec<f32>,
+    /// The current call is a short command (`SttOptions::quick`)
+    quick: bool,
 }
 
 impl WhisperEngine {
     /// Create a new Whisper engine with the given configuration
     pub fn new(config: &Config) -> Result<Self> {
         let model_path = config.whisper_model_path()?;
+        if model_path.exists() {
+            verify_file(&config.models_dir()?, &model_path, config.verify_models)?;
+        }
+        Self::from_path(&model_path, config)
+    }
 
+    /// Create a Whisper engine from the ggml model file at `model_path`
+    /// instead of the configured model
+    ///
+    /// The file isn't checked against recorded checksums; the rest of the
+    /// settings come from `config`.
+    pub fn from_path(model_path: &Path, config: &Config) -> Result<Self> {
         if !model_path.exists() {
-            anyhow::bail!(
-                "Whisper model not found at {:?}. Run 'voiceflow setup' to download models.",
-                model_path
-            );
+            return Err(PipelineError::SttModelNotFound {
+                path: model_path.display().to_string(),
+            }
+            .into());
         }
 
-        tracing::info!("Loading Whisper model from {:?}", model_path);
-
-        let ctx = WhisperContext::new_with_params(
-            model_path.to_str().unwrap(),
-            WhisperContextParameters::default(),
-        )
-        .context("Failed to load Whisper model")?;
+        let provider = whisper_backend(config.stt_execution_provider);
+        let key = ModelKey::new(model_path, provider);
+        let ctx = WHISPER_MODELS.get_or_load(key, || {
+            tracing::info!("Loading Whisper model from {:?}", model_path);
+            let mut params = WhisperContextParameters::default();
+            params.use_gpu(provider != "cpu");
+            WhisperContext::new_with_params(
+                model_path.to_str().context("Whisper model path is not valid UTF-8")?,
+                params,
+            )
+            .context("Failed to load Whisper model")
+        })?;
+        let state = ctx.create_state().context("Failed to create Whisper state")?;
+        tracing::info!("Whisper running on {}", provider);
 
-        Ok(Self { ctx })
+        Ok(Self {
+            ctx,
+            state,
+            initial_prompt: config.stt_initial_prompt(),
+            prompt_tokens: Vec::new(),
+            language: config.language.clone(),
+            task: config.stt_task,
+            provider,
+            threads: config.stt_thread_count(),
+            decode_params: config.stt_decode.clone(),
+            temperatures: config.stt_decode.temperatures(config.deterministic).to_vec(),
+            quick: false,
+        })
     }
 
     /// Transcribe audio samples to text
@@ -72,41 +257,147 @@ impl WhisperEngine {
         audio: &[f32],
         enable_timestamps: bool,
     ) -> Result<TranscriptionResult> {
+        self.transcribe_with_cancel(audio, enable_timestamps, &CancelToken::new())
+    }
+
+    /// Transcribe audio samples, aborting the decode if `cancel` is triggered
+    ///
+    /// whisper.cpp polls the abort callback between compute steps, so
+    /// cancellation takes effect mid-decode rather than after it.
+    pub fn transcribe_with_cancel(
+        &mut self,
+        audio: &[f32],
+        enable_timestamps: bool,
+        cancel: &CancelToken,
+    ) -> Result<TranscriptionResult> {
+        let language = self.language.clone();
+        self.decode(audio, enable_timestamps, &language, self.task, None, cancel)
+    }
+
+    /// Decode audio samples with the given language and task, overriding
+    /// the configured ones
+    ///
+    /// With "auto" the language is detected first; the result reports the
+    /// spoken language, even when the text is translated to English.
+    /// `context` is decoded as prior text ahead of the vocabulary glossary
+    /// (see `SttOptions::context`).
+    pub fn decode(
+        &mut self,
+        audio: &[f32],
+        enable_timestamps: bool,
+        language: &str,
+        task: SttTask,
+        context: Option<&str>,
+        cancel: &CancelToken,
+    ) -> Result<TranscriptionResult> {
+        self.prompt_tokens = self.tokenize_prompt(context)?;
+
         // Audio must already be 16kHz - caller is responsible for resampling
         let audio_16k = audio;
+        let n_threads = self.threads;
 
-        // Create whisper parameters
-        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        let state = &mut self.state;
+        // Language detection runs the encoder on its own
+        let t_encode = Instant::now();
+        let language = if language == AUTO_LANGUAGE {
+            let detected = detect_language(state, audio_16k, n_threads)?;
+            tracing::debug!("Detected language: {}", detected);
+            detected
+        } else {
+            language.to_string()
+        };
+        let encode_ms = t_encode.elapsed().as_millis() as u64;
+
+        // Decode again at the next temperature while the result looks
+        // wrong; whisper.cpp's own fallback is off so the temperature used
+        // is known
+        let t_decode = Instant::now();
+        let temperatures = if self.quick { self.temperatures[..1].to_vec() } else { self.temperatures.clone() };
+        let (mut result, temperature) = with_fallback(
+            &temperatures,
+            |temperature| self.full(audio_16k, enable_timestamps, &language, task, temperature, cancel),
+            |result| {
+                let words: Vec<&str> = result.text.split_whitespace().collect();
+                !words.is_empty() && needs_fallback(result.confidence.ln(), repetition_ratio(&words))
+            },
+        )?;
+        result.encode_ms = encode_ms;
+        result.decode_ms = t_decode.elapsed().as_millis() as u64;
+        result.temperature = temperature;
+        result.temperature_fallback = temperature != temperatures[0];
+        Ok(result)
+    }
+
+    /// One whisper.cpp decode at `temperature`
+    fn full(
+        &mut self,
+        audio_16k: &[f32],
+        enable_timestamps: bool,
+        language: &str,
+        task: SttTask,
+        temperature: f32,
+        cancel: &CancelToken,
+    ) -> Result<TranscriptionResult> {
+        let decode = &self.decode_params;
+        let strategy = if decode.beam_size > 1 && !self.quick {
+            SamplingStrategy::BeamSearch { beam_size: decode.beam_size as i32, patience: decode.patience }
+        } else {
+            SamplingStrategy::Greedy { best_of: 1 }
+        };
+        let mut params = FullParams::new(strategy);
 
         // Configure for speed
-        params.set_n_threads(std::thread::available_parallelism()?.get() as i32);
-        params.set_language(Some("en"));
-        params.set_translate(false);
+        params.set_n_threads(self.threads as i32);
+        params.set_language(Some(language));
+        params.set_translate(task == SttTask::Translate);
         params.set_no_context(true);
         params.set_single_segment(false);
         params.set_print_special(false);
         params.set_print_progress(false);
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
+        if self.quick {
+            params.set_single_segment(true);
+            params.set_max_tokens(QUICK_MAX_TOKENS as i32);
+        }
 
         // Enable token-level timestamps for word extraction
         if enable_timestamps {
             params.set_token_timestamps(true);
-            params.set_max_len(1); // Force word-level segmentation
         }
 
         // Suppress non-speech tokens
-        params.set_suppress_blank(true);
+        params.set_suppress_blank(decode.suppress_blank);
         params.set_suppress_nst(true);
 
-        // Create state and run inference
-        let mut state = self.ctx.create_state()?;
-        state.full(params, audio_16k)?;
+        params.set_temperature(temperature);
+        params.set_temperature_inc(0.0);
+
+        // Bias decoding towards the context and the user's vocabulary
+        if !self.prompt_tokens.is_empty() {
+            params.set_tokens(&self.prompt_tokens);
+        }
+
+        // Let whisper.cpp bail out of the decode when cancelled
+        let abort_token = cancel.clone();
+        params.set_abort_callback_safe(move || abort_token.is_cancelled());
+
+        // Run inference
+        let eot = self.ctx.token_eot();
+        let state = &mut self.state;
+        let full_result = state.full(params, audio_16k);
+        if cancel.is_cancelled() {
+            return Err(PipelineError::cancelled().into());
+        }
+        full_result?;
 
         // Collect all segments
         let num_segments = state.full_n_segments()?;
         let mut text = String::new();
-        let mut word_timestamps = Vec::new();
+        let mut tokens = Vec::new();
+        // A segment that looks like speech keeps the whole result from
+        // being treated as silence
+        let mut no_speech_probability: f32 = 1.0;
 
         for i in 0..num_segments {
             if let Ok(segment) = state.full_get_segment_text(i) {
@@ -114,44 +405,66 @@ impl WhisperEngine {
                 text.push(' ');
             }
 
-            // Extract word timestamps if enabled
-            if enable_timestamps {
-                if let Ok(num_tokens) = state.full_n_tokens(i) {
-                    for j in 0..num_tokens {
-                        // Get token text
-                        if let Ok(token_text) = state.full_get_token_text(i, j) {
-                            let token_str = token_text.trim();
-
-                            // Skip empty tokens and special tokens
-                            if token_str.is_empty() || token_str.starts_with('[') {
-                                continue;
-                            }
-
-                            // Get timing info
-                            if let Ok(token_data) = state.full_get_token_data(i, j) {
-                                // Convert from centiseconds to milliseconds
-                                let start_ms = (token_data.t0 as i64) * 10;
-                                let end_ms = (token_data.t1 as i64) * 10;
-
-                                word_timestamps.push(WordTimestamp {
-                                    word: token_str.to_string(),
-                                    start_ms,
-                                    end_ms,
-                                    probability: token_data.p,
-                                });
-                            }
+            if let Ok(num_tokens) = state.full_n_tokens(i) {
+                let mut first_word = true;
+                for j in 0..num_tokens {
+                    if let (Ok(token_text), Ok(token_data)) =
+                        (state.full_get_token_text(i, j), state.full_get_token_data(i, j))
+                    {
+                        if first_word && token_data.id < eot {
+                            first_word = false;
+                            no_speech_probability = no_speech_probability.min(no_speech_from_first_token(token_data.p));
                         }
+                        // Convert from centiseconds to milliseconds
+                        tokens.push(TokenTiming {
+                            text: token_text,
+                            start_ms: token_data.t0 * 10,
+                            end_ms: token_data.t1 * 10,
+                            probability: token_data.p,
+                            log_probability: token_data.plog,
+                        });
                     }
                 }
             }
         }
 
+        // Token times are only meaningful with token timestamps enabled
+        let word_timestamps = if enable_timestamps {
+            words_from_tokens(&tokens)
+        } else {
+            vec![]
+        };
+
         Ok(TranscriptionResult {
             text: text.trim().to_string(),
             word_timestamps,
+            confidence: confidence_from_tokens(&tokens),
+            no_speech_probability,
+            language: Some(language.to_string()),
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
         })
     }
 
+    /// Tokens of the decoder prompt: `context` followed by the configured
+    /// prompt, keeping the last `MAX_PROMPT_TOKENS`, the text nearest the
+    /// audio
+    fn tokenize_prompt(&self, context: Option<&str>) -> Result<Vec<WhisperToken>> {
+        let Some(prompt) = prompt_text(context, self.initial_prompt.as_deref()) else {
+            return Ok(Vec::new());
+        };
+        // A token covers at least one byte
+        let mut tokens = self.ctx.tokenize(&prompt, prompt.len() + 1)?;
+        if tokens.len() > MAX_PROMPT_TOKENS {
+            tracing::debug!("STT prompt is {} tokens, keeping the last {}", tokens.len(), MAX_PROMPT_TOKENS);
+            tokens.drain(..tokens.len() - MAX_PROMPT_TOKENS);
+        }
+        Ok(tokens)
+    }
+
     /// Get word timestamps as tuples for prosody analysis
     pub fn get_word_timestamp_tuples(result: &TranscriptionResult) -> Vec<(String, i64, i64)> {
         result
@@ -161,3 +474,126 @@ impl WhisperEngine {
             .collect()
     }
 }
+
+impl SpeechToText for WhisperEngine {
+    fn transcribe(&mut self, audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult> {
+        tracing::trace!("Whisper {:?} of {} samples ({})", opts.task, audio.len(), opts.language);
+        self.quick = opts.quick;
+        let result = self.decode(audio, opts.enable_timestamps, opts.language, opts.task, opts.context, opts.cancel);
+        self.quick = false;
+        result
+    }
+
+    fn supports_timestamps(&self) -> bool {
+        true
+    }
+
+    fn execution_provider(&self) -> Option<&'static str> {
+        Some(self.provider)
+    }
+}
+
+/// Prompt text for a call: its context, then the configured prompt
+fn prompt_text(context: Option<&str>, configured: Option<&str>) -> Option<String> {
+    let context = context.map(str::trim).filter(|context| !context.is_empty());
+    match (context, configured) {
+        (Some(context), Some(configured)) => Some(format!("{} {}", context, configured)),
+        (Some(text), None) | (None, Some(text)) => Some(text.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// The whisper.cpp backend `provider` selects in this build
+///
+/// whisper.cpp has no Core ML path without separately converted encoder
+/// models, so `CoreMl` and `Auto` use the GPU backend it was built with.
+/// whisper.cpp itself falls back to the CPU if the GPU can't be set up.
+fn whisper_backend(provider: SttExecutionProvider) -> &'static str {
+    let gpu = if cfg!(feature = "metal") {
+        "metal"
+    } else if cfg!(feature = "cuda") {
+        "cuda"
+    } else {
+        "cpu"
+    };
+    match provider {
+        SttExecutionProvider::Cpu => "cpu",
+        SttExecutionProvider::CoreMl | SttExecutionProvider::Auto => gpu,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whisper_backend() {
+        assert_eq!(whisper_backend(SttExecutionProvider::Cpu), "cpu");
+        assert_eq!(whisper_backend(SttExecutionProvider::Auto), whisper_backend(SttExecutionProvider::CoreMl));
+        if cfg!(feature = "metal") {
+            assert_eq!(whisper_backend(SttExecutionProvider::Auto), "metal");
+        }
+    }
+
+    #[test]
+    fn test_prompt_text_puts_context_first() {
+        let glossary = Some("Glossary: Kubernetes, Grafana.");
+        assert_eq!(
+            prompt_text(Some(" Standup about the cluster. "), glossary).as_deref(),
+            Some("Standup about the cluster. Glossary: Kubernetes, Grafana.")
+        );
+        assert_eq!(prompt_text(Some("  "), glossary).as_deref(), glossary);
+        assert_eq!(prompt_text(Some("kubectl"), None).as_deref(), Some("kubectl"));
+        assert_eq!(prompt_text(None, None), None);
+    }
+
+    fn token(text: &str, start_ms: i64, end_ms: i64, probability: f32) -> TokenTiming {
+        TokenTiming {
+            text: text.to_string(),
+            start_ms,
+            end_ms,
+            probability,
+            log_probability: probability.ln(),
+        }
+    }
+
+    #[test]
+    fn test_words_from_tokens_merges_word_pieces() {
+        let tokens = [
+            token("[_BEG_]", 0, 0, 1.0),
+            token(" Hello", 0, 400, 0.9),
+            token(" Volt", 500, 700, 0.8),
+            token("aire", 700, 900, 0.6),
+            token(".", 900, 950, 1.0),
+            token("<|endoftext|>", 950, 950, 1.0),
+        ];
+        let words = words_from_tokens(&tokens);
+
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].word, "Hello");
+        assert_eq!((words[0].start_ms, words[0].end_ms), (0, 400));
+        assert_eq!(words[1].word, "Voltaire.");
+        assert_eq!((words[1].start_ms, words[1].end_ms), (500, 950));
+        assert!((words[1].probability - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_words_from_tokens_first_token_without_space() {
+        let words = words_from_tokens(&[token("Hi", 0, 200, 0.5), token(" there", 200, 500, 0.5)]);
+        let text: Vec<&str> = words.iter().map(|w| w.word.as_str()).collect();
+        assert_eq!(text, ["Hi", "there"]);
+    }
+
+    #[test]
+    fn test_confidence_ignores_special_tokens() {
+        let tokens = [
+            token("[_BEG_]", 0, 0, 0.01),
+            token(" yes", 0, 200, 0.8),
+            token(" no", 200, 400, 0.2),
+            token("<|endoftext|>", 400, 400, 0.01),
+        ];
+        // Geometric mean of 0.8 and 0.2
+        assert!((confidence_from_tokens(&tokens) - 0.4).abs() < 1e-5);
+        assert_eq!(confidence_from_tokens(&[token("[_BEG_]", 0, 0, 1.0)]), 0.0);
+    }
+}
@@ -0,0 +1,287 @@
+#!/usr/bin/env python3
+"""Write the compressed-audio test fixtures in this directory.
+
+No encoder needed: each file is built by hand in the simplest valid form of
+its format, small enough to keep in the repo.
+
+- tone.flac: 0.25s of a 440 Hz tone, 22.05kHz stereo (left at half scale,
+  right at quarter scale), 16-bit VERBATIM subframes
+- silence.mp3: 1s of silent MPEG-1 Layer III frames, 32kHz mono, 32 kbps
+- silence.m4a: 1s of silent AAC-LC frames in an MP4 container, 16kHz mono
+- silence.ogg: 1s of silent Vorbis packets (every floor unused), 16kHz mono
+
+Run from anywhere: python3 generate.py
+"""
+
+import math
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+class BitWriter:
+    """Bits packed MSB first (FLAC, MPEG, AAC) or LSB first (Vorbis)"""
+
+    def __init__(self, lsb_first=False):
+        self.bits = []
+        self.lsb_first = lsb_first
+
+    def write(self, value, count):
+        bits = [(value >> i) & 1 for i in range(count)]
+        self.bits.extend(bits if self.lsb_first else reversed(bits))
+
+    def bytes(self):
+        bits = self.bits + [0] * (-len(self.bits) % 8)
+        out = bytearray()
+        for i in range(0, len(bits), 8):
+            byte = bits[i:i + 8]
+            if self.lsb_first:
+                byte = list(reversed(byte))
+            out.append(int("".join(map(str, byte)), 2))
+        return bytes(out)
+
+
+def crc(data, poly, width, init=0):
+    top = 1 << (width - 1)
+    mask = (1 << width) - 1
+    value = init
+    for byte in data:
+        value ^= byte << (width - 8)
+        for _ in range(8):
+            value = ((value << 1) ^ poly) if value & top else (value << 1)
+            value &= mask
+    return value
+
+
+# FLAC
+
+def flac():
+    rate, seconds, block = 22050, 0.25, 4096
+    frames = int(rate * seconds)
+    tone = [math.sin(2 * math.pi * 440 * i / rate) for i in range(frames)]
+    left = [round(s * 0.5 * 32767) for s in tone]
+    right = [round(s * 0.25 * 32767) for s in tone]
+
+    info = BitWriter()
+    info.write(block, 16)  # min block size
+    info.write(block, 16)  # max block size
+    info.write(0, 24)  # min frame size (unknown)
+    info.write(0, 24)  # max frame size (unknown)
+    info.write(rate, 20)
+    info.write(2 - 1, 3)  # channels
+    info.write(16 - 1, 5)  # bits per sample
+    info.write(frames, 36)
+    streaminfo = info.bytes() + bytes(16)  # no MD5
+    out = b"fLaC" + bytes([0x80]) + len(streaminfo).to_bytes(3, "big") + streaminfo
+
+    for number, start in enumerate(range(0, frames, block)):
+        size = min(block, frames - start)
+        header = BitWriter()
+        header.write(0b11111111111110, 14)  # sync
+        header.write(0, 1)
+        header.write(0, 1)  # fixed block size
+        header.write(12 if size == block else 7, 4)  # 4096, or 16-bit size - 1 below
+        header.write(6, 4)  # 22.05kHz
+        header.write(1, 4)  # left, right
+        header.write(4, 3)  # 16-bit
+        header.write(0, 1)
+        header.write(number, 8)  # frame number (UTF-8 coded, < 128)
+        if size != block:
+            header.write(size - 1, 16)
+        frame = header.bytes()
+        frame += bytes([crc(frame, 0x07, 8)])
+        for channel in (left, right):
+            frame += bytes([0b00000010])  # VERBATIM subframe
+            frame += struct.pack(">%dh" % size, *channel[start:start + size])
+        frame += crc(frame, 0x8005, 16).to_bytes(2, "big")
+        out += frame
+    return out
+
+
+# MP3
+
+def mp3():
+    # MPEG-1 Layer III, no CRC, 32 kbps, 32kHz, mono: 144-byte frames of
+    # 1152 samples, all-zero side info (no main data, so silence)
+    header = bytes([0xFF, 0xFB, 0x18, 0xC0])
+    frame = header + bytes(144 - len(header))
+    return frame * 28
+
+
+# M4A
+
+def box(kind, *payload):
+    body = b"".join(payload)
+    return struct.pack(">I", 8 + len(body)) + kind + body
+
+
+def full_box(kind, version_flags, *payload):
+    return box(kind, struct.pack(">I", version_flags), *payload)
+
+
+def descriptor(tag, payload):
+    return bytes([tag, len(payload)]) + payload
+
+
+def m4a():
+    rate, frames = 16000, 16
+
+    # One SCE with no scalefactor bands, then END: a silent AAC-LC frame
+    aac = BitWriter()
+    aac.write(0, 3)  # ID_SCE
+    aac.write(0, 4)  # element instance tag
+    aac.write(100, 8)  # global gain
+    aac.write(0, 1)  # ics_reserved_bit
+    aac.write(0, 2)  # ONLY_LONG_SEQUENCE
+    aac.write(0, 1)  # window shape
+    aac.write(0, 6)  # max_sfb
+    aac.write(0, 1)  # predictor data
+    aac.write(0, 1)  # pulse data
+    aac.write(0, 1)  # TNS data
+    aac.write(0, 1)  # gain control data
+    aac.write(7, 3)  # ID_END
+    packet = aac.bytes()
+
+    # AAC-LC, 16kHz, mono
+    config = bytes([0x14, 0x08])
+    decoder_config = descriptor(
+        0x04,
+        bytes([0x40, 0x15]) + (0).to_bytes(3, "big") + struct.pack(">II", 0, 0) + descriptor(0x05, config),
+    )
+    es = descriptor(0x03, struct.pack(">HB", 1, 0) + decoder_config + descriptor(0x06, b"\x02"))
+    mp4a = box(
+        b"mp4a",
+        bytes(6),
+        struct.pack(">H", 1),  # data reference index
+        bytes(8),
+        struct.pack(">HHHH", 1, 16, 0, 0),  # channels, sample size
+        struct.pack(">I", rate << 16),
+        full_box(b"esds", 0, es),
+    )
+
+    duration = frames * 1024
+    matrix = struct.pack(">9I", 0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000)
+
+    def moov(chunk_offset):
+        stbl = box(
+            b"stbl",
+            full_box(b"stsd", 0, struct.pack(">I", 1), mp4a),
+            full_box(b"stts", 0, struct.pack(">III", 1, frames, 1024)),
+            full_box(b"stsc", 0, struct.pack(">IIII", 1, 1, frames, 1)),
+            full_box(b"stsz", 0, struct.pack(">II", len(packet), frames)),
+            full_box(b"stco", 0, struct.pack(">II", 1, chunk_offset)),
+        )
+        minf = box(
+            b"minf",
+            full_box(b"smhd", 0, bytes(4)),
+            box(b"dinf", full_box(b"dref", 0, struct.pack(">I", 1), full_box(b"url ", 1))),
+            stbl,
+        )
+        mdia = box(
+            b"mdia",
+            full_box(b"mdhd", 0, struct.pack(">IIIIHH", 0, 0, rate, duration, 0x55C4, 0)),
+            full_box(b"hdlr", 0, bytes(4), b"soun", bytes(12), b"\0"),
+            minf,
+        )
+        tkhd = full_box(
+            b"tkhd", 3, struct.pack(">IIIII", 0, 0, 1, 0, duration), bytes(8),
+            struct.pack(">HHHH", 0, 0, 0x100, 0), matrix, struct.pack(">II", 0, 0),
+        )
+        mvhd = full_box(
+            b"mvhd", 0, struct.pack(">IIIIIH", 0, 0, rate, duration, 0x10000, 0x100), bytes(10),
+            matrix, bytes(24), struct.pack(">I", 2),
+        )
+        return box(b"moov", mvhd, box(b"trak", tkhd, mdia))
+
+    ftyp = box(b"ftyp", b"M4A ", struct.pack(">I", 0), b"M4A mp42isom")
+    offset = len(ftyp) + len(moov(0)) + 8
+    return ftyp + moov(offset) + box(b"mdat", packet * frames)
+
+
+# Ogg Vorbis
+
+def ogg_page(packets, granule, sequence, flags):
+    segments = []
+    for packet in packets:
+        segments += [255] * (len(packet) // 255) + [len(packet) % 255]
+    header = (
+        b"OggS" + bytes([0, flags]) + struct.pack("<qII", granule, 1, sequence) + bytes(4)
+        + bytes([len(segments)]) + bytes(segments)
+    )
+    page = bytearray(header + b"".join(packets))
+    page[22:26] = struct.pack("<I", crc(page, 0x04C11DB7, 32))
+    return bytes(page)
+
+
+def ogg():
+    rate = 16000
+    identification = (
+        b"\x01vorbis" + struct.pack("<IBIiii", 0, 1, rate, 0, 0, 0)
+        + bytes([0xB8, 1])  # blocksizes 256 and 2048, framing
+    )
+    vendor = b"voiceflow test fixture"
+    comment = b"\x03vorbis" + struct.pack("<I", len(vendor)) + vendor + struct.pack("<I", 0) + b"\x01"
+
+    setup = BitWriter(lsb_first=True)
+    setup.write(0, 8)  # one codebook
+    setup.write(0x564342, 24)
+    setup.write(1, 16)  # dimensions
+    setup.write(2, 24)  # entries
+    setup.write(0, 1)  # not ordered
+    setup.write(0, 1)  # not sparse
+    setup.write(0, 5)  # entry 0: length 1
+    setup.write(0, 5)  # entry 1: length 1
+    setup.write(0, 4)  # no lookup
+    setup.write(0, 6)  # one time-domain transform
+    setup.write(0, 16)
+    setup.write(0, 6)  # one floor
+    setup.write(1, 16)  # floor 1
+    setup.write(0, 5)  # no partitions
+    setup.write(1, 2)  # multiplier 2
+    setup.write(8, 4)  # range bits
+    setup.write(0, 6)  # one residue
+    setup.write(0, 16)  # residue 0
+    setup.write(0, 24)  # begin
+    setup.write(0, 24)  # end
+    setup.write(31, 24)  # partition size 32
+    setup.write(0, 6)  # one classification
+    setup.write(0, 8)  # classbook
+    setup.write(0, 3)  # no cascade
+    setup.write(0, 1)
+    setup.write(0, 6)  # one mapping
+    setup.write(0, 16)  # mapping 0
+    setup.write(0, 1)  # one submap
+    setup.write(0, 1)  # no coupling
+    setup.write(0, 2)
+    setup.write(0, 8)  # submap 0: time
+    setup.write(0, 8)  # floor
+    setup.write(0, 8)  # residue
+    setup.write(0, 6)  # one mode
+    setup.write(0, 1)  # short blocks
+    setup.write(0, 16)
+    setup.write(0, 16)
+    setup.write(0, 8)  # mapping
+    setup.write(1, 1)  # framing
+    setup = b"\x05vorbis" + setup.bytes()
+
+    # Audio packet type, then the channel's floor unused: silence. Each
+    # short block after the first adds 128 samples.
+    packets = [b"\x00"] * (rate // 128 + 1)
+    return (
+        ogg_page([identification], 0, 0, 0x02)
+        + ogg_page([comment, setup], 0, 1, 0)
+        + ogg_page(packets, rate, 2, 0x04)
+    )
+
+
+if __name__ == "__main__":
+    for name, data in [
+        ("tone.flac", flac()),
+        ("silence.mp3", mp3()),
+        ("silence.m4a", m4a()),
+        ("silence.ogg", ogg()),
+    ]:
+        with open(os.path.join(HERE, name), "wb") as f:
+            f.write(data)
+        print("%s: %d bytes" % (name, len(data)))
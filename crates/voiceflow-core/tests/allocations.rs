@@ -0,0 +1,105 @@
+//! Heap use of a warm request, counted by a global allocator that tracks
+//! bytes allocated on the current thread
+//!
+//! Covers the pipeline's own buffers: input conversion, resampling and
+//! voice activity detection. The STT engine is a stub, so feature
+//! extraction and decoding aren't measured; Whisper's mel and KV buffers
+//! live in whisper.cpp and Moonshine's features in ONNX Runtime, outside
+//! the Rust allocator either way.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use anyhow::Result;
+use voiceflow_core::audio::AudioInput;
+use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+use voiceflow_core::{CancelToken, FormattingMode, PipelineBuilder, SpeechToText};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count(bytes: usize) {
+    // Ignore allocations made while the thread is being torn down
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + bytes));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size.saturating_sub(layout.size()));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Bytes allocated on this thread while running `f`
+fn allocated_by(f: impl FnOnce()) -> usize {
+    let before = ALLOCATED.with(Cell::get);
+    f();
+    ALLOCATED.with(Cell::get) - before
+}
+
+/// Stands in for the STT engine, without allocating per sample
+struct FixedTranscript;
+
+impl SpeechToText for FixedTranscript {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        Ok(TranscriptionResult {
+            text: "hello world how are you".to_string(),
+            word_timestamps: Vec::new(),
+            confidence: 0.9,
+            no_speech_probability: 0.0,
+            language: Some("en".to_string()),
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature: 0.0,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
+        })
+    }
+}
+
+/// Interleaved 48kHz stereo tone, kept whole by voice activity detection
+fn stereo_48k(secs: usize) -> Vec<f32> {
+    (0..secs * 48000).flat_map(|i| [(i as f32 * 0.03).sin() * 0.5; 2]).collect()
+}
+
+#[test]
+fn test_warm_request_audio_buffers_independent_of_audio_length() {
+    let mut pipeline =
+        PipelineBuilder::new().stt(Box::new(FixedTranscript)).formatting(FormattingMode::None).build().unwrap();
+    let cancel = CancelToken::new();
+    let (ten, twenty) = (stereo_48k(10), stereo_48k(20));
+    let mut process = |audio: &[f32]| {
+        let result = pipeline.process_input(&AudioInput::new(audio, 48000, 2), None, &cancel).unwrap();
+        assert_eq!(result.raw_transcript, "hello world how are you");
+    };
+
+    // The first request sizes the buffers
+    process(&twenty);
+
+    let ten_secs = allocated_by(|| process(&ten));
+    let twenty_secs = allocated_by(|| process(&twenty));
+    // 10 seconds at 16kHz is 640 KB of samples; what's left is the result
+    // and per-request bookkeeping
+    assert!(ten_secs < 32 * 1024, "10s request allocated {} bytes", ten_secs);
+    assert!(twenty_secs.abs_diff(ten_secs) < 1024, "10s: {} bytes, 20s: {} bytes", ten_secs, twenty_secs);
+}
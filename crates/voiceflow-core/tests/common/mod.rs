@@ -0,0 +1,12 @@
+//! Helpers shared by the integration tests
+
+use std::path::Path;
+
+use voiceflow_core::audio::load_audio_file;
+
+/// The recording at the repo root, as 16kHz mono samples
+pub fn fixture() -> Vec<f32> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+    let buffer = load_audio_file(Path::new(path)).unwrap();
+    buffer.as_input().to_16khz_mono().unwrap().into_owned()
+}
@@ -0,0 +1,29 @@
+//! Deterministic mode gives the same text for the same recording, in one
+//! pipeline and across pipelines
+//!
+//! Needs downloaded models: `cargo test --test deterministic -- --ignored`
+
+mod common;
+
+use common::fixture;
+use voiceflow_core::{Config, Pipeline};
+
+#[test]
+#[ignore]
+fn test_same_fixture_gives_identical_text() {
+    let audio = fixture();
+    let config = Config { deterministic: true, ..Config::default() };
+
+    let mut pipeline = Pipeline::new(&config).unwrap();
+    let first = pipeline.process(&audio, None).unwrap();
+    let second = pipeline.process(&audio, None).unwrap();
+    assert!(!first.formatted_text.is_empty());
+    assert!(!second.format_cache_hit);
+    assert_eq!(first.raw_transcript, second.raw_transcript);
+    assert_eq!(first.formatted_text, second.formatted_text);
+
+    // A freshly loaded pipeline too
+    drop(pipeline);
+    let third = Pipeline::new(&config).unwrap().process(&audio, None).unwrap();
+    assert_eq!(first.formatted_text, third.formatted_text);
+}
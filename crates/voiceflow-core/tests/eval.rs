@@ -0,0 +1,105 @@
+//! The evaluation harness end to end, on the bundled fixture set:
+//! `cargo test --features eval --test eval`
+
+#![cfg(feature = "eval")]
+
+use std::path::Path;
+
+use anyhow::Result;
+use voiceflow_core::eval::{evaluate, load_manifest};
+use voiceflow_core::llm::FormatContext;
+use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+use voiceflow_core::{PipelineBuilder, SpeechToText, TextFormatter};
+
+const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/eval/manifest.jsonl");
+
+/// Transcribes each recording as the next of the texts, and the warm-up's
+/// silence as nothing
+struct TranscriptSequence(std::vec::IntoIter<&'static str>);
+
+impl SpeechToText for TranscriptSequence {
+    fn transcribe(&mut self, audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        let text = if audio.iter().all(|&sample| sample == 0.0) { "" } else { self.0.next().unwrap_or_default() };
+        Ok(TranscriptionResult {
+            text: text.to_string(),
+            word_timestamps: Vec::new(),
+            confidence: 0.9,
+            no_speech_probability: 0.0,
+            language: Some("en".to_string()),
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature: 0.0,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
+        })
+    }
+}
+
+/// Capitalizes the transcript and ends it with a period
+struct SentenceCase;
+
+impl TextFormatter for SentenceCase {
+    fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> Result<String> {
+        let mut chars = transcript.chars();
+        let first = chars.next().map(|c| c.to_uppercase().to_string()).unwrap_or_default();
+        Ok(format!("{}{}.", first, chars.as_str()))
+    }
+}
+
+#[test]
+fn test_evaluate_fixture_set() {
+    let dataset = load_manifest(Path::new(MANIFEST)).unwrap();
+    assert_eq!(dataset.len(), 3);
+    assert!(dataset.iter().all(|(audio, _)| audio.exists()), "{:?}", dataset);
+
+    let transcripts = vec!["turn on the lights", "set a timer for ten minute", "call mom now"];
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(TranscriptSequence(transcripts.into_iter())))
+        .llm_engine(Box::new(SentenceCase))
+        .build()
+        .unwrap();
+
+    let report = evaluate(&mut pipeline, &dataset).unwrap();
+
+    assert_eq!((report.evaluated, report.failed), (3, 0));
+    assert_eq!(report.items[0].formatted_text, "Turn on the lights.");
+    assert_eq!(report.items[0].wer, 0.0);
+    assert_eq!((report.items[1].word_errors, report.items[2].word_errors), (1, 1));
+    // 2 word errors over 12 reference words
+    assert!((report.wer - 2.0 / 12.0).abs() < 1e-6, "{}", report.wer);
+    assert!(report.cer > 0.0 && report.cer < report.wer);
+    assert_eq!(report.format_divergence, 0.0);
+    assert!(report.latency.p50_ms <= report.latency.max_ms);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["items"].as_array().unwrap().len(), 3);
+    assert!(json["latency"]["p90_ms"].is_u64());
+}
+
+#[test]
+fn test_failed_recording_is_reported() {
+    let dir = std::env::temp_dir().join(format!("voiceflow-eval-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let manifest = dir.join("manifest.jsonl");
+    let lights = Path::new(MANIFEST).with_file_name("lights.wav");
+    let lines = format!(
+        "{}\n\n{}\n",
+        serde_json::json!({"audio": lights, "reference": "turn on the lights"}),
+        serde_json::json!({"audio": "missing.wav", "reference": "call mom"}),
+    );
+    std::fs::write(&manifest, lines).unwrap();
+
+    let dataset = load_manifest(&manifest).unwrap();
+    assert_eq!(dataset[1].0, dir.join("missing.wav"));
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(TranscriptSequence(vec!["turn on the lights"].into_iter())))
+        .llm_engine(Box::new(SentenceCase))
+        .build()
+        .unwrap();
+    let report = evaluate(&mut pipeline, &dataset).unwrap();
+
+    assert_eq!((report.evaluated, report.failed), (1, 1));
+    assert!(report.items[1].error.is_some());
+    assert_eq!(report.wer, 0.0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,48 @@
+//! A one-second command takes the fast path, in well under the time the
+//! full pipeline takes on it
+//!
+//! Needs downloaded models: `cargo test --test fast_path -- --ignored --nocapture`
+
+use std::path::Path;
+
+use voiceflow_core::audio::{load_audio_file, speech_regions};
+use voiceflow_core::{Config, Pipeline, ProcessOptions, ProcessProfile};
+
+const RUNS: usize = 5;
+
+/// The first second of speech of the fixture
+fn command() -> Vec<f32> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav");
+    let buffer = load_audio_file(Path::new(path)).unwrap();
+    let audio = buffer.as_input().to_16khz_mono().unwrap();
+    let start = speech_regions(&audio, 0.01, 0)[0].start;
+    audio[start..(start + 16000).min(audio.len())].to_vec()
+}
+
+/// Mean end-to-end time of `RUNS` requests after a warm-up, in milliseconds
+fn bench(pipeline: &mut Pipeline, audio: &[f32], profile: ProcessProfile) -> u64 {
+    let options = ProcessOptions { profile, ..ProcessOptions::default() };
+    let total_ms: u64 = (0..RUNS)
+        .map(|_| {
+            let result = pipeline.process_with_options(audio, None, &options).unwrap();
+            assert_eq!(result.fast_path, profile == ProcessProfile::Command);
+            result.timings.total_ms
+        })
+        .sum();
+    total_ms / RUNS as u64
+}
+
+#[test]
+#[ignore]
+fn test_fast_path_cuts_command_latency() {
+    let audio = command();
+    // Formatted anew each time, so dictation pays for the LLM
+    let config = Config { format_cache_size: 0, ..Config::default() };
+    let mut pipeline = Pipeline::new(&config).unwrap();
+    pipeline.warm_up().unwrap();
+
+    let dictation_ms = bench(&mut pipeline, &audio, ProcessProfile::Dictation);
+    let command_ms = bench(&mut pipeline, &audio, ProcessProfile::Command);
+    println!("1s command: {}ms on the fast path, {}ms as dictation", command_ms, dictation_ms);
+    assert!(command_ms < dictation_ms, "{}ms on the fast path, {}ms as dictation", command_ms, dictation_ms);
+}
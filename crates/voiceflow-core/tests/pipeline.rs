@@ -0,0 +1,963 @@
+//! The pipeline end to end, with stub engines in place of the models:
+//! each test runs one feature through `Pipeline::process` and its options
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use voiceflow_core::config::{
+    AppOverride, AudioOptions, ConfigError, Diarization, HistoryOptions, LlmOptions, ReplacementRule, VocabularyEntry,
+    DETERMINISTIC_SEED,
+};
+use voiceflow_core::diarize::SpeakerEmbedder;
+use voiceflow_core::llm::{format_prompt, FormatContext};
+use voiceflow_core::progress::MIN_INTERVAL;
+use voiceflow_core::session::estimate_tokens;
+use voiceflow_core::transcribe::{Alternative, FilterReason, SttOptions, TranscriptionResult, WordTimestamp};
+use voiceflow_core::{
+    CancelToken, Config, FormattingMode, FormattingPreset, History, Pipeline, PipelineBuilder, PipelineError,
+    ProcessOptions, ProcessProfile, ProcessProgress, ProcessStage, ProgressReporter, PromptTruncation, RawTranscriptHook,
+    SessionState, SpeechToText, TextFormatter, TimeoutStage,
+};
+
+struct FixedTranscript(&'static str);
+
+impl SpeechToText for FixedTranscript {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        Ok(transcript(self.0))
+    }
+}
+
+/// `FixedTranscript` taking as long as the progress throttle
+struct SlowTranscript(&'static str);
+
+impl SpeechToText for SlowTranscript {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        std::thread::sleep(MIN_INTERVAL);
+        Ok(transcript(self.0))
+    }
+}
+
+/// Each call transcribes as the next of the texts
+struct TranscriptSequence(std::vec::IntoIter<&'static str>);
+
+impl SpeechToText for TranscriptSequence {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        Ok(transcript(self.0.next().unwrap_or_default()))
+    }
+}
+
+fn transcript(text: &str) -> TranscriptionResult {
+    TranscriptionResult {
+        text: text.to_string(),
+        word_timestamps: Vec::new(),
+        confidence: 0.9,
+        no_speech_probability: 0.0,
+        language: Some("en".to_string()),
+        encode_ms: 0,
+        decode_ms: 0,
+        temperature: 0.0,
+        temperature_fallback: false,
+        alternatives: Vec::new(),
+    }
+}
+
+/// Transcribes "undo", keeping the length of each call's audio and
+/// whether it was decoded as a short command
+struct QuickRecorder(Arc<Mutex<Vec<(usize, bool)>>>);
+
+impl SpeechToText for QuickRecorder {
+    fn transcribe(&mut self, audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult> {
+        self.0.lock().unwrap().push((audio.len(), opts.quick));
+        Ok(transcript("undo"))
+    }
+}
+
+struct FixedFormatting(&'static str);
+
+impl TextFormatter for FixedFormatting {
+    fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> Result<String> {
+        Ok(self.0.to_string())
+    }
+}
+
+/// `FixedFormatting` that counts its calls
+struct CountingFormatting(&'static str, Arc<AtomicUsize>);
+
+impl TextFormatter for CountingFormatting {
+    fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> Result<String> {
+        self.1.fetch_add(1, Ordering::Relaxed);
+        Ok(self.0.to_string())
+    }
+}
+
+/// Formatter returning the transcript unchanged and keeping the ones it
+/// was given
+struct EchoFormatting(Arc<Mutex<Vec<String>>>);
+
+impl TextFormatter for EchoFormatting {
+    fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> Result<String> {
+        self.0.lock().unwrap().push(transcript.to_string());
+        Ok(transcript.to_string())
+    }
+}
+
+/// Fails every request, as the llama backend does on a context overflow
+struct FailingFormatting;
+
+impl TextFormatter for FailingFormatting {
+    fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> Result<String> {
+        anyhow::bail!("context length exceeded")
+    }
+}
+
+/// Stuck generating: a token every 5ms for a second, stopping only when
+/// cancelled
+struct SlowFormatting;
+
+impl TextFormatter for SlowFormatting {
+    fn format(&mut self, transcript: &str, ctx: &FormatContext) -> Result<String> {
+        for _ in 0..200 {
+            if ctx.cancel.is_cancelled() {
+                return Err(PipelineError::Cancelled { timings: Box::default() }.into());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        Ok(format!("{}.", transcript))
+    }
+}
+
+/// Ends each transcript with a period, recording the sampling options
+/// it gets
+struct OptionsRecorder(Arc<Mutex<Vec<LlmOptions>>>);
+
+impl TextFormatter for OptionsRecorder {
+    fn format(&mut self, transcript: &str, ctx: &FormatContext) -> Result<String> {
+        self.0.lock().unwrap().push(ctx.llm_options.clone());
+        Ok(format!("{}.", transcript))
+    }
+}
+
+/// Two seconds of a loud tone, kept by voice activity detection
+fn speech_fixture() -> Vec<f32> {
+    (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect()
+}
+
+/// Mishears the jargon of a dictation unless the STT context names it,
+/// the way a prompt steers Whisper
+struct JargonListener;
+
+impl SpeechToText for JargonListener {
+    fn transcribe(&mut self, _audio: &[f32], opts: &SttOptions) -> Result<TranscriptionResult> {
+        let primed = opts.context.is_some_and(|context| context.contains("Kubernetes"));
+        Ok(transcript(if primed {
+            "scale the Kubernetes deployment in Grafana"
+        } else {
+            "scale the cooper netties deployment in griffon a"
+        }))
+    }
+}
+
+/// Hears "write to Anya", but might have heard two other things
+struct HedgingTranscript;
+
+impl SpeechToText for HedgingTranscript {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        let alternatives = vec![
+            Alternative { text: "right to Anya".to_string(), confidence: 0.5 },
+            Alternative { text: "write to Tanya".to_string(), confidence: 0.25 },
+        ];
+        Ok(TranscriptionResult { alternatives, ..transcript("write to Anya") })
+    }
+}
+
+/// Words one second each, with timestamps; "/" is a two-second pause
+struct TimedTranscript(&'static str);
+
+impl SpeechToText for TimedTranscript {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> Result<TranscriptionResult> {
+        let mut word_timestamps = Vec::new();
+        let mut start_ms = 0;
+        for word in self.0.split_whitespace() {
+            if word == "/" {
+                start_ms += 2000;
+                continue;
+            }
+            word_timestamps.push(WordTimestamp {
+                word: word.to_string(),
+                start_ms,
+                end_ms: start_ms + 1000,
+                probability: 0.9,
+            });
+            start_ms += 1000;
+        }
+        let text: Vec<&str> = self.0.split_whitespace().filter(|word| *word != "/").collect();
+        Ok(TranscriptionResult { word_timestamps, ..transcript(&text.join(" ")) })
+    }
+}
+
+/// Ends each transcript with a period, recording the prompts it gets
+struct PromptRecorder(Arc<Mutex<Vec<String>>>);
+
+impl TextFormatter for PromptRecorder {
+    fn format(&mut self, transcript: &str, ctx: &FormatContext) -> Result<String> {
+        self.0.lock().unwrap().push(ctx.prompt_template.to_string());
+        Ok(format!("{}.", transcript))
+    }
+}
+
+/// Tells voices apart by pitch: a window's embedding is its rate of
+/// zero crossings, high or low
+struct PitchEmbedder;
+
+impl SpeakerEmbedder for PitchEmbedder {
+    fn embed(&mut self, audio: &[f32]) -> Result<Vec<f32>> {
+        let crossings = audio.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count();
+        let high = crossings as f32 / audio.len() as f32 > 0.05;
+        Ok(if high { vec![0.0, 1.0] } else { vec![1.0, 0.0] })
+    }
+}
+
+/// Three seconds of a low tone, then three of a high one
+fn two_voices_fixture() -> Vec<f32> {
+    (0..96000)
+        .map(|i| {
+            let step = if i < 48000 { 0.1 } else { 0.5 };
+            (i as f32 * step).sin() * 0.5
+        })
+        .collect()
+}
+
+#[test]
+fn test_identical_transcripts_reuse_formatting() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(FixedTranscript("send it")),
+        Box::new(CountingFormatting("Send it.", Arc::clone(&calls))),
+    )
+    .unwrap();
+    let first = pipeline.process(&speech_fixture(), None).unwrap();
+    assert!(!first.format_cache_hit);
+    let second = pipeline.process(&speech_fixture(), None).unwrap();
+    assert!(second.format_cache_hit);
+    assert_eq!(second.formatted_text, "Send it.");
+    assert_eq!(second.timings.llm_formatting_ms, 0);
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    // Another prompt misses
+    assert!(!pipeline.process(&speech_fixture(), Some("email")).unwrap().format_cache_hit);
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+    // Earlier dictations of a session change the right output
+    let mut session = SessionState::new();
+    session.push("Dictated before.");
+    let options = ProcessOptions::default();
+    for _ in 0..2 {
+        let result = pipeline.process_in_session(&mut session, &speech_fixture(), None, &options).unwrap();
+        assert!(!result.format_cache_hit);
+    }
+    assert_eq!(calls.load(Ordering::Relaxed), 4);
+
+    pipeline.clear_format_cache();
+    assert!(!pipeline.process(&speech_fixture(), None).unwrap().format_cache_hit);
+
+    let mut config = pipeline.config().clone();
+    config.format_cache_size = 0;
+    pipeline.update_config(&config).unwrap();
+    assert!(!pipeline.process(&speech_fixture(), None).unwrap().format_cache_hit);
+    assert_eq!(calls.load(Ordering::Relaxed), 6);
+}
+
+#[test]
+fn test_clipped_audio_is_flagged() {
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("hello world")))
+        .formatting(FormattingMode::None)
+        .build()
+        .unwrap();
+    // The fixture's tone driven 4x past full scale
+    let clipped: Vec<f32> = speech_fixture().iter().map(|s| (s * 8.0).clamp(-1.0, 1.0)).collect();
+    let result = pipeline.process(&clipped, None).unwrap();
+    assert!(result.clipping);
+    assert!(result.clipped_percent > 50.0, "{}", result.clipped_percent);
+    assert!(!pipeline.process(&speech_fixture(), None).unwrap().clipping);
+    assert!(pipeline.transcribe_only(&clipped).unwrap().clipping);
+
+    // Skipped for apps that preprocess themselves
+    let mut config = pipeline.config().clone();
+    config.audio.preprocess = false;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&clipped, None).unwrap();
+    assert!(!result.clipping);
+    assert_eq!(result.clipped_percent, 0.0);
+}
+
+#[test]
+fn test_invalid_audio_rejected_or_repaired() {
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("hello world")))
+        .formatting(FormattingMode::None)
+        .build()
+        .unwrap();
+    let error = |pipeline: &mut Pipeline, audio: &[f32]| pipeline.process(audio, None).unwrap_err();
+    assert!(matches!(error(&mut pipeline, &[]), PipelineError::EmptyAudio));
+    assert!(matches!(error(&mut pipeline, &[0.1; 800]), PipelineError::AudioTooShort { duration_ms: 50 }));
+
+    let mut broken = speech_fixture();
+    broken[100..110].fill(f32::NAN);
+    broken[200] = f32::INFINITY;
+    let result = pipeline.process(&broken, None).unwrap();
+    assert_eq!(result.raw_transcript, "hello world");
+    assert_eq!(result.repaired_samples, 11);
+    assert_eq!(pipeline.transcribe_only(&broken).unwrap().repaired_samples, 11);
+    assert_eq!(pipeline.process(&speech_fixture(), None).unwrap().repaired_samples, 0);
+
+    let mut config = pipeline.config().clone();
+    config.audio.strict = true;
+    config.audio.max_duration_secs = 1;
+    pipeline.update_config(&config).unwrap();
+    assert!(matches!(error(&mut pipeline, &broken), PipelineError::InvalidSamples { count: 11 }));
+    let message = pipeline.process(&speech_fixture(), None).unwrap_err().to_string();
+    assert!(message.contains("maximum: 1s"), "{}", message);
+
+    // Preprocessing off still repairs
+    config.audio = AudioOptions { preprocess: false, ..AudioOptions::default() };
+    pipeline.update_config(&config).unwrap();
+    assert_eq!(pipeline.process(&broken, None).unwrap().repaired_samples, 11);
+}
+
+#[test]
+fn test_hallucinations_are_filtered() {
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("Send it. Thank you. Thank you. Thank you.")))
+        .formatting(FormattingMode::None)
+        .build()
+        .unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.raw_transcript, "Send it. Thank you.");
+    assert_eq!(result.filtered_segments.len(), 1);
+    assert_eq!(result.filtered_segments[0].reason, FilterReason::Repetition);
+    assert_eq!(pipeline.transcribe_only(&speech_fixture()).unwrap().raw_transcript, "Send it. Thank you.");
+
+    // The fake engine reports no chance of silence
+    let mut config = pipeline.config().clone();
+    config.hallucination_filter.min_no_speech_probability = 0.0;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.raw_transcript, "Send it.");
+
+    config.hallucination_filter.enabled = false;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.raw_transcript, "Send it. Thank you. Thank you. Thank you.");
+    assert!(result.filtered_segments.is_empty());
+}
+
+#[test]
+fn test_numbers_are_formatted() {
+    let spoken = "Meet at three thirty pm on january fifth, budget is twelve hundred dollars";
+    let written = "Meet at 3:30 PM on January 5th, budget is $1,200";
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript(spoken)))
+        .formatting(FormattingMode::None)
+        .build()
+        .unwrap();
+    assert_eq!(pipeline.process(&speech_fixture(), None).unwrap().raw_transcript, written);
+    assert_eq!(pipeline.transcribe_only(&speech_fixture()).unwrap().raw_transcript, written);
+
+    let mut config = pipeline.config().clone();
+    config.number_formatting.enabled = false;
+    pipeline.update_config(&config).unwrap();
+    assert_eq!(pipeline.process(&speech_fixture(), None).unwrap().raw_transcript, spoken);
+}
+
+#[test]
+fn test_voice_commands_run_before_formatting() {
+    let spoken = "Dear Sam comma new paragraph the report is ready period";
+    let written = "Dear Sam,\n\nThe report is ready.";
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(TranscriptSequence(vec![spoken, spoken, "type literally comma here"].into_iter())),
+        Box::new(EchoFormatting(seen.clone())),
+    )
+    .unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.raw_transcript, written);
+    assert_eq!(result.formatted_text, written);
+    assert_eq!(*seen.lock().unwrap(), [written]);
+
+    // Turned off for one call, the LLM gets the words
+    let options = ProcessOptions { voice_commands: Some(false), ..Default::default() };
+    let result = pipeline.process_with_options(&speech_fixture(), None, &options).unwrap();
+    assert_eq!(result.raw_transcript, spoken);
+    assert_eq!(seen.lock().unwrap().last().unwrap(), spoken);
+
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.raw_transcript, "type comma here");
+    assert_eq!(seen.lock().unwrap().last().unwrap(), "type comma here");
+}
+
+#[test]
+fn test_scratch_that_removes_the_previous_dictation() {
+    let dictations = vec!["Send the draft period", "Scratch that. Send the final version period", "scratch that"];
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(TranscriptSequence(dictations.into_iter())),
+        Box::new(EchoFormatting(seen.clone())),
+    )
+    .unwrap();
+    let mut session = SessionState::new();
+    let options = ProcessOptions::default();
+
+    let result = pipeline.process_in_session(&mut session, &speech_fixture(), None, &options).unwrap();
+    assert!(!result.scratch_previous);
+    assert_eq!(session.context(100).as_deref(), Some("Send the draft."));
+
+    let result = pipeline.process_in_session(&mut session, &speech_fixture(), None, &options).unwrap();
+    assert!(result.scratch_previous);
+    assert_eq!(result.formatted_text, "Send the final version.");
+    assert_eq!(session.context(100).as_deref(), Some("Send the final version."));
+
+    // Nothing left to format
+    let result = pipeline.process_in_session(&mut session, &speech_fixture(), None, &options).unwrap();
+    assert!(result.scratch_previous && !result.no_speech);
+    assert_eq!(result.formatted_text, "");
+    assert!(session.is_empty());
+    assert_eq!(seen.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_stt_context_reaches_the_engine() {
+    let mut pipeline =
+        PipelineBuilder::new().stt(Box::new(JargonListener)).formatting(FormattingMode::None).build().unwrap();
+
+    let result = pipeline.process(&speech_fixture(), Some("code")).unwrap();
+    assert_eq!(result.raw_transcript, "scale the cooper netties deployment in griffon a");
+
+    let options = ProcessOptions {
+        stt_context: Some("Ops standup: Kubernetes, Grafana".to_string()),
+        ..ProcessOptions::default()
+    };
+    let result = pipeline.process_with_options(&speech_fixture(), None, &options).unwrap();
+    assert_eq!(result.raw_transcript, "scale the Kubernetes deployment in Grafana");
+}
+
+#[test]
+fn test_app_override_is_merged_over_the_config() {
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline =
+        Pipeline::with_engines(Box::new(JargonListener), Box::new(PromptRecorder(prompts.clone()))).unwrap();
+    let mut config = pipeline.config().clone();
+    config.personal_dictionary = vec!["VoiceFlow".to_string()];
+    let xcode = AppOverride {
+        formatting_mode: Some(FormattingMode::PunctuationOnly),
+        vocabulary_extra: vec![VocabularyEntry::new("Kubernetes")],
+        replacements_extra: vec![ReplacementRule {
+            pattern: "deployment".to_string(),
+            replacement: "Deployment".to_string(),
+            ..ReplacementRule::default()
+        }],
+        ..AppOverride::default()
+    };
+    config.set_app_override("com.apple.dt.Xcode", xcode).unwrap();
+    let mail = AppOverride { preset: Some(FormattingPreset::Email), ..AppOverride::default() };
+    config.set_app_override("com.apple.mail", mail).unwrap();
+    pipeline.update_config(&config).unwrap();
+    let app = |app_id: &str| ProcessOptions { app_id: Some(app_id.to_string()), ..ProcessOptions::default() };
+
+    // The app's terms reach the STT engine and the prompt, and its
+    // rules run after the configured ones
+    let result = pipeline.process_with_options(&speech_fixture(), None, &app("com.apple.dt.Xcode")).unwrap();
+    assert_eq!(result.raw_transcript, "scale the Kubernetes deployment in Grafana");
+    assert_eq!(result.formatted_text, "scale the Kubernetes Deployment in Grafana.");
+    let prompt = prompts.lock().unwrap().pop().unwrap();
+    assert!(prompt.starts_with("Add punctuation and capitalization"), "{}", prompt);
+    assert!(prompt.contains("\nPersonal vocabulary: VoiceFlow, Kubernetes\n"), "{}", prompt);
+
+    // The app's preset replaces the default one, but not the call's
+    pipeline.process_with_options(&speech_fixture(), None, &app("com.apple.mail")).unwrap();
+    assert!(prompts.lock().unwrap().pop().unwrap().starts_with("Format this dictated text as an email"));
+    let notes = ProcessOptions { preset: Some(FormattingPreset::Notes), ..app("com.apple.mail") };
+    pipeline.process_with_options(&speech_fixture(), None, &notes).unwrap();
+    assert!(prompts.lock().unwrap().pop().unwrap().starts_with("Format this dictated text as notes"));
+
+    // Other apps get the config as it is
+    let result = pipeline.process_with_options(&speech_fixture(), None, &app("com.apple.Notes")).unwrap();
+    assert_eq!(result.formatted_text, "scale the cooper netties deployment in griffon a.");
+    assert!(!prompts.lock().unwrap().pop().unwrap().contains("Kubernetes"));
+}
+
+#[test]
+fn test_alternatives_reach_the_result_and_optionally_the_prompt() {
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline =
+        Pipeline::with_engines(Box::new(HedgingTranscript), Box::new(PromptRecorder(prompts.clone()))).unwrap();
+
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    let texts: Vec<&str> = result.alternatives.iter().map(|alternative| alternative.text.as_str()).collect();
+    assert_eq!(texts, ["right to Anya", "write to Tanya"]);
+    assert!(!prompts.lock().unwrap().pop().unwrap().contains("alternatives"));
+
+    let mut config = pipeline.config().clone();
+    config.stt_decode.alternatives_in_prompt = true;
+    pipeline.update_config(&config).unwrap();
+    pipeline.process(&speech_fixture(), None).unwrap();
+    let prompt = prompts.lock().unwrap().pop().unwrap();
+    assert!(prompt.contains(r#""right to Anya" (0.50), "write to Tanya" (0.25)]"#), "{}", prompt);
+
+    let result = pipeline.transcribe_only(&speech_fixture()).unwrap();
+    assert_eq!(result.alternatives.len(), 2);
+}
+
+#[test]
+fn test_diarization_labels_speaker_turns() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let diarization = Diarization { enabled: true, ..Diarization::default() };
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(TimedTranscript("is it ready yes it is")))
+        .llm_engine(Box::new(EchoFormatting(seen.clone())))
+        .diarization(diarization)
+        .speaker_embedder(Box::new(PitchEmbedder))
+        .build()
+        .unwrap();
+
+    let result = pipeline.process(&two_voices_fixture(), None).unwrap();
+    assert_eq!(result.raw_transcript, "is it ready yes it is");
+    assert_eq!(result.formatted_text, "Speaker 1: is it ready\n\nSpeaker 2: yes it is");
+    assert_eq!(*seen.lock().unwrap(), ["is it ready", "yes it is"]);
+    assert_eq!(result.segments.len(), 2);
+    assert_eq!((result.segments[1].start_ms, result.segments[1].speaker), (3000, Some(1)));
+
+    // One voice throughout: a single unlabeled turn
+    let one_voice: Vec<f32> = (0..96000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+    let result = pipeline.process(&one_voice, None).unwrap();
+    assert_eq!(result.formatted_text, "is it ready yes it is");
+    assert_eq!(result.segments.len(), 1);
+
+    // Labels can be left out of the text
+    let mut config = pipeline.config().clone();
+    config.diarization.label_speakers = false;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&two_voices_fixture(), None).unwrap();
+    assert_eq!(result.formatted_text, "is it ready yes it is");
+    assert_eq!(result.segments.len(), 2);
+}
+
+#[test]
+fn test_history_keeps_each_request() {
+    let dir = std::env::temp_dir().join(format!("voiceflow-pipeline-history-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("send the report")))
+        .llm_engine(Box::new(FixedFormatting("Send the report.")))
+        .history(HistoryOptions { enabled: true, dir: Some(dir.clone()), ..HistoryOptions::default() })
+        .build()
+        .unwrap();
+    pipeline.process(&speech_fixture(), Some("email")).unwrap();
+    // Self-tests aren't kept
+    assert!(pipeline.self_test().passed);
+
+    let history = History::new(&dir);
+    let summaries = history.list(0).unwrap();
+    assert_eq!(summaries.len(), 1);
+    let entry = history.get(&summaries[0].id).unwrap().unwrap();
+    assert_eq!(entry.record.raw_transcript, "send the report");
+    assert_eq!(entry.record.formatted_text, "Send the report.");
+    assert_eq!(entry.record.context.as_deref(), Some("email"));
+    assert_eq!(entry.record.duration_ms, 2000);
+    assert!(entry.audio_path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_repeated_corrections_are_learned() {
+    let dir = std::env::temp_dir().join(format!("voiceflow-pipeline-learning-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config_path = dir.join("config.toml");
+    let config_path = config_path.to_str();
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("scale the cooper netties deployment")))
+        .formatting(FormattingMode::None)
+        .build()
+        .unwrap();
+    let (original, corrected) = ("scale the cooper netties deployment", "scale the Kubernetes deployment");
+    let err = pipeline.report_correction(original, corrected).unwrap_err();
+    assert!(matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::LearningDisabled)));
+
+    let mut config = pipeline.config().clone();
+    config.learning.enabled = true;
+    config.learning.file = Some(dir.join("corrections.json"));
+    pipeline.update_config(&config).unwrap();
+    for count in 1..=3 {
+        let correction = pipeline.report_correction(original, corrected).unwrap().unwrap();
+        assert_eq!((correction.original.as_str(), correction.count), ("cooper netties", count));
+        if count < 3 {
+            assert!(pipeline.learn_corrections(config_path).unwrap().is_empty());
+        }
+    }
+    assert_eq!(pipeline.learn_corrections(config_path).unwrap().len(), 1);
+
+    // The rule applies at once and is saved; it's learned only once
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.formatted_text, "scale the Kubernetes deployment");
+    let saved = Config::load_file(config_path).unwrap();
+    assert_eq!(saved.vocabulary, [VocabularyEntry { term: "Kubernetes".to_string(), sounds_like: vec!["cooper netties".to_string()] }]);
+    assert_eq!(saved.replacements.len(), 1);
+    assert!(pipeline.learn_corrections(config_path).unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_progress_runs_through_the_stages() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(SlowTranscript("send the report")))
+        .llm_engine(Box::new(FixedFormatting("Send the report.")))
+        .build()
+        .unwrap();
+    let options = ProcessOptions {
+        progress: Some(ProgressReporter::new(move |progress: ProcessProgress| recorded.lock().unwrap().push(progress))),
+        ..Default::default()
+    };
+    pipeline.process_with_options(&speech_fixture(), None, &options).unwrap();
+
+    // Formatting started less than `MIN_INTERVAL` after the chunk was
+    // transcribed, so only its end is reported
+    let events = events.lock().unwrap();
+    let stages: Vec<ProcessStage> = events.iter().map(|progress| progress.stage).collect();
+    assert_eq!(stages, [ProcessStage::DetectingSpeech, ProcessStage::Transcribing, ProcessStage::Done]);
+    assert_eq!((events[1].done, events[1].total), (1, 1));
+    assert!(events.windows(2).all(|pair| pair[0].fraction < pair[1].fraction), "{:?}", events);
+    assert_eq!(events[2].fraction, 1.0);
+}
+
+#[test]
+fn test_progress_sink_can_cancel() {
+    let cancel = CancelToken::new();
+    let token = cancel.clone();
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(SlowTranscript("send the report")))
+        .llm_engine(Box::new(FixedFormatting("Send the report.")))
+        .build()
+        .unwrap();
+    let options = ProcessOptions {
+        cancel,
+        progress: Some(ProgressReporter::new(move |progress: ProcessProgress| {
+            if progress.stage == ProcessStage::Transcribing {
+                token.cancel();
+            }
+        })),
+        ..Default::default()
+    };
+    let err = pipeline.process_with_options(&speech_fixture(), None, &options).unwrap_err();
+    assert!(matches!(err.without_context(), PipelineError::Cancelled { .. }), "{:#}", err);
+}
+
+#[test]
+fn test_raw_transcript_comes_before_formatting() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let (raw, formatted) = (seen.clone(), seen.clone());
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("send the report to Sam")))
+        .llm_engine(Box::new(EchoFormatting(formatted)))
+        .build()
+        .unwrap();
+    let options = ProcessOptions {
+        on_raw: Some(RawTranscriptHook::new(move |text: &str| raw.lock().unwrap().push(format!("raw: {}", text)))),
+        ..Default::default()
+    };
+    let result = pipeline.process_with_options(&speech_fixture(), None, &options).unwrap();
+    assert_eq!(*seen.lock().unwrap(), ["raw: send the report to Sam", "send the report to Sam"]);
+    assert_eq!(result.raw_transcript, "send the report to Sam");
+
+    // Cancelled between the passes, the LLM never runs
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("send the report")))
+        .llm_engine(Box::new(CountingFormatting("Send the report.", calls.clone())))
+        .build()
+        .unwrap();
+    let cancel = CancelToken::new();
+    let token = cancel.clone();
+    let options = ProcessOptions {
+        cancel,
+        on_raw: Some(RawTranscriptHook::new(move |_: &str| token.cancel())),
+        ..Default::default()
+    };
+    let err = pipeline.process_with_options(&speech_fixture(), None, &options).unwrap_err();
+    assert!(matches!(err.without_context(), PipelineError::Cancelled { .. }), "{:#}", err);
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_segments_are_formatted_with_the_ones_before() {
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(TimedTranscript("send the report / then call the team")))
+        .llm_engine(Box::new(PromptRecorder(prompts.clone())))
+        .build()
+        .unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+
+    assert_eq!(result.raw_transcript, "send the report then call the team");
+    assert_eq!(result.formatted_text, "send the report. then call the team.");
+    let texts: Vec<(&str, &str)> =
+        result.segments.iter().map(|segment| (segment.raw_text.as_str(), segment.formatted_text.as_str())).collect();
+    assert_eq!(texts, [("send the report", "send the report."), ("then call the team", "then call the team.")]);
+    assert_eq!((result.segments[1].start_ms, result.segments[1].end_ms), (5000, 9000));
+    assert!(result.segments.iter().all(|segment| segment.speaker.is_none() && (segment.confidence - 0.9).abs() < 1e-6));
+
+    // The second segment sees the first as context
+    let prompts = prompts.lock().unwrap();
+    assert!(!prompts[0].contains("send the report."));
+    assert!(prompts[1].contains("send the report."));
+}
+
+#[test]
+fn test_structured_context_reaches_the_prompt_and_joins_the_text() {
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(FixedTranscript("Talked about the launch")),
+        Box::new(PromptRecorder(prompts.clone())),
+    )
+    .unwrap();
+    let context = r#"{"app_name": "Mail", "recipient": "Sam", "text_before_cursor": "Hi Sam, we met on Tuesday and"}"#;
+    let result = pipeline.process(&speech_fixture(), Some(context)).unwrap();
+    assert_eq!(result.formatted_text, " talked about the launch.");
+    assert_eq!(result.segments[0].formatted_text, "talked about the launch.");
+    let prompt = prompts.lock().unwrap().pop().unwrap();
+    assert!(prompt.contains("typed into Mail. It is addressed to Sam."), "{}", prompt);
+    assert!(prompt.contains("\"Hi Sam, we met on Tuesday and\""), "{}", prompt);
+
+    // A new sentence, ending mid-way through one
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(FixedTranscript("then we celebrated")),
+        Box::new(FixedFormatting("then we celebrated.")),
+    )
+    .unwrap();
+    let context = r#"{"text_before_cursor": "It shipped. ", "text_after_cursor": "all week"}"#;
+    let result = pipeline.process(&speech_fixture(), Some(context)).unwrap();
+    assert_eq!(result.formatted_text, "Then we celebrated ");
+
+    // A template can place the fields itself
+    let options = ProcessOptions {
+        preset: Some(FormattingPreset::Custom("Reply to {recipient}: {transcript}".to_string())),
+        ..ProcessOptions::default()
+    };
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(FixedTranscript("sounds good")),
+        Box::new(PromptRecorder(prompts.clone())),
+    )
+    .unwrap();
+    pipeline.process_with_options(&speech_fixture(), Some(r#"{"recipient": "Sam"}"#), &options).unwrap();
+    let prompt = prompts.lock().unwrap().pop().unwrap();
+    assert!(prompt.contains("Reply to Sam: ") && !prompt.contains("[Context:"), "{}", prompt);
+
+    let err = pipeline.process(&speech_fixture(), Some("{\"recipient\": ")).unwrap_err();
+    assert!(matches!(err.without_context(), PipelineError::InvalidContext { .. }), "{:#}", err);
+}
+
+#[test]
+fn test_segments_without_timestamps() {
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("hello world how are you")))
+        .formatting(FormattingMode::None)
+        .build()
+        .unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.segments.len(), 1);
+    let segment = &result.segments[0];
+    assert_eq!((segment.start_ms, segment.end_ms), (0, 2000));
+    assert_eq!(segment.raw_text, "hello world how are you");
+    assert_eq!(segment.formatted_text, result.formatted_text);
+    assert_eq!(segment.speaker, None);
+}
+
+#[test]
+fn test_deterministic_mode_decodes_greedily_every_time() {
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("hello world how are you")))
+        .llm_engine(Box::new(OptionsRecorder(Arc::clone(&recorded))))
+        .llm_options(LlmOptions { temperature: 0.7, ..LlmOptions::default() })
+        .deterministic(true)
+        .build()
+        .unwrap();
+
+    let first = pipeline.process(&speech_fixture(), None).unwrap();
+    let options = ProcessOptions { preset: Some(FormattingPreset::Email), ..ProcessOptions::default() };
+    pipeline.process_with_options(&speech_fixture(), None, &options).unwrap();
+    let second = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(first.formatted_text, second.formatted_text);
+    // Not from the format cache
+    assert!(!second.format_cache_hit);
+
+    let recorded = recorded.lock().unwrap();
+    assert_eq!(recorded.len(), 3);
+    for options in recorded.iter() {
+        assert_eq!((options.temperature, options.seed), (0.0, Some(DETERMINISTIC_SEED)));
+    }
+    assert_eq!(pipeline.config().stt_thread_count(), 1);
+    assert_eq!(pipeline.config().llm_thread_count(), Some(1));
+
+    let mut config = pipeline.config().clone();
+    config.idle_unload_seconds = Some(0);
+    pipeline.update_config(&config).unwrap();
+    assert_eq!(pipeline.idle_unload_in(), None);
+}
+
+#[test]
+fn test_llm_failure_returns_the_raw_transcript() {
+    let mut pipeline =
+        Pipeline::with_engines(Box::new(FixedTranscript("hello world how are you")), Box::new(FailingFormatting))
+            .unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.formatted_text, "hello world how are you");
+    assert!(result.was_fallback);
+    assert!(result.formatting_error.as_deref().unwrap().contains("context length exceeded"));
+
+    // Rejected output isn't an error
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(FixedTranscript("hello world how are you")),
+        Box::new(FixedFormatting("Something else entirely.")),
+    )
+    .unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert!(result.was_fallback);
+    assert_eq!(result.formatting_error, None);
+
+    let mut strict = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("hello world how are you")))
+        .llm_engine(Box::new(FailingFormatting))
+        .llm_strict(true)
+        .build()
+        .unwrap();
+    let err = strict.process(&speech_fixture(), None).unwrap_err();
+    assert!(matches!(err.without_context(), PipelineError::LlmFormattingFailed { .. }), "{:#}", err);
+    assert!(!strict.can_fallback());
+}
+
+#[test]
+fn test_short_commands_take_the_fast_path() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let formatted = Arc::new(AtomicUsize::new(0));
+    let mut pipeline = Pipeline::with_engines(
+        Box::new(QuickRecorder(Arc::clone(&calls))),
+        Box::new(CountingFormatting("Undo.", Arc::clone(&formatted))),
+    )
+    .unwrap();
+    // Formatted anew each time, so every dictation reaches the LLM
+    let config = Config { format_cache_size: 0, ..pipeline.config().clone() };
+    pipeline.update_config(&config).unwrap();
+    // Half a second of speech between quarter seconds of silence
+    let mut command = vec![0.0; 4000];
+    command.extend_from_slice(&speech_fixture()[..8000]);
+    command.extend(vec![0.0; 4000]);
+    let mut process = |audio: &[f32], profile| {
+        let options = ProcessOptions { profile, ..ProcessOptions::default() };
+        let result = pipeline.process_with_options(audio, None, &options).unwrap();
+        (result, calls.lock().unwrap().pop().unwrap())
+    };
+
+    // Transcribed whole, quickly, and left to the deterministic rules
+    let (result, call) = process(&command, ProcessProfile::Auto);
+    assert_eq!(call, (16000, true));
+    assert!(result.fast_path);
+    assert_eq!(result.formatted_text, "undo");
+    assert_eq!(formatted.load(Ordering::Relaxed), 0);
+
+    // Dictation, however short, and anything as long as the threshold
+    let (result, call) = process(&command, ProcessProfile::Dictation);
+    assert!(!result.fast_path && !call.1);
+    assert!(call.0 < 16000, "silence is trimmed");
+    assert_eq!(result.formatted_text, "Undo.");
+    let (result, call) = process(&speech_fixture()[..24000], ProcessProfile::Auto);
+    assert!(!result.fast_path && !call.1);
+    let (result, _) = process(&speech_fixture(), ProcessProfile::Auto);
+    assert!(!result.fast_path);
+    assert_eq!(formatted.load(Ordering::Relaxed), 3);
+
+    // Asked for, but not over the chunk length
+    let (result, call) = process(&speech_fixture(), ProcessProfile::Command);
+    assert!(result.fast_path && call.1);
+    let long: Vec<f32> = speech_fixture().into_iter().cycle().take(16 * 31_000).collect();
+    let (result, _) = process(&long, ProcessProfile::Command);
+    assert!(!result.fast_path);
+    drop(process);
+
+    let mut config = pipeline.config().clone();
+    config.command_max_ms = 0;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&command, None).unwrap();
+    assert!(!result.fast_path);
+}
+
+#[test]
+fn test_llm_timeout_returns_the_raw_transcript() {
+    let mut pipeline =
+        Pipeline::with_engines(Box::new(FixedTranscript("hello world how are you")), Box::new(SlowFormatting))
+            .unwrap();
+    let mut config = pipeline.config().clone();
+    config.timeouts.llm_ms = 20;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.formatted_text, "hello world how are you");
+    assert!(result.was_fallback);
+    assert!(result.formatting_error.as_deref().unwrap().contains("llm time limit"), "{:?}", result.formatting_error);
+    assert!(result.timings.llm_formatting_ms < 1000);
+
+    let mut strict = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript("hello world how are you")))
+        .llm_engine(Box::new(SlowFormatting))
+        .llm_strict(true)
+        .build()
+        .unwrap();
+    // The shared config would turn strict mode back off
+    strict.update_config(&Config { llm_strict: true, ..config.clone() }).unwrap();
+    let err = strict.process(&speech_fixture(), None).unwrap_err();
+    assert!(
+        matches!(err.without_context(), PipelineError::Timeout { stage: TimeoutStage::Llm, elapsed_ms } if *elapsed_ms >= 20),
+        "{:#}",
+        anyhow::Error::from(err)
+    );
+
+    // Out of time for the whole request, the transcript is still kept
+    config.timeouts.llm_ms = 0;
+    config.timeouts.total_ms = 200;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process(&speech_fixture(), None).unwrap();
+    assert_eq!(result.formatted_text, "hello world how are you");
+    assert!(result.formatting_error.as_deref().unwrap().contains("total time limit"));
+}
+
+#[test]
+fn test_prompt_is_cut_to_fit_a_tiny_context_window() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let transcript = "We met the team today. Then we planned the launch. Finally everyone went home.";
+    let mut pipeline =
+        Pipeline::with_engines(Box::new(FixedTranscript(transcript)), Box::new(EchoFormatting(Arc::clone(&seen))))
+            .unwrap();
+    // Room for the prompt and one sentence, without the context
+    let template = "Fix for {context}: {transcript}";
+    let mut config = pipeline.config().clone();
+    let room = estimate_tokens(&format_prompt(&template.replace("{context}", ""), "Then we planned the launch.", &config));
+    config.llm_context_window = Some(config.llm_options.max_tokens + room as u32);
+    pipeline.update_config(&config).unwrap();
+
+    let options =
+        ProcessOptions { preset: Some(FormattingPreset::Custom(template.to_string())), ..ProcessOptions::default() };
+    let result = pipeline.process_with_options(&speech_fixture(), Some("a thread about the launch"), &options).unwrap();
+    assert_eq!(*seen.lock().unwrap(), ["We met the team today.", "Then we planned the launch.", "Finally everyone went home."]);
+    assert_eq!(result.formatted_text, transcript);
+    assert_eq!(
+        result.prompt_truncation,
+        Some(PromptTruncation { history_truncated: false, context_truncated: true, transcript_chunks: 3 })
+    );
+
+    // With the default window nothing is cut
+    config.llm_context_window = None;
+    pipeline.update_config(&config).unwrap();
+    let result = pipeline.process_with_options(&speech_fixture(), Some("a thread about the launch"), &options).unwrap();
+    assert_eq!(result.prompt_truncation, None);
+}
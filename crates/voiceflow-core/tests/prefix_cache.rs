@@ -0,0 +1,37 @@
+//! Keeping the prefill of the prompt's start cuts the LLM's prefill time,
+//! without changing the output
+//!
+//! Needs downloaded models: `cargo test --test prefix_cache -- --ignored --nocapture`
+
+mod common;
+
+use common::fixture;
+use voiceflow_core::{Config, Pipeline, PipelineResult};
+
+const RUNS: usize = 5;
+
+/// Results of `RUNS` requests after a warm-up, and their mean prefill
+/// time in milliseconds
+fn bench(config: &Config, audio: &[f32]) -> (Vec<PipelineResult>, u64) {
+    let mut pipeline = Pipeline::new(config).unwrap();
+    pipeline.warm_up().unwrap();
+    let results: Vec<PipelineResult> = (0..RUNS).map(|_| pipeline.process(audio, None).unwrap()).collect();
+    let prefill_ms = results.iter().map(|result| result.timings.llm_prefill_ms).sum::<u64>() / RUNS as u64;
+    (results, prefill_ms)
+}
+
+#[test]
+#[ignore]
+fn test_prefix_cache_cuts_prefill() {
+    let audio = fixture();
+    // The same output either way, and no cached results
+    let config = Config { deterministic: true, format_cache_size: 0, ..Config::default() };
+
+    let (uncached, uncached_ms) = bench(&Config { llm_prefix_cache: false, ..config.clone() }, &audio);
+    let (cached, cached_ms) = bench(&config, &audio);
+    println!("LLM prefill: {}ms with the prefix cache, {}ms without", cached_ms, uncached_ms);
+
+    assert!(!cached[0].formatted_text.is_empty());
+    assert_eq!(cached[0].formatted_text, uncached[0].formatted_text);
+    assert!(cached_ms < uncached_ms, "{}ms with the prefix cache, {}ms without", cached_ms, uncached_ms);
+}
@@ -0,0 +1,45 @@
+//! Pipelines with the same models share the loaded weights, and transcribe
+//! and format at the same time without getting in each other's way
+//!
+//! Needs downloaded models: `cargo test --test shared_models -- --ignored --nocapture`
+
+mod common;
+
+use common::fixture;
+use voiceflow_core::models::shared::loaded_models;
+use voiceflow_core::{Config, Pipeline};
+
+fn users() -> Vec<(&'static str, usize)> {
+    loaded_models().into_iter().map(|model| (model.kind, model.users)).collect()
+}
+
+#[test]
+#[ignore]
+fn test_pipelines_share_weights() {
+    let audio = fixture();
+    let config = Config { deterministic: true, llm_preload: true, format_cache_size: 0, ..Config::default() };
+
+    let mut first = Pipeline::new(&config).unwrap();
+    let expected = first.process(&audio, None).unwrap();
+    assert!(users().iter().all(|&(_, users)| users == 1), "{:?}", users());
+
+    let mut second = Pipeline::new(&config).unwrap();
+    second.process(&audio, None).unwrap();
+    let shared = users();
+    assert_eq!(shared.len(), 2, "{:?}", shared);
+    assert!(shared.iter().all(|&(_, users)| users == 2), "{:?}", shared);
+
+    // Both at once, each with its own decoder state and KV cache
+    let (a, b) = std::thread::scope(|scope| {
+        let a = scope.spawn(|| first.process(&audio, None).unwrap());
+        let b = scope.spawn(|| second.process(&audio, None).unwrap());
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    assert_eq!(a.formatted_text, expected.formatted_text);
+    assert_eq!(b.formatted_text, expected.formatted_text);
+
+    drop(first);
+    assert!(users().iter().all(|&(_, users)| users == 1), "{:?}", users());
+    drop(second);
+    assert!(users().is_empty(), "{:?}", users());
+}
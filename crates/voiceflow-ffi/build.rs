@@ -15,6 +15,140 @@ fn main() {
         .generate()
         .expect("Unable to generate bindings")
         .write_to_file(output_dir.join("voiceflow.h"));
+    sync_swift_package(std::path::Path::new(&crate_dir), &output_dir.join("voiceflow.h"));
 
-    println!("cargo:rerun-if-changed=src/lib.rs");
+    // Commit the library was built from, for panic reports
+    let build_hash = std::process::Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=VOICEFLOW_BUILD_HASH={}", build_hash);
+
+    // Build time, target and backend versions, for voiceflow_build_info.
+    // SOURCE_DATE_EPOCH pins the time for reproducible builds
+    let build_secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    println!("cargo:rustc-env=VOICEFLOW_BUILD_DATE={}", utc_timestamp(build_secs));
+    println!("cargo:rustc-env=VOICEFLOW_BUILD_TARGET={}", std::env::var("TARGET").unwrap_or_default());
+    println!("cargo:rustc-env=VOICEFLOW_BUILD_PROFILE={}", std::env::var("PROFILE").unwrap_or_default());
+    let lock = std::fs::read_to_string(std::path::Path::new(&crate_dir).join("../../Cargo.lock")).unwrap_or_default();
+    for (package, var) in [
+        ("ort", "VOICEFLOW_ORT_VERSION"),
+        ("mistralrs", "VOICEFLOW_MISTRALRS_VERSION"),
+        ("whisper-rs", "VOICEFLOW_WHISPER_RS_VERSION"),
+    ] {
+        let version = locked_version(&lock, package).unwrap_or_else(|| "unknown".to_string());
+        println!("cargo:rustc-env={}={}", var, version);
+    }
+
+    // The header is generated from every source file, with cbindgen.toml
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../Cargo.lock");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+/// Copy the header into the Swift package at the repo root and check that
+/// every C function its sources call is declared there, so the Swift
+/// wrapper can't fall behind the C API
+fn sync_swift_package(crate_dir: &std::path::Path, header_path: &std::path::Path) {
+    let package = crate_dir.join("../../swift");
+    if !package.is_dir() {
+        return;
+    }
+    let header = std::fs::read_to_string(header_path).expect("Unable to read the generated header");
+    let package_header = package.join("Sources/CVoiceFlow/voiceflow.h");
+    if std::fs::read_to_string(&package_header).ok().as_deref() != Some(header.as_str()) {
+        std::fs::write(&package_header, &header).expect("Unable to copy the header into the Swift package");
+    }
+
+    let mut missing = Vec::new();
+    for dir in ["Sources/VoiceFlow", "Tests/VoiceFlowTests"] {
+        let dir = package.join(dir);
+        println!("cargo:rerun-if-changed={}", dir.display());
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some("swift") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap_or_default();
+            for name in c_functions(&source) {
+                let declared = header.contains(&format!(" {}(", name)) || header.contains(&format!("*{}(", name));
+                if !declared && !missing.contains(&name) {
+                    missing.push(name);
+                }
+            }
+        }
+    }
+    if !missing.is_empty() {
+        panic!("The Swift package calls functions missing from voiceflow.h: {}", missing.join(", "));
+    }
+}
+
+/// Names of the voiceflow_ C functions called in a Swift source
+fn c_functions(source: &str) -> Vec<String> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    source
+        .match_indices("voiceflow_")
+        .filter(|&(start, _)| !source[..start].ends_with(is_ident))
+        .map(|(start, _)| source[start..].chars().take_while(|&c| is_ident(c)).collect::<String>())
+        .filter(|name| source.contains(&format!("{}(", name)))
+        .collect()
+}
+
+/// Version of `package` in Cargo.lock, with the commit for a git
+/// dependency, e.g. "0.6.0 (git 1a2b3c4d5e6f)"
+fn locked_version(lock: &str, package: &str) -> Option<String> {
+    let entry = lock
+        .split("[[package]]")
+        .find(|entry| entry.lines().any(|line| line.trim() == format!("name = \"{}\"", package)))?;
+    let field = |key: &str| {
+        entry.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(key)?.trim_start().strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string())
+        })
+    };
+    let version = field("version")?;
+    let commit = field("source")
+        .filter(|source| source.starts_with("git+"))
+        .and_then(|source| source.rsplit_once('#').map(|(_, commit)| commit.chars().take(12).collect::<String>()));
+    Some(match commit {
+        Some(commit) => format!("{} (git {})", version, commit),
+        None => version,
+    })
+}
+
+/// Seconds since the Unix epoch as an RFC 3339 UTC timestamp
+fn utc_timestamp(secs: u64) -> String {
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
 }
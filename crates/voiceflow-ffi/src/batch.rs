@@ -0,0 +1,130 @@
+//! Processing many files in one call
+
+use std::ffi::{c_char, c_void, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use serde_json::{json, Value};
+use voiceflow_core::{BatchInput, BatchOptions, ProcessOptions};
+
+use crate::error::{classify, clear_last_error, set_last_error};
+use crate::panic_report::caught_panic;
+use crate::worker::UserData;
+use crate::{invalid_handle, lock_pipeline, str_arg, VoiceFlowErrorCode, VoiceFlowHandle};
+
+/// Files decoded ahead of the one being transcribed
+const BATCH_DECODE_THREADS: usize = 2;
+
+/// Progress callback for voiceflow_process_batch
+///
+/// Called on the calling thread after each file with the caller's
+/// user_data, the index of the file that just finished, the files finished
+/// and failed so far, and the number of files.
+pub type VoiceFlowBatchProgressCallback =
+    extern "C" fn(user_data: *mut c_void, index: usize, completed: usize, failed: usize, total: usize);
+
+/// Transcribe and format many audio files (WAV, AIFF or CAF, plus M4A/AAC,
+/// MP3, FLAC and Ogg with the compressed-audio feature) with the loaded
+/// models
+///
+/// Returns a JSON array with an object per file, in the order of paths:
+/// `{"path", "raw_transcript", "formatted_text", "no_speech", "was_fallback",
+/// "formatting_error", "language", "total_ms"}` on success, or `{"path", "error": {"code",
+/// "message"}}` if that file failed; one failed file doesn't stop the
+/// others. voiceflow_cancel fails the files not yet processed with
+/// VF_ERR_CANCELLED. Blocks until done; progress_callback may be null.
+/// Returns null on invalid arguments (see voiceflow_last_error_message).
+/// Free the string with voiceflow_free_string.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - paths must point to path_count valid null-terminated strings
+/// - context can be null
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process_batch(
+    handle: *mut VoiceFlowHandle,
+    paths: *const *const c_char,
+    path_count: usize,
+    context: *const c_char,
+    progress_callback: Option<VoiceFlowBatchProgressCallback>,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    clear_last_error();
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return ptr::null_mut();
+    }
+    if paths.is_null() && path_count > 0 {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "paths must not be null");
+        return ptr::null_mut();
+    }
+    let mut files = Vec::with_capacity(path_count);
+    for i in 0..path_count {
+        let Some(path) = str_arg(*paths.add(i), "path") else {
+            return ptr::null_mut();
+        };
+        files.push(path);
+    }
+    let context = if context.is_null() {
+        None
+    } else {
+        match str_arg(context, "context") {
+            Some(c) => Some(c.to_string()),
+            None => return ptr::null_mut(),
+        }
+    };
+    tracing::debug!("voiceflow_process_batch called with {} files", files.len());
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let inputs: Vec<_> = files.iter().map(|path| BatchInput::File(PathBuf::from(path))).collect();
+    let user_data = UserData(user_data);
+
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let cancel = handle.cancel.begin();
+        let mut pipeline = lock_pipeline(&handle.pipeline);
+        let options = BatchOptions {
+            process: ProcessOptions { cancel: cancel.clone(), ..Default::default() },
+            context,
+            decode_threads: BATCH_DECODE_THREADS,
+        };
+        pipeline.process_batch(&inputs, &options, |p| {
+            if let Some(callback) = progress_callback {
+                callback(user_data.0, p.index, p.completed, p.failed, p.total);
+            }
+        })
+    }));
+    let results = match outcome {
+        Ok(results) => results,
+        Err(e) => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_PANIC,
+                format!("Internal error: {}", caught_panic(e)),
+            );
+            return ptr::null_mut();
+        }
+    };
+
+    let items: Vec<Value> = files
+        .iter()
+        .zip(results)
+        .map(|(path, result)| match result {
+            Ok(r) => json!({
+                "path": path,
+                "raw_transcript": r.raw_transcript,
+                "formatted_text": r.formatted_text,
+                "no_speech": r.no_speech,
+                "was_fallback": r.was_fallback,
+                "formatting_error": r.formatting_error,
+                "language": r.language,
+                "total_ms": r.timings.total_ms,
+            }),
+            Err(e) => json!({
+                "path": path,
+                "error": { "code": classify(&e) as i32, "message": format!("{:#}", e) },
+            }),
+        })
+        .collect();
+    CString::new(Value::Array(items).to_string()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
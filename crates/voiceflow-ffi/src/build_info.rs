@@ -0,0 +1,85 @@
+//! What the library was built from and with, for support reports
+//!
+//! Filled in at compile time by build.rs: the commit, build time, target
+//! and the versions of the inference backends resolved in Cargo.lock
+//! ("unknown" when building without one).
+
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+use serde_json::{json, Value};
+use voiceflow_core::llm::gguf::{SUPPORTED_ARCHITECTURES, SUPPORTED_GGUF_VERSIONS};
+
+/// Cargo features the library was built with
+fn features() -> Vec<&'static str> {
+    [
+        ("metal", cfg!(feature = "metal")),
+        ("cuda", cfg!(feature = "cuda")),
+        ("remote-formatter", cfg!(feature = "remote-formatter")),
+        ("diarization", cfg!(feature = "diarization")),
+        ("compressed-audio", cfg!(feature = "compressed-audio")),
+        ("ios", cfg!(feature = "ios")),
+        ("otlp", cfg!(feature = "otlp")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+fn build_info() -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_hash": env!("VOICEFLOW_BUILD_HASH"),
+        "build_date": env!("VOICEFLOW_BUILD_DATE"),
+        "target": env!("VOICEFLOW_BUILD_TARGET"),
+        "profile": env!("VOICEFLOW_BUILD_PROFILE"),
+        "features": features(),
+        "backends": {
+            "ort": env!("VOICEFLOW_ORT_VERSION"),
+            "mistralrs": env!("VOICEFLOW_MISTRALRS_VERSION"),
+            "whisper_rs": env!("VOICEFLOW_WHISPER_RS_VERSION"),
+        },
+        "model_formats": {
+            "gguf_versions": SUPPORTED_GGUF_VERSIONS.collect::<Vec<_>>(),
+            "gguf_architectures": SUPPORTED_ARCHITECTURES,
+            "whisper": "ggml",
+            "moonshine": "onnx",
+        },
+    })
+}
+
+/// Get what the library was built from and with, as a JSON object
+///
+/// For support reports: "version", "git_hash", "build_date" (RFC 3339,
+/// UTC), "target" (the target triple), "profile", "features" (the Cargo
+/// features, e.g. ["metal", "diarization"]), "backends" (the "ort",
+/// "mistralrs" and "whisper_rs" versions, with the commit for a git
+/// dependency, e.g. "0.6.0 (git 1a2b3c4d5e6f)") and "model_formats" (the
+/// "gguf_versions" and "gguf_architectures" an LLM can use, and the
+/// "whisper" and "moonshine" model formats). Needs no handle. Free the
+/// string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_build_info() -> *mut c_char {
+    CString::new(build_info().to_string()).map_or(ptr::null_mut(), |s| s.into_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_build_info_json() {
+        let ptr = voiceflow_build_info();
+        assert!(!ptr.is_null());
+        let info: Value = serde_json::from_str(unsafe { CStr::from_ptr(ptr) }.to_str().unwrap()).unwrap();
+        unsafe { crate::voiceflow_free_string(ptr) };
+
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert!(info["target"].as_str().unwrap().contains(std::env::consts::ARCH));
+        assert!(info["build_date"].as_str().unwrap().ends_with('Z'));
+        assert!(info["features"].is_array());
+        assert!(info["backends"]["ort"].is_string());
+        assert_eq!(info["model_formats"]["gguf_versions"], json!([2, 3]));
+    }
+}
@@ -0,0 +1,134 @@
+//! Cancellation of a handle's requests: each request gets its own token,
+//! voiceflow_cancel signals the tokens of the requests in flight and
+//! voiceflow_cancel_request the token of one
+
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use voiceflow_core::CancelToken;
+
+/// Tokens of the requests in flight on a handle, from the moment they start
+/// waiting for the pipeline until they return
+///
+/// A request never shares its token, so a cancel can't be lost to another
+/// request clearing it, nor carry over to the next one.
+#[derive(Default)]
+pub(crate) struct InFlight {
+    tokens: Mutex<Vec<(u64, CancelToken)>>,
+    next_id: AtomicU64,
+}
+
+impl InFlight {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::default()
+    }
+
+    /// A new token for a request, signalled by `cancel` until the guard is
+    /// dropped
+    ///
+    /// Call it before waiting for the pipeline, so a cancel sent while the
+    /// request waits still stops it.
+    pub(crate) fn begin(self: &Arc<Self>) -> RequestCancel {
+        let id = self.reserve();
+        self.begin_reserved(id).expect("token just reserved")
+    }
+
+    /// A new request id, its token in flight until the request given it
+    /// returns (see `begin_reserved`); ids start at 1
+    pub(crate) fn reserve(&self) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner()).push((id, CancelToken::new()));
+        id
+    }
+
+    /// The token reserved as `id`, cancelled already if a cancel came since;
+    /// None for an id not reserved, or whose request returned
+    pub(crate) fn begin_reserved(self: &Arc<Self>, id: u64) -> Option<RequestCancel> {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        let token = tokens.iter().find(|(reserved, _)| *reserved == id)?.1.clone();
+        Some(RequestCancel { in_flight: Arc::clone(self), id, token })
+    }
+
+    /// Requests in flight
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Cancel every request in flight
+    pub(crate) fn cancel(&self) {
+        for (_, token) in self.tokens.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            token.cancel();
+        }
+    }
+
+    /// Cancel the request reserved as `id`, if still in flight
+    pub(crate) fn cancel_request(&self, id: u64) {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, token)) = tokens.iter().find(|(reserved, _)| *reserved == id) {
+            token.cancel();
+        }
+    }
+}
+
+/// A request's token, in flight until dropped
+pub(crate) struct RequestCancel {
+    in_flight: Arc<InFlight>,
+    id: u64,
+    token: CancelToken,
+}
+
+impl Deref for RequestCancel {
+    type Target = CancelToken;
+
+    fn deref(&self) -> &CancelToken {
+        &self.token
+    }
+}
+
+impl Drop for RequestCancel {
+    fn drop(&mut self) {
+        self.in_flight.tokens.lock().unwrap_or_else(|e| e.into_inner()).retain(|(id, _)| *id != self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_reaches_every_request_in_flight() {
+        let in_flight = InFlight::new();
+        let (running, waiting) = (in_flight.begin(), in_flight.begin());
+        in_flight.cancel();
+        assert!(running.is_cancelled());
+        assert!(waiting.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_request_reaches_that_request_alone() {
+        let in_flight = InFlight::new();
+        let (id, other) = (in_flight.reserve(), in_flight.begin());
+        // Cancelled before it starts waiting for the pipeline
+        in_flight.cancel_request(id);
+        let request = in_flight.begin_reserved(id).unwrap();
+        assert!(request.is_cancelled());
+        assert!(!other.is_cancelled());
+
+        drop(request);
+        assert!(in_flight.begin_reserved(id).is_none());
+        assert_eq!(in_flight.len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_never_carries_over() {
+        let in_flight = InFlight::new();
+        drop(in_flight.begin());
+        in_flight.cancel();
+        let next = in_flight.begin();
+        assert!(!next.is_cancelled());
+        drop(next);
+        assert!(in_flight.tokens.lock().unwrap().is_empty());
+    }
+}
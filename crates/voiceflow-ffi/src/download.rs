@@ -0,0 +1,234 @@
+//! Model downloads on a background thread, reported through a progress
+//! callback
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void};
+use std::sync::{Mutex, OnceLock};
+
+use voiceflow_core::downloads::{download_model, DownloadError, DownloadableModel};
+use voiceflow_core::integrity::verify_model;
+use voiceflow_core::{CancelToken, Config};
+
+use crate::error::{clear_last_error, set_last_error, set_last_error_from};
+use crate::panic_report::caught_panic;
+use crate::worker::UserData;
+use crate::{memory, str_arg, VoiceFlowErrorCode};
+
+/// State of a download reported to the progress callback
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowDownloadStatus {
+    VF_DOWNLOAD_IN_PROGRESS = 0,
+    VF_DOWNLOAD_COMPLETED = 1,
+    /// See voiceflow_last_error_message from within the callback
+    VF_DOWNLOAD_FAILED = 2,
+    /// The partial file is kept, so the next attempt resumes
+    VF_DOWNLOAD_CANCELLED = 3,
+}
+
+/// Progress callback for voiceflow_download_model
+///
+/// Called on the download thread with the caller's user_data, the bytes
+/// downloaded so far and the total (0 if unknown). Called repeatedly with
+/// VF_DOWNLOAD_IN_PROGRESS, then exactly once with a terminal status.
+pub type VoiceFlowDownloadCallback = extern "C" fn(
+    user_data: *mut c_void,
+    bytes_downloaded: u64,
+    bytes_total: u64,
+    status: VoiceFlowDownloadStatus,
+);
+
+/// Cancel tokens of the downloads in progress, by model id
+fn active_downloads() -> &'static Mutex<HashMap<String, CancelToken>> {
+    static ACTIVE: OnceLock<Mutex<HashMap<String, CancelToken>>> = OnceLock::new();
+    ACTIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Download a model into the models directory on a background thread
+///
+/// model_id is an LLM id from voiceflow_model_info, "whisper-<size>" or
+/// "moonshine-<size>". Files already present are skipped; a partial file
+/// from an interrupted download is resumed. Returns false if the download
+/// could not be started (unknown model, over the memory budget, or already
+/// downloading).
+///
+/// # Safety
+/// - model_id must be a valid null-terminated string
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_download_model(
+    model_id: *const c_char,
+    progress_callback: Option<VoiceFlowDownloadCallback>,
+    user_data: *mut c_void,
+) -> bool {
+    clear_last_error();
+
+    let callback = match progress_callback {
+        Some(cb) => cb,
+        None => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "progress_callback must not be null");
+            return false;
+        }
+    };
+    let id = match str_arg(model_id, "model_id") {
+        Some(id) => id,
+        None => return false,
+    };
+    let model = match DownloadableModel::from_id(id) {
+        Some(model) => model,
+        None => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown model id: {}", id),
+            );
+            return false;
+        }
+    };
+    if !memory::within_budget(&model) {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("{} needs more memory than the budget set with voiceflow_set_memory_budget", id),
+        );
+        return false;
+    }
+    let models_dir = match Config::load(None).unwrap_or_default().models_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            set_last_error_from(&e);
+            return false;
+        }
+    };
+
+    let cancel = CancelToken::new();
+    {
+        let mut active = active_downloads().lock().unwrap_or_else(|e| e.into_inner());
+        if active.contains_key(id) {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("{} is already downloading", id),
+            );
+            return false;
+        }
+        active.insert(id.to_string(), cancel.clone());
+    }
+
+    let user_data = UserData(user_data);
+    let spawned = std::thread::Builder::new()
+        .name("voiceflow-download".to_string())
+        .spawn(move || {
+            let user_data = user_data;
+            let mut last = (0, 0);
+            let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                download_model(&model, &models_dir, &cancel, |done, total| {
+                    last = (done, total.unwrap_or(0));
+                    callback(user_data.0, done, last.1, VoiceFlowDownloadStatus::VF_DOWNLOAD_IN_PROGRESS);
+                })
+            }));
+            active_downloads()
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&model.id());
+
+            // Errors are recorded on this thread, so the callback can read them
+            let status = match outcome {
+                Ok(Ok(())) => VoiceFlowDownloadStatus::VF_DOWNLOAD_COMPLETED,
+                Ok(Err(e)) if matches!(e.downcast_ref::<DownloadError>(), Some(DownloadError::Cancelled)) => {
+                    tracing::info!("Download of {} cancelled", model.id());
+                    set_last_error_from(&e);
+                    VoiceFlowDownloadStatus::VF_DOWNLOAD_CANCELLED
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("Download of {} failed: {:#}", model.id(), e);
+                    set_last_error_from(&e);
+                    VoiceFlowDownloadStatus::VF_DOWNLOAD_FAILED
+                }
+                Err(panic) => {
+                    let msg = caught_panic(panic);
+                    tracing::error!("PANIC caught in download of {}: {}", model.id(), msg);
+                    set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+                    VoiceFlowDownloadStatus::VF_DOWNLOAD_FAILED
+                }
+            };
+            callback(user_data.0, last.0, last.1, status);
+        });
+
+    if let Err(e) = spawned {
+        active_downloads().lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, format!("Failed to start download thread: {}", e));
+        return false;
+    }
+    true
+}
+
+/// Cancel a download started with voiceflow_download_model
+///
+/// The callback then reports VF_DOWNLOAD_CANCELLED. Returns false if the
+/// model isn't downloading.
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_cancel_download(model_id: *const c_char) -> bool {
+    clear_last_error();
+    let id = match str_arg(model_id, "model_id") {
+        Some(id) => id,
+        None => return false,
+    };
+
+    match active_downloads().lock().unwrap_or_else(|e| e.into_inner()).get(id) {
+        Some(cancel) => {
+            cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Check the files of a downloaded model against their recorded checksums
+///
+/// Hashes every file, so this takes seconds for an LLM: call it off the
+/// main thread. Returns false with VF_ERR_MODEL_CORRUPTED if a file is
+/// damaged (offer voiceflow_download_model, which replaces it) or
+/// VF_ERR_MODEL_NOT_FOUND if one is missing. Files downloaded before
+/// checksums were recorded pass unchecked.
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_verify_model(model_id: *const c_char) -> bool {
+    clear_last_error();
+    let id = match str_arg(model_id, "model_id") {
+        Some(id) => id,
+        None => return false,
+    };
+    let model = match DownloadableModel::from_id(id) {
+        Some(model) => model,
+        None => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown model id: {}", id),
+            );
+            return false;
+        }
+    };
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let models_dir = Config::load(None).unwrap_or_default().models_dir()?;
+        verify_model(&model, &models_dir)
+    }));
+    match result {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            tracing::warn!("Verification of {} failed: {:#}", id, e);
+            set_last_error_from(&e);
+            false
+        }
+        Err(panic) => {
+            let msg = caught_panic(panic);
+            tracing::error!("PANIC caught in voiceflow_verify_model: {}", msg);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+            false
+        }
+    }
+}
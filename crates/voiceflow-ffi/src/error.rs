@@ -0,0 +1,220 @@
+//! Thread-local last-error reporting for foreign callers
+//!
+//! Every fallible entry point records why it failed so the host app can show
+//! something more useful than "initialization failed".
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+use voiceflow_core::audio::AudioFileError;
+use voiceflow_core::downloads::DownloadError;
+use voiceflow_core::models::storage::StorageError;
+use voiceflow_core::{ConfigError, PipelineError};
+
+/// Error category for the last failed call on the current thread
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowErrorCode {
+    VF_ERR_OK = 0,
+    VF_ERR_INVALID_ARGUMENT = 1,
+    VF_ERR_CONFIG = 2,
+    VF_ERR_MODEL_NOT_FOUND = 3,
+    VF_ERR_ONNX = 4,
+    VF_ERR_STT = 5,
+    VF_ERR_LLM = 6,
+    VF_ERR_AUDIO = 7,
+    VF_ERR_IO = 8,
+    VF_ERR_PANIC = 9,
+    VF_ERR_INTERNAL = 10,
+    VF_ERR_CANCELLED = 11,
+    VF_ERR_DOWNLOAD = 12,
+    VF_ERR_MODEL_CORRUPTED = 13,
+    VF_ERR_MODEL_IN_USE = 14,
+    VF_ERR_BUSY = 15,
+    VF_ERR_EMPTY_AUDIO = 16,
+    VF_ERR_INVALID_SAMPLES = 17,
+    VF_ERR_AUDIO_TOO_LONG = 18,
+    VF_ERR_TIMEOUT = 19,
+}
+
+struct LastError {
+    code: VoiceFlowErrorCode,
+    message: String,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+/// Record an error for the current thread
+pub(crate) fn set_last_error(code: VoiceFlowErrorCode, message: impl Into<String>) {
+    let message = message.into();
+    LAST_ERROR.with(|slot| {
+        *slot.borrow_mut() = Some(LastError { code, message });
+    });
+}
+
+/// Record an error from voiceflow-core, keeping the full context chain
+pub(crate) fn set_last_error_from(err: &anyhow::Error) {
+    set_last_error(classify(err), format!("{:#}", err));
+}
+
+/// Clear the last error at the start of a fallible call
+///
+/// Also installs the panic hook, since every fallible entry point starts
+/// here.
+pub(crate) fn clear_last_error() {
+    crate::panic_report::install();
+    LAST_ERROR.with(|slot| {
+        *slot.borrow_mut() = None;
+    });
+}
+
+/// Extract a readable message from a caught panic payload
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Unknown panic".to_string()
+    }
+}
+
+/// Map an error chain onto the coarse FFI error categories
+pub(crate) fn classify(err: &anyhow::Error) -> VoiceFlowErrorCode {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<PipelineError>() {
+            return pipeline_code(e);
+        }
+        if let Some(e) = cause.downcast_ref::<AudioFileError>() {
+            return audio_file_code(e);
+        }
+        if let Some(e) = cause.downcast_ref::<DownloadError>() {
+            return match e {
+                DownloadError::Cancelled => VoiceFlowErrorCode::VF_ERR_CANCELLED,
+                DownloadError::UnknownModel { .. } => VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                _ => VoiceFlowErrorCode::VF_ERR_DOWNLOAD,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<StorageError>() {
+            return match e {
+                StorageError::ModelInUse { .. } => VoiceFlowErrorCode::VF_ERR_MODEL_IN_USE,
+                StorageError::NestedModelsDir { .. } => VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                StorageError::CopyMismatch { .. } | StorageError::NotWritable { .. } => VoiceFlowErrorCode::VF_ERR_IO,
+            };
+        }
+        if cause.downcast_ref::<ConfigError>().is_some() {
+            return VoiceFlowErrorCode::VF_ERR_CONFIG;
+        }
+        if cause.downcast_ref::<std::io::Error>().is_some() {
+            return VoiceFlowErrorCode::VF_ERR_IO;
+        }
+    }
+    VoiceFlowErrorCode::VF_ERR_INTERNAL
+}
+
+/// The error code of each pipeline error
+fn pipeline_code(err: &PipelineError) -> VoiceFlowErrorCode {
+    match err.without_context() {
+        PipelineError::SttModelNotFound { .. }
+        | PipelineError::LlmModelNotFound { .. }
+        | PipelineError::SpeakerModelNotFound { .. } => VoiceFlowErrorCode::VF_ERR_MODEL_NOT_FOUND,
+        PipelineError::DiarizationUnavailable | PipelineError::Config(_) => VoiceFlowErrorCode::VF_ERR_CONFIG,
+        PipelineError::ModelCorrupted { .. } => VoiceFlowErrorCode::VF_ERR_MODEL_CORRUPTED,
+        PipelineError::OnnxLoadFailed { .. } => VoiceFlowErrorCode::VF_ERR_ONNX,
+        PipelineError::Cancelled { .. } => VoiceFlowErrorCode::VF_ERR_CANCELLED,
+        PipelineError::Timeout { .. } => VoiceFlowErrorCode::VF_ERR_TIMEOUT,
+        PipelineError::SttInitFailed { .. } | PipelineError::TranscriptionFailed { .. } => {
+            VoiceFlowErrorCode::VF_ERR_STT
+        }
+        PipelineError::LlmInitFailed { .. }
+        | PipelineError::LlmFormattingFailed { .. }
+        | PipelineError::PromptTooLong { .. }
+        | PipelineError::RemoteFormatter { .. } => {
+            VoiceFlowErrorCode::VF_ERR_LLM
+        }
+        PipelineError::AudioTooShort { .. } => VoiceFlowErrorCode::VF_ERR_AUDIO,
+        PipelineError::EmptyAudio => VoiceFlowErrorCode::VF_ERR_EMPTY_AUDIO,
+        PipelineError::InvalidSamples { .. } => VoiceFlowErrorCode::VF_ERR_INVALID_SAMPLES,
+        PipelineError::AudioTooLong { .. } => VoiceFlowErrorCode::VF_ERR_AUDIO_TOO_LONG,
+        PipelineError::InvalidContext { .. } => VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+        PipelineError::AudioFile(e) => audio_file_code(e),
+        PipelineError::Io(_) => VoiceFlowErrorCode::VF_ERR_IO,
+        // The cause wasn't one of the kinds above; its chain may still say
+        PipelineError::Other(e) => classify(e),
+        // Variants added to voiceflow-core since
+        _ => VoiceFlowErrorCode::VF_ERR_INTERNAL,
+    }
+}
+
+fn audio_file_code(err: &AudioFileError) -> VoiceFlowErrorCode {
+    match err {
+        AudioFileError::Io { .. } => VoiceFlowErrorCode::VF_ERR_IO,
+        _ => VoiceFlowErrorCode::VF_ERR_AUDIO,
+    }
+}
+
+/// Get the error code of the last failed call on this thread
+///
+/// Returns VF_ERR_OK if the most recent fallible call succeeded.
+#[no_mangle]
+pub extern "C" fn voiceflow_last_error_code() -> VoiceFlowErrorCode {
+    LAST_ERROR.with(|slot| {
+        slot.borrow()
+            .as_ref()
+            .map(|e| e.code)
+            .unwrap_or(VoiceFlowErrorCode::VF_ERR_OK)
+    })
+}
+
+/// Get the message of the last failed call on this thread, including the
+/// underlying error chain
+///
+/// Returns null if there is no error. Free with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_last_error_message() -> *mut c_char {
+    LAST_ERROR.with(|slot| match slot.borrow().as_ref() {
+        Some(e) => CString::new(e.message.replace('\0', ""))
+            .map(|s| s.into_raw())
+            .unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voiceflow_core::TimeoutStage;
+
+    #[test]
+    fn test_pipeline_errors_map_to_their_codes() {
+        let stt = PipelineError::TranscriptionFailed {
+            engine: "Whisper".to_string(),
+            source: anyhow::anyhow!("decoder failed"),
+        };
+        let err = anyhow::Error::from(PipelineError::Context {
+            context: "Transcribing the recording".to_string(),
+            source: Box::new(stt),
+        });
+        assert_eq!(classify(&err), VoiceFlowErrorCode::VF_ERR_STT);
+
+        let config = PipelineError::Config(ConfigError::InvalidTopP { value: 1.5 });
+        assert_eq!(classify(&config.into()), VoiceFlowErrorCode::VF_ERR_CONFIG);
+        let timeout = PipelineError::Timeout { stage: TimeoutStage::Llm, elapsed_ms: 60_000 };
+        assert_eq!(classify(&timeout.into()), VoiceFlowErrorCode::VF_ERR_TIMEOUT);
+        let unreadable = PipelineError::AudioFile(AudioFileError::Io {
+            path: "missing.wav".to_string(),
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        });
+        assert_eq!(classify(&unreadable.into()), VoiceFlowErrorCode::VF_ERR_IO);
+
+        // Other keeps the chain it was lifted from
+        let other = PipelineError::Other(anyhow::Error::from(DownloadError::Cancelled).context("Fetching the model"));
+        assert_eq!(classify(&other.into()), VoiceFlowErrorCode::VF_ERR_CANCELLED);
+        assert_eq!(classify(&PipelineError::from(anyhow::anyhow!("boom")).into()), VoiceFlowErrorCode::VF_ERR_INTERNAL);
+    }
+}
@@ -0,0 +1,316 @@
+//! Pipeline spans for the host app
+//!
+//! voiceflow-core wraps each request in a `request` span with a child span
+//! per stage (see the pipeline module). While an event callback is set, or
+//! an OTLP endpoint is configured in builds with the `otlp` feature, every
+//! voiceflow span is passed on as it closes, as one JSON object:
+//!
+//! ```json
+//! {"name": "stt", "target": "voiceflow_core::pipeline",
+//!  "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736", "span_id": "00f067aa0ba902b7",
+//!  "parent_span_id": "a3ce929d0e0e4736", "start_unix_ns": 1760601600000000000,
+//!  "duration_ms": 812.4, "fields": {"audio_ms": 4200, "chunks": 1, "provider": "CoreML"}}
+//! ```
+//!
+//! Stages close before the request that contains them, and all spans of one
+//! request share its trace_id. Spans aren't recorded at all while nobody
+//! listens.
+
+use std::collections::hash_map::RandomState;
+use std::ffi::{c_char, c_void, CString};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+use voiceflow_core::config::TelemetryOptions;
+
+use crate::logging;
+use crate::worker::UserData;
+
+/// Event callback: receives one closed span as JSON, valid only for the
+/// duration of the call
+pub type VoiceFlowEventCallback = extern "C" fn(user_data: *mut c_void, event_json: *const c_char);
+
+static CALLBACK: Mutex<Option<(VoiceFlowEventCallback, UserData)>> = Mutex::new(None);
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+fn callback() -> MutexGuard<'static, Option<(VoiceFlowEventCallback, UserData)>> {
+    CALLBACK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Whether closed spans go anywhere, so spans are worth recording
+pub(crate) fn listening() -> bool {
+    LISTENING.load(Ordering::Relaxed)
+}
+
+fn update_listening() {
+    let listening = callback().is_some() || otlp_enabled();
+    LISTENING.store(listening, Ordering::Relaxed);
+    // `enabled` results are cached per call site; drop them so spans start
+    // (or stop) being recorded everywhere
+    tracing::callsite::rebuild_interest_cache();
+}
+
+/// Apply the `[telemetry]` section of a config that was just loaded
+///
+/// Without the `otlp` feature an endpoint is only warned about: nothing
+/// leaves the machine.
+pub(crate) fn set_telemetry(options: &TelemetryOptions) {
+    #[cfg(feature = "otlp")]
+    {
+        crate::otlp::set_endpoint(options.endpoint.as_deref());
+        update_listening();
+    }
+    #[cfg(not(feature = "otlp"))]
+    if let Some(endpoint) = &options.endpoint {
+        tracing::warn!("Ignoring telemetry.endpoint {}: this build has no OTLP exporter", endpoint);
+    }
+}
+
+fn otlp_enabled() -> bool {
+    #[cfg(feature = "otlp")]
+    let enabled = crate::otlp::enabled();
+    #[cfg(not(feature = "otlp"))]
+    let enabled = false;
+    enabled
+}
+
+/// A closed span
+pub(crate) struct SpanEvent {
+    pub(crate) name: &'static str,
+    pub(crate) target: &'static str,
+    pub(crate) trace_id: u128,
+    pub(crate) span_id: u64,
+    pub(crate) parent_span_id: Option<u64>,
+    pub(crate) start_unix_ns: u64,
+    pub(crate) duration_ns: u64,
+    pub(crate) fields: Map<String, Value>,
+}
+
+impl SpanEvent {
+    /// The object passed to the event callback
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "target": self.target,
+            "trace_id": format!("{:032x}", self.trace_id),
+            "span_id": format!("{:016x}", self.span_id),
+            "parent_span_id": self.parent_span_id.map(|id| format!("{:016x}", id)),
+            "start_unix_ns": self.start_unix_ns,
+            "duration_ms": self.duration_ns as f64 / 1e6,
+            "fields": self.fields,
+        })
+    }
+}
+
+/// Timing and fields of an open span, kept in its extensions
+struct OpenSpan {
+    trace_id: u128,
+    span_id: u64,
+    started: Instant,
+    start_unix_ns: u64,
+    fields: Map<String, Value>,
+}
+
+/// Records voiceflow spans and hands them to `dispatch` as they close
+///
+/// Which spans get here is decided by `logging::ForwardLayer::enabled`.
+pub(crate) struct SpanLayer;
+
+impl<S> Layer<S> for SpanLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let trace_id = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<OpenSpan>().map(|open| open.trace_id))
+            .unwrap_or_else(|| (u128::from(random_id()) << 64) | u128::from(random_id()));
+        let mut fields = FieldVisitor(Map::new());
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(OpenSpan {
+            trace_id,
+            span_id: random_id(),
+            started: Instant::now(),
+            start_unix_ns: unix_ns(),
+            fields: fields.0,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(open) = extensions.get_mut::<OpenSpan>() {
+            let mut fields = FieldVisitor(std::mem::take(&mut open.fields));
+            values.record(&mut fields);
+            open.fields = fields.0;
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(open) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+        let parent_span_id = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<OpenSpan>().map(|open| open.span_id));
+        dispatch(SpanEvent {
+            name: span.metadata().name(),
+            target: span.metadata().target(),
+            trace_id: open.trace_id,
+            span_id: open.span_id,
+            parent_span_id,
+            start_unix_ns: open.start_unix_ns,
+            duration_ns: open.started.elapsed().as_nanos() as u64,
+            fields: open.fields,
+        });
+    }
+}
+
+/// Pass a closed span to the callback and the OTLP exporter
+///
+/// Runs inside the subscriber, so nothing here may log.
+fn dispatch(event: SpanEvent) {
+    if let Some((callback, user_data)) = callback().as_ref() {
+        if let Ok(json) = CString::new(event.to_json().to_string()) {
+            callback(user_data.0, json.as_ptr());
+        }
+    }
+    #[cfg(feature = "otlp")]
+    crate::otlp::export(event);
+}
+
+/// A non-zero id, unique enough for tracing
+fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u64(unix_ns());
+    hasher.finish().max(1)
+}
+
+fn unix_ns() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+}
+
+/// Collects span fields as JSON values
+struct FieldVisitor(Map<String, Value>);
+
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+/// Receive each pipeline span as JSON when it closes (e.g. to feed a
+/// metrics dashboard)
+///
+/// Pass null to remove the callback. The JSON has the span's name,
+/// trace_id, span_id, parent_span_id, start_unix_ns, duration_ms and its
+/// fields (sizes and counts, never transcript text). The callback runs on
+/// the thread that ran the stage and must not call back into the library.
+///
+/// # Safety
+/// user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_event_callback(
+    callback: Option<VoiceFlowEventCallback>,
+    user_data: *mut c_void,
+) {
+    logging::install();
+    *self::callback() = callback.map(|cb| (cb, UserData(user_data)));
+    update_listening();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    /// Spans closed under a subscriber with only the span layer
+    fn closed_spans(body: impl FnOnce()) -> Vec<Value> {
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static SERIAL: Mutex<()> = Mutex::new(());
+        extern "C" fn collect(_user_data: *mut c_void, event_json: *const c_char) {
+            let json = unsafe { std::ffi::CStr::from_ptr(event_json) };
+            EVENTS.lock().unwrap().push(json.to_string_lossy().into_owned());
+        }
+
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        EVENTS.lock().unwrap().clear();
+        *callback() = Some((collect, UserData(std::ptr::null_mut())));
+        tracing::subscriber::with_default(Registry::default().with(SpanLayer), body);
+        *callback() = None;
+        let events = std::mem::take(&mut *EVENTS.lock().unwrap());
+        events.iter().map(|json| serde_json::from_str(json).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_closed_span_has_fields_and_timing() {
+        let events = closed_spans(|| {
+            let span = tracing::info_span!("stt", audio_ms = 4200u64, chunks = tracing::field::Empty);
+            span.record("chunks", 2u64);
+        });
+
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event["name"], "stt");
+        assert_eq!(event["fields"]["audio_ms"], 4200);
+        assert_eq!(event["fields"]["chunks"], 2);
+        assert_eq!(event["trace_id"].as_str().unwrap().len(), 32);
+        assert_eq!(event["span_id"].as_str().unwrap().len(), 16);
+        assert!(event["parent_span_id"].is_null());
+        assert!(event["duration_ms"].as_f64().unwrap() >= 0.0);
+    }
+
+    #[test]
+    fn test_stage_spans_share_the_request_trace() {
+        let events = closed_spans(|| {
+            let _request = tracing::info_span!("request").entered();
+            let _vad = tracing::info_span!("vad").entered();
+        });
+
+        let names: Vec<_> = events.iter().map(|e| e["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["vad", "request"], "stages close before their request");
+        let (vad, request) = (&events[0], &events[1]);
+        assert_eq!(vad["trace_id"], request["trace_id"]);
+        assert_eq!(vad["parent_span_id"], request["span_id"]);
+        assert_ne!(vad["span_id"], request["span_id"]);
+    }
+}
@@ -0,0 +1,88 @@
+//! In-flight call tracking, so voiceflow_destroy can wait for calls that
+//! are still using the handle
+
+use std::sync::{Condvar, Mutex};
+
+/// Counts calls currently running against a handle
+#[derive(Default)]
+pub(crate) struct CallTracker {
+    active: Mutex<usize>,
+    idle: Condvar,
+}
+
+impl CallTracker {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark a call as started; it ends when the guard is dropped
+    pub(crate) fn enter(&self) -> CallGuard<'_> {
+        *self.active.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        CallGuard { tracker: self }
+    }
+
+    /// Block until no calls are running
+    pub(crate) fn wait_idle(&self) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        while *active > 0 {
+            active = self.idle.wait(active).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// Held for the duration of an FFI call using the handle
+pub(crate) struct CallGuard<'a> {
+    tracker: &'a CallTracker,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        let mut active = self.tracker.active.lock().unwrap_or_else(|e| e.into_inner());
+        *active -= 1;
+        if *active == 0 {
+            self.tracker.idle.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wait_idle_without_calls() {
+        CallTracker::new().wait_idle();
+    }
+
+    #[test]
+    fn test_wait_idle_blocks_until_calls_finish() {
+        let tracker = Arc::new(CallTracker::new());
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let guards_taken = Arc::new(std::sync::Barrier::new(9));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let tracker = Arc::clone(&tracker);
+                let finished = Arc::clone(&finished);
+                let guards_taken = Arc::clone(&guards_taken);
+                std::thread::spawn(move || {
+                    let _call = tracker.enter();
+                    guards_taken.wait();
+                    std::thread::sleep(Duration::from_millis(50));
+                    finished.store(true, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        guards_taken.wait();
+        tracker.wait_idle();
+        assert!(finished.load(Ordering::SeqCst));
+
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Recordings kept with their results when `history.enabled` is set,
+//! listed, read and cleared from the directory in the config file
+
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+use voiceflow_core::{Config, History};
+
+use crate::error::{clear_last_error, set_last_error, set_last_error_from};
+use crate::{str_arg, VoiceFlowErrorCode};
+
+fn history() -> anyhow::Result<History> {
+    History::for_config(&Config::load(None).unwrap_or_default())
+}
+
+pub(crate) fn json_string(json: serde_json::Result<String>) -> *mut c_char {
+    match json {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// List the history entries as a JSON array, newest first
+///
+/// At most limit entries (0 for all), each with "id", "created_at_ms"
+/// (milliseconds since the Unix epoch), "duration_ms", "preview" (the start
+/// of the formatted text) and "bytes" (recording included). An empty array
+/// when there are none. Returns null if the history directory can't be read
+/// (see voiceflow_last_error_message). Free the string with
+/// voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_history_list(limit: usize) -> *mut c_char {
+    clear_last_error();
+    match history().and_then(|history| history.list(limit)) {
+        Ok(summaries) => json_string(serde_json::to_string(&summaries)),
+        Err(e) => {
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Get a history entry as a JSON object
+///
+/// The object has "id", "created_at_ms", "duration_ms", "context",
+/// "raw_transcript", "formatted_text", "language", "no_speech",
+/// "was_fallback", "formatting_error", "total_ms", "llm", "audio_path" (the
+/// recording, 16kHz mono WAV) and "bytes". Returns null for an unknown id
+/// (VF_ERR_INVALID_ARGUMENT) or an entry that can't be read (see
+/// voiceflow_last_error_message). Free the string with
+/// voiceflow_free_string.
+///
+/// # Safety
+/// id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_history_get(id: *const c_char) -> *mut c_char {
+    clear_last_error();
+    let Some(id) = str_arg(id, "id") else {
+        return ptr::null_mut();
+    };
+    match history().and_then(|history| history.get(id)) {
+        Ok(Some(entry)) => json_string(serde_json::to_string(&entry)),
+        Ok(None) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, format!("No history entry {:?}", id));
+            ptr::null_mut()
+        }
+        Err(e) => {
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Remove every history entry
+///
+/// Returns false if an entry can't be removed (see
+/// voiceflow_last_error_message).
+#[no_mangle]
+pub extern "C" fn voiceflow_history_clear() -> bool {
+    clear_last_error();
+    match history().and_then(|history| history.clear()) {
+        Ok(removed) => {
+            tracing::info!("Cleared {} history entries", removed);
+            true
+        }
+        Err(e) => {
+            set_last_error_from(&e);
+            false
+        }
+    }
+}
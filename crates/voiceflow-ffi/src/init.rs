@@ -0,0 +1,134 @@
+//! Pipeline initialization with progress reporting
+
+use std::ffi::{c_char, c_void};
+
+use voiceflow_core::{Config, InitProgress, InitStage};
+
+use crate::error::{clear_last_error, set_last_error};
+use crate::worker::UserData;
+use crate::{init_handle, new_handle, str_arg, VoiceFlowErrorCode, VoiceFlowHandle};
+
+/// Initialization stage reported to the progress callback
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowInitStage {
+    VF_INIT_LOADING_CONFIG = 0,
+    VF_INIT_LOADING_STT_ENCODER = 1,
+    /// Moonshine only; whisper.cpp loads the whole model as the encoder stage
+    VF_INIT_LOADING_STT_DECODER = 2,
+    VF_INIT_LOADING_LLM = 3,
+    VF_INIT_WARMING_UP = 4,
+    VF_INIT_READY = 5,
+}
+
+impl From<InitStage> for VoiceFlowInitStage {
+    fn from(stage: InitStage) -> Self {
+        match stage {
+            InitStage::LoadingConfig => Self::VF_INIT_LOADING_CONFIG,
+            InitStage::LoadingSttEncoder => Self::VF_INIT_LOADING_STT_ENCODER,
+            InitStage::LoadingSttDecoder => Self::VF_INIT_LOADING_STT_DECODER,
+            InitStage::LoadingLlm => Self::VF_INIT_LOADING_LLM,
+            InitStage::WarmingUp => Self::VF_INIT_WARMING_UP,
+            InitStage::Ready => Self::VF_INIT_READY,
+        }
+    }
+}
+
+/// Progress callback for voiceflow_init_with_progress
+///
+/// Called on the initializing thread with the caller's user_data, the stage
+/// that is starting and the overall progress (0-100). The last call is
+/// VF_INIT_READY at 100, unless initialization fails.
+pub type VoiceFlowInitProgressCallback =
+    extern "C" fn(user_data: *mut c_void, stage: VoiceFlowInitStage, percent: u32);
+
+/// Forwards core progress to the C callback
+struct CallbackProgress {
+    callback: VoiceFlowInitProgressCallback,
+    user_data: UserData,
+}
+
+impl InitProgress for CallbackProgress {
+    fn report(&self, stage: InitStage) {
+        tracing::debug!("Init stage: {:?}", stage);
+        (self.callback)(self.user_data.0, stage.into(), stage.percent() as u32);
+    }
+}
+
+/// Initialize the VoiceFlow pipeline, reporting each loading stage
+///
+/// Unlike voiceflow_init, the LLM is loaded and the STT engine warmed up
+/// before returning, so the first request doesn't pay for them. Blocks like
+/// voiceflow_init: call it off the main thread. Returns null on error (see
+/// voiceflow_last_error_message).
+///
+/// # Safety
+/// - config_path must be a valid null-terminated string or null for default
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_init_with_progress(
+    config_path: *const c_char,
+    progress_callback: Option<VoiceFlowInitProgressCallback>,
+    user_data: *mut c_void,
+) -> *mut VoiceFlowHandle {
+    clear_last_error();
+    let Some(callback) = progress_callback else {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "progress_callback must not be null");
+        return std::ptr::null_mut();
+    };
+
+    let progress = CallbackProgress { callback, user_data: UserData(user_data) };
+    init_handle(config_path, Some(&progress))
+}
+
+/// Initialize the VoiceFlow pipeline from a JSON config, without reading or
+/// writing the config file
+///
+/// For apps that keep their own settings store. The JSON object has the
+/// format of voiceflow_config_json; fields it leaves out take their
+/// defaults. Change settings later with voiceflow_update_config_json. Blocks
+/// like voiceflow_init. Returns null for invalid JSON, unknown fields or
+/// invalid values (see voiceflow_last_error_message).
+///
+/// # Safety
+/// json_object must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_init_with_config_json(json_object: *const c_char) -> *mut VoiceFlowHandle {
+    clear_last_error();
+    let Some(json) = str_arg(json_object, "json_object") else {
+        return std::ptr::null_mut();
+    };
+    new_handle(|| Config::from_json(json), None)
+}
+
+/// Initialize the VoiceFlow pipeline with a profile from the config file
+///
+/// Like voiceflow_init, with the settings of profile_name applied over the
+/// config file (environment overrides still come last). Switch a running
+/// handle to another profile with voiceflow_profile_activate. Returns null
+/// for an unknown profile or invalid settings (see
+/// voiceflow_last_error_message).
+///
+/// # Safety
+/// - config_path must be a valid null-terminated string or null for default
+/// - profile_name must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_init_with_profile(
+    config_path: *const c_char,
+    profile_name: *const c_char,
+) -> *mut VoiceFlowHandle {
+    clear_last_error();
+    let config_path = if config_path.is_null() {
+        None
+    } else {
+        match str_arg(config_path, "config_path") {
+            Some(path) => Some(path),
+            None => return std::ptr::null_mut(),
+        }
+    };
+    let Some(name) = str_arg(profile_name, "profile_name") else {
+        return std::ptr::null_mut();
+    };
+    new_handle(|| Config::load_profile(config_path, name), None)
+}
@@ -0,0 +1,154 @@
+//! Processing with the whole result returned as one JSON document, for
+//! callers that would rather decode JSON than read VoiceFlowResult
+
+use std::ffi::{c_char, c_float, CString};
+use std::ptr;
+
+use serde_json::{json, Map, Value};
+use voiceflow_core::{
+    FormattingMode, FormattingPreset, PipelineResult, ProcessOptions, ProcessProfile, SttTask, RESULT_SCHEMA_VERSION,
+};
+
+use crate::error::{classify, clear_last_error, set_last_error, set_last_error_from};
+use crate::panic_report::caught_panic;
+use crate::{invalid_handle, lock_pipeline, merge_llm_options, str_arg, VoiceFlowErrorCode, VoiceFlowHandle};
+
+/// Process audio samples and return the result as JSON
+///
+/// `options_json` is null or a JSON object with any of "context",
+/// "formatting" ("full", "punctuation-only" or "none"), "preset" (an id from
+/// voiceflow_preset_info), "language", "task" ("transcribe" or "translate"),
+/// "stt_context" (names, jargon or the topic of the recording, to help
+/// recognize them), "voice_commands" (false to keep "comma" and the like as
+/// words), "app_id" (bundle id of the app the text goes into, applying its
+/// override from voiceflow_set_app_override), "profile" ("auto",
+/// "dictation" or "command", the fast path for short voice commands) and
+/// "llm_options" (keys to change, as for voiceflow_set_llm_options).
+///
+/// Always returns an object with "schema_version" (raised when a field is
+/// renamed, removed or changes type) and "success". On success, "result"
+/// holds every field of the result: "raw_transcript", "formatted_text",
+/// "timings", "prosody_hints", "word_timestamps", "confidence",
+/// "alternatives" (other readings of the audio, each with its "text" and
+/// "confidence", when stt_decode.alternatives is above 1),
+/// "no_speech_probability", "no_speech", "language", "original_transcript",
+/// "raw_llm_output", "was_fallback", "formatting_error" (why LLM
+/// formatting failed when the raw transcript was returned instead),
+/// "prompt_truncation" (what was cut to fit the LLM's context window:
+/// "history_truncated", "context_truncated" and "transcript_chunks"),
+/// "format_cache_hit", "clipped_percent", "clipping", "repaired_samples",
+/// "filtered_segments" (text removed as made up by the STT engine, each
+/// with its "text" and "reason":
+/// "repetition" or "known_phrase"), "scratch_previous" and "segments"
+/// (the transcript split at long pauses and speaker turns, each with its
+/// "start_ms", "end_ms", "raw_text", "formatted_text", "confidence" and
+/// "speaker", numbered from 0 when diarization is enabled) and "fast_path"
+/// (taken as a short command), with null for unset values. On failure,
+/// "error" holds "code" (a VoiceFlowErrorCode) and "message", which are
+/// also available from voiceflow_last_error_code/_message. Free the string
+/// with voiceflow_free_string.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - options_json must be null or a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process_json(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    options_json: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    if invalid_handle(handle) || audio_data.is_null() {
+        return invalid_argument("Invalid handle or audio data");
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+
+    let options = if options_json.is_null() {
+        Map::new()
+    } else {
+        match str_arg(options_json, "options_json").map(serde_json::from_str::<Map<String, Value>>) {
+            Some(Ok(options)) => options,
+            Some(Err(e)) => return invalid_argument(&format!("Invalid options JSON: {}", e)),
+            None => return invalid_argument("options_json is not valid UTF-8"),
+        }
+    };
+    let (context, options) = match parse_options(handle, options) {
+        Ok(parsed) => parsed,
+        Err(e) => return invalid_argument(&format!("Invalid options JSON: {:#}", e)),
+    };
+
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let cancel = handle.cancel.begin();
+        let mut pipeline = lock_pipeline(&handle.pipeline);
+        let options = ProcessOptions { cancel: cancel.clone(), ..options };
+        pipeline.process_with_options(audio, context.as_deref(), &options).map_err(anyhow::Error::from)
+    }));
+    let document = match outcome {
+        Ok(Ok(result)) => success(&result),
+        Ok(Err(e)) => {
+            tracing::error!("Pipeline processing failed: {:#}", e);
+            set_last_error_from(&e);
+            failure(classify(&e), &format!("{:#}", e))
+        }
+        Err(e) => {
+            let msg = format!("Internal error: {}", caught_panic(e));
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, msg.as_str());
+            failure(VoiceFlowErrorCode::VF_ERR_PANIC, &msg)
+        }
+    };
+    CString::new(document.to_string()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
+
+/// Read the call options, returning the context separately
+fn parse_options(
+    handle: &VoiceFlowHandle,
+    options: Map<String, Value>,
+) -> anyhow::Result<(Option<String>, ProcessOptions)> {
+    let mut context = None;
+    let mut process = ProcessOptions::default();
+    for (key, value) in options {
+        match key.as_str() {
+            "context" => context = serde_json::from_value(value)?,
+            "formatting" => process.formatting = serde_json::from_value::<FormattingMode>(value)?,
+            "preset" => {
+                let id: String = serde_json::from_value(value)?;
+                let preset = FormattingPreset::from_id(&id);
+                process.preset = Some(preset.ok_or_else(|| anyhow::anyhow!("unknown preset id {:?}", id))?);
+            }
+            "language" => process.language = serde_json::from_value(value)?,
+            "task" => process.task = serde_json::from_value::<Option<SttTask>>(value)?,
+            "stt_context" => process.stt_context = serde_json::from_value(value)?,
+            "voice_commands" => process.voice_commands = serde_json::from_value(value)?,
+            "app_id" => process.app_id = serde_json::from_value(value)?,
+            "profile" => process.profile = serde_json::from_value::<ProcessProfile>(value)?,
+            "llm_options" => {
+                let base = lock_pipeline(&handle.pipeline).config().llm_options.clone();
+                process.llm_options = Some(merge_llm_options(&base, &value.to_string())?);
+            }
+            _ => anyhow::bail!("unknown key {:?}", key),
+        }
+    }
+    Ok((context, process))
+}
+
+fn success(result: &PipelineResult) -> Value {
+    json!({ "schema_version": RESULT_SCHEMA_VERSION, "success": true, "result": result })
+}
+
+fn failure(code: VoiceFlowErrorCode, message: &str) -> Value {
+    json!({
+        "schema_version": RESULT_SCHEMA_VERSION,
+        "success": false,
+        "error": { "code": code as i32, "message": message },
+    })
+}
+
+fn invalid_argument(message: &str) -> *mut c_char {
+    set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, message);
+    let document = failure(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, message);
+    CString::new(document.to_string()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
@@ -0,0 +1,121 @@
+//! Corrections the user made to dictated text, reported for the pipeline
+//! to learn from when `learning.enabled` is set, and listed and cleared
+//! from the file in the config
+
+use std::ffi::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use voiceflow_core::{Config, Corrections};
+
+use crate::error::{clear_last_error, set_last_error, set_last_error_from};
+use crate::history::json_string;
+use crate::{invalid_handle, lock_pipeline, str_arg, VoiceFlowErrorCode, VoiceFlowHandle};
+
+fn corrections() -> anyhow::Result<Corrections> {
+    Corrections::for_config(&Config::load(None).unwrap_or_default())
+}
+
+/// Report that the user corrected original, text a request returned, to
+/// corrected
+///
+/// Only the words that changed are counted ("cooper netties" ->
+/// "Kubernetes"), and only short plain-text changes: nothing longer than a
+/// vocabulary term or with regex characters. Once a correction has been
+/// reported learning.min_reports times, and in learning.min_agreement of
+/// the reports of the same words, it is added in the background to the
+/// vocabulary and the replacement rules of the handle and of the config
+/// file, up to learning.max_learned corrections in all. Waits for a
+/// running request to finish. Returns true when the report was taken, even
+/// if it held nothing to learn, and false when learning.enabled is off
+/// (VF_ERR_CONFIG) or the corrections file can't be written (see
+/// voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - original and corrected must be valid null-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_report_correction(
+    handle: *mut VoiceFlowHandle,
+    original: *const c_char,
+    corrected: *const c_char,
+) -> bool {
+    clear_last_error();
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return false;
+    }
+    let (Some(original), Some(corrected)) = (str_arg(original, "original"), str_arg(corrected, "corrected")) else {
+        return false;
+    };
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+
+    let reported = lock_pipeline(&handle.pipeline).report_correction(original, corrected);
+    match reported {
+        Ok(Some(correction)) => {
+            tracing::debug!(
+                "Correction {:?} -> {:?} reported {} time(s)",
+                correction.original,
+                correction.corrected,
+                correction.count
+            );
+            let pipeline = Arc::clone(&handle.pipeline);
+            let spawned = std::thread::Builder::new().name("voiceflow-learning".to_string()).spawn(move || {
+                if let Err(e) = lock_pipeline(&pipeline).learn_corrections(None) {
+                    tracing::warn!("Failed to learn from the reported corrections: {:#}", e);
+                }
+            });
+            if let Err(e) = spawned {
+                tracing::warn!("Failed to start learning from the reported corrections: {}", e);
+            }
+            true
+        }
+        Ok(None) => true,
+        Err(e) => {
+            set_last_error_from(&e);
+            false
+        }
+    }
+}
+
+/// List the reported corrections as a JSON array, the most reported first
+///
+/// Each correction has "original" and "corrected" (the words that
+/// changed), "count" (times reported), "last_reported_ms" (milliseconds
+/// since the Unix epoch) and "promoted" (added to the vocabulary and the
+/// replacement rules). An empty array when there are none. Returns null if
+/// the corrections file can't be read (see voiceflow_last_error_message).
+/// Free the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_corrections_list() -> *mut c_char {
+    clear_last_error();
+    match corrections().and_then(|corrections| corrections.list()) {
+        Ok(list) => json_string(serde_json::to_string(&list)),
+        Err(e) => {
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Forget every reported correction
+///
+/// Corrections already added to the vocabulary and the replacement rules
+/// stay there; remove them with voiceflow_set_vocabulary and
+/// voiceflow_set_replacements. Returns false if the corrections file can't
+/// be removed (see voiceflow_last_error_message).
+#[no_mangle]
+pub extern "C" fn voiceflow_corrections_clear() -> bool {
+    clear_last_error();
+    match corrections().and_then(|corrections| corrections.clear()) {
+        Ok(removed) => {
+            tracing::info!("Cleared {} reported corrections", removed);
+            true
+        }
+        Err(e) => {
+            set_last_error_from(&e);
+            false
+        }
+    }
+}
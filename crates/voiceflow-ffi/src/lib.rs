@@ -3,33 +3,401 @@
 //! Build: cargo build --release -p voiceflow-ffi
 //! This generates a dylib/staticlib that can be linked from Swift
 
-use std::ffi::{c_char, c_float, CStr, CString};
+use std::borrow::Cow;
+use std::ffi::{c_char, c_float, c_void, CStr, CString};
 use std::ptr;
-use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use voiceflow_core::audio::{i16_to_f32, AudioInput};
+use voiceflow_core::config::{LlmOptions, ModelPrecision};
+use voiceflow_core::downloads::DownloadableModel;
+use voiceflow_core::models::storage;
+use voiceflow_core::transcribe::WordTimestamp;
+use voiceflow_core::{
+    CancelToken, Config, FormattingMode, FormattingPreset, IdleUnloader, InitProgress, InitStage, Pipeline,
+    PipelineError, PipelineResult, ProcessOptions, RecoveryConfig, SttTask, Timings, TokenSink,
+};
+
+mod batch;
+mod build_info;
+mod cancel;
+mod download;
+mod error;
+mod events;
+mod guard;
+mod history;
+mod init;
+mod json;
+mod learning;
+mod live;
+mod logging;
+mod memory;
+mod models_dir;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod panic_report;
+mod profiles;
+mod progress;
+mod result_handle;
+mod session;
+mod stream;
+#[cfg(feature = "stub-pipeline")]
+mod stub;
+mod subtitles;
+mod tokens;
+mod two_phase;
+mod worker;
+
+pub use batch::VoiceFlowBatchProgressCallback;
+pub use download::{VoiceFlowDownloadCallback, VoiceFlowDownloadStatus};
+pub use error::VoiceFlowErrorCode;
+pub use events::VoiceFlowEventCallback;
+pub use init::{VoiceFlowInitProgressCallback, VoiceFlowInitStage};
+pub use logging::{VoiceFlowLogCallback, VoiceFlowLogLevel};
+pub use models_dir::VoiceFlowMigrateProgressCallback;
+pub use panic_report::VoiceFlowPanicCallback;
+pub use progress::{VoiceFlowProcessStage, VoiceFlowProgressCallback};
+pub use result_handle::{VoiceFlowResultHandle, VoiceFlowSegment, VoiceFlowTimingKind};
+pub use session::VoiceFlowSession;
+pub use stream::{VoiceFlowEndpointingOptions, VoiceFlowFinalCallback, VoiceFlowPartialCallback};
+pub use tokens::VoiceFlowTokenCallback;
+pub use two_phase::VoiceFlowRawCallback;
+pub use worker::VoiceFlowCompletionCallback;
+
+use error::{clear_last_error, set_last_error, set_last_error_from};
+use panic_report::caught_panic;
+use guard::CallTracker;
+use stream::StreamState;
+use cancel::InFlight;
+use worker::{Job, LazyWorker};
+
+// The handle is shared across threads by the host app
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<VoiceFlowHandle>;
+};
 
-use voiceflow_core::{Config, Pipeline};
+/// Opaque handle to the VoiceFlow pipeline
+///
+/// The handle is internally synchronized and may be used from any thread.
+/// Calls that need the pipeline while another call is using it block until
+/// it is free; voiceflow_cancel never blocks.
+pub struct VoiceFlowHandle {
+    pipeline: Arc<Mutex<Pipeline>>,
+    /// Calls currently using the handle, waited on by voiceflow_destroy
+    calls: CallTracker,
+    /// Tokens of the requests in flight, signalled by voiceflow_cancel
+    cancel: Arc<InFlight>,
+    /// Active streaming session, between voiceflow_stream_start and _finish
+    stream: Mutex<Option<StreamState>>,
+    /// Background worker for voiceflow_process_async
+    worker: LazyWorker,
+    /// Cleared while a model is being reloaded
+    ready: AtomicBool,
+    /// Unloads models after `idle_unload_seconds`; stopped when the handle
+    /// is destroyed
+    _idle: IdleUnloader,
+}
+
+impl VoiceFlowHandle {
+    fn new(pipeline: Pipeline) -> Self {
+        let pipeline = Arc::new(Mutex::new(pipeline));
+        let idle = IdleUnloader::spawn(Arc::clone(&pipeline));
+        Self {
+            pipeline,
+            calls: CallTracker::new(),
+            cancel: InFlight::new(),
+            stream: Mutex::new(None),
+            worker: LazyWorker::new(),
+            ready: AtomicBool::new(true),
+            _idle: idle,
+        }
+    }
+
+    /// Hand the handle to the caller, recording it as live
+    fn into_raw(self) -> *mut Self {
+        let handle = Box::into_raw(Box::new(self));
+        live::HANDLES.insert(handle);
+        handle
+    }
+
+    /// Queue a request on the worker thread, returning its request id
+    fn submit(
+        &self,
+        audio: Vec<f32>,
+        context: Option<String>,
+        user_data: *mut c_void,
+        callback: VoiceFlowCompletionCallback,
+    ) -> std::io::Result<u64> {
+        self.submit_job(|request_id| Job::new(request_id, audio, context, user_data, callback))
+    }
 
-/// Write debug log to file (since macOS GUI apps don't have stderr)
-fn log_debug(msg: &str) {
-    if let Ok(mut file) = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("/tmp/voiceflow_debug.log")
-    {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let _ = writeln!(file, "[{}] {}", timestamp, msg);
+    /// Queue the job `job` makes for the next request id on the worker
+    /// thread, returning the id
+    pub(crate) fn submit_job(&self, job: impl FnOnce(u64) -> Job) -> std::io::Result<u64> {
+        self.worker.submit_job(&self.pipeline, &self.cancel, job)
     }
 }
 
-/// Opaque handle to the VoiceFlow pipeline
-pub struct VoiceFlowHandle {
-    pipeline: Pipeline,
+/// Lock a pipeline, recovering from a lock poisoned by a caught panic
+pub(crate) fn lock_pipeline(pipeline: &Mutex<Pipeline>) -> MutexGuard<'_, Pipeline> {
+    pipeline.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run the pipeline and convert the outcome into an FFI result
+///
+/// The run uses `cancel` in place of `options.cancel`.
+pub(crate) fn process_audio(
+    pipeline: &Mutex<Pipeline>,
+    cancel: &CancelToken,
+    audio: &[f32],
+    context: Option<&str>,
+    options: ProcessOptions,
+) -> VoiceFlowResult {
+    process_audio_streaming(pipeline, cancel, audio, context, options, None)
+}
+
+/// Same as `process_audio`, streaming the LLM output to `sink`
+pub(crate) fn process_audio_streaming(
+    pipeline: &Mutex<Pipeline>,
+    cancel: &CancelToken,
+    audio: &[f32],
+    context: Option<&str>,
+    options: ProcessOptions,
+    sink: Option<&mut dyn TokenSink>,
+) -> VoiceFlowResult {
+    catch_panic_result(|| pipeline_result(run_pipeline(pipeline, cancel, audio, context, options, sink)))
+}
+
+/// Run the pipeline with `cancel` in place of `options.cancel`
+pub(crate) fn run_pipeline(
+    pipeline: &Mutex<Pipeline>,
+    cancel: &CancelToken,
+    audio: &[f32],
+    context: Option<&str>,
+    options: ProcessOptions,
+    sink: Option<&mut dyn TokenSink>,
+) -> anyhow::Result<PipelineResult> {
+    // Log audio stats
+    let audio_duration = audio.len() as f32 / 16000.0;
+    let max_val = audio.iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+    tracing::debug!("Audio duration: {:.2}s, max amplitude: {:.4}", audio_duration, max_val);
+
+    tracing::debug!("Calling pipeline.process()...");
+    let mut pipeline = lock_pipeline(pipeline);
+    let options = ProcessOptions {
+        cancel: cancel.clone(),
+        ..options
+    };
+    Ok(pipeline.process_streaming(audio, context, &options, sink)?)
+}
+
+/// Run `f`, turning a panic into a failed result
+///
+/// Panics are caught here so they never unwind across the FFI boundary,
+/// whichever thread the request runs on.
+pub(crate) fn catch_panic_result(f: impl FnOnce() -> VoiceFlowResult) -> VoiceFlowResult {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(vf_result) => vf_result,
+        Err(e) => {
+            let msg = caught_panic(e);
+            tracing::error!("PANIC caught in voiceflow_process: {}", msg);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+            error_result(&format!("Internal error: {}", msg))
+        }
+    }
+}
+
+/// Convert a pipeline outcome into an FFI result, recording any error
+pub(crate) fn pipeline_result(outcome: anyhow::Result<PipelineResult>) -> VoiceFlowResult {
+    match outcome {
+        Ok(mut result) => {
+            tracing::debug!("Success! Raw transcript: '{}'", result.raw_transcript);
+            tracing::debug!("Formatted text: '{}'", result.formatted_text);
+            sanitize_result(&mut result);
+            let (words, word_count) = word_timings_to_ffi(&result.word_timestamps);
+            VoiceFlowResult {
+                success: true,
+                formatted_text: c_string(&result.formatted_text).into_raw(),
+                raw_transcript: c_string(&result.raw_transcript).into_raw(),
+                error_message: ptr::null_mut(),
+                transcription_ms: result.timings.transcription_ms,
+                llm_ms: result.timings.llm_formatting_ms,
+                total_ms: result.timings.total_ms,
+                trimmed_ms: result.timings.trimmed_ms,
+                words,
+                word_count,
+                confidence: result.confidence,
+                no_speech_probability: result.no_speech_probability,
+                no_speech: result.no_speech,
+                original_transcript: result
+                    .original_transcript
+                    .map_or(ptr::null_mut(), |text| c_string(&text).into_raw()),
+                detected_language: language_code(result.language.as_deref()),
+                timings: (&result.timings).into(),
+                raw_llm_output: result
+                    .raw_llm_output
+                    .map_or(ptr::null_mut(), |text| c_string(&text).into_raw()),
+                was_fallback: result.was_fallback,
+                format_cache_hit: result.format_cache_hit,
+                clipped_percent: result.clipped_percent,
+                clipping: result.clipping,
+                repaired_samples: result.repaired_samples,
+                scratch_previous: result.scratch_previous,
+                formatting_error: result
+                    .formatting_error
+                    .map_or(ptr::null_mut(), |text| c_string(&text).into_raw()),
+            }
+            .tracked()
+        },
+        Err(e) => {
+            tracing::error!("Pipeline processing failed: {:#}", e);
+            set_last_error_from(&e);
+            let mut vf_result = error_result(&format!("{:#}", e));
+            let cause = e.downcast_ref::<PipelineError>().map(PipelineError::without_context);
+            if let Some(PipelineError::Cancelled { timings }) = cause {
+                vf_result.transcription_ms = timings.transcription_ms;
+                vf_result.llm_ms = timings.llm_formatting_ms;
+                vf_result.total_ms = timings.total_ms;
+                vf_result.timings = VoiceFlowTimings::from(&**timings);
+            }
+            vf_result
+        },
+    }
+}
+
+/// Text as a C string, without the interior NULs C can't represent
+pub(crate) fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+/// Prepare a result for C strings
+///
+/// A NUL in the formatted text means the LLM output is garbled, so that
+/// falls back to the raw transcript like other formatting failures, with
+/// `formatting_error` saying why. NULs elsewhere are dropped by `c_string`.
+pub(crate) fn sanitize_result(result: &mut PipelineResult) {
+    if result.formatted_text.contains('\0') {
+        tracing::warn!("Formatted text contains a NUL byte. Falling back to raw transcript.");
+        tracing::debug!("Rejected formatted text: {:?}", result.formatted_text);
+        result.formatted_text = result.raw_transcript.replace('\0', "");
+        result.was_fallback = true;
+        result
+            .formatting_error
+            .get_or_insert_with(|| "Formatted text contained a NUL byte".to_string());
+    }
+    if result.raw_transcript.contains('\0') {
+        tracing::warn!("Raw transcript contains a NUL byte; removing it");
+    }
+}
+
+/// Context string argument, or None when null
+///
+/// Invalid UTF-8 is replaced with U+FFFD rather than dropping the context,
+/// with a warning logged.
+///
+/// # Safety
+/// context must be null or a valid null-terminated string
+pub(crate) unsafe fn context_arg<'a>(context: *const c_char) -> Option<Cow<'a, str>> {
+    if context.is_null() {
+        return None;
+    }
+    let context = CStr::from_ptr(context).to_string_lossy();
+    if matches!(context, Cow::Owned(_)) {
+        tracing::warn!("Context is not valid UTF-8; invalid sequences replaced with U+FFFD");
+    }
+    Some(context)
+}
+
+/// Null-terminated language code for a result (empty if unknown or too long)
+fn language_code(language: Option<&str>) -> [c_char; 4] {
+    let mut code = [0; 4];
+    if let Some(language) = language.filter(|l| l.len() < code.len()) {
+        for (dst, src) in code.iter_mut().zip(language.bytes()) {
+            *dst = src as c_char;
+        }
+    }
+    code
+}
+
+/// Allocate the word timings array for a result
+fn word_timings_to_ffi(words: &[WordTimestamp]) -> (*mut VoiceFlowWordTiming, usize) {
+    if words.is_empty() {
+        return (ptr::null_mut(), 0);
+    }
+    let timings: Box<[VoiceFlowWordTiming]> = words
+        .iter()
+        .map(|w| VoiceFlowWordTiming {
+            word: c_string(&w.word).into_raw(),
+            start_ms: w.start_ms.max(0) as u64,
+            end_ms: w.end_ms.max(0) as u64,
+            confidence: w.probability,
+        })
+        .collect();
+    let count = timings.len();
+    (Box::into_raw(timings) as *mut VoiceFlowWordTiming, count)
+}
+
+/// Timing of one word of the raw transcript
+#[repr(C)]
+pub struct VoiceFlowWordTiming {
+    pub word: *mut c_char,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// 0.0 - 1.0; 0.0 when the timing is estimated (Moonshine)
+    pub confidence: c_float,
+}
+
+/// Per-stage timing breakdown of a result
+///
+/// Stages that didn't run are 0. Whisper can't split encoding from
+/// decoding: stt_encode_ms only covers language detection there.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VoiceFlowTimings {
+    /// Downmixing and resampling to 16kHz mono
+    pub audio_prep_ms: u64,
+    pub vad_ms: u64,
+    pub stt_encode_ms: u64,
+    pub stt_decode_ms: u64,
+    /// LLM prompt processing, until the first token
+    pub llm_prefill_ms: u64,
+    pub llm_generate_ms: u64,
+    pub llm_tokens_generated: u32,
+    /// LLM generation speed after the first token
+    pub tokens_per_second: c_float,
+    /// Tokens spent in <think> blocks, included in llm_tokens_generated
+    pub llm_thinking_tokens: u32,
+}
+
+impl From<&Timings> for VoiceFlowTimings {
+    fn from(timings: &Timings) -> Self {
+        Self {
+            audio_prep_ms: timings.audio_prep_ms,
+            vad_ms: timings.vad_ms,
+            stt_encode_ms: timings.stt_encode_ms,
+            stt_decode_ms: timings.stt_decode_ms,
+            llm_prefill_ms: timings.llm_prefill_ms,
+            llm_generate_ms: timings.llm_generate_ms,
+            llm_tokens_generated: timings.llm_tokens_generated,
+            tokens_per_second: timings.tokens_per_second,
+            llm_thinking_tokens: timings.llm_thinking_tokens,
+        }
+    }
 }
 
 /// Result struct returned to foreign callers
+///
+/// `words` holds `word_count` word timings for the raw transcript (null
+/// when there are none); it is freed by voiceflow_free_result.
+///
+/// When `no_speech` is set the call succeeded but the audio was judged to
+/// be silence or noise, and both texts are empty.
+///
+/// On success both texts are non-null. No string holds a NUL byte: they
+/// are removed, and formatted text the LLM garbled with one falls back to
+/// the raw transcript, with formatting_error saying so.
 #[repr(C)]
 pub struct VoiceFlowResult {
     pub success: bool,
@@ -39,6 +407,131 @@ pub struct VoiceFlowResult {
     pub transcription_ms: u64,
     pub llm_ms: u64,
     pub total_ms: u64,
+    /// Silence trimmed by VAD before transcription
+    pub trimmed_ms: u64,
+    pub words: *mut VoiceFlowWordTiming,
+    pub word_count: usize,
+    /// STT decoder confidence (0.0 - 1.0)
+    pub confidence: c_float,
+    /// Probability that the audio contains no speech (0.0 - 1.0)
+    pub no_speech_probability: c_float,
+    pub no_speech: bool,
+    /// Transcript in the spoken language when translating with
+    /// keep_original_transcript set, otherwise null
+    pub original_transcript: *mut c_char,
+    /// Language the audio was transcribed in, as a null-terminated ISO
+    /// 639-1 code ("en", "de"; empty if unknown). Detected when the language
+    /// is "auto".
+    pub detected_language: [c_char; 4],
+    /// Breakdown of transcription_ms and llm_ms by stage
+    pub timings: VoiceFlowTimings,
+    /// LLM output before cleanup when `llm_output.keep_raw_output` is set
+    /// in the config, otherwise null
+    pub raw_llm_output: *mut c_char,
+    /// formatted_text is the raw transcript because LLM formatting failed or
+    /// its output strayed too far from the transcript
+    pub was_fallback: bool,
+    /// The LLM output was reused from an earlier identical request, so
+    /// llm_ms is 0 (see voiceflow_clear_cache)
+    pub format_cache_hit: bool,
+    /// Share of the audio's samples at full scale, in percent
+    pub clipped_percent: c_float,
+    /// So much of the audio is clipped that the user should lower the input
+    /// gain (see audio.warn_on_clipping in the config)
+    pub clipping: bool,
+    /// NaN or infinite samples in the audio, transcribed as silence (an
+    /// error instead with audio.strict in the config)
+    pub repaired_samples: usize,
+    /// The dictation opened with "scratch that": remove the text inserted
+    /// for the previous one (sessions forget it themselves)
+    pub scratch_previous: bool,
+    /// Why LLM formatting failed, otherwise null; formatted_text is then the
+    /// raw transcript and success stays true (a failure instead with
+    /// llm.strict in the config)
+    pub formatting_error: *mut c_char,
+}
+
+impl VoiceFlowResult {
+    /// Record the result's allocations as live, for voiceflow_free_result
+    fn tracked(self) -> Self {
+        for allocation in [
+            self.formatted_text.cast::<c_void>(),
+            self.raw_transcript.cast(),
+            self.original_transcript.cast(),
+            self.raw_llm_output.cast(),
+            self.formatting_error.cast(),
+            self.error_message.cast(),
+            self.words.cast(),
+        ] {
+            live::RESULT_ALLOCATIONS.insert(allocation);
+        }
+        self
+    }
+}
+
+/// LLM formatting applied by voiceflow_process_opts
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowFormattingMode {
+    /// Full context-aware formatting (the default)
+    VF_FORMAT_FULL = 0,
+    /// Skip the LLM: formatted_text is the raw transcript and llm_ms is 0
+    VF_FORMAT_NONE = 1,
+    /// Only add punctuation and capitalization, never change words
+    VF_FORMAT_PUNCTUATION_ONLY = 2,
+}
+
+impl From<VoiceFlowFormattingMode> for FormattingMode {
+    fn from(mode: VoiceFlowFormattingMode) -> Self {
+        match mode {
+            VoiceFlowFormattingMode::VF_FORMAT_FULL => FormattingMode::Full,
+            VoiceFlowFormattingMode::VF_FORMAT_NONE => FormattingMode::None,
+            VoiceFlowFormattingMode::VF_FORMAT_PUNCTUATION_ONLY => FormattingMode::PunctuationOnly,
+        }
+    }
+}
+
+/// STT task selected by voiceflow_process_opts
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowSttTask {
+    /// The configured task (the default)
+    VF_TASK_DEFAULT = 0,
+    /// Text in the spoken language
+    VF_TASK_TRANSCRIBE = 1,
+    /// English text whatever the spoken language (Whisper only)
+    VF_TASK_TRANSLATE = 2,
+}
+
+impl From<VoiceFlowSttTask> for Option<SttTask> {
+    fn from(task: VoiceFlowSttTask) -> Self {
+        match task {
+            VoiceFlowSttTask::VF_TASK_DEFAULT => None,
+            VoiceFlowSttTask::VF_TASK_TRANSCRIBE => Some(SttTask::Transcribe),
+            VoiceFlowSttTask::VF_TASK_TRANSLATE => Some(SttTask::Translate),
+        }
+    }
+}
+
+/// Per-call options for voiceflow_process_opts
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceFlowProcessOptions {
+    pub formatting: VoiceFlowFormattingMode,
+    /// Preset id from voiceflow_preset_info, or null for the configured default
+    pub preset: *const c_char,
+    /// ISO 639-1 language code or "auto", or null for the configured language
+    pub language: *const c_char,
+    pub task: VoiceFlowSttTask,
+}
+
+/// Formatting preset info for FFI
+#[repr(C)]
+pub struct PresetInfo {
+    pub id: *mut c_char,
+    pub display_name: *mut c_char,
 }
 
 /// Initialize the VoiceFlow pipeline
@@ -47,64 +540,160 @@ pub struct VoiceFlowResult {
 /// config_path must be a valid null-terminated string or null for default
 #[no_mangle]
 pub unsafe extern "C" fn voiceflow_init(config_path: *const c_char) -> *mut VoiceFlowHandle {
-    log_debug("voiceflow_init called");
+    clear_last_error();
+    init_handle(config_path, None)
+}
 
-    // Wrap everything in catch_unwind to prevent panics from unwinding across FFI boundary
-    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        let config_str = if config_path.is_null() {
-            None
-        } else {
-            match CStr::from_ptr(config_path).to_str() {
-                Ok(s) => Some(s),
-                Err(_) => return ptr::null_mut(),
+/// Load the config and create a handle, reporting progress if given
+///
+/// # Safety
+/// config_path must be a valid null-terminated string or null for default
+pub(crate) unsafe fn init_handle(
+    config_path: *const c_char,
+    progress: Option<&dyn InitProgress>,
+) -> *mut VoiceFlowHandle {
+    let config_str = if config_path.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(config_path).to_str() {
+            Ok(s) => Some(s),
+            Err(_) => {
+                set_last_error(
+                    VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                    "config_path is not valid UTF-8",
+                );
+                return ptr::null_mut();
             }
-        };
+        }
+    };
+    new_handle(|| Config::load(config_str), progress)
+}
+
+/// Create a handle with the config from `load_config`, reporting progress
+/// if given
+pub(crate) fn new_handle(
+    load_config: impl FnOnce() -> anyhow::Result<Config>,
+    progress: Option<&dyn InitProgress>,
+) -> *mut VoiceFlowHandle {
+    logging::install();
+    tracing::debug!("voiceflow_init called");
 
-        let config = match Config::load(config_str) {
+    // Wrap everything in catch_unwind to prevent panics from unwinding across FFI boundary
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if let Some(progress) = progress {
+            progress.report(InitStage::LoadingConfig);
+        }
+        let config = match load_config() {
             Ok(c) => {
-                log_debug(&format!("Config loaded: STT={:?}", c.stt_engine));
+                logging::set_log_file(c.log_file_path().as_deref());
+                events::set_telemetry(&c.telemetry);
+                tracing::info!("Config loaded: STT={:?}", c.stt_engine);
                 c
             },
             Err(e) => {
-                log_debug(&format!("Failed to load config: {}", e));
+                tracing::error!("Failed to load config: {:#}", e);
+                set_last_error_from(&e);
                 return ptr::null_mut();
             }
         };
 
-        log_debug("Creating pipeline (loading ONNX models - this may take a while)...");
-        let pipeline = match Pipeline::new(&config) {
+        tracing::info!("Creating pipeline (loading ONNX models - this may take a while)...");
+        let pipeline = match Pipeline::new_with_progress(&config, RecoveryConfig::default(), progress) {
             Ok(p) => {
-                log_debug("Pipeline created successfully");
+                tracing::info!("Pipeline created successfully");
                 p
             },
             Err(e) => {
-                log_debug(&format!("Failed to create pipeline: {}", e));
+                let e = anyhow::Error::from(e);
+                tracing::error!("Failed to create pipeline: {:#}", e);
+                set_last_error_from(&e);
                 return ptr::null_mut();
             }
         };
 
-        log_debug("voiceflow_init complete - returning handle");
-        Box::into_raw(Box::new(VoiceFlowHandle { pipeline }))
+        tracing::debug!("voiceflow_init complete - returning handle");
+        VoiceFlowHandle::new(pipeline).into_raw()
     }));
 
     match result {
         Ok(ptr) => ptr,
         Err(e) => {
-            let msg = if let Some(s) = e.downcast_ref::<&str>() {
-                s.to_string()
-            } else if let Some(s) = e.downcast_ref::<String>() {
-                s.clone()
-            } else {
-                "Unknown panic".to_string()
-            };
-            log_debug(&format!("PANIC caught in voiceflow_init: {}", msg));
+            let msg = caught_panic(e);
+            tracing::error!("PANIC caught in voiceflow_init: {}", msg);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Check if the handle can take requests, to gate recording in the UI
+///
+/// False for a null handle, while voiceflow_reload_model or
+/// voiceflow_reload_stt_engine is swapping a model, and during
+/// voiceflow_warmup.
+///
+/// # Safety
+/// handle must be null or a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_is_ready(handle: *mut VoiceFlowHandle) -> bool {
+    !invalid_handle(handle) && (*handle).ready.load(Ordering::Acquire)
+}
+
+/// Run a short synthetic inference through the STT engine and the LLM, so
+/// the first request isn't slower than the rest
+///
+/// Loads the LLM if it isn't yet; voiceflow_is_ready is false meanwhile.
+/// Returns the time taken in milliseconds, or -1 on error (see
+/// voiceflow_last_error_message). Set warm_up_on_init in the config to
+/// warm up during voiceflow_init instead.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_warmup(handle: *mut VoiceFlowHandle) -> i64 {
+    clear_last_error();
+    match with_pipeline_unready(handle, "Warm-up", |pipeline| pipeline.warm_up()) {
+        Some(elapsed) => elapsed.as_millis() as i64,
+        None => -1,
+    }
+}
+
+/// Check the models still work: run a bundled recording of about a second
+/// through the whole pipeline and report on each stage, as a JSON object
+///
+/// The object has "passed" (no stage failed), "stages" (in order "audio",
+/// "stt", "llm" and "expected", each with "stage", "status" ("passed",
+/// "failed" or "skipped"), "latency_ms" and "error"), "total_ms",
+/// "stt_engine" (as from voiceflow_active_stt_engine), "stt_provider" (as
+/// from voiceflow_current_stt_provider), "llm", "transcript" and
+/// "formatted_text". A stage fails on an error, when no speech is
+/// recognized, or when formatting fell back to the raw transcript; the
+/// stages after a failed one are skipped. Loads the LLM if it isn't yet;
+/// voiceflow_is_ready is false meanwhile. Meant to run in the background
+/// after an update, to offer a model re-download when it fails. Returns
+/// null for a null handle (see voiceflow_last_error_message). Free the
+/// string with voiceflow_free_string.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_self_test(handle: *mut VoiceFlowHandle) -> *mut c_char {
+    clear_last_error();
+    let report = with_pipeline_unready(handle, "Self-test", |pipeline| Ok(pipeline.self_test()));
+    match report.map(|report| serde_json::to_string(&report)) {
+        Some(Ok(json)) => CString::new(json).map_or(ptr::null_mut(), |s| s.into_raw()),
+        Some(Err(e)) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
             ptr::null_mut()
         }
+        None => ptr::null_mut(),
     }
 }
 
 /// Process audio samples and return formatted text
 ///
+/// The flat result of voiceflow_process2; prefer that in new code.
+///
 /// # Safety
 /// - handle must be a valid pointer from voiceflow_init
 /// - audio_data must point to audio_len floats (16kHz mono PCM)
@@ -116,164 +705,741 @@ pub unsafe extern "C" fn voiceflow_process(
     audio_len: usize,
     context: *const c_char,
 ) -> VoiceFlowResult {
-    log_debug(&format!("voiceflow_process called with {} samples", audio_len));
+    let result = result_handle::voiceflow_process2(handle, audio_data, audio_len, context);
+    live::RESULT_HANDLES.remove(result);
+    Box::from_raw(result).into_result()
+}
+
+/// Process audio samples with per-call options
+///
+/// Same as voiceflow_process; `options` selects how much LLM formatting to
+/// apply for this call only. With VF_FORMAT_NONE the LLM is not loaded or
+/// run.
+///
+/// `context_json` describes where the text goes, as a JSON object with any
+/// of "app_name", "document_kind" (e.g. "email", picking the prompt),
+/// "recipient", "text_before_cursor", "text_after_cursor" and
+/// "user_instructions". With the text before the cursor, the result is
+/// capitalized and punctuated to continue it. Unknown fields are ignored
+/// with a warning; a plain string (e.g. "email") still works, as
+/// user_instructions. Malformed JSON fails with VF_ERR_INVALID_ARGUMENT.
+/// Every function taking a context accepts the same.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - context_json can be null
+/// - options can be null for the defaults (full formatting, configured preset)
+/// - options->preset must be null or a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process_opts(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    context_json: *const c_char,
+    options: *const VoiceFlowProcessOptions,
+) -> VoiceFlowResult {
+    tracing::debug!("voiceflow_process_opts called with {} samples", audio_len);
+    clear_last_error();
 
-    if handle.is_null() || audio_data.is_null() {
-        log_debug("ERROR - Invalid handle or audio data");
+    if invalid_handle(handle) || audio_data.is_null() {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "Invalid handle or audio data",
+        );
         return error_result("Invalid handle or audio data");
     }
 
-    // Store raw pointers for use in closure
-    let handle_ptr = handle;
-    let audio_ptr = audio_data;
-    let context_ptr = context;
-
-    // Wrap in catch_unwind to prevent panics from unwinding across FFI boundary
-    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        let handle = &mut *handle_ptr;
-        let audio = std::slice::from_raw_parts(audio_ptr, audio_len);
-
-        // Log audio stats
-        let audio_duration = audio_len as f32 / 16000.0;
-        let max_val = audio.iter().fold(0.0f32, |a, &b| a.max(b.abs()));
-        log_debug(&format!("Audio duration: {:.2}s, max amplitude: {:.4}", audio_duration, max_val));
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+    let context_str = context_arg(context_json);
+    let process_options = match process_options(options) {
+        Ok(process_options) => process_options,
+        Err(message) => return error_result(message),
+    };
 
-        let context_str = if context_ptr.is_null() {
-            None
-        } else {
-            match CStr::from_ptr(context_ptr).to_str() {
-                Ok(s) => Some(s),
-                Err(_) => None,
-            }
-        };
+    process_audio(&handle.pipeline, &handle.cancel.begin(), audio, context_str.as_deref(), process_options)
+}
 
-        log_debug("Calling pipeline.process()...");
-        match handle.pipeline.process(audio, context_str) {
-            Ok(result) => {
-                log_debug(&format!("Success! Raw transcript: '{}'", result.raw_transcript));
-                log_debug(&format!("Formatted text: '{}'", result.formatted_text));
-                VoiceFlowResult {
-                    success: true,
-                    formatted_text: CString::new(result.formatted_text)
-                        .map(|s| s.into_raw())
-                        .unwrap_or(ptr::null_mut()),
-                    raw_transcript: CString::new(result.raw_transcript)
-                        .map(|s| s.into_raw())
-                        .unwrap_or(ptr::null_mut()),
-                    error_message: ptr::null_mut(),
-                    transcription_ms: result.timings.transcription_ms,
-                    llm_ms: result.timings.llm_formatting_ms,
-                    total_ms: result.timings.total_ms,
+/// Core options for `options` (the defaults when null), or the message of
+/// the failed result after setting the last error
+///
+/// # Safety
+/// options must be null or point to valid options
+pub(crate) unsafe fn process_options(
+    options: *const VoiceFlowProcessOptions,
+) -> Result<ProcessOptions, &'static str> {
+    let mut process_options = ProcessOptions::default();
+    if let Some(options) = options.as_ref() {
+        process_options.formatting = options.formatting.into();
+        process_options.task = options.task.into();
+        if !options.preset.is_null() {
+            let id = str_arg(options.preset, "preset").ok_or("Invalid preset")?;
+            match FormattingPreset::from_id(id) {
+                Some(preset) => process_options.preset = Some(preset),
+                None => {
+                    set_last_error(
+                        VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                        format!("Unknown preset id: {}", id),
+                    );
+                    return Err("Unknown preset id");
                 }
-            },
-            Err(e) => {
-                log_debug(&format!("ERROR - pipeline.process failed: {}", e));
-                error_result(&e.to_string())
-            },
+            }
         }
-    }));
-
-    match result {
-        Ok(vf_result) => vf_result,
-        Err(e) => {
-            let msg = if let Some(s) = e.downcast_ref::<&str>() {
-                s.to_string()
-            } else if let Some(s) = e.downcast_ref::<String>() {
-                s.clone()
-            } else {
-                "Unknown panic".to_string()
-            };
-            log_debug(&format!("PANIC caught in voiceflow_process: {}", msg));
-            error_result(&format!("Internal error: {}", msg))
+        if !options.language.is_null() {
+            let language = str_arg(options.language, "language").ok_or("Invalid language")?;
+            process_options.language = Some(language.to_string());
         }
     }
+    Ok(process_options)
 }
 
-/// Free a VoiceFlowResult's strings
+/// Process audio at any sample rate and return formatted text
+///
+/// Audio is downmixed to mono and resampled to 16kHz internally, so
+/// microphone buffers (typically 44.1kHz or 48kHz) can be passed as-is.
 ///
 /// # Safety
-/// Only call this once per result
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats, interleaved if channels > 1
+/// - channels of 0 is treated as mono
+/// - context can be null
 #[no_mangle]
-pub unsafe extern "C" fn voiceflow_free_result(result: VoiceFlowResult) {
-    if !result.formatted_text.is_null() {
-        let _ = CString::from_raw(result.formatted_text);
-    }
-    if !result.raw_transcript.is_null() {
-        let _ = CString::from_raw(result.raw_transcript);
-    }
-    if !result.error_message.is_null() {
-        let _ = CString::from_raw(result.error_message);
+pub unsafe extern "C" fn voiceflow_process_with_rate(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    sample_rate: u32,
+    channels: u16,
+    context: *const c_char,
+) -> VoiceFlowResult {
+    tracing::debug!(
+        "voiceflow_process_with_rate called with {} samples at {} Hz, {} channel(s)",
+        audio_len, sample_rate, channels
+    );
+    clear_last_error();
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        tracing::error!("Invalid handle or audio data");
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "Invalid handle or audio data",
+        );
+        return error_result("Invalid handle or audio data");
     }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let samples = std::slice::from_raw_parts(audio_data, audio_len);
+    let input = AudioInput::new(samples, sample_rate, channels.max(1));
+    let context_str = context_arg(context);
+
+    let audio = match std::panic::catch_unwind(|| input.to_16khz_mono()) {
+        Ok(Ok(audio)) => audio,
+        Ok(Err(e)) => {
+            let msg = format!("{:#}", e);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, msg.as_str());
+            return error_result(&msg);
+        }
+        Err(e) => {
+            let msg = format!("Internal error: {}", caught_panic(e));
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, msg.as_str());
+            return error_result(&msg);
+        }
+    };
+
+    process_audio(&handle.pipeline, &handle.cancel.begin(), &audio, context_str.as_deref(), ProcessOptions::default())
 }
 
-/// Cleanup and free the handle
+/// Transcribe and format an audio file (WAV, AIFF or CAF, plus M4A/AAC, MP3,
+/// FLAC and Ogg with the compressed-audio feature)
+///
+/// The file is decoded, downmixed and resampled as needed. Unsupported,
+/// corrupt or DRM-protected files fail with VF_ERR_AUDIO and a message such
+/// as "unsupported bit depth 12".
+///
+/// voiceflow_cancel stops it as any request, with VF_ERR_CANCELLED.
 ///
 /// # Safety
-/// Only call this once per handle
+/// - handle must be a valid pointer from voiceflow_init
+/// - path must be a valid null-terminated string
+/// - context can be null
 #[no_mangle]
-pub unsafe extern "C" fn voiceflow_destroy(handle: *mut VoiceFlowHandle) {
-    if !handle.is_null() {
-        let _ = Box::from_raw(handle);
+pub unsafe extern "C" fn voiceflow_process_file(
+    handle: *mut VoiceFlowHandle,
+    path: *const c_char,
+    context: *const c_char,
+) -> VoiceFlowResult {
+    clear_last_error();
+
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle");
+        return error_result("Invalid handle");
     }
+    let path = match str_arg(path, "path") {
+        Some(p) => p,
+        None => return error_result("Invalid path"),
+    };
+    tracing::debug!("voiceflow_process_file called with {}", path);
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let context_str = context_arg(context);
+
+    let cancel = handle.cancel.begin();
+    catch_panic_result(|| {
+        let mut pipeline = lock_pipeline(&handle.pipeline);
+        let result = pipeline.process_file_with_cancel(std::path::Path::new(path), context_str.as_deref(), &cancel);
+        pipeline_result(result.map_err(anyhow::Error::from))
+    })
 }
 
-/// Get the library version
+/// Process 16-bit PCM samples and return formatted text
+///
+/// Same as voiceflow_process, but takes Int16 samples directly (as
+/// delivered by an AVAudioEngine tap) and normalizes them in Rust.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len int16 samples (16kHz mono PCM)
+/// - context can be null
 #[no_mangle]
-pub extern "C" fn voiceflow_version() -> *const c_char {
-    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
-}
-
-fn error_result(msg: &str) -> VoiceFlowResult {
-    VoiceFlowResult {
-        success: false,
-        formatted_text: ptr::null_mut(),
-        raw_transcript: ptr::null_mut(),
-        error_message: CString::new(msg)
-            .map(|s| s.into_raw())
-            .unwrap_or(ptr::null_mut()),
-        transcription_ms: 0,
-        llm_ms: 0,
-        total_ms: 0,
+pub unsafe extern "C" fn voiceflow_process_i16(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const i16,
+    audio_len: usize,
+    context: *const c_char,
+) -> VoiceFlowResult {
+    tracing::debug!("voiceflow_process_i16 called with {} samples", audio_len);
+    clear_last_error();
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        tracing::error!("Invalid handle or audio data");
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "Invalid handle or audio data",
+        );
+        return error_result("Invalid handle or audio data");
     }
-}
 
-/// Model info struct for FFI
-#[repr(C)]
-pub struct ModelInfo {
-    pub id: *mut c_char,
-    pub display_name: *mut c_char,
-    pub filename: *mut c_char,
-    pub size_gb: c_float,
-    pub is_downloaded: bool,
-}
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = i16_to_f32(std::slice::from_raw_parts(audio_data, audio_len));
+    let context_str = context_arg(context);
 
-/// Get the models directory path
-#[no_mangle]
-pub extern "C" fn voiceflow_models_dir() -> *mut c_char {
-    match Config::models_dir() {
-        Ok(path) => CString::new(path.to_string_lossy().to_string())
-            .map(|s| s.into_raw())
-            .unwrap_or(ptr::null_mut()),
-        Err(_) => ptr::null_mut(),
-    }
+    process_audio(&handle.pipeline, &handle.cancel.begin(), &audio, context_str.as_deref(), ProcessOptions::default())
 }
 
-/// Get the number of available models
+/// Process audio samples on a background thread and report the result
+/// through a completion callback
+///
+/// Requests on the same handle are queued and run in submission order, each
+/// transcribed while the LLM formats the one before it. The audio and
+/// context are copied, so the caller may release its buffers as soon as this
+/// returns. The callbacks run one at a time, in the same order, on a thread
+/// of their own, and may call back into the library. The callback owns the
+/// result: free it with voiceflow_free_result.
+///
+/// Returns a non-zero request id that is passed back to the callback, or 0
+/// if the request could not be queued (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - context can be null
+/// - user_data is passed back to the callback untouched
 #[no_mangle]
-pub extern "C" fn voiceflow_model_count() -> usize {
+pub unsafe extern "C" fn voiceflow_process_async(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    context: *const c_char,
+    user_data: *mut c_void,
+    callback: Option<VoiceFlowCompletionCallback>,
+) -> u64 {
+    tracing::debug!("voiceflow_process_async called with {} samples", audio_len);
+    clear_last_error();
+
+    let callback = match callback {
+        Some(cb) => cb,
+        None => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "callback must not be null");
+            return 0;
+        }
+    };
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "Invalid handle or audio data",
+        );
+        return 0;
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = std::slice::from_raw_parts(audio_data, audio_len).to_vec();
+    let context_str = context_arg(context).map(Cow::into_owned);
+
+    match handle.submit(audio, context_str, user_data, callback) {
+        Ok(request_id) => request_id,
+        Err(e) => {
+            tracing::error!("Failed to queue async request: {}", e);
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INTERNAL,
+                format!("Failed to start worker thread: {}", e),
+            );
+            0
+        }
+    }
+}
+
+/// Cancel the request currently being processed on this handle
+///
+/// The running voiceflow_process stops at its next checkpoint and returns a
+/// failed result with VF_ERR_CANCELLED and the timings up to that point, and
+/// so does a call still waiting for the handle. Of the async requests, the
+/// one being formatted and the next one, already being transcribed, are
+/// stopped; those still queued are not affected.
+/// Safe to call from any thread.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_cancel(handle: *mut VoiceFlowHandle) {
+    if invalid_handle(handle) {
+        return;
+    }
+    tracing::debug!("voiceflow_cancel called");
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    handle.cancel.cancel();
+}
+
+/// Reserve an id for a request that voiceflow_cancel_request can cancel on
+/// its own
+///
+/// Pass the id to voiceflow_process2_request, once; a cancel sent in
+/// between still stops that request. Returns 0 for an invalid handle.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_new_request(handle: *mut VoiceFlowHandle) -> u64 {
+    if invalid_handle(handle) {
+        return 0;
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    handle.cancel.reserve()
+}
+
+/// Cancel the request given `request_id` from voiceflow_new_request, as
+/// voiceflow_cancel does, leaving the handle's other requests running
+///
+/// Ids of requests that already returned are ignored. Safe to call from any
+/// thread.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_cancel_request(handle: *mut VoiceFlowHandle, request_id: u64) {
+    if invalid_handle(handle) {
+        return;
+    }
+    tracing::debug!("voiceflow_cancel_request called for request {}", request_id);
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    handle.cancel.cancel_request(request_id);
+}
+
+/// Free a VoiceFlowResult's strings and word timings
+///
+/// Freeing a result twice, or a copy of a freed one, frees nothing and sets
+/// VF_ERR_INVALID_ARGUMENT as the last error.
+///
+/// # Safety
+/// result must come from a voiceflow call returning a VoiceFlowResult
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_free_result(result: VoiceFlowResult) {
+    let mut misused = ptr::null();
+    let mut owned = |allocation: *const c_void| {
+        let was_live = live::RESULT_ALLOCATIONS.remove(allocation);
+        if !was_live && !allocation.is_null() {
+            misused = allocation;
+        }
+        was_live
+    };
+    if owned(result.formatted_text.cast()) {
+        let _ = CString::from_raw(result.formatted_text);
+    }
+    if owned(result.raw_transcript.cast()) {
+        let _ = CString::from_raw(result.raw_transcript);
+    }
+    if owned(result.original_transcript.cast()) {
+        let _ = CString::from_raw(result.original_transcript);
+    }
+    if owned(result.raw_llm_output.cast()) {
+        let _ = CString::from_raw(result.raw_llm_output);
+    }
+    if owned(result.formatting_error.cast()) {
+        let _ = CString::from_raw(result.formatting_error);
+    }
+    if owned(result.error_message.cast()) {
+        let _ = CString::from_raw(result.error_message);
+    }
+    if owned(result.words.cast()) {
+        let words = Box::from_raw(ptr::slice_from_raw_parts_mut(result.words, result.word_count));
+        for word in words.iter() {
+            if !word.word.is_null() {
+                let _ = CString::from_raw(word.word);
+            }
+        }
+    }
+    if !misused.is_null() {
+        live::RESULT_ALLOCATIONS.report(misused);
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "voiceflow_free_result: result was already freed",
+        );
+    }
+}
+
+/// Cleanup and free the handle
+///
+/// Blocks until calls running on other threads have returned, and until
+/// queued asynchronous requests have finished and their callbacks have run.
+///
+/// Calls made with the handle afterwards fail with VF_ERR_INVALID_ARGUMENT,
+/// as with a null handle, and destroying it again does nothing, until a new
+/// handle reuses its address.
+///
+/// # Safety
+/// - No new calls may be started on the handle once this has been called
+/// - Must not be called from a streaming partial callback
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_destroy(handle: *mut VoiceFlowHandle) {
+    if handle.is_null() {
+        return;
+    }
+    if !live::HANDLES.remove(handle) {
+        live::HANDLES.report(handle);
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "voiceflow_destroy: handle was already destroyed",
+        );
+        return;
+    }
+    tracing::debug!("voiceflow_destroy: waiting for in-flight calls");
+    (*handle).calls.wait_idle();
+    let _ = Box::from_raw(handle);
+}
+
+/// Whether `handle` is null, destroyed or not from voiceflow_init
+///
+/// Checked before the handle is dereferenced, so a call after
+/// voiceflow_destroy fails like one with a null handle.
+pub(crate) fn invalid_handle(handle: *const VoiceFlowHandle) -> bool {
+    !live::HANDLES.check(handle)
+}
+
+/// Get the library version
+#[no_mangle]
+pub extern "C" fn voiceflow_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Read a required string argument, recording VF_ERR_INVALID_ARGUMENT on failure
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Option<&'a str> {
+    if ptr.is_null() {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("{} must not be null", name),
+        );
+        return None;
+    }
+    match CStr::from_ptr(ptr).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("{} is not valid UTF-8", name),
+            );
+            None
+        }
+    }
+}
+
+/// Persist a config change, recording the error on failure
+fn save_config(config: &Config) -> bool {
+    match config.save(None) {
+        Ok(()) => true,
+        Err(e) => {
+            set_last_error_from(&e);
+            false
+        }
+    }
+}
+
+/// Swap a model on the handle's pipeline, returning the error code
+unsafe fn reload_pipeline(
+    handle: *mut VoiceFlowHandle,
+    reload: impl FnOnce(&mut Pipeline) -> anyhow::Result<()>,
+) -> VoiceFlowErrorCode {
+    match with_pipeline_unready(handle, "Reload", reload) {
+        Some(()) => VoiceFlowErrorCode::VF_ERR_OK,
+        None => error::voiceflow_last_error_code(),
+    }
+}
+
+/// Run `f` on the handle's pipeline with voiceflow_is_ready false,
+/// recording the error if it fails
+///
+/// Holds the pipeline for the duration, so processing calls wait for `f`
+/// to finish.
+unsafe fn with_pipeline_unready<T>(
+    handle: *mut VoiceFlowHandle,
+    what: &str,
+    f: impl FnOnce(&mut Pipeline) -> anyhow::Result<T>,
+) -> Option<T> {
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return None;
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut pipeline = lock_pipeline(&handle.pipeline);
+        handle.ready.store(false, Ordering::Release);
+        let result = f(&mut pipeline);
+        handle.ready.store(true, Ordering::Release);
+        result
+    }));
+    match result {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(e)) => {
+            tracing::error!("{} failed: {:#}", what, e);
+            set_last_error_from(&e);
+            None
+        }
+        Err(e) => {
+            handle.ready.store(true, Ordering::Release);
+            let msg = caught_panic(e);
+            tracing::error!("PANIC caught during {}: {}", what.to_lowercase(), msg);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+            None
+        }
+    }
+}
+
+fn error_result(msg: &str) -> VoiceFlowResult {
+    VoiceFlowResult {
+        success: false,
+        formatted_text: ptr::null_mut(),
+        raw_transcript: ptr::null_mut(),
+        error_message: c_string(msg).into_raw(),
+        transcription_ms: 0,
+        llm_ms: 0,
+        total_ms: 0,
+        trimmed_ms: 0,
+        words: ptr::null_mut(),
+        word_count: 0,
+        confidence: 0.0,
+        no_speech_probability: 0.0,
+        no_speech: false,
+        original_transcript: ptr::null_mut(),
+        detected_language: [0; 4],
+        timings: VoiceFlowTimings::default(),
+        raw_llm_output: ptr::null_mut(),
+        was_fallback: false,
+        format_cache_hit: false,
+        clipped_percent: 0.0,
+        clipping: false,
+        repaired_samples: 0,
+        scratch_previous: false,
+        formatting_error: ptr::null_mut(),
+    }
+    .tracked()
+}
+
+/// How much of a model is downloaded
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowModelStatus {
+    VF_MODEL_NOT_DOWNLOADED = 0,
+    /// Some of it is downloaded: voiceflow_download_model resumes
+    VF_MODEL_PARTIAL = 1,
+    VF_MODEL_DOWNLOADED = 2,
+    /// A file doesn't have the size it was downloaded with:
+    /// voiceflow_download_model replaces it
+    VF_MODEL_CORRUPT = 3,
+}
+
+impl From<storage::DownloadStatus> for VoiceFlowModelStatus {
+    fn from(status: storage::DownloadStatus) -> Self {
+        match status {
+            storage::DownloadStatus::NotDownloaded => Self::VF_MODEL_NOT_DOWNLOADED,
+            storage::DownloadStatus::Partial => Self::VF_MODEL_PARTIAL,
+            storage::DownloadStatus::Downloaded => Self::VF_MODEL_DOWNLOADED,
+            storage::DownloadStatus::Corrupt => Self::VF_MODEL_CORRUPT,
+        }
+    }
+}
+
+/// Model info struct for FFI
+#[repr(C)]
+pub struct ModelInfo {
+    pub id: *mut c_char,
+    pub display_name: *mut c_char,
+    pub filename: *mut c_char,
+    pub size_gb: c_float,
+    /// Whether status is VF_MODEL_DOWNLOADED
+    pub is_downloaded: bool,
+    /// Bytes on disk, partial downloads included
+    pub downloaded_bytes: u64,
+    /// Size of the complete model (an estimate until it's downloaded)
+    pub expected_bytes: u64,
+    pub status: VoiceFlowModelStatus,
+}
+
+/// Get the models directory path
+#[no_mangle]
+pub extern "C" fn voiceflow_models_dir() -> *mut c_char {
+    clear_last_error();
+    match Config::load(None).unwrap_or_default().models_dir() {
+        Ok(path) => CString::new(path.to_string_lossy().to_string())
+            .map(|s| s.into_raw())
+            .unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Get the total size in bytes of the models directory
+///
+/// Includes every model and any partial downloads. Returns 0 on failure
+/// (see voiceflow_last_error_message).
+#[no_mangle]
+pub extern "C" fn voiceflow_models_disk_usage() -> u64 {
+    clear_last_error();
+    match Config::load(None).unwrap_or_default().models_dir().and_then(|dir| storage::disk_usage(&dir)) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            set_last_error_from(&e);
+            0
+        }
+    }
+}
+
+/// Check a configuration before initializing with it, as a JSON object
+///
+/// Reports whether each model it needs is "downloaded", "missing",
+/// "corrupt" or "remote", the bytes left to download, the estimated memory
+/// and any settings that can't work together, e.g.
+/// `{"ready": false, "components": [{"component": "stt", "id":
+/// "moonshine-base", "status": "missing", "download_bytes": 419430400,
+/// ...}], "download_bytes": 419430400, "memory_bytes": ..., "problems":
+/// ["Moonshine only transcribes English, ..."], "models_dir": "..."}`.
+/// Loads no models and only reads file sizes, so it's quick enough for the
+/// main thread. Returns null if the config file can't be read (see
+/// voiceflow_last_error_message). Free the string with
+/// voiceflow_free_string.
+///
+/// # Safety
+/// config_path must be a valid null-terminated string or null for default
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_preflight(config_path: *const c_char) -> *mut c_char {
+    clear_last_error();
+    let config_path = if config_path.is_null() {
+        None
+    } else {
+        match str_arg(config_path, "config_path") {
+            Some(path) => Some(path),
+            None => return ptr::null_mut(),
+        }
+    };
+    let report = match Config::preflight_file(config_path) {
+        Ok(report) => report,
+        Err(e) => {
+            set_last_error_from(&e);
+            return ptr::null_mut();
+        }
+    };
+    match serde_json::to_string(&report) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Delete a model's files, recording the error on failure
+fn delete_model(model: &DownloadableModel, force: bool) -> bool {
+    let config = Config::load(None).unwrap_or_default();
+    match config.models_dir().and_then(|dir| storage::delete_model(model, &dir, &config, force)) {
+        Ok(_) => true,
+        Err(e) => {
+            set_last_error_from(&e);
+            false
+        }
+    }
+}
+
+/// What of a model is in the models directory (nothing if there is none)
+fn download_state(model: &DownloadableModel, config: &Config) -> storage::DownloadState {
+    match config.models_dir() {
+        Ok(dir) => storage::download_state(model, &dir),
+        Err(_) => storage::DownloadState {
+            status: storage::DownloadStatus::NotDownloaded,
+            downloaded_bytes: 0,
+            expected_bytes: model.download_bytes(),
+        },
+    }
+}
+
+/// Built-in LLM models, followed by the custom model if one is configured
+fn listed_models(config: &Config) -> Vec<voiceflow_core::config::LlmModel> {
     use voiceflow_core::config::LlmModel;
-    LlmModel::all_models().len()
+
+    let mut models: Vec<_> = LlmModel::all_models()
+        .into_iter()
+        .filter(|model| memory::within_budget(&DownloadableModel::Llm(model.clone())))
+        .collect();
+    if let LlmModel::Custom(_) = config.llm_model {
+        models.push(config.llm_model.clone());
+    }
+    models
+}
+
+/// Get the number of available models
+///
+/// Includes the custom model, if one is configured. Models over the memory
+/// budget (see voiceflow_set_memory_budget) are left out.
+#[no_mangle]
+pub extern "C" fn voiceflow_model_count() -> usize {
+    listed_models(&Config::load(None).unwrap_or_default()).len()
 }
 
 /// Get model info by index
 ///
+/// A configured custom model comes last, with id "custom" and its full
+/// path as the filename. status tells an interrupted download, to offer
+/// resuming it with voiceflow_download_model, from a complete one.
+///
 /// # Safety
 /// index must be < voiceflow_model_count()
 #[no_mangle]
 pub unsafe extern "C" fn voiceflow_model_info(index: usize) -> ModelInfo {
     use voiceflow_core::config::LlmModel;
 
-    let models = LlmModel::all_models();
+    let config = Config::load(None).unwrap_or_default();
+    let models = listed_models(&config);
     if index >= models.len() {
         return ModelInfo {
             id: ptr::null_mut(),
@@ -281,14 +1447,31 @@ pub unsafe extern "C" fn voiceflow_model_info(index: usize) -> ModelInfo {
             filename: ptr::null_mut(),
             size_gb: 0.0,
             is_downloaded: false,
+            downloaded_bytes: 0,
+            expected_bytes: 0,
+            status: VoiceFlowModelStatus::VF_MODEL_NOT_DOWNLOADED,
         };
     }
 
     let model = &models[index];
-    let models_dir = Config::models_dir().ok();
-    let is_downloaded = models_dir
-        .map(|dir| dir.join(model.filename()).exists())
-        .unwrap_or(false);
+    let state = match model {
+        // The user's own file, complete if it's there
+        LlmModel::Custom(_) => {
+            let metadata = config.llm_model_path().ok().and_then(|path| std::fs::metadata(path).ok());
+            let bytes = metadata.as_ref().map_or(0, |m| m.len());
+            let status = match metadata {
+                Some(_) => storage::DownloadStatus::Downloaded,
+                None => storage::DownloadStatus::NotDownloaded,
+            };
+            storage::DownloadState { status, downloaded_bytes: bytes, expected_bytes: bytes }
+        }
+        _ => download_state(&DownloadableModel::Llm(model.clone()), &config),
+    };
+
+    let (display_name, size_gb) = match model {
+        LlmModel::Custom(_) => (config.llm_display_name(), state.downloaded_bytes as f32 / 1e9),
+        _ => (model.display_name().to_string(), model.size_gb()),
+    };
 
     let id_str = match model {
         LlmModel::Qwen3_1_7B => "qwen3-1.7b",
@@ -301,10 +1484,13 @@ pub unsafe extern "C" fn voiceflow_model_info(index: usize) -> ModelInfo {
 
     ModelInfo {
         id: CString::new(id_str).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
-        display_name: CString::new(model.display_name()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        display_name: CString::new(display_name).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
         filename: CString::new(model.filename()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
-        size_gb: model.size_gb(),
-        is_downloaded,
+        size_gb,
+        is_downloaded: state.status == storage::DownloadStatus::Downloaded,
+        downloaded_bytes: state.downloaded_bytes,
+        expected_bytes: state.expected_bytes,
+        status: state.status.into(),
     }
 }
 
@@ -337,6 +1523,9 @@ pub unsafe extern "C" fn voiceflow_free_string(s: *mut c_char) {
 }
 
 /// Get the current model ID from config
+///
+/// "custom" for a model set with voiceflow_set_custom_model; its path is
+/// the filename of the last voiceflow_model_info entry.
 #[no_mangle]
 pub extern "C" fn voiceflow_current_model() -> *mut c_char {
     use voiceflow_core::config::LlmModel;
@@ -351,75 +1540,784 @@ pub extern "C" fn voiceflow_current_model() -> *mut c_char {
         LlmModel::Custom(_) => "custom",
     };
 
-    CString::new(id_str).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+    CString::new(id_str).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
+
+/// Set the current model in config (takes effect on restart, or on a
+/// running handle with voiceflow_reload_model)
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_model(model_id: *const c_char) -> bool {
+    use voiceflow_core::config::LlmModel;
+
+    clear_last_error();
+    let id_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let model = match id_str {
+        "qwen3-1.7b" => LlmModel::Qwen3_1_7B,
+        "qwen3-4b" => LlmModel::Qwen3_4B,
+        "smollm3-3b" => LlmModel::SmolLM3_3B,
+        "gemma2-2b" => LlmModel::Gemma2_2B,
+        "phi-2" => LlmModel::Phi2,
+        _ => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown model id: {}", id_str),
+            );
+            return false;
+        }
+    };
+
+    // The custom model's name and chat template don't apply to built-in ones
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.llm_model = model;
+    config.custom_model_name = None;
+    config.chat_template = Default::default();
+    save_config(&config)
+}
+
+/// Use a custom GGUF model file (takes effect on restart, or on a running
+/// handle with voiceflow_reload_model and "custom")
+///
+/// The file must be a GGUF model of an architecture mistral.rs supports
+/// (llama, mistral, phi2, phi3, qwen2, qwen3, smollm3, gemma2, starcoder2);
+/// other files fail with VF_ERR_INVALID_ARGUMENT. display_name may be null
+/// to use the model name in the file's metadata, or else the file name.
+/// chat_template_id is one of "auto" (the template embedded in the file),
+/// "chatml", "llama3", "gemma", "mistral" or "phi3", or null for "auto".
+///
+/// # Safety
+/// - path must be a valid null-terminated string
+/// - display_name and chat_template_id must be valid null-terminated
+///   strings or null
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_custom_model(
+    path: *const c_char,
+    display_name: *const c_char,
+    chat_template_id: *const c_char,
+) -> bool {
+    use voiceflow_core::config::LlmModel;
+    use voiceflow_core::llm::gguf::check_custom_model;
+    use voiceflow_core::llm::ChatTemplate;
+
+    clear_last_error();
+    let path_str = match str_arg(path, "path") {
+        Some(s) => s,
+        None => return false,
+    };
+    let display_name = if display_name.is_null() {
+        None
+    } else {
+        match str_arg(display_name, "display_name") {
+            Some(s) => Some(s.to_string()),
+            None => return false,
+        }
+    };
+    let chat_template = if chat_template_id.is_null() {
+        ChatTemplate::default()
+    } else {
+        let id_str = match str_arg(chat_template_id, "chat_template_id") {
+            Some(s) => s,
+            None => return false,
+        };
+        match ChatTemplate::from_id(id_str) {
+            Some(template) => template,
+            None => {
+                set_last_error(
+                    VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                    format!("Unknown chat template: {}", id_str),
+                );
+                return false;
+            }
+        }
+    };
+
+    let info = match check_custom_model(std::path::Path::new(path_str)) {
+        Ok(info) => info,
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, format!("{:#}", e));
+            return false;
+        }
+    };
+    tracing::info!(
+        "Custom model {}: {} architecture, context length {:?}",
+        path_str,
+        info.architecture,
+        info.context_length
+    );
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.llm_model = LlmModel::Custom(path_str.to_string());
+    config.custom_model_name = display_name.or(info.name);
+    config.chat_template = chat_template;
+    save_config(&config)
+}
+
+/// Switch the handle's LLM without reloading the STT engine
+///
+/// model_id is an id accepted by voiceflow_set_model, or "custom" for the
+/// model set with voiceflow_set_custom_model. Only the handle changes: call
+/// voiceflow_set_model as well to keep the choice across restarts.
+/// Processing calls made during the reload wait for it to finish. On
+/// failure the previous model is reloaded on next use.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_reload_model(
+    handle: *mut VoiceFlowHandle,
+    model_id: *const c_char,
+) -> VoiceFlowErrorCode {
+    use voiceflow_core::config::LlmModel;
+
+    clear_last_error();
+    let id_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+    };
+
+    if id_str == "custom" {
+        let config = Config::load(None).unwrap_or_default();
+        if !matches!(config.llm_model, LlmModel::Custom(_)) {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "No custom model is set");
+            return VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT;
+        }
+        return reload_pipeline(handle, |pipeline| pipeline.reload_llm_from(&config));
+    }
+    match DownloadableModel::from_id(id_str) {
+        Some(DownloadableModel::Llm(model)) => reload_pipeline(handle, |pipeline| pipeline.reload_llm(&model)),
+        _ => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown model id: {}", id_str),
+            );
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT
+        }
+    }
+}
+
+/// Release the handle's LLM, for when the user turns formatting off
+///
+/// The model reloads on the next request that formats, and that request's
+/// VF_TIMING_LLM includes the load. Waits for a running request to finish.
+/// Returns false if the LLM wasn't loaded, or was supplied by the app.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_unload_llm(handle: *mut VoiceFlowHandle) -> bool {
+    clear_last_error();
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return false;
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    lock_pipeline(&handle.pipeline).unload_llm()
+}
+
+/// Forget the formatting results kept for reuse, so the next requests run
+/// the LLM even for a transcript formatted before
+///
+/// The cache holds up to format_cache_size results (config key
+/// "llm.cache_size"). Waits for a running request to finish.
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_clear_cache(handle: *mut VoiceFlowHandle) -> VoiceFlowErrorCode {
+    clear_last_error();
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT;
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    lock_pipeline(&handle.pipeline).clear_format_cache();
+    VoiceFlowErrorCode::VF_ERR_OK
+}
+
+/// Get the HuggingFace download URL for a model
+///
+/// voiceflow_download_model downloads and verifies it instead.
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_model_download_url(model_id: *const c_char) -> *mut c_char {
+    use voiceflow_core::config::LlmModel;
+
+    clear_last_error();
+    let id_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return ptr::null_mut(),
+    };
+
+    let model = match id_str {
+        "qwen3-1.7b" => LlmModel::Qwen3_1_7B,
+        "qwen3-4b" => LlmModel::Qwen3_4B,
+        "smollm3-3b" => LlmModel::SmolLM3_3B,
+        "gemma2-2b" => LlmModel::Gemma2_2B,
+        "phi-2" => LlmModel::Phi2,
+        _ => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown model id: {}", id_str),
+            );
+            return ptr::null_mut();
+        }
+    };
+
+    if let Some(repo) = model.hf_repo() {
+        let url = format!(
+            "https://huggingface.co/{}/resolve/main/{}",
+            repo,
+            model.filename()
+        );
+        CString::new(url).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+    } else {
+        ptr::null_mut()
+    }
+}
+
+/// Delete a downloaded model, including any partial download
+///
+/// model_id is any id accepted by voiceflow_download_model. The configured
+/// model is only deleted if `force` is set; otherwise this fails with
+/// VF_ERR_MODEL_IN_USE. Deleting a model that isn't downloaded succeeds.
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_delete_model(model_id: *const c_char, force: bool) -> bool {
+    clear_last_error();
+    let id_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    match DownloadableModel::from_id(id_str) {
+        Some(model) => delete_model(&model, force),
+        None => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown model id: {}", id_str),
+            );
+            false
+        }
+    }
+}
+
+// =============================================================================
+// Formatting Prompts and Presets
+// =============================================================================
+
+/// Get the custom LLM formatting prompt from config
+///
+/// Returns null when none is set and the built-in prompts are used. Free
+/// the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_get_formatting_prompt() -> *mut c_char {
+    let config = Config::load(None).unwrap_or_default();
+    match config.formatting_prompt {
+        Some(prompt) => CString::new(prompt).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Set the custom LLM formatting prompt in config (requires restart to take effect)
+///
+/// The prompt may use the {transcript}, {context} and {personal_dictionary}
+/// placeholders and must contain {transcript}. Pass null to go back to the
+/// built-in prompts. Returns false if the prompt is invalid (see
+/// voiceflow_last_error_message).
+///
+/// # Safety
+/// prompt must be a valid null-terminated string or null
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_formatting_prompt(prompt: *const c_char) -> bool {
+    clear_last_error();
+    let prompt = if prompt.is_null() {
+        None
+    } else {
+        match str_arg(prompt, "prompt") {
+            Some(s) => Some(s.to_string()),
+            None => return false,
+        }
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.formatting_prompt = prompt;
+    if let Err(e) = config.validate_formatting_prompt() {
+        set_last_error_from(&e);
+        return false;
+    }
+    save_config(&config)
+}
+
+/// Get the number of built-in formatting presets
+#[no_mangle]
+pub extern "C" fn voiceflow_preset_count() -> usize {
+    FormattingPreset::all_presets().len()
+}
+
+/// Get formatting preset info by index
+///
+/// The id can be passed as the preset of VoiceFlowProcessOptions. Free the
+/// strings with voiceflow_free_preset_info.
+#[no_mangle]
+pub extern "C" fn voiceflow_preset_info(index: usize) -> PresetInfo {
+    let presets = FormattingPreset::all_presets();
+    let Some(preset) = presets.get(index) else {
+        return PresetInfo {
+            id: ptr::null_mut(),
+            display_name: ptr::null_mut(),
+        };
+    };
+
+    PresetInfo {
+        id: CString::new(preset.id()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        display_name: CString::new(preset.display_name()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+    }
+}
+
+/// Free preset info strings
+///
+/// # Safety
+/// Only call once per PresetInfo
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_free_preset_info(info: PresetInfo) {
+    if !info.id.is_null() {
+        let _ = CString::from_raw(info.id);
+    }
+    if !info.display_name.is_null() {
+        let _ = CString::from_raw(info.display_name);
+    }
+}
+
+// =============================================================================
+// Vocabulary
+// =============================================================================
+
+/// Get the custom vocabulary from config as a JSON array
+///
+/// Each entry is an object such as
+/// `{"term": "Kubernetes", "sounds_like": ["cooper netties"]}`. Free the
+/// string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_get_vocabulary() -> *mut c_char {
+    clear_last_error();
+    let config = Config::load(None).unwrap_or_default();
+    match serde_json::to_string(&config.vocabulary) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Set the custom vocabulary in config (requires restart to take effect)
+///
+/// Takes a JSON array whose entries are either a term string or an object
+/// with a `term` and optional `sounds_like` array of misrecognitions. At
+/// most 200 entries of up to 64 characters each are allowed. Returns false
+/// if the JSON or an entry is invalid (see voiceflow_last_error_message).
+///
+/// # Safety
+/// json_array must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_vocabulary(json_array: *const c_char) -> bool {
+    clear_last_error();
+    let json = match str_arg(json_array, "json_array") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let vocabulary = match serde_json::from_str(json) {
+        Ok(vocabulary) => vocabulary,
+        Err(e) => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Invalid vocabulary JSON: {}", e),
+            );
+            return false;
+        }
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.vocabulary = vocabulary;
+    if let Err(e) = config.validate_vocabulary() {
+        set_last_error_from(&e);
+        return false;
+    }
+    save_config(&config)
+}
+
+// =============================================================================
+// Replacement Rules
+// =============================================================================
+
+/// Get the find-and-replace rules from config as a JSON array
+///
+/// Each rule is an object with `pattern`, `replacement`, `is_regex`,
+/// `case_sensitive` and `apply_to_raw`. Free the string with
+/// voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_get_replacements() -> *mut c_char {
+    clear_last_error();
+    let config = Config::load(None).unwrap_or_default();
+    match serde_json::to_string(&config.replacements) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Replace the find-and-replace rules in config (requires restart to take effect)
+///
+/// Takes a JSON array in the format returned by voiceflow_get_replacements;
+/// only `pattern` and `replacement` are required. Rules apply in array
+/// order. Returns false if the JSON is invalid or a pattern doesn't compile
+/// (see voiceflow_last_error_message).
+///
+/// # Safety
+/// json_array must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_replacements(json_array: *const c_char) -> bool {
+    clear_last_error();
+    let json = match str_arg(json_array, "json_array") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let replacements = match serde_json::from_str(json) {
+        Ok(replacements) => replacements,
+        Err(e) => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Invalid replacements JSON: {}", e),
+            );
+            return false;
+        }
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.replacements = replacements;
+    if let Err(e) = config.validate_replacements() {
+        set_last_error_from(&e);
+        return false;
+    }
+    save_config(&config)
+}
+
+// =============================================================================
+// App Overrides
+// =============================================================================
+
+/// Get the per-app formatting overrides from config as a JSON object keyed
+/// by bundle id
+///
+/// Each override is an object with any of `preset` (a preset id, or
+/// `{"custom": "<template>"}`), `formatting_mode` ("full",
+/// "punctuation-only" or "none"), `vocabulary_extra` (entries as for
+/// voiceflow_set_vocabulary) and `replacements_extra` (rules as for
+/// voiceflow_set_replacements). Free the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_get_app_overrides() -> *mut c_char {
+    clear_last_error();
+    let config = Config::load(None).unwrap_or_default();
+    match serde_json::to_string(&config.app_overrides) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Set the formatting override for one app in config, replacing any it had
+/// (requires restart to take effect, or voiceflow_update_config_json)
+///
+/// Takes a JSON object in the format of one override from
+/// voiceflow_get_app_overrides. Calls with `app_id` set (see
+/// voiceflow_process_json) get it merged over the config: `preset` replaces
+/// the default preset but not one the call chooses, `formatting_mode` caps
+/// the formatting, `vocabulary_extra` is appended to the vocabulary (an
+/// entry for a term already there replaces it) and `replacements_extra`
+/// runs after the configured rules. Returns false if the JSON is invalid,
+/// has unknown fields, or the merged vocabulary or rules are (see
+/// voiceflow_last_error_message).
+///
+/// # Safety
+/// - app_id must be a valid null-terminated string
+/// - override_json must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_app_override(app_id: *const c_char, override_json: *const c_char) -> bool {
+    clear_last_error();
+    let (Some(app_id), Some(json)) = (str_arg(app_id, "app_id"), str_arg(override_json, "override_json")) else {
+        return false;
+    };
+
+    let app = match serde_json::from_str(json) {
+        Ok(app) => app,
+        Err(e) => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Invalid app override JSON: {}", e),
+            );
+            return false;
+        }
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    if let Err(e) = config.set_app_override(app_id, app) {
+        set_last_error_from(&e);
+        return false;
+    }
+    save_config(&config)
+}
+
+/// Remove the formatting override for one app from config (requires
+/// restart to take effect, or voiceflow_update_config_json)
+///
+/// Returns false if the app has no override or the config can't be saved
+/// (see voiceflow_last_error_message).
+///
+/// # Safety
+/// app_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_remove_app_override(app_id: *const c_char) -> bool {
+    clear_last_error();
+    let Some(app_id) = str_arg(app_id, "app_id") else {
+        return false;
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    if !config.remove_app_override(app_id) {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("No app override for {:?}", app_id),
+        );
+        return false;
+    }
+    save_config(&config)
+}
+
+// =============================================================================
+// LLM Sampling
+// =============================================================================
+
+/// Get the LLM decoding parameters from config as a JSON object
+///
+/// The object has `max_tokens`, `temperature`, `top_p`, `top_k`,
+/// `repeat_penalty`, `seed` (null when unset), `n_gpu_layers` and
+/// `enable_thinking`. Free the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_get_llm_options() -> *mut c_char {
+    clear_last_error();
+    let config = Config::load(None).unwrap_or_default();
+    match serde_json::to_string(&config.llm_options) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Update the LLM decoding parameters in config (requires restart to take effect)
+///
+/// Takes a JSON object in the format returned by voiceflow_get_llm_options;
+/// keys that are left out keep their current value, so
+/// `{"temperature": 0, "seed": 42}` is enough. Out-of-range values are
+/// clamped rather than rejected. Returns false if the JSON is invalid (see
+/// voiceflow_last_error_message).
+///
+/// # Safety
+/// json_object must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_llm_options(json_object: *const c_char) -> bool {
+    clear_last_error();
+    let json = match str_arg(json_object, "json_object") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    match merge_llm_options(&config.llm_options, json) {
+        Ok(llm_options) => config.llm_options = llm_options.clamped(),
+        Err(e) => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Invalid LLM options JSON: {}", e),
+            );
+            return false;
+        }
+    }
+    save_config(&config)
+}
+
+/// Overlay the keys of a JSON object on `base`, rejecting unknown keys
+fn merge_llm_options(base: &LlmOptions, json: &str) -> anyhow::Result<LlmOptions> {
+    let mut merged = serde_json::to_value(base)?;
+    let overrides: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)?;
+    if let Some(merged) = merged.as_object_mut() {
+        merged.extend(overrides);
+    }
+    let merged: LlmOptions = serde_json::from_value(merged)?;
+    if let Some(key) = merged.unknown_fields.keys().find(|key| !base.unknown_fields.contains_key(*key)) {
+        anyhow::bail!("unknown key {:?}", key);
+    }
+    Ok(merged)
+}
+
+// =============================================================================
+// Config Fields
+// =============================================================================
+
+/// Get a config field by dotted key, e.g. "llm.temperature" or "vad.enabled"
+///
+/// See the README for the supported keys. Strings are returned as they are,
+/// unset optional fields as "" and other values as JSON ("true", "0.7").
+/// Returns null for an unknown key (see voiceflow_last_error_message). Free
+/// the string with voiceflow_free_string.
+///
+/// # Safety
+/// key must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_config_get(key: *const c_char) -> *mut c_char {
+    clear_last_error();
+    let Some(key) = str_arg(key, "key") else {
+        return ptr::null_mut();
+    };
+
+    let config = Config::load(None).unwrap_or_default();
+    match config.get_key(key) {
+        Ok(value) => CString::new(value).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
 }
 
-/// Set the current model in config (requires restart to take effect)
+/// Set a config field by dotted key (requires restart to take effect)
+///
+/// The value is parsed as the field's type, in the format voiceflow_config_get
+/// returns; "" clears an optional field. Returns false, leaving the config
+/// file untouched, for an unknown key, a value of the wrong type or one that
+/// fails validation (see voiceflow_last_error_message).
 ///
 /// # Safety
-/// model_id must be a valid null-terminated string
+/// key and value must be valid null-terminated strings
 #[no_mangle]
-pub unsafe extern "C" fn voiceflow_set_model(model_id: *const c_char) -> bool {
-    use voiceflow_core::config::LlmModel;
+pub unsafe extern "C" fn voiceflow_config_set(key: *const c_char, value: *const c_char) -> bool {
+    clear_last_error();
+    let (Some(key), Some(value)) = (str_arg(key, "key"), str_arg(value, "value")) else {
+        return false;
+    };
 
-    if model_id.is_null() {
+    let mut config = Config::load_file(None).unwrap_or_default();
+    if let Err(e) = config.set_key(key, value) {
+        set_last_error_from(&e);
         return false;
     }
+    save_config(&config)
+}
 
-    let id_str = match CStr::from_ptr(model_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return false,
-    };
+/// Get the whole config as a JSON object, with the field names of the TOML
+/// file
+///
+/// Free the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_config_json() -> *mut c_char {
+    clear_last_error();
+    let config = Config::load(None).unwrap_or_default();
+    match config.to_json() {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
+}
 
-    let model = match id_str {
-        "qwen3-1.7b" => LlmModel::Qwen3_1_7B,
-        "qwen3-4b" => LlmModel::Qwen3_4B,
-        "smollm3-3b" => LlmModel::SmolLM3_3B,
-        "gemma2-2b" => LlmModel::Gemma2_2B,
-        "phi-2" => LlmModel::Phi2,
-        _ => return false,
+/// Update several config fields at once (requires restart to take effect)
+///
+/// Takes a JSON object in the format returned by voiceflow_config_json;
+/// nested objects are merged, so only the fields to change are needed, e.g.
+/// `{"llm_options": {"temperature": 0.2}, "audio": {"vad_enabled": false}}`.
+/// Returns false, leaving the config file untouched, for unknown fields or
+/// invalid values (see voiceflow_last_error_message).
+///
+/// # Safety
+/// json_object must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_config_apply_json(json_object: *const c_char) -> bool {
+    clear_last_error();
+    let Some(json) = str_arg(json_object, "json_object") else {
+        return false;
     };
 
-    let mut config = Config::load(None).unwrap_or_default();
-    config.llm_model = model;
-    config.save(None).is_ok()
+    let mut config = Config::load_file(None).unwrap_or_default();
+    if let Err(e) = config.apply_json(json) {
+        set_last_error_from(&e);
+        return false;
+    }
+    save_config(&config)
 }
 
-/// Get the HuggingFace download URL for a model
+/// Apply config changes to a running handle without reinitializing it
+///
+/// Takes a JSON object in the format of voiceflow_config_json, merged over
+/// the handle's current config; the config file is neither read nor
+/// written. Sampling parameters, prompts, audio and VAD settings and
+/// replacements take effect with the next request. Fields that need a model
+/// reload (see the README) keep their current value and are listed in the
+/// returned JSON object, e.g. `{"needs_reload": ["llm_model"]}`; apply them
+/// by creating a new handle. Returns null, changing nothing, for unknown
+/// fields or invalid values (see voiceflow_last_error_message). Free the
+/// string with voiceflow_free_string.
 ///
 /// # Safety
-/// model_id must be a valid null-terminated string
+/// - handle must be a valid pointer from voiceflow_init
+/// - json_object must be a valid null-terminated string
 #[no_mangle]
-pub unsafe extern "C" fn voiceflow_model_download_url(model_id: *const c_char) -> *mut c_char {
-    use voiceflow_core::config::LlmModel;
-
-    if model_id.is_null() {
+pub unsafe extern "C" fn voiceflow_update_config_json(
+    handle: *mut VoiceFlowHandle,
+    json_object: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    let Some(json) = str_arg(json_object, "json_object") else {
         return ptr::null_mut();
-    }
-
-    let id_str = match CStr::from_ptr(model_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return ptr::null_mut(),
-    };
-
-    let model = match id_str {
-        "qwen3-1.7b" => LlmModel::Qwen3_1_7B,
-        "qwen3-4b" => LlmModel::Qwen3_4B,
-        "smollm3-3b" => LlmModel::SmolLM3_3B,
-        "gemma2-2b" => LlmModel::Gemma2_2B,
-        "phi-2" => LlmModel::Phi2,
-        _ => return ptr::null_mut(),
     };
 
-    if let Some(repo) = model.hf_repo() {
-        let url = format!(
-            "https://huggingface.co/{}/resolve/main/{}",
-            repo,
-            model.filename()
-        );
-        CString::new(url).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
-    } else {
-        ptr::null_mut()
+    let updated = with_pipeline_unready(handle, "Config update", |pipeline| {
+        let mut config = pipeline.config().clone();
+        config.apply_json(json)?;
+        let log_file = pipeline.config().log_file.clone();
+        let telemetry_endpoint = pipeline.config().telemetry.endpoint.clone();
+        let needs_reload = pipeline.update_config(&config)?;
+        if config.log_file != log_file {
+            logging::set_log_file(config.log_file_path().as_deref());
+        }
+        if config.telemetry.endpoint != telemetry_endpoint {
+            events::set_telemetry(&config.telemetry);
+        }
+        Ok(serde_json::json!({ "needs_reload": needs_reload }).to_string())
+    });
+    match updated {
+        Some(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
     }
 }
 
@@ -443,72 +2341,349 @@ pub extern "C" fn voiceflow_current_stt_engine() -> *mut c_char {
 
 /// Set the current STT engine ("whisper" or "moonshine")
 ///
+/// Fails for "moonshine" unless the configured language is "en" and the
+/// task is transcribe, since Moonshine only transcribes English.
+///
 /// # Safety
 /// engine_id must be a valid null-terminated string
 #[no_mangle]
 pub unsafe extern "C" fn voiceflow_set_stt_engine(engine_id: *const c_char) -> bool {
     use voiceflow_core::config::SttEngine;
 
-    if engine_id.is_null() {
+    clear_last_error();
+    let engine_str = match str_arg(engine_id, "engine_id") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let engine = match engine_str {
+        "whisper" => SttEngine::Whisper,
+        "moonshine" => SttEngine::Moonshine,
+        _ => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown STT engine: {}", engine_str),
+            );
+            return false;
+        }
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.stt_engine = engine;
+    // Moonshine is English-only
+    if let Err(e) = config.validate_language() {
+        set_last_error_from(&e);
         return false;
     }
+    save_config(&config)
+}
 
-    let engine_str = match CStr::from_ptr(engine_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return false,
-    };
+/// Switch the handle's STT engine ("whisper" or "moonshine") without
+/// reloading the LLM
+///
+/// Only the handle changes: call voiceflow_set_stt_engine as well to keep
+/// the choice across restarts. Processing calls made during the reload
+/// wait for it to finish. Fails with VF_ERR_BUSY while a streaming session
+/// is active; on any failure the previous engine stays in use.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - engine_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_reload_stt_engine(
+    handle: *mut VoiceFlowHandle,
+    engine_id: *const c_char,
+) -> VoiceFlowErrorCode {
+    use voiceflow_core::config::SttEngine;
 
+    clear_last_error();
+    let engine_str = match str_arg(engine_id, "engine_id") {
+        Some(s) => s,
+        None => return VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+    };
     let engine = match engine_str {
         "whisper" => SttEngine::Whisper,
         "moonshine" => SttEngine::Moonshine,
-        _ => return false,
+        _ => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                format!("Unknown STT engine: {}", engine_str),
+            );
+            return VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT;
+        }
     };
 
-    let mut config = Config::load(None).unwrap_or_default();
-    config.stt_engine = engine;
-    config.save(None).is_ok()
+    // A stream's state belongs to the engine that produced it
+    if !invalid_handle(handle) && (*handle).stream.lock().unwrap_or_else(|e| e.into_inner()).is_some() {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_BUSY,
+            "Cannot switch the STT engine during a streaming session",
+        );
+        return VoiceFlowErrorCode::VF_ERR_BUSY;
+    }
+    reload_pipeline(handle, |pipeline| pipeline.reload_stt(engine))
+}
+
+/// Set the hardware the STT engine runs on ("cpu", "coreml" or "auto")
+///
+/// Moonshine uses the Core ML execution provider for "coreml", and for
+/// "auto" on Apple platforms; Whisper uses whisper.cpp's GPU backend for
+/// both. If Core ML can't be used the engine runs on the CPU, with a
+/// warning in the log. Saved to the config file; takes effect the next
+/// time the STT engine loads (voiceflow_init or
+/// voiceflow_reload_stt_engine).
+///
+/// # Safety
+/// provider_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_stt_provider(provider_id: *const c_char) -> bool {
+    use voiceflow_core::SttExecutionProvider;
+
+    clear_last_error();
+    let Some(provider_str) = str_arg(provider_id, "provider_id") else {
+        return false;
+    };
+    let Some(provider) = SttExecutionProvider::from_id(provider_str) else {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("Unknown STT provider: {}", provider_str),
+        );
+        return false;
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.stt_execution_provider = provider;
+    save_config(&config)
 }
 
-/// Get the current Moonshine model ("tiny" or "base")
+/// Get the hardware the handle's STT engine runs on: "cpu", "coreml",
+/// "metal" or "cuda"
+///
+/// This is the provider actually in use after any fallback, also reported
+/// as timings.stt_provider in voiceflow_process_json results. With a null
+/// handle, returns the configured setting instead ("cpu", "coreml" or
+/// "auto"). Returns null for an engine supplied by the app. Free the string
+/// with voiceflow_free_string.
+///
+/// # Safety
+/// handle must be null or a valid pointer from voiceflow_init
 #[no_mangle]
-pub extern "C" fn voiceflow_current_moonshine_model() -> *mut c_char {
-    use voiceflow_core::config::MoonshineModel;
+pub unsafe extern "C" fn voiceflow_current_stt_provider(handle: *mut VoiceFlowHandle) -> *mut c_char {
+    let provider = if invalid_handle(handle) {
+        Some(Config::load(None).unwrap_or_default().stt_execution_provider.id())
+    } else {
+        let handle = &*handle;
+        let _call = handle.calls.enter();
+        lock_pipeline(&handle.pipeline).stt_provider()
+    };
+    provider
+        .and_then(|provider| CString::new(provider).ok())
+        .map_or(ptr::null_mut(), |s| s.into_raw())
+}
+
+/// Get the STT engine the handle runs, as a JSON object: "engine"
+/// ("whisper" or "moonshine"), "model" (a model id), "precision"
+/// ("float32", "quantized" or "int8") and "substituted"
+///
+/// "substituted" is true when stt_fallback_enabled is set and the
+/// configured engine failed to load, so this one runs in its place; a
+/// warning is logged when that happens. The config file keeps the
+/// configured engine. Returns null for a null handle or an engine supplied
+/// by the app. Free the string with voiceflow_free_string.
+///
+/// # Safety
+/// handle must be null or a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_active_stt_engine(handle: *mut VoiceFlowHandle) -> *mut c_char {
+    if invalid_handle(handle) {
+        return ptr::null_mut();
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let active = lock_pipeline(&handle.pipeline).active_stt_engine().cloned();
+    active
+        .and_then(|active| serde_json::to_string(&active).ok())
+        .and_then(|json| CString::new(json).ok())
+        .map_or(ptr::null_mut(), |s| s.into_raw())
+}
 
+/// Get the current Whisper model ("tiny", "base", "small", "medium" or
+/// "large-v3-turbo", with "-quantized" or "-int8" for those precisions)
+#[no_mangle]
+pub extern "C" fn voiceflow_current_whisper_model() -> *mut c_char {
     let config = Config::load(None).unwrap_or_default();
-    let model_str = match config.moonshine_model {
-        MoonshineModel::Tiny => "tiny",
-        MoonshineModel::Base => "base",
-    };
+    let id = config.whisper_precision.variant_id(config.whisper_model.id());
+    CString::new(id).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
 
-    CString::new(model_str).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+/// A Whisper model and precision from a variant id ("small", "small-int8")
+fn whisper_variant(id: &str) -> Option<(voiceflow_core::config::WhisperModel, ModelPrecision)> {
+    let (model_id, precision) = ModelPrecision::split_variant_id(id);
+    voiceflow_core::config::WhisperModel::from_id(model_id).map(|model| (model, precision))
 }
 
-/// Set the current Moonshine model ("tiny" or "base")
+/// Set the current Whisper model ("tiny", "base", "small", "medium" or
+/// "large-v3-turbo", with "-quantized" or "-int8" for those precisions)
+///
+/// Takes effect on the next voiceflow_init, which fails with
+/// VF_ERR_MODEL_NOT_FOUND if the model isn't downloaded.
 ///
 /// # Safety
 /// model_id must be a valid null-terminated string
 #[no_mangle]
-pub unsafe extern "C" fn voiceflow_set_moonshine_model(model_id: *const c_char) -> bool {
-    use voiceflow_core::config::MoonshineModel;
+pub unsafe extern "C" fn voiceflow_set_whisper_model(model_id: *const c_char) -> bool {
+    clear_last_error();
+    let model_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return false,
+    };
 
+    let Some((model, precision)) = whisper_variant(model_str) else {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("Unknown Whisper model: {}", model_str),
+        );
+        return false;
+    };
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.whisper_model = model;
+    config.whisper_precision = precision;
+    save_config(&config)
+}
+
+/// Whisper model info struct for FFI
+#[repr(C)]
+pub struct WhisperModelInfo {
+    pub id: *mut c_char,
+    pub display_name: *mut c_char,
+    pub size_mb: u32,
+    pub is_downloaded: bool,
+    /// "float32", "quantized" or "int8"
+    pub precision: *mut c_char,
+}
+
+/// Whisper model variants within the memory budget
+fn listed_whisper_models() -> Vec<(voiceflow_core::config::WhisperModel, ModelPrecision)> {
+    DownloadableModel::all_models()
+        .into_iter()
+        .filter(memory::within_budget)
+        .filter_map(|model| match model {
+            DownloadableModel::Whisper(model, precision) => Some((model, precision)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Get the number of available Whisper models, one per size and precision
+///
+/// Models over the memory budget (see voiceflow_set_memory_budget) are left
+/// out.
+#[no_mangle]
+pub extern "C" fn voiceflow_whisper_model_count() -> usize {
+    listed_whisper_models().len()
+}
+
+/// Get Whisper model info by index
+///
+/// # Safety
+/// index must be < voiceflow_whisper_model_count()
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_whisper_model_info(index: usize) -> WhisperModelInfo {
+    let Some((model, precision)) = listed_whisper_models().into_iter().nth(index) else {
+        return WhisperModelInfo {
+            id: ptr::null_mut(),
+            display_name: ptr::null_mut(),
+            size_mb: 0,
+            is_downloaded: false,
+            precision: ptr::null_mut(),
+        };
+    };
+
+    let config = Config::load(None).unwrap_or_default();
+    let is_downloaded = config.whisper_model_downloaded_for(&model, precision);
+
+    WhisperModelInfo {
+        id: CString::new(precision.variant_id(model.id())).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        display_name: CString::new(model.display_name()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        size_mb: model.size_mb(precision),
+        is_downloaded,
+        precision: CString::new(precision.id()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+    }
+}
+
+/// Free Whisper model info strings
+///
+/// # Safety
+/// Only call once per WhisperModelInfo
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_free_whisper_model_info(info: WhisperModelInfo) {
+    for string in [info.id, info.display_name, info.precision] {
+        if !string.is_null() {
+            let _ = CString::from_raw(string);
+        }
+    }
+}
+
+/// Check if a Whisper model is downloaded
+///
+/// # Safety
+/// model_id must be a valid null-terminated string (e.g. "small" or
+/// "small-int8")
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_whisper_model_downloaded(model_id: *const c_char) -> bool {
     if model_id.is_null() {
         return false;
     }
 
-    let model_str = match CStr::from_ptr(model_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return false,
+    let Some((model, precision)) = CStr::from_ptr(model_id).to_str().ok().and_then(whisper_variant) else {
+        return false;
+    };
+
+    let config = Config::load(None).unwrap_or_default();
+    config.whisper_model_downloaded_for(&model, precision)
+}
+
+/// Get the current Moonshine model ("tiny" or "base", with "-quantized" or
+/// "-int8" for those precisions)
+#[no_mangle]
+pub extern "C" fn voiceflow_current_moonshine_model() -> *mut c_char {
+    let config = Config::load(None).unwrap_or_default();
+    let id = config.moonshine_precision.variant_id(config.moonshine_model.id());
+    CString::new(id).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
+
+/// A Moonshine model and precision from a variant id ("tiny", "tiny-int8")
+fn moonshine_variant(id: &str) -> Option<(voiceflow_core::config::MoonshineModel, ModelPrecision)> {
+    let (model_id, precision) = ModelPrecision::split_variant_id(id);
+    voiceflow_core::config::MoonshineModel::from_id(model_id).map(|model| (model, precision))
+}
+
+/// Set the current Moonshine model ("tiny" or "base", with "-quantized" or
+/// "-int8" for those precisions)
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_moonshine_model(model_id: *const c_char) -> bool {
+    clear_last_error();
+    let model_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return false,
     };
 
-    let model = match model_str {
-        "tiny" => MoonshineModel::Tiny,
-        "base" => MoonshineModel::Base,
-        _ => return false,
+    let Some((model, precision)) = moonshine_variant(model_str) else {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("Unknown Moonshine model: {}", model_str),
+        );
+        return false;
     };
 
-    let mut config = Config::load(None).unwrap_or_default();
+    let mut config = Config::load_file(None).unwrap_or_default();
     config.moonshine_model = model;
-    config.save(None).is_ok()
+    config.moonshine_precision = precision;
+    save_config(&config)
 }
 
 /// Moonshine model info struct for FFI
@@ -517,13 +2692,37 @@ pub struct MoonshineModelInfo {
     pub id: *mut c_char,
     pub display_name: *mut c_char,
     pub size_mb: u32,
+    /// Whether status is VF_MODEL_DOWNLOADED
     pub is_downloaded: bool,
+    /// "float32", "quantized" or "int8"
+    pub precision: *mut c_char,
+    /// Bytes of its files on disk, partial downloads included
+    pub downloaded_bytes: u64,
+    /// Size of the complete model (an estimate until it's downloaded)
+    pub expected_bytes: u64,
+    /// VF_MODEL_PARTIAL while any of its files is missing
+    pub status: VoiceFlowModelStatus,
+}
+
+/// Moonshine model variants within the memory budget
+fn listed_moonshine_models() -> Vec<(voiceflow_core::config::MoonshineModel, ModelPrecision)> {
+    DownloadableModel::all_models()
+        .into_iter()
+        .filter(memory::within_budget)
+        .filter_map(|model| match model {
+            DownloadableModel::Moonshine(model, precision) => Some((model, precision)),
+            _ => None,
+        })
+        .collect()
 }
 
-/// Get the number of available Moonshine models
+/// Get the number of available Moonshine models, one per size and precision
+///
+/// Models over the memory budget (see voiceflow_set_memory_budget) are left
+/// out.
 #[no_mangle]
 pub extern "C" fn voiceflow_moonshine_model_count() -> usize {
-    2 // Tiny and Base
+    listed_moonshine_models().len()
 }
 
 /// Get Moonshine model info by index
@@ -532,32 +2731,31 @@ pub extern "C" fn voiceflow_moonshine_model_count() -> usize {
 /// index must be < voiceflow_moonshine_model_count()
 #[no_mangle]
 pub unsafe extern "C" fn voiceflow_moonshine_model_info(index: usize) -> MoonshineModelInfo {
-    use voiceflow_core::config::MoonshineModel;
-
-    let model = match index {
-        0 => MoonshineModel::Tiny,
-        1 => MoonshineModel::Base,
-        _ => return MoonshineModelInfo {
+    let Some((model, precision)) = listed_moonshine_models().into_iter().nth(index) else {
+        return MoonshineModelInfo {
             id: ptr::null_mut(),
             display_name: ptr::null_mut(),
             size_mb: 0,
             is_downloaded: false,
-        },
+            precision: ptr::null_mut(),
+            downloaded_bytes: 0,
+            expected_bytes: 0,
+            status: VoiceFlowModelStatus::VF_MODEL_NOT_DOWNLOADED,
+        };
     };
 
     let config = Config::load(None).unwrap_or_default();
-    let is_downloaded = config.moonshine_model_downloaded_for(&model);
-
-    let id_str = match model {
-        MoonshineModel::Tiny => "tiny",
-        MoonshineModel::Base => "base",
-    };
+    let state = download_state(&DownloadableModel::Moonshine(model.clone(), precision), &config);
 
     MoonshineModelInfo {
-        id: CString::new(id_str).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        id: CString::new(precision.variant_id(model.id())).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
         display_name: CString::new(model.display_name()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
-        size_mb: model.size_mb(),
-        is_downloaded,
+        size_mb: model.size_mb(precision),
+        is_downloaded: state.status == storage::DownloadStatus::Downloaded,
+        precision: CString::new(precision.id()).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        downloaded_bytes: state.downloaded_bytes,
+        expected_bytes: state.expected_bytes,
+        status: state.status.into(),
     }
 }
 
@@ -567,39 +2765,57 @@ pub unsafe extern "C" fn voiceflow_moonshine_model_info(index: usize) -> Moonshi
 /// Only call once per MoonshineModelInfo
 #[no_mangle]
 pub unsafe extern "C" fn voiceflow_free_moonshine_model_info(info: MoonshineModelInfo) {
-    if !info.id.is_null() {
-        let _ = CString::from_raw(info.id);
-    }
-    if !info.display_name.is_null() {
-        let _ = CString::from_raw(info.display_name);
+    for string in [info.id, info.display_name, info.precision] {
+        if !string.is_null() {
+            let _ = CString::from_raw(string);
+        }
     }
 }
 
 /// Check if a Moonshine model is downloaded
 ///
 /// # Safety
-/// model_id must be a valid null-terminated string ("tiny" or "base")
+/// model_id must be a valid null-terminated string ("tiny", "base", or
+/// either with "-quantized" or "-int8")
 #[no_mangle]
 pub unsafe extern "C" fn voiceflow_moonshine_model_downloaded(model_id: *const c_char) -> bool {
-    use voiceflow_core::config::MoonshineModel;
-
     if model_id.is_null() {
         return false;
     }
 
-    let model_str = match CStr::from_ptr(model_id).to_str() {
-        Ok(s) => s,
-        Err(_) => return false,
+    let Some((model, precision)) = CStr::from_ptr(model_id).to_str().ok().and_then(moonshine_variant) else {
+        return false;
     };
 
-    let model = match model_str {
-        "tiny" => MoonshineModel::Tiny,
-        "base" => MoonshineModel::Base,
-        _ => return false,
+    let config = Config::load(None).unwrap_or_default();
+    config.moonshine_model_downloaded_for(&model, precision)
+}
+
+/// Delete a downloaded Moonshine model ("tiny", "base", or either with
+/// "-quantized" or "-int8")
+///
+/// Same as voiceflow_delete_model: the configured model is only deleted if
+/// `force` is set.
+///
+/// # Safety
+/// model_id must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_moonshine_delete_model(model_id: *const c_char, force: bool) -> bool {
+    clear_last_error();
+    let model_str = match str_arg(model_id, "model_id") {
+        Some(s) => s,
+        None => return false,
     };
 
-    let config = Config::load(None).unwrap_or_default();
-    config.moonshine_model_downloaded_for(&model)
+    let Some((model, precision)) = moonshine_variant(model_str) else {
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            format!("Unknown Moonshine model: {}", model_str),
+        );
+        return false;
+    };
+
+    delete_model(&DownloadableModel::Moonshine(model, precision), force)
 }
 
 /// Get the Moonshine models directory path
@@ -620,3 +2836,243 @@ pub extern "C" fn voiceflow_moonshine_models_dir() -> *mut c_char {
         Err(_) => ptr::null_mut(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_timings_round_trip() {
+        let result = PipelineResult {
+            raw_transcript: "hello world".to_string(),
+            formatted_text: "Hello world.".to_string(),
+            timings: Default::default(),
+            prosody_hints: None,
+            word_timestamps: vec![
+                WordTimestamp { word: "hello".to_string(), start_ms: 120, end_ms: 480, probability: 0.9 },
+                WordTimestamp { word: "world".to_string(), start_ms: 520, end_ms: 900, probability: 0.7 },
+            ],
+            confidence: 0.8,
+            alternatives: Vec::new(),
+            no_speech_probability: 0.02,
+            no_speech: false,
+            language: Some("en".to_string()),
+            original_transcript: None,
+            raw_llm_output: None,
+            was_fallback: false,
+            formatting_error: None,
+            prompt_truncation: None,
+            format_cache_hit: false,
+            clipped_percent: 0.0,
+            clipping: false,
+            repaired_samples: 0,
+            filtered_segments: Vec::new(),
+            scratch_previous: false,
+            segments: Vec::new(),
+            fast_path: false,
+        };
+
+        let vf_result = pipeline_result(Ok(result));
+        assert_eq!(vf_result.word_count, 2);
+        let words = unsafe { std::slice::from_raw_parts(vf_result.words, vf_result.word_count) };
+        assert_eq!(unsafe { CStr::from_ptr(words[1].word) }.to_str().unwrap(), "world");
+        assert_eq!((words[1].start_ms, words[1].end_ms), (520, 900));
+        assert!((words[0].confidence - 0.9).abs() < 1e-6);
+        let language = unsafe { CStr::from_ptr(vf_result.detected_language.as_ptr()) };
+        assert_eq!(language.to_str().unwrap(), "en");
+        assert!(vf_result.original_transcript.is_null());
+        unsafe { voiceflow_free_result(vf_result) };
+
+        let empty = error_result("failed");
+        assert!(empty.words.is_null());
+        unsafe { voiceflow_free_result(empty) };
+    }
+
+    fn sample_result() -> PipelineResult {
+        PipelineResult {
+            raw_transcript: "hello world".to_string(),
+            formatted_text: "Hello world.".to_string(),
+            timings: Default::default(),
+            prosody_hints: None,
+            word_timestamps: Vec::new(),
+            confidence: 0.8,
+            alternatives: Vec::new(),
+            no_speech_probability: 0.02,
+            no_speech: false,
+            language: Some("en".to_string()),
+            original_transcript: None,
+            raw_llm_output: None,
+            was_fallback: false,
+            formatting_error: None,
+            prompt_truncation: None,
+            format_cache_hit: false,
+            clipped_percent: 0.0,
+            clipping: false,
+            repaired_samples: 0,
+            filtered_segments: Vec::new(),
+            scratch_previous: false,
+            segments: Vec::new(),
+            fast_path: false,
+        }
+    }
+
+    #[test]
+    fn test_c_string_strips_interior_nuls() {
+        assert_eq!(c_string("a\0b\0").to_str().unwrap(), "ab");
+        assert_eq!(c_string("\0").to_bytes(), b"");
+        assert_eq!(c_string("héllo").to_str().unwrap(), "héllo");
+    }
+
+    #[test]
+    fn test_context_arg_is_lossy() {
+        unsafe {
+            assert!(context_arg(ptr::null()).is_none());
+            let valid = CString::new("Slack").unwrap();
+            assert!(matches!(context_arg(valid.as_ptr()), Some(Cow::Borrowed("Slack"))));
+            // Lone continuation byte, truncated sequence and an overlong NUL
+            let invalid = b"Mail \x80 \xe2\x82 \xc0\x80\0";
+            let context = context_arg(invalid.as_ptr().cast()).unwrap();
+            assert_eq!(context, "Mail \u{FFFD} \u{FFFD} \u{FFFD}\u{FFFD}");
+        }
+    }
+
+    #[test]
+    fn test_nul_in_formatted_text_falls_back_to_raw_transcript() {
+        let mut result = PipelineResult {
+            raw_transcript: "hello\0 world".to_string(),
+            formatted_text: "Hello\0 world.".to_string(),
+            ..sample_result()
+        };
+        sanitize_result(&mut result);
+        assert_eq!(result.formatted_text, "hello world");
+        assert!(result.was_fallback);
+        assert_eq!(result.formatting_error.as_deref(), Some("Formatted text contained a NUL byte"));
+
+        let vf_result = pipeline_result(Ok(PipelineResult {
+            formatted_text: "\0".to_string(),
+            raw_transcript: String::new(),
+            raw_llm_output: Some("\0\0".to_string()),
+            ..sample_result()
+        }));
+        assert!(vf_result.success);
+        assert!(!vf_result.formatted_text.is_null() && !vf_result.raw_transcript.is_null());
+        assert_eq!(unsafe { CStr::from_ptr(vf_result.formatted_text) }.to_bytes(), b"");
+        assert_eq!(unsafe { CStr::from_ptr(vf_result.raw_llm_output) }.to_bytes(), b"");
+        unsafe { voiceflow_free_result(vf_result) };
+
+        let failed = error_result("bad\0 output");
+        assert_eq!(unsafe { CStr::from_ptr(failed.error_message) }.to_str().unwrap(), "bad output");
+        unsafe { voiceflow_free_result(failed) };
+    }
+
+    #[test]
+    fn test_result_freed_twice_is_detected() {
+        let result = pipeline_result(Ok(PipelineResult {
+            word_timestamps: vec![WordTimestamp { word: "hello".to_string(), start_ms: 0, end_ms: 300, probability: 0.9 }],
+            original_transcript: Some("helo world".to_string()),
+            ..sample_result()
+        }));
+        // Swift copies the struct, so both copies hold the same pointers
+        let copy = unsafe { ptr::read(&result) };
+        crate::error::clear_last_error();
+        unsafe { voiceflow_free_result(result) };
+        assert_eq!(crate::error::voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_OK);
+        unsafe { voiceflow_free_result(copy) };
+        assert_eq!(crate::error::voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+
+        let failed = error_result("failed");
+        let copy = unsafe { ptr::read(&failed) };
+        crate::error::clear_last_error();
+        unsafe { voiceflow_free_result(failed) };
+        unsafe { voiceflow_free_result(copy) };
+        assert_eq!(crate::error::voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+
+        // A zeroed result owns nothing
+        crate::error::clear_last_error();
+        unsafe { voiceflow_free_result(std::mem::zeroed()) };
+        assert_eq!(crate::error::voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_OK);
+    }
+
+    #[test]
+    fn test_stt_variant_ids() {
+        use voiceflow_core::config::{MoonshineModel, WhisperModel};
+
+        assert_eq!(
+            whisper_variant("large-v3-turbo-quantized"),
+            Some((WhisperModel::LargeV3Turbo, ModelPrecision::Quantized))
+        );
+        assert_eq!(moonshine_variant("base"), Some((MoonshineModel::Base, ModelPrecision::Float32)));
+        assert_eq!(moonshine_variant("tiny-int8"), Some((MoonshineModel::Tiny, ModelPrecision::Int8)));
+        assert_eq!(moonshine_variant("base-fp16"), None);
+    }
+
+    #[test]
+    fn test_merge_llm_options_keeps_unset_keys() {
+        let base = LlmOptions::default();
+        let merged = merge_llm_options(&base, r#"{"temperature": 0, "seed": 42}"#).unwrap();
+        assert_eq!(merged, LlmOptions { temperature: 0.0, seed: Some(42), ..base.clone() });
+
+        assert!(merge_llm_options(&base, "[1, 2]").is_err());
+        assert!(merge_llm_options(&base, r#"{"top_k": -1}"#).is_err());
+        assert!(merge_llm_options(&base, r#"{"temprature": 0.1}"#).is_err());
+    }
+
+    /// Needs downloaded models: `cargo test -p voiceflow-ffi -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_self_test_passes_with_working_models() {
+        assert!(unsafe { voiceflow_self_test(ptr::null_mut()) }.is_null());
+
+        let handle = unsafe { voiceflow_init(ptr::null()) };
+        assert!(!handle.is_null(), "voiceflow_init failed; are the models downloaded?");
+        let json = unsafe { voiceflow_self_test(handle) };
+        assert!(!json.is_null());
+        let report: serde_json::Value = serde_json::from_str(unsafe { CStr::from_ptr(json) }.to_str().unwrap()).unwrap();
+        unsafe { voiceflow_free_string(json) };
+        assert_eq!(report["passed"], true, "{}", report);
+        assert_eq!(report["stages"][1]["stage"], "stt");
+        assert!(!report["transcript"].as_str().unwrap().is_empty());
+        unsafe { voiceflow_destroy(handle) };
+    }
+
+    /// Needs downloaded models: `cargo test -p voiceflow-ffi -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_handle_shared_across_threads() {
+        struct HandlePtr(*mut VoiceFlowHandle);
+        unsafe impl Send for HandlePtr {}
+        unsafe impl Sync for HandlePtr {}
+
+        let handle = unsafe { voiceflow_init(ptr::null()) };
+        assert!(!handle.is_null(), "voiceflow_init failed; are the models downloaded?");
+        let shared = std::sync::Arc::new(HandlePtr(handle));
+
+        // One second of a quiet tone
+        let audio: Vec<f32> = (0..16000).map(|i| (i as f32 * 0.05).sin() * 0.1).collect();
+
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let shared = std::sync::Arc::clone(&shared);
+                let audio = audio.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..5 {
+                        let result = unsafe {
+                            if t % 2 == 0 {
+                                voiceflow_process(shared.0, audio.as_ptr(), audio.len(), ptr::null())
+                            } else {
+                                voiceflow_process_with_rate(shared.0, audio.as_ptr(), audio.len(), 16000, 1, ptr::null())
+                            }
+                        };
+                        assert!(result.success || !result.error_message.is_null());
+                        unsafe { voiceflow_free_result(result) };
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+        unsafe { voiceflow_destroy(handle) };
+    }
+}
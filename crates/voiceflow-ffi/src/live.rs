@@ -0,0 +1,92 @@
+//! Pointers handed to the caller and not yet freed
+//!
+//! Entry points check these before trusting a pointer, so a double free or
+//! a call on a destroyed handle is logged and rejected instead of touching
+//! freed memory. A later allocation can reuse a freed address, so this
+//! catches misuse soon after the free rather than every dangling pointer.
+
+use std::collections::BTreeSet;
+use std::sync::{Mutex, MutexGuard};
+
+/// Addresses of one kind of allocation still owned by the caller
+pub(crate) struct LiveSet {
+    kind: &'static str,
+    addresses: Mutex<BTreeSet<usize>>,
+}
+
+/// Handles from voiceflow_init, until voiceflow_destroy
+pub(crate) static HANDLES: LiveSet = LiveSet::new("handle");
+/// Handles from voiceflow_process2, until voiceflow_result_free
+pub(crate) static RESULT_HANDLES: LiveSet = LiveSet::new("result handle");
+/// Strings and word arrays of flat results, until voiceflow_free_result
+pub(crate) static RESULT_ALLOCATIONS: LiveSet = LiveSet::new("result");
+
+impl LiveSet {
+    const fn new(kind: &'static str) -> Self {
+        Self { kind, addresses: Mutex::new(BTreeSet::new()) }
+    }
+
+    fn addresses(&self) -> MutexGuard<'_, BTreeSet<usize>> {
+        self.addresses.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a pointer handed to the caller (null is ignored)
+    pub(crate) fn insert<T>(&self, ptr: *const T) {
+        if !ptr.is_null() {
+            self.addresses().insert(ptr as usize);
+        }
+    }
+
+    /// Whether `ptr` is live; logs a non-null pointer that isn't
+    pub(crate) fn check<T>(&self, ptr: *const T) -> bool {
+        if ptr.is_null() {
+            return false;
+        }
+        let live = self.addresses().contains(&(ptr as usize));
+        if !live {
+            self.report(ptr);
+        }
+        live
+    }
+
+    /// Forget `ptr` as it's freed, returning whether it was live
+    ///
+    /// Only a live pointer may be freed. Unlike `check`, this doesn't log,
+    /// so a caller freeing several pointers can report misuse once.
+    pub(crate) fn remove<T>(&self, ptr: *const T) -> bool {
+        !ptr.is_null() && self.addresses().remove(&(ptr as usize))
+    }
+
+    /// Log a pointer that was used but isn't live
+    pub(crate) fn report<T>(&self, ptr: *const T) {
+        tracing::error!("{} {:p} was already freed or didn't come from voiceflow", self.kind, ptr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_pointer_is_live_until_removed() {
+        let set = LiveSet::new("test");
+        let value = Box::new(7u32);
+        let ptr: *const u32 = &*value;
+
+        assert!(!set.check(ptr));
+        set.insert(ptr);
+        assert!(set.check(ptr));
+        assert!(set.remove(ptr));
+        assert!(!set.check(ptr));
+        assert!(!set.remove(ptr), "a second free must be caught");
+    }
+
+    #[test]
+    fn test_null_is_never_live() {
+        let set = LiveSet::new("test");
+        set.insert(ptr::null::<u8>());
+        assert!(!set.check(ptr::null::<u8>()));
+        assert!(!set.remove(ptr::null::<u8>()));
+    }
+}
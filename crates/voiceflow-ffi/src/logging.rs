@@ -0,0 +1,209 @@
+//! Log routing for the host app
+//!
+//! voiceflow-core and this crate log through `tracing`. The first FFI call
+//! installs a subscriber that filters by the level set with
+//! voiceflow_set_log_level and forwards each event to the host's callback
+//! and, if `log_file` is set in the config, to that file (except in builds
+//! with the `ios` feature). Nothing is written anywhere unless the host opts
+//! in. Spans are only recorded for the event stream (see the events module).
+
+use std::ffi::{c_char, c_void, CString};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, Once};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::Registry;
+
+use crate::events::{self, SpanLayer};
+use crate::worker::UserData;
+
+/// Log verbosity for voiceflow_set_log_level
+///
+/// Transcript text is only logged at VF_LOG_DEBUG. Warnings are reported
+/// at VF_LOG_ERROR.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VoiceFlowLogLevel {
+    VF_LOG_OFF = 0,
+    VF_LOG_ERROR = 1,
+    VF_LOG_INFO = 2,
+    VF_LOG_DEBUG = 3,
+}
+
+impl VoiceFlowLogLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::VF_LOG_OFF,
+            1 => Self::VF_LOG_ERROR,
+            2 => Self::VF_LOG_INFO,
+            _ => Self::VF_LOG_DEBUG,
+        }
+    }
+
+    fn of(level: &Level) -> Self {
+        match *level {
+            Level::ERROR | Level::WARN => Self::VF_LOG_ERROR,
+            Level::INFO => Self::VF_LOG_INFO,
+            Level::DEBUG | Level::TRACE => Self::VF_LOG_DEBUG,
+        }
+    }
+}
+
+/// Log callback: receives the level and a formatted message, valid only
+/// for the duration of the call
+pub type VoiceFlowLogCallback =
+    extern "C" fn(user_data: *mut c_void, level: VoiceFlowLogLevel, message: *const c_char);
+
+static LEVEL: AtomicU8 = AtomicU8::new(VoiceFlowLogLevel::VF_LOG_INFO as u8);
+static INSTALL: Once = Once::new();
+
+struct Sinks {
+    callback: Option<(VoiceFlowLogCallback, UserData)>,
+    file: Option<File>,
+}
+
+static SINKS: Mutex<Sinks> = Mutex::new(Sinks {
+    callback: None,
+    file: None,
+});
+
+fn sinks() -> std::sync::MutexGuard<'static, Sinks> {
+    SINKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Install the forwarding subscriber (once per process)
+///
+/// If the host process already installed a global subscriber, that one
+/// keeps receiving events and the callback/file sinks stay unused.
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        let subscriber = Registry::default().with(ForwardLayer).with(SpanLayer);
+        let _ = tracing::subscriber::set_global_default(subscriber);
+    });
+}
+
+/// Open (or close, with `None`) the log file from the config, creating its
+/// directory if needed
+///
+/// iOS builds never write log files; they log through the callback only.
+pub(crate) fn set_log_file(path: Option<&Path>) {
+    if cfg!(feature = "ios") {
+        if let Some(path) = path {
+            tracing::warn!("Ignoring log_file {:?}: iOS builds log through the callback only", path);
+        }
+        return;
+    }
+    let file = path.and_then(|p| {
+        if let Some(dir) = p.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(p)
+            .map_err(|e| tracing::error!("Failed to open log file {:?}: {}", p, e))
+            .ok()
+    });
+    sinks().file = file;
+}
+
+/// Forwards tracing events to the configured sinks
+///
+/// Its `enabled` filters for the whole subscriber: events by log level,
+/// and spans (only voiceflow's) by whether anyone listens for them.
+struct ForwardLayer;
+
+impl<S: Subscriber> Layer<S> for ForwardLayer {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        if metadata.is_span() {
+            return events::listening() && metadata.target().starts_with("voiceflow");
+        }
+        VoiceFlowLogLevel::of(metadata.level()) <= current_level()
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = VoiceFlowLogLevel::of(metadata.level());
+        if level > current_level() {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let message = visitor.message;
+
+        let mut sinks = sinks();
+        if let Some((callback, user_data)) = sinks.callback.as_ref() {
+            if let Ok(c_message) = CString::new(message.replace('\0', "")) {
+                callback(user_data.0, level, c_message.as_ptr());
+            }
+        }
+        if let Some(file) = sinks.file.as_mut() {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let _ = writeln!(
+                file,
+                "[{}] {} {}: {}",
+                timestamp,
+                metadata.level(),
+                metadata.target(),
+                message
+            );
+        }
+    }
+}
+
+fn current_level() -> VoiceFlowLogLevel {
+    VoiceFlowLogLevel::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Collects the message and any structured fields into one line
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.message, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Route log messages to a callback (e.g. to forward them into os_log)
+///
+/// Pass null to remove the callback. The callback may be invoked from any
+/// thread and must not call back into the library.
+///
+/// # Safety
+/// user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_log_callback(
+    callback: Option<VoiceFlowLogCallback>,
+    user_data: *mut c_void,
+) {
+    install();
+    sinks().callback = callback.map(|cb| (cb, UserData(user_data)));
+}
+
+/// Set the log verbosity (default VF_LOG_INFO)
+#[no_mangle]
+pub extern "C" fn voiceflow_set_log_level(level: VoiceFlowLogLevel) {
+    install();
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    // `enabled` results are cached per call site; drop them so the new
+    // level takes effect everywhere
+    tracing::callsite::rebuild_interest_cache();
+}
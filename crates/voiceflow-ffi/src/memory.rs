@@ -0,0 +1,164 @@
+//! Memory budget for constrained devices, and freeing memory under pressure
+//!
+//! With a budget set, the model catalog (voiceflow_model_count,
+//! voiceflow_whisper_model_count, voiceflow_moonshine_model_count and their
+//! _info functions) only lists models that fit, and voiceflow_download_model
+//! refuses the rest. Builds with the `ios` feature start with a 2 GiB budget;
+//! others have none until the host sets one.
+
+use std::ffi::{c_char, CString};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::TryLockError;
+
+use voiceflow_core::downloads::{self, DownloadableModel};
+use voiceflow_core::models::shared::loaded_models;
+
+use crate::error::{clear_last_error, set_last_error};
+use crate::{invalid_handle, VoiceFlowErrorCode, VoiceFlowHandle};
+
+const DEFAULT_BUDGET: u64 = if cfg!(feature = "ios") { 2 * 1024 * 1024 * 1024 } else { 0 };
+
+static BUDGET: AtomicU64 = AtomicU64::new(DEFAULT_BUDGET);
+
+/// The current budget in bytes, 0 for none
+fn budget() -> u64 {
+    BUDGET.load(Ordering::Relaxed)
+}
+
+/// Whether `model` fits in the current budget
+pub(crate) fn within_budget(model: &DownloadableModel) -> bool {
+    fits(model, budget())
+}
+
+fn fits(model: &DownloadableModel, budget: u64) -> bool {
+    budget == 0 || model.memory_bytes() <= budget
+}
+
+/// Limit the models offered to those that run in max_ram_bytes of memory
+///
+/// 0 removes the limit. Applies to the whole process, from the next catalog
+/// or download call.
+#[no_mangle]
+pub extern "C" fn voiceflow_set_memory_budget(max_ram_bytes: u64) {
+    BUDGET.store(max_ram_bytes, Ordering::Relaxed);
+}
+
+/// Suggest models for a device with max_ram_bytes of memory to spare
+///
+/// Returns a JSON object with the speech and formatting models that run
+/// together in that much memory, and every model that fits on its own, e.g.
+/// `{"stt": "whisper-small", "llm": "qwen3-1.7b", "fitting": ["whisper-tiny", ...]}`.
+/// "stt" or "llm" is null if nothing fits. 0 uses the budget from
+/// voiceflow_set_memory_budget (no limit if unset). Free the string with
+/// voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_recommended_models(max_ram_bytes: u64) -> *mut c_char {
+    clear_last_error();
+    let max_ram_bytes = if max_ram_bytes == 0 { budget() } else { max_ram_bytes };
+    CString::new(recommendation_json(max_ram_bytes).to_string())
+        .map(|s| s.into_raw())
+        .unwrap_or(ptr::null_mut())
+}
+
+fn recommendation_json(max_ram_bytes: u64) -> serde_json::Value {
+    let recommended = downloads::recommended_models(max_ram_bytes);
+    let fitting: Vec<_> = downloads::models_within(max_ram_bytes)
+        .iter()
+        .map(|model| model.id())
+        .collect();
+    serde_json::json!({
+        "stt": recommended.stt.as_ref().map(DownloadableModel::id),
+        "llm": recommended.llm.as_ref().map(DownloadableModel::id),
+        "fitting": fitting,
+    })
+}
+
+/// Free memory after a memory warning, keeping the handle usable
+///
+/// Unloads the LLM with its KV cache; it reloads on the next request that
+/// formats, so that request is slower. The weights stay loaded while
+/// another handle shares them (see voiceflow_loaded_models). Returns false
+/// if there was nothing to free, or if a request is running (try again once
+/// it returns).
+///
+/// # Safety
+/// handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_trim_memory(handle: *mut VoiceFlowHandle) -> bool {
+    clear_last_error();
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return false;
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let mut pipeline = match handle.pipeline.try_lock() {
+        Ok(pipeline) => pipeline,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return false,
+    };
+    pipeline.trim_memory()
+}
+
+/// List the models whose weights are loaded, as a JSON array
+///
+/// Handles initialized with the same model file and load settings share
+/// its weights, each keeping only its own decoder state and KV cache, and
+/// the weights are freed with the last handle using them. Each entry has
+/// "kind" ("whisper", "moonshine" or "llm"), "path" and "users" (the
+/// engines sharing the weights, one per handle), e.g. `[{"kind": "llm",
+/// "path": ".../qwen3-1.7b-q4_k_m.gguf", "users": 2}]`. Free the string
+/// with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_loaded_models() -> *mut c_char {
+    clear_last_error();
+    match serde_json::to_string(&loaded_models()) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voiceflow_core::config::{ModelPrecision, WhisperModel};
+
+    #[test]
+    fn test_budget_filters_models() {
+        let medium = DownloadableModel::Whisper(WhisperModel::Medium, ModelPrecision::Float32);
+        assert!(fits(&medium, 0));
+        assert!(!fits(&medium, 1024 * 1024 * 1024));
+        let base = DownloadableModel::Whisper(WhisperModel::Base, ModelPrecision::Float32);
+        assert!(fits(&base, 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_recommendation_json() {
+        let json = recommendation_json(3 * 1024 * 1024 * 1024);
+        assert_eq!(json["llm"], "qwen3-1.7b");
+        assert!(json["stt"].is_string());
+        assert!(json["fitting"].as_array().unwrap().iter().any(|id| id == "whisper-tiny"));
+
+        let json = recommendation_json(1);
+        assert!(json["stt"].is_null() && json["llm"].is_null());
+        assert_eq!(json["fitting"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_trim_memory_null_handle() {
+        assert!(!unsafe { voiceflow_trim_memory(std::ptr::null_mut()) });
+    }
+
+    #[test]
+    fn test_loaded_models_json() {
+        let ptr = voiceflow_loaded_models();
+        assert!(!ptr.is_null());
+        let json = unsafe { std::ffi::CStr::from_ptr(ptr) }.to_str().unwrap().to_string();
+        unsafe { crate::voiceflow_free_string(ptr) };
+        assert!(serde_json::from_str::<serde_json::Value>(&json).unwrap().is_array());
+    }
+}
@@ -0,0 +1,100 @@
+//! Choosing where models are stored and moving them there
+
+use std::ffi::{c_char, c_void};
+use std::path::{Path, PathBuf};
+
+use voiceflow_core::models::storage;
+use voiceflow_core::Config;
+
+use crate::error::{clear_last_error, set_last_error, set_last_error_from, voiceflow_last_error_code};
+use crate::panic_report::caught_panic;
+use crate::worker::UserData;
+use crate::{save_config, str_arg, VoiceFlowErrorCode};
+
+/// Progress callback for voiceflow_migrate_models_dir
+///
+/// Called on the migrating thread with the caller's user_data, the bytes
+/// copied so far and the total to copy.
+pub type VoiceFlowMigrateProgressCallback = extern "C" fn(user_data: *mut c_void, bytes_copied: u64, bytes_total: u64);
+
+/// Store models in `path` instead of the platform data directory
+///
+/// The directory is created if needed and must be writable. The setting is
+/// saved to the config file and used by handles created afterwards; models
+/// already downloaded stay where they are (see voiceflow_migrate_models_dir).
+/// An empty path goes back to the platform default. Apps that don't use the
+/// config file set "models_dir_override" in voiceflow_init_with_config_json
+/// instead.
+///
+/// # Safety
+/// path must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_models_dir(path: *const c_char) -> bool {
+    clear_last_error();
+    let Some(path) = str_arg(path, "path") else {
+        return false;
+    };
+
+    let dir = (!path.is_empty()).then(|| PathBuf::from(path));
+    if let Some(dir) = &dir {
+        if let Err(e) = storage::check_writable(dir) {
+            set_last_error_from(&e);
+            return false;
+        }
+    }
+    let mut config = Config::load_file(None).unwrap_or_default();
+    config.models_dir_override = dir;
+    save_config(&config)
+}
+
+/// Move every model from old_dir to new_dir
+///
+/// Each file is copied and verified against the original before any
+/// original is removed, so on failure old_dir is left intact. Files already
+/// in new_dir with the same contents are not copied again. Blocks until
+/// done: call it off the main thread, with no handle using old_dir, then
+/// call voiceflow_set_models_dir(new_dir). progress_callback may be null.
+/// Returns VF_ERR_OK, or the error code (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - old_dir and new_dir must be valid null-terminated strings
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_migrate_models_dir(
+    old_dir: *const c_char,
+    new_dir: *const c_char,
+    progress_callback: Option<VoiceFlowMigrateProgressCallback>,
+    user_data: *mut c_void,
+) -> VoiceFlowErrorCode {
+    clear_last_error();
+    let (Some(from), Some(to)) = (str_arg(old_dir, "old_dir"), str_arg(new_dir, "new_dir")) else {
+        return voiceflow_last_error_code();
+    };
+    if from.is_empty() || to.is_empty() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "old_dir and new_dir must not be empty");
+        return VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT;
+    }
+
+    let user_data = UserData(user_data);
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        storage::migrate_models_dir(Path::new(from), Path::new(to), |copied, total| {
+            if let Some(callback) = progress_callback {
+                callback(user_data.0, copied, total);
+            }
+        })
+    }));
+    match outcome {
+        Ok(Ok(_)) => VoiceFlowErrorCode::VF_ERR_OK,
+        Ok(Err(e)) => {
+            tracing::error!("Moving models to {} failed: {:#}", to, e);
+            set_last_error_from(&e);
+            voiceflow_last_error_code()
+        }
+        Err(panic) => {
+            let msg = caught_panic(panic);
+            tracing::error!("PANIC caught while moving models: {}", msg);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+            VoiceFlowErrorCode::VF_ERR_PANIC
+        }
+    }
+}
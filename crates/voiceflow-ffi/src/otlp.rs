@@ -0,0 +1,197 @@
+//! OTLP/HTTP export of pipeline spans (the `otlp` feature)
+//!
+//! Only runs while `telemetry.endpoint` is set in the config. Closed spans
+//! are queued for a background thread that posts them as OTLP JSON to the
+//! endpoint's /v1/traces, in batches, at most a second after they close.
+//! When the collector is slow or down, spans are dropped rather than
+//! holding up the pipeline.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::events::SpanEvent;
+
+/// Longest a closed span waits before it's sent
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Most spans per request
+const MAX_BATCH: usize = 256;
+/// Spans waiting to be sent before new ones are dropped
+const QUEUE_SIZE: usize = 4096;
+
+struct Exporter {
+    url: String,
+    queue: SyncSender<SpanEvent>,
+}
+
+static EXPORTER: Mutex<Option<Exporter>> = Mutex::new(None);
+
+fn exporter() -> MutexGuard<'static, Option<Exporter>> {
+    EXPORTER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start exporting to `endpoint`, or stop with `None`
+///
+/// A replaced exporter sends what it has queued, then its thread ends.
+pub(crate) fn set_endpoint(endpoint: Option<&str>) {
+    let url = endpoint.map(traces_url);
+    let mut exporter = exporter();
+    if exporter.as_ref().map(|e| &e.url) == url.as_ref() {
+        return;
+    }
+    *exporter = url.and_then(|url| {
+        let (queue, spans) = mpsc::sync_channel(QUEUE_SIZE);
+        let thread_url = url.clone();
+        std::thread::Builder::new()
+            .name("voiceflow-otlp".to_string())
+            .spawn(move || run(&thread_url, spans))
+            .map_err(|e| tracing::error!("Failed to start the OTLP exporter: {}", e))
+            .ok()?;
+        tracing::info!("Exporting pipeline spans to {}", url);
+        Some(Exporter { url, queue })
+    });
+}
+
+/// Whether an endpoint is set
+pub(crate) fn enabled() -> bool {
+    exporter().is_some()
+}
+
+/// Queue a closed span, dropping it if the queue is full
+pub(crate) fn export(span: SpanEvent) {
+    if let Some(exporter) = exporter().as_ref() {
+        let _ = exporter.queue.try_send(span);
+    }
+}
+
+/// The traces URL of a collector, e.g. "http://localhost:4318/v1/traces"
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+/// Send batches of spans until the exporter is replaced or removed
+fn run(url: &str, spans: Receiver<SpanEvent>) {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(10)).build();
+    let mut failing = false;
+    while let Ok(first) = spans.recv() {
+        let deadline = Instant::now() + FLUSH_INTERVAL;
+        let mut batch = vec![first];
+        let mut closed = false;
+        while batch.len() < MAX_BATCH {
+            match spans.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(span) => batch.push(span),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        let body = request_body(&batch).to_string();
+        match agent.post(url).set("Content-Type", "application/json").send_string(&body) {
+            Ok(_) if failing => {
+                tracing::info!("OTLP collector at {} is reachable again", url);
+                failing = false;
+            }
+            Ok(_) => {}
+            // Warn once per outage rather than once per batch
+            Err(e) if !failing => {
+                tracing::warn!("Failed to export {} spans to {}: {}", batch.len(), url, e);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+/// An OTLP/JSON ExportTraceServiceRequest
+fn request_body(spans: &[SpanEvent]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &json!("voiceflow"))],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "voiceflow", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans.iter().map(otlp_span).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+fn otlp_span(span: &SpanEvent) -> Value {
+    json!({
+        "traceId": format!("{:032x}", span.trace_id),
+        "spanId": format!("{:016x}", span.span_id),
+        "parentSpanId": span.parent_span_id.map(|id| format!("{:016x}", id)).unwrap_or_default(),
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": span.start_unix_ns.to_string(),
+        "endTimeUnixNano": (span.start_unix_ns + span.duration_ns).to_string(),
+        "attributes": span.fields.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<_>>(),
+    })
+}
+
+/// A key-value attribute; OTLP/JSON writes 64-bit integers as strings
+fn attribute(key: &str, value: &Value) -> Value {
+    let value = match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_f64() => json!({ "doubleValue": n }),
+        Value::Number(n) => json!({ "intValue": n.to_string() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        other => json!({ "stringValue": other.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Map;
+
+    #[test]
+    fn test_traces_url() {
+        assert_eq!(traces_url("http://localhost:4318"), "http://localhost:4318/v1/traces");
+        assert_eq!(traces_url("http://localhost:4318/"), "http://localhost:4318/v1/traces");
+        assert_eq!(traces_url("https://otel.example.com/v1/traces"), "https://otel.example.com/v1/traces");
+    }
+
+    #[test]
+    fn test_request_body_follows_otlp_json() {
+        let mut fields = Map::new();
+        fields.insert("audio_ms".to_string(), json!(4200));
+        fields.insert("provider".to_string(), json!("CoreML"));
+        let span = SpanEvent {
+            name: "stt",
+            target: "voiceflow_core::pipeline",
+            trace_id: 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736,
+            span_id: 0x00f0_67aa_0ba9_02b7,
+            parent_span_id: None,
+            start_unix_ns: 1_000,
+            duration_ns: 500,
+            fields,
+        };
+
+        let body = request_body(&[span]);
+        let otlp = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(otlp["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(otlp["spanId"], "00f067aa0ba902b7");
+        assert_eq!(otlp["parentSpanId"], "");
+        assert_eq!(otlp["endTimeUnixNano"], "1500");
+        let attributes = otlp["attributes"].as_array().unwrap();
+        assert!(attributes.contains(&json!({ "key": "audio_ms", "value": { "intValue": "4200" } })));
+        assert!(attributes.contains(&json!({ "key": "provider", "value": { "stringValue": "CoreML" } })));
+    }
+}
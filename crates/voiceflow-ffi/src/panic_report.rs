@@ -0,0 +1,220 @@
+//! Panic reports for the host app's crash reporter
+//!
+//! Every entry point catches panics so they never unwind into the host, but
+//! the message alone makes for poor bug reports. A panic hook (installed by
+//! the first fallible call) captures the backtrace and thread while the
+//! stack is still there; when an entry point catches the panic, the report
+//! is completed, kept in a small ring buffer for voiceflow_last_panic_report
+//! and handed to the callback set with voiceflow_set_panic_callback.
+//!
+//! The hook only copies data into a thread-local slot: a panic inside a
+//! panic hook aborts the process, so everything that could fail (formatting
+//! the backtrace, serializing, the callback, dropping the payload) happens
+//! after the catch, itself under catch_unwind.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, Once};
+
+use serde_json::json;
+
+use crate::error::panic_message;
+use crate::worker::UserData;
+
+/// Panic reports kept for voiceflow_last_panic_report
+const MAX_REPORTS: usize = 8;
+
+/// Panic callback: receives a panic report as a JSON string (see
+/// voiceflow_last_panic_report), valid only for the duration of the call
+pub type VoiceFlowPanicCallback = extern "C" fn(user_data: *mut c_void, report_json: *const c_char);
+
+/// What the hook captures before the stack unwinds
+struct Captured {
+    location: Option<String>,
+    backtrace: Backtrace,
+    thread: String,
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+static INSTALL: Once = Once::new();
+static REPORTS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static CALLBACK: Mutex<Option<(VoiceFlowPanicCallback, UserData)>> = Mutex::new(None);
+
+fn reports() -> MutexGuard<'static, VecDeque<String>> {
+    REPORTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Install the capturing panic hook (once per process), in front of the
+/// hook that was there before
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let captured = Captured {
+                location: info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+                backtrace: Backtrace::force_capture(),
+                thread: std::thread::current().name().unwrap_or("unnamed").to_string(),
+            };
+            // `try_` so a panic while the slot is borrowed can't panic again
+            let _ = CAPTURED.try_with(|slot| {
+                if let Ok(mut slot) = slot.try_borrow_mut() {
+                    *slot = Some(captured);
+                }
+            });
+            previous(info);
+        }));
+    });
+}
+
+/// Complete the report of a panic an entry point caught, record it and pass
+/// it to the panic callback, returning the panic message
+///
+/// A panic while reporting (including one dropping the payload) is caught
+/// and leaves the report out rather than unwinding into the host.
+pub(crate) fn caught_panic(payload: Box<dyn Any + Send>) -> String {
+    let message = panic_message(payload.as_ref());
+    let captured = CAPTURED.try_with(|slot| slot.try_borrow_mut().ok().and_then(|mut slot| slot.take()));
+    let report = message.clone();
+    let reported = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+        drop(payload);
+        record(report, captured.ok().flatten());
+    }));
+    if let Err(panic) = reported {
+        // Dropping this payload could panic too; leak it instead
+        std::mem::forget(panic);
+        tracing::error!("Panic while reporting a panic");
+    }
+    message
+}
+
+fn record(message: String, captured: Option<Captured>) {
+    let thread = match &captured {
+        Some(captured) => captured.thread.clone(),
+        None => std::thread::current().name().unwrap_or("unnamed").to_string(),
+    };
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let report = json!({
+        "message": message,
+        "location": captured.as_ref().and_then(|c| c.location.clone()),
+        "backtrace": captured.as_ref().map(|c| c.backtrace.to_string()).unwrap_or_default(),
+        "thread": thread,
+        "version": env!("CARGO_PKG_VERSION"),
+        "build_hash": env!("VOICEFLOW_BUILD_HASH"),
+        "timestamp": timestamp,
+    })
+    .to_string();
+
+    {
+        let mut reports = reports();
+        if reports.len() == MAX_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(report.clone());
+    }
+
+    // Not called under the lock: a slow callback mustn't hold up other threads
+    let callback = CALLBACK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|(callback, user_data)| (*callback, user_data.0));
+    if let Some((callback, user_data)) = callback {
+        if let Ok(c_report) = CString::new(report.replace('\0', "")) {
+            callback(user_data, c_report.as_ptr());
+        }
+    }
+}
+
+/// Get the most recent panic caught in the library as a JSON object
+///
+/// The object has "message", "location" ("file:line:column"),
+/// "backtrace", "thread", "version", "build_hash" and "timestamp" (seconds
+/// since the Unix epoch). The last 8 reports are kept; see
+/// voiceflow_panic_reports. Returns null if nothing has panicked. Free the
+/// string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_last_panic_report() -> *mut c_char {
+    reports()
+        .back()
+        .and_then(|json| CString::new(json.replace('\0', "")).ok())
+        .map_or(ptr::null_mut(), |s| s.into_raw())
+}
+
+/// Get the kept panic reports (up to 8, oldest first) as a JSON array of
+/// the objects voiceflow_last_panic_report returns
+///
+/// Free the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_panic_reports() -> *mut c_char {
+    let json = format!("[{}]", reports().iter().map(String::as_str).collect::<Vec<_>>().join(","));
+    CString::new(json.replace('\0', "")).map_or(ptr::null_mut(), |s| s.into_raw())
+}
+
+/// Forward panic reports to a callback (e.g. the app's crash reporter)
+///
+/// The callback gets each report as it is caught, as the JSON object of
+/// voiceflow_last_panic_report, on the thread that panicked. Pass null to
+/// remove it. The callback must not call back into the library.
+///
+/// # Safety
+/// user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_set_panic_callback(
+    callback: Option<VoiceFlowPanicCallback>,
+    user_data: *mut c_void,
+) {
+    install();
+    *CALLBACK.lock().unwrap_or_else(|e| e.into_inner()) = callback.map(|cb| (cb, UserData(user_data)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catch(f: impl FnOnce()) -> Box<dyn Any + Send> {
+        install();
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err()
+    }
+
+    fn find_report(message: &str) -> Option<serde_json::Value> {
+        reports()
+            .iter()
+            .map(|json| serde_json::from_str::<serde_json::Value>(json).unwrap())
+            .find(|report| report["message"] == message)
+    }
+
+    #[test]
+    fn test_caught_panic_is_reported() {
+        let payload = catch(|| panic!("report test {}", 42));
+        assert_eq!(caught_panic(payload), "report test 42");
+
+        let report = find_report("report test 42").expect("report recorded");
+        assert!(report["location"].as_str().unwrap().contains("panic_report.rs"));
+        assert!(!report["backtrace"].as_str().unwrap().is_empty());
+        assert_eq!(report["version"], env!("CARGO_PKG_VERSION"));
+        assert!(report["thread"].as_str().unwrap().contains("test_caught_panic_is_reported"));
+    }
+
+    #[test]
+    fn test_panicking_payload_does_not_escape() {
+        struct PanicsOnDrop;
+        impl Drop for PanicsOnDrop {
+            fn drop(&mut self) {
+                panic!("drop panic");
+            }
+        }
+
+        let payload = catch(|| std::panic::panic_any(PanicsOnDrop));
+        assert_eq!(caught_panic(payload), "Unknown panic");
+    }
+}
@@ -0,0 +1,103 @@
+//! Named profiles from the config file, listed, saved from a handle and
+//! switched to on a live handle
+
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+use voiceflow_core::Config;
+
+use crate::error::{clear_last_error, set_last_error, set_last_error_from};
+use crate::{
+    events, invalid_handle, lock_pipeline, logging, save_config, str_arg, with_pipeline_unready, VoiceFlowErrorCode,
+    VoiceFlowHandle,
+};
+
+/// Get the names of the profiles in the config file as a JSON array, sorted
+///
+/// Free the string with voiceflow_free_string.
+#[no_mangle]
+pub extern "C" fn voiceflow_profile_list() -> *mut c_char {
+    clear_last_error();
+    let config = Config::load(None).unwrap_or_default();
+    match serde_json::to_string(&config.profile_names()) {
+        Ok(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        Err(e) => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Save the handle's current settings as a profile in the config file
+///
+/// The profile holds the settings that differ from the rest of the config
+/// file, and replaces any profile with the same name. The log file and
+/// models directory aren't part of profiles. Returns false if the name is
+/// empty or the config can't be saved (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - name must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_profile_save_current(handle: *mut VoiceFlowHandle, name: *const c_char) -> bool {
+    clear_last_error();
+    let Some(name) = str_arg(name, "name") else {
+        return false;
+    };
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return false;
+    }
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let current = lock_pipeline(&handle.pipeline).config().clone();
+
+    let mut config = Config::load_file(None).unwrap_or_default();
+    if let Err(e) = config.save_profile(name, &current) {
+        set_last_error_from(&e);
+        return false;
+    }
+    save_config(&config)
+}
+
+/// Switch a running handle to a profile from the config file
+///
+/// The handle gets the config file's settings with the profile applied (and
+/// the VOICEFLOW_* environment overrides), as voiceflow_init_with_profile
+/// would load them. Only the models whose settings changed are reloaded,
+/// so this blocks for as long as loading them takes: call it off the main
+/// thread. Returns a JSON object listing the reloaded components, e.g.
+/// `{"reloaded": ["stt", "llm"]}`, or null for an unknown profile, invalid
+/// settings or a model that fails to load (see
+/// voiceflow_last_error_message); settings applied before the failure are
+/// kept. Free the string with voiceflow_free_string.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - name must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_profile_activate(handle: *mut VoiceFlowHandle, name: *const c_char) -> *mut c_char {
+    clear_last_error();
+    let Some(name) = str_arg(name, "name") else {
+        return ptr::null_mut();
+    };
+
+    let activated = with_pipeline_unready(handle, "Profile activation", |pipeline| {
+        let config = Config::load_profile(None, name)?;
+        let log_file = pipeline.config().log_file.clone();
+        let telemetry_endpoint = pipeline.config().telemetry.endpoint.clone();
+        let reloaded = pipeline.apply_config(&config)?;
+        if config.log_file != log_file {
+            logging::set_log_file(config.log_file_path().as_deref());
+        }
+        if config.telemetry.endpoint != telemetry_endpoint {
+            events::set_telemetry(&config.telemetry);
+        }
+        tracing::info!("Activated profile {:?}", name);
+        Ok(serde_json::json!({ "reloaded": reloaded }).to_string())
+    });
+    match activated {
+        Some(json) => CString::new(json).map(|s| s.into_raw()).unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    }
+}
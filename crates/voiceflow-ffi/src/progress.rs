@@ -0,0 +1,162 @@
+//! Processing that reports its progress, for long recordings
+
+use std::ffi::{c_char, c_float, c_void};
+
+use voiceflow_core::{CancelToken, ProcessProgress, ProcessStage, ProgressReporter, ProgressSink};
+
+use crate::error::{clear_last_error, set_last_error};
+use crate::worker::UserData;
+use crate::{
+    context_arg, error_result, invalid_handle, process_audio, process_options, VoiceFlowErrorCode, VoiceFlowHandle,
+    VoiceFlowProcessOptions, VoiceFlowResult,
+};
+
+/// Stage of a request reported to the progress callback
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowProcessStage {
+    /// Downmixing and resampling (only for audio in another format)
+    VF_STAGE_RESAMPLING = 0,
+    /// Finding the speech to transcribe
+    VF_STAGE_DETECTING_SPEECH = 1,
+    /// done and total count chunks
+    VF_STAGE_TRANSCRIBING = 2,
+    /// done counts tokens generated, total the estimated most
+    VF_STAGE_FORMATTING = 3,
+    /// The result is ready, at fraction 1
+    VF_STAGE_DONE = 4,
+}
+
+impl From<ProcessStage> for VoiceFlowProcessStage {
+    fn from(stage: ProcessStage) -> Self {
+        match stage {
+            ProcessStage::Resampling => Self::VF_STAGE_RESAMPLING,
+            ProcessStage::DetectingSpeech => Self::VF_STAGE_DETECTING_SPEECH,
+            ProcessStage::Transcribing => Self::VF_STAGE_TRANSCRIBING,
+            ProcessStage::Formatting => Self::VF_STAGE_FORMATTING,
+            ProcessStage::Done => Self::VF_STAGE_DONE,
+        }
+    }
+}
+
+/// Progress callback for voiceflow_process_with_progress
+///
+/// Called on the calling thread with the caller's user_data, the stage, the
+/// overall progress (0 to 1, never lower than in the call before) and the
+/// steps of the stage done and to do (0 and 0 for stages without steps).
+/// Return false to cancel the request, as voiceflow_cancel does.
+pub type VoiceFlowProgressCallback = extern "C" fn(
+    user_data: *mut c_void,
+    stage: VoiceFlowProcessStage,
+    fraction: c_float,
+    done: u32,
+    total: u32,
+) -> bool;
+
+/// Forwards core progress to the C callback, cancelling the request when
+/// the callback returns false
+struct CallbackProgress {
+    callback: VoiceFlowProgressCallback,
+    user_data: UserData,
+    cancel: CancelToken,
+}
+
+// The reporter is shared, but only the thread running the request calls
+// the sink, and user_data is only passed back to the caller's callback.
+unsafe impl Sync for CallbackProgress {}
+
+impl ProgressSink for CallbackProgress {
+    fn progress(&self, progress: ProcessProgress) {
+        let ProcessProgress { stage, fraction, done, total } = progress;
+        if !(self.callback)(self.user_data.0, stage.into(), fraction, done, total) {
+            tracing::debug!("Request cancelled from the progress callback");
+            self.cancel.cancel();
+        }
+    }
+}
+
+/// Process audio samples with per-call options, reporting progress to
+/// `progress_callback` as the request runs
+///
+/// Same as voiceflow_process_opts otherwise. The callback runs on the
+/// calling thread at most about ten times a second, with the stages in
+/// order and a last call at VF_STAGE_DONE when the request succeeds. It
+/// can return false to cancel, and voiceflow_cancel works as for any
+/// request; either way the result fails with VF_ERR_CANCELLED.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - context can be null
+/// - options can be null for the defaults (see voiceflow_process_opts)
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process_with_progress(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    context: *const c_char,
+    options: *const VoiceFlowProcessOptions,
+    progress_callback: Option<VoiceFlowProgressCallback>,
+    user_data: *mut c_void,
+) -> VoiceFlowResult {
+    tracing::debug!("voiceflow_process_with_progress called with {} samples", audio_len);
+    clear_last_error();
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle or audio data");
+        return error_result("Invalid handle or audio data");
+    }
+    let Some(callback) = progress_callback else {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "progress_callback must not be null");
+        return error_result("progress_callback must not be null");
+    };
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+    let context_str = context_arg(context);
+    let mut process_options = match process_options(options) {
+        Ok(process_options) => process_options,
+        Err(message) => return error_result(message),
+    };
+    let cancel = handle.cancel.begin();
+    process_options.progress = Some(ProgressReporter::new(CallbackProgress {
+        callback,
+        user_data: UserData(user_data),
+        cancel: cancel.clone(),
+    }));
+
+    process_audio(&handle.pipeline, &cancel, audio, context_str.as_deref(), process_options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::voiceflow_last_error_code;
+    use std::ptr;
+
+    extern "C" fn keep_going(_: *mut c_void, _: VoiceFlowProcessStage, _: c_float, _: u32, _: u32) -> bool {
+        true
+    }
+
+    #[test]
+    fn test_null_handle_fails() {
+        let audio = [0.0f32; 160];
+        let result = unsafe {
+            voiceflow_process_with_progress(
+                ptr::null_mut(),
+                audio.as_ptr(),
+                audio.len(),
+                ptr::null(),
+                ptr::null(),
+                Some(keep_going),
+                ptr::null_mut(),
+            )
+        };
+        assert!(!result.success);
+        assert_eq!(voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+        unsafe { crate::voiceflow_free_result(result) };
+    }
+}
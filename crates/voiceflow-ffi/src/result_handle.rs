@@ -0,0 +1,652 @@
+//! Results behind an opaque handle, read through accessors, so new result
+//! fields don't change the layout callers compiled against
+
+use std::ffi::{c_char, c_float, CString};
+use std::ptr;
+
+use voiceflow_core::{PipelineError, PipelineResult, ProcessOptions, Timings};
+
+use crate::error::{classify, clear_last_error, set_last_error, set_last_error_from};
+use crate::live;
+use crate::panic_report::caught_panic;
+use crate::{
+    c_string, context_arg, error_result, invalid_handle, pipeline_result, run_pipeline, sanitize_result,
+    VoiceFlowErrorCode, VoiceFlowHandle, VoiceFlowResult,
+};
+
+/// Timing read by voiceflow_result_timing, in milliseconds
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceFlowTimingKind {
+    VF_TIMING_TOTAL = 0,
+    VF_TIMING_TRANSCRIPTION = 1,
+    VF_TIMING_LLM = 2,
+    /// Silence trimmed by VAD before transcription
+    VF_TIMING_TRIMMED = 3,
+    /// Downmixing and resampling to 16kHz mono
+    VF_TIMING_AUDIO_PREP = 4,
+    VF_TIMING_VAD = 5,
+    VF_TIMING_STT_ENCODE = 6,
+    VF_TIMING_STT_DECODE = 7,
+    /// LLM prompt processing, until the first token
+    VF_TIMING_LLM_PREFILL = 8,
+    VF_TIMING_LLM_GENERATE = 9,
+    /// Loading the LLM on its first use, part of VF_TIMING_LLM
+    VF_TIMING_LLM_LOAD = 10,
+    /// Reloading the STT engine after an idle unload, part of
+    /// VF_TIMING_TRANSCRIPTION
+    VF_TIMING_STT_LOAD = 11,
+}
+
+/// One segment of a result, read by voiceflow_result_segment
+///
+/// The strings are owned by the result handle.
+#[repr(C)]
+pub struct VoiceFlowSegment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub raw_text: *const c_char,
+    pub formatted_text: *const c_char,
+    /// Mean STT word probability (0.0 - 1.0)
+    pub confidence: c_float,
+    /// Speaker number from 0, or -1 when diarization is off
+    pub speaker: i32,
+}
+
+/// Outcome of a voiceflow_process2 call
+///
+/// Strings returned by the accessors are owned by the handle and stay valid
+/// until voiceflow_result_free. Accessors given a freed handle read it as
+/// null.
+pub struct VoiceFlowResultHandle {
+    outcome: Result<ResultTexts, Failure>,
+}
+
+struct ResultTexts {
+    result: PipelineResult,
+    formatted_text: CString,
+    raw_transcript: CString,
+    formatting_error: Option<CString>,
+    /// Raw and formatted text of each segment
+    segments: Vec<(CString, CString)>,
+}
+
+struct Failure {
+    code: VoiceFlowErrorCode,
+    message: CString,
+    /// Time spent before a cancelled run stopped
+    timings: Timings,
+}
+
+impl VoiceFlowResultHandle {
+    /// Record the outcome of a run, setting the last error if it failed
+    fn new(outcome: std::thread::Result<anyhow::Result<PipelineResult>>) -> Self {
+        let outcome = match outcome {
+            Ok(Ok(mut result)) => {
+                sanitize_result(&mut result);
+                Ok(ResultTexts {
+                    formatted_text: c_string(&result.formatted_text),
+                    raw_transcript: c_string(&result.raw_transcript),
+                    formatting_error: result.formatting_error.as_deref().map(c_string),
+                    segments: result
+                        .segments
+                        .iter()
+                        .map(|segment| (c_string(&segment.raw_text), c_string(&segment.formatted_text)))
+                        .collect(),
+                    result,
+                })
+            }
+            Ok(Err(e)) => {
+                tracing::error!("Pipeline processing failed: {:#}", e);
+                set_last_error_from(&e);
+                let timings = match e.downcast_ref::<PipelineError>().map(PipelineError::without_context) {
+                    Some(PipelineError::Cancelled { timings }) => (**timings).clone(),
+                    _ => Timings::default(),
+                };
+                Err(Failure { code: classify(&e), message: c_string(&format!("{:#}", e)), timings })
+            }
+            Err(e) => {
+                let msg = format!("Internal error: {}", caught_panic(e));
+                tracing::error!("PANIC caught in voiceflow_process2: {}", msg);
+                set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, msg.as_str());
+                Err(Failure {
+                    code: VoiceFlowErrorCode::VF_ERR_PANIC,
+                    message: c_string(&msg),
+                    timings: Timings::default(),
+                })
+            }
+        };
+        Self { outcome }
+    }
+
+    fn invalid_argument(message: &str) -> Self {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, message);
+        Self {
+            outcome: Err(Failure {
+                code: VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                message: c_string(message),
+                timings: Timings::default(),
+            }),
+        }
+    }
+
+    /// Hand the handle to the caller, recording it as live
+    fn into_raw(self) -> *mut Self {
+        let result = Box::into_raw(Box::new(self));
+        live::RESULT_HANDLES.insert(result);
+        result
+    }
+
+    /// The handle behind `result`, or None if it's null or was freed
+    ///
+    /// # Safety
+    /// result must be null or a pointer from voiceflow_process2
+    unsafe fn from_ptr<'a>(result: *const Self) -> Option<&'a Self> {
+        if live::RESULT_HANDLES.check(result) {
+            Some(&*result)
+        } else {
+            None
+        }
+    }
+
+    /// The same outcome as a flat result
+    pub(crate) fn into_result(self) -> VoiceFlowResult {
+        match self.outcome {
+            Ok(texts) => pipeline_result(Ok(texts.result)),
+            Err(failure) => {
+                let mut result = error_result(&failure.message.to_string_lossy());
+                result.transcription_ms = failure.timings.transcription_ms;
+                result.llm_ms = failure.timings.llm_formatting_ms;
+                result.total_ms = failure.timings.total_ms;
+                result.timings = (&failure.timings).into();
+                result
+            }
+        }
+    }
+
+    fn timings(&self) -> &Timings {
+        match &self.outcome {
+            Ok(texts) => &texts.result.timings,
+            Err(failure) => &failure.timings,
+        }
+    }
+}
+
+/// Process audio samples, returning a result handle
+///
+/// Same as voiceflow_process, but the result is read through the
+/// voiceflow_result_* accessors, which keep working as fields are added.
+/// Never returns null, even on error: check voiceflow_result_error. Free the
+/// handle with voiceflow_result_free.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - context can be null
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process2(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    context: *const c_char,
+) -> *mut VoiceFlowResultHandle {
+    tracing::debug!("voiceflow_process2 called with {} samples", audio_len);
+    clear_last_error();
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        tracing::error!("Invalid handle or audio data");
+        return VoiceFlowResultHandle::invalid_argument("Invalid handle or audio data").into_raw();
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+    let context_str = context_arg(context);
+
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        run_pipeline(&handle.pipeline, &handle.cancel.begin(), audio, context_str.as_deref(), ProcessOptions::default(), None)
+    }));
+    VoiceFlowResultHandle::new(outcome).into_raw()
+}
+
+/// voiceflow_process2 for the request given `request_id` by
+/// voiceflow_new_request, which voiceflow_cancel_request cancels
+///
+/// An id not reserved, or used already, fails with
+/// VF_ERR_INVALID_ARGUMENT.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - context can be null
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process2_request(
+    handle: *mut VoiceFlowHandle,
+    request_id: u64,
+    audio_data: *const c_float,
+    audio_len: usize,
+    context: *const c_char,
+) -> *mut VoiceFlowResultHandle {
+    tracing::debug!("voiceflow_process2_request called for request {} with {} samples", request_id, audio_len);
+    clear_last_error();
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        tracing::error!("Invalid handle or audio data");
+        return VoiceFlowResultHandle::invalid_argument("Invalid handle or audio data").into_raw();
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let Some(cancel) = handle.cancel.begin_reserved(request_id) else {
+        return VoiceFlowResultHandle::invalid_argument("Unknown request id, or one used already").into_raw();
+    };
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+    let context_str = context_arg(context);
+
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        run_pipeline(&handle.pipeline, &cancel, audio, context_str.as_deref(), ProcessOptions::default(), None)
+    }));
+    VoiceFlowResultHandle::new(outcome).into_raw()
+}
+
+/// Formatted text of a result, or null if the call failed
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_formatted_text(result: *const VoiceFlowResultHandle) -> *const c_char {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Ok(texts)) => texts.formatted_text.as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+/// Raw transcript of a result, or null if the call failed
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_raw_transcript(result: *const VoiceFlowResultHandle) -> *const c_char {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Ok(texts)) => texts.raw_transcript.as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+/// Error message of a result, or null if the call succeeded
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_error(result: *const VoiceFlowResultHandle) -> *const c_char {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Err(failure)) => failure.message.as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+/// Error code of a result: VF_ERR_OK if the call succeeded
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_error_code(result: *const VoiceFlowResultHandle) -> VoiceFlowErrorCode {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Ok(_)) => VoiceFlowErrorCode::VF_ERR_OK,
+        Some(Err(failure)) => failure.code,
+        None => VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// One timing of a result, in milliseconds
+///
+/// Stages that didn't run are 0. A cancelled call keeps the time spent
+/// before it stopped; other failures are 0.
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_timing(
+    result: *const VoiceFlowResultHandle,
+    kind: VoiceFlowTimingKind,
+) -> u64 {
+    let Some(result) = VoiceFlowResultHandle::from_ptr(result) else {
+        return 0;
+    };
+    let timings = result.timings();
+    match kind {
+        VoiceFlowTimingKind::VF_TIMING_TOTAL => timings.total_ms,
+        VoiceFlowTimingKind::VF_TIMING_TRANSCRIPTION => timings.transcription_ms,
+        VoiceFlowTimingKind::VF_TIMING_LLM => timings.llm_formatting_ms,
+        VoiceFlowTimingKind::VF_TIMING_TRIMMED => timings.trimmed_ms,
+        VoiceFlowTimingKind::VF_TIMING_AUDIO_PREP => timings.audio_prep_ms,
+        VoiceFlowTimingKind::VF_TIMING_VAD => timings.vad_ms,
+        VoiceFlowTimingKind::VF_TIMING_STT_ENCODE => timings.stt_encode_ms,
+        VoiceFlowTimingKind::VF_TIMING_STT_DECODE => timings.stt_decode_ms,
+        VoiceFlowTimingKind::VF_TIMING_LLM_PREFILL => timings.llm_prefill_ms,
+        VoiceFlowTimingKind::VF_TIMING_LLM_GENERATE => timings.llm_generate_ms,
+        VoiceFlowTimingKind::VF_TIMING_LLM_LOAD => timings.llm_load_ms,
+        VoiceFlowTimingKind::VF_TIMING_STT_LOAD => timings.stt_load_ms,
+    }
+}
+
+/// STT decoder confidence of a result (0.0 - 1.0), 0.0 if the call failed
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_confidence(result: *const VoiceFlowResultHandle) -> c_float {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Ok(texts)) => texts.result.confidence,
+        _ => 0.0,
+    }
+}
+
+/// Why LLM formatting failed, or null if it didn't or the call failed
+///
+/// The formatted text is then the raw transcript; with llm.strict in the
+/// config the call fails instead.
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_formatting_error(result: *const VoiceFlowResultHandle) -> *const c_char {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Ok(texts)) => texts.formatting_error.as_ref().map_or(ptr::null(), |error| error.as_ptr()),
+        _ => ptr::null(),
+    }
+}
+
+/// Number of segments of a result (see voiceflow_result_segment), 0 if the
+/// call failed
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2 not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_segment_count(result: *const VoiceFlowResultHandle) -> usize {
+    match VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) {
+        Some(Ok(texts)) => texts.segments.len(),
+        _ => 0,
+    }
+}
+
+/// Read segment `index` of a result into `out`
+///
+/// Segments split the transcript at long pauses and, with diarization, at
+/// speaker turns; their formatted texts joined are the formatted text.
+/// Returns false, leaving `out` alone, if the call failed or `index` is out
+/// of range.
+///
+/// # Safety
+/// - result must be null or a handle from voiceflow_process2 not yet freed
+/// - out must be null or point to a VoiceFlowSegment
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_segment(
+    result: *const VoiceFlowResultHandle,
+    index: usize,
+    out: *mut VoiceFlowSegment,
+) -> bool {
+    let Some(Ok(texts)) = VoiceFlowResultHandle::from_ptr(result).map(|r| &r.outcome) else {
+        return false;
+    };
+    let (Some(segment), Some((raw_text, formatted_text)), Some(out)) =
+        (texts.result.segments.get(index), texts.segments.get(index), out.as_mut())
+    else {
+        return false;
+    };
+    *out = VoiceFlowSegment {
+        start_ms: segment.start_ms.max(0) as u64,
+        end_ms: segment.end_ms.max(0) as u64,
+        raw_text: raw_text.as_ptr(),
+        formatted_text: formatted_text.as_ptr(),
+        confidence: segment.confidence,
+        speaker: segment.speaker.map_or(-1, i32::from),
+    };
+    true
+}
+
+/// Free a result handle and every string read from it
+///
+/// Freeing a handle twice frees nothing the second time and sets
+/// VF_ERR_INVALID_ARGUMENT as the last error.
+///
+/// # Safety
+/// result must be null or a handle from voiceflow_process2
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_free(result: *mut VoiceFlowResultHandle) {
+    if result.is_null() {
+        return;
+    }
+    if live::RESULT_HANDLES.remove(result) {
+        drop(Box::from_raw(result));
+    } else {
+        live::RESULT_HANDLES.report(result);
+        set_last_error(
+            VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+            "voiceflow_result_free: result was already freed",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use voiceflow_core::llm::FormatContext;
+    use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+    use voiceflow_core::{PipelineBuilder, SpeechToText, TextFormatter};
+
+    struct FixedTranscript(&'static str);
+
+    impl SpeechToText for FixedTranscript {
+        fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> anyhow::Result<TranscriptionResult> {
+            Ok(TranscriptionResult {
+                text: self.0.to_string(),
+                word_timestamps: Vec::new(),
+                confidence: 0.9,
+                no_speech_probability: 0.0,
+                language: Some("en".to_string()),
+                encode_ms: 0,
+                decode_ms: 0,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
+            })
+        }
+    }
+
+    /// Fails as the llama backend does when the prompt overflows the context
+    struct FailingFormatting;
+
+    impl TextFormatter for FailingFormatting {
+        fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> anyhow::Result<String> {
+            anyhow::bail!("context length exceeded")
+        }
+    }
+
+    fn failing_llm_handle(strict: bool) -> *mut VoiceFlowHandle {
+        let pipeline = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("hello world how are you")))
+            .llm_engine(Box::new(FailingFormatting))
+            .llm_strict(strict)
+            .build()
+            .unwrap();
+        VoiceFlowHandle::new(pipeline).into_raw()
+    }
+
+    /// Garbles its output with a NUL byte
+    struct NulFormatting;
+
+    impl TextFormatter for NulFormatting {
+        fn format(&mut self, _transcript: &str, _ctx: &FormatContext) -> anyhow::Result<String> {
+            Ok("Hello world,\0 how are you?".to_string())
+        }
+    }
+
+    #[test]
+    fn test_llm_failure_returns_the_raw_transcript() {
+        // Two seconds of a loud tone, kept by voice activity detection
+        let audio: Vec<f32> = (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+        unsafe {
+            let handle = failing_llm_handle(false);
+            let result = voiceflow_process2(handle, audio.as_ptr(), audio.len(), ptr::null());
+            assert_eq!(voiceflow_result_error_code(result), VoiceFlowErrorCode::VF_ERR_OK);
+            let formatted = CStr::from_ptr(voiceflow_result_formatted_text(result));
+            assert_eq!(formatted.to_str().unwrap(), "hello world how are you");
+            let error = CStr::from_ptr(voiceflow_result_formatting_error(result));
+            assert!(error.to_str().unwrap().contains("context length exceeded"));
+
+            let flat = Box::from_raw(result).into_result();
+            assert!(flat.success && flat.was_fallback);
+            assert!(CStr::from_ptr(flat.formatting_error).to_str().unwrap().contains("context length exceeded"));
+            crate::voiceflow_free_result(flat);
+            crate::voiceflow_destroy(handle);
+
+            let handle = failing_llm_handle(true);
+            let result = voiceflow_process2(handle, audio.as_ptr(), audio.len(), ptr::null());
+            assert!(!voiceflow_result_error(result).is_null());
+            assert!(voiceflow_result_formatting_error(result).is_null());
+            voiceflow_result_free(result);
+            crate::voiceflow_destroy(handle);
+        }
+    }
+
+    #[test]
+    fn test_cancel_while_waiting_for_the_handle() {
+        let audio: Vec<f32> = (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+        let handle = failing_llm_handle(false);
+        let address = handle as usize;
+        unsafe {
+            // Another call holds the pipeline while this one waits for it
+            let held = crate::lock_pipeline(&(*handle).pipeline);
+            let waiting = std::thread::spawn(move || {
+                let result = voiceflow_process2(address as *mut VoiceFlowHandle, audio.as_ptr(), audio.len(), ptr::null());
+                let code = voiceflow_result_error_code(result);
+                voiceflow_result_free(result);
+                code
+            });
+            while (*handle).cancel.len() == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            crate::voiceflow_cancel(handle);
+            drop(held);
+            assert_eq!(waiting.join().unwrap(), VoiceFlowErrorCode::VF_ERR_CANCELLED);
+
+            // The cancel doesn't carry over to the next request
+            let audio: Vec<f32> = (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+            let result = voiceflow_process2(handle, audio.as_ptr(), audio.len(), ptr::null());
+            assert_eq!(voiceflow_result_error_code(result), VoiceFlowErrorCode::VF_ERR_OK);
+            voiceflow_result_free(result);
+            crate::voiceflow_destroy(handle);
+        }
+    }
+
+    #[test]
+    fn test_cancel_stops_a_file_job() {
+        let path = std::ffi::CString::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_audio.wav")).unwrap();
+        let handle = failing_llm_handle(false);
+        let address = handle as usize;
+        unsafe {
+            let held = crate::lock_pipeline(&(*handle).pipeline);
+            let path_address = path.as_ptr() as usize;
+            let job = std::thread::spawn(move || {
+                let handle = address as *mut VoiceFlowHandle;
+                let result = crate::voiceflow_process_file(handle, path_address as *const c_char, ptr::null());
+                let code = crate::error::voiceflow_last_error_code();
+                crate::voiceflow_free_result(result);
+                code
+            });
+            while (*handle).cancel.len() == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            crate::voiceflow_cancel(handle);
+            drop(held);
+            assert_eq!(job.join().unwrap(), VoiceFlowErrorCode::VF_ERR_CANCELLED);
+            crate::voiceflow_destroy(handle);
+        }
+    }
+
+    #[test]
+    fn test_nul_bytes_never_reach_c_strings() {
+        let audio: Vec<f32> = (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+        // Context that isn't UTF-8 is used lossily rather than failing
+        let context = b"Mail\xff\xfe\xc3(\0";
+        let pipeline = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("hello\0 world how are you")))
+            .llm_engine(Box::new(NulFormatting))
+            .build()
+            .unwrap();
+        let handle = VoiceFlowHandle::new(pipeline).into_raw();
+        unsafe {
+            let result = voiceflow_process2(handle, audio.as_ptr(), audio.len(), context.as_ptr().cast());
+            assert_eq!(voiceflow_result_error_code(result), VoiceFlowErrorCode::VF_ERR_OK);
+            let formatted = voiceflow_result_formatted_text(result);
+            assert!(!formatted.is_null());
+            assert_eq!(CStr::from_ptr(formatted).to_str().unwrap(), "hello world how are you");
+            let raw = CStr::from_ptr(voiceflow_result_raw_transcript(result));
+            assert_eq!(raw.to_str().unwrap(), "hello world how are you");
+            let error = CStr::from_ptr(voiceflow_result_formatting_error(result));
+            assert!(error.to_str().unwrap().contains("NUL"));
+
+            let flat = Box::from_raw(result).into_result();
+            assert!(flat.success && flat.was_fallback);
+            assert!(!flat.formatted_text.is_null());
+            assert_eq!(CStr::from_ptr(flat.formatted_text).to_str().unwrap(), "hello world how are you");
+            crate::voiceflow_free_result(flat);
+
+            let flat = crate::voiceflow_process(handle, audio.as_ptr(), audio.len(), context.as_ptr().cast());
+            assert!(flat.success && flat.was_fallback);
+            assert_eq!(CStr::from_ptr(flat.formatted_text).to_str().unwrap(), "hello world how are you");
+            assert!(!flat.formatting_error.is_null());
+            crate::voiceflow_free_result(flat);
+            crate::voiceflow_destroy(handle);
+        }
+    }
+
+    #[test]
+    fn test_destroyed_handle_and_freed_result_are_rejected() {
+        let audio: Vec<f32> = (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+        unsafe {
+            let handle = failing_llm_handle(false);
+            crate::voiceflow_destroy(handle);
+
+            // The dangling handle is never dereferenced
+            let result = voiceflow_process2(handle, audio.as_ptr(), audio.len(), ptr::null());
+            assert_eq!(voiceflow_result_error_code(result), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+            assert!(!crate::voiceflow_is_ready(handle));
+            crate::voiceflow_cancel(handle);
+            crate::voiceflow_destroy(handle);
+            assert_eq!(crate::error::voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+
+            voiceflow_result_free(result);
+            assert!(voiceflow_result_error(result).is_null());
+            assert_eq!(voiceflow_result_error_code(result), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+            crate::error::clear_last_error();
+            voiceflow_result_free(result);
+            assert_eq!(crate::error::voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+        }
+    }
+
+    #[test]
+    fn test_failed_result_owns_its_error() {
+        let samples = [0.0f32; 160];
+        let result = unsafe { voiceflow_process2(ptr::null_mut(), samples.as_ptr(), samples.len(), ptr::null()) };
+        unsafe {
+            let error = voiceflow_result_error(result);
+            assert!(!error.is_null());
+            assert_eq!(voiceflow_result_error(result), error);
+            assert_eq!(voiceflow_result_error_code(result), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+            assert!(voiceflow_result_formatted_text(result).is_null());
+            assert_eq!(voiceflow_result_segment_count(result), 0);
+            let mut segment = std::mem::zeroed::<VoiceFlowSegment>();
+            assert!(!voiceflow_result_segment(result, 0, &mut segment));
+            assert_eq!(voiceflow_result_timing(result, VoiceFlowTimingKind::VF_TIMING_TOTAL), 0);
+
+            let flat = Box::from_raw(result).into_result();
+            assert!(!flat.success);
+            assert_eq!(CStr::from_ptr(flat.error_message).to_str().unwrap(), "Invalid handle or audio data");
+            crate::voiceflow_free_result(flat);
+        }
+    }
+}
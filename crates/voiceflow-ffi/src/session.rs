@@ -0,0 +1,176 @@
+//! Dictation sessions: consecutive dictations formatted with the earlier
+//! ones as context
+
+use std::ffi::{c_float, c_void};
+use std::sync::{Arc, Mutex};
+
+use voiceflow_core::{Pipeline, ProcessOptions, SessionState};
+
+use crate::cancel::InFlight;
+use crate::error::{clear_last_error, set_last_error};
+use crate::worker::{Job, LazyWorker, VoiceFlowCompletionCallback};
+use crate::{
+    catch_panic_result, error_result, invalid_handle, lock_pipeline, pipeline_result, VoiceFlowErrorCode,
+    VoiceFlowHandle, VoiceFlowResult,
+};
+
+/// Opaque dictation session on a VoiceFlow handle
+///
+/// Each session keeps its own history, so sessions on the same handle never
+/// share context. A session may be used from any thread, one call at a time.
+pub struct VoiceFlowSession {
+    pipeline: Arc<Mutex<Pipeline>>,
+    /// The handle's requests in flight, so voiceflow_cancel also stops
+    /// session calls
+    cancel: Arc<InFlight>,
+    /// Shared with the dictations queued on the worker
+    state: Arc<Mutex<SessionState>>,
+    /// Background worker for voiceflow_session_process_async
+    worker: LazyWorker,
+}
+
+impl VoiceFlowSession {
+    /// Dictations the session keeps as context
+    #[cfg(test)]
+    pub(crate) fn dictations(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// Start a dictation session on this handle
+///
+/// Returns null on error (see voiceflow_last_error_message). Free the
+/// session with voiceflow_session_destroy.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_session_create(handle: *mut VoiceFlowHandle) -> *mut VoiceFlowSession {
+    clear_last_error();
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "handle must not be null");
+        return std::ptr::null_mut();
+    }
+
+    let handle = &*handle;
+    let session = VoiceFlowSession {
+        pipeline: Arc::clone(&handle.pipeline),
+        cancel: Arc::clone(&handle.cancel),
+        state: Arc::new(Mutex::new(SessionState::new())),
+        worker: LazyWorker::new(),
+    };
+    Box::into_raw(Box::new(session))
+}
+
+/// Process audio samples as the next dictation of the session
+///
+/// Same as voiceflow_process, with the session's earlier formatted outputs
+/// given to the LLM as context (up to session_context_tokens in the config).
+/// The formatted text is then added to the session.
+///
+/// # Safety
+/// - session must be a valid pointer from voiceflow_session_create
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_session_process(
+    session: *mut VoiceFlowSession,
+    audio_data: *const c_float,
+    audio_len: usize,
+) -> VoiceFlowResult {
+    tracing::debug!("voiceflow_session_process called with {} samples", audio_len);
+    clear_last_error();
+
+    if session.is_null() || audio_data.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid session or audio data");
+        return error_result("Invalid session or audio data");
+    }
+
+    let session = &*session;
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+    catch_panic_result(|| {
+        let cancel = session.cancel.begin();
+        let mut state = session.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pipeline = lock_pipeline(&session.pipeline);
+        let options = ProcessOptions { cancel: cancel.clone(), ..Default::default() };
+        pipeline_result(pipeline.process_in_session(&mut state, audio, None, &options).map_err(anyhow::Error::from))
+    })
+}
+
+/// Process audio samples as the next dictation of the session on a
+/// background thread, reporting the result through a completion callback
+///
+/// Same as voiceflow_session_process, queued like voiceflow_process_async
+/// on a worker of the session's own: dictations run in submission order,
+/// each formatted with the ones before it as context, and the next is
+/// transcribed while the LLM formats the current one. The request ids are
+/// the session's own. The audio is copied, so the caller may release its
+/// buffer as soon as this returns. The callback owns the result: free it
+/// with voiceflow_free_result.
+///
+/// Returns a non-zero request id, or 0 if the request could not be queued
+/// (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - session must be a valid pointer from voiceflow_session_create
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_session_process_async(
+    session: *mut VoiceFlowSession,
+    audio_data: *const c_float,
+    audio_len: usize,
+    user_data: *mut c_void,
+    callback: Option<VoiceFlowCompletionCallback>,
+) -> u64 {
+    tracing::debug!("voiceflow_session_process_async called with {} samples", audio_len);
+    clear_last_error();
+
+    let Some(callback) = callback else {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "callback must not be null");
+        return 0;
+    };
+    if session.is_null() || audio_data.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid session or audio data");
+        return 0;
+    }
+
+    let session = &*session;
+    let audio = std::slice::from_raw_parts(audio_data, audio_len).to_vec();
+    let submitted = session.worker.submit_job(&session.pipeline, &session.cancel, |request_id| {
+        Job::new(request_id, audio, None, user_data, callback).in_session(Arc::clone(&session.state))
+    });
+    match submitted {
+        Ok(request_id) => request_id,
+        Err(e) => {
+            tracing::error!("Failed to queue session dictation: {}", e);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, format!("Failed to start worker thread: {}", e));
+            0
+        }
+    }
+}
+
+/// Forget the session's earlier dictations
+///
+/// # Safety
+/// - session must be a valid pointer from voiceflow_session_create
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_session_reset(session: *mut VoiceFlowSession) {
+    if !session.is_null() {
+        (*session).state.lock().unwrap_or_else(|e| e.into_inner()).reset();
+    }
+}
+
+/// Free a session
+///
+/// The handle it was created on is not affected. Blocks until dictations
+/// queued with voiceflow_session_process_async have finished and their
+/// callbacks have run.
+///
+/// # Safety
+/// - Only call this once per session, with no call running on it
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_session_destroy(session: *mut VoiceFlowSession) {
+    if !session.is_null() {
+        let _ = Box::from_raw(session);
+    }
+}
@@ -0,0 +1,400 @@
+//! Streaming dictation: push audio while recording and receive partial
+//! transcripts as each utterance is recognized
+//!
+//! Hands-free sessions also end each utterance by themselves after a pause
+//! and hand it over formatted, then go on with the next one.
+
+use std::borrow::Cow;
+use std::ffi::{c_char, c_float, c_void, CString};
+
+use voiceflow_core::audio::i16_to_f32_into;
+use voiceflow_core::StreamingSession;
+
+use crate::cancel::RequestCancel;
+use crate::error::{clear_last_error, set_last_error, set_last_error_from};
+use crate::{
+    c_string, catch_panic_result, context_arg, error_result, invalid_handle, lock_pipeline, pipeline_result,
+    VoiceFlowErrorCode, VoiceFlowHandle, VoiceFlowResult,
+};
+use crate::panic_report::caught_panic;
+use crate::worker::UserData;
+
+/// Partial transcript callback for streaming mode
+///
+/// Receives the raw transcript recognized so far (UTF-8). The string is
+/// owned by the library and stays valid until the next callback or until
+/// voiceflow_stream_finish returns; copy it to keep it.
+pub type VoiceFlowPartialCallback = extern "C" fn(user_data: *mut c_void, partial_text: *const c_char);
+
+/// Utterance callback for hands-free streaming
+///
+/// Receives each utterance the session ended, formatted, on the thread that
+/// pushed the audio ending it. The callback owns the result and must free
+/// it with voiceflow_free_result; a failed result (e.g. VF_ERR_CANCELLED)
+/// doesn't end the session.
+pub type VoiceFlowFinalCallback = extern "C" fn(user_data: *mut c_void, result: VoiceFlowResult);
+
+/// When a hands-free session ends an utterance (see the `[endpointing]`
+/// config section)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceFlowEndpointingOptions {
+    /// Silence (ms) after speech that ends the utterance, 100 to 10000
+    pub trailing_silence_ms: u32,
+    /// Longest utterance (ms) from its first speech, 1000 to 600000, or 0
+    /// for no limit
+    pub max_utterance_ms: u32,
+}
+
+/// Per-handle state of a streaming session
+pub(crate) struct StreamState {
+    session: StreamingSession,
+    callback: VoiceFlowPartialCallback,
+    /// Set for hands-free sessions
+    on_final: Option<VoiceFlowFinalCallback>,
+    /// Context hint the ended utterances are formatted with
+    context: Option<String>,
+    user_data: UserData,
+    /// Last text passed to the callback, kept alive for the caller
+    partial: CString,
+    /// Int16 pushes converted to f32, reused between pushes
+    converted: Vec<f32>,
+    /// In flight from voiceflow_stream_start until the session ends
+    cancel: RequestCancel,
+}
+
+/// Samples as passed to voiceflow_stream_push or _push_i16
+enum Pushed<'a> {
+    F32(&'a [f32]),
+    I16(&'a [i16]),
+}
+
+/// Start a streaming session on this handle
+///
+/// Any session already in progress is discarded. Returns false on error
+/// (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_stream_start(
+    handle: *mut VoiceFlowHandle,
+    callback: Option<VoiceFlowPartialCallback>,
+    user_data: *mut c_void,
+) -> bool {
+    clear_last_error();
+
+    let callback = match callback {
+        Some(cb) => cb,
+        None => {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "callback must not be null");
+            return false;
+        }
+    };
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle");
+        return false;
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let session = StreamingSession::new(&lock_pipeline(&handle.pipeline).config().audio);
+    start_session(handle, session, callback, None, None, user_data);
+    true
+}
+
+/// Start a hands-free streaming session on this handle
+///
+/// Like voiceflow_stream_start, but the session ends each utterance by
+/// itself once the speaker pauses for `trailing_silence_ms` (or after
+/// `max_utterance_ms`): voiceflow_stream_push then transcribes its tail,
+/// formats it with `context` and passes the result to `on_final`, before
+/// returning. Audio pushed after that point starts the next utterance, so
+/// speech that begins while the previous one is formatted is kept; the
+/// partial callback then reports the new utterance's transcript.
+/// voiceflow_stream_finish returns the utterance in progress.
+///
+/// Returns false on error (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - options can be null for the config's `[endpointing]` thresholds
+/// - context can be null
+/// - user_data is passed back to both callbacks untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_stream_start_hands_free(
+    handle: *mut VoiceFlowHandle,
+    options: *const VoiceFlowEndpointingOptions,
+    context: *const c_char,
+    on_partial: Option<VoiceFlowPartialCallback>,
+    on_final: Option<VoiceFlowFinalCallback>,
+    user_data: *mut c_void,
+) -> bool {
+    clear_last_error();
+
+    let (Some(on_partial), Some(on_final)) = (on_partial, on_final) else {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Callbacks must not be null");
+        return false;
+    };
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle");
+        return false;
+    }
+    let context = context_arg(context).map(Cow::into_owned);
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let session = {
+        let pipeline = lock_pipeline(&handle.pipeline);
+        let config = pipeline.config();
+        let mut endpointing = config.endpointing.clone();
+        if let Some(options) = options.as_ref() {
+            endpointing.trailing_silence_ms = options.trailing_silence_ms;
+            endpointing.max_utterance_ms = options.max_utterance_ms;
+        }
+        if let Err(e) = endpointing.validate() {
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, e.to_string());
+            return false;
+        }
+        StreamingSession::with_endpointing(&config.audio, &endpointing)
+    };
+    start_session(handle, session, on_partial, Some(on_final), context, user_data);
+    true
+}
+
+/// Make `session` the handle's streaming session, discarding any other
+fn start_session(
+    handle: &VoiceFlowHandle,
+    session: StreamingSession,
+    callback: VoiceFlowPartialCallback,
+    on_final: Option<VoiceFlowFinalCallback>,
+    context: Option<String>,
+    user_data: *mut c_void,
+) {
+    let cancel = handle.cancel.begin();
+    let mut stream = handle.stream.lock().unwrap_or_else(|e| e.into_inner());
+    if stream.is_some() {
+        tracing::debug!("voiceflow_stream_start: discarding previous session");
+    }
+    *stream = Some(StreamState {
+        session,
+        callback,
+        on_final,
+        context,
+        user_data: UserData(user_data),
+        partial: CString::default(),
+        converted: Vec::new(),
+        cancel,
+    });
+
+    tracing::debug!("voiceflow_stream_start: session started");
+}
+
+/// Push 16kHz mono samples into the streaming session
+///
+/// When an utterance ends this transcribes it before returning and invokes
+/// the partial callback on the calling thread (and, in a hands-free
+/// session, formats it and invokes on_final first), so call it from a
+/// worker queue rather than the real-time audio thread. The callbacks must
+/// not call back into the voiceflow_stream_* functions.
+///
+/// Returns false on error (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - samples must point to len floats
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_stream_push(
+    handle: *mut VoiceFlowHandle,
+    samples: *const c_float,
+    len: usize,
+) -> bool {
+    clear_last_error();
+
+    if invalid_handle(handle) || samples.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle or samples");
+        return false;
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    push_samples(handle, Pushed::F32(std::slice::from_raw_parts(samples, len)))
+}
+
+/// Push 16kHz mono 16-bit PCM samples into the streaming session
+///
+/// Same as voiceflow_stream_push, but takes Int16 samples directly.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - samples must point to len int16 samples
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_stream_push_i16(
+    handle: *mut VoiceFlowHandle,
+    samples: *const i16,
+    len: usize,
+) -> bool {
+    clear_last_error();
+
+    if invalid_handle(handle) || samples.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle or samples");
+        return false;
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    push_samples(handle, Pushed::I16(std::slice::from_raw_parts(samples, len)))
+}
+
+/// Feed samples to the active session and report a new partial transcript
+fn push_samples(handle: &VoiceFlowHandle, samples: Pushed) -> bool {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut stream = handle.stream.lock().unwrap_or_else(|e| e.into_inner());
+        let state = match stream.as_mut() {
+            Some(state) => state,
+            None => {
+                set_last_error(
+                    VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                    "No streaming session; call voiceflow_stream_start first",
+                );
+                return false;
+            }
+        };
+
+        let samples = match samples {
+            Pushed::F32(samples) => samples,
+            Pushed::I16(samples) => {
+                i16_to_f32_into(samples, &mut state.converted);
+                &state.converted
+            }
+        };
+        let changed = {
+            let mut pipeline = lock_pipeline(&handle.pipeline);
+            state.session.push(&mut pipeline, samples, &state.cancel)
+        };
+
+        // Utterances ended by a hands-free session, before the next one's
+        // partial transcript
+        if let Some(on_final) = state.on_final {
+            while let Some(utterance) = state.session.take_utterance() {
+                let result = {
+                    let mut pipeline = lock_pipeline(&handle.pipeline);
+                    pipeline_result(utterance.format(&mut pipeline, state.context.as_deref(), &state.cancel))
+                };
+                on_final(state.user_data.0, result);
+            }
+        }
+
+        match changed {
+            Ok(true) => {
+                state.partial = c_string(state.session.transcript());
+                (state.callback)(state.user_data.0, state.partial.as_ptr());
+                true
+            }
+            Ok(false) => true,
+            Err(e) => {
+                tracing::error!("Stream push failed: {:#}", e);
+                set_last_error_from(&e);
+                false
+            }
+        }
+    }));
+
+    result.unwrap_or_else(|e| {
+        let msg = caught_panic(e);
+        tracing::error!("PANIC caught in voiceflow_stream_push: {}", msg);
+        set_last_error(VoiceFlowErrorCode::VF_ERR_PANIC, format!("Internal error: {}", msg));
+        false
+    })
+}
+
+/// Finish the streaming session and return the formatted result
+///
+/// Transcribes any remaining audio, then runs prosody and LLM formatting on
+/// the full transcript (of the utterance in progress, for a hands-free
+/// session). Free the result with voiceflow_free_result.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - context can be null
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_stream_finish(
+    handle: *mut VoiceFlowHandle,
+    context: *const c_char,
+) -> VoiceFlowResult {
+    clear_last_error();
+
+    if invalid_handle(handle) {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle");
+        return error_result("Invalid handle");
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let context_str = context_arg(context);
+
+    let state = handle.stream.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let state = match state {
+        Some(state) => state,
+        None => {
+            set_last_error(
+                VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT,
+                "No streaming session; call voiceflow_stream_start first",
+            );
+            return error_result("No streaming session");
+        }
+    };
+
+    catch_panic_result(|| {
+        tracing::debug!("voiceflow_stream_finish: finishing session");
+        let mut pipeline = lock_pipeline(&handle.pipeline);
+        pipeline_result(state.session.finish(&mut pipeline, context_str.as_deref(), &state.cancel))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::voiceflow_last_error_code;
+    use std::ptr;
+
+    extern "C" fn on_partial(_: *mut c_void, _: *const c_char) {}
+
+    extern "C" fn on_final(_: *mut c_void, result: VoiceFlowResult) {
+        unsafe { crate::voiceflow_free_result(result) };
+    }
+
+    #[test]
+    fn test_hands_free_needs_both_callbacks() {
+        let started = unsafe {
+            voiceflow_stream_start_hands_free(
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+                Some(on_partial),
+                None,
+                ptr::null_mut(),
+            )
+        };
+        assert!(!started);
+        assert_eq!(voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn test_hands_free_null_handle_fails() {
+        let options = VoiceFlowEndpointingOptions { trailing_silence_ms: 800, max_utterance_ms: 0 };
+        let started = unsafe {
+            voiceflow_stream_start_hands_free(
+                ptr::null_mut(),
+                &options,
+                ptr::null(),
+                Some(on_partial),
+                Some(on_final),
+                ptr::null_mut(),
+            )
+        };
+        assert!(!started);
+        assert_eq!(voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+    }
+}
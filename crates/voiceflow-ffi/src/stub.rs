@@ -0,0 +1,108 @@
+//! A handle with a fixed transcript and no models, for testing wrappers
+//!
+//! Only built with the stub-pipeline feature, so bindings (the Swift
+//! package's tests, CI) can exercise init, process and destroy without
+//! downloading a model.
+
+use std::ffi::c_char;
+use std::ptr;
+
+use voiceflow_core::llm::FormatContext;
+use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+use voiceflow_core::{PipelineBuilder, SpeechToText, TextFormatter};
+
+use crate::error::{clear_last_error, set_last_error_from};
+use crate::{logging, str_arg, VoiceFlowHandle};
+
+/// Transcribes any audio as the same text
+struct FixedTranscript(String);
+
+impl SpeechToText for FixedTranscript {
+    fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> anyhow::Result<TranscriptionResult> {
+        Ok(TranscriptionResult {
+            text: self.0.clone(),
+            word_timestamps: Vec::new(),
+            confidence: 1.0,
+            no_speech_probability: 0.0,
+            language: Some("en".to_string()),
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature: 0.0,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
+        })
+    }
+}
+
+/// Capitalizes the transcript and ends it with a period
+struct SentenceCase;
+
+impl TextFormatter for SentenceCase {
+    fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> anyhow::Result<String> {
+        let transcript = transcript.trim();
+        let mut chars = transcript.chars();
+        let mut text: String = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => return Ok(String::new()),
+        };
+        if !text.ends_with(['.', '?', '!']) {
+            text.push('.');
+        }
+        Ok(text)
+    }
+}
+
+/// Create a handle whose pipeline transcribes any audio as `transcript`
+///
+/// No config or model is loaded: the formatter only capitalizes the
+/// transcript and ends it with a period. Only exported by builds with the
+/// stub-pipeline feature. Returns null on error (see
+/// voiceflow_last_error_message); free the handle with voiceflow_destroy.
+///
+/// # Safety
+/// transcript must be a valid null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_init_stub(transcript: *const c_char) -> *mut VoiceFlowHandle {
+    logging::install();
+    clear_last_error();
+    let Some(transcript) = str_arg(transcript, "transcript") else {
+        return ptr::null_mut();
+    };
+
+    let pipeline = PipelineBuilder::new()
+        .stt(Box::new(FixedTranscript(transcript.to_string())))
+        .llm_engine(Box::new(SentenceCase))
+        .build();
+    match pipeline {
+        Ok(pipeline) => VoiceFlowHandle::new(pipeline).into_raw(),
+        Err(e) => {
+            tracing::error!("Failed to create stub pipeline: {:#}", e);
+            set_last_error_from(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result_handle::{voiceflow_process2, voiceflow_result_formatted_text, voiceflow_result_free};
+    use crate::voiceflow_destroy;
+    use std::ffi::{CStr, CString};
+
+    #[test]
+    fn test_stub_handle_formats_fixed_transcript() {
+        let transcript = CString::new("hello from the stub").unwrap();
+        let handle = unsafe { voiceflow_init_stub(transcript.as_ptr()) };
+        assert!(!handle.is_null());
+
+        let audio: Vec<f32> = (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+        unsafe {
+            let result = voiceflow_process2(handle, audio.as_ptr(), audio.len(), ptr::null());
+            let text = CStr::from_ptr(voiceflow_result_formatted_text(result));
+            assert_eq!(text.to_str().unwrap(), "Hello from the stub.");
+            voiceflow_result_free(result);
+            voiceflow_destroy(handle);
+        }
+    }
+}
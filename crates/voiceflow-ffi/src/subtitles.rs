@@ -0,0 +1,75 @@
+//! Subtitles from the word timings of a result
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use voiceflow_core::output::{self, Caption, SubtitleError, SubtitleOptions};
+use voiceflow_core::transcribe::WordTimestamp;
+
+use crate::error::{clear_last_error, set_last_error};
+use crate::{VoiceFlowErrorCode, VoiceFlowResult};
+
+/// Get SubRip (.srt) subtitles for the raw transcript of a result
+///
+/// Words are grouped into captions of up to two lines of 42 characters,
+/// shown for at most 7 seconds. A result without speech gives an empty
+/// string. Returns null with VF_ERR_INVALID_ARGUMENT if the result failed
+/// or has no word timings (streaming results). Free the string with
+/// voiceflow_free_string.
+///
+/// # Safety
+/// result must point to a result that hasn't been freed yet
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_to_srt(result: *const VoiceFlowResult) -> *mut c_char {
+    subtitles(result, output::srt)
+}
+
+/// Get WebVTT (.vtt) subtitles for the raw transcript of a result
+///
+/// Same as voiceflow_result_to_srt, in WebVTT format.
+///
+/// # Safety
+/// result must point to a result that hasn't been freed yet
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_result_to_vtt(result: *const VoiceFlowResult) -> *mut c_char {
+    subtitles(result, output::vtt)
+}
+
+unsafe fn subtitles(result: *const VoiceFlowResult, format: fn(&[Caption]) -> String) -> *mut c_char {
+    clear_last_error();
+    if result.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "result must not be null");
+        return ptr::null_mut();
+    }
+    let result = &*result;
+    if !result.success {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "The result is an error");
+        return ptr::null_mut();
+    }
+    let words = words(result);
+    let has_text = !result.raw_transcript.is_null() && !CStr::from_ptr(result.raw_transcript).to_bytes().is_empty();
+    if words.is_empty() && has_text {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, SubtitleError::NoTimestamps.to_string());
+        return ptr::null_mut();
+    }
+
+    let text = format(&output::captions(&words, &SubtitleOptions::default()));
+    CString::new(text).map(|s| s.into_raw()).unwrap_or(ptr::null_mut())
+}
+
+/// Copy the word timings of a result back into core form
+unsafe fn words(result: &VoiceFlowResult) -> Vec<WordTimestamp> {
+    if result.words.is_null() {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(result.words, result.word_count)
+        .iter()
+        .filter(|w| !w.word.is_null())
+        .map(|w| WordTimestamp {
+            word: CStr::from_ptr(w.word).to_string_lossy().into_owned(),
+            start_ms: w.start_ms as i64,
+            end_ms: w.end_ms as i64,
+            probability: w.confidence,
+        })
+        .collect()
+}
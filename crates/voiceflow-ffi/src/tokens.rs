@@ -0,0 +1,83 @@
+//! Processing that streams the LLM output token by token
+
+use std::ffi::{c_char, c_float, c_void};
+
+use voiceflow_core::{ProcessOptions, TokenSink};
+
+use crate::error::{clear_last_error, set_last_error};
+use crate::worker::UserData;
+use crate::{
+    c_string, context_arg, error_result, invalid_handle, process_audio_streaming, VoiceFlowErrorCode, VoiceFlowHandle,
+    VoiceFlowResult,
+};
+
+/// Token callback for voiceflow_process_streaming
+///
+/// Receives the next fragment of formatted text: one or more whole UTF-8
+/// characters, null-terminated. The string is owned by the library and is
+/// only valid during the call; copy it to keep it.
+pub type VoiceFlowTokenCallback = extern "C" fn(user_data: *mut c_void, fragment: *const c_char);
+
+/// Forwards core tokens to the C callback
+struct CallbackSink {
+    callback: VoiceFlowTokenCallback,
+    user_data: UserData,
+}
+
+impl TokenSink for CallbackSink {
+    fn token(&mut self, text: &str) {
+        let fragment = c_string(text);
+        (self.callback)(self.user_data.0, fragment.as_ptr());
+    }
+}
+
+/// Process audio samples, passing the formatted text to `token_callback` as
+/// the LLM generates it
+///
+/// Same as voiceflow_process otherwise. The callback runs on the calling
+/// thread, and every fragment is delivered before this returns. Fragments are
+/// the model's output before cleanup, so the concatenation can differ from
+/// the returned formatted_text, which is the text to use. Nothing is streamed
+/// when the LLM isn't run.
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - context can be null
+/// - user_data is passed back to the callback untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process_streaming(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    context: *const c_char,
+    token_callback: Option<VoiceFlowTokenCallback>,
+    user_data: *mut c_void,
+) -> VoiceFlowResult {
+    tracing::debug!("voiceflow_process_streaming called with {} samples", audio_len);
+    clear_last_error();
+
+    if invalid_handle(handle) || audio_data.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle or audio data");
+        return error_result("Invalid handle or audio data");
+    }
+    let Some(callback) = token_callback else {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "token_callback must not be null");
+        return error_result("token_callback must not be null");
+    };
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let audio = std::slice::from_raw_parts(audio_data, audio_len);
+    let context_str = context_arg(context);
+
+    let mut sink = CallbackSink { callback, user_data: UserData(user_data) };
+    process_audio_streaming(
+        &handle.pipeline,
+        &handle.cancel.begin(),
+        audio,
+        context_str.as_deref(),
+        ProcessOptions::default(),
+        Some(&mut sink),
+    )
+}
@@ -0,0 +1,195 @@
+//! Processing in two passes: the raw transcript as soon as transcription is
+//! done, and the formatted text once the LLM has run
+
+use std::ffi::{c_char, c_float, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use voiceflow_core::{CancelToken, RawTranscriptHook};
+
+use crate::error::{clear_last_error, set_last_error};
+use crate::worker::{Job, UserData, VoiceFlowCompletionCallback};
+use crate::{
+    c_string, invalid_handle, process_options, VoiceFlowErrorCode, VoiceFlowHandle, VoiceFlowProcessOptions,
+    VoiceFlowResult,
+};
+
+/// Raw transcript callback for voiceflow_process_two_phase
+///
+/// Called on a worker thread with the caller's user_data, the request id
+/// and the raw transcript, null-terminated. The string is owned by the
+/// library and is only valid during the call; copy it to keep it. Return
+/// false to skip formatting, as voiceflow_cancel does.
+pub type VoiceFlowRawCallback =
+    extern "C" fn(user_data: *mut c_void, request_id: u64, raw_text: *const c_char) -> bool;
+
+struct RawCallback {
+    callback: VoiceFlowRawCallback,
+    request_id: u64,
+    user_data: UserData,
+    cancel: CancelToken,
+    /// The transcript was passed on
+    sent: AtomicBool,
+}
+
+// The sink is called from one thread at a time (the worker's, then the
+// callback thread's once the result is in), and user_data is only passed
+// back to the caller's callback.
+unsafe impl Sync for RawCallback {}
+
+impl RawCallback {
+    fn send(&self, text: *const c_char) {
+        self.sent.store(true, Ordering::Relaxed);
+        if !(self.callback)(self.user_data.0, self.request_id, text) {
+            tracing::debug!("Formatting of request {} skipped from the raw transcript callback", self.request_id);
+            self.cancel.cancel();
+        }
+    }
+}
+
+/// Forwards a request's raw transcript to the C callback, cancelling the
+/// request when the callback returns false
+pub(crate) struct RawCallbackSink(Arc<RawCallback>);
+
+impl RawCallbackSink {
+    pub(crate) fn new(
+        callback: VoiceFlowRawCallback,
+        request_id: u64,
+        user_data: &UserData,
+        cancel: &CancelToken,
+    ) -> Self {
+        Self(Arc::new(RawCallback {
+            callback,
+            request_id,
+            user_data: UserData(user_data.0),
+            cancel: cancel.clone(),
+            sent: AtomicBool::new(false),
+        }))
+    }
+
+    /// The hook to pass in `ProcessOptions::on_raw`
+    pub(crate) fn hook(&self) -> RawTranscriptHook {
+        let callback = Arc::clone(&self.0);
+        RawTranscriptHook::new(move |text: &str| callback.send(c_string(text).as_ptr()))
+    }
+
+    /// Pass on the raw transcript of a successful request the pipeline
+    /// didn't report (no speech), so the callback runs before the result
+    pub(crate) fn finish(&self, result: &VoiceFlowResult) {
+        if result.success && !self.0.sent.load(Ordering::Relaxed) {
+            self.0.send(result.raw_transcript);
+        }
+    }
+}
+
+/// Process audio samples on the worker thread in two passes: the raw
+/// transcript goes to `on_raw` as soon as transcription is done, and the
+/// result, with the formatted text, to `on_formatted` once the LLM has run
+///
+/// Queued with voiceflow_process_async requests and run in submission
+/// order, with the same request id passed to both callbacks. on_raw gets
+/// the transcript with voice commands, numbers and replacements applied
+/// (empty when there is no speech), and is always called before
+/// on_formatted unless the request fails first, when only on_formatted is
+/// called. Between the two, voiceflow_cancel (or on_raw returning false)
+/// stops formatting, and on_formatted gets a failed result with
+/// VF_ERR_CANCELLED. The audio is copied, so the caller may release its
+/// buffer as soon as this returns. on_formatted owns the result: free it
+/// with voiceflow_free_result.
+///
+/// Returns a non-zero request id, or 0 if the request could not be queued
+/// (see voiceflow_last_error_message).
+///
+/// # Safety
+/// - handle must be a valid pointer from voiceflow_init
+/// - audio_data must point to audio_len floats (16kHz mono PCM)
+/// - options can be null for the defaults (see voiceflow_process_opts)
+/// - user_data is passed back to the callbacks untouched
+#[no_mangle]
+pub unsafe extern "C" fn voiceflow_process_two_phase(
+    handle: *mut VoiceFlowHandle,
+    audio_data: *const c_float,
+    audio_len: usize,
+    options: *const VoiceFlowProcessOptions,
+    on_raw: Option<VoiceFlowRawCallback>,
+    on_formatted: Option<VoiceFlowCompletionCallback>,
+    user_data: *mut c_void,
+) -> u64 {
+    tracing::debug!("voiceflow_process_two_phase called with {} samples", audio_len);
+    clear_last_error();
+
+    let (Some(on_raw), Some(on_formatted)) = (on_raw, on_formatted) else {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "on_raw and on_formatted must not be null");
+        return 0;
+    };
+    if invalid_handle(handle) || audio_data.is_null() {
+        set_last_error(VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT, "Invalid handle or audio data");
+        return 0;
+    }
+
+    let handle = &*handle;
+    let _call = handle.calls.enter();
+    let Ok(process_options) = process_options(options) else {
+        return 0;
+    };
+    let audio = std::slice::from_raw_parts(audio_data, audio_len).to_vec();
+
+    let submitted = handle.submit_job(|request_id| {
+        Job::new(request_id, audio, None, user_data, on_formatted).two_phase(process_options, on_raw)
+    });
+    match submitted {
+        Ok(request_id) => request_id,
+        Err(e) => {
+            tracing::error!("Failed to queue two-phase request: {}", e);
+            set_last_error(VoiceFlowErrorCode::VF_ERR_INTERNAL, format!("Failed to start worker thread: {}", e));
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::voiceflow_last_error_code;
+    use std::ptr;
+
+    extern "C" fn on_raw(_: *mut c_void, _: u64, _: *const c_char) -> bool {
+        true
+    }
+
+    extern "C" fn on_formatted(_: *mut c_void, _: u64, result: VoiceFlowResult) {
+        unsafe { crate::voiceflow_free_result(result) };
+    }
+
+    #[test]
+    fn test_null_arguments_fail() {
+        let audio = [0.0f32; 160];
+        let queued = unsafe {
+            voiceflow_process_two_phase(
+                ptr::null_mut(),
+                audio.as_ptr(),
+                audio.len(),
+                ptr::null(),
+                Some(on_raw),
+                Some(on_formatted),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(queued, 0);
+        assert_eq!(voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+
+        let queued = unsafe {
+            voiceflow_process_two_phase(
+                ptr::null_mut(),
+                audio.as_ptr(),
+                audio.len(),
+                ptr::null(),
+                None,
+                Some(on_formatted),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(queued, 0);
+        assert_eq!(voiceflow_last_error_code(), VoiceFlowErrorCode::VF_ERR_INVALID_ARGUMENT);
+    }
+}
@@ -0,0 +1,392 @@
+//! Background worker that runs queued voiceflow_process_async requests
+
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use voiceflow_core::{Pipeline, PipelineResult, ProcessOptions, QueuedRequest, SessionState};
+
+use crate::cancel::{InFlight, RequestCancel};
+use crate::panic_report::caught_panic;
+use crate::two_phase::{RawCallbackSink, VoiceFlowRawCallback};
+use crate::{lock_pipeline, pipeline_result, VoiceFlowResult};
+
+/// Completion callback for voiceflow_process_async
+///
+/// Called on the worker's callback thread with the caller's user_data, the
+/// request id returned when the request was queued, and the result (which
+/// the callback must free with voiceflow_free_result).
+pub type VoiceFlowCompletionCallback =
+    extern "C" fn(user_data: *mut c_void, request_id: u64, result: VoiceFlowResult);
+
+/// Caller-owned pointer handed back to a callback untouched
+pub(crate) struct UserData(pub(crate) *mut c_void);
+
+// The library never dereferences user_data, it only passes it back to the
+// caller's callback, so moving it to the worker thread is sound.
+unsafe impl Send for UserData {}
+
+/// A queued request
+pub(crate) struct Job {
+    request_id: u64,
+    audio: Vec<f32>,
+    context: Option<String>,
+    user_data: UserData,
+    callback: VoiceFlowCompletionCallback,
+    options: ProcessOptions,
+    /// Set for voiceflow_process_two_phase
+    on_raw: Option<VoiceFlowRawCallback>,
+    /// Set for voiceflow_session_process_async
+    session: Option<Arc<Mutex<SessionState>>>,
+}
+
+impl Job {
+    pub(crate) fn new(
+        request_id: u64,
+        audio: Vec<f32>,
+        context: Option<String>,
+        user_data: *mut c_void,
+        callback: VoiceFlowCompletionCallback,
+    ) -> Self {
+        Self {
+            request_id,
+            audio,
+            context,
+            user_data: UserData(user_data),
+            callback,
+            options: ProcessOptions::default(),
+            on_raw: None,
+            session: None,
+        }
+    }
+
+    /// Run with `options`, passing the raw transcript to `on_raw` before
+    /// formatting
+    pub(crate) fn two_phase(mut self, options: ProcessOptions, on_raw: VoiceFlowRawCallback) -> Self {
+        self.options = options;
+        self.on_raw = Some(on_raw);
+        self
+    }
+
+    /// Run as the next dictation of `session`
+    pub(crate) fn in_session(mut self, session: Arc<Mutex<SessionState>>) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Hand the job to the pipeline, with a token `in_flight` signals
+    fn queued(self, in_flight: &Arc<InFlight>) -> QueuedRequest<Request> {
+        let Job { request_id, audio, context, user_data, callback, mut options, on_raw, session } = self;
+        tracing::debug!("Worker running request {}", request_id);
+        let cancel = in_flight.begin();
+        options.cancel = cancel.clone();
+        let raw_sink = on_raw.map(|on_raw| RawCallbackSink::new(on_raw, request_id, &user_data, &cancel));
+        if let Some(sink) = &raw_sink {
+            options.on_raw = Some(sink.hook());
+        }
+        let request = Request { reply: Reply { request_id, user_data, callback }, raw_sink, cancel };
+        let queued = QueuedRequest::new(request, audio, context, options);
+        match session {
+            Some(session) => queued.in_session(session),
+            None => queued,
+        }
+    }
+}
+
+/// A job the pipeline is working on
+struct Request {
+    reply: Reply,
+    raw_sink: Option<RawCallbackSink>,
+    /// Dropped once the result is in
+    cancel: RequestCancel,
+}
+
+/// Where a job's result goes
+struct Reply {
+    request_id: u64,
+    user_data: UserData,
+    callback: VoiceFlowCompletionCallback,
+}
+
+/// A result on its way to the callback thread
+///
+/// Sent as the pipeline's outcome rather than a VoiceFlowResult, whose C
+/// pointers can't cross threads; the callback thread converts it.
+type Delivery = (Reply, Option<RawCallbackSink>, anyhow::Result<PipelineResult>);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run `first` and the jobs queued behind it until none are left, each
+/// transcribed while the one before it is formatted, sending the results
+/// to `results`
+///
+/// A panic fails the jobs taken off the queue that have no result yet.
+fn run_queue(
+    first: Job,
+    receiver: &Mutex<Receiver<Job>>,
+    pipeline: &Mutex<Pipeline>,
+    in_flight: &Arc<InFlight>,
+    results: &Sender<Delivery>,
+) {
+    let pending = Mutex::new(VecDeque::new());
+    let jobs = std::iter::once(first).chain(std::iter::from_fn(|| lock(receiver).try_recv().ok()));
+    let requests = jobs.map(|job| {
+        let (request_id, user_data, callback) = (job.request_id, UserData(job.user_data.0), job.callback);
+        lock(&pending).push_back(Reply { request_id, user_data, callback });
+        job.queued(in_flight)
+    });
+    let run = catch_unwind(AssertUnwindSafe(|| {
+        lock_pipeline(pipeline).process_queued(requests, |request, result| {
+            let Request { reply, raw_sink, cancel } = request;
+            let result = result.map_err(anyhow::Error::from);
+            drop(cancel);
+            lock(&pending).pop_front();
+            let _ = results.send((reply, raw_sink, result));
+        })
+    }));
+
+    if let Err(e) = run {
+        let msg = caught_panic(e);
+        tracing::error!("PANIC caught in the worker: {}", msg);
+        for reply in lock(&pending).drain(..) {
+            let _ = results.send((reply, None, Err(anyhow::anyhow!("Internal error: {}", msg))));
+        }
+    }
+}
+
+/// Single worker thread per handle, so requests run in submission order,
+/// each transcribed while the one before it is formatted
+///
+/// The worker holds the pipeline while jobs are queued, so their callbacks
+/// run on a thread of their own, in the same order, free to make calls
+/// that need the pipeline.
+pub(crate) struct Worker {
+    sender: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+    callbacks: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    pub(crate) fn spawn(pipeline: Arc<Mutex<Pipeline>>, in_flight: Arc<InFlight>) -> std::io::Result<Self> {
+        let (results, delivered) = mpsc::channel::<Delivery>();
+        let callbacks = std::thread::Builder::new()
+            .name("voiceflow-callbacks".to_string())
+            .spawn(move || {
+                for (reply, raw_sink, result) in delivered {
+                    let result = pipeline_result(result);
+                    if let Some(sink) = &raw_sink {
+                        sink.finish(&result);
+                    }
+                    (reply.callback)(reply.user_data.0, reply.request_id, result);
+                }
+            })?;
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let thread = std::thread::Builder::new()
+            .name("voiceflow-worker".to_string())
+            .spawn(move || {
+                // Shared with the pipeline's STT thread, which takes the
+                // jobs queued while it works
+                let receiver = Mutex::new(receiver);
+                loop {
+                    // The pipeline is only held while there are jobs
+                    let Ok(job) = lock(&receiver).recv() else {
+                        break;
+                    };
+                    run_queue(job, &receiver, &pipeline, &in_flight, &results);
+                }
+                tracing::debug!("Worker queue closed - exiting");
+            })?;
+
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            callbacks: Some(callbacks),
+        })
+    }
+
+    pub(crate) fn submit(&self, job: Job) -> std::io::Result<()> {
+        let sender = self.sender.as_ref().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "worker has shut down")
+        })?;
+        sender.send(job).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "worker thread exited")
+        })
+    }
+}
+
+/// A worker spawned on first use, and the request ids of its jobs
+pub(crate) struct LazyWorker {
+    worker: Mutex<Option<Worker>>,
+    next_request_id: AtomicU64,
+}
+
+impl LazyWorker {
+    pub(crate) fn new() -> Self {
+        Self { worker: Mutex::new(None), next_request_id: AtomicU64::new(1) }
+    }
+
+    /// Queue the job `job` makes for the next request id, returning the id
+    pub(crate) fn submit_job(
+        &self,
+        pipeline: &Arc<Mutex<Pipeline>>,
+        in_flight: &Arc<InFlight>,
+        job: impl FnOnce(u64) -> Job,
+    ) -> std::io::Result<u64> {
+        let mut worker = lock(&self.worker);
+        if worker.is_none() {
+            *worker = Some(Worker::spawn(Arc::clone(pipeline), Arc::clone(in_flight))?);
+        }
+
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let job = job(request_id);
+        if let Some(worker) = worker.as_ref() {
+            worker.submit(job)?;
+        }
+        Ok(request_id)
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the channel lets the thread finish queued jobs and exit
+        self.sender.take();
+
+        // voiceflow_destroy called from inside a callback runs on the
+        // callback thread; joining that would deadlock, so let it deliver
+        // the remaining results on its own.
+        for thread in [self.thread.take(), self.callbacks.take()].into_iter().flatten() {
+            if thread.thread().id() != std::thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{voiceflow_session_create, voiceflow_session_destroy, voiceflow_session_process_async};
+    use crate::VoiceFlowHandle;
+    use std::ptr;
+    use std::time::Duration;
+    use voiceflow_core::llm::FormatContext;
+    use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+    use voiceflow_core::{PipelineBuilder, SpeechToText, TextFormatter};
+
+    struct FixedTranscript(&'static str);
+
+    impl SpeechToText for FixedTranscript {
+        fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> anyhow::Result<TranscriptionResult> {
+            Ok(TranscriptionResult {
+                text: self.0.to_string(),
+                word_timestamps: Vec::new(),
+                confidence: 0.9,
+                no_speech_probability: 0.0,
+                language: Some("en".to_string()),
+                encode_ms: 0,
+                decode_ms: 0,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
+            })
+        }
+    }
+
+    struct Echo;
+
+    impl TextFormatter for Echo {
+        fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> anyhow::Result<String> {
+            Ok(transcript.to_string())
+        }
+    }
+
+    fn handle() -> *mut VoiceFlowHandle {
+        let pipeline = PipelineBuilder::new()
+            .stt(Box::new(FixedTranscript("hello world how are you")))
+            .llm_engine(Box::new(Echo))
+            .build()
+            .unwrap();
+        VoiceFlowHandle::new(pipeline).into_raw()
+    }
+
+    fn speech() -> Vec<f32> {
+        (0..32000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect()
+    }
+
+    /// Where the callbacks report: the handle to make a nested call on (or
+    /// null) and the request ids with whether they, and that call, succeeded
+    struct Reports {
+        handle: *mut VoiceFlowHandle,
+        sender: Mutex<Sender<(u64, bool, bool)>>,
+    }
+
+    extern "C" fn on_done(user_data: *mut c_void, request_id: u64, result: VoiceFlowResult) {
+        let reports = unsafe { &*(user_data as *const Reports) };
+        let nested = reports.handle.is_null() || unsafe {
+            let audio = speech();
+            let nested = crate::voiceflow_process(reports.handle, audio.as_ptr(), audio.len(), ptr::null());
+            let success = nested.success;
+            crate::voiceflow_free_result(nested);
+            success
+        };
+        let _ = lock(&reports.sender).send((request_id, result.success, nested));
+        unsafe { crate::voiceflow_free_result(result) };
+    }
+
+    #[test]
+    fn test_async_requests_finish_in_order_and_may_call_back() {
+        let handle = handle();
+        let (sender, received) = mpsc::channel();
+        let reports = Reports { handle, sender: Mutex::new(sender) };
+        let audio = speech();
+        let user_data = &reports as *const Reports as *mut c_void;
+        unsafe {
+            for _ in 0..3 {
+                let queued = crate::voiceflow_process_async(
+                    handle,
+                    audio.as_ptr(),
+                    audio.len(),
+                    ptr::null(),
+                    user_data,
+                    Some(on_done),
+                );
+                assert_ne!(queued, 0);
+            }
+            // A callback making a call that needs the pipeline doesn't
+            // wait on the worker holding it
+            let done: Vec<_> = (0..3).map(|_| received.recv_timeout(Duration::from_secs(30)).unwrap()).collect();
+            assert_eq!(done, [(1, true, true), (2, true, true), (3, true, true)]);
+            crate::voiceflow_destroy(handle);
+        }
+    }
+
+    #[test]
+    fn test_session_dictations_queue_on_the_worker() {
+        let handle = handle();
+        let (sender, received) = mpsc::channel();
+        let reports = Reports { handle: ptr::null_mut(), sender: Mutex::new(sender) };
+        let audio = speech();
+        unsafe {
+            let session = voiceflow_session_create(handle);
+            let user_data = &reports as *const Reports as *mut c_void;
+            for _ in 0..2 {
+                let queued =
+                    voiceflow_session_process_async(session, audio.as_ptr(), audio.len(), user_data, Some(on_done));
+                assert_ne!(queued, 0);
+            }
+            let done: Vec<_> = (0..2).map(|_| received.recv_timeout(Duration::from_secs(30)).unwrap()).collect();
+            assert_eq!(done, [(1, true, true), (2, true, true)]);
+            // Each dictation was added to the session
+            assert_eq!((*session).dictations(), 2);
+            voiceflow_session_destroy(session);
+            crate::voiceflow_destroy(handle);
+        }
+    }
+}
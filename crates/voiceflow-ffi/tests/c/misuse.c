@@ -0,0 +1,55 @@
+/*
+ * Mistakes a wrapper can make, built with AddressSanitizer by
+ * tests/result_handle_asan.rs: double frees and calls on a destroyed handle
+ * are reported through the last error instead of touching freed memory.
+ */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "voiceflow.h"
+
+/* Whether the last error on this thread says something was already freed */
+static int last_error_is_misuse(void) {
+    char *message = voiceflow_last_error_message();
+    int misuse = voiceflow_last_error_code() == VF_ERR_INVALID_ARGUMENT && message != NULL &&
+                 strstr(message, "already") != NULL;
+    voiceflow_free_string(message);
+    return misuse;
+}
+
+int main(void) {
+    float samples[160] = {0};
+
+    /* Freeing both copies of a flat result frees its strings once */
+    VoiceFlowResult flat = voiceflow_process(NULL, samples, 160, NULL);
+    assert(!flat.success && flat.error_message != NULL);
+    VoiceFlowResult copy = flat;
+    voiceflow_free_result(flat);
+    assert(!last_error_is_misuse());
+    voiceflow_free_result(copy);
+    assert(last_error_is_misuse());
+
+    /* A result handle freed twice, and read after the free */
+    VoiceFlowResultHandle *result = voiceflow_process2(NULL, samples, 160, NULL);
+    voiceflow_result_free(result);
+    assert(voiceflow_result_error(result) == NULL);
+    assert(voiceflow_result_formatted_text(result) == NULL);
+    assert(voiceflow_result_error_code(result) == VF_ERR_INVALID_ARGUMENT);
+    voiceflow_result_free(result);
+    assert(last_error_is_misuse());
+
+    /* A pointer that isn't a live handle is never dereferenced */
+    VoiceFlowHandle *dangling = (VoiceFlowHandle *)samples;
+    VoiceFlowResult rejected = voiceflow_process(dangling, samples, 160, NULL);
+    assert(!rejected.success);
+    assert(voiceflow_last_error_code() == VF_ERR_INVALID_ARGUMENT);
+    voiceflow_free_result(rejected);
+    assert(!voiceflow_is_ready(dangling));
+    voiceflow_cancel(dangling);
+    voiceflow_destroy(dangling);
+    assert(last_error_is_misuse());
+
+    puts("ok");
+    return 0;
+}
@@ -0,0 +1,48 @@
+/*
+ * Ownership rules of the result handle API, built with AddressSanitizer by
+ * tests/result_handle_asan.rs: strings read from a handle stay valid until
+ * voiceflow_result_free, and freeing the handle frees them all.
+ */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "voiceflow.h"
+
+int main(void) {
+    float samples[160] = {0};
+
+    /* Without a pipeline the call fails, but still returns a handle */
+    VoiceFlowResultHandle *result = voiceflow_process2(NULL, samples, 160, NULL);
+    assert(result != NULL);
+    const char *error = voiceflow_result_error(result);
+    assert(error != NULL && strlen(error) > 0);
+    assert(voiceflow_result_error(result) == error);
+    assert(voiceflow_result_error_code(result) == VF_ERR_INVALID_ARGUMENT);
+    assert(voiceflow_result_formatted_text(result) == NULL);
+    assert(voiceflow_result_raw_transcript(result) == NULL);
+    assert(voiceflow_result_timing(result, VF_TIMING_TOTAL) == 0);
+    assert(voiceflow_result_confidence(result) == 0.0f);
+
+    /* Read the whole string, so a short or freed buffer trips ASan */
+    char copy[256];
+    snprintf(copy, sizeof copy, "%s", error);
+    voiceflow_result_free(result);
+    assert(strlen(copy) > 0);
+
+    /* Null handles are safe everywhere */
+    voiceflow_result_free(NULL);
+    assert(voiceflow_result_error(NULL) == NULL);
+    assert(voiceflow_result_formatted_text(NULL) == NULL);
+    assert(voiceflow_result_error_code(NULL) == VF_ERR_INVALID_ARGUMENT);
+    assert(voiceflow_result_timing(NULL, VF_TIMING_LLM) == 0);
+
+    /* The flat API is built on the handle and keeps its own ownership rules */
+    VoiceFlowResult flat = voiceflow_process(NULL, samples, 160, NULL);
+    assert(!flat.success && flat.error_message != NULL);
+    assert(strcmp(flat.error_message, copy) == 0);
+    voiceflow_free_result(flat);
+
+    puts("ok");
+    return 0;
+}
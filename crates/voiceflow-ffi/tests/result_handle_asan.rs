@@ -0,0 +1,63 @@
+//! Builds the programs in tests/c against the shared library with
+//! AddressSanitizer (leak detection included) and runs them
+//!
+//! Needs a C compiler with AddressSanitizer and LeakSanitizer, e.g. clang
+//! or gcc on Linux: `cargo test -p voiceflow-ffi --test result_handle_asan -- --ignored`
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory holding the library built for this test run (target/<profile>)
+fn library_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    // target/<profile>/deps/result_handle_asan-<hash>
+    exe.parent().and_then(Path::parent).unwrap().to_path_buf()
+}
+
+/// Build tests/c/<name>.c against the library with AddressSanitizer and run
+/// it, expecting it to print "ok"
+fn run_under_asan(name: &str) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = library_dir();
+    let program = lib_dir.join(format!("{}_asan_test", name));
+
+    let compiled = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg("-fsanitize=address")
+        .arg("-fno-omit-frame-pointer")
+        .arg("-g")
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join(format!("tests/c/{}.c", name)))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lvoiceflow_ffi")
+        .arg("-o")
+        .arg(&program)
+        .status();
+    match compiled {
+        Ok(status) if status.success() => {}
+        outcome => panic!("Could not build the C test with AddressSanitizer ({:?})", outcome),
+    }
+
+    let output = Command::new(&program).env("ASAN_OPTIONS", "detect_leaks=1").output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "ok");
+}
+
+#[test]
+#[ignore]
+fn test_result_handle_ownership_under_asan() {
+    run_under_asan("result_handle");
+}
+
+#[test]
+#[ignore]
+fn test_misuse_is_caught_under_asan() {
+    run_under_asan("misuse");
+}
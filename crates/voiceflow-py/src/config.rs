@@ -0,0 +1,50 @@
+//! `voiceflow.config`: read and change settings by dotted key
+
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+use voiceflow_core::{Config, ConfigError};
+
+use crate::py_err;
+
+/// Like `py_err`, but an unknown key raises `KeyError`
+fn config_err(err: anyhow::Error) -> PyErr {
+    match err.downcast_ref::<ConfigError>() {
+        Some(ConfigError::UnknownKey { key }) => PyKeyError::new_err(key.clone()),
+        _ => py_err(err),
+    }
+}
+
+/// Read a setting by dotted key, e.g. "llm.temperature"
+///
+/// Strings are returned as they are and everything else as JSON, with
+/// environment overrides applied.
+#[pyfunction]
+#[pyo3(signature = (key, config_path=None))]
+fn get(key: &str, config_path: Option<String>) -> PyResult<String> {
+    Config::load(config_path.as_deref())
+        .and_then(|config| config.get_key(key))
+        .map_err(config_err)
+}
+
+/// Change a setting by dotted key and save the config file
+///
+/// `value` is a string in the format `get` returns, or any value
+/// `json.dumps` accepts (`0.5`, `True`, ...).
+#[pyfunction]
+#[pyo3(signature = (key, value, config_path=None))]
+fn set(key: &str, value: &Bound<'_, PyAny>, config_path: Option<String>) -> PyResult<()> {
+    let value: String = match value.extract() {
+        Ok(value) => value,
+        Err(_) => value.py().import_bound("json")?.call_method1("dumps", (value,))?.extract()?,
+    };
+    let mut config = Config::load_file(config_path.as_deref()).map_err(config_err)?;
+    config.set_key(key, &value).map_err(config_err)?;
+    config.save(config_path.as_deref()).map_err(config_err)
+}
+
+pub(crate) fn register(parent: &Bound<'_, PyModule>) -> PyResult<()> {
+    let module = PyModule::new_bound(parent.py(), "config")?;
+    module.add_function(wrap_pyfunction!(get, &module)?)?;
+    module.add_function(wrap_pyfunction!(set, &module)?)?;
+    parent.add_submodule(&module)
+}
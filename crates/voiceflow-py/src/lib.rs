@@ -0,0 +1,189 @@
+//! Python bindings for VoiceFlow
+//!
+//! Built with maturin into the `voiceflow` module:
+//!
+//! ```python
+//! import voiceflow
+//!
+//! pipeline = voiceflow.Pipeline()
+//! result = pipeline.process(samples_16khz, context="email")
+//! print(result["formatted_text"], result["timings"]["total_ms"])
+//! ```
+//!
+//! Loading and inference run with the GIL released, so pipelines used from
+//! a thread pool run in parallel. A single pipeline runs one call at a time.
+
+use std::sync::Mutex;
+
+use numpy::{PyArray1, PyReadonlyArray1};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use voiceflow_core::llm::FormatContext;
+use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+use voiceflow_core::{Config, Pipeline, SpeechToText, TextFormatter};
+
+mod config;
+mod models;
+
+create_exception!(voiceflow, VoiceFlowError, PyException, "A VoiceFlow call failed");
+
+/// Convert a core error into a Python exception
+///
+/// An exception raised by a Python engine or callback is re-raised as it
+/// was, even from under a `PipelineError` saying which stage failed;
+/// anything else becomes a `VoiceFlowError` with the full cause chain.
+pub(crate) fn py_err(err: anyhow::Error) -> PyErr {
+    match err.downcast::<PyErr>() {
+        Ok(err) => err,
+        Err(err) => match err.chain().find_map(|cause| cause.downcast_ref::<PyErr>()) {
+            Some(raised) => Python::with_gil(|py| raised.clone_ref(py)),
+            None => VoiceFlowError::new_err(format!("{:#}", err)),
+        },
+    }
+}
+
+/// A speech-to-text and formatting pipeline
+///
+/// `Pipeline(config_path=None)` loads the models named by the config file
+/// (the default one if no path is given).
+#[pyclass(name = "Pipeline", module = "voiceflow")]
+struct PyPipeline {
+    pipeline: Mutex<Pipeline>,
+}
+
+#[pymethods]
+impl PyPipeline {
+    #[new]
+    #[pyo3(signature = (config_path=None))]
+    fn new(py: Python<'_>, config_path: Option<String>) -> PyResult<Self> {
+        let pipeline = py
+            .allow_threads(|| Pipeline::new(&Config::load(config_path.as_deref())?))
+            .map_err(|e| py_err(e.into()))?;
+        Ok(Self { pipeline: Mutex::new(pipeline) })
+    }
+
+    /// A pipeline running Python engines instead of the built-in models
+    ///
+    /// `transcribe(samples)` gets the 16kHz mono samples as a float32 numpy
+    /// array and returns the transcript; `format(transcript)` returns the
+    /// formatted text. An exception from `transcribe` is raised by `process`;
+    /// one from `format` falls back to the raw transcript, like a failing LLM.
+    #[staticmethod]
+    fn with_engines(transcribe: PyObject, format: PyObject) -> PyResult<Self> {
+        let pipeline = Pipeline::with_engines(Box::new(PySpeechToText(transcribe)), Box::new(PyFormatter(format)))
+            .map_err(|e| py_err(e.into()))?;
+        Ok(Self { pipeline: Mutex::new(pipeline) })
+    }
+
+    /// Transcribe and format 16kHz mono samples
+    ///
+    /// `samples` is a 1-D float32 numpy array, or bytes of little-endian
+    /// float32. Returns the result as a dict with the fields of
+    /// `voiceflow_process_json` (`formatted_text`, `raw_transcript`,
+    /// `timings`, ...).
+    #[pyo3(signature = (samples, context=None))]
+    fn process(&self, py: Python<'_>, samples: &Bound<'_, PyAny>, context: Option<String>) -> PyResult<PyObject> {
+        let samples = samples_arg(samples)?;
+        let result = py
+            .allow_threads(|| {
+                let mut pipeline = self.pipeline.lock().unwrap_or_else(|e| e.into_inner());
+                pipeline.process(&samples, context.as_deref())
+            })
+            .map_err(|e| py_err(e.into()))?;
+        let json = serde_json::to_value(&result).map_err(|e| py_err(e.into()))?;
+        json_to_py(py, &json)
+    }
+}
+
+/// Copy samples out of a numpy array or bytes, so they can be used without
+/// the GIL
+fn samples_arg(samples: &Bound<'_, PyAny>) -> PyResult<Vec<f32>> {
+    if let Ok(array) = samples.extract::<PyReadonlyArray1<'_, f32>>() {
+        return Ok(array.as_array().iter().copied().collect());
+    }
+    if let Ok(bytes) = samples.downcast::<PyBytes>() {
+        let bytes = bytes.as_bytes();
+        if bytes.len() % 4 != 0 {
+            return Err(PyValueError::new_err("samples bytes must be a whole number of float32 values"));
+        }
+        return Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect());
+    }
+    Err(PyTypeError::new_err(
+        "samples must be a 1-D float32 numpy array or bytes of little-endian float32 (use .astype(numpy.float32))",
+    ))
+}
+
+/// A JSON value as the matching Python object
+pub(crate) fn json_to_py(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    use serde_json::Value;
+
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_py(py),
+            (None, Some(u)) => u.into_py(py),
+            _ => n.as_f64().unwrap_or_default().into_py(py),
+        },
+        Value::String(s) => s.into_py(py),
+        Value::Array(items) => {
+            let items = items.iter().map(|item| json_to_py(py, item)).collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_py(py)
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in fields {
+                dict.set_item(key, json_to_py(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+/// Speech-to-text by a Python callable; see `Pipeline.with_engines`
+struct PySpeechToText(PyObject);
+
+impl SpeechToText for PySpeechToText {
+    fn transcribe(&mut self, audio: &[f32], _opts: &SttOptions) -> anyhow::Result<TranscriptionResult> {
+        let text = Python::with_gil(|py| {
+            let samples = PyArray1::from_slice_bound(py, audio);
+            self.0.call1(py, (samples,))?.extract::<String>(py)
+        })?;
+        Ok(TranscriptionResult {
+            text,
+            word_timestamps: Vec::new(),
+            confidence: 1.0,
+            no_speech_probability: 0.0,
+            language: None,
+            encode_ms: 0,
+            decode_ms: 0,
+            temperature: 0.0,
+            temperature_fallback: false,
+            alternatives: Vec::new(),
+        })
+    }
+}
+
+/// Formatting by a Python callable; see `Pipeline.with_engines`
+struct PyFormatter(PyObject);
+
+impl TextFormatter for PyFormatter {
+    fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> anyhow::Result<String> {
+        Ok(Python::with_gil(|py| self.0.call1(py, (transcript,))?.extract::<String>(py))?)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "voiceflow")]
+fn voiceflow_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("VoiceFlowError", m.py().get_type_bound::<VoiceFlowError>())?;
+    m.add_class::<PyPipeline>()?;
+    config::register(m)?;
+    models::register(m)?;
+    Ok(())
+}
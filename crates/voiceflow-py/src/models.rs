@@ -0,0 +1,85 @@
+//! `voiceflow.models`: list and download models
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use voiceflow_core::downloads::{download_model, DownloadableModel};
+use voiceflow_core::{CancelToken, Config};
+
+use crate::py_err;
+
+/// The given models directory, or the configured one
+fn resolve_models_dir(models_dir: Option<PathBuf>) -> PyResult<PathBuf> {
+    match models_dir {
+        Some(dir) => Ok(dir),
+        None => Config::load(None).and_then(|config| config.models_dir()).map_err(py_err),
+    }
+}
+
+/// Every downloadable model, as dicts of `id`, `name`, `precision` (None
+/// for models other than speech) and `downloaded`
+#[pyfunction]
+#[pyo3(signature = (models_dir=None))]
+fn list(py: Python<'_>, models_dir: Option<PathBuf>) -> PyResult<Vec<PyObject>> {
+    let models_dir = resolve_models_dir(models_dir)?;
+    DownloadableModel::all_models()
+        .iter()
+        .map(|model| {
+            let info = PyDict::new_bound(py);
+            info.set_item("id", model.id())?;
+            info.set_item("name", model.display_name())?;
+            info.set_item("precision", model.precision().map(|precision| precision.id()))?;
+            info.set_item("downloaded", model.is_downloaded(&models_dir))?;
+            Ok(info.into_py(py))
+        })
+        .collect()
+}
+
+/// Download a model by id ("whisper-base", "qwen3-1.7b", ...)
+///
+/// `progress_cb(bytes_downloaded, bytes_total)` is called as the download
+/// goes; `bytes_total` is None if the server didn't report sizes. An
+/// exception from the callback, or Ctrl-C, stops the download and keeps the
+/// partial file so the next call resumes.
+#[pyfunction]
+#[pyo3(signature = (id, progress_cb=None, models_dir=None))]
+fn download(py: Python<'_>, id: &str, progress_cb: Option<PyObject>, models_dir: Option<PathBuf>) -> PyResult<()> {
+    let model =
+        DownloadableModel::from_id(id).ok_or_else(|| PyValueError::new_err(format!("Unknown model id: {}", id)))?;
+    let models_dir = resolve_models_dir(models_dir)?;
+    let cancel = CancelToken::new();
+    let mut callback_err = None;
+
+    let result = py.allow_threads(|| {
+        download_model(&model, &models_dir, &cancel, |done, total| {
+            if callback_err.is_some() {
+                return;
+            }
+            let called = Python::with_gil(|py| {
+                py.check_signals()?;
+                match &progress_cb {
+                    Some(cb) => cb.call1(py, (done, total)).map(drop),
+                    None => Ok(()),
+                }
+            });
+            if let Err(e) = called {
+                callback_err = Some(e);
+                cancel.cancel();
+            }
+        })
+    });
+
+    match callback_err {
+        Some(e) => Err(e),
+        None => result.map_err(py_err),
+    }
+}
+
+pub(crate) fn register(parent: &Bound<'_, PyModule>) -> PyResult<()> {
+    let module = PyModule::new_bound(parent.py(), "models")?;
+    module.add_function(wrap_pyfunction!(list, &module)?)?;
+    module.add_function(wrap_pyfunction!(download, &module)?)?;
+    parent.add_submodule(&module)
+}
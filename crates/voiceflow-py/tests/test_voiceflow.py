@@ -0,0 +1,112 @@
+"""Tests of the Python bindings, with Python engines in place of the models.
+
+Run with `maturin develop && pytest` from crates/voiceflow-py.
+"""
+
+from concurrent.futures import ThreadPoolExecutor
+
+import numpy
+import pytest
+
+import voiceflow
+
+
+def speech():
+    """Two seconds of a loud tone, kept by voice activity detection"""
+    return (numpy.sin(numpy.arange(32000) * 0.1) * 0.5).astype(numpy.float32)
+
+
+def echo_pipeline(transcript="hello world"):
+    return voiceflow.Pipeline.with_engines(
+        lambda samples: transcript,
+        lambda text: text.capitalize() + ".",
+    )
+
+
+def test_process_returns_result_dict():
+    result = echo_pipeline().process(speech())
+    assert result["raw_transcript"] == "hello world"
+    assert result["formatted_text"] == "Hello world."
+    assert result["was_fallback"] is False
+    assert result["timings"]["total_ms"] >= 0
+
+
+def test_process_accepts_bytes():
+    result = echo_pipeline().process(speech().tobytes(), context="email")
+    assert result["formatted_text"] == "Hello world."
+
+
+def test_engines_get_samples_as_numpy():
+    seen = []
+
+    def transcribe(samples):
+        seen.append((samples.dtype, samples.ndim))
+        return "hello"
+
+    voiceflow.Pipeline.with_engines(transcribe, str.upper).process(speech())
+    assert seen and all(s == (numpy.float32, 1) for s in seen)
+
+
+def test_invalid_samples():
+    pipeline = echo_pipeline()
+    with pytest.raises(TypeError):
+        pipeline.process([0.0] * 16000)
+    with pytest.raises(TypeError):
+        pipeline.process(speech().astype(numpy.float64))
+    with pytest.raises(ValueError):
+        pipeline.process(b"\x00\x00\x00")
+
+
+def test_engine_exceptions():
+    def failing(samples):
+        raise RuntimeError("no speech model")
+
+    with pytest.raises(RuntimeError, match="no speech model"):
+        voiceflow.Pipeline.with_engines(failing, str.upper).process(speech())
+
+    def failing_format(text):
+        raise RuntimeError("formatter down")
+
+    result = voiceflow.Pipeline.with_engines(lambda samples: "hello world", failing_format).process(speech())
+    assert result["was_fallback"] is True
+    assert result["raw_transcript"] == "hello world"
+
+
+def test_thread_pool_workers():
+    shared = echo_pipeline()
+    own = [echo_pipeline("worker %d" % i) for i in range(4)]
+    samples = speech()
+
+    with ThreadPoolExecutor(max_workers=4) as pool:
+        shared_results = list(pool.map(lambda _: shared.process(samples), range(8)))
+        own_results = list(pool.map(lambda p: p.process(samples), own))
+
+    assert all(r["formatted_text"] == "Hello world." for r in shared_results)
+    assert [r["raw_transcript"] for r in own_results] == ["worker %d" % i for i in range(4)]
+
+
+def test_config_get_set(tmp_path):
+    path = str(tmp_path / "config.toml")
+    voiceflow.config.set("llm.temperature", 0.5, config_path=path)
+    assert voiceflow.config.get("llm.temperature", config_path=path) == "0.5"
+
+    voiceflow.config.set("llm.temperature", "0.25", config_path=path)
+    assert voiceflow.config.get("llm.temperature", config_path=path) == "0.25"
+
+    with pytest.raises(KeyError):
+        voiceflow.config.get("llm.colour", config_path=path)
+    with pytest.raises(voiceflow.VoiceFlowError):
+        voiceflow.config.set("llm.temperature", "warm", config_path=path)
+
+
+def test_models_list(tmp_path):
+    models = voiceflow.models.list(models_dir=tmp_path)
+    ids = [m["id"] for m in models]
+    assert "whisper-base" in ids
+    assert "qwen3-1.7b" in ids
+    assert not any(m["downloaded"] for m in models)
+
+
+def test_download_unknown_model(tmp_path):
+    with pytest.raises(ValueError):
+        voiceflow.models.download("whisper-gigantic", models_dir=tmp_path)
@@ -0,0 +1,480 @@
+//! HTTP server for transcription from other devices on the network
+//!
+//! - `POST /v1/transcribe`: audio in the body, the result back as JSON
+//! - `GET /v1/models`: the downloadable models and which are present
+//! - `GET /healthz`: liveness, open even when a token is required
+//!
+//! The body is an audio file (WAV, AIFF or CAF, and M4A/AAC, MP3, FLAC or
+//! Ogg with the core's `compressed-audio` feature), or raw little-endian
+//! f32 PCM when the `X-Sample-Rate` header is set (`X-Channels` defaults
+//! to 1). The `context`, `preset`, `language` and `formatting` query
+//! parameters apply to one request. Responses use the envelope of
+//! `voiceflow_process_json`: `{"schema_version", "success", "result"}`, or
+//! `"error"` with a `code` and `message` on failure. Requests share one
+//! pipeline and run one at a time.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::rejection::{BytesRejection, QueryRejection};
+use axum::extract::{DefaultBodyLimit, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use voiceflow_core::audio::{decode_audio, AudioBuffer};
+use voiceflow_core::downloads::DownloadableModel;
+use voiceflow_core::models::storage::is_configured;
+use voiceflow_core::{
+    CancelToken, Config, FormattingMode, FormattingPreset, Pipeline, PipelineError, PipelineResult, ProcessOptions,
+    ProcessProfile, RESULT_SCHEMA_VERSION,
+};
+
+/// Sample rate of a raw f32 PCM body
+pub const SAMPLE_RATE_HEADER: &str = "x-sample-rate";
+/// Channel count of a raw f32 PCM body (interleaved)
+pub const CHANNELS_HEADER: &str = "x-channels";
+
+/// Limits and access control
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Require `Authorization: Bearer <token>` on every route but `/healthz`
+    pub token: Option<String>,
+    /// Largest accepted request body, in bytes
+    pub max_body_bytes: usize,
+    /// Longest a transcription may take, waiting for the pipeline included
+    pub request_timeout: Duration,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            token: None,
+            max_body_bytes: 50 * 1024 * 1024,
+            request_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    pipeline: Arc<Mutex<Pipeline>>,
+    /// The pipeline's config, readable while it is busy
+    config: Arc<Config>,
+    options: Arc<ServerOptions>,
+}
+
+/// Routes serving `pipeline`
+pub fn router(pipeline: Pipeline, options: ServerOptions) -> Router {
+    let state = AppState {
+        config: Arc::new(pipeline.config().clone()),
+        pipeline: Arc::new(Mutex::new(pipeline)),
+        options: Arc::new(options),
+    };
+    Router::new()
+        .route("/v1/transcribe", post(transcribe))
+        .route("/v1/models", get(models))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .route("/healthz", get(|| async { Json(json!({ "status": "ok" })) }))
+        .layer(DefaultBodyLimit::max(state.options.max_body_bytes))
+        .with_state(state)
+}
+
+/// Serve `pipeline` on `addr` until Ctrl-C
+pub async fn serve(addr: SocketAddr, pipeline: Pipeline, options: ServerOptions) -> anyhow::Result<()> {
+    if options.token.is_none() && !addr.ip().is_loopback() {
+        tracing::warn!("Listening on {} without a token: anyone on the network can transcribe", addr);
+    }
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("Listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(pipeline, options))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+/// A failed request: its status, and the error of the response body
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl ToString) -> Self {
+        Self { status, code, message: message.to_string() }
+    }
+
+    fn bad_request(code: &'static str, message: impl ToString) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    fn timeout(timeout: Duration) -> Self {
+        Self::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            "timeout",
+            format!("Transcription took longer than {}s", timeout.as_secs_f32()),
+        )
+    }
+
+    /// Status and code for a pipeline failure
+    fn pipeline(err: PipelineError) -> Self {
+        let (status, code) = match err.without_context() {
+            PipelineError::AudioTooShort { .. } => (StatusCode::UNPROCESSABLE_ENTITY, "audio_too_short"),
+            PipelineError::EmptyAudio => (StatusCode::UNPROCESSABLE_ENTITY, "empty_audio"),
+            PipelineError::InvalidSamples { .. } => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_samples"),
+            PipelineError::AudioTooLong { .. } => (StatusCode::UNPROCESSABLE_ENTITY, "audio_too_long"),
+            PipelineError::InvalidContext { .. } => (StatusCode::BAD_REQUEST, "invalid_context"),
+            PipelineError::SttModelNotFound { .. }
+            | PipelineError::LlmModelNotFound { .. }
+            | PipelineError::SpeakerModelNotFound { .. }
+            | PipelineError::ModelCorrupted { .. } => (StatusCode::SERVICE_UNAVAILABLE, "model_unavailable"),
+            PipelineError::Cancelled { .. } | PipelineError::Timeout { .. } => (StatusCode::GATEWAY_TIMEOUT, "timeout"),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        };
+        let err = anyhow::Error::from(err);
+        tracing::error!("Transcription failed: {:#}", err);
+        Self::new(status, code, format!("{:#}", err))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "schema_version": RESULT_SCHEMA_VERSION,
+            "success": false,
+            "error": { "code": self.code, "message": self.message },
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if let Some(token) = &state.options.token {
+        let sent = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !sent.is_some_and(|sent| constant_time_eq(sent.as_bytes(), token.as_bytes())) {
+            return ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "Missing or wrong bearer token")
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Compare without returning early, so the time taken doesn't reveal how
+/// much of the token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Per-request settings, from the query string
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TranscribeQuery {
+    context: Option<String>,
+    preset: Option<String>,
+    language: Option<String>,
+    formatting: Option<FormattingMode>,
+    stt_context: Option<String>,
+    voice_commands: Option<bool>,
+    app_id: Option<String>,
+    profile: Option<ProcessProfile>,
+}
+
+impl TranscribeQuery {
+    fn process_options(&self, cancel: CancelToken) -> Result<ProcessOptions, ApiError> {
+        let preset = match &self.preset {
+            Some(id) => Some(
+                FormattingPreset::from_id(id)
+                    .ok_or_else(|| ApiError::bad_request("invalid_request", format!("Unknown preset id {:?}", id)))?,
+            ),
+            None => None,
+        };
+        Ok(ProcessOptions {
+            formatting: self.formatting.unwrap_or_default(),
+            preset,
+            language: self.language.clone(),
+            stt_context: self.stt_context.clone(),
+            voice_commands: self.voice_commands,
+            app_id: self.app_id.clone(),
+            profile: self.profile.unwrap_or_default(),
+            cancel,
+            ..ProcessOptions::default()
+        })
+    }
+}
+
+async fn transcribe(
+    State(state): State<AppState>,
+    query: Result<Query<TranscribeQuery>, QueryRejection>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::bad_request("invalid_request", e.body_text()))?;
+    let body = body.map_err(|e| match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::new(e.status(), "payload_too_large", e.body_text()),
+        status => ApiError::new(status, "invalid_request", e.body_text()),
+    })?;
+    let cancel = CancelToken::new();
+    let options = query.process_options(cancel.clone())?;
+    let timeout = state.options.request_timeout;
+
+    let pipeline = state.pipeline.clone();
+    let job = tokio::task::spawn_blocking(move || -> Result<PipelineResult, ApiError> {
+        let samples = decode_body(&headers, &body)?;
+        let mut pipeline = pipeline.lock().unwrap_or_else(|e| e.into_inner());
+        // Timed out while waiting for the pipeline
+        if options.cancel.is_cancelled() {
+            return Err(ApiError::timeout(timeout));
+        }
+        pipeline
+            .process_with_options(&samples, query.context.as_deref(), &options)
+            .map_err(ApiError::pipeline)
+    });
+
+    match tokio::time::timeout(timeout, job).await {
+        Ok(Ok(result)) => {
+            let result = result?;
+            Ok(Json(json!({ "schema_version": RESULT_SCHEMA_VERSION, "success": true, "result": result })))
+        }
+        Ok(Err(e)) => {
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", format!("Internal error: {}", e)))
+        }
+        Err(_) => {
+            cancel.cancel();
+            Err(ApiError::timeout(timeout))
+        }
+    }
+}
+
+/// 16kHz mono samples of a request body
+fn decode_body(headers: &HeaderMap, body: &[u8]) -> Result<Vec<f32>, ApiError> {
+    let buffer = match header_value::<u32>(headers, SAMPLE_RATE_HEADER)? {
+        Some(sample_rate) => {
+            if !body.len().is_multiple_of(4) {
+                return Err(ApiError::bad_request("invalid_audio", "Raw PCM body must be whole f32 samples"));
+            }
+            AudioBuffer {
+                samples: body.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+                sample_rate,
+                channels: header_value::<u16>(headers, CHANNELS_HEADER)?.unwrap_or(1),
+                bits_per_sample: 32,
+            }
+        }
+        None => decode_audio(body, "").map_err(|e| ApiError::bad_request("invalid_audio", e))?,
+    };
+    buffer
+        .as_input()
+        .to_16khz_mono()
+        .map(|samples| samples.into_owned())
+        .map_err(|e| ApiError::bad_request("invalid_audio", e))
+}
+
+fn header_value<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Result<Option<T>, ApiError> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| ApiError::bad_request("invalid_request", format!("Invalid {} header", name)))
+        })
+        .transpose()
+}
+
+async fn models(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let models_dir = state
+        .config
+        .models_dir()
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", format!("{:#}", e)))?;
+    let models: Vec<Value> = DownloadableModel::all_models()
+        .iter()
+        .map(|model| {
+            json!({
+                "id": model.id(),
+                "name": model.display_name(),
+                "precision": model.precision().map(|precision| precision.id()),
+                "downloaded": model.is_downloaded(&models_dir),
+                "configured": is_configured(model, &state.config),
+            })
+        })
+        .collect();
+    Ok(Json(json!({ "models": models })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+    use voiceflow_core::llm::FormatContext;
+    use voiceflow_core::transcribe::{SttOptions, TranscriptionResult};
+    use voiceflow_core::{SpeechToText, TextFormatter};
+
+    struct FixedTranscript(Duration);
+
+    impl SpeechToText for FixedTranscript {
+        fn transcribe(&mut self, _audio: &[f32], _opts: &SttOptions) -> anyhow::Result<TranscriptionResult> {
+            std::thread::sleep(self.0);
+            Ok(TranscriptionResult {
+                text: "hello world".to_string(),
+                word_timestamps: Vec::new(),
+                confidence: 0.9,
+                no_speech_probability: 0.0,
+                language: Some("en".to_string()),
+                encode_ms: 0,
+                decode_ms: 0,
+                temperature: 0.0,
+                temperature_fallback: false,
+                alternatives: Vec::new(),
+            })
+        }
+    }
+
+    struct Capitalize;
+
+    impl TextFormatter for Capitalize {
+        fn format(&mut self, transcript: &str, _ctx: &FormatContext) -> anyhow::Result<String> {
+            Ok(format!("{}.", transcript.replacen('h', "H", 1)))
+        }
+    }
+
+    fn app(options: ServerOptions) -> Router {
+        app_with_delay(options, Duration::ZERO)
+    }
+
+    fn app_with_delay(options: ServerOptions, delay: Duration) -> Router {
+        let pipeline = Pipeline::with_engines(Box::new(FixedTranscript(delay)), Box::new(Capitalize)).unwrap();
+        router(pipeline, options)
+    }
+
+    /// Two seconds of a loud tone at `sample_rate`, kept by voice activity
+    /// detection
+    fn speech(sample_rate: u32) -> Vec<f32> {
+        (0..sample_rate * 2).map(|i| (i as f32 * 1600.0 / sample_rate as f32).sin() * 0.5).collect()
+    }
+
+    fn pcm_bytes(samples: &[f32]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    fn wav_bytes(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| ((s * 32767.0) as i16).to_le_bytes()).collect();
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        wav
+    }
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn post_pcm(uri: &str, samples: &[f32], sample_rate: u32) -> Request<Body> {
+        Request::post(uri)
+            .header(SAMPLE_RATE_HEADER, sample_rate.to_string())
+            .body(Body::from(pcm_bytes(samples)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_pcm_and_wav() {
+        let app = app(ServerOptions::default());
+
+        let (status, body) = send(&app, post_pcm("/v1/transcribe?context=email", &speech(48000), 48000)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["schema_version"], RESULT_SCHEMA_VERSION);
+        assert_eq!(body["success"], true);
+        assert_eq!(body["result"]["raw_transcript"], "hello world");
+        assert_eq!(body["result"]["formatted_text"], "Hello world.");
+
+        let wav = Request::post("/v1/transcribe?formatting=none")
+            .header(header::CONTENT_TYPE, "audio/wav")
+            .body(Body::from(wav_bytes(&speech(16000), 16000)))
+            .unwrap();
+        let (status, body) = send(&app, wav).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["result"]["formatted_text"], "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests() {
+        let app = app(ServerOptions { max_body_bytes: 100_000, ..ServerOptions::default() });
+
+        let garbage = Request::post("/v1/transcribe").body(Body::from(vec![7u8; 64])).unwrap();
+        let (status, body) = send(&app, garbage).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "invalid_audio");
+
+        let (status, body) = send(&app, post_pcm("/v1/transcribe?preset=sonnet", &speech(16000)[..1600], 16000)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "invalid_request");
+
+        let (status, body) = send(&app, post_pcm("/v1/transcribe", &speech(16000)[..800], 16000)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "audio_too_short");
+
+        let (status, body) = send(&app, post_pcm("/v1/transcribe", &speech(16000), 16000)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["success"], false);
+        assert_eq!(body["error"]["code"], "payload_too_large");
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token() {
+        let app = app(ServerOptions { token: Some("s3cret".to_string()), ..ServerOptions::default() });
+
+        let (status, _) = send(&app, Request::get("/healthz").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(&app, Request::get("/v1/models").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"]["code"], "unauthorized");
+
+        let wrong = Request::get("/v1/models").header(header::AUTHORIZATION, "Bearer s3cre").body(Body::empty());
+        let (status, _) = send(&app, wrong.unwrap()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let right = Request::get("/v1/models").header(header::AUTHORIZATION, "Bearer s3cret").body(Body::empty());
+        let (status, body) = send(&app, right.unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        let models = body["models"].as_array().unwrap();
+        assert!(models.iter().any(|m| m["id"] == "whisper-base" && m["configured"] == true));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let options = ServerOptions { request_timeout: Duration::from_millis(50), ..ServerOptions::default() };
+        let app = app_with_delay(options, Duration::from_millis(500));
+        let (status, body) = send(&app, post_pcm("/v1/transcribe", &speech(16000), 16000)).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["error"]["code"], "timeout");
+    }
+}
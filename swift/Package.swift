@@ -0,0 +1,30 @@
+// swift-tools-version:5.9
+//
+// Swift wrapper for the VoiceFlow C API. Build the library first
+// (`cargo build -p voiceflow-ffi --release --features metal`), then point the linker at it:
+//
+//   swift build -Xlinker -L../target/release
+//
+// Sources/CVoiceFlow/voiceflow.h is copied from crates/voiceflow-ffi/include
+// by the ffi crate's build script; don't edit it here.
+
+import PackageDescription
+
+let package = Package(
+    name: "VoiceFlow",
+    platforms: [.macOS(.v13), .iOS(.v16)],
+    products: [
+        .library(name: "VoiceFlow", targets: ["VoiceFlow"]),
+    ],
+    targets: [
+        .systemLibrary(name: "CVoiceFlow", path: "Sources/CVoiceFlow"),
+        .target(name: "VoiceFlow", dependencies: ["CVoiceFlow"]),
+        .testTarget(
+            name: "VoiceFlowTests",
+            dependencies: ["VoiceFlow", "CVoiceFlow"],
+            // Declares voiceflow_init_stub; link a build with the
+            // stub-pipeline feature
+            swiftSettings: [.unsafeFlags(["-Xcc", "-DVOICEFLOW_STUB_PIPELINE"])]
+        ),
+    ]
+)
@@ -0,0 +1,2323 @@
+// VoiceFlow C API - Auto-generated by cbindgen
+//
+// Link against the voiceflow-ffi library, which exports the same symbols on
+// every platform:
+//   macOS:   libvoiceflow_ffi.dylib (or libvoiceflow_ffi.a)
+//   Linux:   libvoiceflow_ffi.so (or libvoiceflow_ffi.a)
+//   Windows: voiceflow_ffi.dll with its import library voiceflow_ffi.dll.lib
+//            (or the static voiceflow_ffi.lib)
+//
+// Strings passed in are UTF-8. Invalid sequences in a context string are
+// replaced with U+FFFD (and a warning logged) rather than dropping it.
+
+#ifndef VOICEFLOW_H
+#define VOICEFLOW_H
+
+/* Warning: this file was auto-generated by cbindgen. Don't modify manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * State of a download reported to the progress callback
+ */
+typedef enum VoiceFlowDownloadStatus {
+  VF_DOWNLOAD_IN_PROGRESS = 0,
+  VF_DOWNLOAD_COMPLETED = 1,
+  /**
+   * See voiceflow_last_error_message from within the callback
+   */
+  VF_DOWNLOAD_FAILED = 2,
+  /**
+   * The partial file is kept, so the next attempt resumes
+   */
+  VF_DOWNLOAD_CANCELLED = 3,
+} VoiceFlowDownloadStatus;
+
+/**
+ * Error category for the last failed call on the current thread
+ */
+typedef enum VoiceFlowErrorCode {
+  VF_ERR_OK = 0,
+  VF_ERR_INVALID_ARGUMENT = 1,
+  VF_ERR_CONFIG = 2,
+  VF_ERR_MODEL_NOT_FOUND = 3,
+  VF_ERR_ONNX = 4,
+  VF_ERR_STT = 5,
+  VF_ERR_LLM = 6,
+  VF_ERR_AUDIO = 7,
+  VF_ERR_IO = 8,
+  VF_ERR_PANIC = 9,
+  VF_ERR_INTERNAL = 10,
+  VF_ERR_CANCELLED = 11,
+  VF_ERR_DOWNLOAD = 12,
+  VF_ERR_MODEL_CORRUPTED = 13,
+  VF_ERR_MODEL_IN_USE = 14,
+  VF_ERR_BUSY = 15,
+  VF_ERR_EMPTY_AUDIO = 16,
+  VF_ERR_INVALID_SAMPLES = 17,
+  VF_ERR_AUDIO_TOO_LONG = 18,
+  VF_ERR_TIMEOUT = 19,
+} VoiceFlowErrorCode;
+
+/**
+ * LLM formatting applied by voiceflow_process_opts
+ */
+typedef enum VoiceFlowFormattingMode {
+  /**
+   * Full context-aware formatting (the default)
+   */
+  VF_FORMAT_FULL = 0,
+  /**
+   * Skip the LLM: formatted_text is the raw transcript and llm_ms is 0
+   */
+  VF_FORMAT_NONE = 1,
+  /**
+   * Only add punctuation and capitalization, never change words
+   */
+  VF_FORMAT_PUNCTUATION_ONLY = 2,
+} VoiceFlowFormattingMode;
+
+/**
+ * Initialization stage reported to the progress callback
+ */
+typedef enum VoiceFlowInitStage {
+  VF_INIT_LOADING_CONFIG = 0,
+  VF_INIT_LOADING_STT_ENCODER = 1,
+  /**
+   * Moonshine only; whisper.cpp loads the whole model as the encoder stage
+   */
+  VF_INIT_LOADING_STT_DECODER = 2,
+  VF_INIT_LOADING_LLM = 3,
+  VF_INIT_WARMING_UP = 4,
+  VF_INIT_READY = 5,
+} VoiceFlowInitStage;
+
+/**
+ * Log verbosity for voiceflow_set_log_level
+ *
+ * Transcript text is only logged at VF_LOG_DEBUG. Warnings are reported
+ * at VF_LOG_ERROR.
+ */
+typedef enum VoiceFlowLogLevel {
+  VF_LOG_OFF = 0,
+  VF_LOG_ERROR = 1,
+  VF_LOG_INFO = 2,
+  VF_LOG_DEBUG = 3,
+} VoiceFlowLogLevel;
+
+/**
+ * How much of a model is downloaded
+ */
+typedef enum VoiceFlowModelStatus {
+  VF_MODEL_NOT_DOWNLOADED = 0,
+  /**
+   * Some of it is downloaded: voiceflow_download_model resumes
+   */
+  VF_MODEL_PARTIAL = 1,
+  VF_MODEL_DOWNLOADED = 2,
+  /**
+   * A file doesn't have the size it was downloaded with:
+   * voiceflow_download_model replaces it
+   */
+  VF_MODEL_CORRUPT = 3,
+} VoiceFlowModelStatus;
+
+/**
+ * Stage of a request reported to the progress callback
+ */
+typedef enum VoiceFlowProcessStage {
+  /**
+   * Downmixing and resampling (only for audio in another format)
+   */
+  VF_STAGE_RESAMPLING = 0,
+  /**
+   * Finding the speech to transcribe
+   */
+  VF_STAGE_DETECTING_SPEECH = 1,
+  /**
+   * done and total count chunks
+   */
+  VF_STAGE_TRANSCRIBING = 2,
+  /**
+   * done counts tokens generated, total the estimated most
+   */
+  VF_STAGE_FORMATTING = 3,
+  /**
+   * The result is ready, at fraction 1
+   */
+  VF_STAGE_DONE = 4,
+} VoiceFlowProcessStage;
+
+/**
+ * STT task selected by voiceflow_process_opts
+ */
+typedef enum VoiceFlowSttTask {
+  /**
+   * The configured task (the default)
+   */
+  VF_TASK_DEFAULT = 0,
+  /**
+   * Text in the spoken language
+   */
+  VF_TASK_TRANSCRIBE = 1,
+  /**
+   * English text whatever the spoken language (Whisper only)
+   */
+  VF_TASK_TRANSLATE = 2,
+} VoiceFlowSttTask;
+
+/**
+ * Timing read by voiceflow_result_timing, in milliseconds
+ */
+typedef enum VoiceFlowTimingKind {
+  VF_TIMING_TOTAL = 0,
+  VF_TIMING_TRANSCRIPTION = 1,
+  VF_TIMING_LLM = 2,
+  /**
+   * Silence trimmed by VAD before transcription
+   */
+  VF_TIMING_TRIMMED = 3,
+  /**
+   * Downmixing and resampling to 16kHz mono
+   */
+  VF_TIMING_AUDIO_PREP = 4,
+  VF_TIMING_VAD = 5,
+  VF_TIMING_STT_ENCODE = 6,
+  VF_TIMING_STT_DECODE = 7,
+  /**
+   * LLM prompt processing, until the first token
+   */
+  VF_TIMING_LLM_PREFILL = 8,
+  VF_TIMING_LLM_GENERATE = 9,
+  /**
+   * Loading the LLM on its first use, part of VF_TIMING_LLM
+   */
+  VF_TIMING_LLM_LOAD = 10,
+  /**
+   * Reloading the STT engine after an idle unload, part of
+   * VF_TIMING_TRANSCRIPTION
+   */
+  VF_TIMING_STT_LOAD = 11,
+} VoiceFlowTimingKind;
+
+/**
+ * Opaque handle to the VoiceFlow pipeline
+ *
+ * The handle is internally synchronized and may be used from any thread.
+ * Calls that need the pipeline while another call is using it block until
+ * it is free; voiceflow_cancel never blocks.
+ */
+typedef struct VoiceFlowHandle VoiceFlowHandle;
+
+/**
+ * Outcome of a voiceflow_process2 call
+ *
+ * Strings returned by the accessors are owned by the handle and stay valid
+ * until voiceflow_result_free. Accessors given a freed handle read it as
+ * null.
+ */
+typedef struct VoiceFlowResultHandle VoiceFlowResultHandle;
+
+/**
+ * Opaque dictation session on a VoiceFlow handle
+ *
+ * Each session keeps its own history, so sessions on the same handle never
+ * share context. A session may be used from any thread, one call at a time.
+ */
+typedef struct VoiceFlowSession VoiceFlowSession;
+
+/**
+ * Timing of one word of the raw transcript
+ */
+typedef struct VoiceFlowWordTiming {
+  char *word;
+  uint64_t start_ms;
+  uint64_t end_ms;
+  /**
+   * 0.0 - 1.0; 0.0 when the timing is estimated (Moonshine)
+   */
+  float confidence;
+} VoiceFlowWordTiming;
+
+/**
+ * One segment of a result, read by voiceflow_result_segment
+ *
+ * The strings are owned by the result handle.
+ */
+typedef struct VoiceFlowSegment {
+  uint64_t start_ms;
+  uint64_t end_ms;
+  const char *raw_text;
+  const char *formatted_text;
+  /**
+   * Mean STT word probability (0.0 - 1.0)
+   */
+  float confidence;
+  /**
+   * Speaker number from 0, or -1 when diarization is off
+   */
+  int32_t speaker;
+} VoiceFlowSegment;
+
+/**
+ * Per-stage timing breakdown of a result
+ *
+ * Stages that didn't run are 0. Whisper can't split encoding from
+ * decoding: stt_encode_ms only covers language detection there.
+ */
+typedef struct VoiceFlowTimings {
+  /**
+   * Downmixing and resampling to 16kHz mono
+   */
+  uint64_t audio_prep_ms;
+  uint64_t vad_ms;
+  uint64_t stt_encode_ms;
+  uint64_t stt_decode_ms;
+  /**
+   * LLM prompt processing, until the first token
+   */
+  uint64_t llm_prefill_ms;
+  uint64_t llm_generate_ms;
+  uint32_t llm_tokens_generated;
+  /**
+   * LLM generation speed after the first token
+   */
+  float tokens_per_second;
+  /**
+   * Tokens spent in <think> blocks, included in llm_tokens_generated
+   */
+  uint32_t llm_thinking_tokens;
+} VoiceFlowTimings;
+
+/**
+ * Result struct returned to foreign callers
+ *
+ * `words` holds `word_count` word timings for the raw transcript (null
+ * when there are none); it is freed by voiceflow_free_result.
+ *
+ * When `no_speech` is set the call succeeded but the audio was judged to
+ * be silence or noise, and both texts are empty.
+ *
+ * On success both texts are non-null. No string holds a NUL byte: they
+ * are removed, and formatted text the LLM garbled with one falls back to
+ * the raw transcript, with formatting_error saying so.
+ */
+typedef struct VoiceFlowResult {
+  bool success;
+  char *formatted_text;
+  char *raw_transcript;
+  char *error_message;
+  uint64_t transcription_ms;
+  uint64_t llm_ms;
+  uint64_t total_ms;
+  /**
+   * Silence trimmed by VAD before transcription
+   */
+  uint64_t trimmed_ms;
+  struct VoiceFlowWordTiming *words;
+  uintptr_t word_count;
+  /**
+   * STT decoder confidence (0.0 - 1.0)
+   */
+  float confidence;
+  /**
+   * Probability that the audio contains no speech (0.0 - 1.0)
+   */
+  float no_speech_probability;
+  bool no_speech;
+  /**
+   * Transcript in the spoken language when translating with
+   * keep_original_transcript set, otherwise null
+   */
+  char *original_transcript;
+  /**
+   * Language the audio was transcribed in, as a null-terminated ISO
+   * 639-1 code ("en", "de"; empty if unknown). Detected when the language
+   * is "auto".
+   */
+  char detected_language[4];
+  /**
+   * Breakdown of transcription_ms and llm_ms by stage
+   */
+  struct VoiceFlowTimings timings;
+  /**
+   * LLM output before cleanup when `llm_output.keep_raw_output` is set
+   * in the config, otherwise null
+   */
+  char *raw_llm_output;
+  /**
+   * formatted_text is the raw transcript because LLM formatting failed or
+   * its output strayed too far from the transcript
+   */
+  bool was_fallback;
+  /**
+   * The LLM output was reused from an earlier identical request, so
+   * llm_ms is 0 (see voiceflow_clear_cache)
+   */
+  bool format_cache_hit;
+  /**
+   * Share of the audio's samples at full scale, in percent
+   */
+  float clipped_percent;
+  /**
+   * So much of the audio is clipped that the user should lower the input
+   * gain (see audio.warn_on_clipping in the config)
+   */
+  bool clipping;
+  /**
+   * NaN or infinite samples in the audio, transcribed as silence (an
+   * error instead with audio.strict in the config)
+   */
+  size_t repaired_samples;
+  /**
+   * The dictation opened with "scratch that": remove the text inserted
+   * for the previous one (sessions forget it themselves)
+   */
+  bool scratch_previous;
+  /**
+   * Why LLM formatting failed, otherwise null; formatted_text is then the
+   * raw transcript and success stays true (a failure instead with
+   * llm.strict in the config)
+   */
+  char *formatting_error;
+} VoiceFlowResult;
+
+/**
+ * Per-call options for voiceflow_process_opts
+ */
+typedef struct VoiceFlowProcessOptions {
+  enum VoiceFlowFormattingMode formatting;
+  /**
+   * Preset id from voiceflow_preset_info, or null for the configured default
+   */
+  const char *preset;
+  /**
+   * ISO 639-1 language code or "auto", or null for the configured language
+   */
+  const char *language;
+  enum VoiceFlowSttTask task;
+} VoiceFlowProcessOptions;
+
+/**
+ * Partial transcript callback for streaming mode
+ *
+ * Receives the raw transcript recognized so far (UTF-8). The string is
+ * owned by the library and stays valid until the next callback or until
+ * voiceflow_stream_finish returns; copy it to keep it.
+ */
+typedef void (*VoiceFlowPartialCallback)(void *userData, const char *partialText);
+
+/**
+ * Utterance callback for hands-free streaming
+ *
+ * Receives each utterance the session ended, formatted, on the thread that
+ * pushed the audio ending it. The callback owns the result and must free
+ * it with voiceflow_free_result; a failed result (e.g. VF_ERR_CANCELLED)
+ * doesn't end the session.
+ */
+typedef void (*VoiceFlowFinalCallback)(void *userData, struct VoiceFlowResult result);
+
+/**
+ * When a hands-free session ends an utterance (see the `[endpointing]`
+ * config section)
+ */
+typedef struct VoiceFlowEndpointingOptions {
+  /**
+   * Silence (ms) after speech that ends the utterance, 100 to 10000
+   */
+  uint32_t trailing_silence_ms;
+  /**
+   * Longest utterance (ms) from its first speech, 1000 to 600000, or 0
+   * for no limit
+   */
+  uint32_t max_utterance_ms;
+} VoiceFlowEndpointingOptions;
+
+/**
+ * Token callback for voiceflow_process_streaming
+ *
+ * Receives the next fragment of formatted text: one or more whole UTF-8
+ * characters, null-terminated. The string is owned by the library and is
+ * only valid during the call; copy it to keep it.
+ */
+typedef void (*VoiceFlowTokenCallback)(void *userData, const char *fragment);
+
+/**
+ * Completion callback for voiceflow_process_async
+ *
+ * Called on the worker's callback thread with the caller's user_data, the
+ * request id returned when the request was queued, and the result (which
+ * the callback must free with voiceflow_free_result).
+ */
+typedef void (*VoiceFlowCompletionCallback)(void *userData,
+                                            uint64_t requestId,
+                                            struct VoiceFlowResult result);
+
+/**
+ * Raw transcript callback for voiceflow_process_two_phase
+ *
+ * Called on a worker thread with the caller's user_data, the request id
+ * and the raw transcript, null-terminated. The string is owned by the
+ * library and is only valid during the call; copy it to keep it. Return
+ * false to skip formatting, as voiceflow_cancel does.
+ */
+typedef bool (*VoiceFlowRawCallback)(void *userData, uint64_t requestId, const char *rawText);
+
+/**
+ * Log callback: receives the level and a formatted message, valid only
+ * for the duration of the call
+ */
+typedef void (*VoiceFlowLogCallback)(void *userData,
+                                     enum VoiceFlowLogLevel level,
+                                     const char *message);
+
+/**
+ * Event callback: receives one closed span as JSON, valid only for the
+ * duration of the call
+ */
+typedef void (*VoiceFlowEventCallback)(void *userData, const char *eventJson);
+
+/**
+ * Panic callback: receives a panic report as a JSON string (see
+ * voiceflow_last_panic_report), valid only for the duration of the call
+ */
+typedef void (*VoiceFlowPanicCallback)(void *userData, const char *reportJson);
+
+/**
+ * Progress callback for voiceflow_download_model
+ *
+ * Called on the download thread with the caller's user_data, the bytes
+ * downloaded so far and the total (0 if unknown). Called repeatedly with
+ * VF_DOWNLOAD_IN_PROGRESS, then exactly once with a terminal status.
+ */
+typedef void (*VoiceFlowDownloadCallback)(void *userData,
+                                          uint64_t bytesDownloaded,
+                                          uint64_t bytesTotal,
+                                          enum VoiceFlowDownloadStatus status);
+
+/**
+ * Progress callback for voiceflow_init_with_progress
+ *
+ * Called on the initializing thread with the caller's user_data, the stage
+ * that is starting and the overall progress (0-100). The last call is
+ * VF_INIT_READY at 100, unless initialization fails.
+ */
+typedef void (*VoiceFlowInitProgressCallback)(void *userData,
+                                              enum VoiceFlowInitStage stage,
+                                              uint32_t percent);
+
+/**
+ * Progress callback for voiceflow_migrate_models_dir
+ *
+ * Called on the migrating thread with the caller's user_data, the bytes
+ * copied so far and the total to copy.
+ */
+typedef void (*VoiceFlowMigrateProgressCallback)(void *userData,
+                                                 uint64_t bytesCopied,
+                                                 uint64_t bytesTotal);
+
+/**
+ * Progress callback for voiceflow_process_batch
+ *
+ * Called on the calling thread after each file with the caller's
+ * user_data, the index of the file that just finished, the files finished
+ * and failed so far, and the number of files.
+ */
+typedef void (*VoiceFlowBatchProgressCallback)(void *userData,
+                                               uintptr_t index,
+                                               uintptr_t completed,
+                                               uintptr_t failed,
+                                               uintptr_t total);
+
+/**
+ * Progress callback for voiceflow_process_with_progress
+ *
+ * Called on the calling thread with the caller's user_data, the stage, the
+ * overall progress (0 to 1, never lower than in the call before) and the
+ * steps of the stage done and to do (0 and 0 for stages without steps).
+ * Return false to cancel the request, as voiceflow_cancel does.
+ */
+typedef bool (*VoiceFlowProgressCallback)(void *userData,
+                                          enum VoiceFlowProcessStage stage,
+                                          float fraction,
+                                          uint32_t done,
+                                          uint32_t total);
+
+/**
+ * Model info struct for FFI
+ */
+typedef struct ModelInfo {
+  char *id;
+  char *display_name;
+  char *filename;
+  float size_gb;
+  /**
+   * Whether status is VF_MODEL_DOWNLOADED
+   */
+  bool is_downloaded;
+  /**
+   * Bytes on disk, partial downloads included
+   */
+  uint64_t downloaded_bytes;
+  /**
+   * Size of the complete model (an estimate until it's downloaded)
+   */
+  uint64_t expected_bytes;
+  enum VoiceFlowModelStatus status;
+} ModelInfo;
+
+/**
+ * Whisper model info struct for FFI
+ */
+typedef struct WhisperModelInfo {
+  char *id;
+  char *display_name;
+  uint32_t size_mb;
+  bool is_downloaded;
+  /**
+   * "float32", "quantized" or "int8"
+   */
+  char *precision;
+} WhisperModelInfo;
+
+/**
+ * Moonshine model info struct for FFI
+ */
+typedef struct MoonshineModelInfo {
+  char *id;
+  char *display_name;
+  uint32_t size_mb;
+  /**
+   * Whether status is VF_MODEL_DOWNLOADED
+   */
+  bool is_downloaded;
+  /**
+   * "float32", "quantized" or "int8"
+   */
+  char *precision;
+  /**
+   * Bytes of its files on disk, partial downloads included
+   */
+  uint64_t downloaded_bytes;
+  /**
+   * Size of the complete model (an estimate until it's downloaded)
+   */
+  uint64_t expected_bytes;
+  /**
+   * VF_MODEL_PARTIAL while any of its files is missing
+   */
+  enum VoiceFlowModelStatus status;
+} MoonshineModelInfo;
+
+/**
+ * Formatting preset info for FFI
+ */
+typedef struct PresetInfo {
+  char *id;
+  char *display_name;
+} PresetInfo;
+
+/**
+ * Transcribe and format many audio files (WAV, AIFF or CAF, plus M4A/AAC,
+ * MP3, FLAC and Ogg with the compressed-audio feature) with the loaded
+ * models
+ *
+ * Returns a JSON array with an object per file, in the order of paths:
+ * `{"path", "raw_transcript", "formatted_text", "no_speech", "was_fallback",
+ * "formatting_error", "language", "total_ms"}` on success, or `{"path", "error": {"code",
+ * "message"}}` if that file failed; one failed file doesn't stop the
+ * others. voiceflow_cancel fails the files not yet processed with
+ * VF_ERR_CANCELLED. Blocks until done; progress_callback may be null.
+ * Returns null on invalid arguments (see voiceflow_last_error_message).
+ * Free the string with voiceflow_free_string.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - paths must point to path_count valid null-terminated strings
+ * - context can be null
+ * - user_data is passed back to the callback untouched
+ */
+char *voiceflow_process_batch(struct VoiceFlowHandle *handle,
+                              const char *const *paths,
+                              uintptr_t pathCount,
+                              const char *context,
+                              VoiceFlowBatchProgressCallback progressCallback,
+                              void *userData);
+
+/**
+ * Download a model into the models directory on a background thread
+ *
+ * model_id is an LLM id from voiceflow_model_info, "whisper-<size>" or
+ * "moonshine-<size>". Files already present are skipped; a partial file
+ * from an interrupted download is resumed. Returns false if the download
+ * could not be started (unknown model, over the memory budget, or already
+ * downloading).
+ *
+ * # Safety
+ * - model_id must be a valid null-terminated string
+ * - user_data is passed back to the callback untouched
+ */
+bool voiceflow_download_model(const char *modelId,
+                              VoiceFlowDownloadCallback progressCallback,
+                              void *userData);
+
+/**
+ * Cancel a download started with voiceflow_download_model
+ *
+ * The callback then reports VF_DOWNLOAD_CANCELLED. Returns false if the
+ * model isn't downloading.
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_cancel_download(const char *modelId);
+
+/**
+ * Check the files of a downloaded model against their recorded checksums
+ *
+ * Hashes every file, so this takes seconds for an LLM: call it off the
+ * main thread. Returns false with VF_ERR_MODEL_CORRUPTED if a file is
+ * damaged (offer voiceflow_download_model, which replaces it) or
+ * VF_ERR_MODEL_NOT_FOUND if one is missing. Files downloaded before
+ * checksums were recorded pass unchecked.
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_verify_model(const char *modelId);
+
+/**
+ * Get the error code of the last failed call on this thread
+ *
+ * Returns VF_ERR_OK if the most recent fallible call succeeded.
+ */
+enum VoiceFlowErrorCode voiceflow_last_error_code(void);
+
+/**
+ * Get the message of the last failed call on this thread, including the
+ * underlying error chain
+ *
+ * Returns null if there is no error. Free with voiceflow_free_string.
+ */
+char *voiceflow_last_error_message(void);
+
+/**
+ * Process audio samples and return the result as JSON
+ *
+ * `options_json` is null or a JSON object with any of "context",
+ * "formatting" ("full", "punctuation-only" or "none"), "preset" (an id from
+ * voiceflow_preset_info), "language", "task" ("transcribe" or "translate"),
+ * "stt_context" (names, jargon or the topic of the recording, to help
+ * recognize them), "voice_commands" (false to keep "comma" and the like as
+ * words), "app_id" (bundle id of the app the text goes into, applying its
+ * override from voiceflow_set_app_override), "profile" ("auto",
+ * "dictation" or "command", the fast path for short voice commands) and
+ * "llm_options" (keys to change, as for voiceflow_set_llm_options).
+ *
+ * Always returns an object with "schema_version" (raised when a field is
+ * renamed, removed or changes type) and "success". On success, "result"
+ * holds every field of the result: "raw_transcript", "formatted_text",
+ * "timings", "prosody_hints", "word_timestamps", "confidence",
+ * "alternatives" (other readings of the audio, each with its "text" and
+ * "confidence", when stt_decode.alternatives is above 1),
+ * "no_speech_probability", "no_speech", "language", "original_transcript",
+ * "raw_llm_output", "was_fallback", "formatting_error" (why LLM
+ * formatting failed when the raw transcript was returned instead),
+ * "prompt_truncation" (what was cut to fit the LLM's context window:
+ * "history_truncated", "context_truncated" and "transcript_chunks"),
+ * "format_cache_hit", "clipped_percent", "clipping", "repaired_samples",
+ * "filtered_segments" (text removed as made up by the STT engine, each
+ * with its "text" and "reason":
+ * "repetition" or "known_phrase"), "scratch_previous" and "segments"
+ * (the transcript split at long pauses and speaker turns, each with its
+ * "start_ms", "end_ms", "raw_text", "formatted_text", "confidence" and
+ * "speaker", numbered from 0 when diarization is enabled) and "fast_path"
+ * (taken as a short command), with null for unset values. On failure,
+ * "error" holds "code" (a VoiceFlowErrorCode) and "message", which are
+ * also available from voiceflow_last_error_code/_message. Free the string
+ * with voiceflow_free_string.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - options_json must be null or a valid null-terminated string
+ */
+char *voiceflow_process_json(struct VoiceFlowHandle *handle,
+                             const float *audioData,
+                             uintptr_t audioLen,
+                             const char *optionsJson);
+
+/**
+ * Limit the models offered to those that run in max_ram_bytes of memory
+ *
+ * 0 removes the limit. Applies to the whole process, from the next catalog
+ * or download call.
+ */
+void voiceflow_set_memory_budget(uint64_t maxRamBytes);
+
+/**
+ * Suggest models for a device with max_ram_bytes of memory to spare
+ *
+ * Returns a JSON object with the speech and formatting models that run
+ * together in that much memory, and every model that fits on its own, e.g.
+ * `{"stt": "whisper-small", "llm": "qwen3-1.7b", "fitting": ["whisper-tiny", ...]}`.
+ * "stt" or "llm" is null if nothing fits. 0 uses the budget from
+ * voiceflow_set_memory_budget (no limit if unset). Free the string with
+ * voiceflow_free_string.
+ */
+char *voiceflow_recommended_models(uint64_t maxRamBytes);
+
+/**
+ * Free memory after a memory warning, keeping the handle usable
+ *
+ * Unloads the LLM with its KV cache; it reloads on the next request that
+ * formats, so that request is slower. The weights stay loaded while
+ * another handle shares them (see voiceflow_loaded_models). Returns false
+ * if there was nothing to free, or if a request is running (try again once
+ * it returns).
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+bool voiceflow_trim_memory(struct VoiceFlowHandle *handle);
+
+/**
+ * List the models whose weights are loaded, as a JSON array
+ *
+ * Handles initialized with the same model file and load settings share
+ * its weights, each keeping only its own decoder state and KV cache, and
+ * the weights are freed with the last handle using them. Each entry has
+ * "kind" ("whisper", "moonshine" or "llm"), "path" and "users" (the
+ * engines sharing the weights, one per handle), e.g. `[{"kind": "llm",
+ * "path": ".../qwen3-1.7b-q4_k_m.gguf", "users": 2}]`. Free the string
+ * with voiceflow_free_string.
+ */
+char *voiceflow_loaded_models(void);
+
+/**
+ * Store models in `path` instead of the platform data directory
+ *
+ * The directory is created if needed and must be writable. The setting is
+ * saved to the config file and used by handles created afterwards; models
+ * already downloaded stay where they are (see voiceflow_migrate_models_dir).
+ * An empty path goes back to the platform default. Apps that don't use the
+ * config file set "models_dir_override" in voiceflow_init_with_config_json
+ * instead.
+ *
+ * # Safety
+ * path must be a valid null-terminated string
+ */
+bool voiceflow_set_models_dir(const char *path);
+
+/**
+ * Move every model from old_dir to new_dir
+ *
+ * Each file is copied and verified against the original before any
+ * original is removed, so on failure old_dir is left intact. Files already
+ * in new_dir with the same contents are not copied again. Blocks until
+ * done: call it off the main thread, with no handle using old_dir, then
+ * call voiceflow_set_models_dir(new_dir). progress_callback may be null.
+ * Returns VF_ERR_OK, or the error code (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - old_dir and new_dir must be valid null-terminated strings
+ * - user_data is passed back to the callback untouched
+ */
+enum VoiceFlowErrorCode voiceflow_migrate_models_dir(const char *oldDir,
+                                                     const char *newDir,
+                                                     VoiceFlowMigrateProgressCallback progressCallback,
+                                                     void *userData);
+
+/**
+ * Get the names of the profiles in the config file as a JSON array, sorted
+ *
+ * Free the string with voiceflow_free_string.
+ */
+char *voiceflow_profile_list(void);
+
+/**
+ * Save the handle's current settings as a profile in the config file
+ *
+ * The profile holds the settings that differ from the rest of the config
+ * file, and replaces any profile with the same name. The log file and
+ * models directory aren't part of profiles. Returns false if the name is
+ * empty or the config can't be saved (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - name must be a valid null-terminated string
+ */
+bool voiceflow_profile_save_current(struct VoiceFlowHandle *handle, const char *name);
+
+/**
+ * Switch a running handle to a profile from the config file
+ *
+ * The handle gets the config file's settings with the profile applied (and
+ * the VOICEFLOW_* environment overrides), as voiceflow_init_with_profile
+ * would load them. Only the models whose settings changed are reloaded,
+ * so this blocks for as long as loading them takes: call it off the main
+ * thread. Returns a JSON object listing the reloaded components, e.g.
+ * `{"reloaded": ["stt", "llm"]}`, or null for an unknown profile, invalid
+ * settings or a model that fails to load (see
+ * voiceflow_last_error_message); settings applied before the failure are
+ * kept. Free the string with voiceflow_free_string.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - name must be a valid null-terminated string
+ */
+char *voiceflow_profile_activate(struct VoiceFlowHandle *handle, const char *name);
+
+/**
+ * Process audio samples, returning a result handle
+ *
+ * Same as voiceflow_process, but the result is read through the
+ * voiceflow_result_* accessors, which keep working as fields are added.
+ * Never returns null, even on error: check voiceflow_result_error. Free the
+ * handle with voiceflow_result_free.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context can be null
+ */
+struct VoiceFlowResultHandle *voiceflow_process2(struct VoiceFlowHandle *handle,
+                                                const float *audioData,
+                                                uintptr_t audioLen,
+                                                const char *context);
+
+/**
+ * voiceflow_process2 for the request given `request_id` by
+ * voiceflow_new_request, which voiceflow_cancel_request cancels
+ *
+ * An id not reserved, or used already, fails with
+ * VF_ERR_INVALID_ARGUMENT.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context can be null
+ */
+struct VoiceFlowResultHandle *voiceflow_process2_request(struct VoiceFlowHandle *handle,
+                                                        uint64_t requestId,
+                                                        const float *audioData,
+                                                        uintptr_t audioLen,
+                                                        const char *context);
+
+/**
+ * Formatted text of a result, or null if the call failed
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+const char *voiceflow_result_formatted_text(const struct VoiceFlowResultHandle *result);
+
+/**
+ * Raw transcript of a result, or null if the call failed
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+const char *voiceflow_result_raw_transcript(const struct VoiceFlowResultHandle *result);
+
+/**
+ * Error message of a result, or null if the call succeeded
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+const char *voiceflow_result_error(const struct VoiceFlowResultHandle *result);
+
+/**
+ * Error code of a result: VF_ERR_OK if the call succeeded
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+enum VoiceFlowErrorCode voiceflow_result_error_code(const struct VoiceFlowResultHandle *result);
+
+/**
+ * One timing of a result, in milliseconds
+ *
+ * Stages that didn't run are 0. A cancelled call keeps the time spent
+ * before it stopped; other failures are 0.
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+uint64_t voiceflow_result_timing(const struct VoiceFlowResultHandle *result,
+                                 enum VoiceFlowTimingKind kind);
+
+/**
+ * STT decoder confidence of a result (0.0 - 1.0), 0.0 if the call failed
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+float voiceflow_result_confidence(const struct VoiceFlowResultHandle *result);
+
+/**
+ * Why LLM formatting failed, or null if it didn't or the call failed
+ *
+ * The formatted text is then the raw transcript; with llm.strict in the
+ * config the call fails instead.
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+const char *voiceflow_result_formatting_error(const struct VoiceFlowResultHandle *result);
+
+/**
+ * Number of segments of a result (see voiceflow_result_segment), 0 if the
+ * call failed
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2 not yet freed
+ */
+uintptr_t voiceflow_result_segment_count(const struct VoiceFlowResultHandle *result);
+
+/**
+ * Read segment `index` of a result into `out`
+ *
+ * Segments split the transcript at long pauses and, with diarization, at
+ * speaker turns; their formatted texts joined are the formatted text.
+ * Returns false, leaving `out` alone, if the call failed or `index` is out
+ * of range.
+ *
+ * # Safety
+ * - result must be null or a handle from voiceflow_process2 not yet freed
+ * - out must be null or point to a VoiceFlowSegment
+ */
+bool voiceflow_result_segment(const struct VoiceFlowResultHandle *result,
+                              uintptr_t index,
+                              struct VoiceFlowSegment *out);
+
+/**
+ * Free a result handle and every string read from it
+ *
+ * Freeing a handle twice frees nothing the second time and sets
+ * VF_ERR_INVALID_ARGUMENT as the last error.
+ *
+ * # Safety
+ * result must be null or a handle from voiceflow_process2
+ */
+void voiceflow_result_free(struct VoiceFlowResultHandle *result);
+
+/**
+ * Start a dictation session on this handle
+ *
+ * Returns null on error (see voiceflow_last_error_message). Free the
+ * session with voiceflow_session_destroy.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ */
+struct VoiceFlowSession *voiceflow_session_create(struct VoiceFlowHandle *handle);
+
+/**
+ * Process audio samples as the next dictation of the session
+ *
+ * Same as voiceflow_process, with the session's earlier formatted outputs
+ * given to the LLM as context (up to session_context_tokens in the config).
+ * The formatted text is then added to the session.
+ *
+ * # Safety
+ * - session must be a valid pointer from voiceflow_session_create
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ */
+struct VoiceFlowResult voiceflow_session_process(struct VoiceFlowSession *session,
+                                                 const float *audioData,
+                                                 uintptr_t audioLen);
+
+/**
+ * Process audio samples as the next dictation of the session on a
+ * background thread, reporting the result through a completion callback
+ *
+ * Same as voiceflow_session_process, queued like voiceflow_process_async
+ * on a worker of the session's own: dictations run in submission order,
+ * each formatted with the ones before it as context, and the next is
+ * transcribed while the LLM formats the current one. The request ids are
+ * the session's own. The audio is copied, so the caller may release its
+ * buffer as soon as this returns. The callback owns the result: free it
+ * with voiceflow_free_result.
+ *
+ * Returns a non-zero request id, or 0 if the request could not be queued
+ * (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - session must be a valid pointer from voiceflow_session_create
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - user_data is passed back to the callback untouched
+ */
+uint64_t voiceflow_session_process_async(struct VoiceFlowSession *session,
+                                         const float *audioData,
+                                         uintptr_t audioLen,
+                                         void *userData,
+                                         VoiceFlowCompletionCallback callback);
+
+/**
+ * Forget the session's earlier dictations
+ *
+ * # Safety
+ * - session must be a valid pointer from voiceflow_session_create
+ */
+void voiceflow_session_reset(struct VoiceFlowSession *session);
+
+/**
+ * Free a session
+ *
+ * The handle it was created on is not affected. Blocks until dictations
+ * queued with voiceflow_session_process_async have finished and their
+ * callbacks have run.
+ *
+ * # Safety
+ * - Only call this once per session, with no call running on it
+ */
+void voiceflow_session_destroy(struct VoiceFlowSession *session);
+
+/**
+ * Start a streaming session on this handle
+ *
+ * Any session already in progress is discarded. Returns false on error
+ * (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - user_data is passed back to the callback untouched
+ */
+bool voiceflow_stream_start(struct VoiceFlowHandle *handle,
+                            VoiceFlowPartialCallback callback,
+                            void *userData);
+
+/**
+ * Start a hands-free streaming session on this handle
+ *
+ * Like voiceflow_stream_start, but the session ends each utterance by
+ * itself once the speaker pauses for `trailing_silence_ms` (or after
+ * `max_utterance_ms`): voiceflow_stream_push then transcribes its tail,
+ * formats it with `context` and passes the result to `on_final`, before
+ * returning. Audio pushed after that point starts the next utterance, so
+ * speech that begins while the previous one is formatted is kept; the
+ * partial callback then reports the new utterance's transcript.
+ * voiceflow_stream_finish returns the utterance in progress.
+ *
+ * Returns false on error (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - options can be null for the config's `[endpointing]` thresholds
+ * - context can be null
+ * - user_data is passed back to both callbacks untouched
+ */
+bool voiceflow_stream_start_hands_free(struct VoiceFlowHandle *handle,
+                                       const struct VoiceFlowEndpointingOptions *options,
+                                       const char *context,
+                                       VoiceFlowPartialCallback onPartial,
+                                       VoiceFlowFinalCallback onFinal,
+                                       void *userData);
+
+/**
+ * Push 16kHz mono samples into the streaming session
+ *
+ * When an utterance ends this transcribes it before returning and invokes
+ * the partial callback on the calling thread (and, in a hands-free
+ * session, formats it and invokes on_final first), so call it from a
+ * worker queue rather than the real-time audio thread. The callbacks must
+ * not call back into the voiceflow_stream_* functions.
+ *
+ * Returns false on error (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - samples must point to len floats
+ */
+bool voiceflow_stream_push(struct VoiceFlowHandle *handle, const float *samples, uintptr_t len);
+
+/**
+ * Push 16kHz mono 16-bit PCM samples into the streaming session
+ *
+ * Same as voiceflow_stream_push, but takes Int16 samples directly.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - samples must point to len int16 samples
+ */
+bool voiceflow_stream_push_i16(struct VoiceFlowHandle *handle,
+                               const int16_t *samples,
+                               uintptr_t len);
+
+/**
+ * Finish the streaming session and return the formatted result
+ *
+ * Transcribes any remaining audio, then runs prosody and LLM formatting on
+ * the full transcript (of the utterance in progress, for a hands-free
+ * session). Free the result with voiceflow_free_result.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - context can be null
+ */
+struct VoiceFlowResult voiceflow_stream_finish(struct VoiceFlowHandle *handle, const char *context);
+
+#if defined(VOICEFLOW_STUB_PIPELINE)
+/**
+ * Create a handle whose pipeline transcribes any audio as `transcript`
+ *
+ * No config or model is loaded: the formatter only capitalizes the
+ * transcript and ends it with a period. Only exported by builds with the
+ * stub-pipeline feature. Returns null on error (see
+ * voiceflow_last_error_message); free the handle with voiceflow_destroy.
+ *
+ * # Safety
+ * transcript must be a valid null-terminated string
+ */
+struct VoiceFlowHandle *voiceflow_init_stub(const char *transcript);
+#endif
+
+/**
+ * Get SubRip (.srt) subtitles for the raw transcript of a result
+ *
+ * Words are grouped into captions of up to two lines of 42 characters,
+ * shown for at most 7 seconds. A result without speech gives an empty
+ * string. Returns null with VF_ERR_INVALID_ARGUMENT if the result failed
+ * or has no word timings (streaming results). Free the string with
+ * voiceflow_free_string.
+ *
+ * # Safety
+ * result must point to a result that hasn't been freed yet
+ */
+char *voiceflow_result_to_srt(const struct VoiceFlowResult *result);
+
+/**
+ * Get WebVTT (.vtt) subtitles for the raw transcript of a result
+ *
+ * Same as voiceflow_result_to_srt, in WebVTT format.
+ *
+ * # Safety
+ * result must point to a result that hasn't been freed yet
+ */
+char *voiceflow_result_to_vtt(const struct VoiceFlowResult *result);
+
+/**
+ * Process audio samples, passing the formatted text to `token_callback` as
+ * the LLM generates it
+ *
+ * Same as voiceflow_process otherwise. The callback runs on the calling
+ * thread, and every fragment is delivered before this returns. Fragments are
+ * the model's output before cleanup, so the concatenation can differ from
+ * the returned formatted_text, which is the text to use. Nothing is streamed
+ * when the LLM isn't run.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context can be null
+ * - user_data is passed back to the callback untouched
+ */
+struct VoiceFlowResult voiceflow_process_streaming(struct VoiceFlowHandle *handle,
+                                                   const float *audioData,
+                                                   uintptr_t audioLen,
+                                                   const char *context,
+                                                   VoiceFlowTokenCallback tokenCallback,
+                                                   void *userData);
+
+/**
+ * Process audio samples with per-call options, reporting progress to
+ * `progress_callback` as the request runs
+ *
+ * Same as voiceflow_process_opts otherwise. The callback runs on the
+ * calling thread at most about ten times a second, with the stages in
+ * order and a last call at VF_STAGE_DONE when the request succeeds. It
+ * can return false to cancel, and voiceflow_cancel works as for any
+ * request; either way the result fails with VF_ERR_CANCELLED.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context can be null
+ * - options can be null for the defaults (see voiceflow_process_opts)
+ * - user_data is passed back to the callback untouched
+ */
+struct VoiceFlowResult voiceflow_process_with_progress(struct VoiceFlowHandle *handle,
+                                                       const float *audioData,
+                                                       uintptr_t audioLen,
+                                                       const char *context,
+                                                       const struct VoiceFlowProcessOptions *options,
+                                                       VoiceFlowProgressCallback progressCallback,
+                                                       void *userData);
+
+/**
+ * Process audio samples on the worker thread in two passes: the raw
+ * transcript goes to `on_raw` as soon as transcription is done, and the
+ * result, with the formatted text, to `on_formatted` once the LLM has run
+ *
+ * Queued with voiceflow_process_async requests and run in submission
+ * order, with the same request id passed to both callbacks. on_raw gets
+ * the transcript with voice commands, numbers and replacements applied
+ * (empty when there is no speech), and is always called before
+ * on_formatted unless the request fails first, when only on_formatted is
+ * called. Between the two, voiceflow_cancel (or on_raw returning false)
+ * stops formatting, and on_formatted gets a failed result with
+ * VF_ERR_CANCELLED. The audio is copied, so the caller may release its
+ * buffer as soon as this returns. on_formatted owns the result: free it
+ * with voiceflow_free_result.
+ *
+ * Returns a non-zero request id, or 0 if the request could not be queued
+ * (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - options can be null for the defaults (see voiceflow_process_opts)
+ * - user_data is passed back to the callbacks untouched
+ */
+uint64_t voiceflow_process_two_phase(struct VoiceFlowHandle *handle,
+                                     const float *audioData,
+                                     uintptr_t audioLen,
+                                     const struct VoiceFlowProcessOptions *options,
+                                     VoiceFlowRawCallback onRaw,
+                                     VoiceFlowCompletionCallback onFormatted,
+                                     void *userData);
+
+/**
+ * Initialize the VoiceFlow pipeline
+ *
+ * # Safety
+ * config_path must be a valid null-terminated string or null for default
+ */
+struct VoiceFlowHandle *voiceflow_init(const char *configPath);
+
+/**
+ * Initialize the VoiceFlow pipeline, reporting each loading stage
+ *
+ * Unlike voiceflow_init, the LLM is loaded and the STT engine warmed up
+ * before returning, so the first request doesn't pay for them. Blocks like
+ * voiceflow_init: call it off the main thread. Returns null on error (see
+ * voiceflow_last_error_message).
+ *
+ * # Safety
+ * - config_path must be a valid null-terminated string or null for default
+ * - user_data is passed back to the callback untouched
+ */
+struct VoiceFlowHandle *voiceflow_init_with_progress(const char *configPath,
+                                                     VoiceFlowInitProgressCallback progressCallback,
+                                                     void *userData);
+
+/**
+ * Initialize the VoiceFlow pipeline from a JSON config, without reading or
+ * writing the config file
+ *
+ * For apps that keep their own settings store. The JSON object has the
+ * format of voiceflow_config_json; fields it leaves out take their
+ * defaults. Change settings later with voiceflow_update_config_json. Blocks
+ * like voiceflow_init. Returns null for invalid JSON, unknown fields or
+ * invalid values (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * json_object must be a valid null-terminated string
+ */
+struct VoiceFlowHandle *voiceflow_init_with_config_json(const char *jsonObject);
+
+/**
+ * Initialize the VoiceFlow pipeline with a profile from the config file
+ *
+ * Like voiceflow_init, with the settings of profile_name applied over the
+ * config file (environment overrides still come last). Switch a running
+ * handle to another profile with voiceflow_profile_activate. Returns null
+ * for an unknown profile or invalid settings (see
+ * voiceflow_last_error_message).
+ *
+ * # Safety
+ * - config_path must be a valid null-terminated string or null for default
+ * - profile_name must be a valid null-terminated string
+ */
+struct VoiceFlowHandle *voiceflow_init_with_profile(const char *configPath,
+                                                    const char *profileName);
+
+/**
+ * Check if the handle can take requests, to gate recording in the UI
+ *
+ * False for a null handle, while voiceflow_reload_model or
+ * voiceflow_reload_stt_engine is swapping a model, and during
+ * voiceflow_warmup.
+ *
+ * # Safety
+ * handle must be null or a valid pointer from voiceflow_init
+ */
+bool voiceflow_is_ready(struct VoiceFlowHandle *handle);
+
+/**
+ * Run a short synthetic inference through the STT engine and the LLM, so
+ * the first request isn't slower than the rest
+ *
+ * Loads the LLM if it isn't yet; voiceflow_is_ready is false meanwhile.
+ * Returns the time taken in milliseconds, or -1 on error (see
+ * voiceflow_last_error_message). Set warm_up_on_init in the config to
+ * warm up during voiceflow_init instead.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+int64_t voiceflow_warmup(struct VoiceFlowHandle *handle);
+
+/**
+ * Check the models still work: run a bundled recording of about a second
+ * through the whole pipeline and report on each stage, as a JSON object
+ *
+ * The object has "passed" (no stage failed), "stages" (in order "audio",
+ * "stt", "llm" and "expected", each with "stage", "status" ("passed",
+ * "failed" or "skipped"), "latency_ms" and "error"), "total_ms",
+ * "stt_engine" (as from voiceflow_active_stt_engine), "stt_provider" (as
+ * from voiceflow_current_stt_provider), "llm", "transcript" and
+ * "formatted_text". A stage fails on an error, when no speech is
+ * recognized, or when formatting fell back to the raw transcript; the
+ * stages after a failed one are skipped. Loads the LLM if it isn't yet;
+ * voiceflow_is_ready is false meanwhile. Meant to run in the background
+ * after an update, to offer a model re-download when it fails. Returns
+ * null for a null handle (see voiceflow_last_error_message). Free the
+ * string with voiceflow_free_string.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+char *voiceflow_self_test(struct VoiceFlowHandle *handle);
+
+/**
+ * Process audio samples and return formatted text
+ *
+ * The flat result of voiceflow_process2; prefer that in new code.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context can be null
+ */
+struct VoiceFlowResult voiceflow_process(struct VoiceFlowHandle *handle,
+                                         const float *audioData,
+                                         uintptr_t audioLen,
+                                         const char *context);
+
+/**
+ * Process audio samples with per-call options
+ *
+ * Same as voiceflow_process; `options` selects how much LLM formatting to
+ * apply for this call only. With VF_FORMAT_NONE the LLM is not loaded or
+ * run.
+ *
+ * `context_json` describes where the text goes, as a JSON object with any
+ * of "app_name", "document_kind" (e.g. "email", picking the prompt),
+ * "recipient", "text_before_cursor", "text_after_cursor" and
+ * "user_instructions". With the text before the cursor, the result is
+ * capitalized and punctuated to continue it. Unknown fields are ignored
+ * with a warning; a plain string (e.g. "email") still works, as
+ * user_instructions. Malformed JSON fails with VF_ERR_INVALID_ARGUMENT.
+ * Every function taking a context accepts the same.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context_json can be null
+ * - options can be null for the defaults (full formatting, configured preset)
+ * - options->preset must be null or a valid null-terminated string
+ */
+struct VoiceFlowResult voiceflow_process_opts(struct VoiceFlowHandle *handle,
+                                              const float *audioData,
+                                              uintptr_t audioLen,
+                                              const char *contextJson,
+                                              const struct VoiceFlowProcessOptions *options);
+
+/**
+ * Process audio at any sample rate and return formatted text
+ *
+ * Audio is downmixed to mono and resampled to 16kHz internally, so
+ * microphone buffers (typically 44.1kHz or 48kHz) can be passed as-is.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats, interleaved if channels > 1
+ * - channels of 0 is treated as mono
+ * - context can be null
+ */
+struct VoiceFlowResult voiceflow_process_with_rate(struct VoiceFlowHandle *handle,
+                                                   const float *audioData,
+                                                   uintptr_t audioLen,
+                                                   uint32_t sampleRate,
+                                                   uint16_t channels,
+                                                   const char *context);
+
+/**
+ * Transcribe and format an audio file (WAV, AIFF or CAF, plus M4A/AAC, MP3,
+ * FLAC and Ogg with the compressed-audio feature)
+ *
+ * The file is decoded, downmixed and resampled as needed. Unsupported,
+ * corrupt or DRM-protected files fail with VF_ERR_AUDIO and a message such
+ * as "unsupported bit depth 12".
+ *
+ * voiceflow_cancel stops it as any request, with VF_ERR_CANCELLED.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - path must be a valid null-terminated string
+ * - context can be null
+ */
+struct VoiceFlowResult voiceflow_process_file(struct VoiceFlowHandle *handle,
+                                              const char *path,
+                                              const char *context);
+
+/**
+ * Process 16-bit PCM samples and return formatted text
+ *
+ * Same as voiceflow_process, but takes Int16 samples directly (as
+ * delivered by an AVAudioEngine tap) and normalizes them in Rust.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len int16 samples (16kHz mono PCM)
+ * - context can be null
+ */
+struct VoiceFlowResult voiceflow_process_i16(struct VoiceFlowHandle *handle,
+                                             const int16_t *audioData,
+                                             uintptr_t audioLen,
+                                             const char *context);
+
+/**
+ * Process audio samples on a background thread and report the result
+ * through a completion callback
+ *
+ * Requests on the same handle are queued and run in submission order, each
+ * transcribed while the LLM formats the one before it. The audio and
+ * context are copied, so the caller may release its buffers as soon as this
+ * returns. The callbacks run one at a time, in the same order, on a thread
+ * of their own, and may call back into the library. The callback owns the
+ * result: free it with voiceflow_free_result.
+ *
+ * Returns a non-zero request id that is passed back to the callback, or 0
+ * if the request could not be queued (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - audio_data must point to audio_len floats (16kHz mono PCM)
+ * - context can be null
+ * - user_data is passed back to the callback untouched
+ */
+uint64_t voiceflow_process_async(struct VoiceFlowHandle *handle,
+                                 const float *audioData,
+                                 uintptr_t audioLen,
+                                 const char *context,
+                                 void *userData,
+                                 VoiceFlowCompletionCallback callback);
+
+/**
+ * Cancel the request currently being processed on this handle
+ *
+ * The running voiceflow_process stops at its next checkpoint and returns a
+ * failed result with VF_ERR_CANCELLED and the timings up to that point, and
+ * so does a call still waiting for the handle. Of the async requests, the
+ * one being formatted and the next one, already being transcribed, are
+ * stopped; those still queued are not affected.
+ * Safe to call from any thread.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+void voiceflow_cancel(struct VoiceFlowHandle *handle);
+
+/**
+ * Reserve an id for a request that voiceflow_cancel_request can cancel on
+ * its own
+ *
+ * Pass the id to voiceflow_process2_request, once; a cancel sent in
+ * between still stops that request. Returns 0 for an invalid handle.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+uint64_t voiceflow_new_request(struct VoiceFlowHandle *handle);
+
+/**
+ * Cancel the request given `request_id` from voiceflow_new_request, as
+ * voiceflow_cancel does, leaving the handle's other requests running
+ *
+ * Ids of requests that already returned are ignored. Safe to call from any
+ * thread.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+void voiceflow_cancel_request(struct VoiceFlowHandle *handle, uint64_t requestId);
+
+/**
+ * Free a VoiceFlowResult's strings and word timings
+ *
+ * Freeing a result twice, or a copy of a freed one, frees nothing and sets
+ * VF_ERR_INVALID_ARGUMENT as the last error.
+ *
+ * # Safety
+ * result must come from a voiceflow call returning a VoiceFlowResult
+ */
+void voiceflow_free_result(struct VoiceFlowResult result);
+
+/**
+ * Cleanup and free the handle
+ *
+ * Blocks until calls running on other threads have returned, and until
+ * queued asynchronous requests have finished and their callbacks have run.
+ *
+ * Calls made with the handle afterwards fail with VF_ERR_INVALID_ARGUMENT,
+ * as with a null handle, and destroying it again does nothing, until a new
+ * handle reuses its address.
+ *
+ * # Safety
+ * - No new calls may be started on the handle once this has been called
+ * - Must not be called from a streaming partial callback
+ */
+void voiceflow_destroy(struct VoiceFlowHandle *handle);
+
+/**
+ * Get the library version
+ */
+const char *voiceflow_version(void);
+
+/**
+ * Get what the library was built from and with, as a JSON object
+ *
+ * For support reports: "version", "git_hash", "build_date" (RFC 3339,
+ * UTC), "target" (the target triple), "profile", "features" (the Cargo
+ * features, e.g. ["metal", "diarization"]), "backends" (the "ort",
+ * "mistralrs" and "whisper_rs" versions, with the commit for a git
+ * dependency, e.g. "0.6.0 (git 1a2b3c4d5e6f)") and "model_formats" (the
+ * "gguf_versions" and "gguf_architectures" an LLM can use, and the
+ * "whisper" and "moonshine" model formats). Needs no handle. Free the
+ * string with voiceflow_free_string.
+ */
+char *voiceflow_build_info(void);
+
+/**
+ * Get the models directory path
+ */
+char *voiceflow_models_dir(void);
+
+/**
+ * Get the total size in bytes of the models directory
+ *
+ * Includes every model and any partial downloads. Returns 0 on failure
+ * (see voiceflow_last_error_message).
+ */
+uint64_t voiceflow_models_disk_usage(void);
+
+/**
+ * List the history entries as a JSON array, newest first
+ *
+ * At most limit entries (0 for all), each with "id", "created_at_ms"
+ * (milliseconds since the Unix epoch), "duration_ms", "preview" (the start
+ * of the formatted text) and "bytes" (recording included). An empty array
+ * when there are none. Returns null if the history directory can't be read
+ * (see voiceflow_last_error_message). Free the string with
+ * voiceflow_free_string.
+ */
+char *voiceflow_history_list(uintptr_t limit);
+
+/**
+ * Get a history entry as a JSON object
+ *
+ * The object has "id", "created_at_ms", "duration_ms", "context",
+ * "raw_transcript", "formatted_text", "language", "no_speech",
+ * "was_fallback", "formatting_error", "total_ms", "llm", "audio_path" (the
+ * recording, 16kHz mono WAV) and "bytes". Returns null for an unknown id
+ * (VF_ERR_INVALID_ARGUMENT) or an entry that can't be read (see
+ * voiceflow_last_error_message). Free the string with
+ * voiceflow_free_string.
+ *
+ * # Safety
+ * id must be a valid null-terminated string
+ */
+char *voiceflow_history_get(const char *id);
+
+/**
+ * Remove every history entry
+ *
+ * Returns false if an entry can't be removed (see
+ * voiceflow_last_error_message).
+ */
+bool voiceflow_history_clear(void);
+
+/**
+ * Report that the user corrected original, text a request returned, to
+ * corrected
+ *
+ * Only the words that changed are counted ("cooper netties" ->
+ * "Kubernetes"), and only short plain-text changes: nothing longer than a
+ * vocabulary term or with regex characters. Once a correction has been
+ * reported learning.min_reports times, and in learning.min_agreement of
+ * the reports of the same words, it is added in the background to the
+ * vocabulary and the replacement rules of the handle and of the config
+ * file, up to learning.max_learned corrections in all. Waits for a
+ * running request to finish. Returns true when the report was taken, even
+ * if it held nothing to learn, and false when learning.enabled is off
+ * (VF_ERR_CONFIG) or the corrections file can't be written (see
+ * voiceflow_last_error_message).
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - original and corrected must be valid null-terminated strings
+ */
+bool voiceflow_report_correction(struct VoiceFlowHandle *handle,
+                                 const char *original,
+                                 const char *corrected);
+
+/**
+ * List the reported corrections as a JSON array, the most reported first
+ *
+ * Each correction has "original" and "corrected" (the words that
+ * changed), "count" (times reported), "last_reported_ms" (milliseconds
+ * since the Unix epoch) and "promoted" (added to the vocabulary and the
+ * replacement rules). An empty array when there are none. Returns null if
+ * the corrections file can't be read (see voiceflow_last_error_message).
+ * Free the string with voiceflow_free_string.
+ */
+char *voiceflow_corrections_list(void);
+
+/**
+ * Forget every reported correction
+ *
+ * Corrections already added to the vocabulary and the replacement rules
+ * stay there; remove them with voiceflow_set_vocabulary and
+ * voiceflow_set_replacements. Returns false if the corrections file can't
+ * be removed (see voiceflow_last_error_message).
+ */
+bool voiceflow_corrections_clear(void);
+
+/**
+ * Check a configuration before initializing with it, as a JSON object
+ *
+ * Reports whether each model it needs is "downloaded", "missing",
+ * "corrupt" or "remote", the bytes left to download, the estimated memory
+ * and any settings that can't work together, e.g.
+ * `{"ready": false, "components": [{"component": "stt", "id":
+ * "moonshine-base", "status": "missing", "download_bytes": 419430400,
+ * ...}], "download_bytes": 419430400, "memory_bytes": ..., "problems":
+ * ["Moonshine only transcribes English, ..."], "models_dir": "..."}`.
+ * Loads no models and only reads file sizes, so it's quick enough for the
+ * main thread. Returns null if the config file can't be read (see
+ * voiceflow_last_error_message). Free the string with
+ * voiceflow_free_string.
+ *
+ * # Safety
+ * config_path must be a valid null-terminated string or null for default
+ */
+char *voiceflow_preflight(const char *configPath);
+
+/**
+ * Get the number of available models
+ *
+ * Includes the custom model, if one is configured. Models over the memory
+ * budget (see voiceflow_set_memory_budget) are left out.
+ */
+uintptr_t voiceflow_model_count(void);
+
+/**
+ * Get model info by index
+ *
+ * A configured custom model comes last, with id "custom" and its full
+ * path as the filename. status tells an interrupted download, to offer
+ * resuming it with voiceflow_download_model, from a complete one.
+ *
+ * # Safety
+ * index must be < voiceflow_model_count()
+ */
+struct ModelInfo voiceflow_model_info(uintptr_t index);
+
+/**
+ * Free model info strings
+ *
+ * # Safety
+ * Only call once per ModelInfo
+ */
+void voiceflow_free_model_info(struct ModelInfo info);
+
+/**
+ * Free a C string returned by other functions
+ *
+ * # Safety
+ * Only call once per string
+ */
+void voiceflow_free_string(char *s);
+
+/**
+ * Get the current model ID from config
+ *
+ * "custom" for a model set with voiceflow_set_custom_model; its path is
+ * the filename of the last voiceflow_model_info entry.
+ */
+char *voiceflow_current_model(void);
+
+/**
+ * Set the current model in config (takes effect on restart, or on a
+ * running handle with voiceflow_reload_model)
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_set_model(const char *modelId);
+
+/**
+ * Use a custom GGUF model file (takes effect on restart, or on a running
+ * handle with voiceflow_reload_model and "custom")
+ *
+ * The file must be a GGUF model of an architecture mistral.rs supports
+ * (llama, mistral, phi2, phi3, qwen2, qwen3, smollm3, gemma2, starcoder2);
+ * other files fail with VF_ERR_INVALID_ARGUMENT. display_name may be null
+ * to use the model name in the file's metadata, or else the file name.
+ * chat_template_id is one of "auto" (the template embedded in the file),
+ * "chatml", "llama3", "gemma", "mistral" or "phi3", or null for "auto".
+ *
+ * # Safety
+ * - path must be a valid null-terminated string
+ * - display_name and chat_template_id must be valid null-terminated
+ *   strings or null
+ */
+bool voiceflow_set_custom_model(const char *path,
+                                const char *displayName,
+                                const char *chatTemplateId);
+
+/**
+ * Switch the handle's LLM without reloading the STT engine
+ *
+ * model_id is an id accepted by voiceflow_set_model, or "custom" for the
+ * model set with voiceflow_set_custom_model. Only the handle changes: call
+ * voiceflow_set_model as well to keep the choice across restarts.
+ * Processing calls made during the reload wait for it to finish. On
+ * failure the previous model is reloaded on next use.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - model_id must be a valid null-terminated string
+ */
+enum VoiceFlowErrorCode voiceflow_reload_model(struct VoiceFlowHandle *handle,
+                                               const char *modelId);
+
+/**
+ * Release the handle's LLM, for when the user turns formatting off
+ *
+ * The model reloads on the next request that formats, and that request's
+ * VF_TIMING_LLM includes the load. Waits for a running request to finish.
+ * Returns false if the LLM wasn't loaded, or was supplied by the app.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+bool voiceflow_unload_llm(struct VoiceFlowHandle *handle);
+
+/**
+ * Forget the formatting results kept for reuse, so the next requests run
+ * the LLM even for a transcript formatted before
+ *
+ * The cache holds up to format_cache_size results (config key
+ * "llm.cache_size"). Waits for a running request to finish.
+ *
+ * # Safety
+ * handle must be a valid pointer from voiceflow_init
+ */
+enum VoiceFlowErrorCode voiceflow_clear_cache(struct VoiceFlowHandle *handle);
+
+/**
+ * Get the HuggingFace download URL for a model
+ *
+ * voiceflow_download_model downloads and verifies it instead.
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+char *voiceflow_model_download_url(const char *modelId);
+
+/**
+ * Delete a downloaded model, including any partial download
+ *
+ * model_id is any id accepted by voiceflow_download_model. The configured
+ * model is only deleted if `force` is set; otherwise this fails with
+ * VF_ERR_MODEL_IN_USE. Deleting a model that isn't downloaded succeeds.
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_delete_model(const char *modelId, bool force);
+
+/**
+ * Get the custom LLM formatting prompt from config
+ *
+ * Returns null when none is set and the built-in prompts are used. Free
+ * the string with voiceflow_free_string.
+ */
+char *voiceflow_get_formatting_prompt(void);
+
+/**
+ * Set the custom LLM formatting prompt in config (requires restart to take effect)
+ *
+ * The prompt may use the {transcript}, {context} and {personal_dictionary}
+ * placeholders and must contain {transcript}. Pass null to go back to the
+ * built-in prompts. Returns false if the prompt is invalid (see
+ * voiceflow_last_error_message).
+ *
+ * # Safety
+ * prompt must be a valid null-terminated string or null
+ */
+bool voiceflow_set_formatting_prompt(const char *prompt);
+
+/**
+ * Get the number of built-in formatting presets
+ */
+uintptr_t voiceflow_preset_count(void);
+
+/**
+ * Get formatting preset info by index
+ *
+ * The id can be passed as the preset of VoiceFlowProcessOptions. Free the
+ * strings with voiceflow_free_preset_info.
+ */
+struct PresetInfo voiceflow_preset_info(uintptr_t index);
+
+/**
+ * Free preset info strings
+ *
+ * # Safety
+ * Only call once per PresetInfo
+ */
+void voiceflow_free_preset_info(struct PresetInfo info);
+
+/**
+ * Get the custom vocabulary from config as a JSON array
+ *
+ * Each entry is an object such as
+ * `{"term": "Kubernetes", "sounds_like": ["cooper netties"]}`. Free the
+ * string with voiceflow_free_string.
+ */
+char *voiceflow_get_vocabulary(void);
+
+/**
+ * Set the custom vocabulary in config (requires restart to take effect)
+ *
+ * Takes a JSON array whose entries are either a term string or an object
+ * with a `term` and optional `sounds_like` array of misrecognitions. At
+ * most 200 entries of up to 64 characters each are allowed. Returns false
+ * if the JSON or an entry is invalid (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * json_array must be a valid null-terminated string
+ */
+bool voiceflow_set_vocabulary(const char *jsonArray);
+
+/**
+ * Get the find-and-replace rules from config as a JSON array
+ *
+ * Each rule is an object with `pattern`, `replacement`, `is_regex`,
+ * `case_sensitive` and `apply_to_raw`. Free the string with
+ * voiceflow_free_string.
+ */
+char *voiceflow_get_replacements(void);
+
+/**
+ * Replace the find-and-replace rules in config (requires restart to take effect)
+ *
+ * Takes a JSON array in the format returned by voiceflow_get_replacements;
+ * only `pattern` and `replacement` are required. Rules apply in array
+ * order. Returns false if the JSON is invalid or a pattern doesn't compile
+ * (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * json_array must be a valid null-terminated string
+ */
+bool voiceflow_set_replacements(const char *jsonArray);
+
+/**
+ * Get the per-app formatting overrides from config as a JSON object keyed
+ * by bundle id
+ *
+ * Each override is an object with any of `preset` (a preset id, or
+ * `{"custom": "<template>"}`), `formatting_mode` ("full",
+ * "punctuation-only" or "none"), `vocabulary_extra` (entries as for
+ * voiceflow_set_vocabulary) and `replacements_extra` (rules as for
+ * voiceflow_set_replacements). Free the string with voiceflow_free_string.
+ */
+char *voiceflow_get_app_overrides(void);
+
+/**
+ * Set the formatting override for one app in config, replacing any it had
+ * (requires restart to take effect, or voiceflow_update_config_json)
+ *
+ * Takes a JSON object in the format of one override from
+ * voiceflow_get_app_overrides. Calls with `app_id` set (see
+ * voiceflow_process_json) get it merged over the config: `preset` replaces
+ * the default preset but not one the call chooses, `formatting_mode` caps
+ * the formatting, `vocabulary_extra` is appended to the vocabulary (an
+ * entry for a term already there replaces it) and `replacements_extra`
+ * runs after the configured rules. Returns false if the JSON is invalid,
+ * has unknown fields, or the merged vocabulary or rules are (see
+ * voiceflow_last_error_message).
+ *
+ * # Safety
+ * - app_id must be a valid null-terminated string
+ * - override_json must be a valid null-terminated string
+ */
+bool voiceflow_set_app_override(const char *appId, const char *overrideJson);
+
+/**
+ * Remove the formatting override for one app from config (requires
+ * restart to take effect, or voiceflow_update_config_json)
+ *
+ * Returns false if the app has no override or the config can't be saved
+ * (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * app_id must be a valid null-terminated string
+ */
+bool voiceflow_remove_app_override(const char *appId);
+
+/**
+ * Get the LLM decoding parameters from config as a JSON object
+ *
+ * The object has `max_tokens`, `temperature`, `top_p`, `top_k`,
+ * `repeat_penalty`, `seed` (null when unset), `n_gpu_layers` and
+ * `enable_thinking`. Free the string with voiceflow_free_string.
+ */
+char *voiceflow_get_llm_options(void);
+
+/**
+ * Update the LLM decoding parameters in config (requires restart to take effect)
+ *
+ * Takes a JSON object in the format returned by voiceflow_get_llm_options;
+ * keys that are left out keep their current value, so
+ * `{"temperature": 0, "seed": 42}` is enough. Out-of-range values are
+ * clamped rather than rejected. Returns false if the JSON is invalid (see
+ * voiceflow_last_error_message).
+ *
+ * # Safety
+ * json_object must be a valid null-terminated string
+ */
+bool voiceflow_set_llm_options(const char *jsonObject);
+
+/**
+ * Get a config field by dotted key, e.g. "llm.temperature" or "vad.enabled"
+ *
+ * See the README for the supported keys. Strings are returned as they are,
+ * unset optional fields as "" and other values as JSON ("true", "0.7").
+ * Returns null for an unknown key (see voiceflow_last_error_message). Free
+ * the string with voiceflow_free_string.
+ *
+ * # Safety
+ * key must be a valid null-terminated string
+ */
+char *voiceflow_config_get(const char *key);
+
+/**
+ * Set a config field by dotted key (requires restart to take effect)
+ *
+ * The value is parsed as the field's type, in the format voiceflow_config_get
+ * returns; "" clears an optional field. Returns false, leaving the config
+ * file untouched, for an unknown key, a value of the wrong type or one that
+ * fails validation (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * key and value must be valid null-terminated strings
+ */
+bool voiceflow_config_set(const char *key, const char *value);
+
+/**
+ * Get the whole config as a JSON object, with the field names of the TOML
+ * file
+ *
+ * Free the string with voiceflow_free_string.
+ */
+char *voiceflow_config_json(void);
+
+/**
+ * Update several config fields at once (requires restart to take effect)
+ *
+ * Takes a JSON object in the format returned by voiceflow_config_json;
+ * nested objects are merged, so only the fields to change are needed, e.g.
+ * `{"llm_options": {"temperature": 0.2}, "audio": {"vad_enabled": false}}`.
+ * Returns false, leaving the config file untouched, for unknown fields or
+ * invalid values (see voiceflow_last_error_message).
+ *
+ * # Safety
+ * json_object must be a valid null-terminated string
+ */
+bool voiceflow_config_apply_json(const char *jsonObject);
+
+/**
+ * Apply config changes to a running handle without reinitializing it
+ *
+ * Takes a JSON object in the format of voiceflow_config_json, merged over
+ * the handle's current config; the config file is neither read nor
+ * written. Sampling parameters, prompts, audio and VAD settings and
+ * replacements take effect with the next request. Fields that need a model
+ * reload (see the README) keep their current value and are listed in the
+ * returned JSON object, e.g. `{"needs_reload": ["llm_model"]}`; apply them
+ * by creating a new handle. Returns null, changing nothing, for unknown
+ * fields or invalid values (see voiceflow_last_error_message). Free the
+ * string with voiceflow_free_string.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - json_object must be a valid null-terminated string
+ */
+char *voiceflow_update_config_json(struct VoiceFlowHandle *handle, const char *jsonObject);
+
+/**
+ * Get the current STT engine ("whisper" or "moonshine")
+ */
+char *voiceflow_current_stt_engine(void);
+
+/**
+ * Set the current STT engine ("whisper" or "moonshine")
+ *
+ * Fails for "moonshine" unless the configured language is "en" and the
+ * task is transcribe, since Moonshine only transcribes English.
+ *
+ * # Safety
+ * engine_id must be a valid null-terminated string
+ */
+bool voiceflow_set_stt_engine(const char *engineId);
+
+/**
+ * Switch the handle's STT engine ("whisper" or "moonshine") without
+ * reloading the LLM
+ *
+ * Only the handle changes: call voiceflow_set_stt_engine as well to keep
+ * the choice across restarts. Processing calls made during the reload
+ * wait for it to finish. Fails with VF_ERR_BUSY while a streaming session
+ * is active; on any failure the previous engine stays in use.
+ *
+ * # Safety
+ * - handle must be a valid pointer from voiceflow_init
+ * - engine_id must be a valid null-terminated string
+ */
+enum VoiceFlowErrorCode voiceflow_reload_stt_engine(struct VoiceFlowHandle *handle,
+                                                    const char *engineId);
+
+/**
+ * Set the hardware the STT engine runs on ("cpu", "coreml" or "auto")
+ *
+ * Moonshine uses the Core ML execution provider for "coreml", and for
+ * "auto" on Apple platforms; Whisper uses whisper.cpp's GPU backend for
+ * both. If Core ML can't be used the engine runs on the CPU, with a
+ * warning in the log. Saved to the config file; takes effect the next
+ * time the STT engine loads (voiceflow_init or
+ * voiceflow_reload_stt_engine).
+ *
+ * # Safety
+ * provider_id must be a valid null-terminated string
+ */
+bool voiceflow_set_stt_provider(const char *providerId);
+
+/**
+ * Get the hardware the handle's STT engine runs on: "cpu", "coreml",
+ * "metal" or "cuda"
+ *
+ * This is the provider actually in use after any fallback, also reported
+ * as timings.stt_provider in voiceflow_process_json results. With a null
+ * handle, returns the configured setting instead ("cpu", "coreml" or
+ * "auto"). Returns null for an engine supplied by the app. Free the string
+ * with voiceflow_free_string.
+ *
+ * # Safety
+ * handle must be null or a valid pointer from voiceflow_init
+ */
+char *voiceflow_current_stt_provider(struct VoiceFlowHandle *handle);
+
+/**
+ * Get the STT engine the handle runs, as a JSON object: "engine"
+ * ("whisper" or "moonshine"), "model" (a model id), "precision"
+ * ("float32", "quantized" or "int8") and "substituted"
+ *
+ * "substituted" is true when stt_fallback_enabled is set and the
+ * configured engine failed to load, so this one runs in its place; a
+ * warning is logged when that happens. The config file keeps the
+ * configured engine. Returns null for a null handle or an engine supplied
+ * by the app. Free the string with voiceflow_free_string.
+ *
+ * # Safety
+ * handle must be null or a valid pointer from voiceflow_init
+ */
+char *voiceflow_active_stt_engine(struct VoiceFlowHandle *handle);
+
+/**
+ * Get the current Whisper model ("tiny", "base", "small", "medium" or
+ * "large-v3-turbo", with "-quantized" or "-int8" for those precisions)
+ */
+char *voiceflow_current_whisper_model(void);
+
+/**
+ * Set the current Whisper model ("tiny", "base", "small", "medium" or
+ * "large-v3-turbo", with "-quantized" or "-int8" for those precisions)
+ *
+ * Takes effect on the next voiceflow_init, which fails with
+ * VF_ERR_MODEL_NOT_FOUND if the model isn't downloaded.
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_set_whisper_model(const char *modelId);
+
+/**
+ * Get the number of available Whisper models, one per size and precision
+ *
+ * Models over the memory budget (see voiceflow_set_memory_budget) are left
+ * out.
+ */
+uintptr_t voiceflow_whisper_model_count(void);
+
+/**
+ * Get Whisper model info by index
+ *
+ * # Safety
+ * index must be < voiceflow_whisper_model_count()
+ */
+struct WhisperModelInfo voiceflow_whisper_model_info(uintptr_t index);
+
+/**
+ * Free Whisper model info strings
+ *
+ * # Safety
+ * Only call once per WhisperModelInfo
+ */
+void voiceflow_free_whisper_model_info(struct WhisperModelInfo info);
+
+/**
+ * Check if a Whisper model is downloaded
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string (e.g. "small" or
+ * "small-int8")
+ */
+bool voiceflow_whisper_model_downloaded(const char *modelId);
+
+/**
+ * Get the current Moonshine model ("tiny" or "base", with "-quantized" or
+ * "-int8" for those precisions)
+ */
+char *voiceflow_current_moonshine_model(void);
+
+/**
+ * Set the current Moonshine model ("tiny" or "base", with "-quantized" or
+ * "-int8" for those precisions)
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_set_moonshine_model(const char *modelId);
+
+/**
+ * Get the number of available Moonshine models, one per size and precision
+ *
+ * Models over the memory budget (see voiceflow_set_memory_budget) are left
+ * out.
+ */
+uintptr_t voiceflow_moonshine_model_count(void);
+
+/**
+ * Get Moonshine model info by index
+ *
+ * # Safety
+ * index must be < voiceflow_moonshine_model_count()
+ */
+struct MoonshineModelInfo voiceflow_moonshine_model_info(uintptr_t index);
+
+/**
+ * Free Moonshine model info strings
+ *
+ * # Safety
+ * Only call once per MoonshineModelInfo
+ */
+void voiceflow_free_moonshine_model_info(struct MoonshineModelInfo info);
+
+/**
+ * Check if a Moonshine model is downloaded
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string ("tiny", "base", or
+ * either with "-quantized" or "-int8")
+ */
+bool voiceflow_moonshine_model_downloaded(const char *modelId);
+
+/**
+ * Delete a downloaded Moonshine model ("tiny", "base", or either with
+ * "-quantized" or "-int8")
+ *
+ * Same as voiceflow_delete_model: the configured model is only deleted if
+ * `force` is set.
+ *
+ * # Safety
+ * model_id must be a valid null-terminated string
+ */
+bool voiceflow_moonshine_delete_model(const char *modelId, bool force);
+
+/**
+ * Get the Moonshine models directory path
+ */
+char *voiceflow_moonshine_models_dir(void);
+
+/**
+ * Route log messages to a callback (e.g. to forward them into os_log)
+ *
+ * Pass null to remove the callback. The callback may be invoked from any
+ * thread and must not call back into the library.
+ *
+ * # Safety
+ * user_data is passed back to the callback untouched
+ */
+void voiceflow_set_log_callback(VoiceFlowLogCallback callback, void *userData);
+
+/**
+ * Set the log verbosity (default VF_LOG_INFO)
+ */
+void voiceflow_set_log_level(enum VoiceFlowLogLevel level);
+
+/**
+ * Receive each pipeline span as JSON when it closes (e.g. to feed a
+ * metrics dashboard)
+ *
+ * Pass null to remove the callback. The JSON has the span's name,
+ * trace_id, span_id, parent_span_id, start_unix_ns, duration_ms and its
+ * fields (sizes and counts, never transcript text). The callback runs on
+ * the thread that ran the stage and must not call back into the library.
+ *
+ * # Safety
+ * user_data is passed back to the callback untouched
+ */
+void voiceflow_set_event_callback(VoiceFlowEventCallback callback, void *userData);
+
+/**
+ * Get the most recent panic caught in the library as a JSON object
+ *
+ * The object has "message", "location" ("file:line:column"),
+ * "backtrace", "thread", "version", "build_hash" and "timestamp" (seconds
+ * since the Unix epoch). The last 8 reports are kept; see
+ * voiceflow_panic_reports. Returns null if nothing has panicked. Free the
+ * string with voiceflow_free_string.
+ */
+char *voiceflow_last_panic_report(void);
+
+/**
+ * Get the kept panic reports (up to 8, oldest first) as a JSON array of
+ * the objects voiceflow_last_panic_report returns
+ *
+ * Free the string with voiceflow_free_string.
+ */
+char *voiceflow_panic_reports(void);
+
+/**
+ * Forward panic reports to a callback (e.g. the app's crash reporter)
+ *
+ * The callback gets each report as it is caught, as the JSON object of
+ * voiceflow_last_panic_report, on the thread that panicked. Pass null to
+ * remove it. The callback must not call back into the library.
+ *
+ * # Safety
+ * user_data is passed back to the callback untouched
+ */
+void voiceflow_set_panic_callback(VoiceFlowPanicCallback callback, void *userData);
+
+#endif  /* VOICEFLOW_H */
@@ -0,0 +1,47 @@
+import Foundation
+
+/// Where a dictation goes, passed to the library as JSON
+///
+/// `documentKind` picks the prompt as a context name such as "email" does.
+/// With `textBeforeCursor`, the result is capitalized and punctuated to
+/// continue that text.
+public struct DictationContext: Encodable, Sendable {
+    public var appName: String?
+    public var documentKind: String?
+    public var recipient: String?
+    /// Text before the insertion point (its end is enough)
+    public var textBeforeCursor: String?
+    /// Text after the insertion point (its start is enough)
+    public var textAfterCursor: String?
+    public var userInstructions: String?
+
+    public init(
+        appName: String? = nil,
+        documentKind: String? = nil,
+        recipient: String? = nil,
+        textBeforeCursor: String? = nil,
+        textAfterCursor: String? = nil,
+        userInstructions: String? = nil
+    ) {
+        self.appName = appName
+        self.documentKind = documentKind
+        self.recipient = recipient
+        self.textBeforeCursor = textBeforeCursor
+        self.textAfterCursor = textAfterCursor
+        self.userInstructions = userInstructions
+    }
+
+    /// The JSON the C API takes as a context
+    func json() throws -> String {
+        let encoder = JSONEncoder()
+        encoder.keyEncodingStrategy = .convertToSnakeCase
+        return String(decoding: try encoder.encode(self), as: UTF8.self)
+    }
+}
+
+extension VoiceFlow {
+    /// Transcribe and format 16kHz mono samples for where they're inserted
+    public func process(samples: [Float], context: DictationContext) async throws -> TranscriptionResult {
+        try await process(samples: samples, context: try context.json())
+    }
+}
@@ -0,0 +1,123 @@
+import CVoiceFlow
+import Foundation
+
+/// A formatting LLM from the catalogue
+public struct Model: Sendable, Identifiable {
+    public let id: String
+    public let displayName: String
+    /// File name in the models directory, or the full path of a custom model
+    public let filename: String
+    public let sizeGB: Float
+    public let isDownloaded: Bool
+    /// `VF_MODEL_PARTIAL` for an interrupted download, which
+    /// `downloadModel` resumes
+    public let status: VoiceFlowModelStatus
+    /// Bytes on disk, partial downloads included
+    public let downloadedBytes: UInt64
+    /// Size of the complete model (an estimate until it's downloaded)
+    public let expectedBytes: UInt64
+}
+
+extension VoiceFlow {
+    /// LLMs that fit the memory budget, then the custom model if one is
+    /// configured
+    public static var models: [Model] {
+        (0..<voiceflow_model_count()).map { index in
+            let info = voiceflow_model_info(index)
+            defer { voiceflow_free_model_info(info) }
+            return Model(
+                id: String(cString: info.id),
+                displayName: String(cString: info.display_name),
+                filename: String(cString: info.filename),
+                sizeGB: info.size_gb,
+                isDownloaded: info.is_downloaded,
+                status: info.status,
+                downloadedBytes: info.downloaded_bytes,
+                expectedBytes: info.expected_bytes
+            )
+        }
+    }
+
+    /// Where models are downloaded to
+    public static func modelsDirectory() throws -> URL {
+        guard let path = voiceflow_models_dir() else {
+            throw VoiceFlowError.last(or: "voiceflow_models_dir failed")
+        }
+        defer { voiceflow_free_string(path) }
+        return URL(fileURLWithPath: String(cString: path), isDirectory: true)
+    }
+
+    /// Download a model into the models directory
+    ///
+    /// `id` is an LLM id from `models`, "whisper-<size>" or
+    /// "moonshine-<size>". `progress` is called on the download thread with
+    /// the bytes downloaded and the total (0 if unknown). Cancelling the
+    /// task cancels the download and throws `CancellationError`; the partial
+    /// file is kept, so the next attempt resumes.
+    public static func downloadModel(
+        id: String,
+        progress: (@Sendable (_ downloaded: UInt64, _ total: UInt64) -> Void)? = nil
+    ) async throws {
+        try await withTaskCancellationHandler {
+            try await withCheckedThrowingContinuation { (continuation: CheckedContinuation<Void, Error>) in
+                let observer = Unmanaged.passRetained(DownloadObserver(progress: progress, continuation: continuation))
+                let started = id.withCString { voiceflow_download_model($0, downloadCallback, observer.toOpaque()) }
+                if !started {
+                    observer.release()
+                    continuation.resume(throwing: VoiceFlowError.last(or: "Couldn't start downloading \(id)"))
+                }
+            }
+        } onCancel: {
+            _ = id.withCString { voiceflow_cancel_download($0) }
+        }
+    }
+
+    /// Delete a downloaded model
+    ///
+    /// A model in use by a live pipeline is only deleted with `force`;
+    /// otherwise this throws `VF_ERR_MODEL_IN_USE`.
+    public static func deleteModel(id: String, force: Bool = false) throws {
+        guard id.withCString({ voiceflow_delete_model($0, force) }) else {
+            throw VoiceFlowError.last(or: "Couldn't delete \(id)")
+        }
+    }
+
+    /// Check a downloaded model's files against their recorded checksums
+    ///
+    /// Hashes every file, which takes seconds for an LLM. Throws
+    /// `VF_ERR_MODEL_CORRUPTED` or `VF_ERR_MODEL_NOT_FOUND`.
+    public static func verifyModel(id: String) throws {
+        guard id.withCString({ voiceflow_verify_model($0) }) else {
+            throw VoiceFlowError.last(or: "Couldn't verify \(id)")
+        }
+    }
+}
+
+/// What a download reports to, kept alive until its terminal status
+private final class DownloadObserver {
+    let progress: (@Sendable (UInt64, UInt64) -> Void)?
+    let continuation: CheckedContinuation<Void, Error>
+
+    init(progress: (@Sendable (UInt64, UInt64) -> Void)?, continuation: CheckedContinuation<Void, Error>) {
+        self.progress = progress
+        self.continuation = continuation
+    }
+}
+
+private let downloadCallback: VoiceFlowDownloadCallback = { userData, downloaded, total, status in
+    guard let userData else {
+        return
+    }
+    let observer = Unmanaged<DownloadObserver>.fromOpaque(userData)
+    switch status {
+    case VF_DOWNLOAD_IN_PROGRESS:
+        observer.takeUnretainedValue().progress?(downloaded, total)
+    case VF_DOWNLOAD_COMPLETED:
+        observer.takeRetainedValue().continuation.resume()
+    case VF_DOWNLOAD_CANCELLED:
+        observer.takeRetainedValue().continuation.resume(throwing: CancellationError())
+    default:
+        // The last error is only set on the download thread, i.e. here
+        observer.takeRetainedValue().continuation.resume(throwing: VoiceFlowError.last(or: "Download failed"))
+    }
+}
@@ -0,0 +1,55 @@
+import CVoiceFlow
+
+/// A successful result of `VoiceFlow.process`, copied out of the C result
+/// handle so nothing needs freeing
+public struct TranscriptionResult: Sendable {
+    /// A part of the transcript, split at long pauses and speaker turns
+    public struct Segment: Sendable {
+        public let startMs: UInt64
+        public let endMs: UInt64
+        public let rawText: String
+        public let formattedText: String
+        /// Mean STT word probability (0.0 - 1.0)
+        public let confidence: Float
+        /// Speaker number from 0, or nil when diarization is off
+        public let speaker: Int?
+    }
+
+    public let formattedText: String
+    public let rawTranscript: String
+    /// Why LLM formatting failed, in which case `formattedText` is the raw
+    /// transcript
+    public let formattingError: String?
+    /// STT decoder confidence (0.0 - 1.0)
+    public let confidence: Float
+    /// Segments whose formatted texts joined are `formattedText`
+    public let segments: [Segment]
+    public let totalMs: UInt64
+    public let transcriptionMs: UInt64
+    public let llmMs: UInt64
+
+    /// Copy a successful result handle; the caller still frees it
+    init(handle: OpaquePointer) {
+        formattedText = String(cString: voiceflow_result_formatted_text(handle))
+        rawTranscript = String(cString: voiceflow_result_raw_transcript(handle))
+        formattingError = voiceflow_result_formatting_error(handle).map { String(cString: $0) }
+        confidence = voiceflow_result_confidence(handle)
+        segments = (0..<voiceflow_result_segment_count(handle)).compactMap { index in
+            var segment = VoiceFlowSegment()
+            guard voiceflow_result_segment(handle, index, &segment) else {
+                return nil
+            }
+            return Segment(
+                startMs: segment.start_ms,
+                endMs: segment.end_ms,
+                rawText: String(cString: segment.raw_text),
+                formattedText: String(cString: segment.formatted_text),
+                confidence: segment.confidence,
+                speaker: segment.speaker < 0 ? nil : Int(segment.speaker)
+            )
+        }
+        totalMs = voiceflow_result_timing(handle, VF_TIMING_TOTAL)
+        transcriptionMs = voiceflow_result_timing(handle, VF_TIMING_TRANSCRIPTION)
+        llmMs = voiceflow_result_timing(handle, VF_TIMING_LLM)
+    }
+}
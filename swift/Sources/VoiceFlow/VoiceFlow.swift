@@ -0,0 +1,89 @@
+@_exported import CVoiceFlow
+import Foundation
+
+/// A VoiceFlow pipeline: speech-to-text followed by LLM formatting
+///
+/// Owns a `VoiceFlowHandle` from the C API and destroys it when released.
+/// Calls may come from any thread; requests on one instance run one at a
+/// time.
+public final class VoiceFlow: @unchecked Sendable {
+    let handle: OpaquePointer
+
+    /// Load the config (the default location if `configPath` is nil) and
+    /// its models
+    ///
+    /// Loading models takes seconds: call this off the main thread.
+    public convenience init(configPath: String? = nil) throws {
+        guard let handle = configPath.withOptionalCString({ voiceflow_init($0) }) else {
+            throw VoiceFlowError.last(or: "voiceflow_init failed")
+        }
+        self.init(handle: handle)
+    }
+
+    /// Take ownership of a handle from one of the voiceflow_init functions
+    init(handle: OpaquePointer) {
+        self.handle = handle
+    }
+
+    deinit {
+        voiceflow_destroy(handle)
+    }
+
+    /// Version of the linked library
+    public static var version: String {
+        String(cString: voiceflow_version())
+    }
+
+    /// Whether the models are loaded; false while a reload or warmup runs
+    public var isReady: Bool {
+        voiceflow_is_ready(handle)
+    }
+
+    /// Transcribe and format 16kHz mono samples
+    ///
+    /// `context` describes where the text goes (e.g. "email" or the app's
+    /// bundle id) and is used by formatting. Runs on a background queue;
+    /// cancelling the task cancels this request alone, which then throws a
+    /// `VoiceFlowError` whose `isCancelled` is set.
+    public func process(samples: [Float], context: String? = nil) async throws -> TranscriptionResult {
+        let request = voiceflow_new_request(handle)
+        return try await withTaskCancellationHandler {
+            try await withCheckedThrowingContinuation { continuation in
+                DispatchQueue.global(qos: .userInitiated).async {
+                    continuation.resume(with: Result {
+                        try self.processBlocking(samples: samples, context: context, request: request)
+                    })
+                }
+            }
+        } onCancel: {
+            voiceflow_cancel_request(self.handle, request)
+        }
+    }
+
+    /// `process` on the calling thread, as the request reserved with
+    /// voiceflow_new_request
+    func processBlocking(samples: [Float], context: String?, request: UInt64) throws -> TranscriptionResult {
+        let processed = samples.withUnsafeBufferPointer { buffer in
+            context.withOptionalCString {
+                voiceflow_process2_request(handle, request, buffer.baseAddress, UInt(buffer.count), $0)
+            }
+        }
+        defer { voiceflow_result_free(processed) }
+        guard let result = processed, voiceflow_result_error_code(result) == VF_ERR_OK else {
+            throw VoiceFlowError(result: processed)
+        }
+        return TranscriptionResult(handle: result)
+    }
+}
+
+extension Optional where Wrapped == String {
+    /// Call `body` with the string as a C string, or with null for nil
+    func withOptionalCString<R>(_ body: (UnsafePointer<CChar>?) throws -> R) rethrows -> R {
+        switch self {
+        case .some(let string):
+            return try string.withCString(body)
+        case .none:
+            return try body(nil)
+        }
+    }
+}
@@ -0,0 +1,44 @@
+import CVoiceFlow
+
+/// A failed VoiceFlow call
+public struct VoiceFlowError: Error, CustomStringConvertible {
+    public let code: VoiceFlowErrorCode
+    /// The message with its underlying error chain
+    public let message: String
+
+    /// Whether the call was cancelled rather than failing
+    public var isCancelled: Bool {
+        code == VF_ERR_CANCELLED
+    }
+
+    /// Whether one of the config's `timeouts` ran out
+    public var isTimeout: Bool {
+        code == VF_ERR_TIMEOUT
+    }
+
+    public var description: String {
+        message
+    }
+
+    /// The error of a failed result handle from voiceflow_process2
+    init(result: OpaquePointer?) {
+        code = voiceflow_result_error_code(result)
+        message = voiceflow_result_error(result).map { String(cString: $0) } ?? "voiceflow_process2 failed"
+    }
+
+    init(code: VoiceFlowErrorCode, message: String) {
+        self.code = code
+        self.message = message
+    }
+
+    /// The last error on this thread, with `fallback` as its message if the
+    /// call that failed didn't record one
+    static func last(or fallback: String) -> VoiceFlowError {
+        let code = voiceflow_last_error_code()
+        guard let message = voiceflow_last_error_message() else {
+            return VoiceFlowError(code: code == VF_ERR_OK ? VF_ERR_INTERNAL : code, message: fallback)
+        }
+        defer { voiceflow_free_string(message) }
+        return VoiceFlowError(code: code, message: String(cString: message))
+    }
+}
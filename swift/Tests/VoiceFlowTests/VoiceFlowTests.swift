@@ -0,0 +1,56 @@
+import CVoiceFlow
+@testable import VoiceFlow
+import XCTest
+
+/// Runs against voiceflow_init_stub, so no config or model is needed. Link a
+/// library built with `cargo build -p voiceflow-ffi --features stub-pipeline`.
+final class VoiceFlowTests: XCTestCase {
+    /// Two seconds of a tone at 16kHz
+    let samples = (0..<32000).map { sin(Float($0) * 0.1) * 0.5 }
+
+    func stub(_ transcript: String = "hello from swift") throws -> VoiceFlow {
+        VoiceFlow(handle: try XCTUnwrap(voiceflow_init_stub(transcript)))
+    }
+
+    func testProcessReturnsFormattedTranscript() async throws {
+        let voiceflow = try stub()
+        XCTAssertTrue(voiceflow.isReady)
+
+        let result = try await voiceflow.process(samples: samples, context: "email")
+        XCTAssertEqual(result.rawTranscript, "hello from swift")
+        XCTAssertEqual(result.formattedText, "Hello from swift.")
+        XCTAssertNil(result.formattingError)
+    }
+
+    func testStructuredContextContinuesTextBeforeCursor() async throws {
+        let voiceflow = try stub()
+        let context = DictationContext(appName: "Notes", textBeforeCursor: "We tried it and")
+        let result = try await voiceflow.process(samples: samples, context: context)
+        XCTAssertEqual(result.formattedText, " hello from swift.")
+    }
+
+    func testFailedProcessThrows() async throws {
+        let voiceflow = try stub()
+        do {
+            _ = try await voiceflow.process(samples: [])
+            XCTFail("processing no samples should throw")
+        } catch let error as VoiceFlowError {
+            XCTAssertNotEqual(error.code, VF_ERR_OK)
+            XCTAssertFalse(error.message.isEmpty)
+        }
+    }
+
+    func testReleaseDestroysHandle() throws {
+        var voiceflow: VoiceFlow? = try stub()
+        let handle = try XCTUnwrap(voiceflow?.handle)
+        XCTAssertTrue(voiceflow_is_ready(handle))
+
+        voiceflow = nil
+        // The C API rejects a destroyed handle instead of reading it
+        XCTAssertFalse(voiceflow_is_ready(handle))
+    }
+
+    func testVersion() {
+        XCTAssertFalse(VoiceFlow.version.isEmpty)
+    }
+}